@@ -0,0 +1,27 @@
+# Meldungen beim Importieren
+import-success = Songs erfolgreich hinzugefügt!
+import-no-new-tracks = Keine neuen Songs zum Hinzufügen.
+playlist-created = Playlist { $name } mit der ID { $id } erstellt
+tracks-added = { $count ->
+    [one] 1 Song zur Playlist { $id } hinzugefügt
+   *[other] { $count } Songs zur Playlist { $id } hinzugefügt
+}
+missing-track-id = Fehlende Track-ID für: { $song }
+
+# --dry-run
+dry-run-add = Würde hinzufügen: { $track }
+dry-run-duplicate = Schon in der Playlist: { $track }
+dry-run-summary = { $added } Songs würden hinzugefügt, { $skipped } als Duplikate übersprungen.
+
+# Fehler
+error-hint = Tipp: { $hint }
+
+# Berichte
+report-title = Import in { $playlist }
+report-source = { $date } aus { $source }
+report-stats = { $songs } Songs gelesen, { $added } hinzugefügt ({ $duration }), { $failed } konnten nicht hinzugefügt werden
+report-added = Hinzugefügt
+report-songwriters = Songwriter
+report-written-by = , geschrieben von { $writers }
+report-failures = Fehler
+report-failed-lines = CSV-Zeilen ohne gültige Track-ID: { $lines }
@@ -0,0 +1,24 @@
+# Messages shown while importing
+import-success = Successfully added songs!
+import-no-new-tracks = No new tracks to add.
+playlist-created = Created playlist { $name } with ID { $id }
+tracks-added = Added { $count } tracks to playlist { $id }
+missing-track-id = Missing track ID for: { $song }
+
+# --dry-run
+dry-run-add = Would add: { $track }
+dry-run-duplicate = Already in playlist: { $track }
+dry-run-summary = { $added } tracks would be added, { $skipped } skipped as duplicates.
+
+# Errors
+error-hint = Hint: { $hint }
+
+# Run reports
+report-title = Import into { $playlist }
+report-source = { $date } from { $source }
+report-stats = { $songs } songs read, { $added } tracks added ({ $duration }), { $failed } songs could not be added
+report-added = Added
+report-songwriters = Songwriters
+report-written-by = , written by { $writers }
+report-failures = Failures
+report-failed-lines = CSV lines without a usable track ID: { $lines }
@@ -0,0 +1,27 @@
+# Mensajes mostrados al importar
+import-success = ¡Canciones añadidas!
+import-no-new-tracks = No hay canciones nuevas que añadir.
+playlist-created = Se creó la playlist { $name } con el ID { $id }
+tracks-added = { $count ->
+    [one] Se añadió 1 canción a la playlist { $id }
+   *[other] Se añadieron { $count } canciones a la playlist { $id }
+}
+missing-track-id = Falta el ID de la canción: { $song }
+
+# --dry-run
+dry-run-add = Se añadiría: { $track }
+dry-run-duplicate = Ya está en la playlist: { $track }
+dry-run-summary = Se añadirían { $added } canciones y se omitirían { $skipped } repetidas.
+
+# Errores
+error-hint = Sugerencia: { $hint }
+
+# Informes
+report-title = Importación a { $playlist }
+report-source = { $date } desde { $source }
+report-stats = { $songs } canciones leídas, { $added } añadidas ({ $duration }), { $failed } no se pudieron añadir
+report-added = Añadidas
+report-songwriters = Compositores
+report-written-by = , escrita por { $writers }
+report-failures = Errores
+report-failed-lines = Líneas del CSV sin un ID de canción válido: { $lines }
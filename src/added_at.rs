@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use csv_to_playlist::Song;
+
+extern crate chrono;
+use self::chrono::{DateTime,NaiveDate,NaiveDateTime};
+
+/// The CSV columns that the date each song was added can be read from, in
+/// order of preference
+pub const ADDED_AT_COLUMNS: [&str; 2] = ["added_at (S)", "added_at"];
+
+/// The error when a row's `added_at` isn't a date that can be read
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidAddedAt {
+    /// The line of the row, where the first song is on line 2
+    pub line: usize,
+    pub value: String,
+}
+
+impl Error for InvalidAddedAt {
+    fn description(&self) -> &str {
+        "Expected an added_at like 2019-05-01T12:00:00Z, 2019-05-01 12:00:00 or 2019-05-01"
+    }
+}
+
+impl fmt::Display for InvalidAddedAt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, line {} has {}", self.description(), self.line, self.value)
+    }
+}
+
+/// Read a date in the format Spotify exports `added_at` in, or a plain date
+/// and time. Times without a timezone are treated as UTC
+///
+/// # Arguments
+///
+/// * `value` - The date, eg. `2019-05-01T12:00:00Z`
+pub fn parse_added_at(value: &str) -> Option<NaiveDateTime> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.naive_utc());
+    }
+    if let Ok(date) = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S") {
+        return Some(date);
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0))
+}
+
+/// Read the `added_at` of each song, keyed by the song's ID. Rows with an
+/// empty `added_at` are left out, and the first row wins for songs that are
+/// in the CSV twice.
+///
+/// # Arguments
+///
+/// * `rows` - The songs read from the CSV, in order, with their lines
+/// * `column` - The `added_at` on each line, from the first of
+/// `ADDED_AT_COLUMNS` that the CSV has, see `ParsedCsv::column`
+pub fn read_added_at(rows: &[(usize, Song)],
+                     column: &HashMap<usize, String>) -> Result<HashMap<String, NaiveDateTime>, InvalidAddedAt> {
+    let mut added_at = HashMap::new();
+    for (line, song) in rows {
+        let value = match column.get(line) {
+            Some(value) if !value.is_empty() => value,
+            _ => continue,
+        };
+        let date = parse_added_at(value).ok_or_else(|| InvalidAddedAt { line: *line, value: value.to_owned() })?;
+        added_at.entry(song.song_id.clone()).or_insert(date);
+    }
+    Ok(added_at)
+}
+
+/// Sort songs from the earliest added to the latest, so that a playlist
+/// restored from an export reads in the order it was built up. Songs without
+/// a date go last and otherwise the order is kept.
+///
+/// # Arguments
+///
+/// * `songs` - The songs to sort
+/// * `added_at` - When each song was added, see `read_added_at`
+pub fn sort_by_added_at(songs: &mut [Song], added_at: &HashMap<String, NaiveDateTime>) {
+    songs.sort_by_key(|song| match added_at.get(&song.song_id) {
+        Some(date) => (false, Some(*date)),
+        None => (true, None),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(id: &str) -> Song {
+        Song { music: id.to_string(), song_id: id.to_string() }
+    }
+
+    #[test]
+    fn songs_are_sorted_by_when_they_were_added() {
+        // Given
+        let mut songs = vec![song("id_a"), song("id_b"), song("id_c"), song("id_d")];
+        let mut added_at = HashMap::new();
+        added_at.insert("id_a".to_owned(), parse_added_at("2020-01-01T09:00:00+10:00").unwrap());
+        added_at.insert("id_c".to_owned(), parse_added_at("2019-12-31").unwrap());
+        added_at.insert("id_d".to_owned(), parse_added_at("2019-12-31 22:00:00").unwrap());
+        // When
+        sort_by_added_at(&mut songs, &added_at);
+        // Then
+        assert_eq!(vec![song("id_c"), song("id_d"), song("id_a"), song("id_b")], songs);
+        assert_eq!(None, parse_added_at("yesterday"));
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+
+use csv_to_playlist::{has_track_id,DryRun,Song};
+use track_uri::TrackUri;
+
+extern crate serde_json;
+use self::serde_json::Value;
+
+/// The version of the dry run artifact format. Increase this when the format
+/// changes
+const ARTIFACT_VERSION: u32 = 1;
+
+/// Where the songs of a dry run came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditInput {
+    /// The file, URL or table that was read
+    pub source: String,
+    /// The SHA-256 of the file, when it was read from disk
+    pub sha256: Option<String>,
+    /// The number of songs that were read
+    pub songs: usize,
+    /// The lines of rows without a usable track ID
+    pub failed_lines: Vec<usize>,
+}
+
+/// The playlist a dry run would change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditPlaylist {
+    pub name: String,
+    /// `None` when the playlist doesn't exist yet and would be created
+    pub id: Option<String>,
+    /// The snapshot ID of the playlist when the dry run was made, so that
+    /// later changes to it can be spotted
+    pub snapshot_id: Option<String>,
+}
+
+/// What a dry run would do with a song
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchOutcome {
+    /// The track would be added
+    Add,
+    /// The track is already in the playlist
+    Duplicate,
+    /// The track wouldn't be added this time, eg. past `--limit`
+    Skipped,
+    /// The song has no ID that can be added
+    NoTrack,
+}
+
+/// The track that a song was matched to and what would happen to it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SongMatch {
+    pub music: String,
+    pub song_id: String,
+    pub track: Option<TrackUri>,
+    pub outcome: MatchOutcome,
+}
+
+/// A change that a dry run would make
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Mutation {
+    /// The playlist would be created first
+    CreatePlaylist { name: String, public: bool },
+    /// The tracks would be added, at `position` or else at the end
+    AddTracks { tracks: Vec<TrackUri>, position: Option<usize> },
+}
+
+/// Everything a dry run found, saved so that it can be archived as a record
+/// of what an import would have done and why
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DryRunArtifact {
+    pub version: u32,
+    /// When the dry run was made, as RFC 3339
+    pub created: String,
+    pub input: AuditInput,
+    pub playlist: AuditPlaylist,
+    /// Each song that was read, in order
+    pub matches: Vec<SongMatch>,
+    /// The changes that would be made, in order
+    pub mutations: Vec<Mutation>,
+    /// The options the dry run was made with
+    pub config: BTreeMap<String, Value>,
+}
+
+impl DryRunArtifact {
+    /// Save the artifact as JSON
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the artifact file
+    pub fn write(&self, filename: &str) -> Result<(), Box<Error>> {
+        let file = File::create(filename)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+/// Put together the artifact for a dry run
+///
+/// # Arguments
+///
+/// * `created` - When the dry run was made, as RFC 3339
+/// * `input` - Where the songs came from
+/// * `playlist` - The playlist that would be changed
+/// * `songs` - The songs that were read
+/// * `dry_run` - The tracks that would be added and skipped, see
+/// `dry_run_add_songs`
+/// * `public` - Whether the playlist would be created as public
+/// * `position` - Where the tracks would be added, or `None` for the end
+/// * `config` - The options the dry run was made with
+#[allow(clippy::too_many_arguments)]
+pub fn build_artifact(created: &str,
+                      input: AuditInput,
+                      playlist: AuditPlaylist,
+                      songs: &[Song],
+                      dry_run: &DryRun,
+                      public: bool,
+                      position: Option<usize>,
+                      config: BTreeMap<String, Value>) -> DryRunArtifact {
+    let matches = songs.iter().map(|song| {
+        let track = Some(TrackUri::parse(&song.song_id))
+            .filter(|track| has_track_id(song) && track.is_addable());
+        let outcome = match track {
+            None => MatchOutcome::NoTrack,
+            Some(ref track) if dry_run.additions.contains(track) => MatchOutcome::Add,
+            Some(ref track) if dry_run.duplicates.contains(track) => MatchOutcome::Duplicate,
+            Some(_) => MatchOutcome::Skipped,
+        };
+        SongMatch { music: song.music.clone(), song_id: song.song_id.clone(), track: track, outcome: outcome }
+    }).collect();
+    let mut mutations = Vec::new();
+    if playlist.id.is_none() {
+        mutations.push(Mutation::CreatePlaylist { name: playlist.name.clone(), public: public });
+    }
+    if !dry_run.additions.is_empty() {
+        mutations.push(Mutation::AddTracks { tracks: dry_run.additions.clone(), position: position });
+    }
+    DryRunArtifact {
+        version: ARTIFACT_VERSION,
+        created: created.to_owned(),
+        input: input,
+        playlist: playlist,
+        matches: matches,
+        mutations: mutations,
+        config: config,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(music: &str, song_id: &str) -> Song {
+        Song { music: music.to_string(), song_id: song_id.to_string() }
+    }
+
+    #[test]
+    fn artifact_records_matches_and_mutations() {
+        // Given
+        let songs = vec![
+            song("Robyn - Honey", "honey00000000000000000"),
+            song("Robyn - Missing U", "spotify:track:missingu00000000000000"),
+            song("Robyn - Ever Again", "ever000000000000000000"),
+            song("Robyn - Human Being", "true"),
+        ];
+        let dry_run = DryRun {
+            additions: vec![TrackUri::Track("honey00000000000000000".to_string())],
+            duplicates: vec![TrackUri::Track("missingu00000000000000".to_string())],
+        };
+        let input = AuditInput { source: "songs.csv".to_string(), sha256: None, songs: 4, failed_lines: vec![5] };
+        let playlist = AuditPlaylist { name: "Honey".to_string(), id: None, snapshot_id: None };
+        let mut config = BTreeMap::new();
+        config.insert("limit".to_string(), Value::from("1"));
+        // When
+        let artifact = build_artifact(
+            "2018-03-01T10:00:00+00:00", input, playlist, &songs, &dry_run, false, None, config
+        );
+        // Then
+        let outcomes: Vec<MatchOutcome> = artifact.matches.iter().map(|m| m.outcome).collect();
+        assert_eq!(
+            vec![MatchOutcome::Add, MatchOutcome::Duplicate, MatchOutcome::Skipped, MatchOutcome::NoTrack],
+            outcomes
+        );
+        assert_eq!(vec![
+            Mutation::CreatePlaylist { name: "Honey".to_string(), public: false },
+            Mutation::AddTracks { tracks: vec![TrackUri::Track("honey00000000000000000".to_string())], position: None },
+        ], artifact.mutations);
+        let json = serde_json::to_value(&artifact).unwrap();
+        assert_eq!(Some("create_playlist"), json.pointer("/mutations/0/action").and_then(Value::as_str));
+        assert_eq!(Some("no_track"), json.pointer("/matches/3/outcome").and_then(Value::as_str));
+        assert_eq!(Some("spotify:track:honey00000000000000000"), json.pointer("/matches/0/track").and_then(Value::as_str));
+    }
+}
@@ -0,0 +1,56 @@
+use std::error::Error;
+use std::io;
+
+extern crate aws_config;
+use self::aws_config::{BehaviorVersion,Region,SdkConfig};
+
+extern crate tokio;
+use self::tokio::runtime::{Builder,Runtime};
+
+/// Which AWS credentials and region to use. Anything not given is found the
+/// way the AWS CLI finds it, eg. from `AWS_PROFILE`, `AWS_REGION` or
+/// `~/.aws/config`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AwsOptions {
+    /// The region, eg. `us-east-1`
+    pub region: Option<String>,
+    /// The named profile in `~/.aws/credentials`
+    pub profile: Option<String>,
+}
+
+impl AwsOptions {
+    /// Load the AWS config for making requests with the SDK
+    ///
+    /// # Arguments
+    ///
+    /// * `runtime` - The runtime to load with, see `runtime`
+    pub fn load(&self, runtime: &Runtime) -> SdkConfig {
+        let mut loader = aws_config::defaults(BehaviorVersion::latest());
+        if let Some(ref region) = self.region {
+            loader = loader.region(Region::new(region.to_owned()));
+        }
+        if let Some(ref profile) = self.profile {
+            loader = loader.profile_name(profile);
+        }
+        runtime.block_on(loader.load())
+    }
+}
+
+/// A runtime to wait for the SDK's requests on. Requests are made one at a
+/// time, so it only needs the current thread
+pub fn runtime() -> io::Result<Runtime> {
+    Builder::new_current_thread().enable_all().build()
+}
+
+/// Describe an error along with what caused it, since the SDK's own
+/// messages, eg. "dispatch failure", leave the cause out
+pub fn describe_error(e: &Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
@@ -0,0 +1,239 @@
+use std::error::Error;
+use std::fs::File;
+
+use name_match::{find_track,has_artist,same_name,search_value};
+use playlist::{PlaylistAPI,TrackMetadata};
+use user_agent;
+
+extern crate reqwest;
+use self::reqwest::header::Cookie;
+
+extern crate serde_json;
+
+const COLLECTION_SUMMARY_URL: &str = "https://bandcamp.com/api/fan/2/collection_summary";
+const COLLECTION_ITEMS_URL: &str = "https://bandcamp.com/api/fancollection/1/collection_items";
+
+/// The number of items to ask for in each request
+const ITEMS_PER_PAGE: usize = 100;
+
+/// Bandcamp pages the collection from the newest purchase using tokens. This
+/// token is newer than any purchase, so it starts from the top
+const NEWEST_TOKEN: &str = "9999999999::a::";
+
+/// The most tracks to look at when matching an album. Albums with more
+/// tracks than this are only partly matched
+const ALBUM_SEARCH_LIMIT: u32 = 50;
+
+#[derive(Deserialize)]
+struct CollectionSummary {
+    fan_id: u64,
+}
+
+#[derive(Serialize)]
+struct CollectionItemsRequest<'a> {
+    fan_id: u64,
+    older_than_token: &'a str,
+    count: usize,
+}
+
+/// A page of the collection. This is also the format of a saved collection
+#[derive(Deserialize)]
+struct CollectionPage {
+    items: Vec<CollectionItem>,
+    #[serde(default)]
+    more_available: bool,
+    last_token: Option<String>,
+}
+
+/// An album or track that the fan bought. Merch that comes with an album is
+/// an album
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CollectionItem {
+    /// `a` for albums and `t` for tracks
+    pub tralbum_type: String,
+    pub item_title: String,
+    pub band_name: String,
+}
+
+impl CollectionItem {
+    fn is_album(&self) -> bool {
+        self.tralbum_type == "a"
+    }
+}
+
+/// Read a collection that was saved from Bandcamp's `collection_items` API
+///
+/// # Arguments
+///
+/// * `filename` - The path to the saved JSON
+pub fn read_collection(filename: &str) -> Result<Vec<CollectionItem>, Box<Error>> {
+    let page: CollectionPage = serde_json::from_reader(File::open(filename)?)?;
+    Ok(page.items)
+}
+
+/// Get every item in a fan's collection, newest purchase first
+///
+/// # Arguments
+///
+/// * `identity` - The value of the `identity` cookie from a browser that's
+/// logged in to Bandcamp
+pub fn get_collection(identity: &str) -> Result<Vec<CollectionItem>, Box<Error>> {
+    let client = user_agent::client();
+    let mut cookie = Cookie::new();
+    cookie.append("identity", identity.to_owned());
+    let summary: CollectionSummary = client.get(COLLECTION_SUMMARY_URL)
+        .header(cookie.clone())
+        .send()?
+        .error_for_status()?
+        .json()?;
+    let mut items = Vec::new();
+    let mut token = NEWEST_TOKEN.to_owned();
+    loop {
+        let page: CollectionPage = client.post(COLLECTION_ITEMS_URL)
+            .header(cookie.clone())
+            .json(&CollectionItemsRequest {
+                fan_id: summary.fan_id,
+                older_than_token: &token,
+                count: ITEMS_PER_PAGE,
+            })
+            .send()?
+            .error_for_status()?
+            .json()?;
+        debug!("Fetched {} items from the Bandcamp collection", page.items.len());
+        items.extend(page.items);
+        match page.last_token {
+            Some(last_token) if page.more_available => token = last_token,
+            _ => break,
+        }
+    }
+    Ok(items)
+}
+
+/// The tracks on Spotify that match a collection item and what couldn't be
+/// matched
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matches {
+    /// The matching tracks, in the order of the collection
+    pub tracks: Vec<TrackMetadata>,
+    /// The items without a match
+    pub unmatched: Vec<CollectionItem>,
+}
+
+/// Find each item of a collection on Spotify by name. Tracks match on their
+/// name and artist. Albums add every track found from an album with the
+/// same name and artist.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance to search with
+/// * `items` - The Bandcamp collection
+pub fn match_collection<E>(playlist_api: &PlaylistAPI<E>,
+                           items: &[CollectionItem]) -> Result<Matches, E> {
+    let mut matches = Matches { tracks: Vec::new(), unmatched: Vec::new() };
+    for item in items {
+        let found: Vec<TrackMetadata> = if item.is_album() {
+            let query = format!(
+                "album:\"{}\" artist:\"{}\"", search_value(&item.item_title), search_value(&item.band_name)
+            );
+            playlist_api.search_tracks(&query, ALBUM_SEARCH_LIMIT)?
+                .into_iter()
+                .filter(|track| same_name(&track.album, &item.item_title) && has_artist(track, &item.band_name))
+                .collect()
+        } else {
+            find_track(playlist_api, &item.item_title, &item.band_name)?.into_iter().collect()
+        };
+        if found.is_empty() {
+            matches.unmatched.push(item.clone());
+        }
+        matches.tracks.extend(found);
+    }
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_api::{FakeError,FakePlaylistAPI};
+    use track_uri::TrackUri;
+
+    use std::cell::RefCell;
+
+    /// Returns the same search results for every search
+    struct MockPlaylistAPI {
+        search_tracks_returns: Vec<TrackMetadata>,
+        searches: RefCell<Vec<String>>,
+    }
+
+    impl FakePlaylistAPI for MockPlaylistAPI {
+        #[allow(unused_variables)]
+        fn search_tracks(&self, query: &str, limit: u32) -> Result<Vec<TrackMetadata>, FakeError> {
+            self.searches.borrow_mut().push(query.to_owned());
+            Ok(self.search_tracks_returns.clone())
+        }
+    }
+
+    fn track(id: &str, name: &str, artist: &str, album: &str) -> TrackMetadata {
+        TrackMetadata {
+            uri: TrackUri::Track(id.to_string()),
+            name: name.to_string(),
+            artists: vec![artist.to_string()],
+            album: album.to_string(),
+            duration_ms: 0,
+            image_url: None,
+        }
+    }
+
+    fn item(tralbum_type: &str, title: &str, band: &str) -> CollectionItem {
+        CollectionItem {
+            tralbum_type: tralbum_type.to_string(),
+            item_title: title.to_string(),
+            band_name: band.to_string(),
+        }
+    }
+
+    #[test]
+    fn match_collection_matches_names() {
+        // Given
+        let api = MockPlaylistAPI {
+            search_tracks_returns: vec![
+                track("1", "Pixel Lights", "Cool Band", "Night \"Drive\""),
+                track("2", "Night Drive", "Cool Band", "Singles"),
+                track("3", "Highway", "cool band", "night \"drive\" "),
+                track("4", "Night Drive", "Cover Band", "Night \"Drive\""),
+            ],
+            searches: RefCell::new(Vec::new()),
+        };
+        let items = [
+            item("a", "Night \"Drive\"", "Cool Band"),
+            item("t", "Night Drive", "Cool Band"),
+            item("t", "Unreleased", "Cool Band"),
+        ];
+        // When
+        let matches = match_collection(&api, &items).unwrap();
+        // Then
+        let ids: Vec<TrackUri> = matches.tracks.into_iter().map(|track| track.uri).collect();
+        assert_eq!(vec![
+            TrackUri::Track("1".to_string()),
+            TrackUri::Track("3".to_string()),
+            TrackUri::Track("2".to_string()),
+        ], ids);
+        assert_eq!(vec![item("t", "Unreleased", "Cool Band")], matches.unmatched);
+        assert_eq!(
+            "album:\"Night Drive\" artist:\"Cool Band\"",
+            api.searches.borrow()[0]
+        );
+    }
+
+    #[test]
+    fn saved_collection_can_be_parsed() {
+        // Given
+        let json = r#"{"more_available": false, "last_token": "1520000000:1:a::", "items": [
+            {"tralbum_type": "a", "item_type": "package", "item_title": "Night Drive",
+             "band_name": "Cool Band", "album_title": "Night Drive"}
+        ]}"#;
+        // When
+        let page: CollectionPage = serde_json::from_str(json).unwrap();
+        // Then
+        assert_eq!(vec![item("a", "Night Drive", "Cool Band")], page.items);
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::str::FromStr;
+
+use playlist::{get_playlist_id_create_if_needed,PlaylistAPI,PlaylistError};
+use track_uri::TrackUri;
+
+extern crate serde_json;
+
+/// The most items Spotify allows in a playlist
+pub const PLAYLIST_CAPACITY: usize = 10_000;
+
+/// What to do when adding tracks would take a playlist past
+/// `PLAYLIST_CAPACITY`
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum OnFull {
+    /// Add nothing and fail with `PlaylistFull`
+    #[default]
+    Abort,
+    /// Add the tracks that fit and skip the rest
+    Truncate,
+    /// Add the tracks that fit and the rest to continuation playlists, see
+    /// `spill_tracks`
+    Spill,
+}
+
+/// The error when an on full policy isn't known
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownOnFull {}
+
+impl Error for UnknownOnFull {
+    fn description(&self) -> &str {
+        "Expected abort, truncate or spill"
+    }
+}
+
+impl fmt::Display for UnknownOnFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl FromStr for OnFull {
+    type Err = UnknownOnFull;
+
+    fn from_str(s: &str) -> Result<OnFull, UnknownOnFull> {
+        match s {
+            "abort" => Ok(OnFull::Abort),
+            "truncate" => Ok(OnFull::Truncate),
+            "spill" => Ok(OnFull::Spill),
+            _ => Err(UnknownOnFull {}),
+        }
+    }
+}
+
+/// The error when the tracks won't fit in the playlist
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PlaylistFull {
+    /// The number of items already in the playlist
+    pub existing: usize,
+    /// The number of tracks that were going to be added
+    pub adding: usize,
+}
+
+impl Error for PlaylistFull {
+    fn description(&self) -> &str {
+        "The playlist would go over Spotify's limit of 10,000 tracks"
+    }
+}
+
+impl fmt::Display for PlaylistFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "{}. It has {} tracks and {} were going to be added",
+            self.description(), self.existing, self.adding
+        )
+    }
+}
+
+/// Split the tracks into those that fit in a playlist and those that don't,
+/// keeping their order
+///
+/// # Arguments
+///
+/// * `existing` - The number of items already in the playlist
+/// * `tracks` - The tracks to be added
+pub fn split_at_capacity(existing: usize, mut tracks: Vec<TrackUri>) -> (Vec<TrackUri>, Vec<TrackUri>) {
+    let room = PLAYLIST_CAPACITY.saturating_sub(existing);
+    let overflow = if tracks.len() > room {
+        tracks.split_off(room)
+    } else {
+        Vec::new()
+    };
+    (tracks, overflow)
+}
+
+/// The name of one part of a playlist that's too big for Spotify. The first
+/// part is the playlist itself and later parts carry on from it, eg.
+/// `Liked (Part 2)`
+///
+/// # Arguments
+///
+/// * `playlist_name` - The name of the playlist
+/// * `part` - Which part this is, starting from 1
+pub fn part_name(playlist_name: &str, part: usize) -> String {
+    match part {
+        0 | 1 => playlist_name.to_owned(),
+        _ => format!("{} (Part {})", playlist_name, part),
+    }
+}
+
+/// Tracks that were added to a later part of a playlist
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpilledPart {
+    /// Which part of the playlist, see `part_name`
+    pub part: usize,
+    /// The name of the part's playlist
+    pub name: String,
+    /// The tracks that were added to it
+    pub tracks: Vec<TrackUri>,
+}
+
+/// Add the tracks that didn't fit in one part of a playlist to the parts
+/// after it, creating them as needed. Tracks already in a later part are
+/// skipped. Returns each part that was added to.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the tracks should be added
+/// * `playlist_name` - The name of the playlist, ie. its first part
+/// * `full_part` - The part that the tracks didn't fit in
+/// * `tracks` - The tracks that didn't fit
+pub fn spill_tracks<E>(playlist_api: &PlaylistAPI<E>,
+                       playlist_name: &str,
+                       full_part: usize,
+                       tracks: Vec<TrackUri>) -> Result<Vec<SpilledPart>, PlaylistError<E>> {
+    let mut remaining = tracks;
+    let mut spilled = Vec::new();
+    let mut part = full_part.max(1) + 1;
+    while !remaining.is_empty() {
+        let name = part_name(playlist_name, part);
+        let playlist_id = get_playlist_id_create_if_needed(playlist_api, &name)?;
+        let existing = playlist_api.get_track_ids_in_playlist(&playlist_id)
+            .map_err(PlaylistError::APIError)?;
+        remaining.retain(|track| !existing.contains(track));
+        let (fits, overflow) = split_at_capacity(existing.len(), remaining);
+        if !fits.is_empty() {
+            playlist_api.add_tracks_to_playlist(&playlist_id, &fits, None)
+                .map_err(PlaylistError::APIError)?;
+            info!("Added {} tracks to {}", fits.len(), name);
+            spilled.push(SpilledPart { part: part, name: name, tracks: fits });
+        }
+        remaining = overflow;
+        part += 1;
+    }
+    Ok(spilled)
+}
+
+/// The part of each playlist that new tracks are added to. This is kept
+/// between runs so that once a part is full, later imports go straight to
+/// the part after it.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Parts {
+    /// The part being added to, keyed by playlist name
+    active: BTreeMap<String, usize>,
+}
+
+impl Parts {
+    /// Read parts that were saved using `write`. A missing file is treated
+    /// as every playlist being on its first part.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the parts file
+    pub fn read(filename: &str) -> Result<Parts, Box<Error>> {
+        match File::open(filename) {
+            Ok(file) => Ok(serde_json::from_reader(file)?),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Parts::default()),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    /// Save the parts as JSON
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the parts file
+    pub fn write(&self, filename: &str) -> Result<(), Box<Error>> {
+        let file = File::create(filename)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// The part of the playlist that tracks are added to, starting from 1
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_name` - The name of the playlist, ie. its first part
+    pub fn active_part(&self, playlist_name: &str) -> usize {
+        self.active.get(playlist_name).cloned().unwrap_or(1)
+    }
+
+    /// Record the parts that were spilled into, so that the last one is
+    /// added to next time
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_name` - The name of the playlist, ie. its first part
+    /// * `spilled` - The parts that tracks were added to
+    pub fn record(&mut self, playlist_name: &str, spilled: &[SpilledPart]) {
+        if let Some(last) = spilled.iter().map(|part| part.part).max() {
+            let active = self.active.entry(playlist_name.to_owned()).or_insert(1);
+            *active = (*active).max(last);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracks(count: usize) -> Vec<TrackUri> {
+        (0..count).map(|i| TrackUri::Track(i.to_string())).collect()
+    }
+
+    #[test]
+    fn split_at_capacity_keeps_tracks_that_fit() {
+        // When
+        let (fits, overflow) = split_at_capacity(PLAYLIST_CAPACITY - 2, tracks(5));
+        let (all, none) = split_at_capacity(0, tracks(5));
+        let (full, rest) = split_at_capacity(PLAYLIST_CAPACITY + 1, tracks(1));
+        // Then
+        assert_eq!(tracks(2), fits);
+        assert_eq!(tracks(5)[2..].to_vec(), overflow);
+        assert_eq!((tracks(5), Vec::new()), (all, none));
+        assert_eq!((Vec::new(), tracks(1)), (full, rest));
+    }
+
+    #[test]
+    fn parts_follow_the_last_spilled_part() {
+        // Given
+        let mut parts = Parts::default();
+        let spilled = |part| SpilledPart { part: part, name: part_name("Liked", part), tracks: tracks(1) };
+        // When
+        parts.record("Liked", &[spilled(2), spilled(3)]);
+        parts.record("Liked", &[]);
+        // Then
+        assert_eq!(3, parts.active_part("Liked"));
+        assert_eq!(1, parts.active_part("Other"));
+        assert_eq!("Liked (Part 3)", part_name("Liked", 3));
+        assert_eq!("Liked", part_name("Liked", 1));
+    }
+}
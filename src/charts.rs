@@ -0,0 +1,295 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use csv_to_playlist::Song;
+use name_match::find_track;
+use playlist::PlaylistAPI;
+use user_agent;
+
+extern crate chrono;
+use self::chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+/// The words that join a lead artist to the artists featured on a track
+const FEATURING_SEPARATORS: [&str; 7] = [" featuring ", " feat. ", " ft. ", " ft ", " & ", " x ", ", "];
+
+/// A weekly singles chart that can be read
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Chart {
+    /// The Billboard Hot 100, see https://www.billboard.com/charts/hot-100
+    BillboardHot100,
+    /// The first 40 of the Official Singles Chart, see
+    /// https://www.officialcharts.com/charts/singles-chart
+    UkTop40,
+}
+
+/// The error when a chart name isn't known
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownChart {}
+
+impl Error for UnknownChart {
+    fn description(&self) -> &str {
+        "Expected billboard-hot-100 or uk-top-40"
+    }
+}
+
+impl fmt::Display for UnknownChart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl FromStr for Chart {
+    type Err = UnknownChart;
+
+    fn from_str(s: &str) -> Result<Chart, UnknownChart> {
+        match s {
+            "billboard-hot-100" => Ok(Chart::BillboardHot100),
+            "uk-top-40" => Ok(Chart::UkTop40),
+            _ => Err(UnknownChart {}),
+        }
+    }
+}
+
+impl fmt::Display for Chart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Chart::BillboardHot100 => write!(f, "Billboard Hot 100"),
+            Chart::UkTop40 => write!(f, "UK Top 40"),
+        }
+    }
+}
+
+impl Chart {
+    /// Get the date of the chart that covers a day. Billboard charts are
+    /// dated by the Saturday that ends their week and Official Charts by the
+    /// Friday that starts it.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - Any day in the chart's week
+    pub fn chart_date(&self, date: NaiveDate) -> NaiveDate {
+        match *self {
+            Chart::BillboardHot100 => {
+                let days_until = (Weekday::Sat.num_days_from_monday() + 7
+                    - date.weekday().num_days_from_monday()) % 7;
+                date + Duration::days(i64::from(days_until))
+            },
+            Chart::UkTop40 => {
+                let days_since = (date.weekday().num_days_from_monday() + 7
+                    - Weekday::Fri.num_days_from_monday()) % 7;
+                date - Duration::days(i64::from(days_since))
+            },
+        }
+    }
+
+    fn url(&self, chart_date: NaiveDate) -> String {
+        match *self {
+            Chart::BillboardHot100 => format!(
+                "https://www.billboard.com/charts/hot-100/{}/", chart_date.format("%Y-%m-%d")
+            ),
+            // 7501 is the ID of the singles chart
+            Chart::UkTop40 => format!(
+                "https://www.officialcharts.com/charts/singles-chart/{}/7501/", chart_date.format("%Y%m%d")
+            ),
+        }
+    }
+
+    fn parse(&self, html: &str) -> Vec<ChartEntry> {
+        match *self {
+            Chart::BillboardHot100 => parse_entries(
+                html, "class=\"o-chart-results-list-row ", "id=\"title-of-a-story\"", "class=\"c-label  a-no-trucate", 100
+            ),
+            Chart::UkTop40 => parse_entries(
+                html, "class=\"chart-item ", "class=\"chart-name ", "class=\"chart-artist ", 40
+            ),
+        }
+    }
+}
+
+/// A song in a chart
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartEntry {
+    pub position: usize,
+    pub title: String,
+    /// The artists as the chart credits them, eg. `Drake Featuring Future`
+    pub artist: String,
+}
+
+impl ChartEntry {
+    /// Get the artist the song is credited to first, since that's who
+    /// Spotify will have it under
+    pub fn lead_artist(&self) -> &str {
+        // ASCII only so that positions in it are the same as in the artist
+        let lowercase = self.artist.to_ascii_lowercase();
+        let end = FEATURING_SEPARATORS.iter()
+            .filter_map(|separator| lowercase.find(separator))
+            .min()
+            .unwrap_or(self.artist.len());
+        self.artist[..end].trim()
+    }
+}
+
+/// Replace the HTML entities that chart pages use in names
+fn decode_entities(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#039;", "'")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Get the text of the element that has an attribute, without any tags
+/// inside it
+///
+/// # Arguments
+///
+/// * `html` - The HTML to search
+/// * `attribute` - Text from the element's opening tag, eg. `class="title "`
+fn element_text(html: &str, attribute: &str) -> Option<String> {
+    let start = html.find(attribute)?;
+    let tag_start = html[..start].rfind('<')?;
+    let tag_name: String = html[tag_start + 1..].chars().take_while(|c| c.is_alphanumeric()).collect();
+    let content_start = start + html[start..].find('>')? + 1;
+    let content_end = content_start + html[content_start..].find(&format!("</{}>", tag_name))?;
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html[content_start..content_end].chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {},
+        }
+    }
+    let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if text.is_empty() {
+        return None;
+    }
+    Some(decode_entities(&text))
+}
+
+/// Read the entries from a chart page, where each entry has the same markup
+///
+/// # Arguments
+///
+/// * `html` - The chart page
+/// * `row` - Text from the opening tag of each entry
+/// * `title` - Text from the opening tag of the entry's title
+/// * `artist` - Text from the opening tag of the entry's artist
+/// * `size` - The number of entries in the chart
+fn parse_entries(html: &str, row: &str, title: &str, artist: &str, size: usize) -> Vec<ChartEntry> {
+    html.split(row)
+        .skip(1)
+        .filter_map(|entry| {
+            // The artist comes after the title, and the markup before the
+            // title can look like the artist, eg. the position
+            let title_start = entry[..entry.find(title)?].rfind('<')?;
+            let entry = &entry[title_start..];
+            let title = element_text(entry, title)?;
+            let artist = element_text(entry, artist)?;
+            Some((title, artist))
+        })
+        .take(size)
+        .enumerate()
+        .map(|(i, (title, artist))| ChartEntry { position: i + 1, title: title, artist: artist })
+        .collect()
+}
+
+/// Get the entries of a chart in chart order. Fails if the page has no
+/// entries, which means the page has changed and the chart can't be read.
+///
+/// # Arguments
+///
+/// * `chart` - The chart to read
+/// * `date` - Any day in the chart's week, see `Chart::chart_date`
+pub fn get_chart(chart: Chart, date: NaiveDate) -> Result<Vec<ChartEntry>, Box<Error>> {
+    let url = chart.url(chart.chart_date(date));
+    let html = user_agent::client().get(&url).send()?.error_for_status()?.text()?;
+    let entries = chart.parse(&html);
+    debug!("Read {} entries from {}", entries.len(), url);
+    if entries.is_empty() {
+        return Err(From::from(format!("No chart entries found at {}", url)));
+    }
+    Ok(entries)
+}
+
+/// Find each chart entry on Spotify by its title and lead artist. Returns
+/// the songs to sync in chart order and the entries without a match.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance to search with
+/// * `entries` - The chart entries
+pub fn match_chart<E>(playlist_api: &PlaylistAPI<E>,
+                      entries: &[ChartEntry]) -> Result<(Vec<Song>, Vec<ChartEntry>), E> {
+    let mut songs = Vec::new();
+    let mut unmatched = Vec::new();
+    for entry in entries {
+        match find_track(playlist_api, &entry.title, entry.lead_artist())? {
+            Some(track) => songs.push(Song {
+                music: format!("{} - {}", entry.artist, entry.title),
+                song_id: track.uri.uri(),
+            }),
+            None => unmatched.push(entry.clone()),
+        }
+    }
+    Ok((songs, unmatched))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(position: usize, title: &str, artist: &str) -> ChartEntry {
+        ChartEntry { position: position, title: title.to_string(), artist: artist.to_string() }
+    }
+
+    #[test]
+    fn chart_date_picks_the_week_the_chart_covers() {
+        // 2024-01-03 is a Wednesday
+        let date = NaiveDate::from_ymd(2024, 1, 3);
+        assert_eq!(NaiveDate::from_ymd(2024, 1, 6), Chart::BillboardHot100.chart_date(date));
+        assert_eq!(NaiveDate::from_ymd(2023, 12, 29), Chart::UkTop40.chart_date(date));
+        let friday = NaiveDate::from_ymd(2024, 1, 5);
+        assert_eq!(friday, Chart::UkTop40.chart_date(friday));
+    }
+
+    #[test]
+    fn billboard_entries_are_read_in_order() {
+        // Given
+        let html = r#"<div><ul class="o-chart-results-list-row //">
+            <li><span class="c-label  a-font-primary-bold-l">1</span>
+            <h3 id="title-of-a-story" class="c-title a-no-trucate">
+                Lovin On Me
+            </h3><span class="c-label  a-no-trucate">
+                Jack Harlow
+            </span></li></ul>
+            <ul class="o-chart-results-list-row //">
+            <li><h3 id="title-of-a-story" class="c-title">Rich Baby Daddy</h3>
+            <span class="c-label  a-no-trucate">Drake Featuring Sexyy Red &amp; SZA</span></li></ul></div>"#;
+        // When
+        let entries = Chart::BillboardHot100.parse(html);
+        // Then
+        assert_eq!(vec![
+            entry(1, "Lovin On Me", "Jack Harlow"),
+            entry(2, "Rich Baby Daddy", "Drake Featuring Sexyy Red & SZA"),
+        ], entries);
+        assert_eq!("Drake", entries[1].lead_artist());
+    }
+
+    #[test]
+    fn official_charts_entries_are_read() {
+        // Given
+        let html = r#"<div class="chart-item relative"><div class="description">
+            <a class="chart-name font-bold" href="/songs/x/"><span></span><span>LOVIN ON ME</span></a>
+            <a class="chart-artist text-lg" href="/artist/y/"><span>JACK HARLOW</span></a>
+            </div></div>"#;
+        // When
+        let entries = Chart::UkTop40.parse(html);
+        // Then
+        assert_eq!(vec![entry(1, "LOVIN ON ME", "JACK HARLOW")], entries);
+        assert_eq!("Chart", entry(1, "x", "Chart FT Other").lead_artist());
+    }
+}
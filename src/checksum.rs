@@ -0,0 +1,84 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+
+extern crate openssl;
+use self::openssl::sha::sha256;
+
+/// An error when a file isn't the version that was expected
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChecksumMismatch {
+    filename: String,
+    expected: String,
+    actual: String,
+}
+
+impl Error for ChecksumMismatch {
+    fn description(&self) -> &str {
+        "File does not match the expected SHA-256"
+    }
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}: expected {}, got {}",
+               self.description(), self.filename, self.expected, self.actual)
+    }
+}
+
+/// Get the SHA-256 of the data as lowercase hex, as printed by `sha256sum`
+///
+/// # Arguments
+///
+/// * `data` - The contents to hash
+pub fn sha256_hex(data: &[u8]) -> String {
+    sha256(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Get the SHA-256 of a file's contents, see `sha256_hex`
+///
+/// # Arguments
+///
+/// * `filename` - The path to the file
+pub fn file_sha256(filename: &str) -> Result<String, Box<Error>> {
+    let mut data = Vec::new();
+    File::open(filename)?.read_to_end(&mut data)?;
+    Ok(sha256_hex(&data))
+}
+
+/// Check that a file has the expected SHA-256, so that a run only uses the
+/// exact version of a file that was reviewed
+///
+/// # Arguments
+///
+/// * `filename` - The path to the file
+/// * `expected` - The hex encoded SHA-256. Case is ignored
+pub fn verify_sha256(filename: &str, expected: &str) -> Result<(), Box<Error>> {
+    let actual = file_sha256(filename)?;
+    if actual != expected.trim().to_lowercase() {
+        return Err(Box::new(ChecksumMismatch {
+            filename: filename.to_owned(),
+            expected: expected.to_owned(),
+            actual: actual,
+        }));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_hex_matches_sha256sum() {
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            sha256_hex(b"")
+        );
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            sha256_hex(b"abc")
+        );
+    }
+}
@@ -0,0 +1,1493 @@
+use clap::{App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
+
+/// The Spotify app credentials, shared by every subcommand that talks to
+/// Spotify. These can also be set through the environment so that they don't
+/// need to be repeated, or kept in a profile in the config file
+fn credential_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("client_id")
+            .long("client-id")
+            .env("SPOTIFY_CLIENT_ID")
+            .takes_value(true)
+            .required_unless_one(&["profile", "provider"])
+            .help("Spotify Client ID"),
+        Arg::with_name("client_secret")
+            .long("client-secret")
+            .env("SPOTIFY_CLIENT_SECRET")
+            .takes_value(true)
+            .required_unless_one(&["profile", "provider"])
+            .hide_env_values(true)
+            .help("Spotify Client Secret"),
+        Arg::with_name("profile")
+            .long("profile")
+            .env("PLAYLIST_FROM_CSV_PROFILE")
+            .takes_value(true)
+            .help("Log in with a profile from the config file. Each profile \
+                  has its own cached token"),
+        Arg::with_name("config_file")
+            .long("config-file")
+            .env("PLAYLIST_FROM_CSV_CONFIG")
+            .takes_value(true)
+            .default_value("playlist-from-csv.toml")
+            .help("The config file that --profile is read from"),
+    ]
+}
+
+/// The arguments needed to log in as a user, see `login_from_args`
+fn login_args() -> Vec<Arg<'static, 'static>> {
+    let mut args = credential_args();
+    args.push(Arg::with_name("username")
+        .long("username")
+        .env("SPOTIFY_USERNAME")
+        .takes_value(true)
+        .required_unless_one(&["profile", "provider"])
+        .help("Spotify Username"));
+    args.push(Arg::with_name("non_interactive")
+        .long("non-interactive")
+        .help("Fail straight away if there's no cached token instead of \
+              opening a browser and waiting to log in, eg. in CI or cron"));
+    args
+}
+
+/// Where playlists are kept, see `provider_from_args`. The sandbox needs
+/// no credentials
+fn provider_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("provider")
+            .long("provider")
+            .takes_value(true)
+            .possible_values(&["spotify", "memory"])
+            .help("Where the playlists are. memory is a sandbox saved to \
+                  --state-file, to rehearse changes without logging in"),
+        Arg::with_name("state_file")
+            .long("state-file")
+            .takes_value(true)
+            .default_value("playlist-sandbox.json")
+            .help("Where --provider memory keeps its playlists"),
+    ]
+}
+
+/// Limits on how much work a run can do before it stops
+fn limit_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("max_requests")
+            .long("max-requests")
+            .takes_value(true)
+            .help("Stop once this many requests have been made to Spotify, \
+                  eg. to protect a shared client ID"),
+        Arg::with_name("deadline")
+            .long("deadline")
+            .takes_value(true)
+            .help("Stop making changes after this long, eg. 10m or 1h"),
+    ]
+}
+
+/// Whether the playlist can be created or already exist, see
+/// `create_policy_from_args`
+fn create_policy_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("no_create")
+            .long("no-create")
+            .help("Fail if the playlist doesn't exist instead of creating it, \
+                  eg. to catch a misspelt playlist name"),
+        Arg::with_name("create_only")
+            .long("create-only")
+            .conflicts_with_all(&["no_create", "every", "cron"])
+            .help("Fail if the playlist already exists instead of adding to it"),
+    ]
+}
+
+/// Where added tracks go in the playlist, see `Position`
+fn position_arg() -> Arg<'static, 'static> {
+    Arg::with_name("position")
+        .long("position")
+        .takes_value(true)
+        .value_name("start|end|N")
+        .default_value("end")
+        .help("Where to put the added tracks in the playlist: before the \
+              first track, after the last or before the track at index N, \
+              starting from 0")
+}
+
+/// The visibility of a playlist that gets created, see
+/// `visibility_from_args`
+fn public_arg() -> Arg<'static, 'static> {
+    Arg::with_name("public")
+        .long("public")
+        .help("Make the playlist public if it's created. An existing playlist \
+              is left as is, see set-visibility")
+}
+
+/// The details to set on the playlist after it's updated, see
+/// `update_details`
+fn details_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("description")
+            .long("description")
+            .takes_value(true)
+            .conflicts_with("description_template")
+            .help("Playlist description to set after each run"),
+        Arg::with_name("cover")
+            .long("cover")
+            .takes_value(true)
+            .help("JPEG to use as the playlist's cover image, up to 190KB"),
+    ]
+}
+
+fn playlist_name_arg() -> Arg<'static, 'static> {
+    Arg::with_name("playlist_name")
+        .required(true)
+        .help("Spotify Playlist name")
+}
+
+/// Where the notes read from a CSV's note column are kept, see `NoteIndex`
+fn notes_file_arg() -> Arg<'static, 'static> {
+    Arg::with_name("notes_file")
+        .long("notes-file")
+        .takes_value(true)
+        .default_value("playlist-notes.json")
+        .help("Where the notes from a csv's note or comment column are kept, \
+              since Spotify can't store a note on a track")
+}
+
+fn with_notes_arg() -> Arg<'static, 'static> {
+    Arg::with_name("with_notes")
+        .long("with-notes")
+        .help("Include the note on each track from --notes-file")
+}
+
+/// The CSV columns that songs are read from, see `CsvColumns`
+fn column_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("name_column")
+            .long("name-column")
+            .takes_value(true)
+            .help("The csv column with the name of each song. Defaults to \
+                  \"music (S)\""),
+        Arg::with_name("id_column")
+            .long("id-column")
+            .takes_value(true)
+            .help("The csv column with the Spotify ID, URI or link of each \
+                  song. Defaults to \"song_id (S)\""),
+        Arg::with_name("strict_headers")
+            .long("strict-headers")
+            .help("Only read columns whose headers match exactly. By default \
+                  case and spaces are ignored, eg. \"Music(S)\" is read as \
+                  \"music (S)\""),
+        Arg::with_name("encoding")
+            .long("encoding")
+            .takes_value(true)
+            .possible_values(&["utf-8", "utf-16le", "utf-16be"])
+            .help("The encoding of the csv. By default it's detected, eg. \
+                  UTF-16 from Excel's \"Unicode Text\", and a byte order \
+                  mark is skipped"),
+    ]
+}
+
+/// Where AWS credentials come from, for files in S3 and DynamoDB tables
+fn aws_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("region")
+            .long("region")
+            .takes_value(true)
+            .help("The AWS region of the S3 bucket or DynamoDB table. Defaults \
+                  to the region the AWS CLI would use"),
+        Arg::with_name("aws_profile")
+            .long("aws-profile")
+            .takes_value(true)
+            .help("The AWS profile to use for S3 and DynamoDB, eg. from \
+                  ~/.aws/credentials. Defaults to the profile the AWS CLI \
+                  would use"),
+    ]
+}
+
+/// Limits on downloading a file given as an http or https URL
+fn download_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("download_timeout")
+            .long("download-timeout")
+            .takes_value(true)
+            .default_value("30s")
+            .help("How long to wait for a CSV given as a URL, eg. 30s or 2m"),
+        Arg::with_name("max_download_mb")
+            .long("max-download-mb")
+            .takes_value(true)
+            .default_value("50")
+            .help("The biggest CSV to download from a URL, in megabytes"),
+    ]
+}
+
+fn input_format_arg() -> Arg<'static, 'static> {
+    Arg::with_name("input_format")
+        .long("format")
+        .takes_value(true)
+        .possible_values(&["csv", "dynamodb-json", "json", "m3u"])
+        .help("The format of the songs file. json is an array of objects \
+              with music and song_id fields and m3u is an M3U or M3U8 \
+              playlist. Defaults to dynamodb-json for .json and .jsonl \
+              files, m3u for .m3u and .m3u8 files and csv otherwise")
+}
+
+fn dedupe_against_arg() -> Arg<'static, 'static> {
+    Arg::with_name("dedupe_against")
+        .long("dedupe-against")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .help("Skip songs already in these playlists. Names ending in * \
+              match any playlist starting with the rest, eg. \"Monthly *\"")
+}
+
+fn expect_sha256_arg(file: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name("expect_sha256")
+        .long("expect-sha256")
+        .takes_value(true)
+        .help(file)
+}
+
+fn import_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("import")
+        .about("Create a playlist with songs from a csv. Use the `plan` and \
+               `apply` commands to review changes before they're made")
+        .args(&login_args())
+        .args(&column_args())
+        .arg(input_format_arg())
+        .args(&aws_args())
+        .args(&download_args())
+        .args(&provider_args())
+        .args(&limit_args())
+        .args(&create_policy_args())
+        .arg(public_arg())
+        .args(&details_args())
+        .arg(notes_file_arg())
+        .arg(playlist_name_arg()
+            .required_unless("profile")
+            .help("Spotify Playlist name. Can be left out when the --profile \
+                  has a playlist"))
+        .arg(Arg::with_name("csv_filename")
+            .required_unless_one(&["git_repo", "dynamodb_table", "profile"])
+            .help("CSV Filename, s3://bucket/key.csv, an https:// URL, or - \
+                  to read from stdin. Not needed with --git-repo or \
+                  --dynamodb-table"))
+        .arg(expect_sha256_arg("Stop unless the CSV has this SHA-256, eg. the \
+                               version that was reviewed")
+            .conflicts_with_all(&["git_repo", "dynamodb_table"]))
+        .arg(Arg::with_name("skip_invalid_rows")
+            .long("skip-invalid-rows")
+            .conflicts_with_all(&["git_repo", "dynamodb_table"])
+            .help("Skip rows of the CSV that can't be read, eg. with the wrong \
+                  number of fields, and list them rather than stopping"))
+        .arg(Arg::with_name("id_namespace")
+            .long("id-namespace")
+            .takes_value(true)
+            .possible_values(&["spotify", "isrc", "youtube", "apple-music", "file"])
+            .default_value("spotify")
+            .help("Where the IDs in the csv come from. Songs with isrc IDs, \
+                  or an isrc column, are found on Spotify by their ISRC and \
+                  the rest by their name. file is for paths of local files, \
+                  and is used for M3U playlists"))
+        .arg(Arg::with_name("stream")
+            .long("stream")
+            .conflicts_with_all(&[
+                "git_repo", "dynamodb_table", "skip_invalid_rows", "merge", "diff_only", "dry_run",
+                "confirm", "sort", "order_by_added_at", "collapse_versions", "spread_artists",
+                "rotate_at", "limit", "report_out", "ledger", "allow_duplicates", "dedupe_against",
+                "id_namespace",
+            ])
+            .help("Read the CSV and add its tracks a chunk of rows at a time, \
+                  for files too big to read at once, eg. a DynamoDB export \
+                  with millions of rows. Tracks are added to the end of the \
+                  playlist and the import stops if it fills up"))
+        .arg(Arg::with_name("chunk_size")
+            .long("chunk-size")
+            .takes_value(true)
+            .value_name("N")
+            .default_value("1000")
+            .help("How many rows --stream reads before adding their tracks"))
+        .arg(Arg::with_name("dynamodb_table")
+            .long("dynamodb-table")
+            .takes_value(true)
+            .conflicts_with_all(&["git_repo", "csv_filename"])
+            .help("Scan the songs from this DynamoDB table instead of an \
+                  export of it. Attributes are named with --name-column and \
+                  --id-column"))
+        .arg(Arg::with_name("git_repo")
+            .long("git-repo")
+            .takes_value(true)
+            .requires("git_file")
+            .help("Only import rows added to the CSV in this Git repository \
+                  since --since-ref"))
+        .arg(Arg::with_name("git_file")
+            .long("file")
+            .takes_value(true)
+            .help("Path of the CSV within --git-repo"))
+        .arg(Arg::with_name("since_ref")
+            .long("since-ref")
+            .takes_value(true)
+            .default_value("HEAD~1")
+            .help("Git revision to compare against, eg. origin/main~1"))
+        .arg(Arg::with_name("merge")
+            .long("merge")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Other CSVs to merge in. Each track is only added once"))
+        .arg(Arg::with_name("merge_priority")
+            .long("merge-priority")
+            .takes_value(true)
+            .possible_values(&["first", "last"])
+            .default_value("first")
+            .help("Which CSV's details win when a track differs between CSVs"))
+        .arg(Arg::with_name("provenance_out")
+            .long("provenance-out")
+            .takes_value(true)
+            .help("Save which CSV each merged track came from as JSON"))
+        .arg(Arg::with_name("description_template")
+            .long("description-template")
+            .takes_value(true)
+            .help("Playlist description to set after each run. Supports \
+                  {track_count}, {source}, {date} and {top_artist}"))
+        .arg(Arg::with_name("diff_only")
+            .long("diff-only")
+            .help("Print the pending changes as a diff without applying them. \
+                  Exits with 9 if there are changes"))
+        .arg(Arg::with_name("dry_run")
+            .long("dry-run")
+            .help("Print the tracks that would be added and the ones skipped \
+                  as duplicates without changing anything"))
+        .arg(Arg::with_name("dry_run_out")
+            .long("dry-run-out")
+            .takes_value(true)
+            .requires("dry_run")
+            .help("Save everything the --dry-run found as JSON, for an audit \
+                  trail: the CSV's SHA-256, the track each song matched, the \
+                  changes that would be made and the options used"))
+        .arg(Arg::with_name("every")
+            .long("every")
+            .takes_value(true)
+            .conflicts_with_all(&["cron", "dry_run", "diff_only", "confirm"])
+            .help("Keep running and import again this long after each \
+                  import started, eg. 6h. Ctrl-C stops once the current \
+                  import finishes"))
+        .arg(Arg::with_name("cron")
+            .long("cron")
+            .takes_value(true)
+            .conflicts_with_all(&["dry_run", "diff_only", "confirm"])
+            .help("Keep running and import at the times a cron expression \
+                  matches, eg. \"0 3 * * *\" for 3am each day"))
+        .arg(Arg::with_name("sort")
+            .long("sort")
+            .takes_value(true)
+            .possible_values(&["harmonic"])
+            .help("The order to add tracks in. harmonic orders them by key on \
+                  the Camelot wheel and by tempo, so that they mix well"))
+        .arg(Arg::with_name("journal")
+            .long("journal")
+            .conflicts_with_all(&["every", "cron"])
+            .help("Keep a journal of the tracks added so far, so that running \
+                  again after a failed import skips the batches that were \
+                  already added without fetching the whole playlist"))
+        .arg(Arg::with_name("journal_file")
+            .long("journal-file")
+            .takes_value(true)
+            .default_value(".playlist-from-csv-state.json")
+            .help("Where --journal is kept. It's removed once an import \
+                  finishes"))
+        .arg(Arg::with_name("order_by_added_at")
+            .long("order-by-added-at")
+            .conflicts_with("sort")
+            .help("Add the tracks in the order of the csv's added_at column, \
+                  eg. to restore an export so that it reads chronologically. \
+                  Spotify still dates each track as added today"))
+        .arg(Arg::with_name("collapse_versions")
+            .long("collapse-versions")
+            .takes_value(true)
+            .possible_values(&["popular", "album"])
+            .help("Only add one version of each song, eg. not both the single \
+                  and a remaster. Versions are found by their title without \
+                  notes like \"- Remastered\" and their first artist. popular \
+                  keeps the most played version and album keeps the album \
+                  version"))
+        .arg(Arg::with_name("spread_artists")
+            .long("spread-artists")
+            .takes_value(true)
+            .value_name("N")
+            .help("Reorder the added tracks so that the same artist doesn't \
+                  play twice within N tracks. This is done after --sort"))
+        .arg(position_arg())
+        .arg(Arg::with_name("on_full")
+            .long("on-full")
+            .takes_value(true)
+            .possible_values(&["abort", "truncate", "spill"])
+            .default_value("abort")
+            .help("What to do when the new tracks won't fit in the playlist, \
+                  since Spotify playlists hold at most 10,000 tracks. abort \
+                  adds nothing, truncate adds the tracks that fit and spill \
+                  adds the rest to \"<playlist name> (Part 2)\", \"(Part 3)\" \
+                  and so on"))
+        .arg(Arg::with_name("parts_file")
+            .long("parts-file")
+            .takes_value(true)
+            .default_value("playlist-parts.json")
+            .help("Where --on-full spill keeps which part of each playlist \
+                  is being added to, so that later imports go straight there"))
+        .arg(Arg::with_name("rotate_at")
+            .long("rotate-at")
+            .takes_value(true)
+            .value_name("N")
+            .help("Once the playlist has N tracks, add to \"<playlist name> \
+                  (2)\" instead, then \"(3)\" and so on, creating them as \
+                  needed. Songs in an earlier playlist are skipped"))
+        .arg(Arg::with_name("limit")
+            .long("limit")
+            .takes_value(true)
+            .value_name("N")
+            .help("Only add the first N new tracks in the csv, eg. to add a \
+                  few tracks to a playlist each day"))
+        .arg(Arg::with_name("confirm")
+            .long("confirm")
+            .help("Print the tracks that would be added and ask before adding \
+                  them"))
+        .arg(Arg::with_name("gha")
+            .long("gha")
+            .help("Write a step summary, annotations and outputs for GitHub \
+                  Actions. On by default when GITHUB_ACTIONS is set"))
+        .arg(Arg::with_name("report_out")
+            .long("report-out")
+            .takes_value(true)
+            .help("Save a report of the run with a link to the playlist, the \
+                  tracks that were added and any failures"))
+        .arg(Arg::with_name("report_format")
+            .long("report-format")
+            .takes_value(true)
+            .possible_values(&["md", "html"])
+            .default_value("md")
+            .help("Write the --report-out as Markdown or HTML"))
+        .arg(Arg::with_name("genius_token")
+            .long("genius-token")
+            .takes_value(true)
+            .env("GENIUS_ACCESS_TOKEN")
+            .help("Add the Genius page and songwriters of each added track to \
+                  the --report-out, using a Genius API client access token. \
+                  Not used without --report-out"))
+        .arg(dedupe_against_arg())
+        .arg(Arg::with_name("ledger")
+            .long("ledger")
+            .takes_value(true)
+            .help("Record every track imported into each playlist in this \
+                  file"))
+        .arg(Arg::with_name("allow_duplicates")
+            .long("allow-duplicates")
+            .help("Add a track once for each of its rows, eg. for DJ sets that \
+                  play a track twice, rather than only once. An allow_dup \
+                  column of true or false overrides this for a row's track"))
+        .arg(Arg::with_name("never_readd")
+            .long("never-readd")
+            .requires("ledger")
+            .help("Skip songs in the --ledger for this playlist, even if \
+                  they've since been removed from it"))
+}
+
+fn import_many_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("import-many")
+        .about("Import into many playlists at once, from a directory with a \
+               csv per playlist or a csv with a `playlist` column")
+        .args(&login_args())
+        .args(&limit_args())
+        .arg(Arg::with_name("path")
+            .required(true)
+            .help("Directory of csv files, named after their playlists, or a \
+                  grouped csv file"))
+        .arg(Arg::with_name("jobs")
+            .long("jobs")
+            .takes_value(true)
+            .default_value("4")
+            .help("The most playlists to import at the same time"))
+        .arg(dedupe_against_arg())
+}
+
+fn export_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("export")
+        .about("Save the tracks in a playlist as a csv that can be imported \
+               again")
+        .args(&login_args())
+        .args(&limit_args())
+        .arg(playlist_name_arg())
+        .arg(Arg::with_name("out")
+            .long("out")
+            .takes_value(true)
+            .help("Where to save the csv. Printed when this is not set"))
+        .arg(with_notes_arg())
+        .arg(notes_file_arg())
+}
+
+fn show_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("show")
+        .about("Print the tracks in a playlist in order")
+        .args(&login_args())
+        .args(&provider_args())
+        .args(&limit_args())
+        .arg(playlist_name_arg())
+        .arg(with_notes_arg())
+        .arg(notes_file_arg())
+}
+
+fn bandcamp_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("bandcamp")
+        .about("Find the albums and tracks bought on Bandcamp on Spotify and \
+               save them as a csv that can be imported")
+        .args(&login_args())
+        .args(&limit_args())
+        .arg(Arg::with_name("collection")
+            .required_unless("identity")
+            .help("Collection saved from Bandcamp's collection_items API as \
+                  JSON. Not needed with --identity"))
+        .arg(Arg::with_name("identity")
+            .long("identity")
+            .takes_value(true)
+            .conflicts_with("collection")
+            .help("Read the collection from Bandcamp using the identity \
+                  cookie of a logged in browser"))
+        .arg(Arg::with_name("out")
+            .long("out")
+            .takes_value(true)
+            .help("Where to save the csv. Printed when this is not set"))
+}
+
+fn sync_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("sync")
+        .about("Make a playlist match a csv exactly, in the same order. \
+               Tracks are only moved, added or removed where needed so that \
+               unchanged tracks keep their added date")
+        .args(&login_args())
+        .args(&column_args())
+        .arg(input_format_arg())
+        .args(&aws_args())
+        .args(&download_args())
+        .args(&provider_args())
+        .args(&limit_args())
+        .args(&create_policy_args())
+        .arg(public_arg())
+        .args(&details_args())
+        .arg(playlist_name_arg())
+        .arg(Arg::with_name("csv_filename")
+            .required(true)
+            .help("CSV Filename, s3://bucket/key.csv, an https:// URL, or - \
+                  to read from stdin"))
+        .arg(expect_sha256_arg("Stop unless the CSV has this SHA-256, eg. the \
+                               version that was reviewed"))
+        .arg(Arg::with_name("dry_run")
+            .long("dry-run")
+            .help("Print the changes without making them"))
+        .arg(Arg::with_name("quarantine")
+            .long("quarantine")
+            .takes_value(true)
+            .help("Move removed tracks into this playlist instead of deleting \
+                  them, eg. \"Removed by sync\""))
+        .arg(Arg::with_name("max_removals")
+            .long("max-removals")
+            .takes_value(true)
+            .help("Stop without changing anything if more than this many \
+                  tracks would be removed"))
+        .arg(Arg::with_name("max_changes_percent")
+            .long("max-changes-percent")
+            .takes_value(true)
+            .help("Stop without changing anything if more than this \
+                  percentage of the playlist would be removed or moved"))
+}
+
+fn plan_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("plan")
+        .about("Save the changes needed to add the songs from a csv so they \
+               can be applied later")
+        .args(&login_args())
+        .args(&column_args())
+        .arg(input_format_arg())
+        .args(&aws_args())
+        .args(&download_args())
+        .args(&limit_args())
+        .arg(playlist_name_arg())
+        .arg(Arg::with_name("csv_filename")
+            .required(true)
+            .help("CSV Filename, s3://bucket/key.csv, an https:// URL, or - \
+                  to read from stdin"))
+        .arg(expect_sha256_arg("Stop unless the CSV has this SHA-256, eg. the \
+                               version that was reviewed"))
+        .arg(position_arg())
+        .arg(public_arg())
+        .arg(Arg::with_name("remove_missing")
+            .long("remove-missing")
+            .help("Also remove the tracks in the playlist that aren't in the \
+                  csv. They're removed before the new tracks are added"))
+        .arg(Arg::with_name("out")
+            .long("out")
+            .takes_value(true)
+            .required(true)
+            .help("Where to save the plan"))
+}
+
+fn apply_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("apply")
+        .about("Make the changes saved by the plan command")
+        .args(&login_args())
+        .args(&limit_args())
+        .arg(Arg::with_name("plan")
+            .required(true)
+            .help("Plan Filename"))
+        .arg(expect_sha256_arg("Stop unless the plan has this SHA-256, eg. the \
+                               version that was reviewed"))
+        .arg(Arg::with_name("verify_key")
+            .long("verify-key")
+            .takes_value(true)
+            .help("Only apply the plan if it was signed by the private key \
+                  for this PEM encoded Ed25519, ECDSA or RSA public key"))
+}
+
+fn sign_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("sign")
+        .about("Sign a plan or snapshot so that it can be applied with \
+               --verify-key")
+        .arg(Arg::with_name("plan")
+            .required(true)
+            .help("Plan or snapshot Filename"))
+        .arg(Arg::with_name("key")
+            .long("key")
+            .takes_value(true)
+            .required(true)
+            .help("PEM encoded Ed25519, ECDSA or RSA private key to sign \
+                  with"))
+}
+
+fn preview_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("preview")
+        .about("Show what a playlist created from a csv would look like, \
+               without creating it")
+        .args(&login_args())
+        .args(&column_args())
+        .arg(input_format_arg()
+            .long("input-format"))
+        .args(&aws_args())
+        .args(&download_args())
+        .args(&limit_args())
+        .arg(playlist_name_arg())
+        .arg(Arg::with_name("csv_filename")
+            .required(true)
+            .help("CSV Filename, s3://bucket/key.csv, an https:// URL, or - \
+                  to read from stdin"))
+        .arg(Arg::with_name("format")
+            .long("format")
+            .takes_value(true)
+            .possible_values(&["md", "html"])
+            .default_value("md")
+            .help("Render the preview as Markdown or HTML"))
+        .arg(Arg::with_name("out")
+            .long("out")
+            .takes_value(true)
+            .help("Where to save the preview. Printed when not given"))
+}
+
+fn clone_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("clone")
+        .about("Copy a playlist's tracks, description and cover into a new \
+               playlist, eg. to make a copy of someone else's playlist that \
+               can be changed")
+        .args(&login_args())
+        .args(&provider_args())
+        .args(&limit_args())
+        .arg(Arg::with_name("from")
+            .long("from")
+            .takes_value(true)
+            .required(true)
+            .help("The name of the playlist to copy"))
+        .arg(Arg::with_name("to")
+            .long("to")
+            .takes_value(true)
+            .required(true)
+            .help("The name of the new playlist. Fails if it already exists"))
+        .arg(public_arg()
+            .help("Make the new playlist public"))
+}
+
+fn transfer_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("transfer")
+        .about("Move a playlist to a copy owned by you. The playlist is \
+               cloned and the copy is checked against it before the \
+               original is renamed or unfollowed")
+        .args(&login_args())
+        .args(&provider_args())
+        .args(&limit_args())
+        .arg(Arg::with_name("from")
+            .long("from")
+            .takes_value(true)
+            .required(true)
+            .help("The name of the playlist to move"))
+        .arg(Arg::with_name("to")
+            .long("to")
+            .takes_value(true)
+            .required(true)
+            .help("The name of the new playlist. Fails if it already exists"))
+        .arg(public_arg()
+            .help("Make the new playlist public"))
+        .arg(Arg::with_name("rename_original")
+            .long("rename-original")
+            .takes_value(true)
+            .help("Rename the original once the copy matches it, eg. \
+                  \"Road Trip (moved)\", so that its followers notice"))
+        .arg(Arg::with_name("unfollow_original")
+            .long("unfollow-original")
+            .help("Unfollow the original once the copy matches it"))
+}
+
+#[cfg(feature = "lambda")]
+fn lambda_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("lambda")
+        .about("Run as an AWS Lambda function triggered by a DynamoDB stream, \
+               adding the songs inserted into the table to a playlist")
+        .args(&login_args())
+        .args(&provider_args())
+        .args(&column_args())
+        .arg(playlist_name_arg())
+}
+
+#[cfg(feature = "gui")]
+fn gui_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("gui")
+        .about("Answer calls from a desktop GUI, one JSON object per line on \
+               stdin, eg. {\"id\":1,\"cmd\":\"list_playlists\"}. Replies \
+               are written to stdout")
+        .args(&login_args())
+        .args(&provider_args())
+}
+
+fn snapshot_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("snapshot")
+        .about("Save the tracks in a playlist so that they can be restored \
+               later")
+        .args(&login_args())
+        .args(&limit_args())
+        .arg(playlist_name_arg())
+        .arg(Arg::with_name("out")
+            .long("out")
+            .takes_value(true)
+            .required(true)
+            .help("Where to save the snapshot"))
+}
+
+fn restore_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("restore")
+        .about("Make a playlist match a snapshot exactly, creating it if \
+               needed")
+        .args(&login_args())
+        .args(&limit_args())
+        .arg(Arg::with_name("snapshot")
+            .required(true)
+            .help("Snapshot Filename"))
+        .arg(Arg::with_name("playlist_name")
+            .long("playlist")
+            .takes_value(true)
+            .help("Restore into this playlist instead of the one the \
+                  snapshot was taken from"))
+        .arg(Arg::with_name("dry_run")
+            .long("dry-run")
+            .help("Print the changes without making them"))
+        .arg(Arg::with_name("verify_key")
+            .long("verify-key")
+            .takes_value(true)
+            .help("Only restore the snapshot if it was signed by the private \
+                  key for this PEM encoded Ed25519, ECDSA or RSA public key"))
+}
+
+fn snapshot_order_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("snapshot-order")
+        .about("Save the order of the tracks in a playlist so that it can be \
+               put back with restore-order, eg. after a collaborator sorts it")
+        .args(&login_args())
+        .args(&provider_args())
+        .args(&limit_args())
+        .arg(Arg::with_name("playlist_name")
+            .long("playlist")
+            .takes_value(true)
+            .required(true)
+            .help("Spotify Playlist name"))
+        .arg(Arg::with_name("out")
+            .long("out")
+            .takes_value(true)
+            .required(true)
+            .help("Where to save the order"))
+}
+
+fn restore_order_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("restore-order")
+        .about("Move the tracks in a playlist back into the order saved by \
+               snapshot-order. Tracks are never added or removed")
+        .args(&login_args())
+        .args(&provider_args())
+        .args(&limit_args())
+        .arg(Arg::with_name("snapshot")
+            .required(true)
+            .help("Order snapshot Filename"))
+        .arg(Arg::with_name("playlist_name")
+            .long("playlist")
+            .takes_value(true)
+            .help("Reorder this playlist instead of the one the snapshot was \
+                  taken from"))
+        .arg(Arg::with_name("dry_run")
+            .long("dry-run")
+            .help("Print the moves without making them"))
+}
+
+fn config_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("config")
+        .about("Work with the config file of jobs")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("validate")
+            .about("Check a config file, listing every problem that's found")
+            .arg(Arg::with_name("config")
+                .required(true)
+                .help("Config Filename")))
+}
+
+fn daemon_subcommand() -> App<'static, 'static> {
+    let app = SubCommand::with_name("daemon")
+        .about("Keep running the jobs in a config file on their schedules. \
+               Changes to the config are picked up without restarting")
+        .args(&login_args())
+        .args(&provider_args())
+        .arg(Arg::with_name("config")
+            .required(true)
+            .help("Config Filename"))
+        .arg(Arg::with_name("feed")
+            .long("feed")
+            .takes_value(true)
+            .help("Save an Atom feed of the tracks each job adds, so that \
+                  people can follow the playlists in a feed reader"))
+        .arg(Arg::with_name("quiet_hours")
+            .long("quiet-hours")
+            .takes_value(true)
+            .value_name("HH:MM-HH:MM")
+            .help("Don't run jobs during these hours of the day, eg. \
+                  01:00-06:00 while another tool uses the same client ID. Jobs \
+                  that are due wait until they're over. A job's quiet_hours \
+                  replaces this"))
+        .arg(Arg::with_name("drip")
+            .long("drip")
+            .takes_value(true)
+            .value_name("TRACKS/PERIOD")
+            .help("Add the tracks import jobs find gradually, eg. 10/day or \
+                  1/hour, rather than all at once. The rest wait in a queue \
+                  in each job's state directory. A job's drip replaces this"));
+    #[cfg(unix)]
+    let app = app.arg(Arg::with_name("control_socket")
+        .long("control-socket")
+        .takes_value(true)
+        .help("Listen on this Unix socket for the commands trigger <job>, \
+              status, pause and resume, one per line"));
+    app
+}
+
+fn clean_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("clean")
+        .about("Delete the state that jobs keep between runs, eg. their \
+               ledger")
+        .arg(Arg::with_name("config")
+            .required(true)
+            .help("Config Filename"))
+        .arg(Arg::with_name("job")
+            .long("job")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .required_unless("all")
+            .help("The name of the job to delete the state of"))
+        .arg(Arg::with_name("all")
+            .long("all")
+            .conflicts_with("job")
+            .help("Delete the state of every job, including jobs that are no \
+                  longer in the config"))
+}
+
+fn remove_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("remove")
+        .about("Remove the songs in a csv from a playlist, eg. to clean up \
+               after a bad import")
+        .args(&login_args())
+        .args(&column_args())
+        .arg(input_format_arg())
+        .args(&aws_args())
+        .args(&download_args())
+        .args(&limit_args())
+        .arg(Arg::with_name("csv_filename")
+            .long("csv")
+            .takes_value(true)
+            .required(true)
+            .help("CSV Filename of the songs to remove, or - to read from \
+                  stdin"))
+        .arg(expect_sha256_arg("Stop unless the CSV has this SHA-256, eg. the \
+                               version that was reviewed"))
+        .arg(Arg::with_name("playlist_name")
+            .long("playlist")
+            .takes_value(true)
+            .required(true)
+            .help("Spotify Playlist name"))
+        .arg(Arg::with_name("dry_run")
+            .long("dry-run")
+            .help("Print the tracks that would be removed without removing \
+                  them"))
+        .arg(Arg::with_name("report")
+            .long("report")
+            .takes_value(true)
+            .help("Save what was removed as JSON"))
+}
+
+fn set_visibility_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("set-visibility")
+        .about("Make an existing playlist public or private")
+        .args(&login_args())
+        .arg(playlist_name_arg())
+        .arg(Arg::with_name("visibility")
+            .required(true)
+            .possible_values(&["public", "private"])
+            .help("Whether the playlist is shown on your profile"))
+}
+
+fn build_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("build")
+        .about("Make playlists match YAML specs of their sources, filters, \
+               order and size, creating them if needed")
+        .args(&login_args())
+        .args(&provider_args())
+        .args(&limit_args())
+        .arg(Arg::with_name("spec")
+            .required(true)
+            .multiple(true)
+            .help("The spec files. Sources that specs share are only read \
+                  once"))
+        .arg(Arg::with_name("cache_file")
+            .long("cache-file")
+            .takes_value(true)
+            .help("Keep the tracks read from CSV sources in this file, so \
+                  that later builds skip CSVs that haven't changed"))
+        .arg(Arg::with_name("dry_run")
+            .long("dry-run")
+            .help("Print the changes that would be made without making them"))
+}
+
+fn replace_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("replace")
+        .about("Swap tracks in a playlist using a csv with old_id and new_id \
+               columns. New tracks keep the position of the old ones")
+        .args(&login_args())
+        .args(&limit_args())
+        .arg(Arg::with_name("mapping")
+            .long("mapping")
+            .takes_value(true)
+            .required(true)
+            .help("CSV Filename of the mapping"))
+        .arg(Arg::with_name("playlist_name")
+            .long("playlist")
+            .takes_value(true)
+            .required(true)
+            .help("Spotify Playlist name"))
+        .arg(Arg::with_name("dry_run")
+            .long("dry-run")
+            .help("Print the replacements without making them"))
+}
+
+fn relink_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("relink")
+        .about("Check a playlist against another country, eg. after moving, and \
+               list the tracks that can't be played there along with \
+               equivalents that can")
+        .args(&login_args())
+        .args(&provider_args())
+        .args(&limit_args())
+        .arg(Arg::with_name("playlist_name")
+            .long("playlist")
+            .takes_value(true)
+            .required(true)
+            .help("Spotify Playlist name"))
+        .arg(Arg::with_name("market")
+            .long("market")
+            .takes_value(true)
+            .required(true)
+            .help("Two letter code of the country to check, eg. AU"))
+        .arg(Arg::with_name("replace")
+            .long("replace")
+            .help("Swap the unavailable tracks for their equivalents. Without \
+                   this the playlist isn't changed"))
+}
+
+fn serve_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("serve")
+        .about("Run a server where multiple users can log in with Spotify and \
+               upload csvs to their own playlists")
+        .args(&credential_args())
+        .arg(Arg::with_name("address")
+            .long("address")
+            .takes_value(true)
+            .default_value("127.0.0.1:8888")
+            .help("Address to listen on"))
+        .arg(Arg::with_name("base_url")
+            .long("base-url")
+            .takes_value(true)
+            .help("URL that users reach the server on. Defaults to \
+                  http://<address>"))
+        .arg(Arg::with_name("token_dir")
+            .long("token-dir")
+            .takes_value(true)
+            .default_value("tokens")
+            .help("Directory to store each user's token in"))
+        .arg(Arg::with_name("contribute_secret")
+            .long("contribute-secret")
+            .takes_value(true)
+            .requires("contribute_user")
+            .help("Enable the upload form at /contribute, protected by this \
+                  secret"))
+        .arg(Arg::with_name("contribute_user")
+            .long("contribute-user")
+            .takes_value(true)
+            .requires("contribute_secret")
+            .help("Spotify Username whose playlists the upload form adds to. \
+                  They must log in through /login first"))
+        .arg(Arg::with_name("uploads_per_hour")
+            .long("uploads-per-hour")
+            .takes_value(true)
+            .default_value("5")
+            .help("How many times each IP address can use the upload form per \
+                  hour"))
+        .arg(Arg::with_name("slack_signing_secret")
+            .long("slack-signing-secret")
+            .takes_value(true)
+            .requires_all(&["slack_user", "slack_playlist"])
+            .help("Enable the Slack slash command at /slack, checking requests \
+                  with this signing secret"))
+        .arg(Arg::with_name("slack_user")
+            .long("slack-user")
+            .takes_value(true)
+            .requires_all(&["slack_signing_secret", "slack_playlist"])
+            .help("Spotify Username who owns the Slack playlist. They must log \
+                  in through /login first"))
+        .arg(Arg::with_name("slack_playlist")
+            .long("slack-playlist")
+            .takes_value(true)
+            .requires_all(&["slack_signing_secret", "slack_user"])
+            .help("Spotify Playlist name that the Slack command adds to"))
+}
+
+fn telegram_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("telegram")
+        .about("Run a Telegram bot that adds Spotify links and csv files sent \
+               to it to a playlist")
+        .args(&login_args())
+        .arg(playlist_name_arg())
+        .arg(Arg::with_name("bot_token")
+            .long("bot-token")
+            .takes_value(true)
+            .required(true)
+            .help("Telegram bot token from @BotFather"))
+        .arg(Arg::with_name("allow_chat")
+            .long("allow-chat")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Only respond in these chat IDs. All chats are allowed when \
+                  this is not set"))
+}
+
+fn radio_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("radio")
+        .about("Add the songs a radio station plays to a playlist as they're \
+               played")
+        .args(&login_args())
+        .arg(playlist_name_arg())
+        .arg(Arg::with_name("radio_paradise")
+            .long("radio-paradise")
+            .takes_value(true)
+            .value_name("CHANNEL")
+            .required_unless("feed_url")
+            .conflicts_with("feed_url")
+            .help("Follow a Radio Paradise channel, eg. 0 for the main mix"))
+        .arg(Arg::with_name("feed_url")
+            .long("feed-url")
+            .takes_value(true)
+            .requires_all(&["artist_field", "title_field"])
+            .help("Follow any station with a JSON feed of what's playing"))
+        .arg(Arg::with_name("artist_field")
+            .long("artist-field")
+            .takes_value(true)
+            .help("JSON pointer to the artist in --feed-url, eg. /now/artist"))
+        .arg(Arg::with_name("title_field")
+            .long("title-field")
+            .takes_value(true)
+            .help("JSON pointer to the song title in --feed-url, eg. /now/title"))
+        .arg(Arg::with_name("every")
+            .long("every")
+            .takes_value(true)
+            .default_value("30s")
+            .help("How often to ask the station what's playing"))
+}
+
+fn setlist_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("setlist")
+        .about("Make a playlist of a concert's setlist from setlist.fm, using \
+               the studio versions of the songs in the order they were played")
+        .args(&login_args())
+        .arg(Arg::with_name("url")
+            .required(true)
+            .help("Link to the setlist on setlist.fm"))
+        .arg(Arg::with_name("playlist_name")
+            .help("Spotify Playlist name. Named after the artist, venue and \
+                  date when this is not set"))
+        .arg(Arg::with_name("api_key")
+            .long("api-key")
+            .takes_value(true)
+            .required(true)
+            .env("SETLISTFM_API_KEY")
+            .help("setlist.fm API key, see https://www.setlist.fm/settings/api"))
+}
+
+fn chart_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("chart")
+        .about("Make a playlist of a week's singles chart, in chart order. \
+               Running it again for the same week updates the playlist to \
+               match the chart")
+        .args(&login_args())
+        .arg(Arg::with_name("chart")
+            .required(true)
+            .possible_values(&["billboard-hot-100", "uk-top-40"])
+            .help("The chart to read"))
+        .arg(Arg::with_name("playlist_name")
+            .help("Spotify Playlist name. Named after the chart and its date \
+                  when this is not set"))
+        .arg(Arg::with_name("date")
+            .long("date")
+            .takes_value(true)
+            .help("Any day in the chart's week as YYYY-MM-DD. Defaults to \
+                  this week's chart"))
+        .arg(Arg::with_name("dry_run")
+            .long("dry-run")
+            .help("Print the changes without making them"))
+}
+
+fn segment_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("segment")
+        .about("Split the songs in a csv into playlists by energy or tempo, \
+               eg. for the warmup, peak and cooldown of a set. Each playlist \
+               is created if needed and new songs are added to it")
+        .args(&login_args())
+        .args(&column_args())
+        .arg(input_format_arg())
+        .args(&aws_args())
+        .args(&download_args())
+        .arg(playlist_name_arg()
+            .help("The name the playlists are based on, see --name-template"))
+        .arg(Arg::with_name("csv_filename")
+            .required(true)
+            .help("CSV Filename, s3://bucket/key.csv or an https:// URL"))
+        .arg(Arg::with_name("by")
+            .long("by")
+            .takes_value(true)
+            .possible_values(&["energy", "tempo"])
+            .default_value("energy")
+            .help("The audio feature to split the songs by"))
+        .arg(Arg::with_name("segment")
+            .long("segment")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("A playlist for the songs in a range of --by, as \
+                  name:min-max, eg. peak:0.7-1. Songs go in the first \
+                  segment they fit. Defaults to warmup, peak and cooldown \
+                  for energy and slow, mid and fast for tempo"))
+        .arg(Arg::with_name("name_template")
+            .long("name-template")
+            .takes_value(true)
+            .default_value("{playlist} - {segment}")
+            .help("The name of each playlist. {playlist} is replaced with \
+                  the playlist name and {segment} with the segment's name"))
+        .arg(Arg::with_name("dry_run")
+            .long("dry-run")
+            .help("Print how the songs would be split without adding them"))
+}
+
+fn recap_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("recap")
+        .about("Make \"Top Tracks 2024\" style playlists of the most played \
+               tracks in each year, quarter or month of a play history. Each \
+               playlist is created if needed and kept in order of plays")
+        .args(&login_args())
+        .args(&provider_args())
+        .args(&column_args())
+        .args(&aws_args())
+        .args(&download_args())
+        .arg(public_arg())
+        .arg(Arg::with_name("csv_filename")
+            .required_unless("listenbrainz_user")
+            .help("CSV Filename of the play history, with a row for each play \
+                  and a played_at column, eg. a scrobble export"))
+        .arg(Arg::with_name("listenbrainz_user")
+            .long("listenbrainz-user")
+            .takes_value(true)
+            .conflicts_with("csv_filename")
+            .requires("since")
+            .help("Count the listens of this ListenBrainz user instead of a \
+                  csv"))
+        .arg(Arg::with_name("since")
+            .long("since")
+            .takes_value(true)
+            .help("Only count plays from this date, eg. 2024-01-01"))
+        .arg(Arg::with_name("period")
+            .long("period")
+            .takes_value(true)
+            .possible_values(&["year", "quarter", "month"])
+            .default_value("year")
+            .help("How long each playlist covers"))
+        .arg(Arg::with_name("top")
+            .long("top")
+            .takes_value(true)
+            .default_value("50")
+            .help("The most tracks in each playlist"))
+        .arg(Arg::with_name("min_plays")
+            .long("min-plays")
+            .takes_value(true)
+            .default_value("1")
+            .help("Leave out tracks played fewer times than this in a period"))
+        .arg(Arg::with_name("name_template")
+            .long("name-template")
+            .takes_value(true)
+            .default_value("Top Tracks {period}")
+            .help("The name of each playlist. {period} is replaced with the \
+                  year, eg. 2024, quarter, eg. 2024 Q1, or month, eg. 2024-03"))
+        .arg(Arg::with_name("dry_run")
+            .long("dry-run")
+            .help("Print the top tracks of each period without changing any \
+                  playlists"))
+}
+
+fn profile_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("profile")
+        .about("Time each stage of importing a csv, ie. parsing it, \
+               normalizing the track IDs, matching them on Spotify, deduping \
+               against the playlist and adding, to find what's slow")
+        .args(&login_args())
+        .args(&column_args())
+        .arg(input_format_arg())
+        .args(&aws_args())
+        .args(&download_args())
+        .arg(Arg::with_name("csv")
+            .long("csv")
+            .takes_value(true)
+            .required(true)
+            .help("The CSV to import"))
+        .arg(Arg::with_name("playlist")
+            .long("playlist")
+            .takes_value(true)
+            .help("Dedupe against this playlist. Without it the dedupe and \
+                  add stages are skipped"))
+        .arg(Arg::with_name("add")
+            .long("add")
+            .requires("playlist")
+            .help("Add the new tracks to the --playlist so that adding is \
+                  timed too. Nothing is added without it"))
+}
+
+fn completions_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("completions")
+        .about("Print a script that completes the commands and flags in a \
+               shell, eg. `playlist-from-csv completions bash > \
+               /etc/bash_completion.d/playlist-from-csv`")
+        .arg(Arg::with_name("shell")
+            .required(true)
+            .possible_values(&Shell::variants())
+            .help("The shell to complete in"))
+}
+
+/// Get the matches for the subcommand that was run. Global arguments such as
+/// `--verbose` are only combined here, since they can be given before or after
+/// the subcommand
+///
+/// # Arguments
+///
+/// * `matches` - The matches from `build_cli`
+pub fn innermost_matches<'a>(matches: &'a ArgMatches<'a>) -> &'a ArgMatches<'a> {
+    match matches.subcommand() {
+        (_, Some(sub_matches)) => innermost_matches(sub_matches),
+        _ => matches,
+    }
+}
+
+/// Build the command line interface. Every operation is a subcommand with its
+/// own flags, and those that talk to Spotify share the same login arguments.
+pub fn build_cli() -> App<'static, 'static> {
+    let app = App::new("playlist-from-csv")
+        .about("Keep Spotify playlists up to date with csv files")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(Arg::with_name("verbose")
+            .short("v")
+            .long("verbose")
+            .multiple(true)
+            .global(true)
+            .help("Show more detail, eg. each page fetched from Spotify. \
+                  Use -vv to also show every request"))
+        .arg(Arg::with_name("quiet")
+            .short("q")
+            .long("quiet")
+            .global(true)
+            .conflicts_with("verbose")
+            .help("Only show errors"))
+        .arg(Arg::with_name("lang")
+            .long("lang")
+            .takes_value(true)
+            .global(true)
+            .possible_values(&["en", "es", "de"])
+            .help("The language to show messages and reports in. Defaults to \
+                  the language of the locale, eg. from LANG"))
+        .arg(Arg::with_name("events")
+            .long("events")
+            .takes_value(true)
+            .global(true)
+            .possible_values(&["ndjson"])
+            .help("Write a JSON object to stdout for each row parsed, track \
+                  matched, duplicate skipped, batch added and error as it \
+                  happens, so that other programs can show progress"))
+        .arg(Arg::with_name("verbose_errors")
+            .long("verbose-errors")
+            .global(true)
+            .help("Show everything that caused an error, rather than just \
+                  what went wrong and how to fix it"))
+        .arg(Arg::with_name("user_agent")
+            .long("user-agent")
+            .takes_value(true)
+            .global(true)
+            .env("PLAYLIST_FROM_CSV_USER_AGENT")
+            .help("The User-Agent to send with every request instead of the \
+                  default, eg. when a provider asks for a contact address"))
+        .arg(Arg::with_name("app_name")
+            .long("app-name")
+            .takes_value(true)
+            .global(true)
+            .env("PLAYLIST_FROM_CSV_APP_NAME")
+            .help("Put this name in front of the default User-Agent, eg. to \
+                  tell deployments apart"))
+        .subcommand(SubCommand::with_name("auth")
+            .about("Log in to Spotify and cache the token for later runs")
+            .args(&login_args()))
+        .subcommand(SubCommand::with_name("list")
+            .visible_alias("list-playlists")
+            .about("List the playlists you follow with their IDs, track counts, \
+                   whether they're public and who owns them. Names with spaces \
+                   at either end are quoted, since playlists are found by their \
+                   exact name")
+            .args(&login_args())
+            .args(&provider_args())
+            .args(&limit_args()))
+        .subcommand(import_subcommand())
+        .subcommand(import_many_subcommand())
+        .subcommand(export_subcommand())
+        .subcommand(show_subcommand())
+        .subcommand(bandcamp_subcommand())
+        .subcommand(sync_subcommand())
+        .subcommand(plan_subcommand())
+        .subcommand(apply_subcommand())
+        .subcommand(sign_subcommand())
+        .subcommand(preview_subcommand())
+        .subcommand(clone_subcommand())
+        .subcommand(transfer_subcommand())
+        .subcommand(snapshot_subcommand())
+        .subcommand(restore_subcommand())
+        .subcommand(snapshot_order_subcommand())
+        .subcommand(restore_order_subcommand())
+        .subcommand(config_subcommand())
+        .subcommand(daemon_subcommand())
+        .subcommand(clean_subcommand())
+        .subcommand(remove_subcommand())
+        .subcommand(replace_subcommand())
+        .subcommand(relink_subcommand())
+        .subcommand(set_visibility_subcommand())
+        .subcommand(build_subcommand())
+        .subcommand(serve_subcommand())
+        .subcommand(telegram_subcommand())
+        .subcommand(radio_subcommand())
+        .subcommand(setlist_subcommand())
+        .subcommand(chart_subcommand())
+        .subcommand(segment_subcommand())
+        .subcommand(recap_subcommand())
+        .subcommand(profile_subcommand())
+        .subcommand(completions_subcommand());
+    #[cfg(feature = "lambda")]
+    let app = app.subcommand(lambda_subcommand());
+    #[cfg(feature = "gui")]
+    let app = app.subcommand(gui_subcommand());
+    app
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<(String, Vec<String>), String> {
+        let mut all_args = vec!["playlist-from-csv"];
+        all_args.extend_from_slice(args);
+        let matches = build_cli().get_matches_from_safe(all_args).map_err(|e| e.message)?;
+        let (name, sub_matches) = matches.subcommand();
+        let merge = sub_matches
+            .and_then(|m| m.values_of("merge"))
+            .map(|values| values.map(|v| v.to_owned()).collect())
+            .unwrap_or_default();
+        Ok((name.to_owned(), merge))
+    }
+
+    #[test]
+    fn import_takes_login_and_repeated_options() {
+        let result = parse(&[
+            "import", "--client-id", "id", "--client-secret", "secret", "--username", "me",
+            "Playlist", "a.csv", "--merge", "b.csv", "--merge", "c.csv",
+        ]);
+        let expected = ("import".to_string(), vec!["b.csv".to_string(), "c.csv".to_string()]);
+        assert_eq!(Ok(expected), result);
+    }
+
+    #[test]
+    fn import_needs_a_csv_unless_reading_from_git() {
+        let login = ["--client-id", "id", "--client-secret", "secret", "--username", "me"];
+        let mut args = vec!["import", "Playlist"];
+        args.extend_from_slice(&login);
+        assert!(parse(&args).is_err());
+        args.extend_from_slice(&["--git-repo", ".", "--file", "songs.csv"]);
+        assert!(parse(&args).is_ok());
+    }
+
+    #[test]
+    fn sign_does_not_need_login() {
+        assert!(parse(&["sign", "plan.json", "--key", "private.pem"]).is_ok());
+    }
+
+    #[test]
+    fn csv_can_be_read_from_stdin() {
+        let result = parse(&[
+            "sync", "--client-id", "id", "--client-secret", "secret", "--username", "me",
+            "Playlist", "-",
+        ]);
+        assert_eq!(Ok(("sync".to_string(), Vec::new())), result);
+    }
+
+    #[test]
+    fn verbosity_can_be_given_either_side_of_the_subcommand() {
+        let verbosity = |args: Vec<&str>| {
+            let matches = build_cli().get_matches_from_safe(args).unwrap();
+            innermost_matches(&matches).occurrences_of("verbose")
+        };
+        assert_eq!(2, verbosity(vec!["playlist-from-csv", "sign", "plan.json", "-vv", "--key", "k"]));
+        assert_eq!(1, verbosity(vec!["playlist-from-csv", "-v", "config", "validate", "jobs.toml"]));
+    }
+
+    #[test]
+    fn completions_include_subcommands_and_flags() {
+        let mut script = Vec::new();
+        build_cli().gen_completions_to("playlist-from-csv", Shell::Bash, &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("import"));
+        assert!(script.contains("--dry-run"));
+    }
+}
@@ -0,0 +1,181 @@
+use cover::check_cover_image;
+use playlist::{get_playlist_id_with_policy,CreatePolicy,PlaylistAPI,PlaylistError,Visibility};
+use track_uri::TrackUri;
+
+/// A playlist made by `clone_playlist`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClonedPlaylist {
+    /// The ID of the playlist that was copied
+    pub source_id: String,
+    /// The ID of the new playlist
+    pub playlist_id: String,
+    /// The number of items that were copied
+    pub copied: usize,
+    /// The items that couldn't be added to the new playlist, eg. local files
+    pub skipped: Vec<TrackUri>,
+    /// Whether the description was copied
+    pub copied_description: bool,
+    /// Whether the cover image was copied
+    pub copied_cover: bool,
+}
+
+/// Copy a playlist's tracks, description and cover into a new playlist
+/// owned by the user. Fails without changing anything if a playlist with the
+/// new name already exists. Covers that Spotify wouldn't accept as an upload,
+/// eg. ones that are too large, are left out.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance to copy the playlist with
+/// * `source_name` - The name of the playlist to copy
+/// * `new_name` - The name of the new playlist
+/// * `visibility` - Whether the new playlist is public
+pub fn clone_playlist<E>(playlist_api: &PlaylistAPI<E>,
+                         source_name: &str,
+                         new_name: &str,
+                         visibility: Visibility) -> Result<ClonedPlaylist, PlaylistError<E>> {
+    let source_id = playlist_api.get_playlist_id(source_name)?;
+    let tracks = playlist_api.get_track_ids_in_playlist(&source_id).map_err(PlaylistError::APIError)?;
+    let details = playlist_api.get_playlist_details(&source_id).map_err(PlaylistError::APIError)?;
+    let cover = playlist_api.get_cover_image(&source_id)
+        .map_err(PlaylistError::APIError)?
+        .filter(|cover| match check_cover_image(cover) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Not copying the cover of {}: {}", source_name, e);
+                false
+            },
+        });
+    let (addable, skipped): (Vec<TrackUri>, Vec<TrackUri>) = tracks.into_iter()
+        .partition(|track| track.is_addable());
+    let playlist_id = get_playlist_id_with_policy(
+        playlist_api, new_name, CreatePolicy::CreateOnly, visibility
+    )?;
+    if !addable.is_empty() {
+        playlist_api.add_tracks_to_playlist(&playlist_id, &addable, None).map_err(PlaylistError::APIError)?;
+    }
+    let copied_description = details.description.is_some();
+    if copied_description {
+        playlist_api.update_playlist_details(&playlist_id, &details).map_err(PlaylistError::APIError)?;
+    }
+    if let Some(ref cover) = cover {
+        playlist_api.upload_cover_image(&playlist_id, cover).map_err(PlaylistError::APIError)?;
+    }
+    Ok(ClonedPlaylist {
+        source_id: source_id,
+        playlist_id: playlist_id,
+        copied: addable.len(),
+        skipped: skipped,
+        copied_description: copied_description,
+        copied_cover: cover.is_some(),
+    })
+}
+
+/// How a clone differs from the playlist it was copied from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Parity {
+    /// Items in the original that aren't in the clone
+    pub missing: Vec<TrackUri>,
+    /// Items in the clone that aren't in the original
+    pub extra: Vec<TrackUri>,
+    /// Whether the items that are in both are in the same order
+    pub same_order: bool,
+}
+
+impl Parity {
+    /// Whether the clone matches the original exactly
+    pub fn is_exact(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.same_order
+    }
+}
+
+/// Compare a clone with the playlist it was copied from. Items that can't
+/// be added, eg. local files, aren't expected in the clone. An item that's
+/// in a playlist more than once counts each time.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance to read the playlists with
+/// * `cloned` - The clone, see `clone_playlist`
+pub fn check_parity<E>(playlist_api: &PlaylistAPI<E>, cloned: &ClonedPlaylist) -> Result<Parity, E> {
+    let original: Vec<TrackUri> = playlist_api.get_track_ids_in_playlist(&cloned.source_id)?
+        .into_iter()
+        .filter(|track| track.is_addable())
+        .collect();
+    let clone = playlist_api.get_track_ids_in_playlist(&cloned.playlist_id)?;
+    let mut extra = clone.clone();
+    let mut missing = Vec::new();
+    for track in &original {
+        match extra.iter().position(|t| t == track) {
+            Some(i) => {
+                extra.remove(i);
+            },
+            None => missing.push(track.clone()),
+        }
+    }
+    let in_both = |tracks: &[TrackUri], other: &[TrackUri]| -> Vec<TrackUri> {
+        tracks.iter().filter(|track| other.contains(track)).cloned().collect()
+    };
+    Ok(Parity {
+        same_order: in_both(&original, &clone) == in_both(&clone, &original),
+        missing: missing,
+        extra: extra,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::MemoryAPI;
+    use playlist::PlaylistDetails;
+    use std::env;
+
+    #[test]
+    fn clone_copies_tracks_and_description_into_a_new_playlist() {
+        // Given
+        let path = env::temp_dir().join("playlist-from-csv-clone-test.json");
+        let filename = path.to_str().unwrap();
+        let _ = ::std::fs::remove_file(filename);
+        let api = MemoryAPI::open(filename).unwrap();
+        let source = api.create_playlist("Road Trip", Visibility::Private).unwrap();
+        let tracks = vec![TrackUri::Track("a".to_owned()), TrackUri::Episode("b".to_owned())];
+        api.add_tracks_to_playlist(&source, &tracks, None).unwrap();
+        let details = PlaylistDetails { description: Some("Songs for the drive".to_owned()) };
+        api.update_playlist_details(&source, &details).unwrap();
+        // When
+        let cloned = clone_playlist(&api, "Road Trip", "Road Trip 2", Visibility::Public).unwrap();
+        let existing = clone_playlist(&api, "Road Trip", "Road Trip 2", Visibility::Public);
+        // Then
+        assert_eq!(2, cloned.copied);
+        assert!(cloned.skipped.is_empty());
+        assert!(cloned.copied_description);
+        assert!(!cloned.copied_cover);
+        assert_eq!(tracks, api.get_track_ids_in_playlist(&cloned.playlist_id).unwrap());
+        assert_eq!(details, api.get_playlist_details(&cloned.playlist_id).unwrap());
+        assert!(matches!(existing, Err(PlaylistError::PlaylistAlreadyExists(_))));
+    }
+
+    #[test]
+    fn parity_finds_differences_between_a_clone_and_the_original() {
+        // Given
+        let path = env::temp_dir().join("playlist-from-csv-parity-test.json");
+        let filename = path.to_str().unwrap();
+        let _ = ::std::fs::remove_file(filename);
+        let api = MemoryAPI::open(filename).unwrap();
+        let track = |id: &str| TrackUri::Track(id.to_owned());
+        let source = api.create_playlist("Road Trip", Visibility::Private).unwrap();
+        api.add_tracks_to_playlist(&source, &[track("a"), track("b"), track("c")], None).unwrap();
+        let cloned = clone_playlist(&api, "Road Trip", "Road Trip 2", Visibility::Private).unwrap();
+        // When
+        let exact = check_parity(&api, &cloned).unwrap();
+        api.move_track_in_playlist(&cloned.playlist_id, 0, 3).unwrap();
+        let reordered = check_parity(&api, &cloned).unwrap();
+        api.remove_tracks_from_playlist(&cloned.playlist_id, &[track("b")]).unwrap();
+        api.add_tracks_to_playlist(&cloned.playlist_id, &[track("d")], None).unwrap();
+        let changed = check_parity(&api, &cloned).unwrap();
+        // Then
+        assert!(exact.is_exact());
+        assert_eq!(Parity { missing: vec![], extra: vec![], same_order: false }, reordered);
+        assert_eq!(Parity { missing: vec![track("b")], extra: vec![track("d")], same_order: false }, changed);
+    }
+}
@@ -0,0 +1,256 @@
+// The GUI is the only caller of some commands until the CLI is moved onto them
+#![cfg_attr(not(feature = "gui"), allow(dead_code))]
+
+use config::read_config;
+use csv_to_playlist::{add_songs_returning_tracks,dry_run_add_songs,get_lines_without_track_id,AddOptions,CsvColumns,PlaylistAddError};
+use exit_code;
+use hints::render_api_error;
+use playlist::{get_playlist_id_create_if_needed,PlaylistAPI,PlaylistError,PlaylistInfo};
+use source::{SongFile,SongSource};
+use spotify::{DeadlineExceeded,RequestBudgetExhausted};
+
+extern crate failure;
+
+extern crate serde;
+use self::serde::Serialize;
+use self::serde::de::DeserializeOwned;
+
+extern crate serde_json;
+use self::serde_json::Value;
+
+/// The error when a command fails, with the exit code that the CLI would
+/// exit with
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CommandError {
+    pub message: String,
+    pub exit_code: i32,
+}
+
+impl CommandError {
+    fn new(message: String, exit_code: i32) -> CommandError {
+        CommandError { message: message, exit_code: exit_code }
+    }
+}
+
+/// Describe a failed request, with a hint when there's a common fix
+fn api_error(error: failure::Error) -> CommandError {
+    let stopped = if error.downcast_ref::<RequestBudgetExhausted>().is_some() {
+        Some(exit_code::REQUEST_BUDGET_EXHAUSTED)
+    } else if error.downcast_ref::<DeadlineExceeded>().is_some() {
+        Some(exit_code::DEADLINE_EXCEEDED)
+    } else {
+        None
+    };
+    match stopped {
+        Some(code) => CommandError::new(
+            format!("{}. Run again to continue, changes that were already made are skipped", error),
+            code
+        ),
+        None => CommandError::new(render_api_error("Spotify request failed", &error), exit_code::API_ERROR),
+    }
+}
+
+fn playlist_error(error: PlaylistError<failure::Error>) -> CommandError {
+    let code = exit_code::playlist_error_code(&error);
+    match error {
+        PlaylistError::APIError(e) => api_error(e),
+        e => CommandError::new(e.to_string(), code),
+    }
+}
+
+/// Get the playlists that the user follows with their track counts,
+/// visibility and owners, like the `list` command
+///
+/// # Arguments
+///
+/// * `api` - Where the playlists are
+pub fn list_playlists(api: &PlaylistAPI<failure::Error>) -> Result<Vec<PlaylistInfo>, CommandError> {
+    api.list_playlists().map_err(api_error)
+}
+
+/// The arguments of `import`, named like the CLI's flags
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ImportArgs {
+    pub playlist_name: String,
+    pub csv_filename: String,
+    #[serde(default)]
+    pub dry_run: bool,
+    pub name_column: Option<String>,
+    pub id_column: Option<String>,
+}
+
+/// What `import` did, or would do for a dry run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportResult {
+    /// The playlist's ID, which is `None` for a dry run when it doesn't
+    /// exist yet
+    pub playlist_id: Option<String>,
+    /// The URIs of the tracks that were added
+    pub added: Vec<String>,
+    /// The URIs of the tracks that were skipped since they're already in the
+    /// playlist. This is only known for a dry run
+    pub duplicates: Vec<String>,
+    /// The lines of the CSV that have no track ID
+    pub failed_lines: Vec<usize>,
+}
+
+/// Add the songs in a file to a playlist, creating it if needed, like the
+/// `import` command without its options
+///
+/// # Arguments
+///
+/// * `api` - Where the playlist is
+/// * `args` - The playlist and the file to import
+pub fn import(api: &PlaylistAPI<failure::Error>, args: &ImportArgs) -> Result<ImportResult, CommandError> {
+    let columns = CsvColumns::new(args.name_column.as_deref(), args.id_column.as_deref());
+    let songs = SongFile::new(&args.csv_filename, None, columns).read_songs()
+        .map_err(|e| CommandError::new(format!("Could not read the CSV: {}", e), exit_code::INVALID_CSV))?;
+    let failed_lines = get_lines_without_track_id(&songs);
+    if args.dry_run {
+        let playlist_id = match api.get_playlist_id(&args.playlist_name) {
+            Ok(id) => Some(id),
+            Err(PlaylistError::PlaylistNotFound(_)) => None,
+            Err(e) => return Err(playlist_error(e)),
+        };
+        let dry_run = dry_run_add_songs(api, playlist_id.as_deref(), &songs).map_err(api_error)?;
+        return Ok(ImportResult {
+            playlist_id: playlist_id,
+            added: dry_run.additions.iter().map(|track| track.uri()).collect(),
+            duplicates: dry_run.duplicates.iter().map(|track| track.uri()).collect(),
+            failed_lines: failed_lines,
+        });
+    }
+    let playlist_id = get_playlist_id_create_if_needed(api, &args.playlist_name).map_err(playlist_error)?;
+    let added = match add_songs_returning_tracks(api, &playlist_id, songs, &AddOptions::default()) {
+        Ok(added) => added.added,
+        Err(PlaylistAddError::NoNewTracks(_)) => Vec::new(),
+        Err(PlaylistAddError::APIError(e)) => return Err(api_error(e)),
+        Err(e) => {
+            let code = exit_code::add_error_code(&e);
+            let message = match e {
+                PlaylistAddError::ReadError(e) => format!("Could not read the CSV: {}", e),
+                PlaylistAddError::PlaylistFull(e) => e.to_string(),
+                _ => unreachable!(),
+            };
+            return Err(CommandError::new(message, code));
+        },
+    };
+    Ok(ImportResult {
+        playlist_id: Some(playlist_id),
+        added: added.iter().map(|track| track.uri()).collect(),
+        duplicates: Vec::new(),
+        failed_lines: failed_lines,
+    })
+}
+
+/// The arguments of `validate_config`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ValidateConfigArgs {
+    pub config: String,
+}
+
+/// A problem found by `validate_config`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Problem {
+    pub location: String,
+    pub message: String,
+}
+
+/// What `validate_config` found
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConfigReport {
+    /// The names of the jobs, when the config is valid
+    pub jobs: Vec<String>,
+    /// Every problem found, which is empty when the config is valid
+    pub problems: Vec<Problem>,
+}
+
+/// Check a config file, like the `config validate` command
+///
+/// # Arguments
+///
+/// * `args` - The config file to check
+pub fn validate_config(args: &ValidateConfigArgs) -> ConfigReport {
+    match read_config(&args.config) {
+        Ok(config) => ConfigReport {
+            jobs: config.jobs.into_iter().map(|job| job.name).collect(),
+            problems: Vec::new(),
+        },
+        Err(diagnostics) => ConfigReport {
+            jobs: Vec::new(),
+            problems: diagnostics.into_iter()
+                .map(|diagnostic| Problem { location: diagnostic.location, message: diagnostic.message })
+                .collect(),
+        },
+    }
+}
+
+/// Read a command's arguments from JSON
+fn parse_args<T: DeserializeOwned>(args: Value) -> Result<T, CommandError> {
+    serde_json::from_value(args)
+        .map_err(|e| CommandError::new(format!("Invalid arguments: {}", e), exit_code::FAILURE))
+}
+
+fn to_json<T: Serialize>(result: T) -> Result<Value, CommandError> {
+    serde_json::to_value(result)
+        .map_err(|e| CommandError::new(format!("Could not write the result: {}", e), exit_code::FAILURE))
+}
+
+/// Run a command by name with its arguments as JSON, the way a GUI calls
+/// commands, eg. `invoke("import", {"playlist_name": ...})` with Tauri.
+/// Returns the command's result as JSON.
+///
+/// # Arguments
+///
+/// * `api` - Where the playlists are
+/// * `command` - The name of the command, eg. `list_playlists`
+/// * `args` - The command's arguments, named like the fields of its `Args`
+pub fn invoke(api: &PlaylistAPI<failure::Error>, command: &str, args: Value) -> Result<Value, CommandError> {
+    match command {
+        "list_playlists" => to_json(list_playlists(api)?),
+        "import" => to_json(import(api, &parse_args(args)?)?),
+        "validate_config" => to_json(validate_config(&parse_args(args)?)),
+        _ => Err(CommandError::new(
+            format!("Expected list_playlists, import or validate_config, got {:?}", command),
+            exit_code::FAILURE
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::serde_json::json;
+    use memory::MemoryAPI;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn commands_are_invoked_with_json() {
+        // Given
+        let sandbox = env::temp_dir().join("playlist-from-csv-commands-test.json");
+        let sandbox = sandbox.to_str().unwrap();
+        let csv = env::temp_dir().join("playlist-from-csv-commands-test.csv");
+        let csv = csv.to_str().unwrap();
+        let _ = fs::remove_file(sandbox);
+        fs::write(csv, "Track,URI\nRobyn - Honey,spotify:track:abc0000000000000000000\nLocal song,\n").unwrap();
+        let api = MemoryAPI::open(sandbox).unwrap();
+        let args = json!({"playlist_name": "Mix", "csv_filename": csv, "name_column": "Track", "id_column": "URI"});
+        // When
+        let imported = invoke(&api, "import", args.clone()).unwrap();
+        let again = invoke(&api, "import", json!({"dry_run": true, "playlist_name": "Mix", "csv_filename": csv,
+                                                   "name_column": "Track", "id_column": "URI"})).unwrap();
+        let playlists = invoke(&api, "list_playlists", Value::Null).unwrap();
+        let invalid = invoke(&api, "import", json!({"playlist_name": "Mix"}));
+        let unknown = invoke(&api, "delete_everything", Value::Null);
+        // Then
+        assert_eq!(json!({"playlist_id": "sandbox1", "added": ["spotify:track:abc0000000000000000000"], "duplicates": [], "failed_lines": [3]}), imported);
+        assert_eq!(json!(["spotify:track:abc0000000000000000000"]), again["duplicates"]);
+        assert_eq!(
+            json!([{"id": "sandbox1", "name": "Mix", "track_count": 1, "public": false, "owner": "sandbox"}]),
+            playlists
+        );
+        assert!(invalid.unwrap_err().message.starts_with("Invalid arguments: missing field `csv_filename`"));
+        assert_eq!(exit_code::FAILURE, unknown.unwrap_err().exit_code);
+    }
+}
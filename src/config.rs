@@ -0,0 +1,659 @@
+use std::collections::{BTreeMap,HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+use deadline::RunTime;
+use id_namespace::IdNamespace;
+use quiet_hours::QuietHours;
+use drip::DripRate;
+
+extern crate toml;
+use self::toml::Value;
+
+/// The keys allowed at the top of a config file
+const CONFIG_KEYS: &[&str] = &["state_dir", "job", "profiles"];
+
+/// The keys allowed in each `[[job]]`
+const JOB_KEYS: &[&str] = &[
+    "name", "playlist", "mode", "csv", "git_repo", "file", "since_ref",
+    "dedupe_against", "quarantine", "max_removals", "max_changes_percent",
+    "every", "after", "never_readd", "listenbrainz_user", "listened_since",
+    "name_column", "id_column", "quiet_hours", "id_namespace", "drip",
+];
+
+/// The keys allowed in each `[profiles.<name>]`
+const PROFILE_KEYS: &[&str] = &["client_id", "client_secret", "username", "playlist"];
+
+/// Where jobs keep their state when `state_dir` isn't set
+const DEFAULT_STATE_DIR: &str = "playlist-from-csv-state";
+
+/// Jobs that are run from a config file instead of the command line
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Config {
+    /// The directory where each job keeps its state, see `state_dir`
+    #[serde(rename = "state_dir")]
+    pub state_dir_name: Option<String>,
+    #[serde(default, rename = "job")]
+    pub jobs: Vec<Job>,
+    /// Spotify accounts that can be picked with `--profile`, by name
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Config {
+    /// The directory where each job keeps its state between runs, in a
+    /// directory named after the job
+    pub fn state_dir(&self) -> PathBuf {
+        PathBuf::from(self.state_dir_name.as_deref().unwrap_or(DEFAULT_STATE_DIR))
+    }
+}
+
+/// A Spotify account to log in as. Anything that's left out is taken from
+/// the command line or the environment instead.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Profile {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub username: Option<String>,
+    /// The playlist to import into when no playlist name is given
+    pub playlist: Option<String>,
+}
+
+/// What a job does to its playlist
+#[derive(Debug, Copy, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Add new songs, the same as the `import` command
+    #[default]
+    Import,
+    /// Make the playlist match the CSV, the same as the `sync` command
+    Sync,
+}
+
+/// A playlist to update from a CSV. The options are the same as the command
+/// line options for the `import` and `sync` commands.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Job {
+    /// A unique name for the job
+    pub name: String,
+    /// The Spotify playlist name
+    pub playlist: String,
+    #[serde(default)]
+    pub mode: Mode,
+    /// The CSV to read. Not needed with `git_repo`
+    pub csv: Option<String>,
+    /// Only import rows added to `file` in this Git repository
+    pub git_repo: Option<String>,
+    /// The path of the CSV within `git_repo`
+    pub file: Option<String>,
+    /// The Git revision to compare against
+    pub since_ref: Option<String>,
+    /// The CSV column with the name of each song, instead of "music (S)"
+    pub name_column: Option<String>,
+    /// The CSV column with the ID of each song, instead of "song_id (S)"
+    pub id_column: Option<String>,
+    /// Where the IDs in the CSV come from, eg. youtube, see `IdNamespace`
+    pub id_namespace: Option<String>,
+    /// Import the tracks this ListenBrainz user listens to instead of a CSV
+    pub listenbrainz_user: Option<String>,
+    /// Where to start from the first time `listenbrainz_user` is read, in
+    /// seconds since the Unix epoch. Later runs continue from the last listen
+    /// that was imported
+    pub listened_since: Option<i64>,
+    #[serde(default)]
+    pub dedupe_against: Vec<String>,
+    pub quarantine: Option<String>,
+    pub max_removals: Option<usize>,
+    pub max_changes_percent: Option<f64>,
+    /// How often the job should run, eg. 6h
+    pub every: Option<String>,
+    /// The names of jobs that have to run first when they run at the same
+    /// time as this job
+    #[serde(default)]
+    pub after: Vec<String>,
+    /// When the job shouldn't run, eg. 01:00-06:00. A job that's due then
+    /// waits until they're over
+    pub quiet_hours: Option<String>,
+    /// How fast the songs the job finds are added, eg. 10/day. The rest wait
+    /// in a queue in the job's state directory
+    pub drip: Option<String>,
+    /// Skip songs that this job has imported before, even if they've since
+    /// been removed from the playlist. The imported songs are kept in the
+    /// job's state directory
+    #[serde(default)]
+    pub never_readd: bool,
+}
+
+/// A problem found in a config file. `location` is empty for problems with
+/// the whole file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.location.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.location, self.message)
+        }
+    }
+}
+
+/// Get the number of single character edits needed to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + if a_char == *b_char { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Describe a key that isn't in `known`, suggesting the closest known key
+/// if there is one that looks like a typo
+fn unknown_key_message(key: &str, known: &[&str]) -> String {
+    let closest = known.iter()
+        .map(|k| (edit_distance(key, k), k))
+        .min();
+    match closest {
+        Some((distance, k)) if distance <= 2 || distance <= key.len() / 3 => {
+            format!("unknown key `{}`, did you mean `{}`?", key, k)
+        },
+        _ => format!("unknown key `{}`", key),
+    }
+}
+
+/// Describe a job for diagnostics, using its name when it has one
+fn job_location(index: usize, job: &Value) -> String {
+    match job.get("name").and_then(Value::as_str) {
+        Some(name) => format!("job \"{}\"", name),
+        None => format!("job {}", index + 1),
+    }
+}
+
+/// Find keys that aren't part of the schema, since they'd otherwise be
+/// silently ignored
+fn check_unknown_keys(value: &Value) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let table = match value.as_table() {
+        Some(table) => table,
+        None => return diagnostics,
+    };
+    for key in table.keys().filter(|key| !CONFIG_KEYS.contains(&key.as_str())) {
+        diagnostics.push(Diagnostic {
+            location: String::new(),
+            message: unknown_key_message(key, CONFIG_KEYS),
+        });
+    }
+    let jobs = table.get("job").and_then(Value::as_array).map(|jobs| jobs.as_slice());
+    for (i, job) in jobs.unwrap_or_default().iter().enumerate() {
+        let keys = job.as_table().into_iter().flat_map(|job| job.keys());
+        for key in keys.filter(|key| !JOB_KEYS.contains(&key.as_str())) {
+            diagnostics.push(Diagnostic {
+                location: job_location(i, job),
+                message: unknown_key_message(key, JOB_KEYS),
+            });
+        }
+    }
+    let profiles = table.get("profiles").and_then(Value::as_table);
+    for (name, profile) in profiles.into_iter().flat_map(|profiles| profiles.iter()) {
+        let keys = profile.as_table().into_iter().flat_map(|profile| profile.keys());
+        for key in keys.filter(|key| !PROFILE_KEYS.contains(&key.as_str())) {
+            diagnostics.push(Diagnostic {
+                location: format!("profile \"{}\"", name),
+                message: unknown_key_message(key, PROFILE_KEYS),
+            });
+        }
+    }
+    diagnostics
+}
+
+/// Whether a name can be used in a file name, which is only letters,
+/// numbers, -, _ and . without a . at the start
+fn is_safe_name(name: &str) -> bool {
+    let is_safe_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.';
+    name.chars().all(is_safe_char) && !name.starts_with('.')
+}
+
+/// Check the rules between fields of a job that the types can't express
+fn check_job(job: &Job) -> Vec<String> {
+    let mut problems = Vec::new();
+    if job.name.trim().is_empty() {
+        problems.push("`name` can't be empty".to_owned());
+    }
+    // The name is used as the directory for the job's state
+    if !is_safe_name(&job.name) {
+        problems.push("`name` can only contain letters, numbers, -, _ and . and can't \
+                       start with .".to_owned());
+    }
+    let sources = [
+        ("csv", job.csv.is_some()),
+        ("git_repo", job.git_repo.is_some()),
+        ("listenbrainz_user", job.listenbrainz_user.is_some()),
+    ];
+    let set: Vec<String> = sources.iter()
+        .filter(|&&(_, is_set)| is_set)
+        .map(|&(key, _)| format!("`{}`", key))
+        .collect();
+    match set.len() {
+        0 => problems.push("needs one of `csv`, `git_repo` or `listenbrainz_user`".to_owned()),
+        1 => {},
+        _ => problems.push(format!("only one of {} can be set", set.join(", "))),
+    }
+    if job.git_repo.is_some() && job.file.is_none() {
+        problems.push("`git_repo` needs `file`, the path of the CSV in the repository".to_owned());
+    }
+    if job.git_repo.is_none() {
+        let git_only = [("file", job.file.is_some()), ("since_ref", job.since_ref.is_some())];
+        for &(key, is_set) in &git_only {
+            if is_set {
+                problems.push(format!("`{}` is only used with `git_repo`", key));
+            }
+        }
+    }
+    if job.listenbrainz_user.is_none() && job.listened_since.is_some() {
+        problems.push("`listened_since` is only used with `listenbrainz_user`".to_owned());
+    }
+    match job.mode {
+        Mode::Import => {
+            let sync_only = [
+                ("quarantine", job.quarantine.is_some()),
+                ("max_removals", job.max_removals.is_some()),
+                ("max_changes_percent", job.max_changes_percent.is_some()),
+            ];
+            for &(key, is_set) in &sync_only {
+                if is_set {
+                    problems.push(format!("`{}` is only used with mode = \"sync\"", key));
+                }
+            }
+        },
+        Mode::Sync => {
+            if job.git_repo.is_some() {
+                problems.push("mode = \"sync\" needs the whole CSV so `git_repo` can't be used".to_owned());
+            }
+            if job.listenbrainz_user.is_some() {
+                problems.push("mode = \"sync\" needs the whole CSV so `listenbrainz_user` can't \
+                               be used".to_owned());
+            }
+            let import_only = [
+                ("dedupe_against", !job.dedupe_against.is_empty()),
+                ("never_readd", job.never_readd),
+                ("drip", job.drip.is_some()),
+            ];
+            for &(key, is_set) in &import_only {
+                if is_set {
+                    problems.push(format!("`{}` is only used with mode = \"import\"", key));
+                }
+            }
+        },
+    }
+    if let Some(percent) = job.max_changes_percent {
+        if !(0.0..=100.0).contains(&percent) {
+            problems.push("`max_changes_percent` must be between 0 and 100".to_owned());
+        }
+    }
+    if let Some(ref every) = job.every {
+        if let Err(e) = every.parse::<RunTime>() {
+            problems.push(format!("invalid `every`: {}", e));
+        }
+    }
+    if let Some(ref namespace) = job.id_namespace {
+        if let Err(e) = namespace.parse::<IdNamespace>() {
+            problems.push(format!("invalid `id_namespace`: {}", e));
+        }
+        if job.listenbrainz_user.is_some() {
+            problems.push("`id_namespace` can't be used with `listenbrainz_user`".to_owned());
+        }
+    }
+    if let Some(ref quiet_hours) = job.quiet_hours {
+        if let Err(e) = quiet_hours.parse::<QuietHours>() {
+            problems.push(format!("invalid `quiet_hours`: {}", e));
+        }
+    }
+    if let Some(ref drip) = job.drip {
+        if let Err(e) = drip.parse::<DripRate>() {
+            problems.push(format!("invalid `drip`: {}", e));
+        }
+    }
+    problems
+}
+
+/// Order the jobs so that each job comes after the jobs in its `after`.
+/// Jobs keep their config order otherwise. When jobs depend on each other in
+/// a cycle, the names of the jobs that can't be ordered are returned instead.
+///
+/// # Arguments
+///
+/// * `jobs` - The jobs to order. Names in `after` that aren't one of these
+/// jobs are ignored
+pub fn order_jobs<'a>(jobs: &[&'a Job]) -> Result<Vec<&'a Job>, Vec<String>> {
+    let names: HashSet<&str> = jobs.iter().map(|job| job.name.as_str()).collect();
+    let mut remaining = jobs.to_vec();
+    let mut ordered: Vec<&Job> = Vec::with_capacity(jobs.len());
+    while !remaining.is_empty() {
+        let next = remaining.iter().position(|job| job.after.iter().all(|dependency| {
+            !names.contains(dependency.as_str()) ||
+                ordered.iter().any(|done| done.name == *dependency)
+        }));
+        match next {
+            Some(index) => ordered.push(remaining.remove(index)),
+            None => return Err(remaining.iter().map(|job| job.name.clone()).collect()),
+        }
+    }
+    Ok(ordered)
+}
+
+/// Check the rules between jobs and within each job
+fn check_config(config: &Config) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut names = HashSet::new();
+    let all_names: Vec<&str> = config.jobs.iter().map(|job| job.name.as_str()).collect();
+    for job in &config.jobs {
+        let location = format!("job \"{}\"", job.name);
+        if !names.insert(&job.name) {
+            diagnostics.push(Diagnostic {
+                location: location.clone(),
+                message: "another job has the same name".to_owned(),
+            });
+        }
+        for message in check_job(job) {
+            diagnostics.push(Diagnostic { location: location.clone(), message: message });
+        }
+        for dependency in job.after.iter().filter(|d| !all_names.contains(&d.as_str())) {
+            diagnostics.push(Diagnostic {
+                location: location.clone(),
+                message: format!("`after` names a job that doesn't exist: \"{}\"", dependency),
+            });
+        }
+    }
+    let jobs: Vec<&Job> = config.jobs.iter().collect();
+    if let Err(cycle) = order_jobs(&jobs) {
+        diagnostics.push(Diagnostic {
+            location: String::new(),
+            message: format!("these jobs depend on each other in a cycle: {}", cycle.join(", ")),
+        });
+    }
+    // The name is used in the file name of the profile's token cache
+    for name in config.profiles.keys().filter(|name| name.is_empty() || !is_safe_name(name)) {
+        diagnostics.push(Diagnostic {
+            location: format!("profile \"{}\"", name),
+            message: "profile names can only contain letters, numbers, -, _ and . and can't \
+                      start with .".to_owned(),
+        });
+    }
+    diagnostics
+}
+
+/// Parse and validate a config. Every problem that's found is returned, so
+/// that they can all be fixed at once.
+///
+/// # Arguments
+///
+/// * `data` - The TOML config
+pub fn parse_config(data: &str) -> Result<Config, Vec<Diagnostic>> {
+    let file_error = |message: String| vec![Diagnostic {
+        location: String::new(),
+        message: message,
+    }];
+    let value: Value = data.parse().map_err(|e: toml::de::Error| file_error(e.to_string()))?;
+    let mut diagnostics = check_unknown_keys(&value);
+    match value.try_into::<Config>() {
+        Ok(config) => {
+            diagnostics.extend(check_config(&config));
+            if diagnostics.is_empty() {
+                return Ok(config);
+            }
+        },
+        Err(e) => diagnostics.extend(file_error(e.to_string())),
+    }
+    Err(diagnostics)
+}
+
+/// Read and validate a config file, see `parse_config`
+///
+/// # Arguments
+///
+/// * `filename` - The path to the config file
+pub fn read_config(filename: &str) -> Result<Config, Vec<Diagnostic>> {
+    let mut data = String::new();
+    File::open(filename)
+        .and_then(|mut file| file.read_to_string(&mut data))
+        .map_err(|e| vec![Diagnostic { location: filename.to_owned(), message: e.to_string() }])?;
+    parse_config(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(result: Result<Config, Vec<Diagnostic>>) -> Vec<String> {
+        result.unwrap_err().iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_config_reads_jobs() {
+        // Given
+        let data = r#"
+            [[job]]
+            name = "requests"
+            playlist = "Requests"
+            csv = "songs.csv"
+            every = "6h"
+
+            [[job]]
+            name = "mirror"
+            playlist = "Mirror"
+            mode = "sync"
+            csv = "mirror.csv"
+            max_removals = 5
+        "#;
+        // When
+        let config = parse_config(data).unwrap();
+        // Then
+        assert_eq!(2, config.jobs.len());
+        assert_eq!(Mode::Import, config.jobs[0].mode);
+        assert_eq!(Mode::Sync, config.jobs[1].mode);
+        assert_eq!(Some(5), config.jobs[1].max_removals);
+    }
+
+    #[test]
+    fn parse_config_suggests_unknown_keys() {
+        // Given
+        let data = r#"
+            [[jobs]]
+            name = "requests"
+
+            [[job]]
+            name = "requests"
+            plalist = "Requests"
+            csv = "songs.csv"
+            colour = "blue"
+        "#;
+        // When
+        let result = parse_config(data);
+        // Then
+        assert_eq!(vec![
+            "unknown key `jobs`, did you mean `job`?".to_string(),
+            "job \"requests\": unknown key `colour`".to_string(),
+            "job \"requests\": unknown key `plalist`, did you mean `playlist`?".to_string(),
+            "missing field `playlist` for key `job`".to_string(),
+        ], messages(result));
+    }
+
+    #[test]
+    fn parse_config_checks_fields_together() {
+        // Given
+        let data = r#"
+            [[job]]
+            name = "a"
+            playlist = "A"
+            csv = "a.csv"
+            quarantine = "Removed"
+
+            [[job]]
+            name = "a"
+            playlist = "B"
+            mode = "sync"
+            git_repo = "."
+            every = "often"
+        "#;
+        // When
+        let result = parse_config(data);
+        // Then
+        assert_eq!(vec![
+            "job \"a\": `quarantine` is only used with mode = \"sync\"".to_string(),
+            "job \"a\": another job has the same name".to_string(),
+            "job \"a\": `git_repo` needs `file`, the path of the CSV in the repository".to_string(),
+            "job \"a\": mode = \"sync\" needs the whole CSV so `git_repo` can't be used".to_string(),
+            "job \"a\": invalid `every`: Expected a length of time such as 30s, 10m or 1h, got \"often\"".to_string(),
+        ], messages(result));
+    }
+
+    #[test]
+    fn parse_config_checks_listenbrainz_jobs() {
+        // Given
+        let data = r#"
+            [[job]]
+            name = "this-week"
+            playlist = "This Week"
+            listenbrainz_user = "rob"
+            listened_since = 1520000000
+            every = "1h"
+
+            [[job]]
+            name = "mirror"
+            playlist = "Mirror"
+            mode = "sync"
+            csv = "mirror.csv"
+            listenbrainz_user = "rob"
+
+            [[job]]
+            name = "requests"
+            playlist = "Requests"
+            csv = "songs.csv"
+            listened_since = 1520000000
+        "#;
+        // When
+        let result = parse_config(data);
+        // Then
+        assert_eq!(vec![
+            "job \"mirror\": only one of `csv`, `listenbrainz_user` can be set".to_string(),
+            "job \"mirror\": mode = \"sync\" needs the whole CSV so `listenbrainz_user` can't be used".to_string(),
+            "job \"requests\": `listened_since` is only used with `listenbrainz_user`".to_string(),
+        ], messages(result));
+    }
+
+    #[test]
+    fn order_jobs_runs_dependencies_first() {
+        // Given
+        let config = parse_config(r#"
+            [[job]]
+            name = "clear-and-import"
+            playlist = "Weekly"
+            mode = "sync"
+            csv = "weekly.csv"
+            after = ["archive"]
+
+            [[job]]
+            name = "requests"
+            playlist = "Requests"
+            csv = "songs.csv"
+
+            [[job]]
+            name = "archive"
+            playlist = "Archive"
+            csv = "weekly.csv"
+        "#).unwrap();
+        let jobs: Vec<&Job> = config.jobs.iter().collect();
+        // When
+        let ordered = order_jobs(&jobs).unwrap();
+        // Then
+        let names: Vec<&str> = ordered.iter().map(|job| job.name.as_str()).collect();
+        assert_eq!(vec!["requests", "archive", "clear-and-import"], names);
+    }
+
+    #[test]
+    fn parse_config_finds_dependency_cycles() {
+        // Given
+        let data = r#"
+            [[job]]
+            name = "a"
+            playlist = "A"
+            csv = "a.csv"
+            after = ["b"]
+
+            [[job]]
+            name = "b"
+            playlist = "B"
+            csv = "b.csv"
+            after = ["a", "c"]
+        "#;
+        // When
+        let result = parse_config(data);
+        // Then
+        assert_eq!(vec![
+            "job \"b\": `after` names a job that doesn't exist: \"c\"".to_string(),
+            "these jobs depend on each other in a cycle: a, b".to_string(),
+        ], messages(result));
+    }
+
+    #[test]
+    fn parse_config_needs_job_names_that_are_safe_directories() {
+        // Given
+        let data = r#"
+            state_dir = "/var/lib/playlist-from-csv"
+
+            [[job]]
+            name = "../requests"
+            playlist = "Requests"
+            csv = "songs.csv"
+            never_readd = true
+        "#;
+        // When
+        let result = parse_config(data);
+        // Then
+        assert_eq!(vec![
+            "job \"../requests\": `name` can only contain letters, numbers, -, _ and . and \
+             can't start with .".to_string(),
+        ], messages(result));
+    }
+
+    #[test]
+    fn parse_config_reads_profiles() {
+        // Given
+        let data = r#"
+            [profiles.work]
+            client_id = "abc"
+            username = "rob-at-work"
+            playlist = "Office"
+
+            [profiles."../home"]
+            usernme = "rob"
+        "#;
+        // When
+        let config = parse_config(&data[..data.find("[profiles.\"").unwrap()]).unwrap();
+        let result = parse_config(data);
+        // Then
+        assert_eq!(Some(&Profile {
+            client_id: Some("abc".to_string()),
+            client_secret: None,
+            username: Some("rob-at-work".to_string()),
+            playlist: Some("Office".to_string()),
+        }), config.profiles.get("work"));
+        assert_eq!(vec![
+            "profile \"../home\": unknown key `usernme`, did you mean `username`?".to_string(),
+            "profile \"../home\": profile names can only contain letters, numbers, -, _ and . \
+             and can't start with .".to_string(),
+        ], messages(result));
+    }
+}
@@ -0,0 +1,43 @@
+use std::io::{self, BufRead, Write};
+
+/// Ask a yes or no question, defaulting to no. Only `y` or `yes` count as a
+/// yes, so that nothing happens by accident.
+///
+/// # Arguments
+///
+/// * `input` - Where the answer is read from, eg. stdin
+/// * `output` - Where the question is written, eg. stdout
+/// * `question` - The question to ask
+pub fn confirm<R: BufRead, W: Write>(mut input: R,
+                                     mut output: W,
+                                     question: &str) -> io::Result<bool> {
+    write!(output, "{} [y/N] ", question)?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    let answer = answer.trim().to_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirm_accepts_yes() {
+        // Given
+        let mut output = Vec::new();
+        // When
+        let result = confirm(&b"Yes\n"[..], &mut output, "Add 2 tracks?").unwrap();
+        // Then
+        assert!(result);
+        assert_eq!("Add 2 tracks? [y/N] ", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn confirm_defaults_to_no() {
+        for answer in &["\n", "n\n", "maybe\n", ""] {
+            assert!(!confirm(answer.as_bytes(), Vec::new(), "Add 2 tracks?").unwrap());
+        }
+    }
+}
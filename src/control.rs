@@ -0,0 +1,155 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::str::FromStr;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+/// A command sent to the daemon over its control socket, one per line
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlCommand {
+    /// Run a job now, whatever its schedule
+    Trigger(String),
+    /// Describe the daemon and its jobs
+    Status,
+    /// Stop running jobs on their schedule. Triggered jobs still run
+    Pause,
+    /// Run jobs on their schedule again
+    Resume,
+}
+
+impl FromStr for ControlCommand {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<ControlCommand, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let rest: Vec<&str> = words.collect();
+        match (command, rest.len()) {
+            ("trigger", 0) => Err("trigger needs the name of a job".to_owned()),
+            // Job names can have spaces
+            ("trigger", _) => Ok(ControlCommand::Trigger(rest.join(" "))),
+            ("status", 0) => Ok(ControlCommand::Status),
+            ("pause", 0) => Ok(ControlCommand::Pause),
+            ("resume", 0) => Ok(ControlCommand::Resume),
+            _ => Err(format!("Expected trigger <job>, status, pause or resume, got {:?}", line)),
+        }
+    }
+}
+
+/// A command waiting for the daemon to handle it
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    reply: Sender<String>,
+}
+
+impl ControlRequest {
+    /// Send the result of the command back to whoever sent it
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - What happened, which can be more than one line
+    pub fn reply(self, message: &str) {
+        // The sender may have disconnected, which doesn't affect the daemon
+        let _ = self.reply.send(message.to_owned());
+    }
+}
+
+/// Answer each line sent on a connection, waiting for the daemon to handle
+/// each command before reading the next. Each reply ends with an empty line
+/// so that replies of more than one line can be read.
+fn serve(stream: UnixStream, requests: &Sender<ControlRequest>) -> io::Result<()> {
+    let reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match line.parse() {
+            Ok(command) => {
+                let (reply, replies) = channel();
+                requests.send(ControlRequest { command: command, reply: reply })
+                    .map_err(|_| io::Error::other("The daemon stopped"))?;
+                replies.recv().unwrap_or_else(|_| "error: the daemon stopped".to_owned())
+            },
+            Err(e) => format!("error: {}", e),
+        };
+        writeln!(writer, "{}\n", reply)?;
+    }
+    Ok(())
+}
+
+/// Listen for commands on a Unix socket, eg. from
+/// `echo status | nc -U daemon.sock`. A socket left behind by a daemon that
+/// stopped is replaced. Connections are served on their own threads and the
+/// commands are returned to be handled by the daemon.
+///
+/// # Arguments
+///
+/// * `path` - Where to create the socket
+pub fn listen(path: &str) -> io::Result<Receiver<ControlRequest>> {
+    let is_socket = fs::symlink_metadata(path)
+        .map(|metadata| metadata.file_type().is_socket())
+        .unwrap_or(false);
+    if is_socket {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    let (requests, received) = channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Could not accept a control connection: {}", e);
+                    continue;
+                },
+            };
+            let requests = requests.clone();
+            thread::spawn(move || {
+                if let Err(e) = serve(stream, &requests) {
+                    debug!("Control connection closed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(received)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn commands_are_read_from_lines_and_answered() {
+        // Given
+        let path = env::temp_dir().join("playlist-from-csv-control-test.sock");
+        let path = path.to_str().unwrap();
+        let requests = listen(path).unwrap();
+        let daemon = thread::spawn(move || {
+            for request in requests.iter().take(2) {
+                let message = format!("handled {:?}", request.command);
+                request.reply(&message);
+            }
+        });
+        // When
+        let mut stream = UnixStream::connect(path).unwrap();
+        stream.write_all(b"trigger weekly mix\nunpause\nstatus\n").unwrap();
+        let lines: Vec<String> = BufReader::new(stream).lines().take(6).map(|line| line.unwrap()).collect();
+        daemon.join().unwrap();
+        fs::remove_file(path).unwrap();
+        // Then
+        assert_eq!(vec![
+            "handled Trigger(\"weekly mix\")".to_owned(),
+            "".to_owned(),
+            "error: Expected trigger <job>, status, pause or resume, got \"unpause\"".to_owned(),
+            "".to_owned(),
+            "handled Status".to_owned(),
+            "".to_owned(),
+        ], lines);
+        assert_eq!(Err("trigger needs the name of a job".to_owned()), "trigger".parse::<ControlCommand>());
+    }
+}
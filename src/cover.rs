@@ -0,0 +1,101 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+
+/// The largest cover image Spotify accepts once it's base64 encoded
+pub const MAX_COVER_IMAGE_SIZE: usize = 256 * 1024;
+
+/// The bytes that every JPEG starts with
+const JPEG_MAGIC: [u8; 3] = [0xFF, 0xD8, 0xFF];
+
+/// The error when a cover image can't be uploaded to Spotify
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InvalidCoverImage {
+    /// Spotify only accepts JPEG cover images
+    NotJpeg,
+    /// The image is over `MAX_COVER_IMAGE_SIZE` once encoded, along with its
+    /// encoded size
+    TooLarge(usize),
+}
+
+impl Error for InvalidCoverImage {
+    fn description(&self) -> &str {
+        match *self {
+            InvalidCoverImage::NotJpeg => "The cover image must be a JPEG",
+            InvalidCoverImage::TooLarge(_) => "The cover image must be under 256KB once base64 encoded",
+        }
+    }
+}
+
+impl fmt::Display for InvalidCoverImage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InvalidCoverImage::TooLarge(size) => write!(
+                f, "{}, this image is {}KB", self.description(), size.div_ceil(1024)
+            ),
+            _ => write!(f, "{}", self.description()),
+        }
+    }
+}
+
+/// The size of the data once it's base64 encoded with padding
+fn encoded_size(length: usize) -> usize {
+    length.div_ceil(3) * 4
+}
+
+/// Check that Spotify will accept the image as a playlist cover
+///
+/// # Arguments
+///
+/// * `image` - The contents of the image file
+pub fn check_cover_image(image: &[u8]) -> Result<(), InvalidCoverImage> {
+    if !image.starts_with(&JPEG_MAGIC) {
+        return Err(InvalidCoverImage::NotJpeg);
+    }
+    let size = encoded_size(image.len());
+    if size > MAX_COVER_IMAGE_SIZE {
+        return Err(InvalidCoverImage::TooLarge(size));
+    }
+    Ok(())
+}
+
+/// Read a cover image, checking that Spotify will accept it so that a bad
+/// image is caught before the playlist is changed
+///
+/// # Arguments
+///
+/// * `filename` - The path to the JPEG
+pub fn read_cover_image(filename: &str) -> Result<Vec<u8>, Box<Error>> {
+    let mut image = Vec::new();
+    File::open(filename)?.read_to_end(&mut image)?;
+    check_cover_image(&image)?;
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jpeg(length: usize) -> Vec<u8> {
+        let mut image = JPEG_MAGIC.to_vec();
+        image.resize(length, 0);
+        image
+    }
+
+    #[test]
+    fn check_cover_image_accepts_small_jpegs() {
+        // Given
+        let largest = MAX_COVER_IMAGE_SIZE / 4 * 3;
+        // When
+        let small = check_cover_image(&jpeg(1024));
+        let fits = check_cover_image(&jpeg(largest));
+        let too_large = check_cover_image(&jpeg(largest + 1));
+        let png = check_cover_image(b"\x89PNG\r\n\x1a\n");
+        // Then
+        assert_eq!(Ok(()), small);
+        assert_eq!(Ok(()), fits);
+        assert_eq!(Err(InvalidCoverImage::TooLarge(MAX_COVER_IMAGE_SIZE + 4)), too_large);
+        assert_eq!(Err(InvalidCoverImage::NotJpeg), png);
+    }
+}
@@ -1,62 +1,484 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::error::Error;
 use std::fs::File;
+use std::io::{self, Read};
+use std::str::FromStr;
 
+use capacity::{split_at_capacity,OnFull,PlaylistFull};
+use duplicates::{add_duplicate_copies,DuplicatePolicy};
+use encoding::{decode,Decoded,Encoding};
+use harmonic::{sort_tracks,SortOrder};
+use i18n::tr;
+use spread::spread_tracks;
+use versions::{collapse_tracks,VersionPreference};
 use playlist::{PlaylistAPI};
+use events::{emit,Event};
+use progress::{NoProgress,Progress,Stage};
+use source::SongSource;
+use track_uri::TrackUri;
 
 extern crate csv;
 
 /// A struct containing relevant spotify information for playlist tracks.
-/// This is specifically used for a DynamoDB export to CSV
-#[derive(Clone, Debug, Deserialize)]
+/// The column names are those of a DynamoDB export to CSV, which are used
+/// when writing songs. Other columns can be read using `CsvColumns`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Song {
     /// A human readable name of the song
     #[serde(rename = "music (S)")]
     pub music: String,
-    /// A Spotify ID for the track
+    /// A Spotify ID for the track. This can also be a Spotify URI or link,
+    /// eg. for podcast episodes
     #[serde(rename = "song_id (S)")]
     pub song_id: String,
 }
 
-/// Parse a CSV file to retrieve song information. The fields
-/// it should have are "music (S)" and "song_id (S)" as described
-/// in the struct above.
+/// The filename used to read a CSV from stdin, eg. when piping an export
+pub const STDIN_FILENAME: &str = "-";
+
+/// The names of the CSV columns that songs are read from. The defaults are
+/// the columns of a DynamoDB export, "music (S)" and "song_id (S)".
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvColumns {
+    /// The column with a human readable name of each song
+    pub name: String,
+    /// The column with each song's Spotify ID, URI or link
+    pub id: String,
+    /// Only match headers that are exactly the same as the column names,
+    /// rather than ignoring case and spaces
+    pub strict: bool,
+    /// The encoding of the CSV, or `None` to detect it, see `decode`
+    pub encoding: Option<Encoding>,
+}
+
+impl Default for CsvColumns {
+    fn default() -> CsvColumns {
+        CsvColumns { name: "music (S)".to_owned(), id: "song_id (S)".to_owned(), strict: false, encoding: None }
+    }
+}
+
+/// Headers are compared without case or spaces, since spreadsheets often
+/// change them, eg. `Music(S)` or `SONG_ID (S)`
+fn normalize_header(header: &str) -> String {
+    header.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+impl CsvColumns {
+    /// Columns where any that aren't given are left as the default
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The column with the name of each song
+    /// * `id` - The column with the ID of each song
+    pub fn new(name: Option<&str>, id: Option<&str>) -> CsvColumns {
+        let default = CsvColumns::default();
+        CsvColumns {
+            name: name.map(|name| name.to_owned()).unwrap_or(default.name),
+            id: id.map(|id| id.to_owned()).unwrap_or(default.id),
+            strict: default.strict,
+            encoding: default.encoding,
+        }
+    }
+
+    /// Match headers exactly, see `strict`
+    ///
+    /// # Arguments
+    ///
+    /// * `strict` - Whether headers have to be exactly the same as the
+    /// column names
+    pub fn with_strict(self, strict: bool) -> CsvColumns {
+        CsvColumns { strict: strict, ..self }
+    }
+
+    /// Read the CSV in the given encoding rather than detecting it
+    ///
+    /// # Arguments
+    ///
+    /// * `encoding` - The encoding of the CSV, or `None` to detect it
+    pub fn with_encoding(self, encoding: Option<Encoding>) -> CsvColumns {
+        CsvColumns { encoding: encoding, ..self }
+    }
+
+    /// Find the index of each column in the CSV's header, as (name, id). An
+    /// exact match is preferred over one that only differs in case or spaces
+    fn find(&self, headers: &csv::StringRecord) -> Result<(usize, usize), MissingColumn> {
+        let position = |column: &str| {
+            let normalized = normalize_header(column);
+            let similar = headers.iter().position(|header| normalize_header(header) == normalized);
+            match (headers.iter().position(|header| header == column), similar) {
+                (Some(position), _) => Ok(position),
+                (None, Some(position)) if !self.strict => Ok(position),
+                (None, similar) => Err(MissingColumn {
+                    column: column.to_owned(),
+                    similar: similar.and_then(|position| headers.get(position)).map(|header| header.to_owned()),
+                }),
+            }
+        };
+        Ok((position(&self.name)?, position(&self.id)?))
+    }
+}
+
+/// The error when a CSV doesn't have a column that songs are read from
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingColumn {
+    pub column: String,
+    /// A header that only differs in case or spaces, which isn't used in
+    /// strict mode
+    pub similar: Option<String>,
+}
+
+impl Error for MissingColumn {
+    fn description(&self) -> &str {
+        "The CSV is missing a column, use --name-column and --id-column to \
+        read other columns"
+    }
+}
+
+impl fmt::Display for MissingColumn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The CSV has no column named \"{}\"", self.column)?;
+        match self.similar {
+            Some(ref similar) => write!(f, ", but has \"{}\" which isn't used with --strict-headers", similar),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Parse a CSV file to retrieve song information, reading each song from
+/// the given columns. A DynamoDB export has "music (S)" and "song_id (S)"
+/// columns as described in the struct above, see `CsvColumns::default`.
 ///
 /// # Arguments
 ///
-/// * `filename` - The path to the CSV file
-pub fn parse_csv_file(filename: &str) -> Result<Vec<Song>, Box<Error>> {
+/// * `filename` - The path to the CSV file, or `-` to read from stdin
+/// * `columns` - The columns to read each song from
+pub fn parse_csv_file_with_columns(filename: &str, columns: &CsvColumns) -> Result<Vec<Song>, Box<Error>> {
+    parse_csv_file_with_progress(filename, columns, &[], false, &NoProgress).map(ParsedCsv::into_songs)
+}
+
+/// A row of a CSV that couldn't be read and was skipped
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidRow {
+    /// The line the row is on, where the header is line 1
+    pub line: usize,
+    /// Why the row couldn't be read
+    pub reason: String,
+}
+
+impl fmt::Display for InvalidRow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+/// The songs read from a CSV along with the rows that were skipped
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParsedCsv {
+    /// Each song with the line it's on, where the header is line 1
+    pub rows: Vec<(usize, Song)>,
+    /// The rows that couldn't be read, which is only ever non-empty when
+    /// invalid rows are skipped
+    pub invalid_rows: Vec<InvalidRow>,
+    /// The values of the other columns that were read along with the songs,
+    /// keyed by header and then by line, see `column`
+    pub side_columns: HashMap<String, HashMap<usize, String>>,
+}
+
+impl ParsedCsv {
+    /// Songs that weren't read from a CSV, numbered as if they were so that
+    /// the first is on line 2
+    ///
+    /// # Arguments
+    ///
+    /// * `songs` - The songs in the order they were read
+    pub fn from_songs(songs: Vec<Song>) -> ParsedCsv {
+        ParsedCsv {
+            rows: songs.into_iter().enumerate().map(|(i, song)| (i + 2, song)).collect(),
+            ..ParsedCsv::default()
+        }
+    }
+
+    /// The first of the columns that the CSV has, with the value on each
+    /// line. Only columns that were asked for when parsing are read, and
+    /// rows that were skipped have no value. Returns `None` when the CSV has
+    /// none of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - The column names, in order of preference
+    pub fn column(&self, names: &[&str]) -> Option<&HashMap<usize, String>> {
+        names.iter().filter_map(|name| self.side_columns.get(*name)).next()
+    }
+
+    /// The songs without their lines
+    pub fn into_songs(self) -> Vec<Song> {
+        self.rows.into_iter().map(|(_, song)| song).collect()
+    }
+
+    /// The lines of songs without a track ID and of rows that were skipped,
+    /// see `get_lines_without_track_id`
+    pub fn failed_lines(&self) -> Vec<usize> {
+        let mut lines: Vec<usize> = self.rows.iter()
+            .filter(|&(_, song)| !has_track_id(song) || !TrackUri::parse(&song.song_id).is_addable())
+            .map(|&(line, _)| line)
+            .chain(self.invalid_rows.iter().map(|row| row.line))
+            .collect();
+        lines.sort();
+        lines
+    }
+}
+
+/// Describe a row that can't be read, or `None` when the error isn't about
+/// a single row, eg. when the file can't be read
+fn invalid_row(error: &csv::Error) -> Option<InvalidRow> {
+    let (position, reason) = match *error.kind() {
+        csv::ErrorKind::UnequalLengths { ref pos, expected_len, len } => {
+            (pos, format!("has {} fields but the header has {}", len, expected_len))
+        },
+        csv::ErrorKind::Utf8 { ref pos, .. } => (pos, "is not valid UTF-8".to_owned()),
+        csv::ErrorKind::Deserialize { ref pos, ref err } => (pos, err.to_string()),
+        _ => return None,
+    };
+    let line = position.as_ref()?.line() as usize;
+    Some(InvalidRow { line: line, reason: reason })
+}
+
+/// Parse a CSV file in the same way as `parse_csv_file_with_columns`,
+/// reporting each row that's read. Rows that can't be read, eg. since they
+/// have the wrong number of fields, stop the parse unless
+/// `skip_invalid_rows` is set, in which case they're returned instead.
+/// Columns that aren't part of `Song` are read in the same pass, so that
+/// their values stay on the same lines as the songs, see `ParsedCsv::column`.
+///
+/// # Arguments
+///
+/// * `filename` - The path to the CSV file, or `-` to read from stdin
+/// * `columns` - The columns to read each song from
+/// * `side_columns` - Other columns to read, which are skipped when the CSV
+/// doesn't have them
+/// * `skip_invalid_rows` - Whether to carry on past rows that can't be read
+/// * `progress` - Where to report the rows read to
+pub fn parse_csv_file_with_progress(filename: &str,
+                                    columns: &CsvColumns,
+                                    side_columns: &[&str],
+                                    skip_invalid_rows: bool,
+                                    progress: &Progress) -> Result<ParsedCsv, Box<Error>> {
+    if filename == STDIN_FILENAME {
+        let stdin = io::stdin();
+        return parse_csv_with_progress(stdin.lock(), columns, side_columns, skip_invalid_rows, progress);
+    }
     let file = File::open(filename)?;
-    let mut rdr = csv::Reader::from_reader(file);
-    let mut results = Vec::new();
-    for result in rdr.deserialize() {
-        let record: Song = result?;
-        results.push(record);
+    parse_csv_with_progress(file, columns, side_columns, skip_invalid_rows, progress)
+}
+
+/// Parse CSV data to retrieve song information, see
+/// `parse_csv_file_with_columns`. The columns of a DynamoDB export are read.
+///
+/// # Arguments
+///
+/// * `reader` - Where the CSV data is read from
+pub fn parse_csv<R: Read>(reader: R) -> Result<Vec<Song>, Box<Error>> {
+    parse_csv_with_columns(reader, &CsvColumns::default())
+}
+
+/// Parse CSV data, reading songs from the given columns
+///
+/// # Arguments
+///
+/// * `reader` - Where the CSV data is read from
+/// * `columns` - The columns to read each song from
+pub fn parse_csv_with_columns<R: Read>(reader: R, columns: &CsvColumns) -> Result<Vec<Song>, Box<Error>> {
+    parse_csv_with_progress(reader, columns, &[], false, &NoProgress).map(ParsedCsv::into_songs)
+}
+
+fn parse_csv_with_progress<R: Read>(reader: R,
+                                    columns: &CsvColumns,
+                                    side_columns: &[&str],
+                                    skip_invalid_rows: bool,
+                                    progress: &Progress) -> Result<ParsedCsv, Box<Error>> {
+    let mut records = stream_csv(reader, columns)?;
+    // The index of each side column that the CSV has, along with its values
+    let mut side: Vec<(&str, usize, HashMap<usize, String>)> = side_columns.iter()
+        .filter_map(|&name| records.position(name).map(|index| (name, index, HashMap::new())))
+        .collect();
+    let mut parsed = ParsedCsv::default();
+    while let Some(result) = records.next_record() {
+        match result {
+            Ok((line, song, record)) => {
+                for column in &mut side {
+                    column.2.insert(line, record.get(column.1).unwrap_or_default().trim().to_owned());
+                }
+                parsed.rows.push((line, song));
+            },
+            Err(e) => match invalid_row(&e) {
+                Some(row) if skip_invalid_rows => {
+                    emit(&Event::RowSkipped { line: row.line, reason: &row.reason });
+                    parsed.invalid_rows.push(row);
+                    continue;
+                },
+                _ => return Err(From::from(e)),
+            },
+        }
+        progress.update(Stage::CsvRows, parsed.rows.len() as u64, None);
     }
-    Ok(results)
+    progress.finish(Stage::CsvRows);
+    parsed.side_columns = side.into_iter().map(|(name, _, values)| (name.to_owned(), values)).collect();
+    Ok(parsed)
 }
 
-/// Used to get the ID out of the Song struct
-fn get_track_id_from_song(song: &Song) -> Option<String> {
+/// The songs in CSV data along with their lines, read a row at a time so
+/// that the whole file doesn't have to fit in memory, see `stream_csv`
+pub struct SongRecords<R: Read> {
+    records: csv::StringRecordsIntoIter<Decoded<R>>,
+    /// The CSV's header, to find other columns in
+    headers: csv::StringRecord,
+    /// The index of the name column
+    name: usize,
+    /// The index of the ID column
+    id: usize,
+    /// The number of rows read so far
+    rows: usize,
+}
+
+impl<R: Read> SongRecords<R> {
+    /// The index of a column, if the CSV has it
+    fn position(&self, header: &str) -> Option<usize> {
+        self.headers.iter().position(|name| name == header)
+    }
+
+    /// Read the next song along with the row it's from, so that other
+    /// columns can be read from the row
+    fn next_record(&mut self) -> Option<Result<(usize, Song, csv::StringRecord), csv::Error>> {
+        let record = match self.records.next()? {
+            Ok(record) => record,
+            Err(e) => return Some(Err(e)),
+        };
+        self.rows += 1;
+        // Lines come from the row's position so that quoted fields with line
+        // breaks don't throw them off
+        let line = record.position().map_or(self.rows + 1, |position| position.line() as usize);
+        let song = Song {
+            music: record.get(self.name).unwrap_or_default().to_owned(),
+            song_id: record.get(self.id).unwrap_or_default().to_owned(),
+        };
+        emit(&Event::RowParsed { line: line, music: &song.music, song_id: &song.song_id });
+        Some(Ok((line, song, record)))
+    }
+}
+
+impl<R: Read> Iterator for SongRecords<R> {
+    type Item = Result<(usize, Song), csv::Error>;
+
+    fn next(&mut self) -> Option<Result<(usize, Song), csv::Error>> {
+        self.next_record().map(|result| result.map(|(line, song, _)| (line, song)))
+    }
+}
+
+/// Read songs from CSV data a row at a time, see `SongRecords`. The header
+/// is read straight away so that missing columns are found before any rows.
+/// Data that isn't UTF-8 is transcoded first, see `decode`
+///
+/// # Arguments
+///
+/// * `reader` - Where the CSV data is read from
+/// * `columns` - The columns to read each song from
+pub fn stream_csv<R: Read>(reader: R, columns: &CsvColumns) -> Result<SongRecords<R>, Box<Error>> {
+    let mut rdr = csv::Reader::from_reader(decode(reader, columns.encoding)?);
+    let headers = rdr.headers()?.clone();
+    let (name, id) = columns.find(&headers)?;
+    Ok(SongRecords { records: rdr.into_records(), headers: headers, name: name, id: id, rows: 0 })
+}
+
+/// Read songs from a CSV file a row at a time, see `stream_csv`
+///
+/// # Arguments
+///
+/// * `filename` - The path to the CSV file, or `-` to read from stdin
+/// * `columns` - The columns to read each song from
+pub fn stream_csv_file(filename: &str, columns: &CsvColumns) -> Result<SongRecords<Box<Read>>, Box<Error>> {
+    let reader: Box<Read> = if filename == STDIN_FILENAME {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(filename)?)
+    };
+    stream_csv(reader, columns)
+}
+
+/// Whether the song has a Spotify track ID
+pub fn has_track_id(song: &Song) -> bool {
     // DynamoDB will set the track ID to true if it's null
     // since the type is NULL instead of String. This is
     // weird but it's fine. "true" just means there's no ID
     // I'm willing to make the assumption that "true" is not
     // a valid Spotify track ID
-    if song.song_id == "true" {
-        println!("Missing track ID for: {}", song.music);
+    song.song_id != "true"
+}
+
+/// Read the first of the columns that a CSV has, with a value for each row.
+/// This is for columns that aren't part of `Song`, so rows are matched to
+/// songs by their position, which only holds when no rows are skipped, see
+/// `ParsedCsv::column`. Returns `None` when the CSV has none of them.
+/// The encoding is detected the same way as for the songs, see `decode`.
+///
+/// # Arguments
+///
+/// * `filename` - The path to the CSV file
+/// * `names` - The column names, in order of preference
+pub fn read_column(filename: &str, names: &[&str]) -> Result<Option<Vec<String>>, Box<Error>> {
+    let mut rdr = csv::Reader::from_reader(decode(File::open(filename)?, None)?);
+    let headers = rdr.headers()?.clone();
+    let column = names.iter()
+        .filter_map(|name| headers.iter().position(|header| header == *name))
+        .next();
+    let column = match column {
+        Some(column) => column,
+        None => return Ok(None),
+    };
+    let mut values = Vec::new();
+    for record in rdr.records() {
+        values.push(record?.get(column).unwrap_or_default().trim().to_owned());
+    }
+    Ok(Some(values))
+}
+
+/// Used to get the ID out of the Song struct. Local files and IDs that
+/// can't be recognised are skipped since they can't be added
+fn get_track_id_from_song(song: &Song) -> Option<TrackUri> {
+    if !has_track_id(song) {
+        warn!("{}", tr("missing-track-id", &[("song", song.music.as_str().into())]));
         return None
     }
-    Some(song.song_id.to_owned())
+    match TrackUri::parse(&song.song_id) {
+        TrackUri::Local(_) => {
+            warn!("Skipping local file: {}", song.music);
+            None
+        },
+        TrackUri::Unknown(id) => {
+            warn!("Unrecognised track ID `{}` for: {}", id, song.music);
+            None
+        },
+        track => {
+            emit(&Event::Matched { music: &song.music, track: &track.uri() });
+            Some(track)
+        },
+    }
 }
 
 /// Playlist error enum for different errors when adding tracks to playlist
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PlaylistAddError<E> {
     /// Generic playlist error from API
     APIError(E),
+    /// The error when the songs can't be read from their source
+    ReadError(String),
     /// The error when the playlist cannot be found
     NoNewTracks(NoNewTracks),
+    /// The error when the tracks won't fit in the playlist
+    PlaylistFull(PlaylistFull),
 }
 
 /// An error when there are no new tracks to add to the playlist
@@ -75,7 +497,121 @@ impl fmt::Display for NoNewTracks {
     }
 }
 
-/// Add songs to a playlist using the input API.
+/// Where in the playlist added tracks go
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum Position {
+    /// Before the playlist's first track
+    Start,
+    /// After the playlist's last track
+    #[default]
+    End,
+    /// Before the track at this index, starting from 0
+    Index(usize),
+}
+
+impl Position {
+    /// The index to insert at in a playlist with `length` items, or `None`
+    /// to add to the end. Indexes past the end add to the end
+    pub fn index(&self, length: usize) -> Option<usize> {
+        match *self {
+            Position::Start => Some(0),
+            Position::End => None,
+            Position::Index(index) if index >= length => None,
+            Position::Index(index) => Some(index),
+        }
+    }
+}
+
+/// The error when a position isn't start, end or an index
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidPosition {}
+
+impl Error for InvalidPosition {
+    fn description(&self) -> &str {
+        "Expected start, end or an index starting from 0"
+    }
+}
+
+impl fmt::Display for InvalidPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl FromStr for Position {
+    type Err = InvalidPosition;
+
+    fn from_str(s: &str) -> Result<Position, InvalidPosition> {
+        match s {
+            "start" => Ok(Position::Start),
+            "end" => Ok(Position::End),
+            _ => s.parse().map(Position::Index).map_err(|_| InvalidPosition {}),
+        }
+    }
+}
+
+/// Changes to how songs are added. The defaults add every new track
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AddOptions {
+    /// Keep only one version of each song, see `collapse_versions`. This is
+    /// done first so that the other options see one track per song
+    pub collapse_versions: Option<VersionPreference>,
+    /// The most tracks to add, see `limit_tracks`
+    pub max_tracks: Option<usize>,
+    /// Which tracks are added once for each of their rows rather than only
+    /// once, see `add_duplicate_copies`. Copies are added after
+    /// `collapse_versions` and `max_tracks`, which see one of each track
+    pub duplicates: DuplicatePolicy,
+    /// Add the tracks in the order of the songs instead of sorted by ID,
+    /// see `keep_song_order`
+    pub keep_order: bool,
+    /// The order to add the tracks in, instead of sorted by ID. This is
+    /// done after `keep_order`
+    pub sort: Option<SortOrder>,
+    /// The fewest tracks between two tracks by the same artist, see
+    /// `spread_artists`. This is done after sorting
+    pub spread_artists: Option<usize>,
+    /// What to do when the tracks won't all fit in the playlist. This is
+    /// checked last, right before adding
+    pub on_full: OnFull,
+    /// Where the tracks go in the playlist. They're kept in order, eg. when
+    /// added to the start the first added track becomes the first track
+    pub position: Position,
+}
+
+/// The tracks added by `add_songs_returning_tracks`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AddedTracks {
+    /// The tracks that were added, in the order they were added
+    pub added: Vec<TrackUri>,
+    /// The tracks that didn't fit in the playlist, to be spilled into
+    /// continuation playlists. Only set for `OnFull::Spill`
+    pub overflow: Vec<TrackUri>,
+}
+
+/// Add songs to a playlist using the input API. Returns the number of
+/// tracks that were added.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the tracks should be added
+/// * `playlist_id` - The playlist ID to be added to. This is the ID and *not*
+/// the name.
+/// * `songs` - Where the songs are read from, eg. a vec of the songs or a
+/// `CsvFile`
+pub fn add_songs_to_playlist<E, S: SongSource>(playlist_api: &PlaylistAPI<E>,
+                                               playlist_id: &str,
+                                               songs: S) -> Result<usize, PlaylistAddError<E>> {
+    let songs = songs.read_songs().map_err(|e| PlaylistAddError::ReadError(e.to_string()))?;
+    add_songs_returning_tracks(
+        playlist_api, playlist_id, songs, &AddOptions::default()
+    ).map(|added| added.added.len())
+}
+
+/// Add songs to a playlist in the same way as `add_songs_to_playlist`, but
+/// return the tracks that were added in the order they were added. Tracks
+/// that would take the playlist past Spotify's limit are handled as
+/// `options.on_full` says.
 ///
 /// # Arguments
 ///
@@ -83,32 +619,274 @@ impl fmt::Display for NoNewTracks {
 /// * `playlist_id` - The playlist ID to be added to. This is the ID and *not*
 /// the name.
 /// * `songs` - A vec of the songs
-pub fn add_songs_to_playlist<E>(playlist_api: &PlaylistAPI<E>,
-                                playlist_id: &str,
-                                songs: Vec<Song>) -> Result<(), PlaylistAddError<E>> {
+/// * `options` - Changes to which tracks are added and their order
+pub fn add_songs_returning_tracks<E>(playlist_api: &PlaylistAPI<E>,
+                                     playlist_id: &str,
+                                     songs: Vec<Song>,
+                                     options: &AddOptions) -> Result<AddedTracks, PlaylistAddError<E>> {
+    // The playlist's tracks are fetched once for both the duplicates and
+    // how full it is
+    let existing = playlist_api.get_track_ids_in_playlist(playlist_id)
+        .map_err(PlaylistAddError::APIError)?;
+    let mut filtered = remove_existing(&existing, get_unique_track_ids(&songs));
+    if let Some(preference) = options.collapse_versions {
+        filtered = collapse_tracks(playlist_api, &filtered, preference).map_err(PlaylistAddError::APIError)?;
+    }
+    if let Some(max_tracks) = options.max_tracks {
+        filtered = limit_tracks(&songs, filtered, max_tracks);
+    }
+    filtered = add_duplicate_copies(filtered, &songs, &existing, &options.duplicates);
+    if options.keep_order {
+        filtered = keep_song_order(&songs, filtered);
+    }
+    if let Some(order) = options.sort {
+        filtered = sort_tracks(playlist_api, &filtered, order).map_err(PlaylistAddError::APIError)?;
+    }
+    if let Some(spacing) = options.spread_artists {
+        filtered = spread_tracks(playlist_api, &filtered, spacing).map_err(PlaylistAddError::APIError)?;
+    }
+    let (filtered, overflow) = split_at_capacity(existing.len(), filtered);
+    let overflow = if overflow.is_empty() {
+        overflow
+    } else {
+        match options.on_full {
+            OnFull::Abort => {
+                return Err(PlaylistAddError::PlaylistFull(PlaylistFull {
+                    existing: existing.len(),
+                    adding: filtered.len() + overflow.len(),
+                }));
+            },
+            OnFull::Truncate => {
+                warn!("Skipping {} tracks that don't fit in the playlist", overflow.len());
+                Vec::new()
+            },
+            OnFull::Spill => overflow,
+        }
+    };
+    // If there's no tracks left then send back a message to indicate that
+    if filtered.is_empty() && overflow.is_empty() {
+        return Err(PlaylistAddError::NoNewTracks(NoNewTracks {}));
+    }
+    // Add the IDs to the playlist
+    if !filtered.is_empty() {
+        playlist_api.add_tracks_to_playlist(
+            playlist_id, &filtered[..], options.position.index(existing.len())
+        ).map_err(PlaylistAddError::APIError)?;
+        info!("{}", tr("tracks-added", &[("count", filtered.len().into()), ("id", playlist_id.into())]));
+    }
+    Ok(AddedTracks { added: filtered, overflow: overflow })
+}
+
+/// What `add_songs_streaming` did
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamedSongs {
+    /// The number of rows that were read
+    pub rows: usize,
+    /// The number of tracks that were added
+    pub added: usize,
+    /// The lines of rows without a usable track ID, see
+    /// `get_lines_without_track_id`
+    pub failed_lines: Vec<usize>,
+}
+
+/// Add songs to a playlist as they're read, a chunk at a time, for files too
+/// big to read all at once, eg. an export with millions of rows. Each chunk
+/// is added the way `add_songs_to_playlist` adds a whole file, skipping
+/// tracks already in the playlist or added by an earlier chunk. Only the
+/// tracks in the playlist are kept in memory, rather than every row.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the tracks should be added
+/// * `playlist_id` - The playlist ID to be added to. This is the ID and *not*
+/// the name.
+/// * `rows` - The songs along with their lines, eg. from `stream_csv_file`
+/// * `chunk_size` - The most rows to read before adding their tracks
+/// * `progress` - Where to report the rows read to
+pub fn add_songs_streaming<E, I>(playlist_api: &PlaylistAPI<E>,
+                                 playlist_id: &str,
+                                 rows: I,
+                                 chunk_size: usize,
+                                 progress: &Progress) -> Result<StreamedSongs, PlaylistAddError<E>>
+        where I: Iterator<Item = Result<(usize, Song), csv::Error>> {
+    let mut in_playlist: HashSet<TrackUri> = playlist_api.get_track_ids_in_playlist(playlist_id)
+        .map_err(PlaylistAddError::APIError)?
+        .into_iter()
+        .collect();
+    let mut streamed = StreamedSongs::default();
+    let mut chunk: Vec<Song> = Vec::with_capacity(chunk_size);
+    let mut rows = rows.peekable();
+    while rows.peek().is_some() {
+        chunk.clear();
+        for row in rows.by_ref().take(chunk_size) {
+            let (line, song) = row.map_err(|e| PlaylistAddError::ReadError(e.to_string()))?;
+            if !has_track_id(&song) || !TrackUri::parse(&song.song_id).is_addable() {
+                streamed.failed_lines.push(line);
+            }
+            chunk.push(song);
+        }
+        streamed.rows += chunk.len();
+        progress.update(Stage::CsvRows, streamed.rows as u64, None);
+        let tracks: Vec<TrackUri> = get_unique_track_ids(&chunk)
+            .into_iter()
+            .filter(|track| !in_playlist.contains(track))
+            .collect();
+        if tracks.is_empty() {
+            continue;
+        }
+        let (tracks, overflow) = split_at_capacity(in_playlist.len(), tracks);
+        if !overflow.is_empty() {
+            return Err(PlaylistAddError::PlaylistFull(PlaylistFull {
+                existing: in_playlist.len(),
+                adding: tracks.len() + overflow.len(),
+            }));
+        }
+        playlist_api.add_tracks_to_playlist(playlist_id, &tracks, None).map_err(PlaylistAddError::APIError)?;
+        streamed.added += tracks.len();
+        in_playlist.extend(tracks);
+    }
+    progress.finish(Stage::CsvRows);
+    if streamed.added == 0 {
+        return Err(PlaylistAddError::NoNewTracks(NoNewTracks {}));
+    }
+    info!("{}", tr("tracks-added", &[("count", streamed.added.into()), ("id", playlist_id.into())]));
+    Ok(streamed)
+}
+
+/// Get the tracks that would be added to the playlist. This removes songs
+/// without a usable ID, duplicates within the songs and songs that are
+/// already in the playlist.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the tracks should be added
+/// * `playlist_id` - The playlist ID to be added to. This is the ID and *not*
+/// the name.
+/// * `songs` - A slice of the songs
+pub fn get_tracks_to_add<E>(playlist_api: &PlaylistAPI<E>,
+                            playlist_id: &str,
+                            songs: &[Song]) -> Result<Vec<TrackUri>, E> {
+    let track_ids = get_unique_track_ids(songs);
+    filter_duplicates(playlist_api, playlist_id, track_ids)
+}
+
+/// The tracks that adding songs to a playlist would add and skip, used to
+/// print what an import would do without changing anything
+#[derive(Debug, Clone, PartialEq)]
+pub struct DryRun {
+    /// The tracks that would be added, in the order they'd be added
+    pub additions: Vec<TrackUri>,
+    /// The tracks that would be skipped since they're already in the playlist
+    pub duplicates: Vec<TrackUri>,
+}
+
+/// Work out which tracks `add_songs_to_playlist` would add and which it would
+/// skip as duplicates, without modifying the playlist.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the tracks would be added
+/// * `playlist_id` - The playlist ID to be added to, or `None` when the
+/// playlist doesn't exist yet and every track is new
+/// * `songs` - A slice of the songs
+pub fn dry_run_add_songs<E>(playlist_api: &PlaylistAPI<E>,
+                            playlist_id: Option<&str>,
+                            songs: &[Song]) -> Result<DryRun, E> {
+    let track_ids = get_unique_track_ids(songs);
+    let existing = match playlist_id {
+        Some(id) => playlist_api.get_track_ids_in_playlist(id)?,
+        None => Vec::new(),
+    };
+    let (duplicates, additions) = track_ids
+        .into_iter()
+        .partition(|id| existing.contains(id));
+    Ok(DryRun {
+        additions: additions,
+        duplicates: duplicates,
+    })
+}
+
+/// Get the sorted tracks of the songs with duplicates and songs without a
+/// usable ID removed.
+///
+/// # Arguments
+///
+/// * `songs` - A slice of the songs
+pub fn get_unique_track_ids(songs: &[Song]) -> Vec<TrackUri> {
     // Map the songs to IDs
-    let mut track_ids: Vec<String> = songs
+    let mut track_ids: Vec<TrackUri> = songs
         .iter()
-        .map(get_track_id_from_song)
-        .filter(|id| id.is_some())
-        .map(|id| id.unwrap())
+        .filter_map(get_track_id_from_song)
         .collect();
     // Sort so that dedup removes all duplicates
     track_ids.sort();
     // Remove all duplicates
+    let count = track_ids.len();
     track_ids.dedup();
-    let filtered = filter_duplicates(
-        playlist_api, playlist_id, track_ids
-    ).map_err(PlaylistAddError::APIError)?;
-    // If there's no tracks left then send back a message to indicate that
-    if filtered.is_empty() {
-        return Err(PlaylistAddError::NoNewTracks(NoNewTracks {}));
+    debug!("Skipped {} duplicate tracks within the songs", count - track_ids.len());
+    track_ids
+}
+
+/// Keep only the tracks of the first songs in the CSV, so that a playlist
+/// can be filled a few tracks at a time. This should be given the tracks
+/// after duplicates are removed so that each run adds new tracks.
+///
+/// # Arguments
+///
+/// * `songs` - A slice of the songs in the order they were read
+/// * `tracks` - The tracks that would be added
+/// * `max_tracks` - The most tracks to keep
+pub fn limit_tracks(songs: &[Song], tracks: Vec<TrackUri>, max_tracks: usize) -> Vec<TrackUri> {
+    if tracks.len() <= max_tracks {
+        return tracks;
     }
-    // Add the IDs to the playlist
-    playlist_api.add_tracks_to_playlist(
-        playlist_id, &filtered[..]
-    ).map_err(PlaylistAddError::APIError)?;
-    Ok(())
+    let new_tracks: HashSet<&TrackUri> = tracks.iter().collect();
+    let mut kept: HashSet<TrackUri> = HashSet::new();
+    // Parsed again rather than with get_track_id_from_song so that missing
+    // IDs aren't warned about twice
+    for id in songs.iter().filter(|song| has_track_id(song)).map(|song| TrackUri::parse(&song.song_id)) {
+        if kept.len() == max_tracks {
+            break;
+        }
+        if new_tracks.contains(&id) {
+            kept.insert(id);
+        }
+    }
+    info!("Only adding {} of the {} new tracks", kept.len(), tracks.len());
+    tracks.into_iter().filter(|id| kept.contains(id)).collect()
+}
+
+/// Put the tracks in the order their songs were read, eg. after the songs
+/// were sorted by `sort_by_added_at`
+///
+/// # Arguments
+///
+/// * `songs` - A slice of the songs in the order they should be added
+/// * `tracks` - The tracks that would be added
+pub fn keep_song_order(songs: &[Song], tracks: Vec<TrackUri>) -> Vec<TrackUri> {
+    let mut positions: HashMap<TrackUri, usize> = HashMap::new();
+    for (position, song) in songs.iter().enumerate().filter(|&(_, song)| has_track_id(song)) {
+        positions.entry(TrackUri::parse(&song.song_id)).or_insert(position);
+    }
+    let mut tracks = tracks;
+    tracks.sort_by_key(|track| positions.get(track).cloned().unwrap_or(songs.len()));
+    tracks
+}
+
+/// Get the line numbers of the songs that have no track ID or an ID that
+/// can't be added, so that they can be reported. The header is line 1 so
+/// the first song is on line 2.
+///
+/// # Arguments
+///
+/// * `songs` - A slice of the songs in the order they were read
+pub fn get_lines_without_track_id(songs: &[Song]) -> Vec<usize> {
+    songs
+        .iter()
+        .enumerate()
+        .filter(|&(_, song)| !has_track_id(song) || !TrackUri::parse(&song.song_id).is_addable())
+        .map(|(i, _)| i + 2)
+        .collect()
 }
 
 /// Filter tracks that are already in the playlist.
@@ -118,56 +896,67 @@ pub fn add_songs_to_playlist<E>(playlist_api: &PlaylistAPI<E>,
 /// * `playlist_api` - The instance where the tracks should be added
 /// * `playlist_id` - The playlist ID to be added to. This is the ID and *not*
 /// the name.
-/// * `track_ids` - A vec of IDs for each song
+/// * `track_ids` - A vec of tracks for each song
 pub fn filter_duplicates<E>(playlist_api: &PlaylistAPI<E>,
                             playlist_id: &str,
-                            track_ids: Vec<String>) -> Result<Vec<String>, E> {
+                            track_ids: Vec<TrackUri>) -> Result<Vec<TrackUri>, E> {
     let tracks = playlist_api.get_track_ids_in_playlist(playlist_id)?;
-    let filtered: Vec<String> = track_ids
-        .iter()
-        .filter(|id| !tracks.contains(id))
-        .cloned()
-        .collect();
-    Ok(filtered)
+    Ok(remove_existing(&tracks, track_ids))
+}
+
+/// Remove the tracks that are already in the playlist
+fn remove_existing(existing: &[TrackUri], track_ids: Vec<TrackUri>) -> Vec<TrackUri> {
+    let (duplicates, filtered): (Vec<TrackUri>, Vec<TrackUri>) = track_ids
+        .into_iter()
+        .partition(|id| existing.contains(id));
+    for id in duplicates {
+        debug!("Already in the playlist: {}", id.uri());
+        emit(&Event::SkippedDuplicate { track: &id.uri() });
+    }
+    filtered
 }
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use playlist::{PlaylistAPI,PlaylistError};
+    use fake_api::{FakeError,FakePlaylistAPI};
+    use capacity::PLAYLIST_CAPACITY;
+    use playlist::{PlaylistAPI,PlaylistError,Visibility};
 
+    use memory::MemoryAPI;
     use std::cell::RefCell;
+    use std::env;
+    use std::fs;
 
     /// Keep track of calls made to MockPlaylistAPI
     #[derive(Debug, Clone)]
     struct CallHistory {
         create_playlist_called_with: Option<String>,
         get_playlist_id_called_with: Option<String>,
-        add_tracks_to_playlist_called_with: Option<(String, Vec<String>)>,
+        add_tracks_to_playlist_called_with: Option<(String, Vec<TrackUri>)>,
+        add_tracks_to_playlist_position: Option<usize>,
         get_track_ids_in_playlist_called_with: Option<String>,
     }
 
-    #[derive(Debug, Copy, Clone, PartialEq)]
-    struct FakeError {}
-
     /// A fake API where you can specify the return values to the functions
     struct MockPlaylistAPI {
         call_history: RefCell<CallHistory>,
         add_tracks_to_playlist_returns: Result<(), FakeError>,
-        get_track_ids_in_playlist_returns: Result<Vec<String>, FakeError>,
+        get_track_ids_in_playlist_returns: Result<Vec<TrackUri>, FakeError>,
     }
 
     impl MockPlaylistAPI {
         /// Create a new MockPlaylistAPI
         fn new(add_tracks_to_playlist_returns: Result<(), FakeError>,
-               get_track_ids_in_playlist_returns: Result<Vec<String>, FakeError>) -> MockPlaylistAPI {
+               get_track_ids_in_playlist_returns: Result<Vec<TrackUri>, FakeError>) -> MockPlaylistAPI {
             MockPlaylistAPI {
                 call_history: RefCell::new(
                     CallHistory{
                         create_playlist_called_with: None,
                         get_playlist_id_called_with: None,
                         add_tracks_to_playlist_called_with: None,
+                        add_tracks_to_playlist_position: None,
                         get_track_ids_in_playlist_called_with: None,
                     }
                 ),
@@ -177,30 +966,31 @@ mod tests {
         }
     }
 
-    impl PlaylistAPI<FakeError> for MockPlaylistAPI {
+    impl FakePlaylistAPI for MockPlaylistAPI {
         fn get_playlist_id(&self, playlist_name: &str) -> Result<String, PlaylistError<FakeError>> {
             let mut calls = self.call_history.borrow_mut();
             calls.get_playlist_id_called_with = Some(playlist_name.to_owned());
             Ok("".to_string())
         }
 
-        fn create_playlist(&self, playlist_name: &str) -> Result<String, FakeError> {
+        #[allow(unused_variables)]
+        fn create_playlist(&self, playlist_name: &str, visibility: Visibility) -> Result<String, FakeError> {
             let mut calls = self.call_history.borrow_mut();
             calls.create_playlist_called_with = Some(playlist_name.to_owned());
             Ok("".to_string())
         }
 
         #[allow(unused_variables)]
-        fn add_tracks_to_playlist(&self, playlist_id: &str, track_ids: &[String]) -> Result<(), FakeError> {
-            let mut x = vec!["".to_string(); track_ids.len()];
-            x.clone_from_slice(track_ids);
+        fn add_tracks_to_playlist(&self, playlist_id: &str, tracks: &[TrackUri], position: Option<usize>) -> Result<(), FakeError> {
+            let x = tracks.to_vec();
             let mut calls = self.call_history.borrow_mut();
             calls.add_tracks_to_playlist_called_with = Some((playlist_id.to_owned(), x));
+            calls.add_tracks_to_playlist_position = position;
             self.add_tracks_to_playlist_returns.clone()
         }
 
         #[allow(unused_variables)]
-        fn get_track_ids_in_playlist(&self, playlist_id: &str) -> Result<Vec<String>, FakeError> {
+        fn get_track_ids_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackUri>, FakeError> {
             let mut calls = self.call_history.borrow_mut();
             calls.get_track_ids_in_playlist_called_with = Some(playlist_id.to_owned());
             self.get_track_ids_in_playlist_returns.clone()
@@ -208,26 +998,105 @@ mod tests {
     }
 
     /// Used for declaring the inputs for each test
-    fn test_setup() -> (String, [String; 3], Vec<Song>) {
+    fn test_setup() -> (String, [TrackUri; 3], Vec<Song>) {
         let playlist_name = "test_playlist_name1".to_string();
         // These must be in alphabetical order to make the tests simpler.
         // This is because add_songs_to_playlist performs a sort that changes
         // the order that tracks are added
         let expected_tracks = [
-            "3ndjkfd9".to_string(),
-            "asqww_nf".to_string(),
-            "vvcs33".to_string()
+            TrackUri::Track("3ndjkfd900000000000000".to_string()),
+            TrackUri::Track("asqwwnf000000000000000".to_string()),
+            TrackUri::Track("vvcs330000000000000000".to_string())
         ];
         // Create some songs to add
         let mut songs = Vec::new();
-        songs.push(Song{music:"BLA".to_string(), song_id:expected_tracks[0].to_owned()});
-        songs.push(Song{music:"test song".to_string(), song_id:expected_tracks[1].to_owned()});
+        songs.push(Song{music:"BLA".to_string(), song_id:"3ndjkfd900000000000000".to_owned()});
+        songs.push(Song{music:"test song".to_string(), song_id:"asqwwnf000000000000000".to_owned()});
         // This will be stripped out since DynamoDB uses "true" to indicate NULL
         songs.push(Song{music:"djgfdj".to_string(), song_id:"true".to_owned()});
-        songs.push(Song{music:"another 1".to_string(), song_id:expected_tracks[2].to_owned()});
+        songs.push(Song{music:"another 1".to_string(), song_id:"vvcs330000000000000000".to_owned()});
         (playlist_name, expected_tracks, songs)
     }
 
+    #[test]
+    fn add_songs_returning_tracks_adds_first_new_songs_up_to_limit() {
+        // Given
+        let (playlist_name, expected_tracks, mut songs) = test_setup();
+        songs.reverse();
+        // The first song is already in the playlist so it doesn't count
+        let api = MockPlaylistAPI::new(Ok(()), Ok(vec![expected_tracks[2].clone()]));
+        // When
+        let options = AddOptions { max_tracks: Some(1), ..AddOptions::default() };
+        let added = add_songs_returning_tracks(&api, &playlist_name, songs, &options).unwrap();
+        // Then
+        assert_eq!(vec![expected_tracks[1].clone()], added.added);
+    }
+
+    #[test]
+    fn keep_song_order_follows_the_songs() {
+        // Given
+        let songs = vec![
+            Song { music: "C".to_string(), song_id: "idc0000000000000000000".to_string() },
+            Song { music: "A".to_string(), song_id: "ida0000000000000000000".to_string() },
+            Song { music: "B".to_string(), song_id: "idb0000000000000000000".to_string() },
+        ];
+        let track = |id: &str| TrackUri::Track(id.to_string());
+        // When
+        let ordered = keep_song_order(&songs, get_unique_track_ids(&songs));
+        // Then
+        assert_eq!(vec![track("idc0000000000000000000"), track("ida0000000000000000000"), track("idb0000000000000000000")], ordered);
+    }
+
+    #[test]
+    fn add_songs_returning_tracks_inserts_at_position() {
+        // Given
+        let (playlist_name, expected_tracks, songs) = test_setup();
+        let existing = vec![TrackUri::Track("existing10000000000000".to_string()), TrackUri::Track("existing20000000000000".to_string())];
+        let api = MockPlaylistAPI::new(Ok(()), Ok(existing));
+        let position = |position: &str| AddOptions { position: position.parse().unwrap(), ..AddOptions::default() };
+        // When
+        add_songs_returning_tracks(&api, &playlist_name, songs.clone(), &position("start")).unwrap();
+        let start = api.call_history.borrow().add_tracks_to_playlist_position;
+        add_songs_returning_tracks(&api, &playlist_name, songs.clone(), &position("1")).unwrap();
+        let index = api.call_history.borrow().add_tracks_to_playlist_position;
+        // Indexes past the end of the playlist add to the end
+        add_songs_returning_tracks(&api, &playlist_name, songs, &position("5")).unwrap();
+        let past_end = api.call_history.borrow().add_tracks_to_playlist_position;
+        // Then
+        assert_eq!(Some(0), start);
+        assert_eq!(Some(1), index);
+        assert_eq!(None, past_end);
+        assert_eq!(Some((playlist_name, expected_tracks.to_vec())), api.call_history.borrow().add_tracks_to_playlist_called_with);
+        assert_eq!(Err(InvalidPosition {}), "middle".parse::<Position>());
+    }
+
+    #[test]
+    fn add_songs_returning_tracks_handles_full_playlist() {
+        // Given
+        let (playlist_name, expected_tracks, songs) = test_setup();
+        // There's only room for one more track
+        let existing: Vec<TrackUri> = (1..PLAYLIST_CAPACITY)
+            .map(|i| TrackUri::Track(format!("existing{:014}", i)))
+            .collect();
+        let api = MockPlaylistAPI::new(Ok(()), Ok(existing));
+        let options = |on_full| AddOptions { on_full: on_full, ..AddOptions::default() };
+        // When
+        let abort = add_songs_returning_tracks(&api, &playlist_name, songs.clone(), &options(OnFull::Abort));
+        let abort_calls = api.call_history.borrow().add_tracks_to_playlist_called_with.clone();
+        let truncate = add_songs_returning_tracks(&api, &playlist_name, songs.clone(), &options(OnFull::Truncate));
+        let spill = add_songs_returning_tracks(&api, &playlist_name, songs, &options(OnFull::Spill));
+        // Then
+        let full = PlaylistFull { existing: PLAYLIST_CAPACITY - 1, adding: expected_tracks.len() };
+        assert_eq!(Err(PlaylistAddError::PlaylistFull(full)), abort);
+        // Ensure nothing is added when aborting
+        assert_eq!(None, abort_calls);
+        assert_eq!(Ok(AddedTracks { added: expected_tracks[..1].to_vec(), overflow: Vec::new() }), truncate);
+        assert_eq!(
+            Ok(AddedTracks { added: expected_tracks[..1].to_vec(), overflow: expected_tracks[1..].to_vec() }),
+            spill
+        );
+    }
+
     #[test]
     fn add_songs_to_playlist_success() {
         // Given
@@ -235,8 +1104,9 @@ mod tests {
         let api = MockPlaylistAPI::new(Ok(()), Ok(Vec::new()));
         // When
         // Ensure it doesn't fail using unwrap
-        add_songs_to_playlist(&api, &playlist_name, songs).unwrap();
+        let added = add_songs_to_playlist(&api, &playlist_name, songs).unwrap();
         // Then
+        assert_eq!(expected_tracks.len(), added);
         let expected = Some((playlist_name.to_string(), expected_tracks.to_vec()));
         let expected_track_id_call = Some(playlist_name.to_string());
         // Check the call history
@@ -383,4 +1253,204 @@ mod tests {
         assert_eq!(None, calls.create_playlist_called_with);
         assert_eq!(None, calls.get_playlist_id_called_with);
     }
+
+    #[test]
+    fn get_lines_without_track_id_counts_header() {
+        // Given
+        let (_, _, songs) = test_setup();
+        // When
+        let result = get_lines_without_track_id(&songs);
+        // Then
+        assert_eq!(vec![4], result);
+    }
+
+    #[test]
+    fn add_songs_to_playlist_handles_mixed_items() {
+        // Given
+        let songs = vec![
+            Song{music:"track".to_string(), song_id:"spotify:track:aa10000000000000000000".to_string()},
+            Song{music:"episode".to_string(), song_id:"https://open.spotify.com/episode/bb20000000000000000000".to_string()},
+            Song{music:"local".to_string(), song_id:"spotify:local:A:B:C:180".to_string()},
+            Song{music:"album".to_string(), song_id:"spotify:album:cc3".to_string()},
+        ];
+        // The track is also in the playlist as a bare ID
+        let existing_tracks = vec![
+            TrackUri::Local("spotify:local:X:Y:Z:100".to_string()),
+            TrackUri::Track("aa10000000000000000000".to_string()),
+        ];
+        let api = MockPlaylistAPI::new(Ok(()), Ok(existing_tracks));
+        // When
+        let added = add_songs_to_playlist(&api, "playlist_id", songs.clone()).unwrap();
+        // Then
+        assert_eq!(1, added);
+        let expected = Some((
+            "playlist_id".to_string(),
+            vec![TrackUri::Episode("bb20000000000000000000".to_string())]
+        ));
+        let calls = api.call_history.borrow();
+        assert_eq!(expected, calls.add_tracks_to_playlist_called_with);
+        // Local files and unknown IDs are reported
+        assert_eq!(vec![4, 5], get_lines_without_track_id(&songs));
+    }
+
+    #[test]
+    fn dry_run_add_songs_splits_out_duplicates() {
+        // Given
+        let (playlist_name, adding_tracks, songs) = test_setup();
+        let existing_tracks = vec![adding_tracks[1].clone()];
+        let api = MockPlaylistAPI::new(Ok(()), Ok(existing_tracks));
+        // When
+        let dry_run = dry_run_add_songs(&api, Some(&playlist_name), &songs).unwrap();
+        // Then
+        let expected = DryRun {
+            additions: vec![adding_tracks[0].clone(), adding_tracks[2].clone()],
+            duplicates: vec![adding_tracks[1].clone()],
+        };
+        assert_eq!(expected, dry_run);
+        // Ensure nothing was added
+        let calls = api.call_history.borrow();
+        assert_eq!(None, calls.add_tracks_to_playlist_called_with);
+    }
+
+    #[test]
+    fn dry_run_add_songs_without_playlist() {
+        // Given
+        let (_, expected_tracks, songs) = test_setup();
+        let api = MockPlaylistAPI::new(Ok(()), Err(FakeError{}));
+        // When
+        let dry_run = dry_run_add_songs(&api, None, &songs).unwrap();
+        // Then
+        assert_eq!(expected_tracks.to_vec(), dry_run.additions);
+        assert!(dry_run.duplicates.is_empty());
+        let calls = api.call_history.borrow();
+        assert_eq!(None, calls.get_track_ids_in_playlist_called_with);
+    }
+
+    struct RecordedProgress {
+        updates: RefCell<Vec<(Stage, u64, Option<u64>)>>,
+        finished: RefCell<Vec<Stage>>,
+    }
+
+    impl Progress for RecordedProgress {
+        fn update(&self, stage: Stage, done: u64, total: Option<u64>) {
+            self.updates.borrow_mut().push((stage, done, total));
+        }
+
+        fn finish(&self, stage: Stage) {
+            self.finished.borrow_mut().push(stage);
+        }
+    }
+
+    #[test]
+    fn parse_csv_reads_the_given_columns() {
+        // Given
+        let data = "Track URI,Track Name,Artist\nspotify:track:abc0000000000000000000,Dancing On My Own,Robyn\n";
+        let columns = CsvColumns::new(Some("Track Name"), Some("Track URI"));
+        // When
+        let songs = parse_csv_with_columns(data.as_bytes(), &columns).unwrap();
+        let missing = parse_csv(data.as_bytes()).unwrap_err();
+        // Then
+        assert_eq!(vec![Song { music: "Dancing On My Own".to_owned(), song_id: "spotify:track:abc0000000000000000000".to_owned() }], songs);
+        assert_eq!("The CSV has no column named \"music (S)\"", missing.to_string());
+    }
+
+    #[test]
+    fn parse_csv_ignores_case_and_spaces_in_headers_unless_strict() {
+        // Given
+        let data = "Music(S),SONG_ID (S)\nRobyn - Honey,abc0000000000000000000\n";
+        let strict = CsvColumns::default().with_strict(true);
+        // When
+        let songs = parse_csv(data.as_bytes()).unwrap();
+        let missing = parse_csv_with_columns(data.as_bytes(), &strict).unwrap_err();
+        // Then
+        assert_eq!(vec![Song { music: "Robyn - Honey".to_owned(), song_id: "abc0000000000000000000".to_owned() }], songs);
+        assert_eq!(
+            "The CSV has no column named \"music (S)\", but has \"Music(S)\" which isn't used with --strict-headers",
+            missing.to_string()
+        );
+    }
+
+    #[test]
+    fn invalid_rows_are_skipped_when_asked() {
+        // Given
+        let data = "music (S),song_id (S)\nRobyn - Honey,abc0000000000000000000\nBroken row\n\"Robyn - Hang\nWith Me\",true\n";
+        // When
+        let strict = parse_csv_with_progress(data.as_bytes(), &CsvColumns::default(), &[], false, &NoProgress);
+        let parsed = parse_csv_with_progress(data.as_bytes(), &CsvColumns::default(), &[], true, &NoProgress).unwrap();
+        // Then
+        assert!(strict.is_err());
+        assert_eq!(vec![2, 4], parsed.rows.iter().map(|&(line, _)| line).collect::<Vec<usize>>());
+        assert_eq!(vec![InvalidRow { line: 3, reason: "has 1 fields but the header has 2".to_owned() }], parsed.invalid_rows);
+        assert_eq!(vec![3, 4], parsed.failed_lines());
+    }
+
+    #[test]
+    fn side_columns_stay_on_their_lines_when_rows_are_skipped() {
+        // Given
+        let data = "music (S),song_id (S),note\nRobyn - Honey,honey00000000000000000,Opener\nBroken row\nRobyn - Hang With Me,hang000000000000000000, Closer \n";
+        let side_columns = ["note (S)", "note", "playlist (S)"];
+        // When
+        let parsed = parse_csv_with_progress(data.as_bytes(), &CsvColumns::default(), &side_columns, true, &NoProgress).unwrap();
+        // Then
+        let notes = parsed.column(&["note (S)", "note"]).unwrap();
+        assert_eq!(2, notes.len());
+        assert_eq!("Opener", notes[&2]);
+        assert_eq!("Closer", notes[&4]);
+        assert_eq!(None, parsed.column(&["playlist (S)"]));
+    }
+
+    #[test]
+    fn rows_that_cant_be_deserialized_are_invalid() {
+        // Given
+        let data = "music (S),plays (N)\nRobyn - Honey,many\n";
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        let error = rdr.deserialize::<(String, u32)>().next().unwrap().unwrap_err();
+        // When
+        let row = invalid_row(&error).unwrap();
+        // Then
+        assert_eq!(2, row.line);
+    }
+
+    #[test]
+    fn parse_csv_reports_each_row() {
+        // Given
+        let data = "music (S),song_id (S)\nRobyn - Dancing On My Own,abc0000000000000000000\nRobyn - Hang With Me,def0000000000000000000\n";
+        let progress = RecordedProgress { updates: RefCell::new(Vec::new()), finished: RefCell::new(Vec::new()) };
+        // When
+        let parsed = parse_csv_with_progress(data.as_bytes(), &CsvColumns::default(), &[], false, &progress).unwrap();
+        // Then
+        assert_eq!(2, parsed.rows.len());
+        assert_eq!(vec![(Stage::CsvRows, 1, None), (Stage::CsvRows, 2, None)], *progress.updates.borrow());
+        assert_eq!(vec![Stage::CsvRows], *progress.finished.borrow());
+    }
+
+    #[test]
+    fn add_songs_streaming_adds_each_chunk_once() {
+        // Given
+        let sandbox = env::temp_dir().join("playlist-from-csv-streaming-test.json");
+        let sandbox = sandbox.to_str().unwrap();
+        let _ = fs::remove_file(sandbox);
+        let api = MemoryAPI::open(sandbox).unwrap();
+        let id = api.create_playlist("Archive", Visibility::Private).unwrap();
+        let track = |name: &str| TrackUri::Track(format!("{}{}", name, "0".repeat(21)));
+        api.add_tracks_to_playlist(&id, &[track("x")], None).unwrap();
+        let data = "music (S),song_id (S)\n\
+            c,c000000000000000000000\n\
+            a,a000000000000000000000\n\
+            b,b000000000000000000000\n\
+            a,a000000000000000000000\n\
+            x,x000000000000000000000\n\
+            Broken,true\n\
+            d,d000000000000000000000\n";
+        let rows = stream_csv(data.as_bytes(), &CsvColumns::default()).unwrap();
+        // When
+        let streamed = add_songs_streaming(&api, &id, rows, 2, &NoProgress).unwrap();
+        let again = stream_csv(data.as_bytes(), &CsvColumns::default()).unwrap();
+        let again = add_songs_streaming(&api, &id, again, 2, &NoProgress);
+        // Then
+        assert_eq!(StreamedSongs { rows: 7, added: 4, failed_lines: vec![7] }, streamed);
+        let tracks: Vec<TrackUri> = ["x", "a", "c", "b", "d"].iter().map(|name| track(name)).collect();
+        assert_eq!(tracks, api.get_track_ids_in_playlist(&id).unwrap());
+        assert!(matches!(again, Err(PlaylistAddError::NoNewTracks(_))));
+    }
 }
@@ -0,0 +1,432 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::time::{Instant, SystemTime};
+
+use config::{order_jobs,read_config,Config,Diagnostic,Job};
+use deadline::RunTime;
+use quiet_hours::QuietHours;
+
+extern crate chrono;
+use self::chrono::NaiveTime;
+
+/// Keeps the latest valid config from a file. The file is only read again
+/// when it has been modified, and a config with problems never replaces the
+/// one that's running.
+pub struct ConfigWatcher {
+    filename: String,
+    modified: Option<SystemTime>,
+    config: Config,
+}
+
+/// Get when a file was last modified, if it can be read
+fn modified_time(filename: &str) -> Option<SystemTime> {
+    fs::metadata(filename).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl ConfigWatcher {
+    /// Read the config for the first time. This fails if the config has any
+    /// problems, since there's nothing to fall back on
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the config file
+    pub fn new(filename: &str) -> Result<ConfigWatcher, Vec<Diagnostic>> {
+        let modified = modified_time(filename);
+        let config = read_config(filename)?;
+        Ok(ConfigWatcher {
+            filename: filename.to_owned(),
+            modified: modified,
+            config: config,
+        })
+    }
+
+    /// The config that jobs should be run from
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Read the config again if the file was modified since it was last
+    /// read. Returns `None` when nothing changed. When the new config has
+    /// problems the current config is kept and the problems are returned, so
+    /// a bad edit is only reported once.
+    pub fn reload_if_changed(&mut self) -> Option<Result<(), Vec<Diagnostic>>> {
+        let modified = modified_time(&self.filename);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(read_config(&self.filename).map(|config| {
+            self.config = config;
+        }))
+    }
+}
+
+/// When each job last ran, used to work out which jobs are due. Jobs are
+/// tracked by name so that a reload keeps their schedule.
+#[derive(Debug, Default)]
+pub struct Schedule {
+    last_run: HashMap<String, Instant>,
+    /// How each job went the last time it was due
+    outcomes: HashMap<String, JobOutcome>,
+    /// Jobs to run as soon as possible, whatever their schedule
+    triggered: HashSet<String>,
+    /// Whether jobs have stopped running on their schedule
+    paused: bool,
+    /// The quiet hours of jobs that don't have their own
+    quiet_hours: Option<QuietHours>,
+}
+
+impl Schedule {
+    /// Get the jobs that should run now. Jobs run in the order they're
+    /// configured, except that jobs come after the jobs in their `after`.
+    /// Jobs that haven't run yet are always due. Jobs without `every` only
+    /// run once. While paused, only triggered jobs are due. During a job's
+    /// quiet hours it isn't due, so it runs once they're over.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The current config
+    /// * `now` - The current time
+    /// * `time` - The current local time of day, for quiet hours
+    pub fn due_jobs<'a>(&self, config: &'a Config, now: Instant, time: NaiveTime) -> Vec<&'a Job> {
+        let due: Vec<&Job> = config.jobs.iter().filter(|job| {
+            if self.triggered.contains(&job.name) {
+                return true;
+            }
+            if self.paused || self.is_quiet(job, time) {
+                return false;
+            }
+            let last_run = match self.last_run.get(&job.name) {
+                Some(last_run) => *last_run,
+                None => return true,
+            };
+            // The config is validated so `every` always parses
+            let every = job.every.as_ref().and_then(|every| every.parse::<RunTime>().ok());
+            match every {
+                Some(RunTime(every)) => now.duration_since(last_run) >= every,
+                None => false,
+            }
+        }).collect();
+        // The config is validated so there are no cycles
+        order_jobs(&due).unwrap_or(due)
+    }
+
+    /// Whether a job is in its quiet hours, or the default ones when it
+    /// doesn't have its own
+    ///
+    /// # Arguments
+    ///
+    /// * `job` - The job to check
+    /// * `time` - The current local time of day
+    pub fn is_quiet(&self, job: &Job, time: NaiveTime) -> bool {
+        // The config is validated so `quiet_hours` always parses
+        job.quiet_hours.as_ref()
+            .and_then(|quiet_hours| quiet_hours.parse::<QuietHours>().ok())
+            .or(self.quiet_hours)
+            .is_some_and(|quiet_hours| quiet_hours.contains(time))
+    }
+
+    /// Record that a job ran, whether or not it succeeded
+    ///
+    /// # Arguments
+    ///
+    /// * `job_name` - The name of the job
+    /// * `now` - When the job was run
+    pub fn record_run(&mut self, job_name: &str, now: Instant) {
+        self.last_run.insert(job_name.to_owned(), now);
+        self.triggered.remove(job_name);
+    }
+
+    /// Record how a job went, to be shown by `status`
+    ///
+    /// # Arguments
+    ///
+    /// * `job_name` - The name of the job
+    /// * `outcome` - What happened to the job
+    pub fn record_outcome(&mut self, job_name: &str, outcome: &JobOutcome) {
+        self.outcomes.insert(job_name.to_owned(), outcome.clone());
+    }
+
+    /// Run a job the next time due jobs are run, even if it isn't due or
+    /// the schedule is paused
+    ///
+    /// # Arguments
+    ///
+    /// * `job_name` - The name of the job
+    pub fn trigger(&mut self, job_name: &str) {
+        self.triggered.insert(job_name.to_owned());
+    }
+
+    /// Stop or start running jobs on their schedule
+    ///
+    /// # Arguments
+    ///
+    /// * `paused` - Whether only triggered jobs should run
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Set the quiet hours for jobs that don't have their own
+    ///
+    /// # Arguments
+    ///
+    /// * `quiet_hours` - When jobs shouldn't run, if ever
+    pub fn set_quiet_hours(&mut self, quiet_hours: Option<QuietHours>) {
+        self.quiet_hours = quiet_hours;
+    }
+
+    /// Describe the schedule, with a line for whether it's paused and then a
+    /// line for each job saying when it last ran and how that went
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The current config
+    /// * `now` - The current time
+    pub fn status(&self, config: &Config, now: Instant) -> String {
+        let mut lines = vec![if self.paused { "paused" } else { "running" }.to_owned()];
+        for job in &config.jobs {
+            let last_run = match self.last_run.get(&job.name) {
+                Some(last_run) => format!("ran {}s ago", now.duration_since(*last_run).as_secs()),
+                None => "not run yet".to_owned(),
+            };
+            let outcome = self.outcomes.get(&job.name)
+                .map(|outcome| format!(", {}", outcome))
+                .unwrap_or_default();
+            lines.push(format!("{}: {}{}", job.name, last_run, outcome));
+        }
+        lines.join("\n")
+    }
+}
+
+/// What happened to a job when it was due
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobOutcome {
+    /// The job ran and made this many changes
+    Changed(usize),
+    /// The job ran and failed
+    Failed(String),
+    /// The job didn't run since a job in its `after` failed
+    Skipped(String),
+}
+
+impl fmt::Display for JobOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JobOutcome::Changed(changes) => write!(f, "made {} changes", changes),
+            JobOutcome::Failed(ref e) => write!(f, "{}", e),
+            JobOutcome::Skipped(ref dependency) => {
+                write!(f, "skipped since \"{}\" failed", dependency)
+            },
+        }
+    }
+}
+
+/// Run jobs one after another, in the order given. A job is skipped when a
+/// job in its `after` failed or was skipped earlier in the same run.
+///
+/// # Arguments
+///
+/// * `jobs` - The jobs to run, see `Schedule::due_jobs`
+/// * `run` - Runs a single job, returning the number of changes made
+/// * `report` - Called with the outcome of each job as soon as it's known
+pub fn run_in_order<R, F>(jobs: &[&Job], mut run: R, mut report: F)
+        where R: FnMut(&Job) -> Result<usize, String>,
+              F: FnMut(&Job, &JobOutcome) {
+    let mut failed = HashSet::new();
+    for job in jobs {
+        let failed_dependency = job.after.iter().find(|dependency| failed.contains(*dependency));
+        let outcome = match failed_dependency {
+            Some(dependency) => JobOutcome::Skipped(dependency.to_owned()),
+            None => match run(job) {
+                Ok(changes) => JobOutcome::Changed(changes),
+                Err(e) => JobOutcome::Failed(e),
+            },
+        };
+        if !matches!(outcome, JobOutcome::Changed(_)) {
+            failed.insert(job.name.clone());
+        }
+        report(job, &outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::parse_config;
+
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+    use std::time::Duration;
+
+    fn noon() -> NaiveTime {
+        NaiveTime::from_hms_opt(12, 0, 0).unwrap()
+    }
+
+    fn write_file(filename: &str, data: &str) {
+        File::create(filename).unwrap().write_all(data.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn due_jobs_follows_every() {
+        // Given
+        let config = parse_config(r#"
+            [[job]]
+            name = "hourly"
+            playlist = "Hourly"
+            csv = "hourly.csv"
+            every = "1h"
+
+            [[job]]
+            name = "once"
+            playlist = "Once"
+            csv = "once.csv"
+        "#).unwrap();
+        let start = Instant::now();
+        let mut schedule = Schedule::default();
+        // When
+        let first = schedule.due_jobs(&config, start, noon()).len();
+        schedule.record_run("hourly", start);
+        schedule.record_run("once", start);
+        let soon = schedule.due_jobs(&config, start + Duration::from_secs(60), noon()).len();
+        let later = schedule.due_jobs(&config, start + Duration::from_secs(60 * 60), noon());
+        // Then
+        assert_eq!(2, first);
+        assert_eq!(0, soon);
+        assert_eq!(vec!["hourly"], later.iter().map(|job| job.name.as_str()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn paused_schedules_only_run_triggered_jobs() {
+        // Given
+        let config = parse_config(r#"
+            [[job]]
+            name = "hourly"
+            playlist = "Hourly"
+            csv = "hourly.csv"
+            every = "1h"
+
+            [[job]]
+            name = "once"
+            playlist = "Once"
+            csv = "once.csv"
+        "#).unwrap();
+        let start = Instant::now();
+        let mut schedule = Schedule::default();
+        schedule.record_run("hourly", start);
+        schedule.record_outcome("hourly", &JobOutcome::Changed(2));
+        // When
+        schedule.set_paused(true);
+        let paused = schedule.due_jobs(&config, start + Duration::from_secs(60 * 60), noon()).len();
+        schedule.trigger("hourly");
+        let triggered = schedule.due_jobs(&config, start, noon());
+        let status = schedule.status(&config, start + Duration::from_secs(5));
+        schedule.record_run("hourly", start);
+        let after_run = schedule.due_jobs(&config, start, noon()).len();
+        // Then
+        assert_eq!(0, paused);
+        assert_eq!(vec!["hourly"], triggered.iter().map(|job| job.name.as_str()).collect::<Vec<_>>());
+        assert_eq!("paused\nhourly: ran 5s ago, made 2 changes\nonce: not run yet", status);
+        assert_eq!(0, after_run);
+    }
+
+    #[test]
+    fn quiet_hours_defer_due_jobs() {
+        // Given
+        let config = parse_config(r#"
+            [[job]]
+            name = "nightly"
+            playlist = "Nightly"
+            csv = "nightly.csv"
+            quiet_hours = "09:00-17:00"
+
+            [[job]]
+            name = "daily"
+            playlist = "Daily"
+            csv = "daily.csv"
+        "#).unwrap();
+        let start = Instant::now();
+        let mut schedule = Schedule::default();
+        schedule.set_quiet_hours(Some("01:00-06:00".parse().unwrap()));
+        let names = |jobs: Vec<&Job>| jobs.iter().map(|job| job.name.clone()).collect::<Vec<_>>();
+        // When
+        let at_noon = schedule.due_jobs(&config, start, noon());
+        let early = schedule.due_jobs(&config, start, NaiveTime::from_hms_opt(3, 0, 0).unwrap());
+        schedule.trigger("daily");
+        let triggered = schedule.due_jobs(&config, start, NaiveTime::from_hms_opt(3, 0, 0).unwrap());
+        // Then
+        assert_eq!(vec!["daily"], names(at_noon));
+        assert_eq!(vec!["nightly"], names(early));
+        assert_eq!(vec!["nightly", "daily"], names(triggered));
+    }
+
+    #[test]
+    fn reload_keeps_config_when_edit_is_invalid() {
+        // Given
+        let path = env::temp_dir().join("playlist-from-csv-reload-test.toml");
+        let filename = path.to_str().unwrap();
+        let valid = "[[job]]\nname = \"a\"\nplaylist = \"A\"\ncsv = \"a.csv\"\n";
+        write_file(filename, valid);
+        let mut watcher = ConfigWatcher::new(filename).unwrap();
+        // When
+        let unchanged = watcher.reload_if_changed();
+        watcher.modified = None;
+        write_file(filename, "[[job]]\nname = \"a\"\n");
+        let invalid = watcher.reload_if_changed();
+        let kept = watcher.config().jobs.len();
+        watcher.modified = None;
+        write_file(filename, &format!("{}every = \"1h\"\n", valid));
+        let fixed = watcher.reload_if_changed();
+        fs::remove_file(filename).unwrap();
+        // Then
+        assert_eq!(None, unchanged);
+        assert!(matches!(invalid, Some(Err(_))));
+        assert_eq!(1, kept);
+        assert_eq!(Some(Ok(())), fixed);
+        assert_eq!(Some("1h".to_string()), watcher.config().jobs[0].every);
+    }
+
+    #[test]
+    fn run_in_order_skips_dependents_of_failed_jobs() {
+        // Given
+        let config = parse_config(r#"
+            [[job]]
+            name = "archive"
+            playlist = "Archive"
+            csv = "weekly.csv"
+
+            [[job]]
+            name = "clear-and-import"
+            playlist = "Weekly"
+            csv = "weekly.csv"
+            after = ["archive"]
+
+            [[job]]
+            name = "announce"
+            playlist = "Announcements"
+            csv = "weekly.csv"
+            after = ["clear-and-import"]
+
+            [[job]]
+            name = "requests"
+            playlist = "Requests"
+            csv = "songs.csv"
+        "#).unwrap();
+        let jobs: Vec<&Job> = config.jobs.iter().collect();
+        let mut outcomes = Vec::new();
+        // When
+        run_in_order(
+            &jobs,
+            |job| if job.name == "archive" { Err("Playlist not found".to_string()) } else { Ok(1) },
+            |job, outcome| outcomes.push((job.name.clone(), outcome.clone()))
+        );
+        // Then
+        assert_eq!(vec![
+            ("archive".to_string(), JobOutcome::Failed("Playlist not found".to_string())),
+            ("clear-and-import".to_string(), JobOutcome::Skipped("archive".to_string())),
+            ("announce".to_string(), JobOutcome::Skipped("clear-and-import".to_string())),
+            ("requests".to_string(), JobOutcome::Changed(1)),
+        ], outcomes);
+    }
+}
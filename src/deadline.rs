@@ -0,0 +1,74 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// A length of time given on the command line, eg. `90s`, `10m` or `2h`.
+/// Numbers without a unit are seconds
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RunTime(pub Duration);
+
+/// An error when a length of time can't be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidRunTime {
+    value: String,
+}
+
+impl Error for InvalidRunTime {
+    fn description(&self) -> &str {
+        "Expected a length of time such as 30s, 10m or 1h"
+    }
+}
+
+impl fmt::Display for InvalidRunTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, got {:?}", self.description(), self.value)
+    }
+}
+
+impl FromStr for RunTime {
+    type Err = InvalidRunTime;
+
+    fn from_str(value: &str) -> Result<RunTime, InvalidRunTime> {
+        let invalid = || InvalidRunTime { value: value.to_owned() };
+        let value = value.trim();
+        let (number, multiplier) = match value.chars().last() {
+            Some('s') => (&value[..value.len() - 1], 1),
+            Some('m') => (&value[..value.len() - 1], 60),
+            Some('h') => (&value[..value.len() - 1], 60 * 60),
+            _ => (value, 1),
+        };
+        let number: u64 = number.parse().map_err(|_| invalid())?;
+        number.checked_mul(multiplier)
+            .map(|secs| RunTime(Duration::from_secs(secs)))
+            .ok_or_else(invalid)
+    }
+}
+
+impl RunTime {
+    /// When a run that starts now should stop
+    pub fn deadline_from_now(&self) -> Instant {
+        Instant::now() + self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_units() {
+        assert_eq!(Ok(RunTime(Duration::from_secs(45))), "45".parse());
+        assert_eq!(Ok(RunTime(Duration::from_secs(30))), "30s".parse());
+        assert_eq!(Ok(RunTime(Duration::from_secs(600))), "10m".parse());
+        assert_eq!(Ok(RunTime(Duration::from_secs(7200))), "2h".parse());
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        for value in ["", "m", "10d", "-5m", "1.5h", "ten minutes"].iter() {
+            let result: Result<RunTime, InvalidRunTime> = value.parse();
+            assert_eq!(Err(InvalidRunTime { value: value.to_string() }), result);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashSet;
+
+use csv_to_playlist::Song;
+use playlist::PlaylistAPI;
+use track_uri::TrackUri;
+
+/// Whether a playlist name is part of a family. Patterns ending in `*` match
+/// any name starting with the rest of the pattern, eg. `Monthly *`, otherwise
+/// the name has to match exactly.
+///
+/// # Arguments
+///
+/// * `name` - The playlist name
+/// * `patterns` - The patterns that make up the family
+pub fn is_in_family(name: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|pattern| match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    })
+}
+
+/// Get every track in the user's playlists that are part of the family. This
+/// is fetched once per run so that it can be reused for each playlist being
+/// imported.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance to read the playlists from
+/// * `patterns` - The patterns that make up the family, see `is_in_family`
+pub fn get_family_tracks<E>(playlist_api: &PlaylistAPI<E>,
+                            patterns: &[String]) -> Result<HashSet<TrackUri>, E> {
+    let mut tracks = HashSet::new();
+    for playlist in playlist_api.list_playlists()? {
+        if is_in_family(&playlist.name, patterns) {
+            tracks.extend(playlist_api.get_track_ids_in_playlist(&playlist.id)?);
+        }
+    }
+    Ok(tracks)
+}
+
+/// Split the songs into those that haven't been added to the family yet and
+/// those that have, keeping the order of the CSV.
+///
+/// # Arguments
+///
+/// * `songs` - The songs to be imported
+/// * `family_tracks` - The tracks already in the family, see
+/// `get_family_tracks`
+pub fn remove_family_tracks(songs: Vec<Song>,
+                            family_tracks: &HashSet<TrackUri>) -> (Vec<Song>, Vec<Song>) {
+    songs.into_iter()
+        .partition(|song| !family_tracks.contains(&TrackUri::parse(&song.song_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_api::{FakeError,FakePlaylistAPI};
+    use playlist::{PlaylistError,PlaylistInfo,Visibility};
+
+    use std::cell::RefCell;
+
+    /// A fake API with a few monthly playlists
+    struct MockPlaylistAPI {
+        get_track_ids_in_playlist_called_with: RefCell<Vec<String>>,
+    }
+
+    impl FakePlaylistAPI for MockPlaylistAPI {
+        #[allow(unused_variables)]
+        fn get_playlist_id(&self, playlist_name: &str) -> Result<String, PlaylistError<FakeError>> {
+            Ok("".to_string())
+        }
+
+        #[allow(unused_variables)]
+        fn create_playlist(&self, playlist_name: &str, visibility: Visibility) -> Result<String, FakeError> {
+            Ok("".to_string())
+        }
+
+        fn get_track_ids_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackUri>, FakeError> {
+            self.get_track_ids_in_playlist_called_with.borrow_mut().push(playlist_id.to_owned());
+            let ids = match playlist_id {
+                "march" => vec!["ida0000000000000000000", "idb0000000000000000000"],
+                "april" => vec!["idb0000000000000000000", "idc0000000000000000000"],
+                _ => vec!["idd0000000000000000000"],
+            };
+            Ok(ids.into_iter().map(|id| TrackUri::Track(id.to_string())).collect())
+        }
+
+        fn list_playlists(&self) -> Result<Vec<PlaylistInfo>, FakeError> {
+            Ok(vec![playlist("march", "Monthly March"), playlist("favs", "Favourites"), playlist("april", "Monthly April")])
+        }
+    }
+
+    fn playlist(id: &str, name: &str) -> PlaylistInfo {
+        PlaylistInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            track_count: 0,
+            public: false,
+            owner: "rob".to_string(),
+        }
+    }
+
+    fn song(id: &str) -> Song {
+        Song { music: id.to_string(), song_id: id.to_string() }
+    }
+
+    #[test]
+    fn is_in_family_matches_prefixes_and_names() {
+        let patterns = vec!["Monthly *".to_string(), "Favourites".to_string()];
+        assert!(is_in_family("Monthly March", &patterns));
+        assert!(is_in_family("Favourites", &patterns));
+        assert!(!is_in_family("Monthly", &patterns));
+        assert!(!is_in_family("Favourites 2", &patterns));
+    }
+
+    #[test]
+    fn get_family_tracks_reads_matching_playlists() {
+        // Given
+        let api = MockPlaylistAPI { get_track_ids_in_playlist_called_with: RefCell::new(Vec::new()) };
+        // When
+        let result = get_family_tracks(&api, &["Monthly *".to_string()]).unwrap();
+        // Then
+        let expected: HashSet<TrackUri> = ["ida0000000000000000000", "idb0000000000000000000", "idc0000000000000000000"].iter()
+            .map(|id| TrackUri::Track(id.to_string()))
+            .collect();
+        assert_eq!(expected, result);
+        assert_eq!(vec!["march", "april"], *api.get_track_ids_in_playlist_called_with.borrow());
+    }
+
+    #[test]
+    fn remove_family_tracks_skips_tracks_already_added() {
+        // Given
+        let family: HashSet<TrackUri> = vec![TrackUri::Track("idb0000000000000000000".to_string())].into_iter().collect();
+        let songs = vec![song("ida0000000000000000000"), song("spotify:track:idb0000000000000000000"), song("idc0000000000000000000")];
+        // When
+        let (kept, skipped) = remove_family_tracks(songs, &family);
+        // Then
+        assert_eq!(vec![song("ida0000000000000000000"), song("idc0000000000000000000")], kept);
+        assert_eq!(vec![song("spotify:track:idb0000000000000000000")], skipped);
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+
+use playlist::{PlaylistAPI,PlaylistDetails,TrackMetadata};
+
+/// The values that can be substituted into a description template
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescriptionContext {
+    /// The number of tracks in the playlist after the run
+    pub track_count: usize,
+    /// Where the songs came from, such as the CSV filename
+    pub source: String,
+    /// The date of the run
+    pub date: String,
+    /// The artist that appears most in the playlist
+    pub top_artist: Option<String>,
+}
+
+/// Get the value for a placeholder in a description template, or `None` if
+/// it isn't one of the supported placeholders
+fn placeholder_value(name: &str, context: &DescriptionContext) -> Option<String> {
+    match name {
+        "track_count" => Some(context.track_count.to_string()),
+        "source" => Some(context.source.to_owned()),
+        "date" => Some(context.date.to_owned()),
+        "top_artist" => Some(context.top_artist.clone().unwrap_or_default()),
+        _ => None,
+    }
+}
+
+/// Fill out a description template. The supported placeholders are
+/// `{track_count}`, `{source}`, `{date}` and `{top_artist}`. Anything else
+/// is left untouched. The template is read once, so placeholders inside the
+/// values, eg. a source named `{date}.csv`, aren't filled in.
+///
+/// # Arguments
+///
+/// * `template` - The description with placeholders
+/// * `context` - The values to be substituted in
+pub fn render_description(template: &str, context: &DescriptionContext) -> String {
+    let mut description = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        description.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let placeholder = rest.find('}')
+            .and_then(|end| placeholder_value(&rest[1..end], context).map(|value| (end, value)));
+        match placeholder {
+            Some((end, value)) => {
+                description.push_str(&value);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                description.push('{');
+                rest = &rest[1..];
+            },
+        }
+    }
+    description.push_str(rest);
+    description
+}
+
+/// Find the artist with the most tracks. When there's a tie the artist that
+/// reached that many tracks first wins.
+fn get_top_artist(tracks: &[TrackMetadata]) -> Option<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut top: Option<(&str, usize)> = None;
+    for artist in tracks.iter().flat_map(|t| t.artists.iter()) {
+        let count = counts.entry(artist).or_insert(0);
+        *count += 1;
+        let is_new_top = match top {
+            Some((_, top_count)) => *count > top_count,
+            None => true,
+        };
+        if is_new_top {
+            top = Some((artist, *count));
+        }
+    }
+    top.map(|(artist, _)| artist.to_owned())
+}
+
+/// Render the description template using the current state of the playlist
+/// and apply it to the playlist. Returns the description that was set.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the playlist lives
+/// * `playlist_id` - The playlist ID to be updated. This is the ID and *not*
+/// the name.
+/// * `template` - The description with placeholders
+/// * `source` - Where the songs came from
+/// * `date` - The date to use for `{date}`
+pub fn update_playlist_description<E>(playlist_api: &PlaylistAPI<E>,
+                                      playlist_id: &str,
+                                      template: &str,
+                                      source: &str,
+                                      date: &str) -> Result<String, E> {
+    let tracks = playlist_api.get_tracks_with_metadata_in_playlist(playlist_id)?;
+    let context = DescriptionContext {
+        track_count: tracks.len(),
+        source: source.to_owned(),
+        date: date.to_owned(),
+        top_artist: get_top_artist(&tracks),
+    };
+    let description = render_description(template, &context);
+    let details = PlaylistDetails {
+        description: Some(description.to_owned()),
+    };
+    playlist_api.update_playlist_details(playlist_id, &details)?;
+    Ok(description)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_api::{FakeError,FakePlaylistAPI};
+    use playlist::{PlaylistError,Visibility};
+    use track_uri::TrackUri;
+
+    use std::cell::RefCell;
+
+    /// A fake API that returns a fixed set of tracks and records the details
+    /// that were set
+    struct MockPlaylistAPI {
+        update_playlist_details_called_with: RefCell<Option<(String, PlaylistDetails)>>,
+        get_tracks_with_metadata_in_playlist_returns: Result<Vec<TrackMetadata>, FakeError>,
+    }
+
+    impl MockPlaylistAPI {
+        /// Create a new MockPlaylistAPI
+        fn new(get_tracks_with_metadata_in_playlist_returns: Result<Vec<TrackMetadata>, FakeError>) -> MockPlaylistAPI {
+            MockPlaylistAPI {
+                update_playlist_details_called_with: RefCell::new(None),
+                get_tracks_with_metadata_in_playlist_returns: get_tracks_with_metadata_in_playlist_returns,
+            }
+        }
+    }
+
+    impl FakePlaylistAPI for MockPlaylistAPI {
+        #[allow(unused_variables)]
+        fn get_playlist_id(&self, playlist_name: &str) -> Result<String, PlaylistError<FakeError>> {
+            Ok("".to_string())
+        }
+
+        #[allow(unused_variables)]
+        fn create_playlist(&self, playlist_name: &str, visibility: Visibility) -> Result<String, FakeError> {
+            Ok("".to_string())
+        }
+
+        #[allow(unused_variables)]
+        fn get_tracks_with_metadata_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackMetadata>, FakeError> {
+            self.get_tracks_with_metadata_in_playlist_returns.clone()
+        }
+
+        fn update_playlist_details(&self, playlist_id: &str, details: &PlaylistDetails) -> Result<(), FakeError> {
+            *self.update_playlist_details_called_with.borrow_mut() = Some(
+                (playlist_id.to_owned(), details.clone())
+            );
+            Ok(())
+        }
+    }
+
+    /// Create a track with the given artists
+    fn track(id: &str, artists: &[&str]) -> TrackMetadata {
+        TrackMetadata {
+            uri: TrackUri::Track(id.to_string()),
+            name: format!("song {}", id),
+            artists: artists.iter().map(|a| a.to_string()).collect(),
+            album: "album".to_string(),
+            duration_ms: 0,
+            image_url: None,
+        }
+    }
+
+    #[test]
+    fn render_description_fills_all_placeholders() {
+        // Given
+        let context = DescriptionContext {
+            track_count: 12,
+            source: "songs.csv".to_string(),
+            date: "2018-07-01".to_string(),
+            top_artist: Some("Robyn".to_string()),
+        };
+        let template = "{track_count} songs from {source} on {date}. Mostly {top_artist}";
+        // When
+        let result = render_description(template, &context);
+        // Then
+        assert_eq!("12 songs from songs.csv on 2018-07-01. Mostly Robyn", result);
+    }
+
+    #[test]
+    fn render_description_leaves_unknown_placeholders() {
+        // Given
+        let context = DescriptionContext {
+            track_count: 0,
+            source: "songs.csv".to_string(),
+            date: "2018-07-01".to_string(),
+            top_artist: None,
+        };
+        // When
+        let result = render_description("{unknown} [{top_artist}]", &context);
+        // Then
+        assert_eq!("{unknown} []", result);
+    }
+
+    #[test]
+    fn render_description_does_not_fill_placeholders_in_values() {
+        // Given
+        let context = DescriptionContext {
+            track_count: 3,
+            source: "{date}.csv".to_string(),
+            date: "2018-07-01".to_string(),
+            top_artist: Some("{source}".to_string()),
+        };
+        // When
+        let result = render_description("{{track_count}} from {source} by {top_artist}", &context);
+        // Then
+        assert_eq!("{3} from {date}.csv by {source}", result);
+    }
+
+    #[test]
+    fn get_top_artist_uses_first_to_reach_count_on_tie() {
+        // Given
+        let tracks = vec![
+            track("1", &["A"]),
+            track("2", &["B", "C"]),
+            track("3", &["C"]),
+            track("4", &["A"]),
+        ];
+        // When
+        let result = get_top_artist(&tracks);
+        // Then
+        // A and C both have two tracks but C reached two first
+        assert_eq!(Some("C".to_string()), result);
+    }
+
+    #[test]
+    fn update_playlist_description_sets_details() {
+        // Given
+        let tracks = vec![track("1", &["A"]), track("2", &["B"]), track("3", &["B"])];
+        let api = MockPlaylistAPI::new(Ok(tracks));
+        // When
+        let result = update_playlist_description(
+            &api, "id_123", "{track_count} by {top_artist} from {source} ({date})",
+            "songs.csv", "2018-07-01"
+        ).unwrap();
+        // Then
+        let expected = "3 by B from songs.csv (2018-07-01)".to_string();
+        assert_eq!(expected, result);
+        let details = PlaylistDetails { description: Some(expected) };
+        assert_eq!(
+            Some(("id_123".to_string(), details)),
+            *api.update_playlist_details_called_with.borrow()
+        );
+    }
+
+    #[test]
+    fn update_playlist_description_handles_api_error() {
+        // Given
+        let api = MockPlaylistAPI::new(Err(FakeError{}));
+        // When
+        let result = update_playlist_description(
+            &api, "id_123", "{track_count}", "songs.csv", "2018-07-01"
+        );
+        // Then
+        assert_eq!(Err(FakeError{}), result);
+        // Ensure we don't update the playlist when we couldn't get the tracks
+        assert_eq!(None, *api.update_playlist_details_called_with.borrow());
+    }
+}
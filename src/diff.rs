@@ -0,0 +1,115 @@
+use playlist::TrackMetadata;
+
+/// Format a track as a single human readable line, eg. "Artist — Title"
+pub fn format_track(track: &TrackMetadata) -> String {
+    format!("{} — {}", track.artists.join(", "), track.name)
+}
+
+/// Format the playlist and source as the header of a unified diff, with
+/// each track on its own line after it
+fn format_changes(playlist_name: &str,
+                  source: &str,
+                  prefix: &str,
+                  tracks: &[TrackMetadata]) -> String {
+    let mut lines = vec![
+        format!("--- {}", playlist_name),
+        format!("+++ {}", source),
+    ];
+    for track in tracks {
+        lines.push(format!("{}{}", prefix, format_track(track)));
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Format pending changes to a playlist in a unified diff style, so that
+/// they can be reviewed before being applied. Each added track is shown on
+/// its own line prefixed with `+`.
+///
+/// # Arguments
+///
+/// * `playlist_name` - The name of the playlist being changed
+/// * `source` - Where the changes came from, such as the CSV filename
+/// * `additions` - The tracks that would be added
+pub fn format_diff(playlist_name: &str,
+                   source: &str,
+                   additions: &[TrackMetadata]) -> String {
+    format_changes(playlist_name, source, "+", additions)
+}
+
+/// Format tracks that would be removed from a playlist in the same style as
+/// `format_diff`, with each track prefixed with `-`.
+///
+/// # Arguments
+///
+/// * `playlist_name` - The name of the playlist being changed
+/// * `source` - Where the changes came from, such as the CSV filename
+/// * `removals` - The tracks that would be removed
+pub fn format_removal_diff(playlist_name: &str,
+                           source: &str,
+                           removals: &[TrackMetadata]) -> String {
+    format_changes(playlist_name, source, "-", removals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use track_uri::TrackUri;
+
+    #[test]
+    fn format_diff_shows_additions() {
+        // Given
+        let additions = vec![
+            TrackMetadata {
+                uri: TrackUri::Track("1".to_string()),
+                name: "Dancing On My Own".to_string(),
+                artists: vec!["Robyn".to_string()],
+                album: "Body Talk".to_string(),
+                duration_ms: 0,
+                image_url: None,
+            },
+            TrackMetadata {
+                uri: TrackUri::Track("2".to_string()),
+                name: "Under Pressure".to_string(),
+                artists: vec!["Queen".to_string(), "David Bowie".to_string()],
+                album: "Hot Space".to_string(),
+                duration_ms: 0,
+                image_url: None,
+            },
+        ];
+        // When
+        let result = format_diff("Requests", "songs.csv", &additions);
+        // Then
+        let expected = "--- Requests\n\
+                        +++ songs.csv\n\
+                        +Robyn — Dancing On My Own\n\
+                        +Queen, David Bowie — Under Pressure\n";
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn format_diff_with_no_changes() {
+        // When
+        let result = format_diff("Requests", "songs.csv", &[]);
+        // Then
+        assert_eq!("--- Requests\n+++ songs.csv\n", result);
+    }
+
+    #[test]
+    fn format_removal_diff_shows_removals() {
+        // Given
+        let removals = vec![
+            TrackMetadata {
+                uri: TrackUri::Track("1".to_string()),
+                name: "Dancing On My Own".to_string(),
+                artists: vec!["Robyn".to_string()],
+                album: "Body Talk".to_string(),
+                duration_ms: 0,
+                image_url: None,
+            },
+        ];
+        // When
+        let result = format_removal_diff("Requests", "bad.csv", &removals);
+        // Then
+        assert_eq!("--- Requests\n+++ bad.csv\n-Robyn — Dancing On My Own\n", result);
+    }
+}
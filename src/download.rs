@@ -0,0 +1,110 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use user_agent;
+
+/// Whether a filename is a URL to download, eg. the published link of a
+/// Google Sheet
+///
+/// # Arguments
+///
+/// * `filename` - The filename given on the command line
+pub fn is_http_url(filename: &str) -> bool {
+    filename.starts_with("https://") || filename.starts_with("http://")
+}
+
+/// How long a download can take and how big it can be
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DownloadLimits {
+    /// How long to wait for the server before giving up
+    pub timeout: Duration,
+    /// The most bytes to download
+    pub max_bytes: u64,
+}
+
+/// The error when a download is bigger than its limit
+#[derive(Debug, Clone, PartialEq)]
+pub struct TooLarge {
+    pub max_bytes: u64,
+}
+
+impl Error for TooLarge {
+    fn description(&self) -> &str {
+        "The file is bigger than the download limit"
+    }
+}
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} of {} bytes, see --max-download-mb", self.description(), self.max_bytes)
+    }
+}
+
+/// The name to save a download as. It ends with the last part of the URL's
+/// path so that its extension can be used to pick its format, and starts
+/// with a hash of the URL so that different URLs ending the same way, eg.
+/// `.../pub?output=csv`, don't share a file.
+fn download_name(url: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let last = path.rsplit('/').next().unwrap_or_default();
+    let name: String = last.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '-' || *c == '_')
+        .collect();
+    format!("playlist-from-csv-{:016x}-{}", hasher.finish(), name)
+}
+
+/// Download a URL to a temporary file, so that it can be read like any other
+/// file
+///
+/// # Arguments
+///
+/// * `url` - The http or https URL to download
+/// * `limits` - How long the download can take and how big it can be
+pub fn download_url(url: &str, limits: &DownloadLimits) -> Result<PathBuf, Box<Error>> {
+    let response = user_agent::client_builder()
+        .timeout(limits.timeout)
+        .build()?
+        .get(url)
+        .send()?
+        .error_for_status()?;
+    // One byte more than the limit is read to tell whether it was reached
+    let mut body = Vec::new();
+    response.take(limits.max_bytes + 1).read_to_end(&mut body)?;
+    if body.len() as u64 > limits.max_bytes {
+        return Err(From::from(TooLarge { max_bytes: limits.max_bytes }));
+    }
+    debug!("Downloaded {} bytes from {}", body.len(), url);
+    let path = env::temp_dir().join(download_name(url));
+    fs::write(&path, body)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downloads_keep_the_name_at_the_end_of_the_url() {
+        // Given
+        let sheet = "https://docs.google.com/spreadsheets/d/e/abc/pub?output=csv";
+        let other_sheet = "https://docs.google.com/spreadsheets/d/e/def/pub?output=csv";
+        // When
+        let name = download_name("https://example.com/exports/songs.jsonl?token=x");
+        // Then
+        assert!(is_http_url(sheet));
+        assert!(!is_http_url("songs.csv"));
+        assert!(name.starts_with("playlist-from-csv-"));
+        assert!(name.ends_with("-songs.jsonl"));
+        assert!(download_name(sheet).ends_with("-pub"));
+        assert_ne!(download_name(sheet), download_name(other_sheet));
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::str::FromStr;
+
+use csv_to_playlist::{has_track_id,Song};
+use track_uri::TrackUri;
+
+extern crate serde_json;
+
+/// How many tracks can be released into a playlist in a period, eg.
+/// `10/day`, for playlists that should grow gradually
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DripRate {
+    pub tracks: usize,
+    /// The length of the period in seconds
+    pub period: i64,
+}
+
+/// An error when a drip rate can't be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidDripRate {
+    value: String,
+}
+
+impl Error for InvalidDripRate {
+    fn description(&self) -> &str {
+        "Expected a number of tracks per hour, day or week such as 10/day"
+    }
+}
+
+impl fmt::Display for InvalidDripRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, got {:?}", self.description(), self.value)
+    }
+}
+
+impl FromStr for DripRate {
+    type Err = InvalidDripRate;
+
+    fn from_str(value: &str) -> Result<DripRate, InvalidDripRate> {
+        let invalid = || InvalidDripRate { value: value.to_owned() };
+        let mut parts = value.splitn(2, '/').map(|part| part.trim());
+        let tracks: usize = parts.next()
+            .and_then(|tracks| tracks.parse().ok())
+            .filter(|&tracks| tracks > 0)
+            .ok_or_else(invalid)?;
+        let period = match parts.next() {
+            Some("hour") => 60 * 60,
+            Some("day") => 24 * 60 * 60,
+            Some("week") => 7 * 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        Ok(DripRate { tracks: tracks, period: period })
+    }
+}
+
+impl fmt::Display for DripRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let period = match self.period {
+            3600 => "hour",
+            86400 => "day",
+            _ => "week",
+        };
+        write!(f, "{}/{}", self.tracks, period)
+    }
+}
+
+/// The songs that a job has found but hasn't added to its playlist yet,
+/// which are released a few at a time with `release`. This is kept in the
+/// job's state directory between runs.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DripQueue {
+    /// The songs waiting to be added, in the order they were found
+    #[serde(default)]
+    queued: Vec<Song>,
+    /// When songs were released in the last period, in seconds since the
+    /// Unix epoch
+    #[serde(default)]
+    released: Vec<i64>,
+}
+
+impl DripQueue {
+    /// Read a queue that was saved using `write`. A missing file is treated
+    /// as an empty queue so that the first run doesn't need one.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the queue file
+    pub fn read(filename: &str) -> Result<DripQueue, Box<Error>> {
+        match File::open(filename) {
+            Ok(file) => Ok(serde_json::from_reader(file)?),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(DripQueue::default()),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    /// Save the queue as JSON
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the queue file
+    pub fn write(&self, filename: &str) -> Result<(), Box<Error>> {
+        let file = File::create(filename)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// The number of songs waiting to be added
+    pub fn len(&self) -> usize {
+        self.queued.len()
+    }
+
+    /// Whether there are no songs waiting to be added
+    pub fn is_empty(&self) -> bool {
+        self.queued.is_empty()
+    }
+
+    /// Add songs to the end of the queue. Songs without an ID that can be
+    /// added and songs that are already in the playlist or the queue are
+    /// skipped. Returns the number of songs that were queued.
+    ///
+    /// # Arguments
+    ///
+    /// * `songs` - The songs that were found, in the order to add them
+    /// * `in_playlist` - The tracks that are already in the playlist
+    pub fn enqueue(&mut self, songs: Vec<Song>, in_playlist: &[TrackUri]) -> usize {
+        let mut seen: HashSet<TrackUri> = in_playlist.iter().cloned()
+            .chain(self.queued.iter().map(|song| TrackUri::parse(&song.song_id)))
+            .collect();
+        let before = self.queued.len();
+        for song in songs {
+            let track = TrackUri::parse(&song.song_id);
+            if has_track_id(&song) && track.is_addable() && seen.insert(track) {
+                self.queued.push(song);
+            }
+        }
+        self.queued.len() - before
+    }
+
+    /// Take the songs that can be added now from the front of the queue.
+    /// No more than `rate.tracks` songs are released in any period, so a
+    /// run soon after another may release none.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - How fast songs can be released
+    /// * `now` - The current time in seconds since the Unix epoch
+    pub fn release(&mut self, rate: DripRate, now: i64) -> Vec<Song> {
+        self.released.retain(|&released| now - released < rate.period);
+        let allowed = rate.tracks.saturating_sub(self.released.len()).min(self.queued.len());
+        self.released.extend(vec![now; allowed]);
+        self.queued.drain(..allowed).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(music: &str, song_id: &str) -> Song {
+        Song { music: music.to_string(), song_id: song_id.to_string() }
+    }
+
+    #[test]
+    fn queued_songs_are_released_at_the_drip_rate() {
+        // Given
+        let rate: DripRate = "2/day".parse().unwrap();
+        let mut queue = DripQueue::default();
+        let day = 24 * 60 * 60;
+        // When
+        let queued = queue.enqueue(vec![
+            song("Robyn - Honey", "honey00000000000000000"),
+            song("Robyn - Missing U", "missingu00000000000000"),
+            song("Robyn - Honey", "spotify:track:honey00000000000000000"),
+            song("Robyn - Ever Again", "ever000000000000000000"),
+            song("Robyn - Human Being", "true"),
+            song("Robyn - Beach2k20", "beach00000000000000000"),
+        ], &[TrackUri::Track("beach00000000000000000".to_string())]);
+        let first = queue.release(rate, 1000);
+        let same_day = queue.release(rate, 1000 + day - 1);
+        let requeued = queue.enqueue(vec![song("Robyn - Honey", "honey00000000000000000"), song("Robyn - Ever Again", "ever000000000000000000")], &[]);
+        let next_day = queue.release(rate, 1000 + day);
+        // Then
+        assert_eq!(3, queued);
+        assert_eq!(vec![song("Robyn - Honey", "honey00000000000000000"), song("Robyn - Missing U", "missingu00000000000000")], first);
+        assert!(same_day.is_empty());
+        // Released songs are in the playlist by the next run, so only the
+        // ones still queued are skipped here
+        assert_eq!(1, requeued);
+        assert_eq!(vec![song("Robyn - Ever Again", "ever000000000000000000"), song("Robyn - Honey", "honey00000000000000000")], next_day);
+        assert!(queue.is_empty());
+        assert_eq!("2/day", rate.to_string());
+        assert!("0/day".parse::<DripRate>().is_err());
+        assert!("10/month".parse::<DripRate>().is_err());
+    }
+}
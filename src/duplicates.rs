@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use csv_to_playlist::{has_track_id,Song};
+use track_uri::TrackUri;
+
+/// The CSV columns that say whether a song can be in a playlist more than
+/// once, in order of preference
+pub const ALLOW_DUP_COLUMNS: [&str; 2] = ["allow_dup (BOOL)", "allow_dup"];
+
+/// The error when a row's allow_dup value isn't true, false or empty
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidAllowDup {
+    pub line: usize,
+    pub value: String,
+}
+
+impl Error for InvalidAllowDup {
+    fn description(&self) -> &str {
+        "Expected true, false or nothing in the allow_dup column"
+    }
+}
+
+impl fmt::Display for InvalidAllowDup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} on line {}: {}", self.description(), self.line, self.value)
+    }
+}
+
+/// Read the values of the allow_dup column of a CSV, see
+/// `ALLOW_DUP_COLUMNS`. Lines with an empty value are left out.
+///
+/// # Arguments
+///
+/// * `values` - The value on each line, see `ParsedCsv::column`
+pub fn parse_allow_dup_column(values: &HashMap<usize, String>) -> Result<HashMap<usize, bool>, InvalidAllowDup> {
+    values.iter()
+        .filter(|&(_, value)| !value.is_empty())
+        .map(|(&line, value)| match value.to_lowercase().as_str() {
+            "true" | "yes" | "1" => Ok((line, true)),
+            "false" | "no" | "0" => Ok((line, false)),
+            _ => Err(InvalidAllowDup { line: line, value: value.to_owned() }),
+        })
+        .collect()
+}
+
+/// Which tracks can be in a playlist more than once, eg. a DJ set that plays
+/// a track twice. These are added once for each of their rows rather than
+/// only once.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DuplicatePolicy {
+    /// Whether every track can be added more than once, from
+    /// `--allow-duplicates`
+    pub allow: bool,
+    /// Tracks where the allow_dup column overrides `allow`
+    pub overrides: HashMap<TrackUri, bool>,
+}
+
+impl DuplicatePolicy {
+    /// Match each song to the allow_dup value on its line. Songs without a
+    /// usable ID or an allow_dup value are left to `allow`, and a later row's
+    /// value wins.
+    ///
+    /// # Arguments
+    ///
+    /// * `allow` - Whether tracks can be added more than once by default
+    /// * `rows` - The songs in the order they were read, with their lines
+    /// * `allow_dup` - The allow_dup value on each line, see
+    /// `parse_allow_dup_column`
+    pub fn new(allow: bool, rows: &[(usize, Song)], allow_dup: &HashMap<usize, bool>) -> DuplicatePolicy {
+        let overrides = rows.iter()
+            .filter(|&(_, song)| has_track_id(song))
+            .filter_map(|(line, song)| allow_dup.get(line).map(|&value| (TrackUri::parse(&song.song_id), value)))
+            .collect();
+        DuplicatePolicy { allow: allow, overrides: overrides }
+    }
+
+    /// Whether a track can be in the playlist more than once
+    pub fn allows(&self, track: &TrackUri) -> bool {
+        self.overrides.get(track).cloned().unwrap_or(self.allow)
+    }
+}
+
+/// Add copies of the tracks that can be duplicated, so that each ends up in
+/// the playlist once for each of its rows. Copies already in the playlist
+/// count towards this. Each copy goes straight after the track, or at the
+/// end for tracks that are only being added again.
+///
+/// # Arguments
+///
+/// * `tracks` - The new tracks that would be added, with one of each
+/// * `songs` - The songs that were read
+/// * `existing` - The tracks already in the playlist
+/// * `policy` - Which tracks can be added more than once
+pub fn add_duplicate_copies(tracks: Vec<TrackUri>,
+                            songs: &[Song],
+                            existing: &[TrackUri],
+                            policy: &DuplicatePolicy) -> Vec<TrackUri> {
+    // The number of rows of each track that can be duplicated, in the order
+    // they were first read
+    let mut counts: Vec<(TrackUri, usize)> = Vec::new();
+    for song in songs.iter().filter(|song| has_track_id(song)) {
+        let track = TrackUri::parse(&song.song_id);
+        if !track.is_addable() || !policy.allows(&track) {
+            continue;
+        }
+        match counts.iter().position(|(t, _)| *t == track) {
+            Some(i) => counts[i].1 += 1,
+            None => counts.push((track, 1)),
+        }
+    }
+    let count = |track: &TrackUri| counts.iter().find(|(t, _)| t == track).map_or(1, |&(_, count)| count);
+    let mut with_copies = Vec::new();
+    for track in &tracks {
+        for _ in 0..count(track) {
+            with_copies.push(track.clone());
+        }
+    }
+    // Tracks that are already in the playlist, but fewer times than they
+    // have rows. Tracks that were dropped along the way, eg. by --limit,
+    // aren't added
+    for &(ref track, rows) in counts.iter().filter(|(t, _)| !tracks.contains(t)) {
+        let present = existing.iter().filter(|t| *t == track).count();
+        if present == 0 {
+            continue;
+        }
+        for _ in present..rows {
+            with_copies.push(track.clone());
+        }
+    }
+    with_copies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pad the name out to a Spotify ID
+    fn id(name: &str) -> String {
+        format!("{:0<22}", name)
+    }
+
+    fn song(name: &str) -> Song {
+        Song { music: name.to_owned(), song_id: id(name) }
+    }
+
+    fn tracks(names: &[&str]) -> Vec<TrackUri> {
+        names.iter().map(|name| TrackUri::Track(id(name))).collect()
+    }
+
+    #[test]
+    fn duplicates_are_added_once_for_each_row_when_allowed() {
+        // Given
+        let songs: Vec<Song> = ["a", "b", "a", "c", "b", "d", "d", "d"].iter().map(|id| song(id)).collect();
+        let rows: Vec<(usize, Song)> = songs.iter().cloned().enumerate().map(|(i, song)| (i + 2, song)).collect();
+        let values: HashMap<usize, String> = vec![(2, ""), (6, "no"), (9, "TRUE")].into_iter()
+            .map(|(line, value)| (line, value.to_owned()))
+            .collect();
+        let allow_dup = parse_allow_dup_column(&values).unwrap();
+        // b is already in the playlist and d is there once of three times
+        let existing = tracks(&["b", "d"]);
+        let new_tracks = tracks(&["a", "c"]);
+        // When
+        let allowed = add_duplicate_copies(
+            new_tracks.clone(), &songs, &existing, &DuplicatePolicy::new(true, &rows, &allow_dup)
+        );
+        let only_d = add_duplicate_copies(
+            new_tracks.clone(), &songs, &existing, &DuplicatePolicy::new(false, &rows, &allow_dup)
+        );
+        let none = add_duplicate_copies(new_tracks.clone(), &songs, &existing, &DuplicatePolicy::default());
+        // Then
+        assert_eq!(tracks(&["a", "a", "c", "d", "d"]), allowed);
+        assert_eq!(tracks(&["a", "c", "d", "d"]), only_d);
+        assert_eq!(new_tracks, none);
+    }
+}
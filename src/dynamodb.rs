@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+
+use aws::{describe_error,runtime,AwsOptions};
+use csv_to_playlist::{CsvColumns,Song,STDIN_FILENAME};
+use source::SongSource;
+
+extern crate serde_json;
+use self::serde_json::{Deserializer,Map,Value};
+
+extern crate aws_sdk_dynamodb;
+use self::aws_sdk_dynamodb::Client;
+use self::aws_sdk_dynamodb::types::AttributeValue;
+
+/// The error when an item in a DynamoDB JSON export isn't an object
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidItem {
+    /// The position of the item, where the first item is 1
+    pub item: usize,
+}
+
+impl Error for InvalidItem {
+    fn description(&self) -> &str {
+        "Expected each item to be an object of attributes like \
+        {\"music\":{\"S\":\"...\"}}"
+    }
+}
+
+impl fmt::Display for InvalidItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, item {} isn't", self.description(), self.item)
+    }
+}
+
+/// Get the attribute that a CSV column was exported from. CSV exports add
+/// the attribute's type to each column, eg. `music (S)` is `music`.
+///
+/// # Arguments
+///
+/// * `column` - The CSV column, eg. `music (S)`
+pub fn attribute_name(column: &str) -> &str {
+    match column.rfind(" (") {
+        Some(i) if column.ends_with(')') => {
+            let type_name = &column[i + 2..column.len() - 1];
+            if !type_name.is_empty() && type_name.chars().all(|c| c.is_ascii_uppercase()) {
+                &column[..i]
+            } else {
+                column
+            }
+        },
+        _ => column,
+    }
+}
+
+/// Read an attribute the way a CSV export writes it. A NULL is written as
+/// `true`, which `has_track_id` reads as there being no ID.
+pub fn read_attribute(item: &Map<String, Value>, name: &str) -> String {
+    let typed = match item.get(name).and_then(|value| value.as_object()) {
+        Some(typed) => typed,
+        None => return String::new(),
+    };
+    match typed.values().next() {
+        Some(Value::String(value)) => value.to_owned(),
+        Some(value) => value.to_string(),
+        None => String::new(),
+    }
+}
+
+/// Parse a DynamoDB JSON export to retrieve song information. Items can be
+/// one per line, as AWS exports tables, wrapped in `{"Item": ...}` the way
+/// exports to S3 are, or in the `{"Items": [...]}` that a scan prints. Songs
+/// are read from the attributes that the columns were exported from, see
+/// `attribute_name`.
+///
+/// # Arguments
+///
+/// * `reader` - Where the JSON is read from
+/// * `columns` - The columns to read each song from
+pub fn parse_dynamodb_json<R: Read>(reader: R, columns: &CsvColumns) -> Result<Vec<Song>, Box<Error>> {
+    let name = attribute_name(&columns.name);
+    let id = attribute_name(&columns.id);
+    let mut items = Vec::new();
+    for value in Deserializer::from_reader(reader).into_iter::<Value>() {
+        let value = value?;
+        if let Some(scanned) = value.get("Items").and_then(|scanned| scanned.as_array()) {
+            items.extend(scanned.iter().cloned());
+        } else if let Some(item) = value.get("Item") {
+            items.push(item.clone());
+        } else {
+            items.push(value);
+        }
+    }
+    items.iter()
+        .enumerate()
+        .map(|(i, item)| match *item {
+            Value::Object(ref item) => Ok(Song {
+                music: read_attribute(item, name),
+                song_id: read_attribute(item, id),
+            }),
+            _ => Err(From::from(InvalidItem { item: i + 1 })),
+        })
+        .collect()
+}
+
+/// Parse a DynamoDB JSON export file, see `parse_dynamodb_json`
+///
+/// # Arguments
+///
+/// * `filename` - The path to the JSON file, or `-` to read from stdin
+/// * `columns` - The columns to read each song from
+pub fn parse_dynamodb_json_file(filename: &str, columns: &CsvColumns) -> Result<Vec<Song>, Box<Error>> {
+    if filename == STDIN_FILENAME {
+        let stdin = io::stdin();
+        return parse_dynamodb_json(stdin.lock(), columns);
+    }
+    parse_dynamodb_json(File::open(filename)?, columns)
+}
+
+/// A DynamoDB table to scan for songs, so that it doesn't need to be exported
+/// first
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamoDbTable {
+    pub table: String,
+    /// The credentials and region to scan the table with
+    pub aws: AwsOptions,
+    /// The columns to read each song from, see `attribute_name`
+    pub columns: CsvColumns,
+}
+
+/// Read an attribute the way a CSV export writes it, see `read_attribute`
+fn attribute_to_string(value: &AttributeValue) -> String {
+    match *value {
+        AttributeValue::S(ref value) | AttributeValue::N(ref value) => value.to_owned(),
+        AttributeValue::Bool(value) => value.to_string(),
+        AttributeValue::Null(_) => "true".to_owned(),
+        _ => String::new(),
+    }
+}
+
+/// Get the song in an item scanned from a table
+///
+/// # Arguments
+///
+/// * `item` - The item's attributes
+/// * `columns` - The columns to read the song from, see `attribute_name`
+pub fn song_from_item(item: &HashMap<String, AttributeValue>, columns: &CsvColumns) -> Song {
+    let read = |column: &str| item.get(attribute_name(column))
+        .map(attribute_to_string)
+        .unwrap_or_default();
+    Song {
+        music: read(&columns.name),
+        song_id: read(&columns.id),
+    }
+}
+
+impl SongSource for DynamoDbTable {
+    /// Scan every page of the table. The order of songs is the order that
+    /// DynamoDB returns them in, which isn't the order they were added.
+    fn read_songs(self) -> Result<Vec<Song>, Box<Error>> {
+        let DynamoDbTable { table, aws, columns } = self;
+        let runtime = runtime()?;
+        let client = Client::new(&aws.load(&runtime));
+        let mut songs = Vec::new();
+        let mut start_key = None;
+        loop {
+            let page = runtime.block_on(
+                client.scan().table_name(table.as_str()).set_exclusive_start_key(start_key).send()
+            ).map_err(|e| describe_error(&e))?;
+            songs.extend(page.items().iter().map(|item| song_from_item(item, &columns)));
+            start_key = page.last_evaluated_key().cloned();
+            if start_key.is_none() {
+                return Ok(songs);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn songs_are_read_from_every_kind_of_dynamodb_json() {
+        // Given
+        let lines = "{\"music\":{\"S\":\"Robyn - Dancing On My Own\"},\"song_id\":{\"S\":\"abc\"}}\n\
+                     {\"Item\":{\"music\":{\"S\":\"Local song\"},\"song_id\":{\"NULL\":true}}}\n";
+        let scan = "{\"Items\":[{\"music\":{\"S\":\"Robyn - Hang With Me\"},\"song_id\":{\"S\":\"def\"}}],\"Count\":1}";
+        let columns = CsvColumns::new(Some("title (S)"), Some("uri"));
+        let custom = "{\"title\":{\"S\":\"Robyn - Honey\"},\"uri\":{\"S\":\"ghi\"}}";
+        // When
+        let from_lines = parse_dynamodb_json(lines.as_bytes(), &CsvColumns::default()).unwrap();
+        let from_scan = parse_dynamodb_json(scan.as_bytes(), &CsvColumns::default()).unwrap();
+        let from_custom = parse_dynamodb_json(custom.as_bytes(), &columns).unwrap();
+        let invalid = parse_dynamodb_json("{\"Items\":[1]}".as_bytes(), &CsvColumns::default());
+        // Then
+        assert_eq!(
+            vec![
+                Song { music: "Robyn - Dancing On My Own".to_owned(), song_id: "abc".to_owned() },
+                Song { music: "Local song".to_owned(), song_id: "true".to_owned() },
+            ],
+            from_lines
+        );
+        assert_eq!(vec![Song { music: "Robyn - Hang With Me".to_owned(), song_id: "def".to_owned() }], from_scan);
+        assert_eq!(vec![Song { music: "Robyn - Honey".to_owned(), song_id: "ghi".to_owned() }], from_custom);
+        assert_eq!(
+            "Expected each item to be an object of attributes like {\"music\":{\"S\":\"...\"}}, item 1 isn't",
+            invalid.unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn songs_are_read_from_scanned_items_like_exports() {
+        // Given
+        let mut item = HashMap::new();
+        item.insert("music".to_owned(), AttributeValue::S("Robyn - Honey".to_owned()));
+        item.insert("song_id".to_owned(), AttributeValue::Null(true));
+        // When
+        let song = song_from_item(&item, &CsvColumns::default());
+        let unnamed = song_from_item(&item, &CsvColumns::new(Some("title"), None));
+        // Then
+        assert_eq!(Song { music: "Robyn - Honey".to_owned(), song_id: "true".to_owned() }, song);
+        assert_eq!(Song { music: "".to_owned(), song_id: "true".to_owned() }, unnamed);
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt;
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+use std::str::FromStr;
+
+/// The text encoding of a CSV. Excel often saves "Unicode Text" as UTF-16
+/// and puts a byte order mark at the start of UTF-8
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Encoding, String> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be),
+            _ => Err(format!("{} is not one of utf-8, utf-16le or utf-16be", s)),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Guess the encoding from the start of a file. A byte order mark decides
+/// it, otherwise UTF-16 is recognised by the zero bytes of ASCII characters,
+/// eg. a header like "music (S)". Anything else is read as UTF-8
+///
+/// # Arguments
+///
+/// * `start` - The first bytes of the file
+pub fn detect_encoding(start: &[u8]) -> Encoding {
+    if start.starts_with(&[0xFF, 0xFE]) {
+        return Encoding::Utf16Le;
+    }
+    if start.starts_with(&[0xFE, 0xFF]) {
+        return Encoding::Utf16Be;
+    }
+    if start.len() >= 2 && start[0] != 0 && start[1] == 0 {
+        return Encoding::Utf16Le;
+    }
+    if start.len() >= 2 && start[0] == 0 && start[1] != 0 {
+        return Encoding::Utf16Be;
+    }
+    Encoding::Utf8
+}
+
+/// The length of the byte order mark that `data` starts with, if it's in
+/// the encoding
+fn bom_length(data: &[u8], encoding: Encoding) -> usize {
+    let bom: &[u8] = match encoding {
+        Encoding::Utf8 => &[0xEF, 0xBB, 0xBF],
+        Encoding::Utf16Le => &[0xFF, 0xFE],
+        Encoding::Utf16Be => &[0xFE, 0xFF],
+    };
+    if data.starts_with(bom) { bom.len() } else { 0 }
+}
+
+/// Text read as UTF-8, transcoded from the encoding it's in without the
+/// byte order mark, see `decode`
+pub enum Decoded<R: Read> {
+    Utf8(BufReader<R>),
+    Utf16(Utf16Reader<BufReader<R>>),
+}
+
+impl<R: Read> Read for Decoded<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Decoded::Utf8(ref mut reader) => reader.read(buf),
+            Decoded::Utf16(ref mut reader) => reader.read(buf),
+        }
+    }
+}
+
+/// Read text as UTF-8, so that CSVs in other encodings can be handed to the
+/// csv reader, which only reads UTF-8. The encoding is detected with
+/// `detect_encoding` unless it's given. Any byte order mark is removed.
+///
+/// # Arguments
+///
+/// * `reader` - Where the text is read from
+/// * `encoding` - The encoding of the text, or `None` to detect it
+pub fn decode<R: Read>(reader: R, encoding: Option<Encoding>) -> io::Result<Decoded<R>> {
+    let mut reader = BufReader::new(reader);
+    let (encoding, bom) = {
+        let start = reader.fill_buf()?;
+        let encoding = encoding.unwrap_or_else(|| detect_encoding(start));
+        (encoding, bom_length(start, encoding))
+    };
+    reader.consume(bom);
+    debug!("Reading the CSV as {}", encoding);
+    Ok(match encoding {
+        Encoding::Utf8 => Decoded::Utf8(reader),
+        Encoding::Utf16Le => Decoded::Utf16(Utf16Reader::new(reader, false)),
+        Encoding::Utf16Be => Decoded::Utf16(Utf16Reader::new(reader, true)),
+    })
+}
+
+/// Transcodes UTF-16 to UTF-8 as it's read, see `decode`
+pub struct Utf16Reader<R: Read> {
+    reader: R,
+    big_endian: bool,
+    /// A byte of a code unit that hasn't been read in full yet
+    odd_byte: Option<u8>,
+    /// The first half of a surrogate pair that hasn't been read in full yet
+    high_surrogate: Option<u16>,
+    /// UTF-8 that hasn't been read yet
+    decoded: Vec<u8>,
+    /// Where the unread UTF-8 starts
+    position: usize,
+}
+
+impl<R: Read> Utf16Reader<R> {
+    fn new(reader: R, big_endian: bool) -> Utf16Reader<R> {
+        Utf16Reader {
+            reader: reader,
+            big_endian: big_endian,
+            odd_byte: None,
+            high_surrogate: None,
+            decoded: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Decode the next bytes that are read into UTF-8. Returns false at the
+    /// end of the text
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut raw = [0; 8192];
+        let mut length = 0;
+        if let Some(byte) = self.odd_byte.take() {
+            raw[0] = byte;
+            length = 1;
+        }
+        let read = self.reader.read(&mut raw[length..])?;
+        length += read;
+        if read == 0 {
+            if length > 0 || self.high_surrogate.is_some() {
+                return Err(invalid_utf16());
+            }
+            return Ok(false);
+        }
+        if length % 2 == 1 {
+            self.odd_byte = Some(raw[length - 1]);
+            length -= 1;
+        }
+        let big_endian = self.big_endian;
+        let mut units: Vec<u16> = self.high_surrogate.take().into_iter()
+            .chain(raw[..length].chunks(2).map(|pair| if big_endian {
+                u16::from(pair[0]) << 8 | u16::from(pair[1])
+            } else {
+                u16::from(pair[1]) << 8 | u16::from(pair[0])
+            }))
+            .collect();
+        // The rest of the pair comes with the next read
+        if let Some(&(0xD800..=0xDBFF)) = units.last() {
+            self.high_surrogate = units.pop();
+        }
+        let text: String = ::std::char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .map_err(|_| invalid_utf16())?;
+        self.decoded = text.into_bytes();
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+fn invalid_utf16() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "The CSV is not valid UTF-16")
+}
+
+impl<R: Read> Read for Utf16Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.decoded.len() {
+            if !self.fill()? {
+                return Ok(0);
+            }
+        }
+        let unread = &self.decoded[self.position..];
+        let length = unread.len().min(buf.len());
+        buf[..length].copy_from_slice(&unread[..length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(|unit| vec![unit as u8, (unit >> 8) as u8]).collect()
+    }
+
+    fn read_all<R: Read>(mut reader: R) -> String {
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn excel_exports_are_read_as_utf8() {
+        // Given
+        let csv = "music (S),song_id (S)\nSigur Rós - Hoppípolla 🎶,abc\n";
+        let mut with_bom = vec![0xFF, 0xFE];
+        with_bom.extend(utf16le(csv));
+        let mut utf8_bom = vec![0xEF, 0xBB, 0xBF];
+        utf8_bom.extend(csv.as_bytes());
+        let big_endian: Vec<u8> = csv.encode_utf16().flat_map(|unit| vec![(unit >> 8) as u8, unit as u8]).collect();
+        // When
+        let from_utf16 = read_all(decode(&with_bom[..], None).unwrap());
+        let without_bom = read_all(decode(&utf16le(csv)[..], None).unwrap());
+        let from_utf8 = read_all(decode(&utf8_bom[..], None).unwrap());
+        let overridden = read_all(decode(&big_endian[..], Some(Encoding::Utf16Be)).unwrap());
+        let mut truncated = utf16le(csv);
+        truncated.pop();
+        let invalid = decode(&truncated[..], None).unwrap().read_to_string(&mut String::new());
+        // Then
+        assert_eq!(csv, from_utf16);
+        assert_eq!(csv, without_bom);
+        assert_eq!(csv, from_utf8);
+        assert_eq!(csv, overridden);
+        assert_eq!(io::ErrorKind::InvalidData, invalid.unwrap_err().kind());
+        assert_eq!(Ok(Encoding::Utf16Le), "UTF-16LE".parse());
+    }
+}
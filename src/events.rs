@@ -0,0 +1,104 @@
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+extern crate serde_json;
+
+/// How events are written to stdout, from `--events`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EventFormat {
+    /// One JSON object on each line
+    Ndjson,
+}
+
+impl FromStr for EventFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<EventFormat, String> {
+        match value {
+            "ndjson" => Ok(EventFormat::Ndjson),
+            _ => Err(format!("Expected ndjson, got {:?}", value)),
+        }
+    }
+}
+
+/// Something that happened while importing, so that wrappers can show their
+/// own progress without reading the logs. Each is written with an `event`
+/// field naming it, eg. `{"event":"row-parsed","line":2,...}`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    /// A row was read from a CSV. The header is line 1
+    RowParsed { line: usize, music: &'a str, song_id: &'a str },
+    /// A row of a CSV couldn't be read and was skipped, with
+    /// `--skip-invalid-rows`
+    RowSkipped { line: usize, reason: &'a str },
+    /// A song has a track that can be added
+    Matched { music: &'a str, track: &'a str },
+    /// A track wasn't added since it's already in the playlist
+    SkippedDuplicate { track: &'a str },
+    /// A batch of tracks was added to a playlist, where the first batch is 1
+    BatchAdded { playlist_id: &'a str, batch: u64, batches: u64, tracks: usize },
+    /// Something went wrong and the run is stopping
+    Error { message: &'a str },
+}
+
+impl<'a> Event<'a> {
+    /// The event as a line of NDJSON, without the newline
+    pub fn to_ndjson(&self) -> String {
+        serde_json::to_string(self).expect("Events can always be written as JSON")
+    }
+}
+
+static EVENT_FORMAT: OnceLock<EventFormat> = OnceLock::new();
+
+/// Write events to stdout from now on, from `--events`. This should be
+/// called once at startup, later calls are ignored
+///
+/// # Arguments
+///
+/// * `format` - How each event is written
+pub fn set_event_format(format: EventFormat) {
+    if EVENT_FORMAT.set(format).is_err() {
+        warn!("--events was already set");
+    }
+}
+
+/// Write an event to stdout straight away, if `set_event_format` was called
+///
+/// # Arguments
+///
+/// * `event` - What happened
+pub fn emit(event: &Event) {
+    let line = match EVENT_FORMAT.get() {
+        Some(&EventFormat::Ndjson) => event.to_ndjson(),
+        None => return,
+    };
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    // Wrappers read events as they happen, so each is flushed rather than
+    // waiting for the buffer to fill
+    if writeln!(stdout, "{}", line).and_then(|_| stdout.flush()).is_err() {
+        warn!("Could not write an event to stdout");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_written_as_one_json_object_each() {
+        // Given
+        let parsed = Event::RowParsed { line: 2, music: "Robyn - Honey", song_id: "abc" };
+        let added = Event::BatchAdded { playlist_id: "p1", batch: 1, batches: 2, tracks: 100 };
+        // When
+        let parsed = parsed.to_ndjson();
+        let added = added.to_ndjson();
+        // Then
+        assert_eq!("{\"event\":\"row-parsed\",\"line\":2,\"music\":\"Robyn - Honey\",\"song_id\":\"abc\"}", parsed);
+        assert_eq!("{\"event\":\"batch-added\",\"playlist_id\":\"p1\",\"batch\":1,\"batches\":2,\"tracks\":100}", added);
+        assert_eq!(Ok(EventFormat::Ndjson), "ndjson".parse());
+        assert!("json".parse::<EventFormat>().is_err());
+    }
+}
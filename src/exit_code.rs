@@ -0,0 +1,84 @@
+use csv_to_playlist::PlaylistAddError;
+use playlist::PlaylistError;
+
+/// Exit codes, so that scripts can tell why a run stopped. These are listed
+/// in the README and shouldn't change once released.
+pub const SUCCESS: i32 = 0;
+/// Anything that doesn't have its own code, including invalid arguments
+pub const FAILURE: i32 = 1;
+/// Every track is already in the playlist
+pub const NO_NEW_TRACKS: i32 = 2;
+/// Logging in failed or the user isn't allowed to change the playlist
+pub const AUTH_FAILED: i32 = 3;
+/// The CSV couldn't be read
+pub const INVALID_CSV: i32 = 4;
+/// A request to Spotify failed
+pub const API_ERROR: i32 = 5;
+/// The requests allowed by `--max-requests` ran out
+pub const REQUEST_BUDGET_EXHAUSTED: i32 = 6;
+/// The `--deadline` passed
+pub const DEADLINE_EXCEEDED: i32 = 7;
+/// The tracks won't fit in the playlist and `--on-full` is abort
+pub const PLAYLIST_FULL: i32 = 8;
+/// `--diff-only` found changes that haven't been made yet. This has its own
+/// code so that a CI check can tell it apart from invalid arguments
+pub const CHANGES_PENDING: i32 = 9;
+
+/// Get the exit code for a playlist that couldn't be found or changed
+pub fn playlist_error_code<E>(error: &PlaylistError<E>) -> i32 {
+    match *error {
+        PlaylistError::APIError(_) => API_ERROR,
+        PlaylistError::PlaylistNotFound(_) => FAILURE,
+        PlaylistError::NotAuthorizedToModify(_) => AUTH_FAILED,
+        PlaylistError::PlaylistAlreadyExists(_) => FAILURE,
+        PlaylistError::PlaylistFull(_) => PLAYLIST_FULL,
+    }
+}
+
+/// Get the exit code for songs that couldn't be added
+pub fn add_error_code<E>(error: &PlaylistAddError<E>) -> i32 {
+    match *error {
+        PlaylistAddError::APIError(_) => API_ERROR,
+        PlaylistAddError::ReadError(_) => INVALID_CSV,
+        PlaylistAddError::NoNewTracks(_) => NO_NEW_TRACKS,
+        PlaylistAddError::PlaylistFull(_) => PLAYLIST_FULL,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capacity::PlaylistFull;
+    use csv_to_playlist::NoNewTracks;
+    use playlist::{NotAuthorizedToModify,PlaylistAlreadyExists,PlaylistNotFound};
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let codes = [
+            SUCCESS, FAILURE, NO_NEW_TRACKS, AUTH_FAILED, INVALID_CSV, API_ERROR,
+            REQUEST_BUDGET_EXHAUSTED, DEADLINE_EXCEEDED, PLAYLIST_FULL, CHANGES_PENDING,
+        ];
+        for (i, code) in codes.iter().enumerate() {
+            assert!(!codes[i + 1..].contains(code), "{} is used twice", code);
+        }
+    }
+
+    #[test]
+    fn errors_map_to_their_codes() {
+        assert_eq!(API_ERROR, playlist_error_code(&PlaylistError::APIError(())));
+        assert_eq!(FAILURE, playlist_error_code::<()>(&PlaylistError::PlaylistNotFound(PlaylistNotFound {})));
+        assert_eq!(
+            FAILURE,
+            playlist_error_code::<()>(&PlaylistError::PlaylistAlreadyExists(PlaylistAlreadyExists {}))
+        );
+        assert_eq!(
+            AUTH_FAILED,
+            playlist_error_code::<()>(&PlaylistError::NotAuthorizedToModify(NotAuthorizedToModify {}))
+        );
+        assert_eq!(API_ERROR, add_error_code(&PlaylistAddError::APIError(())));
+        assert_eq!(NO_NEW_TRACKS, add_error_code::<()>(&PlaylistAddError::NoNewTracks(NoNewTracks {})));
+        let full = PlaylistFull { existing: 9_999, adding: 2 };
+        assert_eq!(PLAYLIST_FULL, add_error_code::<()>(&PlaylistAddError::PlaylistFull(full)));
+        assert_eq!(PLAYLIST_FULL, playlist_error_code::<()>(&PlaylistError::PlaylistFull(full)));
+    }
+}
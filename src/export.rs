@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+
+use csv_to_playlist::Song;
+use playlist::TrackMetadata;
+use track_uri::TrackUri;
+
+extern crate csv;
+
+/// Turn a track from a playlist into a song that can be imported again. The
+/// name includes the artists so that the CSV is easy to read
+///
+/// # Arguments
+///
+/// * `track` - The track from the playlist
+pub fn song_from_metadata(track: &TrackMetadata) -> Song {
+    let music = if track.artists.is_empty() {
+        track.name.to_owned()
+    } else {
+        format!("{} - {}", track.artists.join(", "), track.name)
+    };
+    Song {
+        music: music,
+        song_id: track.uri.uri(),
+    }
+}
+
+/// A row of an exported CSV. The first two columns are the ones read by
+/// `parse_csv`, so the CSV can be imported again. The other columns are
+/// ignored when importing
+#[derive(Debug, Serialize)]
+struct ExportRow<'a> {
+    #[serde(rename = "music (S)")]
+    music: String,
+    #[serde(rename = "song_id (S)")]
+    song_id: String,
+    track: &'a str,
+    artist: String,
+    album: &'a str,
+}
+
+/// An `ExportRow` along with the track's note, see `NoteIndex`. The note
+/// column is read back as the song's note when importing
+#[derive(Debug, Serialize)]
+struct NotedExportRow<'a> {
+    #[serde(rename = "music (S)")]
+    music: String,
+    #[serde(rename = "song_id (S)")]
+    song_id: String,
+    track: &'a str,
+    artist: String,
+    album: &'a str,
+    #[serde(rename = "note (S)")]
+    note: &'a str,
+}
+
+/// Write tracks as a CSV in the same format that's read by `parse_csv`,
+/// along with the track name, artists and album of each track
+///
+/// # Arguments
+///
+/// * `writer` - Where the CSV data is written
+/// * `tracks` - The tracks to write, in order
+pub fn write_csv<W: Write>(writer: W, tracks: &[TrackMetadata]) -> Result<(), Box<Error>> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    for track in tracks {
+        let song = song_from_metadata(track);
+        wtr.serialize(ExportRow {
+            music: song.music,
+            song_id: song.song_id,
+            track: &track.name,
+            artist: track.artists.join(", "),
+            album: &track.album,
+        })?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Write tracks as a CSV in the same way as `write_csv`, with a note column
+/// that's empty for tracks without a note
+///
+/// # Arguments
+///
+/// * `writer` - Where the CSV data is written
+/// * `tracks` - The tracks to write, in order
+/// * `notes` - The note on each track, see `NoteIndex::playlist_notes`
+pub fn write_csv_with_notes<W: Write>(writer: W,
+                                      tracks: &[TrackMetadata],
+                                      notes: &BTreeMap<TrackUri, String>) -> Result<(), Box<Error>> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    for track in tracks {
+        let song = song_from_metadata(track);
+        wtr.serialize(NotedExportRow {
+            music: song.music,
+            song_id: song.song_id,
+            track: &track.name,
+            artist: track.artists.join(", "),
+            album: &track.album,
+            note: notes.get(&track.uri).map_or("", |note| note.as_str()),
+        })?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_to_playlist::parse_csv;
+    use track_uri::TrackUri;
+
+    #[test]
+    fn exported_songs_can_be_imported_again() {
+        // Given
+        let tracks = [
+            TrackMetadata {
+                uri: TrackUri::Track("id_a".to_string()),
+                name: "Song, Part 1".to_string(),
+                artists: vec!["A".to_string(), "B".to_string()],
+                album: "Album".to_string(),
+                duration_ms: 0,
+                image_url: None,
+            },
+            TrackMetadata {
+                uri: TrackUri::Local("spotify:local:C:D:E:1".to_string()),
+                name: "Local".to_string(),
+                artists: Vec::new(),
+                album: "".to_string(),
+                duration_ms: 0,
+                image_url: None,
+            },
+        ];
+        let songs: Vec<Song> = tracks.iter().map(song_from_metadata).collect();
+        let mut data = Vec::new();
+        // When
+        write_csv(&mut data, &tracks).unwrap();
+        // Then
+        let expected = "music (S),song_id (S),track,artist,album\n\
+                        \"A, B - Song, Part 1\",spotify:track:id_a,\"Song, Part 1\",\"A, B\",Album\n\
+                        Local,spotify:local:C:D:E:1,Local,,\n";
+        assert_eq!(expected, String::from_utf8(data.clone()).unwrap());
+        assert_eq!(songs, parse_csv(&data[..]).unwrap());
+    }
+}
@@ -0,0 +1,215 @@
+use playlist::{AudioFeatures,PlaylistAPI,PlaylistDetails,PlaylistError,PlaylistInfo,PlaylistNotFound,TrackAvailability,TrackMetadata,TrackVersion,Visibility};
+use track_uri::TrackUri;
+
+/// The error returned by fake APIs
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FakeError {}
+
+/// `PlaylistAPI` for tests, with a default for every call so that mocks
+/// only override the calls that their tests use. Reads find nothing and
+/// changes succeed without doing anything, except for creating a playlist,
+/// which fails since there is no ID to return
+pub trait FakePlaylistAPI {
+    fn get_playlist_id(&self, _playlist_name: &str) -> Result<String, PlaylistError<FakeError>> {
+        Err(PlaylistError::PlaylistNotFound(PlaylistNotFound {}))
+    }
+
+    fn create_playlist(&self, _playlist_name: &str, _visibility: Visibility) -> Result<String, FakeError> {
+        Err(FakeError {})
+    }
+
+    fn add_tracks_to_playlist(&self, _playlist_id: &str, _tracks: &[TrackUri], _position: Option<usize>) -> Result<(), FakeError> {
+        Ok(())
+    }
+
+    fn get_track_ids_in_playlist(&self, _playlist_id: &str) -> Result<Vec<TrackUri>, FakeError> {
+        Ok(Vec::new())
+    }
+
+    fn get_tracks_with_metadata_in_playlist(&self, _playlist_id: &str) -> Result<Vec<TrackMetadata>, FakeError> {
+        Ok(Vec::new())
+    }
+
+    fn get_playlist_snapshot_id(&self, _playlist_id: &str) -> Result<String, FakeError> {
+        Ok(String::new())
+    }
+
+    /// Counts the tracks from `get_track_ids_in_playlist`
+    fn get_playlist_size(&self, playlist_id: &str) -> Result<usize, FakeError> {
+        FakePlaylistAPI::get_track_ids_in_playlist(self, playlist_id).map(|tracks| tracks.len())
+    }
+
+    fn get_playlist_details(&self, _playlist_id: &str) -> Result<PlaylistDetails, FakeError> {
+        Ok(PlaylistDetails::default())
+    }
+
+    fn get_cover_image(&self, _playlist_id: &str) -> Result<Option<Vec<u8>>, FakeError> {
+        Ok(None)
+    }
+
+    fn get_tracks_metadata(&self, _tracks: &[TrackUri]) -> Result<Vec<TrackMetadata>, FakeError> {
+        Ok(Vec::new())
+    }
+
+    fn update_playlist_details(&self, _playlist_id: &str, _details: &PlaylistDetails) -> Result<(), FakeError> {
+        Ok(())
+    }
+
+    fn set_playlist_visibility(&self, _playlist_id: &str, _visibility: Visibility) -> Result<(), FakeError> {
+        Ok(())
+    }
+
+    fn upload_cover_image(&self, _playlist_id: &str, _jpeg: &[u8]) -> Result<(), FakeError> {
+        Ok(())
+    }
+
+    fn can_modify_playlist(&self, _playlist_id: &str) -> Result<bool, FakeError> {
+        Ok(true)
+    }
+
+    fn rename_playlist(&self, _playlist_id: &str, _name: &str) -> Result<(), FakeError> {
+        Ok(())
+    }
+
+    fn unfollow_playlist(&self, _playlist_id: &str) -> Result<(), FakeError> {
+        Ok(())
+    }
+
+    fn remove_tracks_from_playlist(&self, _playlist_id: &str, _tracks: &[TrackUri]) -> Result<(), FakeError> {
+        Ok(())
+    }
+
+    fn insert_tracks_into_playlist(&self, _playlist_id: &str, _tracks: &[TrackUri], _position: usize) -> Result<(), FakeError> {
+        Ok(())
+    }
+
+    fn remove_tracks_at_positions(&self, _playlist_id: &str, _occurrences: &[(TrackUri, usize)]) -> Result<(), FakeError> {
+        Ok(())
+    }
+
+    fn move_track_in_playlist(&self, _playlist_id: &str, _from: usize, _insert_before: usize) -> Result<(), FakeError> {
+        Ok(())
+    }
+
+    fn list_playlists(&self) -> Result<Vec<PlaylistInfo>, FakeError> {
+        Ok(Vec::new())
+    }
+
+    fn get_track_versions(&self, _tracks: &[TrackUri]) -> Result<Vec<TrackVersion>, FakeError> {
+        Ok(Vec::new())
+    }
+
+    fn search_tracks(&self, _query: &str, _limit: u32) -> Result<Vec<TrackMetadata>, FakeError> {
+        Ok(Vec::new())
+    }
+
+    fn get_audio_features(&self, _tracks: &[TrackUri]) -> Result<Vec<AudioFeatures>, FakeError> {
+        Ok(Vec::new())
+    }
+
+    fn get_track_availability(&self, _tracks: &[TrackUri], _market: &str) -> Result<Vec<TrackAvailability>, FakeError> {
+        Ok(Vec::new())
+    }
+}
+
+impl<T: FakePlaylistAPI> PlaylistAPI<FakeError> for T {
+    fn get_playlist_id(&self, playlist_name: &str) -> Result<String, PlaylistError<FakeError>> {
+        FakePlaylistAPI::get_playlist_id(self, playlist_name)
+    }
+
+    fn create_playlist(&self, playlist_name: &str, visibility: Visibility) -> Result<String, FakeError> {
+        FakePlaylistAPI::create_playlist(self, playlist_name, visibility)
+    }
+
+    fn add_tracks_to_playlist(&self, playlist_id: &str, tracks: &[TrackUri], position: Option<usize>) -> Result<(), FakeError> {
+        FakePlaylistAPI::add_tracks_to_playlist(self, playlist_id, tracks, position)
+    }
+
+    fn get_track_ids_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackUri>, FakeError> {
+        FakePlaylistAPI::get_track_ids_in_playlist(self, playlist_id)
+    }
+
+    fn get_tracks_with_metadata_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackMetadata>, FakeError> {
+        FakePlaylistAPI::get_tracks_with_metadata_in_playlist(self, playlist_id)
+    }
+
+    fn get_playlist_snapshot_id(&self, playlist_id: &str) -> Result<String, FakeError> {
+        FakePlaylistAPI::get_playlist_snapshot_id(self, playlist_id)
+    }
+
+    fn get_playlist_size(&self, playlist_id: &str) -> Result<usize, FakeError> {
+        FakePlaylistAPI::get_playlist_size(self, playlist_id)
+    }
+
+    fn get_playlist_details(&self, playlist_id: &str) -> Result<PlaylistDetails, FakeError> {
+        FakePlaylistAPI::get_playlist_details(self, playlist_id)
+    }
+
+    fn get_cover_image(&self, playlist_id: &str) -> Result<Option<Vec<u8>>, FakeError> {
+        FakePlaylistAPI::get_cover_image(self, playlist_id)
+    }
+
+    fn get_tracks_metadata(&self, tracks: &[TrackUri]) -> Result<Vec<TrackMetadata>, FakeError> {
+        FakePlaylistAPI::get_tracks_metadata(self, tracks)
+    }
+
+    fn update_playlist_details(&self, playlist_id: &str, details: &PlaylistDetails) -> Result<(), FakeError> {
+        FakePlaylistAPI::update_playlist_details(self, playlist_id, details)
+    }
+
+    fn set_playlist_visibility(&self, playlist_id: &str, visibility: Visibility) -> Result<(), FakeError> {
+        FakePlaylistAPI::set_playlist_visibility(self, playlist_id, visibility)
+    }
+
+    fn upload_cover_image(&self, playlist_id: &str, jpeg: &[u8]) -> Result<(), FakeError> {
+        FakePlaylistAPI::upload_cover_image(self, playlist_id, jpeg)
+    }
+
+    fn can_modify_playlist(&self, playlist_id: &str) -> Result<bool, FakeError> {
+        FakePlaylistAPI::can_modify_playlist(self, playlist_id)
+    }
+
+    fn rename_playlist(&self, playlist_id: &str, name: &str) -> Result<(), FakeError> {
+        FakePlaylistAPI::rename_playlist(self, playlist_id, name)
+    }
+
+    fn unfollow_playlist(&self, playlist_id: &str) -> Result<(), FakeError> {
+        FakePlaylistAPI::unfollow_playlist(self, playlist_id)
+    }
+
+    fn remove_tracks_from_playlist(&self, playlist_id: &str, tracks: &[TrackUri]) -> Result<(), FakeError> {
+        FakePlaylistAPI::remove_tracks_from_playlist(self, playlist_id, tracks)
+    }
+
+    fn insert_tracks_into_playlist(&self, playlist_id: &str, tracks: &[TrackUri], position: usize) -> Result<(), FakeError> {
+        FakePlaylistAPI::insert_tracks_into_playlist(self, playlist_id, tracks, position)
+    }
+
+    fn remove_tracks_at_positions(&self, playlist_id: &str, occurrences: &[(TrackUri, usize)]) -> Result<(), FakeError> {
+        FakePlaylistAPI::remove_tracks_at_positions(self, playlist_id, occurrences)
+    }
+
+    fn move_track_in_playlist(&self, playlist_id: &str, from: usize, insert_before: usize) -> Result<(), FakeError> {
+        FakePlaylistAPI::move_track_in_playlist(self, playlist_id, from, insert_before)
+    }
+
+    fn list_playlists(&self) -> Result<Vec<PlaylistInfo>, FakeError> {
+        FakePlaylistAPI::list_playlists(self)
+    }
+
+    fn get_track_versions(&self, tracks: &[TrackUri]) -> Result<Vec<TrackVersion>, FakeError> {
+        FakePlaylistAPI::get_track_versions(self, tracks)
+    }
+
+    fn search_tracks(&self, query: &str, limit: u32) -> Result<Vec<TrackMetadata>, FakeError> {
+        FakePlaylistAPI::search_tracks(self, query, limit)
+    }
+
+    fn get_audio_features(&self, tracks: &[TrackUri]) -> Result<Vec<AudioFeatures>, FakeError> {
+        FakePlaylistAPI::get_audio_features(self, tracks)
+    }
+
+    fn get_track_availability(&self, tracks: &[TrackUri], market: &str) -> Result<Vec<TrackAvailability>, FakeError> {
+        FakePlaylistAPI::get_track_availability(self, tracks, market)
+    }
+}
@@ -0,0 +1,168 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+
+use playlist::TrackMetadata;
+use render::{escape_html,html_track_table};
+
+extern crate serde_json;
+
+/// The name of the file in the state directory that keeps the feed's entries
+pub const ENTRIES_FILENAME: &str = "feed.json";
+
+/// Feed readers only look at recent entries, so older runs are dropped
+const MAX_ENTRIES: usize = 50;
+
+/// The tracks that a job added to its playlist in a single run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedEntry {
+    pub job_name: String,
+    pub playlist_name: String,
+    pub playlist_url: String,
+    /// When the run finished, in RFC 3339 format
+    pub updated: String,
+    pub tracks: Vec<TrackMetadata>,
+}
+
+/// The runs that added tracks, newest first. The entries are kept between
+/// runs of the daemon so that a restart doesn't empty the feed.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Feed {
+    entries: Vec<FeedEntry>,
+}
+
+impl Feed {
+    /// Read entries that were saved using `write`. A missing file is treated
+    /// as an empty feed.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the saved entries
+    pub fn read(filename: &str) -> Result<Feed, Box<Error>> {
+        match File::open(filename) {
+            Ok(file) => Ok(serde_json::from_reader(file)?),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Feed::default()),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    /// Save the entries as JSON
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the saved entries
+    pub fn write(&self, filename: &str) -> Result<(), Box<Error>> {
+        let file = File::create(filename)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Add the latest run to the top of the feed
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The tracks added in the run
+    pub fn add(&mut self, entry: FeedEntry) {
+        self.entries.insert(0, entry);
+        self.entries.truncate(MAX_ENTRIES);
+    }
+
+    /// Render the feed as an Atom document
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The title of the feed
+    /// * `id` - A URI that stays the same for the life of the feed
+    pub fn to_atom(&self, title: &str, id: &str) -> String {
+        // An empty feed still needs a time, so use the start of the epoch
+        let updated = self.entries.first()
+            .map(|entry| entry.updated.as_str())
+            .unwrap_or("1970-01-01T00:00:00+00:00");
+        let mut output = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+             <title>{}</title>\n<id>{}</id>\n<updated>{}</updated>\n\
+             <author><name>playlist-from-csv</name></author>\n",
+            escape_html(title), escape_html(id), escape_html(updated)
+        );
+        for entry in &self.entries {
+            output += &format!(
+                "<entry>\n<title>{} tracks added to {}</title>\n\
+                 <id>{}#{}</id>\n<updated>{}</updated>\n<link href=\"{}\"/>\n\
+                 <content type=\"html\">{}</content>\n</entry>\n",
+                entry.tracks.len(), escape_html(&entry.playlist_name),
+                escape_html(&entry.playlist_url), escape_html(&entry.updated),
+                escape_html(&entry.updated), escape_html(&entry.playlist_url),
+                escape_html(&html_track_table(&entry.tracks))
+            );
+        }
+        output += "</feed>\n";
+        output
+    }
+
+    /// Save the feed as an Atom document
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the Atom file
+    /// * `title` - The title of the feed
+    /// * `id` - A URI that stays the same for the life of the feed
+    pub fn write_atom(&self, filename: &str, title: &str, id: &str) -> Result<(), Box<Error>> {
+        File::create(filename)?.write_all(self.to_atom(title, id).as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use track_uri::TrackUri;
+
+    fn entry(updated: &str) -> FeedEntry {
+        FeedEntry {
+            job_name: "requests".to_string(),
+            playlist_name: "Rock & Roll".to_string(),
+            playlist_url: "https://open.spotify.com/playlist/p1".to_string(),
+            updated: updated.to_string(),
+            tracks: vec![TrackMetadata {
+                uri: TrackUri::Track("1".to_string()),
+                name: "Dancing On My Own".to_string(),
+                artists: vec!["Robyn".to_string()],
+                album: "Body Talk".to_string(),
+                duration_ms: 281_000,
+                image_url: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn to_atom_lists_newest_runs_first() {
+        // Given
+        let mut feed = Feed::default();
+        feed.add(entry("2018-03-01T10:00:00+00:00"));
+        feed.add(entry("2018-03-02T10:00:00+00:00"));
+        // When
+        let result = feed.to_atom("Requests", "urn:playlist-from-csv:jobs.toml");
+        // Then
+        assert!(result.contains("<updated>2018-03-02T10:00:00+00:00</updated>\n<author>"));
+        let newest = result.find("<id>https://open.spotify.com/playlist/p1#2018-03-02").unwrap();
+        let oldest = result.find("<id>https://open.spotify.com/playlist/p1#2018-03-01").unwrap();
+        assert!(newest < oldest);
+        assert!(result.contains("<title>1 tracks added to Rock &amp; Roll</title>"));
+        // The track table is escaped so that it's shown as HTML
+        assert!(result.contains("&lt;a href=&quot;https://open.spotify.com/track/1&quot;&gt;"));
+    }
+
+    #[test]
+    fn add_drops_oldest_entries() {
+        // Given
+        let mut feed = Feed::default();
+        // When
+        for day in 0..MAX_ENTRIES + 1 {
+            feed.add(entry(&day.to_string()));
+        }
+        // Then
+        assert_eq!(MAX_ENTRIES, feed.entries.len());
+        assert_eq!("1", feed.entries.last().unwrap().updated);
+    }
+}
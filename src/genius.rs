@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use name_match::same_name;
+use playlist::TrackMetadata;
+use track_uri::TrackUri;
+use user_agent;
+
+extern crate reqwest;
+use self::reqwest::Client;
+use self::reqwest::header::{Authorization, Bearer};
+
+const API_URL: &str = "https://api.genius.com";
+
+/// The wrappers around Genius responses, see https://docs.genius.com
+#[derive(Deserialize)]
+struct SearchResponse {
+    response: SearchHits,
+}
+
+#[derive(Deserialize)]
+struct SearchHits {
+    hits: Vec<SearchHit>,
+}
+
+#[derive(Deserialize)]
+struct SearchHit {
+    result: SongSummary,
+}
+
+#[derive(Deserialize)]
+struct SongSummary {
+    id: u64,
+    title: String,
+    primary_artist: GeniusArtist,
+}
+
+#[derive(Deserialize)]
+struct GeniusArtist {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct SongResponse {
+    response: SongWrapper,
+}
+
+#[derive(Deserialize)]
+struct SongWrapper {
+    song: GeniusSong,
+}
+
+#[derive(Deserialize)]
+struct GeniusSong {
+    url: String,
+    #[serde(default)]
+    writer_artists: Vec<GeniusArtist>,
+}
+
+/// The Genius page and writers of a song, for publishing notes with a
+/// playlist
+#[derive(Debug, Clone, PartialEq)]
+pub struct SongAnnotation {
+    pub url: String,
+    /// The songwriters, which can be empty when Genius doesn't know them
+    pub writers: Vec<String>,
+}
+
+/// Remove the version from a Spotify track name, since Genius names the
+/// song rather than the recording, eg. `Song - Remastered 2011` becomes
+/// `Song`
+fn song_title(name: &str) -> &str {
+    name.split(" - ").next().unwrap_or(name)
+}
+
+/// Pick the search result for a track. The first result with the same title
+/// and one of the track's artists is used.
+fn find_song(hits: Vec<SearchHit>, track: &TrackMetadata) -> Option<SongSummary> {
+    hits.into_iter()
+        .map(|hit| hit.result)
+        .find(|song| {
+            same_name(&song.title, song_title(&track.name)) &&
+                track.artists.iter().any(|artist| same_name(artist, &song.primary_artist.name))
+        })
+}
+
+/// Find a track on Genius. Returns `None` when Genius doesn't have it.
+///
+/// # Arguments
+///
+/// * `client` - The client to make requests with
+/// * `token` - A Genius API client access token
+/// * `track` - The track to find
+fn get_annotation(client: &Client,
+                  token: &str,
+                  track: &TrackMetadata) -> Result<Option<SongAnnotation>, Box<Error>> {
+    let query = format!(
+        "{} {}", song_title(&track.name), track.artists.first().map(|a| a.as_str()).unwrap_or("")
+    );
+    let search: SearchResponse = client.get(&format!("{}/search", API_URL))
+        .query(&[("q", query)])
+        .header(Authorization(Bearer { token: token.to_owned() }))
+        .send()?
+        .error_for_status()?
+        .json()?;
+    let song = match find_song(search.response.hits, track) {
+        Some(song) => song,
+        None => return Ok(None),
+    };
+    let details: SongResponse = client.get(&format!("{}/songs/{}", API_URL, song.id))
+        .header(Authorization(Bearer { token: token.to_owned() }))
+        .send()?
+        .error_for_status()?
+        .json()?;
+    Ok(Some(SongAnnotation {
+        url: details.response.song.url,
+        writers: details.response.song.writer_artists.into_iter().map(|artist| artist.name).collect(),
+    }))
+}
+
+/// Find the Genius page and writers for each track. Annotations are extra,
+/// so tracks that can't be looked up are left out instead of failing.
+///
+/// # Arguments
+///
+/// * `token` - A Genius API client access token, see
+/// https://genius.com/api-clients
+/// * `tracks` - The tracks to annotate
+pub fn annotate_tracks(token: &str, tracks: &[TrackMetadata]) -> HashMap<TrackUri, SongAnnotation> {
+    let client = user_agent::client();
+    let mut annotations = HashMap::new();
+    for track in tracks {
+        match get_annotation(&client, token, track) {
+            Ok(Some(annotation)) => {
+                annotations.insert(track.uri.clone(), annotation);
+            },
+            Ok(None) => debug!("Not found on Genius: {}", track.name),
+            Err(e) => warn!("Could not look up {} on Genius: {}", track.name, e),
+        }
+    }
+    annotations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate serde_json;
+
+    #[test]
+    fn find_song_matches_title_and_artist() {
+        // Given
+        let search: SearchResponse = serde_json::from_str(r#"{"response": {"hits": [
+            {"type": "song", "result": {"id": 1, "title": "Dancing On My Own",
+             "primary_artist": {"name": "Calum Scott"}}},
+            {"type": "song", "result": {"id": 2, "title": "Dancing on My Own",
+             "primary_artist": {"name": "Robyn"}}}
+        ]}}"#).unwrap();
+        let track = TrackMetadata {
+            uri: TrackUri::Track("1".to_string()),
+            name: "Dancing On My Own - Radio Edit".to_string(),
+            artists: vec!["Robyn".to_string()],
+            album: "Body Talk".to_string(),
+            duration_ms: 0,
+            image_url: None,
+        };
+        // When
+        let song = find_song(search.response.hits, &track);
+        // Then
+        assert_eq!(Some(2), song.map(|song| song.id));
+    }
+}
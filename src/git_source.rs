@@ -0,0 +1,147 @@
+use std::error::Error;
+use std::fmt;
+use std::process::Command;
+
+use csv_to_playlist::{CsvColumns,Song};
+use source::{CsvData,SongSource};
+
+/// An error when a git command fails
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitError {
+    message: String,
+}
+
+impl Error for GitError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "git failed: {}", self.message)
+    }
+}
+
+/// Run git in the repository and return its output
+fn run_git(repo: &str, args: &[&str]) -> Result<Vec<u8>, Box<Error>> {
+    let output = Command::new("git").arg("-C").arg(repo).args(args).output()?;
+    if !output.status.success() {
+        return Err(Box::new(GitError {
+            message: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        }));
+    }
+    Ok(output.stdout)
+}
+
+/// Read the songs in a file as it was at a commit. A file that didn't exist
+/// yet has no songs.
+fn read_songs_at(repo: &str,
+                 revision: &str,
+                 file: &str,
+                 columns: &CsvColumns) -> Result<Vec<Song>, Box<Error>> {
+    let object = format!("{}:{}", revision, file);
+    // Check the revision exists so that typos aren't mistaken for a new file
+    run_git(repo, &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", revision)])?;
+    if run_git(repo, &["cat-file", "-e", &object]).is_err() {
+        return Ok(Vec::new());
+    }
+    CsvData::with_columns(&run_git(repo, &["show", &object])?[..], columns.clone()).read_songs()
+}
+
+/// Get the songs that are in `new` but not in `old`, along with their line
+/// number in `new`. Rows are compared as a whole and each old row only
+/// cancels out one new row, so a song that's added again counts as an
+/// addition.
+///
+/// # Arguments
+///
+/// * `old` - The songs before the change
+/// * `new` - The songs after the change
+pub fn get_added_songs(old: &[Song], new: Vec<Song>) -> Vec<(usize, Song)> {
+    let mut remaining: Vec<&Song> = old.iter().collect();
+    new.into_iter()
+        .enumerate()
+        // The header is line 1
+        .map(|(i, song)| (i + 2, song))
+        .filter(|(_, song)| {
+            let position = remaining.iter().position(|s| {
+                s.music == song.music && s.song_id == song.song_id
+            });
+            match position {
+                Some(i) => {
+                    remaining.remove(i);
+                    false
+                },
+                None => true,
+            }
+        })
+        .collect()
+}
+
+/// Read the rows of a Git tracked CSV that were added in the commits after
+/// `since_ref`, up to and including `HEAD`. Each song is returned with its
+/// line number in the file.
+///
+/// # Arguments
+///
+/// * `repo` - The path to the Git repository
+/// * `file` - The path to the CSV within the repository
+/// * `since_ref` - The commit to compare against, eg. `origin/main~1`
+/// * `columns` - The columns to read each song from
+pub fn read_songs_added_since(repo: &str,
+                              file: &str,
+                              since_ref: &str,
+                              columns: &CsvColumns) -> Result<Vec<(usize, Song)>, Box<Error>> {
+    let old = read_songs_at(repo, since_ref, file, columns)?;
+    let new = read_songs_at(repo, "HEAD", file, columns)?;
+    Ok(get_added_songs(&old, new))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(music: &str, song_id: &str) -> Song {
+        Song { music: music.to_string(), song_id: song_id.to_string() }
+    }
+
+    fn ids(songs: &[(usize, Song)]) -> Vec<String> {
+        songs.iter().map(|(_, s)| s.song_id.to_owned()).collect()
+    }
+
+    #[test]
+    fn get_added_songs_returns_new_rows() {
+        // Given
+        let old = vec![song("a", "1"), song("b", "2")];
+        let new = vec![song("a", "1"), song("c", "3"), song("b", "2"), song("d", "4")];
+        // When
+        let result = get_added_songs(&old, new);
+        // Then
+        assert_eq!(vec!["3".to_string(), "4".to_string()], ids(&result));
+        let lines: Vec<usize> = result.iter().map(|&(line, _)| line).collect();
+        assert_eq!(vec![3, 5], lines);
+    }
+
+    #[test]
+    fn get_added_songs_counts_repeated_rows() {
+        // Given
+        let old = vec![song("a", "1")];
+        let new = vec![song("a", "1"), song("a", "1")];
+        // When
+        let result = get_added_songs(&old, new);
+        // Then
+        assert_eq!(vec!["1".to_string()], ids(&result));
+    }
+
+    #[test]
+    fn get_added_songs_ignores_removed_rows() {
+        // Given
+        let old = vec![song("a", "1"), song("b", "2")];
+        let new = vec![song("b", "2")];
+        // When
+        let result = get_added_songs(&old, new);
+        // Then
+        assert!(result.is_empty());
+    }
+}
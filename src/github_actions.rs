@@ -0,0 +1,146 @@
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+
+/// The result of importing a CSV, reported back to GitHub Actions
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportReport {
+    /// The CSV that was imported
+    pub source: String,
+    pub playlist_name: String,
+    pub playlist_url: String,
+    /// The number of tracks that were added
+    pub added: usize,
+    /// Line numbers in the CSV of rows that could not be added
+    pub failed_lines: Vec<usize>,
+}
+
+/// Whether we're running inside a GitHub Actions workflow
+pub fn is_github_actions() -> bool {
+    env::var("GITHUB_ACTIONS").map(|v| v == "true").unwrap_or(false)
+}
+
+/// Escape a workflow command message, see
+/// https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions
+fn escape_data(value: &str) -> String {
+    value.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+/// Escape a workflow command property, which also can't contain `:` or `,`
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// Format an `::error` annotation that GitHub shows against a line of a file
+///
+/// # Arguments
+///
+/// * `file` - The path to the file, relative to the repository
+/// * `line` - The line number in the file
+/// * `message` - The error to show
+pub fn format_error_annotation(file: &str, line: usize, message: &str) -> String {
+    format!(
+        "::error file={},line={}::{}",
+        escape_property(file), line, escape_data(message)
+    )
+}
+
+/// Format the report as markdown for the job's step summary
+///
+/// # Arguments
+///
+/// * `report` - The result of the import
+pub fn format_step_summary(report: &ImportReport) -> String {
+    let mut summary = format!(
+        "### [{}]({})\n\nAdded {} tracks from `{}`.\n",
+        report.playlist_name, report.playlist_url, report.added, report.source
+    );
+    if !report.failed_lines.is_empty() {
+        let lines: Vec<String> = report.failed_lines.iter().map(|l| l.to_string()).collect();
+        summary += &format!(
+            "\n{} rows have no Spotify track ID (lines {}).\n",
+            report.failed_lines.len(), lines.join(", ")
+        );
+    }
+    summary
+}
+
+/// Format the step outputs that later steps can use
+///
+/// # Arguments
+///
+/// * `report` - The result of the import
+pub fn format_outputs(report: &ImportReport) -> String {
+    format!("added_count={}\nplaylist_url={}\n", report.added, report.playlist_url)
+}
+
+/// Append to the file named by an environment variable, if it's set
+fn append_to_env_file(variable: &str, contents: &str) -> io::Result<()> {
+    if let Ok(path) = env::var(variable) {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(contents.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Report the import to GitHub Actions. Annotations for failed rows are
+/// printed, the summary is added to `GITHUB_STEP_SUMMARY` and outputs are
+/// written to `GITHUB_OUTPUT`.
+///
+/// # Arguments
+///
+/// * `report` - The result of the import
+pub fn write_report(report: &ImportReport) -> io::Result<()> {
+    for line in &report.failed_lines {
+        println!("{}", format_error_annotation(
+            &report.source, *line, "Row has no Spotify track ID"
+        ));
+    }
+    append_to_env_file("GITHUB_STEP_SUMMARY", &format_step_summary(report))?;
+    append_to_env_file("GITHUB_OUTPUT", &format_outputs(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_setup() -> ImportReport {
+        ImportReport {
+            source: "songs.csv".to_string(),
+            playlist_name: "Requests".to_string(),
+            playlist_url: "https://open.spotify.com/playlist/abc".to_string(),
+            added: 3,
+            failed_lines: vec![4, 7],
+        }
+    }
+
+    #[test]
+    fn format_error_annotation_escapes_values() {
+        // When
+        let result = format_error_annotation("data/a,b.csv", 4, "100% bad\nrow");
+        // Then
+        assert_eq!("::error file=data/a%2Cb.csv,line=4::100%25 bad%0Arow", result);
+    }
+
+    #[test]
+    fn format_step_summary_lists_failed_lines() {
+        // When
+        let result = format_step_summary(&test_setup());
+        // Then
+        let expected = "### [Requests](https://open.spotify.com/playlist/abc)\n\n\
+                        Added 3 tracks from `songs.csv`.\n\n\
+                        2 rows have no Spotify track ID (lines 4, 7).\n";
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn format_outputs_includes_count_and_url() {
+        // When
+        let result = format_outputs(&test_setup());
+        // Then
+        assert_eq!(
+            "added_count=3\nplaylist_url=https://open.spotify.com/playlist/abc\n",
+            result
+        );
+    }
+}
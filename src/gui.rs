@@ -0,0 +1,102 @@
+use std::io::{self, BufRead, Write};
+
+use commands::{invoke,CommandError};
+use exit_code;
+use playlist::PlaylistAPI;
+
+extern crate failure;
+
+extern crate serde_json;
+use self::serde_json::Value;
+
+/// A call from the GUI, eg. `{"id":1,"cmd":"import","args":{...}}`. The ID
+/// is sent back with the reply so that calls can be matched to replies
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct InvokeRequest {
+    #[serde(default)]
+    id: Value,
+    cmd: String,
+    #[serde(default)]
+    args: Value,
+}
+
+/// The reply to a call, with either the command's result or its error
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct InvokeReply {
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<CommandError>,
+}
+
+/// Answer a line sent by the GUI
+fn reply_to(api: &PlaylistAPI<failure::Error>, line: &str) -> InvokeReply {
+    let request: InvokeRequest = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return InvokeReply {
+            id: Value::Null,
+            result: None,
+            error: Some(CommandError { message: format!("Invalid call: {}", e), exit_code: exit_code::FAILURE }),
+        },
+    };
+    match invoke(api, &request.cmd, request.args) {
+        Ok(result) => InvokeReply { id: request.id, result: Some(result), error: None },
+        Err(e) => InvokeReply { id: request.id, result: None, error: Some(e) },
+    }
+}
+
+/// Answer calls from a GUI, one JSON object per line, until the input ends.
+/// A Tauri app can run this as a sidecar and pass its `invoke` calls
+/// through, see `commands::invoke`.
+///
+/// # Arguments
+///
+/// * `api` - Where the playlists are
+/// * `input` - Where calls are read from, eg. stdin
+/// * `output` - Where replies are written, eg. stdout
+pub fn serve_invokes<R: BufRead, W: Write>(api: &PlaylistAPI<failure::Error>,
+                                           input: R,
+                                           mut output: W) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = serde_json::to_string(&reply_to(api, &line)).map_err(io::Error::other)?;
+        writeln!(output, "{}", reply)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::MemoryAPI;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn each_call_gets_a_reply_with_its_id() {
+        // Given
+        let sandbox = env::temp_dir().join("playlist-from-csv-gui-test.json");
+        let sandbox = sandbox.to_str().unwrap();
+        let _ = fs::remove_file(sandbox);
+        let api = MemoryAPI::open(sandbox).unwrap();
+        let calls = "{\"id\":1,\"cmd\":\"list_playlists\"}\n\nnot json\n{\"id\":\"b\",\"cmd\":\"rename\"}\n";
+        let mut output = Vec::new();
+        // When
+        serve_invokes(&api, calls.as_bytes(), &mut output).unwrap();
+        // Then
+        let replies: Vec<Value> = String::from_utf8(output).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(3, replies.len());
+        assert_eq!("{\"id\":1,\"result\":[]}", replies[0].to_string());
+        assert!(replies[1]["error"]["message"].as_str().unwrap().starts_with("Invalid call"));
+        assert_eq!(Value::from("b"), replies[2]["id"]);
+        assert_eq!(Value::from(1), replies[2]["error"]["exit_code"]);
+    }
+}
@@ -0,0 +1,202 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use playlist::{AudioFeatures,PlaylistAPI};
+use track_uri::TrackUri;
+
+/// How much a step around the Camelot wheel counts compared to a difference
+/// of one beat per minute. A clash of keys is much easier to hear than a
+/// small change in tempo
+const KEY_STEP_BPM: f64 = 10.0;
+
+/// How tracks should be ordered when they're added
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SortOrder {
+    /// Each track in a key that mixes well with the one before it and with
+    /// a close tempo, as a DJ would play them
+    Harmonic,
+}
+
+/// The error when a sort order isn't known
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownSortOrder {}
+
+impl Error for UnknownSortOrder {
+    fn description(&self) -> &str {
+        "Expected harmonic"
+    }
+}
+
+impl fmt::Display for UnknownSortOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = UnknownSortOrder;
+
+    fn from_str(s: &str) -> Result<SortOrder, UnknownSortOrder> {
+        match s {
+            "harmonic" => Ok(SortOrder::Harmonic),
+            _ => Err(UnknownSortOrder {}),
+        }
+    }
+}
+
+/// A key's position on the Camelot wheel, eg. `8B` for C major. Keys a step
+/// apart on the wheel mix well together
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Camelot {
+    /// From 1 to 12, going round the circle of fifths
+    pub number: u8,
+    /// B for major keys and A for minor keys
+    pub major: bool,
+}
+
+impl Camelot {
+    /// Get the position of a key
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The pitch class, eg. 0 is C
+    /// * `major` - Whether the key is major rather than minor
+    pub fn from_key(key: u8, major: bool) -> Camelot {
+        // Minor keys share a number with their relative major, which is
+        // three semitones up
+        let major_key = if major { key } else { (key + 3) % 12 };
+        // C major is 8B and each fifth up is one more
+        Camelot { number: (major_key * 7 % 12 + 7) % 12 + 1, major: major }
+    }
+
+    /// The number of steps between two keys, where moving to the next
+    /// number or between major and minor is a step
+    pub fn distance(&self, other: &Camelot) -> u8 {
+        let apart = (i16::from(self.number) - i16::from(other.number)).unsigned_abs() as u8;
+        apart.min(12 - apart) + if self.major == other.major { 0 } else { 1 }
+    }
+}
+
+impl fmt::Display for Camelot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.number, if self.major { "B" } else { "A" })
+    }
+}
+
+/// How far apart two tracks sound, in beats per minute
+fn mix_distance(a: &Camelot, a_tempo: f64, b: &Camelot, b_tempo: f64) -> f64 {
+    f64::from(a.distance(b)) * KEY_STEP_BPM + (a_tempo - b_tempo).abs()
+}
+
+/// Order tracks so that each one mixes well with the one before. This
+/// starts from the slowest track and picks the closest track each time.
+/// Tracks without a key come last in the order they were given.
+///
+/// # Arguments
+///
+/// * `tracks` - The tracks to order
+/// * `features` - The features of the tracks, see
+/// `PlaylistAPI::get_audio_features`
+pub fn harmonic_order(tracks: &[TrackUri], features: &[AudioFeatures]) -> Vec<TrackUri> {
+    let mut remaining: Vec<(&TrackUri, Camelot, f64)> = Vec::new();
+    let mut unknown: Vec<TrackUri> = Vec::new();
+    for track in tracks {
+        let found = features.iter().find(|f| f.uri == *track);
+        match found.and_then(|f| f.key.map(|key| (Camelot::from_key(key, f.major), f.tempo))) {
+            Some((camelot, tempo)) => remaining.push((track, camelot, tempo)),
+            None => unknown.push(track.clone()),
+        }
+    }
+    let mut ordered = Vec::new();
+    let mut current: Option<(Camelot, f64)> = None;
+    while !remaining.is_empty() {
+        let next = (0..remaining.len()).min_by(|&a, &b| {
+            let distance = |i: usize| {
+                let (_, camelot, tempo) = remaining[i];
+                match current {
+                    Some((current_camelot, current_tempo)) => {
+                        mix_distance(&current_camelot, current_tempo, &camelot, tempo)
+                    },
+                    None => tempo,
+                }
+            };
+            distance(a).partial_cmp(&distance(b)).unwrap_or(::std::cmp::Ordering::Equal)
+        }).unwrap();
+        let (track, camelot, tempo) = remaining.remove(next);
+        debug!("{} in {} at {:.0} BPM", track, camelot, tempo);
+        current = Some((camelot, tempo));
+        ordered.push(track.clone());
+    }
+    ordered.extend(unknown);
+    ordered
+}
+
+/// Put tracks in a sort order
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance to look up the tracks with
+/// * `tracks` - The tracks to sort
+/// * `order` - How to sort them
+pub fn sort_tracks<E>(playlist_api: &PlaylistAPI<E>,
+                      tracks: &[TrackUri],
+                      order: SortOrder) -> Result<Vec<TrackUri>, E> {
+    match order {
+        SortOrder::Harmonic => {
+            let features = playlist_api.get_audio_features(tracks)?;
+            Ok(harmonic_order(tracks, &features))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features(id: &str, key: Option<u8>, major: bool, tempo: f64) -> AudioFeatures {
+        AudioFeatures {
+            uri: TrackUri::Track(id.to_string()),
+            key: key,
+            major: major,
+            tempo: tempo,
+            energy: 0.5,
+        }
+    }
+
+    #[test]
+    fn camelot_wheel_positions() {
+        // C major, A minor, E major and F♯ minor
+        assert_eq!("8B", Camelot::from_key(0, true).to_string());
+        assert_eq!("8A", Camelot::from_key(9, false).to_string());
+        assert_eq!("12B", Camelot::from_key(4, true).to_string());
+        assert_eq!("11A", Camelot::from_key(6, false).to_string());
+        // 12 is next to 1
+        assert_eq!(1, Camelot { number: 12, major: true }.distance(&Camelot { number: 1, major: true }));
+        assert_eq!(2, Camelot { number: 8, major: true }.distance(&Camelot { number: 9, major: false }));
+    }
+
+    #[test]
+    fn harmonic_order_follows_compatible_keys() {
+        // Given
+        let tracks: Vec<TrackUri> = ["a", "b", "c", "d", "e"].iter()
+            .map(|id| TrackUri::Track(id.to_string()))
+            .collect();
+        let features = vec![
+            // 8B
+            features("a", Some(0), true, 120.0),
+            // 3B, far from the others
+            features("b", Some(1), true, 121.0),
+            // 9B
+            features("c", Some(7), true, 124.0),
+            // 8A, the slowest
+            features("d", Some(9), false, 118.0),
+            features("e", None, true, 120.0),
+        ];
+        // When
+        let ordered = harmonic_order(&tracks, &features);
+        // Then
+        let ids: Vec<&str> = ordered.iter().map(|track| track.id().unwrap()).collect();
+        assert_eq!(vec!["d", "a", "c", "b", "e"], ids);
+    }
+}
@@ -0,0 +1,150 @@
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+use i18n::tr;
+use spotify::LoginError;
+
+extern crate failure;
+
+/// The redirect URI that logging in sends Spotify back to
+pub const REDIRECT_URI: &str = "http://localhost:8888/callback";
+
+static VERBOSE_ERRORS: OnceLock<bool> = OnceLock::new();
+
+/// Set whether errors are shown with everything that caused them, from
+/// `--verbose-errors`. This should be called once at startup, later calls
+/// are ignored
+///
+/// # Arguments
+///
+/// * `verbose` - Whether to show the causes and backtrace of each error
+pub fn set_verbose_errors(verbose: bool) {
+    if VERBOSE_ERRORS.set(verbose).is_err() {
+        warn!("--verbose-errors was already set");
+    }
+}
+
+fn verbose_errors() -> bool {
+    VERBOSE_ERRORS.get().cloned().unwrap_or(false)
+}
+
+/// Get the HTTP status of a failed Spotify request from its message, eg.
+/// `send request failed, http code:401, error message:...`
+fn status_code(message: &str) -> Option<u16> {
+    let start = message.find("http code:")? + "http code:".len();
+    let code: String = message[start..].chars().take_while(|c| c.is_ascii_digit()).collect();
+    code.parse().ok()
+}
+
+/// A hint at how to fix a failed Spotify request, when there's a common fix
+///
+/// # Arguments
+///
+/// * `message` - The error message of the request
+pub fn api_error_hint(message: &str) -> Option<&'static str> {
+    match status_code(message) {
+        Some(401) => Some(
+            "The Spotify token is no longer valid. Delete \
+             .spotify_token_cache.json, or .spotify_token_cache-<profile>.json, \
+             and log in again"
+        ),
+        Some(403) if message.contains("scope") => Some(
+            "The cached token doesn't allow everything this needs, eg. since it \
+             was cached by an older version. Delete .spotify_token_cache.json and \
+             log in again"
+        ),
+        Some(403) => Some(
+            "Spotify refused the request. Playlists can only be changed by their \
+             owner or collaborators, and apps in development mode only work for \
+             users added to them in the Spotify dashboard"
+        ),
+        Some(404) => Some("Spotify couldn't find it. Check the playlist or track ID"),
+        Some(429) => Some(
+            "Spotify is limiting how often requests can be made. Wait a few \
+             minutes, or use --max-requests to make fewer in each run"
+        ),
+        Some(code) if code >= 500 => Some("Spotify couldn't handle the request. Try again later"),
+        Some(_) => None,
+        None if message.contains("connect") || message.contains("dns") => Some(
+            "Couldn't reach Spotify. Check the internet connection and any proxy \
+             settings"
+        ),
+        None => None,
+    }
+}
+
+/// A hint at how to fix a failed login
+///
+/// # Arguments
+///
+/// * `error` - Why logging in failed
+pub fn login_error_hint(error: &LoginError) -> String {
+    match *error {
+        LoginError::AuthenticationFailed(_) => format!(
+            "Check SPOTIFY_CLIENT_ID and SPOTIFY_CLIENT_SECRET. Your client's \
+             redirect URI must include {} — add it in the Spotify dashboard",
+            REDIRECT_URI
+        ),
+        LoginError::LoginRequired(_) => "Run `playlist-from-csv auth` somewhere with a \
+            browser and copy .spotify_token_cache.json here".to_owned(),
+    }
+}
+
+/// Format an error for people, with a hint at how to fix it on the next line
+///
+/// # Arguments
+///
+/// * `message` - What went wrong
+/// * `hint` - How to fix it
+pub fn render_error(message: &str, hint: Option<&str>) -> String {
+    match hint {
+        Some(hint) => format!("{}\n{}", message, tr("error-hint", &[("hint", hint.into())])),
+        None => message.to_owned(),
+    }
+}
+
+/// Format a failed Spotify request with `render_error`. With
+/// `--verbose-errors`, everything that caused it and the backtrace are
+/// included as well
+///
+/// # Arguments
+///
+/// * `context` - What was being done, eg. `Spotify request failed`
+/// * `error` - The error of the request
+pub fn render_api_error(context: &str, error: &failure::Error) -> String {
+    let message = error.to_string();
+    let mut rendered = render_error(&format!("{}: {}", context, message), api_error_hint(&message));
+    if verbose_errors() {
+        for cause in error.causes().skip(1) {
+            write!(rendered, "\nCaused by: {}", cause).unwrap();
+        }
+        // The backtrace is only captured when RUST_BACKTRACE is set
+        let backtrace = error.backtrace().to_string();
+        if !backtrace.is_empty() {
+            write!(rendered, "\n{}", backtrace).unwrap();
+        }
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hints_are_given_for_common_spotify_errors() {
+        // Given
+        let expired = "send request failed, http code:401, error message:{\"error\":\
+                       {\"status\":401,\"message\":\"The access token expired\"}}";
+        let scope = "send request failed, http code:403, error message:Insufficient client scope";
+        let unknown = "send request failed, http code:418, error message:I'm a teapot";
+        // When
+        let rendered = render_error("Spotify request failed: expired", api_error_hint(expired));
+        // Then
+        assert_eq!(Some(401), status_code(expired));
+        assert!(rendered.starts_with("Spotify request failed: expired\nHint: The Spotify token is no longer valid"));
+        assert!(api_error_hint(scope).unwrap().starts_with("The cached token"));
+        assert_eq!(None, api_error_hint(unknown));
+        assert_eq!("Could not log in", render_error("Could not log in", None));
+    }
+}
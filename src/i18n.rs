@@ -0,0 +1,176 @@
+use std::env;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+extern crate fluent;
+use self::fluent::{FluentArgs,FluentResource,FluentValue};
+use self::fluent::concurrent::FluentBundle;
+
+extern crate unic_langid;
+use self::unic_langid::LanguageIdentifier;
+
+/// The languages that messages can be shown in
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Lang {
+    En,
+    Es,
+    De,
+}
+
+impl Lang {
+    /// Pick a language from a locale, eg. `de_DE.UTF-8`. Locales in other
+    /// languages, and `C`, are shown in English
+    ///
+    /// # Arguments
+    ///
+    /// * `locale` - The locale, as it's set in `LANG`
+    pub fn from_locale(locale: &str) -> Lang {
+        let language = locale.split(['_', '-', '.']).next().unwrap_or_default();
+        language.to_lowercase().parse().unwrap_or(Lang::En)
+    }
+
+    /// Pick a language from the environment, the same way `gettext` does
+    pub fn from_env() -> Lang {
+        ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|locale| !locale.is_empty())
+            .map(|locale| Lang::from_locale(&locale))
+            .unwrap_or(Lang::En)
+    }
+
+    fn messages(&self) -> &'static str {
+        match *self {
+            Lang::En => include_str!("../locales/en.ftl"),
+            Lang::Es => include_str!("../locales/es.ftl"),
+            Lang::De => include_str!("../locales/de.ftl"),
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match *self {
+            Lang::En => "en",
+            Lang::Es => "es",
+            Lang::De => "de",
+        }
+    }
+}
+
+impl FromStr for Lang {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Lang, String> {
+        match value {
+            "en" => Ok(Lang::En),
+            "es" => Ok(Lang::Es),
+            "de" => Ok(Lang::De),
+            _ => Err(format!("Expected en, es or de, got {:?}", value)),
+        }
+    }
+}
+
+/// Formats messages in one language, falling back to English for messages
+/// that haven't been translated
+pub struct Localizer {
+    bundle: FluentBundle<FluentResource>,
+    english: Option<FluentBundle<FluentResource>>,
+}
+
+fn bundle(lang: Lang) -> FluentBundle<FluentResource> {
+    let id: LanguageIdentifier = lang.code().parse().expect("Invalid language code");
+    let resource = FluentResource::try_new(lang.messages().to_owned())
+        .unwrap_or_else(|(_, errors)| panic!("Invalid {} messages: {:?}", lang.code(), errors));
+    let mut bundle = FluentBundle::new_concurrent(vec![id]);
+    // Isolation marks only help with right-to-left text and show up in
+    // terminals that don't understand them
+    bundle.set_use_isolating(false);
+    bundle.add_resource(resource).expect("Messages are defined twice");
+    bundle
+}
+
+impl Localizer {
+    pub fn new(lang: Lang) -> Localizer {
+        Localizer {
+            bundle: bundle(lang),
+            english: if lang == Lang::En { None } else { Some(bundle(Lang::En)) },
+        }
+    }
+
+    /// Format a message, see `locales/en.ftl`. The ID is returned when
+    /// there's no such message
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the message, eg. `import-success`
+    /// * `args` - The values to put in the message
+    pub fn format(&self, id: &str, args: &[(&str, FluentValue)]) -> String {
+        let mut fluent_args = FluentArgs::new();
+        for &(name, ref value) in args {
+            fluent_args.set(name, value.clone());
+        }
+        let found = Some(&self.bundle).into_iter()
+            .chain(self.english.as_ref())
+            .filter_map(|bundle| bundle.get_message(id).and_then(|message| message.value()).map(|value| (bundle, value)))
+            .next();
+        match found {
+            Some((bundle, pattern)) => {
+                let mut errors = Vec::new();
+                let message = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+                if !errors.is_empty() {
+                    warn!("Could not format {}: {:?}", id, errors);
+                }
+                message.into_owned()
+            },
+            None => {
+                warn!("No message for {}", id);
+                id.to_owned()
+            },
+        }
+    }
+}
+
+static LOCALIZER: OnceLock<Localizer> = OnceLock::new();
+
+/// Set the language that messages are shown in. This should be called once
+/// at startup, later calls are ignored
+///
+/// # Arguments
+///
+/// * `lang` - The language, eg. from `--lang` or `Lang::from_env`
+pub fn set_lang(lang: Lang) {
+    if LOCALIZER.set(Localizer::new(lang)).is_err() {
+        warn!("The language was already set");
+    }
+}
+
+/// Get a message in the language set with `set_lang`, or English if it
+/// wasn't set
+///
+/// # Arguments
+///
+/// * `id` - The ID of the message, eg. `import-success`
+/// * `args` - The values to put in the message
+pub fn tr(id: &str, args: &[(&str, FluentValue)]) -> String {
+    LOCALIZER.get_or_init(|| Localizer::new(Lang::En)).format(id, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_shown_in_the_chosen_language() {
+        // Given
+        let args = [("count", FluentValue::from(1)), ("id", FluentValue::from("p1"))];
+        // When
+        let english = Localizer::new(Lang::En);
+        let german = Localizer::new(Lang::De);
+        let spanish = Localizer::new(Lang::Es);
+        // Then
+        assert_eq!("Added 1 tracks to playlist p1", english.format("tracks-added", &args));
+        assert_eq!("1 Song zur Playlist p1 hinzugefügt", german.format("tracks-added", &args));
+        assert_eq!("Se añadió 1 canción a la playlist p1", spanish.format("tracks-added", &args));
+        assert_eq!("unknown-message", spanish.format("unknown-message", &[]));
+        assert_eq!(Lang::De, Lang::from_locale("de_DE.UTF-8"));
+        assert_eq!(Lang::En, Lang::from_locale("C"));
+    }
+}
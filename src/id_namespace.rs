@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use csv_to_playlist::{has_track_id,read_column,Song};
+use name_match::{find_track,same_name,search_value};
+use playlist::PlaylistAPI;
+use track_uri::TrackUri;
+
+/// The columns with each song's ISRC, in order of preference
+pub const ISRC_COLUMNS: [&str; 2] = ["isrc (S)", "isrc"];
+
+/// The most tracks to look at when matching a song by its name
+const NAME_SEARCH_LIMIT: u32 = 5;
+
+/// The ID written for songs that couldn't be found on Spotify, which
+/// `has_track_id` reads as there being no ID
+const NO_TRACK_ID: &str = "true";
+
+/// The service that the IDs in a source's `song_id` column come from
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum IdNamespace {
+    /// Spotify IDs, URIs or links, which are added as they are
+    #[default]
+    Spotify,
+    /// International Standard Recording Codes, eg. `USUM71703861`
+    Isrc,
+    /// YouTube video IDs
+    YouTube,
+    /// Apple Music song IDs
+    AppleMusic,
+    /// Paths or URLs of local files, eg. from an M3U playlist. Any that are
+    /// Spotify URIs or links are added as they are
+    File,
+}
+
+impl FromStr for IdNamespace {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<IdNamespace, String> {
+        match value {
+            "spotify" => Ok(IdNamespace::Spotify),
+            "isrc" => Ok(IdNamespace::Isrc),
+            "youtube" => Ok(IdNamespace::YouTube),
+            "apple-music" => Ok(IdNamespace::AppleMusic),
+            "file" => Ok(IdNamespace::File),
+            _ => Err(format!("Expected spotify, isrc, youtube, apple-music or file, got {:?}", value)),
+        }
+    }
+}
+
+impl fmt::Display for IdNamespace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            IdNamespace::Spotify => "spotify",
+            IdNamespace::Isrc => "isrc",
+            IdNamespace::YouTube => "youtube",
+            IdNamespace::AppleMusic => "apple-music",
+            IdNamespace::File => "file",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Read the ISRC of each row of a CSV, see `ISRC_COLUMNS`. Returns `None`
+/// when the CSV has no ISRC column.
+///
+/// # Arguments
+///
+/// * `filename` - The path to the CSV file
+pub fn read_isrc_column(filename: &str) -> Result<Option<Vec<String>>, Box<Error>> {
+    read_column(filename, &ISRC_COLUMNS)
+}
+
+/// Songs whose IDs were looked up on Spotify, see `resolve_song_ids`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedSongs {
+    /// Every song in its original order. Songs that were found have their
+    /// Spotify URI as their ID and the rest have no ID
+    pub songs: Vec<Song>,
+    /// The songs that couldn't be found on Spotify, as they were given
+    pub unresolved: Vec<Song>,
+}
+
+/// Whether an ID is explicitly a Spotify URI or link, rather than an ID that
+/// could be from anywhere
+fn is_spotify_link(song_id: &str) -> bool {
+    let song_id = song_id.trim();
+    (song_id.starts_with("spotify:") || song_id.contains("open.spotify.com/")) &&
+        TrackUri::parse(song_id).is_addable()
+}
+
+/// Find the Spotify track with an ISRC
+fn find_by_isrc<E>(playlist_api: &PlaylistAPI<E>, isrc: &str) -> Result<Option<TrackUri>, E> {
+    let query = format!("isrc:{}", search_value(isrc.trim()));
+    let results = playlist_api.search_tracks(&query, 1)?;
+    Ok(results.into_iter().next().map(|track| track.uri))
+}
+
+/// Find the Spotify track for a song's name. Names like "Artist - Title",
+/// as in DynamoDB exports, have to match both, otherwise only the title has
+/// to match
+fn find_by_name<E>(playlist_api: &PlaylistAPI<E>, music: &str) -> Result<Option<TrackUri>, E> {
+    let mut parts = music.splitn(2, " - ");
+    if let (Some(artist), Some(title)) = (parts.next(), parts.next()) {
+        return Ok(find_track(playlist_api, title, artist)?.map(|track| track.uri));
+    }
+    let query = format!("track:\"{}\"", search_value(music));
+    let results = playlist_api.search_tracks(&query, NAME_SEARCH_LIMIT)?;
+    Ok(results.into_iter().find(|track| same_name(&track.name, music)).map(|track| track.uri))
+}
+
+/// Look up songs whose IDs come from another service on Spotify. Songs are
+/// found by their ISRC when it's known, either as their ID or from
+/// `isrcs`, and otherwise by their name. Each ID is only looked up once.
+/// Songs from Spotify are returned as they are.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance to search with
+/// * `songs` - The songs that were read
+/// * `namespace` - Where the songs' IDs come from
+/// * `isrcs` - The ISRC of each song, by position, if the source has them
+pub fn resolve_song_ids<E>(playlist_api: &PlaylistAPI<E>,
+                           songs: Vec<Song>,
+                           namespace: IdNamespace,
+                           isrcs: &[String]) -> Result<ResolvedSongs, E> {
+    if namespace == IdNamespace::Spotify {
+        return Ok(ResolvedSongs { songs: songs, unresolved: Vec::new() });
+    }
+    let mut found: HashMap<String, Option<TrackUri>> = HashMap::new();
+    let mut resolved = ResolvedSongs { songs: Vec::with_capacity(songs.len()), unresolved: Vec::new() };
+    for (i, song) in songs.into_iter().enumerate() {
+        if !has_track_id(&song) || (namespace == IdNamespace::File && is_spotify_link(&song.song_id)) {
+            resolved.songs.push(song);
+            continue;
+        }
+        let isrc = match namespace {
+            IdNamespace::Isrc => Some(song.song_id.as_str()),
+            _ => isrcs.get(i).map(|isrc| isrc.as_str()).filter(|isrc| !isrc.is_empty()),
+        };
+        let key = format!("{}\n{}", song.song_id, isrc.unwrap_or_default());
+        if !found.contains_key(&key) {
+            let track = match isrc {
+                Some(isrc) => find_by_isrc(playlist_api, isrc)?,
+                None => None,
+            };
+            let track = match track {
+                Some(track) => Some(track),
+                None => find_by_name(playlist_api, &song.music)?,
+            };
+            found.insert(key.clone(), track);
+        }
+        match found[&key] {
+            Some(ref track) => resolved.songs.push(Song { music: song.music, song_id: track.uri() }),
+            None => {
+                resolved.songs.push(Song { music: song.music.clone(), song_id: NO_TRACK_ID.to_owned() });
+                resolved.unresolved.push(song);
+            },
+        }
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_api::{FakeError,FakePlaylistAPI};
+    use playlist::{PlaylistError,TrackMetadata,Visibility};
+
+    use std::cell::RefCell;
+
+    /// A fake API that can find one song by its ISRC and one by its name
+    struct MockPlaylistAPI {
+        search_tracks_called_with: RefCell<Vec<String>>,
+    }
+
+    fn track(id: &str) -> TrackUri {
+        TrackUri::Track(id.to_string())
+    }
+
+    fn metadata(id: &str, name: &str) -> TrackMetadata {
+        TrackMetadata {
+            uri: track(id),
+            name: name.to_string(),
+            artists: vec!["Robyn".to_string()],
+            album: "Honey".to_string(),
+            duration_ms: 0,
+            image_url: None,
+        }
+    }
+
+    impl FakePlaylistAPI for MockPlaylistAPI {
+        #[allow(unused_variables)]
+        fn get_playlist_id(&self, playlist_name: &str) -> Result<String, PlaylistError<FakeError>> {
+            Ok("".to_string())
+        }
+
+        #[allow(unused_variables)]
+        fn create_playlist(&self, playlist_name: &str, visibility: Visibility) -> Result<String, FakeError> {
+            Ok("".to_string())
+        }
+
+        #[allow(unused_variables)]
+        fn search_tracks(&self, query: &str, limit: u32) -> Result<Vec<TrackMetadata>, FakeError> {
+            self.search_tracks_called_with.borrow_mut().push(query.to_owned());
+            Ok(match query {
+                "isrc:SE5BU1800101" => vec![metadata("missingu00000000000000", "Missing U")],
+                "track:\"Honey\" artist:\"Robyn\"" => vec![metadata("remix", "Honey - Remix"), metadata("honey00000000000000000", "Honey")],
+                _ => Vec::new(),
+            })
+        }
+    }
+
+    fn song(music: &str, song_id: &str) -> Song {
+        Song { music: music.to_string(), song_id: song_id.to_string() }
+    }
+
+    #[test]
+    fn youtube_ids_are_found_by_isrc_or_name() {
+        // Given
+        let api = MockPlaylistAPI { search_tracks_called_with: RefCell::new(Vec::new()) };
+        let songs = vec![
+            song("Robyn - Missing U", "yt_missing"),
+            song("Robyn - Honey", "yt_honey"),
+            song("Robyn - Honey", "yt_honey"),
+            song("Robyn - Ever Again", "yt_ever"),
+            song("Robyn - Send To Robin Immediately", "true"),
+        ];
+        let isrcs = vec!["SE5BU1800101".to_string()];
+        // When
+        let spotify = resolve_song_ids(&api, songs.clone(), IdNamespace::Spotify, &isrcs).unwrap();
+        let resolved = resolve_song_ids(&api, songs.clone(), IdNamespace::YouTube, &isrcs).unwrap();
+        // Then
+        assert_eq!(songs, spotify.songs);
+        assert_eq!(vec![
+            song("Robyn - Missing U", "spotify:track:missingu00000000000000"),
+            song("Robyn - Honey", "spotify:track:honey00000000000000000"),
+            song("Robyn - Honey", "spotify:track:honey00000000000000000"),
+            song("Robyn - Ever Again", "true"),
+            song("Robyn - Send To Robin Immediately", "true"),
+        ], resolved.songs);
+        assert_eq!(vec![song("Robyn - Ever Again", "yt_ever")], resolved.unresolved);
+        // Each ID is only looked up once
+        assert_eq!(vec![
+            "isrc:SE5BU1800101".to_string(),
+            "track:\"Honey\" artist:\"Robyn\"".to_string(),
+            "track:\"Ever Again\" artist:\"Robyn\"".to_string(),
+        ], *api.search_tracks_called_with.borrow());
+        assert_eq!(Ok(IdNamespace::AppleMusic), "apple-music".parse());
+    }
+
+    #[test]
+    fn local_files_are_found_by_name() {
+        // Given
+        let api = MockPlaylistAPI { search_tracks_called_with: RefCell::new(Vec::new()) };
+        let songs = vec![
+            song("Robyn - Honey", "/music/Robyn/Honey.flac"),
+            song("Robyn - Missing U", "https://open.spotify.com/track/missingu00000000000000"),
+        ];
+        // When
+        let resolved = resolve_song_ids(&api, songs, IdNamespace::File, &[]).unwrap();
+        // Then
+        assert_eq!(vec![
+            song("Robyn - Honey", "spotify:track:honey00000000000000000"),
+            song("Robyn - Missing U", "https://open.spotify.com/track/missingu00000000000000"),
+        ], resolved.songs);
+        assert_eq!(
+            vec!["track:\"Honey\" artist:\"Robyn\"".to_string()],
+            *api.search_tracks_called_with.borrow()
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Get the directory where a job keeps its state between runs, eg. its
+/// ledger. Every job has its own directory so that jobs can't read or
+/// overwrite each other's state. Job names are checked when the config is
+/// validated so that they're safe to use as a directory name.
+///
+/// # Arguments
+///
+/// * `state_dir` - The directory that holds the state of every job
+/// * `job_name` - The name of the job
+pub fn job_state_dir(state_dir: &Path, job_name: &str) -> PathBuf {
+    state_dir.join(job_name)
+}
+
+/// Delete the state of a single job. Returns whether there was any state to
+/// delete.
+///
+/// # Arguments
+///
+/// * `state_dir` - The directory that holds the state of every job
+/// * `job_name` - The name of the job
+pub fn clean_job(state_dir: &Path, job_name: &str) -> io::Result<bool> {
+    match fs::remove_dir_all(job_state_dir(state_dir, job_name)) {
+        Ok(()) => Ok(true),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Delete the state of every job, including jobs that have since been
+/// removed from the config. Returns the names of the jobs whose state was
+/// deleted, sorted by name.
+///
+/// # Arguments
+///
+/// * `state_dir` - The directory that holds the state of every job
+pub fn clean_all(state_dir: &Path) -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(state_dir) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut cleaned = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        fs::remove_dir_all(entry.path())?;
+        cleaned.push(entry.file_name().to_string_lossy().into_owned());
+    }
+    cleaned.sort();
+    Ok(cleaned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs::File;
+
+    fn test_setup(name: &str) -> PathBuf {
+        let state_dir = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&state_dir);
+        for job in &["archive", "requests"] {
+            let dir = job_state_dir(&state_dir, job);
+            fs::create_dir_all(&dir).unwrap();
+            File::create(dir.join("ledger.json")).unwrap();
+        }
+        state_dir
+    }
+
+    #[test]
+    fn clean_job_only_removes_that_job() {
+        // Given
+        let state_dir = test_setup("playlist-from-csv-clean-job-test");
+        // When
+        let removed = clean_job(&state_dir, "archive").unwrap();
+        let removed_again = clean_job(&state_dir, "archive").unwrap();
+        // Then
+        assert!(removed);
+        assert!(!removed_again);
+        assert!(job_state_dir(&state_dir, "requests").join("ledger.json").exists());
+        fs::remove_dir_all(&state_dir).unwrap();
+    }
+
+    #[test]
+    fn clean_all_removes_every_job() {
+        // Given
+        let state_dir = test_setup("playlist-from-csv-clean-all-test");
+        // When
+        let cleaned = clean_all(&state_dir).unwrap();
+        // Then
+        assert_eq!(vec!["archive".to_string(), "requests".to_string()], cleaned);
+        assert_eq!(0, fs::read_dir(&state_dir).unwrap().count());
+        fs::remove_dir_all(&state_dir).unwrap();
+    }
+}
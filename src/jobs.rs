@@ -0,0 +1,252 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use config::{Job,Mode};
+use csv_to_playlist::{add_songs_returning_tracks,AddOptions,CsvColumns,PlaylistAddError,Song};
+use dedupe::{get_family_tracks,remove_family_tracks};
+use drip::{DripQueue,DripRate};
+use git_source::read_songs_added_since;
+use id_namespace::{read_isrc_column,resolve_song_ids,IdNamespace};
+use job_state::job_state_dir;
+use ledger::Ledger;
+use listenbrainz::{get_listens_since,listens_to_songs,newest_listen,read_cursor,write_cursor};
+use playlist::{get_playlist_id_create_if_needed,PlaylistAPI,PlaylistError};
+use source::{InputFormat,SongFile,SongSource};
+use sync::{sync_playlist,ChangeLimits,Edit,SyncError,TooManyChanges};
+use track_uri::TrackUri;
+
+extern crate chrono;
+use self::chrono::Utc;
+
+/// The name of the file in a job's state directory that keeps its ledger
+const LEDGER_FILENAME: &str = "ledger.json";
+
+/// The name of the file in a job's state directory that keeps the songs
+/// waiting to be released with `drip`
+const DRIP_QUEUE_FILENAME: &str = "drip.json";
+
+/// The name of the file in a job's state directory that keeps the time of
+/// the last listen imported from ListenBrainz
+const LISTENBRAINZ_CURSOR_FILENAME: &str = "listenbrainz_cursor";
+
+/// Job error enum for the different ways a configured job can fail
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobError<E> {
+    /// The error when the CSV couldn't be read
+    ReadError(String),
+    /// Generic playlist error from API
+    PlaylistError(PlaylistError<E>),
+    /// The error when a sync would change more than the job allows
+    TooManyChanges(TooManyChanges),
+    /// The error when the job's state couldn't be read or saved
+    StateError(String),
+}
+
+impl<E: fmt::Display> fmt::Display for JobError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JobError::ReadError(ref e) => write!(f, "Could not read songs: {}", e),
+            JobError::PlaylistError(PlaylistError::APIError(ref e)) => write!(f, "{}", e),
+            JobError::PlaylistError(PlaylistError::PlaylistNotFound(ref e)) => write!(f, "{}", e),
+            JobError::PlaylistError(PlaylistError::NotAuthorizedToModify(ref e)) => write!(f, "{}", e),
+            JobError::PlaylistError(PlaylistError::PlaylistAlreadyExists(ref e)) => write!(f, "{}", e),
+            JobError::PlaylistError(PlaylistError::PlaylistFull(ref e)) => write!(f, "{}", e),
+            JobError::TooManyChanges(ref e) => write!(f, "{}", e),
+            JobError::StateError(ref e) => write!(f, "Could not use job state: {}", e),
+        }
+    }
+}
+
+/// What a job changed in its playlist
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobChanges {
+    pub playlist_id: String,
+    /// The number of changes made to the playlist
+    pub changes: usize,
+    /// The tracks that were added, in the order they were added
+    pub added: Vec<TrackUri>,
+}
+
+/// Read the songs for a job from its CSV or Git repository
+fn read_job_songs(job: &Job) -> Result<Vec<Song>, String> {
+    let columns = CsvColumns::new(job.name_column.as_deref(), job.id_column.as_deref());
+    match (job.git_repo.as_deref(), job.file.as_deref(), job.csv.as_deref()) {
+        (Some(repo), Some(file), _) => {
+            let since_ref = job.since_ref.as_deref().unwrap_or("HEAD~1");
+            read_songs_added_since(repo, file, since_ref, &columns)
+                .map(|added| added.into_iter().map(|(_, song)| song).collect())
+                .map_err(|e| e.to_string())
+        },
+        (_, _, Some(csv)) => SongFile::new(csv, None, columns).read_songs().map_err(|e| e.to_string()),
+        // Validating the config makes sure there's a source
+        _ => Err("No csv or git_repo".to_owned()),
+    }
+}
+
+/// Look up the songs on Spotify when the job's IDs come from another
+/// service, see `resolve_song_ids`. ISRCs are read from the job's CSV
+fn resolve_job_songs<E>(playlist_api: &PlaylistAPI<E>,
+                        job: &Job,
+                        songs: Vec<Song>) -> Result<Vec<Song>, JobError<E>> {
+    // The config is validated so `id_namespace` always parses
+    let namespace: IdNamespace = match job.csv.as_deref() {
+        Some(csv) if job.git_repo.is_none() && InputFormat::from_filename(csv) == InputFormat::M3u => {
+            IdNamespace::File
+        },
+        _ => job.id_namespace.as_ref()
+            .and_then(|namespace| namespace.parse().ok())
+            .unwrap_or_default(),
+    };
+    if namespace == IdNamespace::Spotify {
+        return Ok(songs);
+    }
+    let isrcs = match (job.csv.as_deref(), job.git_repo.is_none()) {
+        (Some(csv), true) if InputFormat::from_filename(csv) == InputFormat::Csv => {
+            read_isrc_column(csv).map_err(|e| JobError::ReadError(e.to_string()))?
+        },
+        _ => None,
+    };
+    resolve_song_ids(playlist_api, songs, namespace, isrcs.as_deref().unwrap_or_default())
+        .map(|resolved| resolved.songs)
+        .map_err(|e| JobError::PlaylistError(PlaylistError::APIError(e)))
+}
+
+/// Read the songs listened to since the job last ran. Returns the songs
+/// along with the time of the newest listen, which should be saved with
+/// `write_cursor` once they've been imported
+fn read_listened_songs<E>(job: &Job, user: &str,
+                          cursor_filename: &str) -> Result<(Vec<Song>, Option<i64>), JobError<E>> {
+    let cursor = read_cursor(cursor_filename).map_err(|e| JobError::StateError(e.to_string()))?;
+    let since = cursor.or(job.listened_since).unwrap_or(0);
+    let listens = get_listens_since(user, since).map_err(|e| JobError::ReadError(e.to_string()))?;
+    Ok((listens_to_songs(&listens), newest_listen(&listens)))
+}
+
+/// Import songs into the playlist. With `never_readd` the job's ledger is
+/// used to skip songs it has imported before, and is saved once the import
+/// succeeds. With `drip` the songs are queued and only those that the rate
+/// allows are added, see `DripQueue`
+fn import_songs<E>(playlist_api: &PlaylistAPI<E>,
+                   playlist_id: &str,
+                   job: &Job,
+                   state_dir: &Path,
+                   mut songs: Vec<Song>) -> Result<Vec<TrackUri>, JobError<E>> {
+    let job_dir = job_state_dir(state_dir, &job.name);
+    let ledger_filename = job_dir.join(LEDGER_FILENAME).to_string_lossy().into_owned();
+    let state_error = |e: &fmt::Display| JobError::StateError(e.to_string());
+    let ledger = if job.never_readd {
+        let mut ledger = Ledger::read(&ledger_filename).map_err(|e| state_error(&e))?;
+        songs = ledger.remove_imported_songs(&job.playlist, songs).0;
+        // Every song is in the playlist once the import succeeds
+        ledger.record(&job.playlist, &songs);
+        Some(ledger)
+    } else {
+        None
+    };
+    let drip_filename = job_dir.join(DRIP_QUEUE_FILENAME).to_string_lossy().into_owned();
+    // The config is validated so `drip` always parses
+    let drip = job.drip.as_ref().and_then(|drip| drip.parse::<DripRate>().ok());
+    let queue = match drip {
+        Some(rate) => {
+            let mut queue = DripQueue::read(&drip_filename).map_err(|e| state_error(&*e))?;
+            let in_playlist = playlist_api.get_track_ids_in_playlist(playlist_id)
+                .map_err(|e| JobError::PlaylistError(PlaylistError::APIError(e)))?;
+            queue.enqueue(songs, &in_playlist);
+            songs = queue.release(rate, Utc::now().timestamp());
+            if !queue.is_empty() {
+                info!("{}: {} songs are waiting to be released at {}", job.name, queue.len(), rate);
+            }
+            Some(queue)
+        },
+        None => None,
+    };
+    let added = match add_songs_returning_tracks(playlist_api, playlist_id, songs, &AddOptions::default()) {
+        Ok(added) => added.added,
+        Err(PlaylistAddError::NoNewTracks(_)) => Vec::new(),
+        Err(PlaylistAddError::APIError(e)) => {
+            return Err(JobError::PlaylistError(PlaylistError::APIError(e)));
+        },
+        Err(PlaylistAddError::PlaylistFull(e)) => {
+            return Err(JobError::PlaylistError(PlaylistError::PlaylistFull(e)));
+        },
+        Err(PlaylistAddError::ReadError(e)) => return Err(JobError::ReadError(e)),
+    };
+    if let Some(ledger) = ledger {
+        fs::create_dir_all(&job_dir).map_err(|e| state_error(&e))?;
+        ledger.write(&ledger_filename).map_err(|e| state_error(&e))?;
+    }
+    // Only saved once the released songs are in the playlist, so that they
+    // stay queued if adding them fails
+    if let Some(queue) = queue {
+        fs::create_dir_all(&job_dir).map_err(|e| state_error(&e))?;
+        queue.write(&drip_filename).map_err(|e| state_error(&*e))?;
+    }
+    Ok(added)
+}
+
+/// Run a job from the config once. Returns what was changed in the
+/// playlist.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the playlist should be updated
+/// * `job` - The job to run
+/// * `state_dir` - The directory that holds the state of every job, see
+/// `job_state_dir`
+pub fn run_job<E>(playlist_api: &PlaylistAPI<E>,
+                  job: &Job,
+                  state_dir: &Path) -> Result<JobChanges, JobError<E>> {
+    let job_dir = job_state_dir(state_dir, &job.name);
+    let cursor_filename = job_dir.join(LISTENBRAINZ_CURSOR_FILENAME).to_string_lossy().into_owned();
+    let (mut songs, cursor) = match job.listenbrainz_user {
+        Some(ref user) => read_listened_songs(job, user, &cursor_filename)?,
+        None => {
+            let songs = read_job_songs(job).map_err(JobError::ReadError)?;
+            (resolve_job_songs(playlist_api, job, songs)?, None)
+        },
+    };
+    if !job.dedupe_against.is_empty() {
+        let family_tracks = get_family_tracks(playlist_api, &job.dedupe_against)
+            .map_err(|e| JobError::PlaylistError(PlaylistError::APIError(e)))?;
+        songs = remove_family_tracks(songs, &family_tracks).0;
+    }
+    let playlist_id = get_playlist_id_create_if_needed(playlist_api, &job.playlist)
+        .map_err(JobError::PlaylistError)?;
+    match job.mode {
+        Mode::Import => {
+            let added = import_songs(playlist_api, &playlist_id, job, state_dir, songs)?;
+            // Only move the cursor once the listens are in the playlist
+            if let Some(cursor) = cursor {
+                let state_error = |e: &fmt::Display| JobError::StateError(e.to_string());
+                fs::create_dir_all(&job_dir).map_err(|e| state_error(&e))?;
+                write_cursor(&cursor_filename, cursor).map_err(|e| state_error(&*e))?;
+            }
+            Ok(JobChanges { playlist_id: playlist_id, changes: added.len(), added: added })
+        },
+        Mode::Sync => {
+            let quarantine_id = match job.quarantine {
+                Some(ref name) => Some(
+                    get_playlist_id_create_if_needed(playlist_api, name)
+                        .map_err(JobError::PlaylistError)?
+                ),
+                None => None,
+            };
+            let limits = ChangeLimits {
+                max_removals: job.max_removals,
+                max_changes_percent: job.max_changes_percent,
+            };
+            let edits = sync_playlist(
+                playlist_api, &playlist_id, &songs, quarantine_id.as_deref(), &limits, false
+            ).map_err(|e| match e {
+                SyncError::APIError(e) => JobError::PlaylistError(PlaylistError::APIError(e)),
+                SyncError::TooManyChanges(e) => JobError::TooManyChanges(e),
+            })?;
+            let added = edits.iter().filter_map(|edit| match *edit {
+                Edit::Insert { ref track, .. } => Some(track.clone()),
+                _ => None,
+            }).collect();
+            Ok(JobChanges { playlist_id: playlist_id, changes: edits.len(), added: added })
+        },
+    }
+}
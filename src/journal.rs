@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::sync::{Mutex, MutexGuard};
+
+use playlist::{AudioFeatures,PlaylistAPI,PlaylistDetails,PlaylistError,PlaylistInfo,TrackAvailability,TrackMetadata,TrackVersion,Visibility};
+use track_uri::TrackUri;
+
+extern crate serde_json;
+
+/// The most tracks added in each checkpointed batch. This is the most that
+/// Spotify adds in one request
+const BATCH_SIZE: usize = 100;
+
+/// The tracks in each playlist as of the last change that was made, keyed
+/// by playlist ID
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct JournalState {
+    playlists: BTreeMap<String, Vec<TrackUri>>,
+}
+
+/// A playlist provider that keeps a journal of the tracks in each playlist
+/// it changes, saved after every batch of tracks that's added. When a run
+/// fails part way, eg. from a network error, running it again reads the
+/// playlist from the journal instead of fetching all of it, so the batches
+/// that were already added are skipped as duplicates. The journal is only
+/// used while the playlist's size still matches it.
+pub struct JournaledAPI<'a, E: 'a> {
+    api: &'a PlaylistAPI<E>,
+    filename: String,
+    state: Mutex<JournalState>,
+}
+
+impl<'a, E> JournaledAPI<'a, E> {
+    /// Wrap a provider, carrying on from the journal saved in the file. A
+    /// missing file is treated as an empty journal
+    ///
+    /// # Arguments
+    ///
+    /// * `api` - The provider that changes are made with
+    /// * `filename` - The path to the journal file
+    pub fn open(api: &'a PlaylistAPI<E>, filename: &str) -> Result<JournaledAPI<'a, E>, Box<Error>> {
+        let state = match File::open(filename) {
+            Ok(file) => serde_json::from_reader(file)?,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => JournalState::default(),
+            Err(e) => return Err(Box::new(e)),
+        };
+        Ok(JournaledAPI { api: api, filename: filename.to_owned(), state: Mutex::new(state) })
+    }
+
+    /// Remove the journal once the run has finished, so that the next run
+    /// starts from the playlists as they are
+    pub fn finish(&self) -> Result<(), Box<Error>> {
+        match fs::remove_file(&self.filename) {
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, JournalState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Save the journal. A journal that can't be saved only means that a
+    /// failed run has to fetch the playlist again, so the run carries on
+    fn save(&self, state: &JournalState) {
+        let result = File::create(&self.filename)
+            .map_err(|e| e.to_string())
+            .and_then(|file| serde_json::to_writer(file, state).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("Could not save the journal {}: {}", self.filename, e);
+        }
+    }
+
+    /// Record tracks that were added to a playlist
+    fn record(&self, playlist_id: &str, tracks: &[TrackUri], position: Option<usize>) {
+        let mut state = self.lock();
+        if let Some(journaled) = state.playlists.get_mut(playlist_id) {
+            let addable = tracks.iter().filter(|track| track.is_addable()).cloned();
+            let index = position.unwrap_or(journaled.len()).min(journaled.len());
+            let after = journaled.split_off(index);
+            journaled.extend(addable);
+            journaled.extend(after);
+        }
+        self.save(&state);
+    }
+
+    /// Stop using the journal for a playlist after a change it doesn't
+    /// follow, so the playlist is fetched again
+    fn forget(&self, playlist_id: &str) {
+        let mut state = self.lock();
+        if state.playlists.remove(playlist_id).is_some() {
+            self.save(&state);
+        }
+    }
+
+}
+
+impl<'a, E> PlaylistAPI<E> for JournaledAPI<'a, E> {
+    fn get_playlist_id(&self, playlist_name: &str) -> Result<String, PlaylistError<E>> {
+        self.api.get_playlist_id(playlist_name)
+    }
+
+    fn create_playlist(&self, playlist_name: &str, visibility: Visibility) -> Result<String, E> {
+        self.api.create_playlist(playlist_name, visibility)
+    }
+
+    fn add_tracks_to_playlist(&self, playlist_id: &str, tracks: &[TrackUri], position: Option<usize>) -> Result<(), E> {
+        // Each batch is recorded once it's added, so that a failure part way
+        // leaves the journal with the batches that made it
+        for (i, batch) in tracks.chunks(BATCH_SIZE).enumerate() {
+            let batch_position = position.map(|position| position + i * BATCH_SIZE);
+            self.api.add_tracks_to_playlist(playlist_id, batch, batch_position)?;
+            self.record(playlist_id, batch, batch_position);
+        }
+        Ok(())
+    }
+
+    fn get_track_ids_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackUri>, E> {
+        let journaled = self.lock().playlists.get(playlist_id).cloned();
+        if let Some(journaled) = journaled {
+            // Checking the size is a single request, unlike fetching a large
+            // playlist
+            if self.api.get_playlist_size(playlist_id)? == journaled.len() {
+                info!("Carrying on from the journal of playlist {}", playlist_id);
+                return Ok(journaled);
+            }
+            info!("Playlist {} has changed since the journal was saved", playlist_id);
+        }
+        let tracks = self.api.get_track_ids_in_playlist(playlist_id)?;
+        let mut state = self.lock();
+        state.playlists.insert(playlist_id.to_owned(), tracks.clone());
+        self.save(&state);
+        Ok(tracks)
+    }
+
+    fn get_tracks_with_metadata_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackMetadata>, E> {
+        self.api.get_tracks_with_metadata_in_playlist(playlist_id)
+    }
+
+    fn get_playlist_snapshot_id(&self, playlist_id: &str) -> Result<String, E> {
+        self.api.get_playlist_snapshot_id(playlist_id)
+    }
+
+    fn get_playlist_size(&self, playlist_id: &str) -> Result<usize, E> {
+        self.api.get_playlist_size(playlist_id)
+    }
+
+    fn get_playlist_details(&self, playlist_id: &str) -> Result<PlaylistDetails, E> {
+        self.api.get_playlist_details(playlist_id)
+    }
+
+    fn get_cover_image(&self, playlist_id: &str) -> Result<Option<Vec<u8>>, E> {
+        self.api.get_cover_image(playlist_id)
+    }
+
+    fn get_tracks_metadata(&self, tracks: &[TrackUri]) -> Result<Vec<TrackMetadata>, E> {
+        self.api.get_tracks_metadata(tracks)
+    }
+
+    fn update_playlist_details(&self, playlist_id: &str, details: &PlaylistDetails) -> Result<(), E> {
+        self.api.update_playlist_details(playlist_id, details)
+    }
+
+    fn set_playlist_visibility(&self, playlist_id: &str, visibility: Visibility) -> Result<(), E> {
+        self.api.set_playlist_visibility(playlist_id, visibility)
+    }
+
+    fn upload_cover_image(&self, playlist_id: &str, jpeg: &[u8]) -> Result<(), E> {
+        self.api.upload_cover_image(playlist_id, jpeg)
+    }
+
+    fn can_modify_playlist(&self, playlist_id: &str) -> Result<bool, E> {
+        self.api.can_modify_playlist(playlist_id)
+    }
+
+    fn rename_playlist(&self, playlist_id: &str, name: &str) -> Result<(), E> {
+        self.api.rename_playlist(playlist_id, name)
+    }
+
+    fn unfollow_playlist(&self, playlist_id: &str) -> Result<(), E> {
+        self.forget(playlist_id);
+        self.api.unfollow_playlist(playlist_id)
+    }
+
+    fn remove_tracks_from_playlist(&self, playlist_id: &str, tracks: &[TrackUri]) -> Result<(), E> {
+        self.forget(playlist_id);
+        self.api.remove_tracks_from_playlist(playlist_id, tracks)
+    }
+
+    fn insert_tracks_into_playlist(&self, playlist_id: &str, tracks: &[TrackUri], position: usize) -> Result<(), E> {
+        for (i, batch) in tracks.chunks(BATCH_SIZE).enumerate() {
+            let batch_position = position + i * BATCH_SIZE;
+            self.api.insert_tracks_into_playlist(playlist_id, batch, batch_position)?;
+            self.record(playlist_id, batch, Some(batch_position));
+        }
+        Ok(())
+    }
+
+    fn remove_tracks_at_positions(&self, playlist_id: &str, occurrences: &[(TrackUri, usize)]) -> Result<(), E> {
+        self.forget(playlist_id);
+        self.api.remove_tracks_at_positions(playlist_id, occurrences)
+    }
+
+    fn move_track_in_playlist(&self, playlist_id: &str, from: usize, insert_before: usize) -> Result<(), E> {
+        self.forget(playlist_id);
+        self.api.move_track_in_playlist(playlist_id, from, insert_before)
+    }
+
+    fn list_playlists(&self) -> Result<Vec<PlaylistInfo>, E> {
+        self.api.list_playlists()
+    }
+
+    fn get_track_versions(&self, tracks: &[TrackUri]) -> Result<Vec<TrackVersion>, E> {
+        self.api.get_track_versions(tracks)
+    }
+
+    fn search_tracks(&self, query: &str, limit: u32) -> Result<Vec<TrackMetadata>, E> {
+        self.api.search_tracks(query, limit)
+    }
+
+    fn get_audio_features(&self, tracks: &[TrackUri]) -> Result<Vec<AudioFeatures>, E> {
+        self.api.get_audio_features(tracks)
+    }
+
+    fn get_track_availability(&self, tracks: &[TrackUri], market: &str) -> Result<Vec<TrackAvailability>, E> {
+        self.api.get_track_availability(tracks, market)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::MemoryAPI;
+    use std::env;
+
+    fn tracks(ids: &[&str]) -> Vec<TrackUri> {
+        ids.iter().map(|id| TrackUri::Track(format!("{:0<22}", id))).collect()
+    }
+
+    #[test]
+    fn journal_is_used_while_the_playlist_size_matches() {
+        // Given
+        let dir = env::temp_dir();
+        let sandbox = dir.join("playlist-from-csv-journal-sandbox.json");
+        let _ = fs::remove_file(&sandbox);
+        let journal_file = dir.join("playlist-from-csv-journal-test.json");
+        let journal_filename = journal_file.to_str().unwrap();
+        let _ = fs::remove_file(journal_filename);
+        let api = MemoryAPI::open(sandbox.to_str().unwrap()).unwrap();
+        let id = api.create_playlist("Mix", Visibility::Private).unwrap();
+        api.add_tracks_to_playlist(&id, &tracks(&["a"]), None).unwrap();
+        // When
+        let first_run = JournaledAPI::open(&api, journal_filename).unwrap();
+        first_run.get_track_ids_in_playlist(&id).unwrap();
+        first_run.add_tracks_to_playlist(&id, &tracks(&["b", "c"]), Some(0)).unwrap();
+        let resumed = JournaledAPI::open(&api, journal_filename).unwrap();
+        let journaled = resumed.get_track_ids_in_playlist(&id).unwrap();
+        // Changed outside the journal, so it's fetched again
+        api.add_tracks_to_playlist(&id, &tracks(&["d"]), None).unwrap();
+        let changed = resumed.get_track_ids_in_playlist(&id).unwrap();
+        resumed.finish().unwrap();
+        // Then
+        assert_eq!(tracks(&["b", "c", "a"]), journaled);
+        assert_eq!(tracks(&["b", "c", "a", "d"]), changed);
+        assert!(!journal_file.exists());
+    }
+}
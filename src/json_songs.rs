@@ -0,0 +1,113 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+
+use csv_to_playlist::{CsvColumns,Song,STDIN_FILENAME};
+use dynamodb::attribute_name;
+
+extern crate serde_json;
+use self::serde_json::{Map,Value};
+
+/// The error when a song in a JSON array isn't an object
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidSong {
+    /// The position of the song, where the first song is 1
+    pub song: usize,
+}
+
+impl Error for InvalidSong {
+    fn description(&self) -> &str {
+        "Expected each song to be an object like {\"music\": \"...\", \"song_id\": \"...\"}"
+    }
+}
+
+impl fmt::Display for InvalidSong {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, song {} isn't", self.description(), self.song)
+    }
+}
+
+/// Read a field as a string. A missing or null field is empty, so that the
+/// song is counted as having no ID
+fn read_field(song: &Map<String, Value>, name: &str) -> String {
+    match song.get(name) {
+        Some(Value::String(value)) => value.to_owned(),
+        Some(Value::Null) | None => String::new(),
+        Some(value) => value.to_string(),
+    }
+}
+
+/// Parse a JSON array of songs, eg. `[{"music": "...", "song_id": "..."}]`,
+/// which is easier to write from a script than a CSV. Songs are read from
+/// the fields named like the attributes the columns were exported from, see
+/// `attribute_name`, so `music` and `song_id` by default. Other fields are
+/// ignored.
+///
+/// # Arguments
+///
+/// * `reader` - Where the JSON is read from
+/// * `columns` - The columns to read each song from
+pub fn parse_json_songs<R: Read>(reader: R, columns: &CsvColumns) -> Result<Vec<Song>, Box<Error>> {
+    let name = attribute_name(&columns.name);
+    let id = attribute_name(&columns.id);
+    let songs: Vec<Value> = serde_json::from_reader(reader)?;
+    songs.iter()
+        .enumerate()
+        .map(|(i, song)| match *song {
+            Value::Object(ref song) => Ok(Song {
+                music: read_field(song, name),
+                song_id: read_field(song, id),
+            }),
+            _ => Err(From::from(InvalidSong { song: i + 1 })),
+        })
+        .collect()
+}
+
+/// Parse a file with a JSON array of songs, see `parse_json_songs`
+///
+/// # Arguments
+///
+/// * `filename` - The path to the JSON file, or `-` to read from stdin
+/// * `columns` - The columns to read each song from
+pub fn parse_json_songs_file(filename: &str, columns: &CsvColumns) -> Result<Vec<Song>, Box<Error>> {
+    if filename == STDIN_FILENAME {
+        let stdin = io::stdin();
+        return parse_json_songs(stdin.lock(), columns);
+    }
+    parse_json_songs(File::open(filename)?, columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_songs_are_read_from_an_array() {
+        // Given
+        let data = r#"[
+            {"music": "Robyn - Dancing On My Own", "song_id": "abc", "rating": 5},
+            {"music": "Robyn - Hang With Me", "song_id": null},
+            {"title": "Robyn - Honey", "uri": "spotify:track:def"}
+        ]"#;
+        let columns = CsvColumns::new(Some("title"), Some("uri"));
+        // When
+        let songs = parse_json_songs(data.as_bytes(), &CsvColumns::default()).unwrap();
+        let renamed = parse_json_songs(data.as_bytes(), &columns).unwrap();
+        let invalid = parse_json_songs("[\"abc\"]".as_bytes(), &columns);
+        let not_array = parse_json_songs("{\"music\": \"abc\"}".as_bytes(), &columns);
+        // Then
+        assert_eq!(vec![
+            Song { music: "Robyn - Dancing On My Own".to_owned(), song_id: "abc".to_owned() },
+            Song { music: "Robyn - Hang With Me".to_owned(), song_id: String::new() },
+            Song { music: String::new(), song_id: String::new() },
+        ], songs);
+        assert_eq!(Song { music: "Robyn - Honey".to_owned(), song_id: "spotify:track:def".to_owned() }, renamed[2]);
+        assert_eq!(
+            "Expected each song to be an object like {\"music\": \"...\", \"song_id\": \"...\"}, song 1 isn't",
+            invalid.unwrap_err().to_string()
+        );
+        assert!(not_array.is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::error::Error;
+use std::fmt;
+use std::future;
+
+use aws::runtime;
+use csv_to_playlist::{add_songs_to_playlist,CsvColumns,PlaylistAddError,Song};
+use dynamodb::{attribute_name,read_attribute};
+use hints::render_api_error;
+use playlist::{get_playlist_id_create_if_needed,PlaylistAPI};
+use source::SongSource;
+
+extern crate failure;
+
+extern crate lambda_runtime;
+use self::lambda_runtime::{service_fn,LambdaEvent};
+
+extern crate serde_json;
+use self::serde_json::{Map,Value};
+
+/// The error when a stream record of an inserted item doesn't have the item
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingNewImage {}
+
+impl Error for MissingNewImage {
+    fn description(&self) -> &str {
+        "Expected each INSERT record to have a NewImage. Set the stream's view \
+        type to NEW_IMAGE or NEW_AND_OLD_IMAGES"
+    }
+}
+
+impl fmt::Display for MissingNewImage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+/// The songs inserted into a table, from a DynamoDB Streams event as Lambda
+/// receives it. Only INSERT records are read, since modified and removed
+/// items were already imported when they were inserted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamEvent {
+    /// The event, eg. `{"Records": [{"eventName": "INSERT", ...}]}`
+    pub event: Value,
+    /// The columns to read each song from, see `attribute_name`
+    pub columns: CsvColumns,
+}
+
+impl SongSource for StreamEvent {
+    fn read_songs(self) -> Result<Vec<Song>, Box<Error>> {
+        let name = attribute_name(&self.columns.name);
+        let id = attribute_name(&self.columns.id);
+        let records = self.event.get("Records")
+            .and_then(|records| records.as_array())
+            .map(|records| records.as_slice())
+            .unwrap_or_default();
+        records.iter()
+            .filter(|record| record.get("eventName").and_then(|name| name.as_str()) == Some("INSERT"))
+            .map(|record| {
+                let item = record.pointer("/dynamodb/NewImage")
+                    .and_then(|item| item.as_object())
+                    .ok_or(MissingNewImage {})?;
+                Ok(Song { music: read_attribute(item, name), song_id: read_attribute(item, id) })
+            })
+            .collect()
+    }
+}
+
+/// Add the songs inserted into a DynamoDB table to a playlist, creating it
+/// if needed. Returns the response to the event, eg. `{"added": 2}`, or why
+/// it failed so that Lambda retries it.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance to add the songs with
+/// * `playlist_name` - The name of the playlist
+/// * `columns` - The columns to read each song from, see `attribute_name`
+/// * `event` - The DynamoDB Streams event, see `StreamEvent`
+pub fn handle_stream_event(playlist_api: &PlaylistAPI<failure::Error>,
+                           playlist_name: &str,
+                           columns: &CsvColumns,
+                           event: Value) -> Result<Value, String> {
+    let playlist_id = get_playlist_id_create_if_needed(playlist_api, playlist_name)
+        .map_err(|e| e.to_string())?;
+    let stream = StreamEvent { event: event, columns: columns.clone() };
+    let added = match add_songs_to_playlist(playlist_api, &playlist_id, stream) {
+        Ok(added) => added,
+        Err(PlaylistAddError::NoNewTracks(_)) => 0,
+        Err(PlaylistAddError::APIError(e)) => return Err(render_api_error("Spotify request failed", &e)),
+        Err(PlaylistAddError::ReadError(e)) => return Err(e),
+        Err(PlaylistAddError::PlaylistFull(e)) => return Err(e.to_string()),
+    };
+    let mut response = Map::new();
+    response.insert("added".to_owned(), Value::from(added));
+    Ok(Value::Object(response))
+}
+
+/// Run as an AWS Lambda function, handling each event that Lambda sends
+/// until the runtime stops. Events are handled one at a time, so the
+/// handler can block.
+///
+/// # Arguments
+///
+/// * `handle` - Handles an event, returning the response or why it failed
+pub fn run_lambda<F>(handle: F) -> Result<(), Box<Error>>
+        where F: Fn(Value) -> Result<Value, String> {
+    let runtime = runtime()?;
+    let handler = service_fn(|event: LambdaEvent<Value>| {
+        future::ready(handle(event.payload).map_err(lambda_runtime::Error::from))
+    });
+    runtime.block_on(lambda_runtime::run(handler)).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_inserted_songs_are_read_from_stream_events() {
+        // Given
+        let record = |event: &str, id: &str| format!(
+            "{{\"eventName\":\"{}\",\"dynamodb\":{{\"NewImage\":\
+             {{\"music\":{{\"S\":\"Song {}\"}},\"song_id\":{{\"S\":\"{}\"}}}}}}}}",
+            event, id, id
+        );
+        let event = format!("{{\"Records\":[{},{}]}}", record("INSERT", "abc"), record("MODIFY", "def"));
+        let keys_only = "{\"Records\":[{\"eventName\":\"INSERT\",\"dynamodb\":{\"Keys\":{}}}]}";
+        let stream = |json: &str| StreamEvent {
+            event: serde_json::from_str(json).unwrap(),
+            columns: CsvColumns::default(),
+        };
+        // When
+        let songs = stream(&event).read_songs().unwrap();
+        let missing = stream(keys_only).read_songs();
+        // Then
+        assert_eq!(vec![Song { music: "Song abc".to_owned(), song_id: "abc".to_owned() }], songs);
+        assert!(missing.unwrap_err().to_string().starts_with("Expected each INSERT record to have a NewImage"));
+    }
+}
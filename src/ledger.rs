@@ -0,0 +1,121 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::ErrorKind;
+
+use csv_to_playlist::{has_track_id,Song};
+use track_uri::TrackUri;
+
+extern crate serde_json;
+
+/// Every track that has been imported into each playlist. This is kept
+/// between runs so that tracks can be skipped if they were removed from the
+/// playlist by hand afterwards.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Ledger {
+    /// The tracks imported into each playlist, keyed by playlist name
+    playlists: BTreeMap<String, BTreeSet<TrackUri>>,
+}
+
+impl Ledger {
+    /// Read a ledger that was saved using `write`. A missing file is treated
+    /// as an empty ledger so that the first run doesn't need one.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the ledger file
+    pub fn read(filename: &str) -> Result<Ledger, Box<Error>> {
+        match File::open(filename) {
+            Ok(file) => Ok(serde_json::from_reader(file)?),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Ledger::default()),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    /// Save the ledger as JSON
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the ledger file
+    pub fn write(&self, filename: &str) -> Result<(), Box<Error>> {
+        let file = File::create(filename)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Record that songs have been imported into a playlist. Songs without
+    /// an ID that can be added are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_name` - The playlist the songs were imported into
+    /// * `songs` - The songs that are now in the playlist
+    pub fn record(&mut self, playlist_name: &str, songs: &[Song]) {
+        let tracks = songs.iter()
+            .filter(|song| has_track_id(song))
+            .map(|song| TrackUri::parse(&song.song_id))
+            .filter(|track| track.is_addable());
+        self.playlists.entry(playlist_name.to_owned())
+            .or_default()
+            .extend(tracks);
+    }
+
+    /// Split the songs into those that have never been imported into the
+    /// playlist and those that have, keeping the order of the CSV.
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_name` - The playlist the songs will be imported into
+    /// * `songs` - The songs to be imported
+    pub fn remove_imported_songs(&self, playlist_name: &str,
+                                 songs: Vec<Song>) -> (Vec<Song>, Vec<Song>) {
+        match self.playlists.get(playlist_name) {
+            Some(tracks) => songs.into_iter()
+                .partition(|song| !tracks.contains(&TrackUri::parse(&song.song_id))),
+            None => (songs, Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(id: &str) -> Song {
+        Song { music: id.to_string(), song_id: id.to_string() }
+    }
+
+    #[test]
+    fn remove_imported_songs_skips_tracks_in_the_ledger() {
+        // Given
+        let mut ledger = Ledger::default();
+        ledger.record("March", &[song("ida0000000000000000000"), song("idb0000000000000000000")]);
+        ledger.record("April", &[song("idc0000000000000000000")]);
+        let songs = vec![song("ida0000000000000000000"), song("idc0000000000000000000"), song("spotify:track:idb0000000000000000000")];
+        // When
+        let (kept, skipped) = ledger.remove_imported_songs("March", songs.clone());
+        // Then
+        assert_eq!(vec![song("idc0000000000000000000")], kept);
+        assert_eq!(vec![song("ida0000000000000000000"), song("spotify:track:idb0000000000000000000")], skipped);
+        // Playlists that have never been imported into keep every song
+        assert_eq!((songs.clone(), Vec::new()), ledger.remove_imported_songs("May", songs));
+    }
+
+    #[test]
+    fn ledger_round_trips_through_json() {
+        // Given
+        let mut ledger = Ledger::default();
+        ledger.record("March", &[song("idb0000000000000000000"), song("spotify:episode:ide0000000000000000000")]);
+        // Songs without a usable ID aren't recorded
+        ledger.record("March", &[song("ida0000000000000000000"), song("true"), song("spotify:local:a:b:c:1")]);
+        // When
+        let json = serde_json::to_string(&ledger).unwrap();
+        // Then
+        assert_eq!(
+            r#"{"playlists":{"March":["spotify:track:ida0000000000000000000","spotify:track:idb0000000000000000000","spotify:episode:ide0000000000000000000"]}}"#,
+            json
+        );
+        let parsed: Ledger = serde_json::from_str(&json).unwrap();
+        assert_eq!(ledger, parsed);
+    }
+}
@@ -0,0 +1,76 @@
+/// Get the track ID from a Spotify link or URI. Chat apps such as Slack may
+/// wrap links in angle brackets, eg. `<https://open.spotify.com/track/ID>`
+///
+/// # Arguments
+///
+/// * `text` - The link or URI
+pub fn parse_track_id(text: &str) -> Option<String> {
+    let text = text.trim().trim_start_matches('<').trim_end_matches('>');
+    // Remove the link label if there is one
+    let link = text.split('|').next().unwrap_or("");
+    let id = if let Some(id) = link.strip_prefix("spotify:track:") {
+        id
+    } else {
+        let path = link.split('?').next().unwrap_or("");
+        let mut parts = path.rsplitn(2, "/track/");
+        let id = parts.next().unwrap_or("");
+        match parts.next() {
+            Some(host) if host.ends_with("open.spotify.com") => id,
+            _ => return None,
+        }
+    };
+    if !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Some(id.to_owned())
+    } else {
+        None
+    }
+}
+
+/// Find all of the Spotify track links or URIs within a message, ignoring
+/// duplicates
+///
+/// # Arguments
+///
+/// * `text` - The message text
+pub fn find_track_ids(text: &str) -> Vec<String> {
+    let mut track_ids: Vec<String> = Vec::new();
+    for id in text.split_whitespace().filter_map(parse_track_id) {
+        if !track_ids.contains(&id) {
+            track_ids.push(id);
+        }
+    }
+    track_ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_track_id_handles_links_and_uris() {
+        let expected = Some("6rqhFgbbKwnb9MLmUQDhG6".to_string());
+        assert_eq!(expected, parse_track_id("spotify:track:6rqhFgbbKwnb9MLmUQDhG6"));
+        assert_eq!(expected, parse_track_id("https://open.spotify.com/track/6rqhFgbbKwnb9MLmUQDhG6"));
+        assert_eq!(expected, parse_track_id("<https://open.spotify.com/track/6rqhFgbbKwnb9MLmUQDhG6?si=abc>"));
+        assert_eq!(expected, parse_track_id("<https://open.spotify.com/track/6rqhFgbbKwnb9MLmUQDhG6|song> "));
+    }
+
+    #[test]
+    fn parse_track_id_rejects_other_links() {
+        assert_eq!(None, parse_track_id("https://open.spotify.com/album/6rqhFgbbKwnb9MLmUQDhG6"));
+        assert_eq!(None, parse_track_id("https://example.com/track/6rqhFgbbKwnb9MLmUQDhG6"));
+        assert_eq!(None, parse_track_id("spotify:track:../me"));
+        assert_eq!(None, parse_track_id(""));
+    }
+
+    #[test]
+    fn find_track_ids_in_message() {
+        // Given
+        let text = "check these out https://open.spotify.com/track/abc123?si=x\n\
+                    spotify:track:def456 and https://open.spotify.com/track/abc123";
+        // When
+        let result = find_track_ids(text);
+        // Then
+        assert_eq!(vec!["abc123".to_string(), "def456".to_string()], result);
+    }
+}
@@ -0,0 +1,178 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+
+use csv_to_playlist::Song;
+use user_agent;
+
+const API_URL: &str = "https://api.listenbrainz.org/1";
+
+/// The number of listens to ask for in each request
+const LISTENS_PER_PAGE: usize = 100;
+
+/// The wrapper around a page of listens, see
+/// https://listenbrainz.readthedocs.io/en/latest/users/api/core.html
+#[derive(Deserialize)]
+struct ListensResponse {
+    payload: ListensPayload,
+}
+
+#[derive(Deserialize)]
+struct ListensPayload {
+    listens: Vec<Listen>,
+}
+
+/// A track that a user listened to
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Listen {
+    /// When the track was listened to, in seconds since the Unix epoch
+    pub listened_at: i64,
+    track_metadata: ListenMetadata,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct ListenMetadata {
+    artist_name: String,
+    track_name: String,
+    #[serde(default)]
+    additional_info: AdditionalInfo,
+}
+
+/// Details added by the player that submitted the listen. Listens from
+/// Spotify link to the track
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+struct AdditionalInfo {
+    spotify_id: Option<String>,
+    origin_url: Option<String>,
+}
+
+impl Listen {
+    /// Get the Spotify link for the track, if the player included one
+    fn spotify_link(&self) -> Option<&str> {
+        let info = &self.track_metadata.additional_info;
+        info.spotify_id.as_deref()
+            .or(info.origin_url.as_deref())
+            .filter(|link| link.contains("open.spotify.com/"))
+    }
+}
+
+/// Get every listen after a time, oldest first. Only a page of listens is
+/// requested at a time so that a long gap between runs still works.
+///
+/// # Arguments
+///
+/// * `user` - The ListenBrainz user name
+/// * `since` - Only listens after this time are returned, in seconds since
+/// the Unix epoch
+pub fn get_listens_since(user: &str, since: i64) -> Result<Vec<Listen>, Box<Error>> {
+    let client = user_agent::client();
+    let url = format!("{}/user/{}/listens", API_URL, user);
+    let mut listens: Vec<Listen> = Vec::new();
+    let mut min_ts = since;
+    loop {
+        let response: ListensResponse = client.get(&url)
+            .query(&[("min_ts", min_ts.to_string()), ("count", LISTENS_PER_PAGE.to_string())])
+            .send()?
+            .error_for_status()?
+            .json()?;
+        let page = response.payload.listens;
+        debug!("Fetched {} listens for {} after {}", page.len(), user, min_ts);
+        let newest = match newest_listen(&page) {
+            Some(newest) => newest,
+            None => break,
+        };
+        let is_last_page = page.len() < LISTENS_PER_PAGE;
+        listens.extend(page);
+        if is_last_page {
+            break;
+        }
+        min_ts = newest;
+    }
+    listens.sort_by_key(|listen| listen.listened_at);
+    Ok(listens)
+}
+
+/// Get the time of the most recent listen, which is where the next run
+/// should continue from
+pub fn newest_listen(listens: &[Listen]) -> Option<i64> {
+    listens.iter().map(|listen| listen.listened_at).max()
+}
+
+/// Turn listens into songs that can be imported. Listens without a Spotify
+/// link are kept without a track ID so that they're reported as missing.
+///
+/// # Arguments
+///
+/// * `listens` - The listens in the order they should be added
+pub fn listens_to_songs(listens: &[Listen]) -> Vec<Song> {
+    listens.iter().map(|listen| Song {
+        music: format!("{} - {}", listen.track_metadata.artist_name, listen.track_metadata.track_name),
+        // "true" is how a missing ID is written in the CSVs
+        song_id: listen.spotify_link().unwrap_or("true").to_owned(),
+    }).collect()
+}
+
+/// Read the time of the last listen that was imported. A missing file means
+/// nothing has been imported yet.
+///
+/// # Arguments
+///
+/// * `filename` - The path to the cursor file
+pub fn read_cursor(filename: &str) -> Result<Option<i64>, Box<Error>> {
+    let mut data = String::new();
+    match File::open(filename) {
+        Ok(mut file) => file.read_to_string(&mut data)?,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Box::new(e)),
+    };
+    Ok(Some(data.trim().parse()?))
+}
+
+/// Save the time of the last listen that was imported
+///
+/// # Arguments
+///
+/// * `filename` - The path to the cursor file
+/// * `cursor` - The time of the last listen, in seconds since the Unix epoch
+pub fn write_cursor(filename: &str, cursor: i64) -> Result<(), Box<Error>> {
+    writeln!(File::create(filename)?, "{}", cursor)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate serde_json;
+
+    #[test]
+    fn listens_to_songs_uses_spotify_links() {
+        // Given
+        let response: ListensResponse = serde_json::from_str(r#"{"payload": {"count": 3, "listens": [
+            {"listened_at": 1520000300, "track_metadata": {
+                "artist_name": "Robyn", "track_name": "Dancing On My Own",
+                "additional_info": {"spotify_id": "https://open.spotify.com/track/2mShgu2gxkzAPlmoe5Go7o"}
+            }},
+            {"listened_at": 1520000200, "track_metadata": {
+                "artist_name": "Robyn", "track_name": "Hang With Me",
+                "additional_info": {"origin_url": "https://www.youtube.com/watch?v=x"}
+            }},
+            {"listened_at": 1520000100, "track_metadata": {
+                "artist_name": "Robyn", "track_name": "Indestructible"
+            }}
+        ]}}"#).unwrap();
+        let listens = response.payload.listens;
+        // When
+        let songs = listens_to_songs(&listens);
+        // Then
+        assert_eq!(vec![
+            Song {
+                music: "Robyn - Dancing On My Own".to_string(),
+                song_id: "https://open.spotify.com/track/2mShgu2gxkzAPlmoe5Go7o".to_string(),
+            },
+            Song { music: "Robyn - Hang With Me".to_string(), song_id: "true".to_string() },
+            Song { music: "Robyn - Indestructible".to_string(), song_id: "true".to_string() },
+        ], songs);
+        assert_eq!(Some(1520000300), newest_listen(&listens));
+    }
+}
@@ -0,0 +1,108 @@
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+
+use csv_to_playlist::{Song,STDIN_FILENAME};
+use encoding::{decode,Encoding};
+
+/// The directive with the length and title of the entry that follows it
+const EXTINF: &str = "#EXTINF:";
+
+/// Get the title from an `#EXTINF` line, eg. `Robyn - Honey` from
+/// `#EXTINF:301 tvg-name="Honey, live",Robyn - Honey`. The title is after
+/// the first comma that isn't in a quoted attribute
+fn extinf_title(info: &str) -> Option<&str> {
+    let mut quoted = false;
+    for (i, c) in info.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => return Some(info[i + 1..].trim()).filter(|title| !title.is_empty()),
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Get a name for an entry without a title from its file name, eg.
+/// `Robyn - Honey` from `C:\Music\Robyn - Honey.mp3`
+fn name_from_path(path: &str) -> &str {
+    let file = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    match file.rfind('.') {
+        Some(dot) if dot > 0 => &file[..dot],
+        _ => file,
+    }
+}
+
+/// Parse an M3U or M3U8 playlist, as saved by local players like VLC or
+/// foobar2000. Each entry becomes a song named with its `#EXTINF` title, or
+/// its file name when it has none, and with its path or URL as its ID.
+/// Local files aren't on Spotify, so the songs are found by their names
+/// with `IdNamespace::File`. Other directives and comments are ignored.
+///
+/// # Arguments
+///
+/// * `reader` - Where the playlist is read from
+pub fn parse_m3u<R: Read>(reader: R) -> Result<Vec<Song>, Box<Error>> {
+    // M3U8 is always UTF-8, and a byte order mark is the only way to tell
+    // that an M3U isn't
+    let reader = BufReader::new(decode(reader, Some(Encoding::Utf8))?);
+    let mut songs = Vec::new();
+    let mut title: Option<String> = None;
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if let Some(info) = line.strip_prefix(EXTINF) {
+            title = extinf_title(info).map(|title| title.to_owned());
+        } else if !line.is_empty() && !line.starts_with('#') {
+            songs.push(Song {
+                music: title.take().unwrap_or_else(|| name_from_path(line).to_owned()),
+                song_id: line.to_owned(),
+            });
+        }
+    }
+    Ok(songs)
+}
+
+/// Parse an M3U or M3U8 playlist file, see `parse_m3u`
+///
+/// # Arguments
+///
+/// * `filename` - The path to the playlist, or `-` to read from stdin
+pub fn parse_m3u_file(filename: &str) -> Result<Vec<Song>, Box<Error>> {
+    if filename == STDIN_FILENAME {
+        let stdin = io::stdin();
+        return parse_m3u(stdin.lock());
+    }
+    parse_m3u(File::open(filename)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(music: &str, song_id: &str) -> Song {
+        Song { music: music.to_string(), song_id: song_id.to_string() }
+    }
+
+    #[test]
+    fn m3u_entries_are_named_by_their_titles() {
+        // Given
+        let playlist = "\u{FEFF}#EXTM3U\n\
+            #EXTINF:301,Robyn - Honey\n\
+            /music/Robyn/Honey/01 Honey.flac\n\
+            \n\
+            # A comment\n\
+            C:\\Music\\Robyn - Missing U.mp3\n\
+            #EXTINF:-1 tvg-name=\"Ever Again, live\",Robyn - Ever Again\r\n\
+            https://open.spotify.com/track/ever\r\n";
+        // When
+        let songs = parse_m3u(playlist.as_bytes()).unwrap();
+        // Then
+        assert_eq!(vec![
+            song("Robyn - Honey", "/music/Robyn/Honey/01 Honey.flac"),
+            song("Robyn - Missing U", "C:\\Music\\Robyn - Missing U.mp3"),
+            song("Robyn - Ever Again", "https://open.spotify.com/track/ever"),
+        ], songs);
+    }
+}
@@ -1,84 +1,2625 @@
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
+extern crate log;
+extern crate env_logger;
+use log::{Level,LevelFilter};
 
 mod csv_to_playlist;
-use csv_to_playlist::{parse_csv_file,add_songs_to_playlist,PlaylistAddError};
+use csv_to_playlist::{parse_csv_file_with_progress,add_songs_streaming,stream_csv_file,AddOptions,CsvColumns,add_songs_to_playlist,add_songs_returning_tracks,dry_run_add_songs,filter_duplicates,get_lines_without_track_id,DryRun,Position,get_unique_track_ids,limit_tracks,has_track_id,ParsedCsv,PlaylistAddError,Song,STDIN_FILENAME};
 
 mod playlist;
-use playlist::{get_playlist_id_create_if_needed};
+use playlist::{check_can_modify,check_create_policy,format_playlist_table,get_playlist_id_create_if_needed,get_playlist_id_with_policy,CreatePolicy,PlaylistAPI,PlaylistDetails,PlaylistError,Visibility};
+
+#[cfg(test)]
+mod fake_api;
 
 mod spotify;
-use spotify::SpotifyAPI;
+use spotify::{refresh_token_if_needed,AuthStrategy,DeadlineExceeded,RequestBudgetExhausted,SpotifyAPI};
+
+mod description;
+use description::update_playlist_description;
+
+mod diff;
+use diff::{format_diff,format_removal_diff,format_track};
+
+mod confirm;
+use confirm::confirm;
+
+mod merge;
+use merge::{merge_sources,Priority};
+
+mod plan;
+use plan::{create_plan,apply_plan,write_plan,read_plan,parse_plan,ApplyError,PlanOptions};
+
+mod remove;
+use remove::remove_songs_from_playlist;
+
+mod duplicates;
+use duplicates::{parse_allow_dup_column,DuplicatePolicy,ALLOW_DUP_COLUMNS};
+
+mod encoding;
+
+mod quiet_hours;
+
+mod drip;
+use drip::DripRate;
+
+mod replace;
+use replace::{parse_mapping_file,replace_tracks};
+
+mod relink;
+use relink::{find_unavailable_tracks,get_relink_mapping,Equivalent,Market};
+
+mod sync;
+use sync::{compute_edits,get_sync_target,sync_playlist,ChangeLimits,Edit,SyncError};
+
+mod multi_import;
+use multi_import::{import_playlists,read_playlists,route_songs,RoutedSongs,PLAYLIST_COLUMN};
+
+mod ledger;
+use ledger::Ledger;
+
+mod dedupe;
+use dedupe::{get_family_tracks,remove_family_tracks};
+
+mod signature;
+use signature::{sign_file,read_verified_file,signature_filename};
+
+mod rate_limit;
+
+mod deadline;
+use deadline::RunTime;
+
+mod git_source;
+use git_source::read_songs_added_since;
+
+mod github_actions;
+use github_actions::{is_github_actions,write_report,ImportReport};
+
+mod links;
+
+mod export;
+use export::{song_from_metadata,write_csv,write_csv_with_notes};
+
+mod name_match;
+use name_match::find_track;
+
+mod station;
+use station::{JsonFeed,NowPlayingPoller,PlayedSong,RadioParadise,StationSource};
+
+mod bandcamp;
+use bandcamp::{get_collection,match_collection,read_collection};
+
+mod setlist;
+use setlist::{get_setlist,match_setlist,setlist_id_from_url};
+
+mod charts;
+use charts::{get_chart,match_chart,Chart};
+
+mod genius;
+use genius::annotate_tracks;
+
+mod scheduler;
+use scheduler::{parse_cron,RunSchedule,StopSignal};
+
+mod harmonic;
+
+mod progress;
+use progress::{NoProgress,Progress,ProgressBars};
+
+mod spread;
+
+mod versions;
+
+mod timing;
+use timing::{format_breakdown,Stopwatch};
+
+mod capacity;
+use capacity::{part_name,spill_tracks,OnFull,Parts,SpilledPart};
+
+mod segment;
+use segment::{segment_playlist_name,segment_tracks,Feature,Segment};
+
+mod checksum;
+use checksum::{file_sha256,verify_sha256};
+
+mod audit;
+use audit::{build_artifact,AuditInput,AuditPlaylist};
+
+mod render;
+use render::Format;
+
+mod preview;
+use preview::build_preview;
+
+mod report;
+use report::RunReport;
+
+mod snapshot;
+use snapshot::{take_snapshot,Snapshot};
+
+mod order;
+use order::{restore_order,take_order_snapshot,OrderSnapshot};
+
+mod config;
+use config::{read_config,Config,Diagnostic,Job,Mode,Profile};
+
+mod listenbrainz;
+use listenbrainz::get_listens_since;
+
+mod jobs;
+use jobs::{run_job,JobChanges};
+
+mod feed;
+use feed::{Feed,FeedEntry};
+
+mod job_state;
+use job_state::{clean_all,clean_job};
+
+mod daemon;
+use daemon::{run_in_order,ConfigWatcher,JobOutcome,Schedule};
+
+#[cfg(unix)]
+mod control;
+#[cfg(unix)]
+use control::{listen,ControlCommand,ControlRequest};
+
+mod exit_code;
+use exit_code::{add_error_code,playlist_error_code};
+
+mod commands;
+use commands::{list_playlists,CommandError};
+
+#[cfg(feature = "gui")]
+mod gui;
+#[cfg(feature = "gui")]
+use gui::serve_invokes;
+
+mod cli;
+use cli::{build_cli,innermost_matches};
+
+mod track_uri;
+use track_uri::TrackUri;
+
+mod slack;
+
+mod server;
+
+mod telegram;
+use telegram::run_bot;
+
+mod user_agent;
+use user_agent::{build_user_agent,set_user_agent};
+
+mod memory;
+use memory::MemoryAPI;
+
+mod cover;
+use cover::read_cover_image;
+
+mod added_at;
+use added_at::{read_added_at,sort_by_added_at,ADDED_AT_COLUMNS};
+
+mod notes;
+use notes::{notes_by_track,NoteIndex,NOTE_COLUMNS};
+
+mod rotation;
+use rotation::{find_rotation_target,rotation_name,RotationPolicy};
+
+mod journal;
+use journal::JournaledAPI;
+
+mod recap;
+use recap::{parse_played_at,plays_from_listens,read_plays,recap_playlist_name,top_tracks,Period};
+
+mod clone;
+use clone::{check_parity,clone_playlist,ClonedPlaylist};
+
+mod source;
+use source::{InputFormat,SongFile,SongSource};
+
+mod aws;
+use aws::AwsOptions;
+
+mod s3;
+use s3::{download_object,parse_s3_url};
+
+mod download;
+use download::{download_url,is_http_url,DownloadLimits};
+
+mod dynamodb;
+use dynamodb::DynamoDbTable;
+
+mod json_songs;
+
+mod m3u;
+
+mod id_namespace;
+use id_namespace::{resolve_song_ids,IdNamespace,ISRC_COLUMNS};
+
+#[cfg(feature = "lambda")]
+mod lambda;
+#[cfg(feature = "lambda")]
+use lambda::{handle_stream_event,run_lambda};
+
+mod i18n;
+use i18n::{set_lang,tr,Lang};
+
+mod events;
+use events::{emit,set_event_format,Event};
+
+mod hints;
+use hints::{login_error_hint,render_api_error,render_error,set_verbose_errors,REDIRECT_URI};
+
+mod spec;
+use spec::{build_playlist,read_spec,BuildError,PlaylistSpec,SourceCache};
+use server::{run_server,ServerConfig,ContributeConfig,SlackConfig};
+
+use std::collections::{BTreeMap,HashMap,HashSet};
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::fmt::Display;
+use std::io::{self, stdout, Write};
+use std::mem;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
 
 extern crate rspotify;
 use rspotify::spotify::oauth2::SpotifyOAuth;
 
-extern crate argparse;
-use argparse::{ArgumentParser, Store};
-
-fn update_playlist_from_csv(client_id: &str, client_secret: &str,
-                            username: &str, playlist_name: &str,
-                            csv_filename: &str) {
-    // Set up Spotify OAuth
-    let mut oauth = SpotifyOAuth::default()
-        .scope("playlist-read-private playlist-modify-private")
-        .client_id(&client_id)
-        .client_secret(&client_secret)
-        .redirect_uri("http://localhost:8888/callback")
-        .build();
+extern crate clap;
+use clap::{ArgMatches, Shell};
+
+extern crate serde_json;
+
+extern crate failure;
+
+extern crate chrono;
+use chrono::{Local,NaiveDate,NaiveDateTime,Utc};
+
+/// Get a value given on the command line, exiting if it's invalid
+fn parse_arg<T>(matches: &ArgMatches, name: &str) -> Option<T>
+        where T: FromStr, T::Err: Display {
+    matches.value_of(name).map(|value| value.parse().unwrap_or_else(|e| {
+        eprintln!("Invalid value for {}: {}", name, e);
+        process::exit(exit_code::FAILURE);
+    }))
+}
+
+/// The cover image given with --cover, exiting if Spotify won't accept it
+fn cover_from_args(matches: &ArgMatches) -> Option<Vec<u8>> {
+    matches.value_of("cover").map(|filename| read_cover_image(filename).unwrap_or_else(|e| {
+        eprintln!("Invalid cover image {}: {}", filename, e);
+        process::exit(exit_code::FAILURE);
+    }))
+}
+
+/// Set the description and cover image given with --description and --cover
+fn update_details(spotify: &PlaylistAPI<failure::Error>, playlist_id: &str,
+                  description: Option<&str>, cover: Option<&[u8]>) {
+    if let Some(description) = description {
+        let details = PlaylistDetails { description: Some(description.to_owned()) };
+        unwrap_api_result(spotify.update_playlist_details(playlist_id, &details));
+        println!("Updated description: {}", description);
+    }
+    if let Some(cover) = cover {
+        unwrap_api_result(spotify.upload_cover_image(playlist_id, cover));
+        println!("Updated cover image.");
+    }
+}
+
+/// The visibility of a playlist that gets created, from --public
+fn visibility_from_args(matches: &ArgMatches) -> Visibility {
+    if matches.is_present("public") {
+        Visibility::Public
+    } else {
+        Visibility::Private
+    }
+}
+
+/// Whether the playlist can be created or already exist, from --no-create
+/// and --create-only
+fn create_policy_from_args(matches: &ArgMatches) -> CreatePolicy {
+    if matches.is_present("no_create") {
+        CreatePolicy::NoCreate
+    } else if matches.is_present("create_only") {
+        CreatePolicy::CreateOnly
+    } else {
+        CreatePolicy::CreateIfNeeded
+    }
+}
+
+/// Get every value of an option that can be given more than once
+fn arg_values(matches: &ArgMatches, name: &str) -> Vec<String> {
+    matches.values_of(name)
+        .map(|values| values.map(|value| value.to_owned()).collect())
+        .unwrap_or_default()
+}
+
+/// Stop unless the file has the SHA-256 given with --expect-sha256
+fn verify_expected_sha256(matches: &ArgMatches, filename: &str) {
+    if let Some(expected) = matches.value_of("expect_sha256") {
+        if filename == STDIN_FILENAME {
+            eprintln!("--expect-sha256 needs a file, not stdin");
+            process::exit(exit_code::FAILURE);
+        }
+        verify_sha256(filename, expected).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(exit_code::FAILURE);
+        });
+    }
+}
+
+/// The details needed to log in, from `--profile` and the command line
+struct LoginDetails {
+    client_id: String,
+    client_secret: String,
+    /// Only needed by subcommands that log in as a user, see `username`
+    username: Option<String>,
+    /// The profile's playlist, see `Profile::playlist`
+    playlist: Option<String>,
+    profile: Option<String>,
+}
+
+/// Read the profile named by `--profile` from the config file
+fn profile_from_args(matches: &ArgMatches) -> Option<(String, Profile)> {
+    let name = matches.value_of("profile")?;
+    let filename = matches.value_of("config_file").unwrap();
+    let config = read_config(filename).unwrap_or_else(|diagnostics| {
+        print_diagnostics(filename, &diagnostics);
+        process::exit(exit_code::FAILURE);
+    });
+    let profile = config.profiles.get(name).cloned().unwrap_or_else(|| {
+        eprintln!("There's no profile named {} in {}", name, filename);
+        process::exit(exit_code::FAILURE);
+    });
+    Some((name.to_owned(), profile))
+}
+
+/// Get the details to log in with. Values from the command line and the
+/// environment are used over a profile's, which fill in anything they leave
+/// out
+fn login_details(matches: &ArgMatches) -> LoginDetails {
+    let (name, profile) = match profile_from_args(matches) {
+        Some((name, profile)) => (Some(name), profile),
+        None => (None, Profile::default()),
+    };
+    let value = |from_profile: Option<String>, arg: &str| {
+        matches.value_of(arg).map(|value| value.to_owned()).or(from_profile)
+    };
+    let client_id = value(profile.client_id, "client_id");
+    let client_secret = value(profile.client_secret, "client_secret");
+    LoginDetails {
+        client_id: required_login_value(client_id, "client_id", &name),
+        client_secret: required_login_value(client_secret, "client_secret", &name),
+        username: value(profile.username, "username"),
+        playlist: profile.playlist,
+        profile: name,
+    }
+}
+
+/// Exit if a value that's needed to log in wasn't given. The parser checks
+/// this when there's no profile or provider, so a missing value usually means
+/// the profile doesn't set it.
+fn required_login_value(value: Option<String>, arg: &str, profile: &Option<String>) -> String {
+    value.unwrap_or_else(|| {
+        match *profile {
+            Some(ref profile) => eprintln!("--{} is needed since profile {} doesn't set {}",
+                                           arg.replace('_', "-"), profile, arg),
+            None => eprintln!("--{} is needed to log in to Spotify", arg.replace('_', "-")),
+        }
+        process::exit(exit_code::FAILURE);
+    })
+}
+
+impl LoginDetails {
+    /// Get the username, exiting if it wasn't given
+    fn username(&self) -> String {
+        required_login_value(self.username.clone(), "username", &self.profile)
+    }
+}
+
+/// Log in with the arguments shared by every subcommand that talks to
+/// Spotify, applying the request budget and deadline if they're given
+fn login_from_args(matches: &ArgMatches) -> SpotifyAPI {
+    let details = login_details(matches);
+    let spotify = login(
+        &details.client_id,
+        &details.client_secret,
+        &details.username(),
+        details.profile.as_deref(),
+        auth_strategy_from_args(matches)
+    );
+    with_limits_from_args(spotify, matches)
+}
+
+/// Open the sandbox when `--provider memory` is given, see `MemoryAPI`
+fn sandbox_from_args(matches: &ArgMatches) -> Option<MemoryAPI> {
+    if matches.value_of("provider") != Some("memory") {
+        return None;
+    }
+    let filename = matches.value_of("state_file").unwrap();
+    Some(MemoryAPI::open(filename).unwrap_or_else(|e| {
+        eprintln!("Could not read the sandbox {}: {}", filename, e);
+        process::exit(exit_code::FAILURE);
+    }))
+}
+
+/// Use the provider given with `--provider`, logging in to Spotify unless
+/// it's the sandbox
+fn provider_from_args(matches: &ArgMatches) -> Box<PlaylistAPI<failure::Error>> {
+    match sandbox_from_args(matches) {
+        Some(sandbox) => Box::new(sandbox),
+        None => Box::new(login_from_args(matches)),
+    }
+}
+
+/// Apply `--max-requests` and `--deadline`. The deadline starts from now
+fn with_limits_from_args(spotify: SpotifyAPI, matches: &ArgMatches) -> SpotifyAPI {
+    let deadline: Option<RunTime> = parse_arg(matches, "deadline");
+    with_limits(spotify, parse_arg(matches, "max_requests"), deadline.map(|d| d.deadline_from_now()))
+}
+
+/// Set up logging in to Spotify. Each profile caches its token in its own
+/// file, so that logging in as one account doesn't replace another's token
+fn spotify_oauth(client_id: &str, client_secret: &str, profile: Option<&str>) -> SpotifyOAuth {
+    let oauth = SpotifyOAuth::default()
+        .scope("playlist-read-private playlist-modify-private playlist-modify-public ugc-image-upload")
+        .client_id(client_id)
+        .client_secret(client_secret)
+        .redirect_uri(REDIRECT_URI);
+    match profile {
+        Some(profile) => oauth.cache_path(PathBuf::from(format!(".spotify_token_cache-{}.json", profile))),
+        None => oauth,
+    }.build()
+}
+
+/// Whether the user can be asked to log in, from --non-interactive
+fn auth_strategy_from_args(matches: &ArgMatches) -> AuthStrategy {
+    if matches.is_present("non_interactive") {
+        AuthStrategy::NonInteractive
+    } else {
+        AuthStrategy::Interactive
+    }
+}
+
+/// Log in to Spotify, prompting the user if there's no cached token and the
+/// strategy allows it
+fn login(client_id: &str, client_secret: &str, username: &str, profile: Option<&str>,
+         strategy: AuthStrategy) -> SpotifyAPI {
+    let mut oauth = spotify_oauth(client_id, client_secret, profile);
     // Log in with username
-    let spotify = SpotifyAPI::new(&username, &mut oauth).unwrap();
+    SpotifyAPI::new(username, &mut oauth, strategy).unwrap_or_else(|e| {
+        eprintln!("{}", render_error(&e.to_string(), Some(&login_error_hint(&e))));
+        process::exit(exit_code::AUTH_FAILED);
+    })
+}
+
+fn with_limits(spotify: SpotifyAPI, max_requests: Option<usize>,
+               deadline: Option<Instant>) -> SpotifyAPI {
+    let spotify = match max_requests {
+        Some(max_requests) => spotify.with_max_requests(max_requests),
+        None => spotify,
+    };
+    match deadline {
+        Some(deadline) => spotify.with_deadline(deadline),
+        None => spotify,
+    }
+}
+
+/// Exit with a helpful message if the request budget ran out or the deadline
+/// passed, otherwise exit with the API error
+fn exit_on_api_error(error: failure::Error) -> ! {
+    emit(&Event::Error { message: &error.to_string() });
+    if let Some(e) = error.downcast_ref::<RequestBudgetExhausted>() {
+        eprintln!("{}. Run again to continue, changes that were already made \
+                   are skipped", e);
+        process::exit(exit_code::REQUEST_BUDGET_EXHAUSTED);
+    }
+    if let Some(e) = error.downcast_ref::<DeadlineExceeded>() {
+        eprintln!("{}. Run again to continue, changes that were already made \
+                   are skipped", e);
+        process::exit(exit_code::DEADLINE_EXCEEDED);
+    }
+    eprintln!("{}", render_api_error("Spotify request failed", &error));
+    process::exit(exit_code::API_ERROR)
+}
+
+/// Exit with the message and exit code of a command that failed
+fn exit_on_command_error(error: CommandError) -> ! {
+    emit(&Event::Error { message: &error.message });
+    eprintln!("{}", error.message);
+    process::exit(error.exit_code)
+}
+
+/// Get the songs from a CSV, exiting if it can't be read
+fn unwrap_csv_result<T, E: Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|e| {
+        let message = format!("Could not read the CSV: {}", e);
+        emit(&Event::Error { message: &message });
+        eprintln!("{}", message);
+        process::exit(exit_code::INVALID_CSV);
+    })
+}
+
+/// Get the value from an API result, exiting if the request budget ran out
+fn unwrap_api_result<T>(result: Result<T, failure::Error>) -> T {
+    result.unwrap_or_else(|e| exit_on_api_error(e))
+}
+
+/// Get the value from a playlist result, exiting with a helpful message if the
+/// playlist can't be modified
+fn unwrap_playlist_result<T>(result: Result<T, PlaylistError<failure::Error>>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            let code = playlist_error_code(&e);
+            match e {
+                PlaylistError::APIError(e) => exit_on_api_error(e),
+                e => {
+                    emit(&Event::Error { message: &e.to_string() });
+                    eprintln!("{}", e);
+                },
+            }
+            process::exit(code);
+        },
+    }
+}
+
+/// Get the edits from a sync, exiting with a helpful message if there were
+/// too many
+fn unwrap_sync_result(result: Result<Vec<Edit>, SyncError<failure::Error>>) -> Vec<Edit> {
+    match result {
+        Ok(edits) => edits,
+        Err(SyncError::TooManyChanges(e)) => {
+            eprintln!("{}. Nothing was changed. Check the csv, or use --dry-run \
+                       to review the changes and raise the limits", e);
+            process::exit(exit_code::FAILURE);
+        },
+        Err(SyncError::APIError(e)) => exit_on_api_error(e),
+    }
+}
+
+/// Cap the tracks that would be added when `--limit` is given
+fn limit_additions(songs: &[Song], additions: Vec<TrackUri>, max_tracks: Option<usize>) -> Vec<TrackUri> {
+    match max_tracks {
+        Some(max_tracks) => limit_tracks(songs, additions, max_tracks),
+        None => additions,
+    }
+}
+
+/// Print the changes that would be made to the playlist and exit. The exit
+/// code is non-zero when there are pending changes.
+fn print_diff_and_exit(spotify: &PlaylistAPI<failure::Error>, playlist_name: &str,
+                       source: &str, songs: &[Song], max_tracks: Option<usize>) {
+    // Planning won't create the playlist since nothing should be changed
+    let plan = unwrap_playlist_result(create_plan(spotify, playlist_name, songs, &PlanOptions::default()));
+    let additions = unwrap_api_result(spotify.get_tracks_metadata(
+        &limit_additions(songs, plan.additions, max_tracks)
+    ));
+    print!("{}", format_diff(playlist_name, source, &additions));
+    process::exit(if additions.is_empty() { exit_code::SUCCESS } else { exit_code::CHANGES_PENDING });
+}
+
+/// Print the tracks that would be added to the playlist and the ones that
+/// would be skipped as duplicates, without creating or changing the playlist
+fn print_dry_run(spotify: &PlaylistAPI<failure::Error>, playlist_name: &str, songs: &[Song],
+                 max_tracks: Option<usize>) -> (Option<String>, DryRun) {
+    let playlist_id = match spotify.get_playlist_id(playlist_name) {
+        Ok(playlist_id) => Some(playlist_id),
+        Err(PlaylistError::PlaylistNotFound(_)) => None,
+        result => Some(unwrap_playlist_result(result)),
+    };
+    let mut dry_run = unwrap_api_result(
+        dry_run_add_songs(spotify, playlist_id.as_deref(), songs)
+    );
+    dry_run.additions = limit_additions(songs, dry_run.additions, max_tracks);
+    for track in &dry_run.additions {
+        println!("{}", tr("dry-run-add", &[("track", track.to_string().into())]));
+    }
+    for track in &dry_run.duplicates {
+        println!("{}", tr("dry-run-duplicate", &[("track", track.to_string().into())]));
+    }
+    println!("{}", tr("dry-run-summary", &[
+        ("added", dry_run.additions.len().into()),
+        ("skipped", dry_run.duplicates.len().into()),
+    ]));
+    (playlist_id, dry_run)
+}
+
+/// The options of an import that change what it does, which are saved in
+/// the `--dry-run-out` artifact. Credentials are left out
+const AUDITED_OPTIONS: &[&str] = &[
+    "profile", "provider", "name_column", "id_column", "strict_headers", "encoding", "input_format",
+    "id_namespace", "skip_invalid_rows", "dynamodb_table", "git_repo", "git_file", "since_ref",
+    "merge", "merge_priority", "sort", "order_by_added_at", "collapse_versions", "spread_artists",
+    "position", "on_full", "rotate_at", "limit", "allow_duplicates", "never_readd", "ledger",
+    "dedupe_against", "no_create", "create_only", "public", "description", "description_template",
+    "cover", "max_requests", "expect_sha256",
+];
+
+/// Get the options that were used, with each flag as `true`, each option as
+/// its value and options given more than once as a list
+fn options_snapshot(matches: &ArgMatches, names: &[&str]) -> BTreeMap<String, serde_json::Value> {
+    names.iter()
+        .filter(|name| matches.is_present(name))
+        .map(|name| {
+            let values = arg_values(matches, name);
+            let value = match values.len() {
+                0 => serde_json::Value::Bool(true),
+                1 => serde_json::Value::from(values[0].clone()),
+                _ => serde_json::Value::from(values),
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+/// Save everything a dry run found for `--dry-run-out`, see `DryRunArtifact`
+#[allow(clippy::too_many_arguments)]
+fn write_dry_run_artifact(matches: &ArgMatches, spotify: &PlaylistAPI<failure::Error>,
+                          playlist_name: &str, playlist_id: Option<String>,
+                          csv_filename: &str, songs: &[Song], failed_lines: &[usize],
+                          dry_run: &DryRun, filename: &str) {
+    let from_disk = !matches.is_present("git_repo") && !matches.is_present("dynamodb_table") &&
+        csv_filename != STDIN_FILENAME;
+    let (snapshot_id, size) = match playlist_id {
+        Some(ref id) => (
+            Some(unwrap_api_result(spotify.get_playlist_snapshot_id(id))),
+            unwrap_api_result(spotify.get_playlist_size(id)),
+        ),
+        None => (None, 0),
+    };
+    let position: Position = parse_arg(matches, "position").unwrap();
+    let artifact = build_artifact(
+        &Utc::now().to_rfc3339(),
+        AuditInput {
+            source: csv_filename.to_owned(),
+            sha256: if from_disk { file_sha256(csv_filename).ok() } else { None },
+            songs: songs.len(),
+            failed_lines: failed_lines.to_vec(),
+        },
+        AuditPlaylist { name: playlist_name.to_owned(), id: playlist_id, snapshot_id: snapshot_id },
+        songs,
+        dry_run,
+        matches.is_present("public"),
+        position.index(size),
+        options_snapshot(matches, AUDITED_OPTIONS),
+    );
+    unwrap_csv_result(artifact.write(filename));
+    println!("Dry run saved to {}", filename);
+}
+
+/// Print the tracks that would be added to the playlist and ask whether to
+/// add them. Returns whether the import should go ahead.
+fn confirm_import(spotify: &PlaylistAPI<failure::Error>, playlist_name: &str, songs: &[Song],
+                  max_tracks: Option<usize>) -> bool {
+    // Planning won't create the playlist before the user has agreed
+    let plan = unwrap_playlist_result(create_plan(spotify, playlist_name, songs, &PlanOptions::default()));
+    let track_ids = limit_additions(songs, plan.additions, max_tracks);
+    if track_ids.is_empty() {
+        return true;
+    }
+    let additions = unwrap_api_result(spotify.get_tracks_metadata(&track_ids));
+    for track in &additions {
+        println!("+ {}", format_track(track));
+    }
+    let question = format!("Add {} tracks to \"{}\"?", track_ids.len(), playlist_name);
+    let stdin = io::stdin();
+    confirm(stdin.lock(), stdout(), &question).unwrap()
+}
+
+/// The CSV columns to read songs from, see `--name-column`, `--id-column`
+/// and `--strict-headers`
+fn columns_from_args(matches: &ArgMatches) -> CsvColumns {
+    CsvColumns::new(matches.value_of("name_column"), matches.value_of("id_column"))
+        .with_strict(matches.is_present("strict_headers"))
+        .with_encoding(parse_arg(matches, "encoding"))
+}
+
+/// The format of a file given on the command line, from --format or else
+/// its extension
+fn input_format_from_args(matches: &ArgMatches, filename: &str) -> InputFormat {
+    parse_arg(matches, "input_format").unwrap_or_else(|| InputFormat::from_filename(filename))
+}
+
+/// The AWS credentials and region from --aws-profile and --region
+fn aws_from_args(matches: &ArgMatches) -> AwsOptions {
+    AwsOptions {
+        region: matches.value_of("region").map(|region| region.to_owned()),
+        profile: matches.value_of("aws_profile").map(|profile| profile.to_owned()),
+    }
+}
+
+/// The limits on downloads from --download-timeout and --max-download-mb
+fn download_limits_from_args(matches: &ArgMatches) -> DownloadLimits {
+    let RunTime(timeout) = parse_arg(matches, "download_timeout").unwrap();
+    let max_mb: u64 = parse_arg(matches, "max_download_mb").unwrap();
+    DownloadLimits {
+        timeout: timeout,
+        max_bytes: max_mb.saturating_mul(1024 * 1024),
+    }
+}
+
+/// Download a file given as s3://bucket/key or an http or https URL, exiting
+/// if it can't be. Other files are read from where they are
+fn local_filename(matches: &ArgMatches, filename: &str) -> String {
+    let downloaded = match parse_s3_url(filename) {
+        Some(url) => download_object(&url, &aws_from_args(matches)),
+        None if is_http_url(filename) => download_url(filename, &download_limits_from_args(matches)),
+        None => return filename.to_owned(),
+    };
+    let path = downloaded.unwrap_or_else(|e| {
+        eprintln!("Could not download {}: {}", filename, e);
+        process::exit(exit_code::FAILURE);
+    });
+    path.to_string_lossy().into_owned()
+}
+
+/// Read the songs in a file given on the command line, exiting if it can't
+/// be read
+fn read_song_file(matches: &ArgMatches, filename: &str) -> Vec<Song> {
+    let format = input_format_from_args(matches, filename);
+    unwrap_csv_result(SongFile::new(filename, Some(format), columns_from_args(matches)).read_songs())
+}
+
+/// Read the songs to import and the CSV lines of songs that have no track
+/// ID. When a Git repository is given, only rows added since `since_ref`
+/// are read. With `skip_invalid_rows`, rows of a CSV that can't be read are
+/// printed and counted as failed lines rather than stopping the import.
+/// The `side_columns` are only read from CSVs, see `ParsedCsv::column`.
+#[allow(clippy::too_many_arguments)]
+fn read_songs(csv_filename: &str,
+              git_repo: Option<String>,
+              since_ref: &str,
+              format: InputFormat,
+              columns: &CsvColumns,
+              side_columns: &[&str],
+              skip_invalid_rows: bool,
+              progress: &Progress) -> (ParsedCsv, Vec<usize>) {
+    match git_repo {
+        Some(repo) => {
+            let added = unwrap_csv_result(read_songs_added_since(&repo, csv_filename, since_ref, columns));
+            let failed_lines = added.iter()
+                .filter(|&(_, song)| !has_track_id(song))
+                .map(|&(line, _)| line)
+                .collect();
+            (ParsedCsv { rows: added, ..ParsedCsv::default() }, failed_lines)
+        },
+        None if format == InputFormat::Csv => {
+            let parsed = unwrap_csv_result(
+                parse_csv_file_with_progress(csv_filename, columns, side_columns, skip_invalid_rows, progress)
+            );
+            if !parsed.invalid_rows.is_empty() {
+                eprintln!("Skipped {} rows that could not be read:", parsed.invalid_rows.len());
+                for row in &parsed.invalid_rows {
+                    eprintln!("  {}", row);
+                }
+            }
+            let failed_lines = parsed.failed_lines();
+            (parsed, failed_lines)
+        },
+        None => {
+            let songs = unwrap_csv_result(SongFile::new(csv_filename, Some(format), columns.clone()).read_songs());
+            let failed_lines = get_lines_without_track_id(&songs);
+            (ParsedCsv::from_songs(songs), failed_lines)
+        },
+    }
+}
+
+/// Merge songs from other CSVs into the songs that were read, printing any
+/// conflicts. The provenance of each song is saved if `provenance_out` is
+/// given.
+fn merge_songs(csv_filename: &str,
+               songs: Vec<Song>,
+               other_filenames: &[String],
+               columns: &CsvColumns,
+               priority: Priority,
+               provenance_out: Option<String>) -> Vec<Song> {
+    let mut sources = vec![(csv_filename.to_owned(), songs)];
+    for filename in other_filenames {
+        let songs = SongFile::new(filename, None, columns.clone()).read_songs();
+        sources.push((filename.to_owned(), unwrap_csv_result(songs)));
+    }
+    let merged = merge_sources(sources, priority);
+    for provenance in &merged.provenance {
+        for conflict in &provenance.conflicts {
+            println!(
+                "Conflict for {}: using \"{}\" from {} over \"{}\" from {}",
+                provenance.song_id, provenance.music, provenance.source,
+                conflict.music, conflict.source
+            );
+        }
+    }
+    if let Some(filename) = provenance_out {
+        let file = File::create(&filename).unwrap();
+        serde_json::to_writer_pretty(file, &merged.provenance).unwrap();
+        println!("Provenance saved to {}", filename);
+    }
+    merged.songs
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_playlist_from_csv(spotify: &PlaylistAPI<failure::Error>, playlist_name: &str,
+                            part: usize,
+                            csv_filename: &str,
+                            songs: Vec<Song>,
+                            failed_lines: Vec<usize>,
+                            description_template: Option<String>,
+                            description: Option<&str>,
+                            cover: Option<&[u8]>,
+                            github_actions: bool,
+                            report: Option<(Format, &str)>,
+                            genius_token: Option<&str>,
+                            create_policy: CreatePolicy,
+                            visibility: Visibility,
+                            options: &AddOptions) -> (i32, Vec<TrackUri>, Vec<SpilledPart>) {
+    let full_name = playlist_name;
+    let playlist_name = &part_name(playlist_name, part);
     // Get playlist ID from playlist name
-    let playlist_id = get_playlist_id_create_if_needed(&spotify, &playlist_name).unwrap();
-    let songs = parse_csv_file(csv_filename).unwrap();
-    match add_songs_to_playlist(&spotify, &playlist_id, songs) {
-        Ok(()) => println!("Successfully added songs!"),
+    let playlist_id = unwrap_playlist_result(
+        get_playlist_id_with_policy(spotify, playlist_name, create_policy, visibility)
+    );
+    let song_count = songs.len();
+    let mut spilled = Vec::new();
+    let (added, code) = match add_songs_returning_tracks(spotify, &playlist_id, songs, options) {
+        Ok(added) => {
+            info!("{}", tr("import-success", &[]));
+            if !added.overflow.is_empty() {
+                spilled = unwrap_playlist_result(
+                    spill_tracks(spotify, full_name, part, added.overflow)
+                );
+                for spilled_part in &spilled {
+                    println!("{} was full, so {} tracks were added to {}",
+                             playlist_name, spilled_part.tracks.len(), spilled_part.name);
+                }
+            }
+            (added.added, exit_code::SUCCESS)
+        },
         Err(error) => {
+            let code = add_error_code(&error);
             match error {
                 PlaylistAddError::NoNewTracks(_) => {
-                    println!("No new tracks to add.");
+                    info!("{}", tr("import-no-new-tracks", &[]));
+                    (Vec::new(), code)
+                },
+                PlaylistAddError::APIError(e) => exit_on_api_error(e),
+                PlaylistAddError::PlaylistFull(e) => {
+                    emit(&Event::Error { message: &e.to_string() });
+                    eprintln!("{}", e);
+                    process::exit(code);
                 },
-                PlaylistAddError::APIError(e) => panic!(e),
+                PlaylistAddError::ReadError(e) => unwrap_csv_result(Err(e)),
             }
         }
+    };
+    let playlist_url = format!("https://open.spotify.com/playlist/{}", playlist_id);
+    if let Some((format, filename)) = report {
+        let added_tracks = unwrap_api_result(spotify.get_tracks_metadata(&added));
+        let report = RunReport {
+            playlist_name: playlist_name.to_owned(),
+            playlist_url: playlist_url.clone(),
+            source: csv_filename.to_owned(),
+            date: Local::now().format("%Y-%m-%d %H:%M").to_string(),
+            song_count: song_count,
+            annotations: match genius_token {
+                Some(token) => annotate_tracks(token, &added_tracks),
+                None => HashMap::new(),
+            },
+            added: added_tracks,
+            failed_lines: failed_lines.clone(),
+        };
+        File::create(filename).unwrap().write_all(report.render(format).as_bytes()).unwrap();
+        println!("Report saved to {}", filename);
+    }
+    if github_actions {
+        write_report(&ImportReport {
+            source: csv_filename.to_owned(),
+            playlist_name: playlist_name.to_owned(),
+            playlist_url: playlist_url,
+            added: added.len(),
+            failed_lines: failed_lines,
+        }).unwrap();
+    }
+    // Update the description now that the playlist is up to date
+    if let Some(template) = description_template {
+        let date = Local::today().format("%Y-%m-%d").to_string();
+        let description = unwrap_api_result(update_playlist_description(
+            spotify, &playlist_id, &template, csv_filename, &date
+        ));
+        println!("Updated description: {}", description);
     }
+    update_details(spotify, &playlist_id, description, cover);
+    (code, added, spilled)
 }
 
-fn main() {
-    // Parse arguments
-    let mut client_id = String::new();
-    let mut client_secret = String::new();
-    let mut username = String::new();
-    let mut playlist_name = String::new();
-    let mut csv_filename = String::new();
-    {
-        // Create parser in scope so that we can retrieve borrowed values
-        // after parser is released
-        let mut parser = ArgumentParser::new();
-        parser.set_description("Create a playlist with songs from a csv");
-        parser.refer(&mut client_id)
-            .add_argument("client_id", Store,
-                          "Spotify Client ID")
-            .required();
-        parser.refer(&mut client_secret)
-            .add_argument("client_secret", Store,
-                          "Spotify Client Secret")
-            .required();
-        parser.refer(&mut username)
-            .add_argument("username", Store,
-                          "Spotify Username")
-            .required();
-        parser.refer(&mut playlist_name)
-            .add_argument("playlist_name", Store,
-                          "Spotify Playlist name")
-            .required();
-        parser.refer(&mut csv_filename)
-            .add_argument("csv_filename", Store,
-                          "CSV Filename")
-            .required();
-        parser.parse_args_or_exit();
-    }
-    update_playlist_from_csv(
-        &client_id, &client_secret, &username, &playlist_name, &csv_filename
+/// Drop the songs that are already in a playlist from the family, printing
+/// each one that's skipped
+fn skip_family_songs(songs: Vec<Song>, family_tracks: &HashSet<TrackUri>) -> Vec<Song> {
+    let (songs, skipped) = remove_family_tracks(songs, family_tracks);
+    for song in skipped {
+        println!("Already added to the playlist family: {}", song.music);
+    }
+    songs
+}
+
+fn import_command(matches: &ArgMatches) {
+    let schedule = match (matches.value_of("every"), matches.value_of("cron")) {
+        (Some(_), _) => Some(RunSchedule::from(parse_arg::<RunTime>(matches, "every").unwrap())),
+        (None, Some(cron)) => Some(parse_cron(cron).unwrap_or_else(|e| {
+            eprintln!("Invalid value for cron: {}", e);
+            process::exit(exit_code::FAILURE);
+        })),
+        (None, None) => None,
+    };
+    if schedule.is_some() && import_names(matches).1.as_deref() == Some(STDIN_FILENAME) {
+        eprintln!("stdin can only be read once, so the CSV can't be read from stdin on a schedule");
+        process::exit(exit_code::FAILURE);
+    }
+    if schedule.is_some() && matches.value_of("provider") == Some("memory") {
+        eprintln!("--provider memory can't be used with --every or --cron, use a daemon config instead");
+        process::exit(exit_code::FAILURE);
+    }
+    match schedule {
+        Some(schedule) => import_on_schedule(matches, &schedule),
+        None => {
+            let progress = import_progress(matches);
+            let code = match sandbox_from_args(matches) {
+                Some(sandbox) => import_with_journal(matches, &sandbox, &*progress),
+                None => {
+                    let spotify = login_from_args(matches).with_progress(progress.clone());
+                    import_with_journal(matches, &spotify, &*progress)
+                },
+            };
+            process::exit(code)
+        },
+    }
+}
+
+/// Show the progress of an import on stderr, unless `--quiet` is given
+fn import_progress(matches: &ArgMatches) -> Arc<Progress + Send + Sync> {
+    if matches.is_present("quiet") {
+        Arc::new(NoProgress)
+    } else {
+        Arc::new(ProgressBars::new())
+    }
+}
+
+/// Keep importing on a schedule until asked to stop. The token is refreshed
+/// before a run when it's about to expire. A run that fails stops the
+/// schedule with its exit code, so that a supervisor can restart it.
+fn import_on_schedule(matches: &ArgMatches, schedule: &RunSchedule) {
+    let stop = StopSignal::install().unwrap();
+    let details = login_details(matches);
+    let oauth = spotify_oauth(&details.client_id, &details.client_secret, details.profile.as_deref());
+    let progress = import_progress(matches);
+    let mut token_info = None;
+    loop {
+        let started = Local::now();
+        let spotify = match token_info {
+            None => login_from_args(matches),
+            Some(token_info) => {
+                let token_info = refresh_token_if_needed(&oauth, token_info).unwrap_or_else(|e| {
+                    eprintln!("Could not refresh the Spotify token: {}", e);
+                    process::exit(exit_code::AUTH_FAILED);
+                });
+                let spotify = SpotifyAPI::with_token(&details.username(), token_info);
+                with_limits_from_args(spotify, matches)
+            },
+        };
+        let spotify = spotify.with_progress(progress.clone());
+        token_info = spotify.token_info();
+        let code = import_once(matches, &spotify, &*progress);
+        if code != exit_code::SUCCESS && code != exit_code::NO_NEW_TRACKS {
+            process::exit(code);
+        }
+        let next = match schedule.next_run(started) {
+            Some(next) => next,
+            None => break,
+        };
+        info!("Next import at {}", next.format("%Y-%m-%d %H:%M"));
+        if !stop.sleep_until(next) {
+            break;
+        }
+    }
+    println!("Stopped.");
+}
+
+/// Get the playlist name and CSV filename given to `import`. With a
+/// `--profile` the playlist name can be left out, in which case the only
+/// name given is the CSV
+fn import_names(matches: &ArgMatches) -> (Option<String>, Option<String>) {
+    let playlist_name = matches.value_of("playlist_name").map(|name| name.to_owned());
+    let csv_filename = matches.value_of("csv_filename").map(|name| name.to_owned());
+    match (playlist_name, csv_filename) {
+        (Some(csv_filename), None) if !matches.is_present("git_repo") && !matches.is_present("dynamodb_table") => {
+            (None, Some(csv_filename))
+        },
+        names => names,
+    }
+}
+
+/// Import the songs once, keeping a journal of the tracks added when
+/// `--journal` is given so that a failed import can be carried on. The
+/// journal is removed once the import finishes.
+fn import_with_journal(matches: &ArgMatches, spotify: &PlaylistAPI<failure::Error>, progress: &Progress) -> i32 {
+    if !matches.is_present("journal") {
+        return import_once(matches, spotify, progress);
+    }
+    let journal_file = matches.value_of("journal_file").unwrap();
+    let journaled = JournaledAPI::open(spotify, journal_file).unwrap_or_else(|e| {
+        eprintln!("Could not read the journal {}: {}", journal_file, e);
+        process::exit(exit_code::FAILURE);
+    });
+    let code = import_once(matches, &journaled, progress);
+    if code == exit_code::SUCCESS || code == exit_code::NO_NEW_TRACKS {
+        if let Err(e) = journaled.finish() {
+            eprintln!("Could not remove the journal {}: {}", journal_file, e);
+        }
+    }
+    code
+}
+
+/// Import the songs once, returning the exit code
+fn import_once(matches: &ArgMatches, spotify: &PlaylistAPI<failure::Error>, progress: &Progress) -> i32 {
+    let (playlist_name, csv_filename) = import_names(matches);
+    let playlist_name = playlist_name
+        .or_else(|| login_details(matches).playlist)
+        .unwrap_or_else(|| {
+            eprintln!("No playlist name was given and profile {} doesn't set a playlist",
+                      matches.value_of("profile").unwrap_or_default());
+            process::exit(exit_code::FAILURE);
+        });
+    let playlist_name = playlist_name.as_str();
+    let git_repo = matches.value_of("git_repo").map(|repo| repo.to_owned());
+    let dynamodb_table = matches.value_of("dynamodb_table");
+    // Files in a Git repository are given with --file instead, and a table
+    // is named after itself
+    let csv_filename = match (&git_repo, dynamodb_table) {
+        (&Some(_), _) => matches.value_of("git_file").unwrap().to_owned(),
+        (_, Some(table)) => table.to_owned(),
+        _ => local_filename(matches, &csv_filename.unwrap()),
+    };
+    let csv_filename = csv_filename.as_str();
+    let merge_filenames = arg_values(matches, "merge");
+    let cover = cover_from_args(matches);
+    verify_expected_sha256(matches, csv_filename);
+    if matches.is_present("confirm") && csv_filename == STDIN_FILENAME {
+        eprintln!("--confirm reads the answer from stdin, so the CSV can't be read from stdin");
+        process::exit(exit_code::FAILURE);
+    }
+    if matches.is_present("stream") {
+        return import_streaming(matches, spotify, playlist_name, csv_filename, cover.as_deref(), progress);
+    }
+    let from_csv = git_repo.is_none() && dynamodb_table.is_none();
+    let format = input_format_from_args(matches, csv_filename);
+    // The entries of an M3U playlist are local files, which are found by
+    // their names
+    let namespace = match format {
+        InputFormat::M3u if git_repo.is_none() && dynamodb_table.is_none() => IdNamespace::File,
+        _ => parse_arg(matches, "id_namespace").unwrap(),
+    };
+    // Columns that aren't part of the songs are read along with them, so
+    // that each value stays with its row when invalid rows are skipped
+    let side_columns = [&[PLAYLIST_COLUMN][..], &NOTE_COLUMNS, &ALLOW_DUP_COLUMNS, &ISRC_COLUMNS, &ADDED_AT_COLUMNS].concat();
+    let columns = columns_from_args(matches);
+    let (parsed, failed_lines) = match dynamodb_table {
+        Some(table) => {
+            let table = DynamoDbTable {
+                table: table.to_owned(),
+                aws: aws_from_args(matches),
+                columns: columns.clone(),
+            };
+            let songs = table.read_songs().unwrap_or_else(|e| {
+                eprintln!("Could not read the table: {}", e);
+                process::exit(exit_code::FAILURE);
+            });
+            let failed_lines = get_lines_without_track_id(&songs);
+            (ParsedCsv::from_songs(songs), failed_lines)
+        },
+        None => read_songs(
+            csv_filename, git_repo, matches.value_of("since_ref").unwrap(), format, &columns,
+            &side_columns, matches.is_present("skip_invalid_rows"), progress
+        ),
+    };
+    let (mut parsed, failed_lines) = resolve_namespace(spotify, parsed, failed_lines, namespace);
+    let notes = parsed.column(&NOTE_COLUMNS).map(|column| notes_by_track(&parsed.rows, column));
+    let allow_dup = match parsed.column(&ALLOW_DUP_COLUMNS) {
+        Some(column) => unwrap_csv_result(parse_allow_dup_column(column)),
+        None => HashMap::new(),
+    };
+    let duplicates = DuplicatePolicy::new(matches.is_present("allow_duplicates"), &parsed.rows, &allow_dup);
+    let added_at = if matches.is_present("order_by_added_at") {
+        Some(added_at_from_csv(csv_filename, &parsed, from_csv))
+    } else {
+        None
+    };
+    if let Some(routes) = parsed.side_columns.remove(PLAYLIST_COLUMN) {
+        if !merge_filenames.is_empty() || matches.is_present("diff_only") {
+            eprintln!("A CSV with a {} column can't be used with --merge or --diff-only", PLAYLIST_COLUMN);
+            process::exit(exit_code::FAILURE);
+        }
+        let mut routed = route_songs(parsed.rows, &failed_lines, &routes, playlist_name);
+        if let Some(ref added_at) = added_at {
+            for playlist in &mut routed {
+                sort_by_added_at(&mut playlist.songs, added_at);
+            }
+        }
+        return import_routed_songs(
+            matches, spotify, csv_filename, routed, cover.as_deref(), notes.as_ref(), &duplicates
+        );
+    }
+    let mut songs = parsed.into_songs();
+    if !merge_filenames.is_empty() {
+        songs = merge_songs(
+            csv_filename, songs, &merge_filenames, &columns,
+            parse_arg(matches, "merge_priority").unwrap(),
+            matches.value_of("provenance_out").map(|out| out.to_owned())
+        );
+    }
+    if let Some(ref added_at) = added_at {
+        sort_by_added_at(&mut songs, added_at);
+    }
+    let code = import_songs(
+        matches, spotify, playlist_name, csv_filename, songs.clone(), failed_lines, cover.as_deref(),
+        &duplicates
+    );
+    if let Some(ref notes) = notes {
+        record_notes(matches, playlist_name, &songs, notes, code);
+    }
+    code
+}
+
+/// Import a CSV a chunk of rows at a time for `--stream`, see
+/// `add_songs_streaming`. Returns the exit code
+fn import_streaming(matches: &ArgMatches, spotify: &PlaylistAPI<failure::Error>,
+                    playlist_name: &str, csv_filename: &str, cover: Option<&[u8]>,
+                    progress: &Progress) -> i32 {
+    if input_format_from_args(matches, csv_filename) != InputFormat::Csv {
+        eprintln!("--stream can only read CSV files");
+        process::exit(exit_code::FAILURE);
+    }
+    let chunk_size: usize = parse_arg(matches, "chunk_size").unwrap();
+    if chunk_size == 0 {
+        eprintln!("--chunk-size must be at least 1");
+        process::exit(exit_code::FAILURE);
+    }
+    let rows = unwrap_csv_result(stream_csv_file(csv_filename, &columns_from_args(matches)));
+    let playlist_id = unwrap_playlist_result(get_playlist_id_with_policy(
+        spotify, playlist_name, create_policy_from_args(matches), visibility_from_args(matches)
+    ));
+    let (added, failed_lines, code) = match add_songs_streaming(spotify, &playlist_id, rows, chunk_size, progress) {
+        Ok(streamed) => {
+            info!("{}", tr("import-success", &[]));
+            println!("Read {} rows and added {} tracks", streamed.rows, streamed.added);
+            (streamed.added, streamed.failed_lines, exit_code::SUCCESS)
+        },
+        Err(error) => {
+            let code = add_error_code(&error);
+            match error {
+                PlaylistAddError::NoNewTracks(_) => {
+                    info!("{}", tr("import-no-new-tracks", &[]));
+                    (0, Vec::new(), code)
+                },
+                PlaylistAddError::APIError(e) => exit_on_api_error(e),
+                PlaylistAddError::PlaylistFull(e) => {
+                    emit(&Event::Error { message: &e.to_string() });
+                    eprintln!("{}", e);
+                    process::exit(code);
+                },
+                PlaylistAddError::ReadError(e) => unwrap_csv_result(Err(e)),
+            }
+        },
+    };
+    if matches.is_present("gha") || is_github_actions() {
+        write_report(&ImportReport {
+            source: csv_filename.to_owned(),
+            playlist_name: playlist_name.to_owned(),
+            playlist_url: format!("https://open.spotify.com/playlist/{}", playlist_id),
+            added: added,
+            failed_lines: failed_lines,
+        }).unwrap();
+    }
+    if let Some(template) = matches.value_of("description_template") {
+        let date = Local::today().format("%Y-%m-%d").to_string();
+        let description = unwrap_api_result(update_playlist_description(
+            spotify, &playlist_id, template, csv_filename, &date
+        ));
+        println!("Updated description: {}", description);
+    }
+    update_details(spotify, &playlist_id, matches.value_of("description"), cover);
+    code
+}
+
+/// Look up songs whose IDs come from another service on Spotify for
+/// `--id-namespace`, see `resolve_song_ids`. The songs that can't be found
+/// are printed and counted as failed lines
+fn resolve_namespace(spotify: &PlaylistAPI<failure::Error>, mut parsed: ParsedCsv,
+                     mut failed_lines: Vec<usize>,
+                     namespace: IdNamespace) -> (ParsedCsv, Vec<usize>) {
+    if namespace == IdNamespace::Spotify {
+        return (parsed, failed_lines);
+    }
+    let isrcs: Vec<String> = match parsed.column(&ISRC_COLUMNS) {
+        Some(column) => parsed.rows.iter().map(|(line, _)| column.get(line).cloned().unwrap_or_default()).collect(),
+        None => Vec::new(),
+    };
+    let (lines, songs): (Vec<usize>, Vec<Song>) = parsed.rows.into_iter().unzip();
+    let resolved = unwrap_api_result(resolve_song_ids(spotify, songs, namespace, &isrcs));
+    if !resolved.unresolved.is_empty() {
+        eprintln!("Could not find {} songs on Spotify:", resolved.unresolved.len());
+        for song in &resolved.unresolved {
+            eprintln!("  {} ({} {})", song.music, namespace, song.song_id);
+        }
+    }
+    parsed.rows = lines.into_iter().zip(resolved.songs).collect();
+    failed_lines.extend(parsed.failed_lines());
+    failed_lines.sort();
+    failed_lines.dedup();
+    (parsed, failed_lines)
+}
+
+/// Read when each song was added for `--order-by-added-at`, from the column
+/// that was read along with the songs, so the songs have to come from a CSV
+fn added_at_from_csv(csv_filename: &str, parsed: &ParsedCsv, from_csv: bool) -> HashMap<String, NaiveDateTime> {
+    if !from_csv {
+        eprintln!("--order-by-added-at needs a CSV, rather than --git-repo or --dynamodb-table");
+        process::exit(exit_code::FAILURE);
+    }
+    match parsed.column(&ADDED_AT_COLUMNS) {
+        Some(column) => unwrap_csv_result(read_added_at(&parsed.rows, column)),
+        None => {
+            eprintln!("{} has no {} column", csv_filename, ADDED_AT_COLUMNS[1]);
+            process::exit(exit_code::INVALID_CSV);
+        },
+    }
+}
+
+/// Keep the notes from the CSV for the songs that were imported into a
+/// playlist, see `NoteIndex`. Nothing is kept for a dry run or a failed
+/// import
+fn record_notes(matches: &ArgMatches, playlist_name: &str, songs: &[Song],
+                notes: &HashMap<TrackUri, String>, code: i32) {
+    if matches.is_present("dry_run") || (code != exit_code::SUCCESS && code != exit_code::NO_NEW_TRACKS) {
+        return;
+    }
+    let filename = matches.value_of("notes_file").unwrap();
+    let mut index = NoteIndex::read(filename).unwrap();
+    index.record(playlist_name, songs, notes);
+    index.write(filename).unwrap();
+}
+
+/// Import the songs from a CSV that routes its rows to playlists, one
+/// playlist at a time. Returns the first failing exit code, otherwise
+/// `NO_NEW_TRACKS` only if no playlist had new tracks
+fn import_routed_songs(matches: &ArgMatches, spotify: &PlaylistAPI<failure::Error>,
+                       csv_filename: &str, routed: Vec<RoutedSongs>,
+                       cover: Option<&[u8]>,
+                       notes: Option<&HashMap<TrackUri, String>>,
+                       duplicates: &DuplicatePolicy) -> i32 {
+    let mut outcomes = Vec::new();
+    for playlist in routed {
+        println!("Importing {} songs into {}", playlist.songs.len(), playlist.playlist_name);
+        let code = import_songs(
+            matches, spotify, &playlist.playlist_name, csv_filename, playlist.songs.clone(),
+            playlist.failed_lines, cover, duplicates
+        );
+        if let Some(notes) = notes {
+            record_notes(matches, &playlist.playlist_name, &playlist.songs, notes, code);
+        }
+        outcomes.push((playlist.playlist_name, code));
+    }
+    for &(ref playlist_name, code) in &outcomes {
+        match code {
+            exit_code::SUCCESS => println!("{}: added songs", playlist_name),
+            exit_code::NO_NEW_TRACKS => println!("{}: no new songs", playlist_name),
+            code => println!("{}: failed with exit code {}", playlist_name, code),
+        }
+    }
+    let codes: Vec<i32> = outcomes.into_iter().map(|(_, code)| code).collect();
+    codes.iter().cloned()
+        .find(|&code| code != exit_code::SUCCESS && code != exit_code::NO_NEW_TRACKS)
+        .unwrap_or(if codes.contains(&exit_code::SUCCESS) {
+            exit_code::SUCCESS
+        } else {
+            exit_code::NO_NEW_TRACKS
+        })
+}
+
+/// Import the songs that were read into a playlist, returning the exit code
+#[allow(clippy::too_many_arguments)]
+fn import_songs(matches: &ArgMatches, spotify: &PlaylistAPI<failure::Error>,
+                playlist_name: &str, csv_filename: &str, mut songs: Vec<Song>,
+                failed_lines: Vec<usize>, cover: Option<&[u8]>,
+                duplicates: &DuplicatePolicy) -> i32 {
+    let dedupe_against = arg_values(matches, "dedupe_against");
+    let ledger_filename = matches.value_of("ledger");
+    let github_actions = matches.is_present("gha") || is_github_actions();
+    let max_tracks: Option<usize> = parse_arg(matches, "limit");
+    let mut create_policy = create_policy_from_args(matches);
+    if !dedupe_against.is_empty() {
+        let family_tracks = unwrap_api_result(get_family_tracks(spotify, &dedupe_against));
+        songs = skip_family_songs(songs, &family_tracks);
+    }
+    let mut ledger = ledger_filename.map(|filename| Ledger::read(filename).unwrap());
+    if matches.is_present("never_readd") {
+        if let Some(ref ledger) = ledger {
+            let (kept, skipped) = ledger.remove_imported_songs(playlist_name, songs);
+            for song in skipped {
+                println!("Imported in an earlier run: {}", song.music);
+            }
+            songs = kept;
+        }
+    }
+    let on_full: OnFull = parse_arg(matches, "on_full").unwrap();
+    // With --rotate-at, tracks are added to the newest playlist in the
+    // rotation and those already in an earlier one are skipped
+    let rotation: Option<RotationPolicy> = parse_arg(matches, "rotate_at");
+    if rotation.is_some() && on_full == OnFull::Spill {
+        eprintln!("--rotate-at can't be used with --on-full spill");
+        process::exit(exit_code::FAILURE);
+    }
+    let target = rotation.map(|policy| {
+        unwrap_playlist_result(find_rotation_target(spotify, playlist_name, policy))
+    });
+    if let Some(ref target) = target {
+        if target.rotated {
+            println!("{} is nearly full, so tracks will be added to {}",
+                     rotation_name(playlist_name, target.generation - 1), target.name);
+            create_policy = CreatePolicy::CreateIfNeeded;
+        }
+        if target.generation > 1 {
+            let earlier: Vec<String> = (1..target.generation)
+                .map(|generation| rotation_name(playlist_name, generation))
+                .collect();
+            let earlier_tracks = unwrap_api_result(get_family_tracks(spotify, &earlier));
+            let (kept, skipped) = remove_family_tracks(songs, &earlier_tracks);
+            info!("Skipping {} songs already in earlier playlists of {}", skipped.len(), playlist_name);
+            songs = kept;
+        }
+    }
+    let import_name = target.as_ref().map_or(playlist_name, |target| &target.name);
+    // When spilling, tracks are added to the part that isn't full yet and
+    // those already in an earlier part are skipped
+    let parts_filename = matches.value_of("parts_file").unwrap();
+    let mut parts = match on_full {
+        OnFull::Spill => Some(Parts::read(parts_filename).unwrap()),
+        _ => None,
+    };
+    let part = parts.as_ref().map_or(1, |parts| parts.active_part(import_name));
+    if part > 1 {
+        let earlier_parts: Vec<String> = (1..part).map(|part| part_name(import_name, part)).collect();
+        let earlier_tracks = unwrap_api_result(get_family_tracks(spotify, &earlier_parts));
+        let (kept, skipped) = remove_family_tracks(songs, &earlier_tracks);
+        info!("Skipping {} songs already in earlier parts of {}", skipped.len(), import_name);
+        songs = kept;
+    }
+    let part_playlist_name = part_name(import_name, part);
+    // Checked before previewing so that a dry run fails the same way
+    unwrap_playlist_result(check_create_policy(spotify, &part_playlist_name, create_policy));
+    if matches.is_present("diff_only") {
+        print_diff_and_exit(spotify, &part_playlist_name, csv_filename, &songs, max_tracks);
+    }
+    if matches.is_present("dry_run") {
+        let (playlist_id, dry_run) = print_dry_run(spotify, &part_playlist_name, &songs, max_tracks);
+        if let Some(filename) = matches.value_of("dry_run_out") {
+            write_dry_run_artifact(
+                matches, spotify, &part_playlist_name, playlist_id, csv_filename, &songs, &failed_lines,
+                &dry_run, filename
+            );
+        }
+        return exit_code::SUCCESS;
+    }
+    if matches.is_present("confirm") && !confirm_import(spotify, &part_playlist_name, &songs, max_tracks) {
+        println!("Nothing was added.");
+        return exit_code::SUCCESS;
+    }
+    // Every song is in the playlist once the import succeeds, since a failed
+    // import exits before the ledger is written. With --limit only some of
+    // the songs are added, so the rest are left for later runs
+    if let Some(ref mut ledger) = ledger {
+        if max_tracks.is_none() {
+            ledger.record(playlist_name, &songs);
+        }
+    }
+    let (code, added, spilled) = update_playlist_from_csv(
+        spotify, import_name, part, csv_filename, songs.clone(), failed_lines,
+        matches.value_of("description_template").map(|t| t.to_owned()),
+        matches.value_of("description"),
+        cover,
+        github_actions,
+        matches.value_of("report_out").map(|filename| {
+            (parse_arg(matches, "report_format").unwrap(), filename)
+        }),
+        matches.value_of("genius_token"),
+        create_policy,
+        visibility_from_args(matches),
+        &AddOptions {
+            collapse_versions: parse_arg(matches, "collapse_versions"),
+            max_tracks: max_tracks,
+            duplicates: duplicates.clone(),
+            keep_order: matches.is_present("order_by_added_at"),
+            sort: parse_arg(matches, "sort"),
+            spread_artists: parse_arg(matches, "spread_artists"),
+            on_full: on_full,
+            position: parse_arg(matches, "position").unwrap(),
+        }
+    );
+    if let Some(ref mut parts) = parts {
+        if !spilled.is_empty() {
+            parts.record(import_name, &spilled);
+            parts.write(parts_filename).unwrap();
+        }
+    }
+    if let Some(ref mut ledger) = ledger {
+        if max_tracks.is_some() {
+            let added_songs: Vec<Song> = songs.into_iter()
+                .filter(|song| {
+                    let track = TrackUri::parse(&song.song_id);
+                    added.contains(&track) || spilled.iter().any(|part| part.tracks.contains(&track))
+                })
+                .collect();
+            ledger.record(playlist_name, &added_songs);
+        }
+    }
+    if let (Some(ledger), Some(filename)) = (ledger, ledger_filename) {
+        ledger.write(filename).unwrap();
+    }
+    code
+}
+
+fn plan_command(matches: &ArgMatches) {
+    let playlist_name = matches.value_of("playlist_name").unwrap();
+    let out = matches.value_of("out").unwrap();
+    let csv_filename = &local_filename(matches, matches.value_of("csv_filename").unwrap());
+    verify_expected_sha256(matches, csv_filename);
+    let spotify = login_from_args(matches);
+    let songs = read_song_file(matches, csv_filename);
+    let options = PlanOptions {
+        position: parse_arg(matches, "position").unwrap(),
+        remove_missing: matches.is_present("remove_missing"),
+        visibility: visibility_from_args(matches),
+    };
+    let plan = unwrap_playlist_result(create_plan(&spotify, playlist_name, &songs, &options));
+    write_plan(&plan, out).unwrap();
+    println!(
+        "Planned {} tracks to add and {} to remove. Saved to {}",
+        plan.additions.len(), plan.removals.len(), out
+    );
+}
+
+fn apply_command(matches: &ArgMatches) {
+    let plan_filename = matches.value_of("plan").unwrap();
+    verify_expected_sha256(matches, plan_filename);
+    let plan = match matches.value_of("verify_key") {
+        Some(key_filename) => {
+            let data = read_verified_file(
+                plan_filename, key_filename
+            ).unwrap_or_else(|e| {
+                eprintln!("Could not verify plan signature: {}", e);
+                process::exit(exit_code::FAILURE);
+            });
+            parse_plan(&data).unwrap()
+        },
+        None => read_plan(plan_filename).unwrap(),
+    };
+    let spotify = login_from_args(matches);
+    match apply_plan(&spotify, &plan) {
+        Ok(_) => println!(
+            "Applied plan, added {} tracks and removed {}.", plan.additions.len(), plan.removals.len()
+        ),
+        Err(ApplyError::PlaylistChanged(e)) => {
+            eprintln!("{}. Create a new plan and try again.", e);
+            process::exit(exit_code::FAILURE);
+        },
+        Err(ApplyError::NotAuthorizedToModify(e)) => {
+            eprintln!("{}", e);
+            process::exit(exit_code::FAILURE);
+        },
+        Err(ApplyError::APIError(e)) => exit_on_api_error(e),
+    }
+}
+
+fn sign_command(matches: &ArgMatches) {
+    let plan_filename = matches.value_of("plan").unwrap();
+    sign_file(plan_filename, matches.value_of("key").unwrap()).unwrap();
+    println!("Signature saved to {}", signature_filename(plan_filename));
+}
+
+fn set_visibility_command(matches: &ArgMatches) {
+    let playlist_name = matches.value_of("playlist_name").unwrap();
+    let visibility: Visibility = parse_arg(matches, "visibility").unwrap();
+    let spotify = login_from_args(matches);
+    let playlist_id = unwrap_playlist_result(spotify.get_playlist_id(playlist_name));
+    unwrap_playlist_result(check_can_modify(&spotify, &playlist_id));
+    unwrap_api_result(spotify.set_playlist_visibility(&playlist_id, visibility));
+    match visibility {
+        Visibility::Public => println!("{} is now public.", playlist_name),
+        Visibility::Private => println!("{} is now private.", playlist_name),
+    }
+}
+
+fn build_command(matches: &ArgMatches) {
+    let dry_run = matches.is_present("dry_run");
+    // Every spec is read before anything is built, so a typo in the last
+    // spec doesn't leave the others half built
+    let specs: Vec<PlaylistSpec> = matches.values_of("spec").unwrap().map(|filename| {
+        read_spec(filename).unwrap_or_else(|e| {
+            eprintln!("Invalid spec {}: {}", filename, e);
+            process::exit(exit_code::FAILURE);
+        })
+    }).collect();
+    let mut cache = match matches.value_of("cache_file") {
+        Some(filename) => SourceCache::open(filename).unwrap_or_else(|e| {
+            eprintln!("Could not read the cache {}: {}", filename, e);
+            process::exit(exit_code::FAILURE);
+        }),
+        None => SourceCache::new(),
+    };
+    let spotify = provider_from_args(matches);
+    for spec in &specs {
+        let built = match build_playlist(&*spotify, spec, &mut cache, dry_run) {
+            Ok(built) => built,
+            Err(BuildError::ReadError(e)) => {
+                eprintln!("Could not read source {}", e);
+                process::exit(exit_code::INVALID_CSV);
+            },
+            Err(BuildError::PlaylistError(e)) => unwrap_playlist_result(Err(e)),
+        };
+        for edit in &built.edits {
+            println!("{}", edit);
+        }
+        if dry_run {
+            println!("{} changes would be made to {}.", built.edits.len(), spec.name);
+        } else {
+            println!("Built {} with {} changes.", spec.name, built.edits.len());
+        }
+        // Saved after each spec so that a failed build keeps what was read
+        if let Err(e) = cache.save() {
+            warn!("Could not save the cache: {}", e);
+        }
+    }
+    debug!("Read {} sources for {} specs", cache.reads, specs.len());
+}
+
+fn remove_command(matches: &ArgMatches) {
+    let playlist_name = matches.value_of("playlist_name").unwrap();
+    let csv_filename = &local_filename(matches, matches.value_of("csv_filename").unwrap());
+    let dry_run = matches.is_present("dry_run");
+    verify_expected_sha256(matches, csv_filename);
+    let songs = read_song_file(matches, csv_filename);
+    let spotify = login_from_args(matches);
+    let removal = match remove_songs_from_playlist(&spotify, playlist_name, &songs, dry_run) {
+        Err(PlaylistError::PlaylistNotFound(e)) => {
+            eprintln!("{}: {}", e, playlist_name);
+            process::exit(exit_code::FAILURE);
+        },
+        result => unwrap_playlist_result(result),
+    };
+    let removed = unwrap_api_result(spotify.get_tracks_metadata(&removal.removed));
+    print!("{}", format_removal_diff(playlist_name, csv_filename, &removed));
+    if dry_run {
+        println!("{} tracks would be removed.", removal.removed.len());
+    } else {
+        println!("Removed {} tracks.", removal.removed.len());
+    }
+    if !removal.not_in_playlist.is_empty() {
+        println!("{} tracks were not in the playlist.", removal.not_in_playlist.len());
+    }
+    if let Some(filename) = matches.value_of("report") {
+        let file = File::create(filename).unwrap();
+        serde_json::to_writer_pretty(file, &removal).unwrap();
+        println!("Report saved to {}", filename);
+    }
+}
+
+fn replace_command(matches: &ArgMatches) {
+    let playlist_name = matches.value_of("playlist_name").unwrap();
+    let dry_run = matches.is_present("dry_run");
+    let mapping = parse_mapping_file(matches.value_of("mapping").unwrap()).unwrap_or_else(|e| {
+        eprintln!("Could not read mapping: {}", e);
+        process::exit(exit_code::FAILURE);
+    });
+    let spotify = login_from_args(matches);
+    let playlist_id = unwrap_playlist_result(spotify.get_playlist_id(playlist_name));
+    if !dry_run {
+        unwrap_playlist_result(check_can_modify(&spotify, &playlist_id));
+    }
+    let replacements = unwrap_api_result(replace_tracks(&spotify, &playlist_id, &mapping, dry_run));
+    for replacement in &replacements {
+        println!("{}: {} -> {}", replacement.position + 1, replacement.old, replacement.new);
+    }
+    if dry_run {
+        println!("{} tracks would be replaced.", replacements.len());
+    } else {
+        println!("Replaced {} tracks.", replacements.len());
+    }
+}
+
+fn relink_command(matches: &ArgMatches) {
+    let playlist_name = matches.value_of("playlist_name").unwrap();
+    let market: Market = parse_arg(matches, "market").unwrap();
+    let replace = matches.is_present("replace");
+    let spotify = provider_from_args(matches);
+    let spotify = &*spotify;
+    let playlist_id = unwrap_playlist_result(spotify.get_playlist_id(playlist_name));
+    if replace {
+        unwrap_playlist_result(check_can_modify(spotify, &playlist_id));
+    }
+    let unavailable = unwrap_api_result(find_unavailable_tracks(spotify, &playlist_id, &market));
+    for track in &unavailable {
+        let name = format!("{} - {}", track.track.artists.join(", "), track.track.name);
+        match track.equivalent {
+            Equivalent::Relinked(ref uri) => println!("{} ({}): relinked by Spotify to {}", name, track.track.uri, uri),
+            Equivalent::Found(ref uri) => println!("{} ({}): available as {}", name, track.track.uri, uri),
+            Equivalent::Missing => println!("{} ({}): no equivalent in {}", name, track.track.uri, market),
+        }
+    }
+    let mapping = get_relink_mapping(&unavailable);
+    let replacements = unwrap_api_result(replace_tracks(spotify, &playlist_id, &mapping, !replace));
+    if replace {
+        println!("Replaced {} tracks.", replacements.len());
+    } else {
+        println!("{} tracks are unavailable in {}, {} would be replaced. Use --replace to replace them.",
+                 unavailable.len(), market, replacements.len());
+    }
+}
+
+/// Make a playlist match the songs, creating it if the policy allows. When
+/// `dry_run` is set the changes are only worked out, even if the playlist
+/// doesn't exist.
+#[allow(clippy::too_many_arguments)]
+fn sync_songs(spotify: &PlaylistAPI<failure::Error>, playlist_name: &str, songs: &[Song],
+              quarantine: Option<&str>, limits: &ChangeLimits,
+              create_policy: CreatePolicy, visibility: Visibility,
+              dry_run: bool) -> Vec<Edit> {
+    if dry_run {
+        unwrap_playlist_result(check_create_policy(spotify, playlist_name, create_policy));
+        match spotify.get_playlist_id(playlist_name) {
+            Ok(playlist_id) => unwrap_sync_result(
+                sync_playlist(spotify, &playlist_id, songs, None, limits, true)
+            ),
+            // The playlist would be created empty
+            Err(PlaylistError::PlaylistNotFound(_)) => compute_edits(&[], &get_sync_target(songs)),
+            result => unwrap_playlist_result(result.map(|_| Vec::new())),
+        }
+    } else {
+        let playlist_id = unwrap_playlist_result(
+            get_playlist_id_with_policy(spotify, playlist_name, create_policy, visibility)
+        );
+        let quarantine_id = quarantine.map(|name| unwrap_playlist_result(
+            get_playlist_id_create_if_needed(spotify, name)
+        ));
+        unwrap_sync_result(sync_playlist(
+            spotify, &playlist_id, songs, quarantine_id.as_deref(), limits, false
+        ))
+    }
+}
+
+fn sync_command(matches: &ArgMatches) {
+    let playlist_name = matches.value_of("playlist_name").unwrap();
+    let dry_run = matches.is_present("dry_run");
+    let quarantine = matches.value_of("quarantine");
+    let limits = ChangeLimits {
+        max_removals: parse_arg(matches, "max_removals"),
+        max_changes_percent: parse_arg(matches, "max_changes_percent"),
+    };
+    let csv_filename = &local_filename(matches, matches.value_of("csv_filename").unwrap());
+    let cover = cover_from_args(matches);
+    verify_expected_sha256(matches, csv_filename);
+    let songs = read_song_file(matches, csv_filename);
+    let spotify = provider_from_args(matches);
+    let spotify = &*spotify;
+    let edits = sync_songs(
+        spotify, playlist_name, &songs, quarantine, &limits, create_policy_from_args(matches),
+        visibility_from_args(matches), dry_run
+    );
+    for edit in &edits {
+        println!("{}", edit);
+    }
+    if dry_run {
+        println!("{} changes would be made.", edits.len());
+    } else {
+        println!("Made {} changes.", edits.len());
+        let playlist_id = unwrap_playlist_result(spotify.get_playlist_id(playlist_name));
+        update_details(spotify, &playlist_id, matches.value_of("description"), cover.as_deref());
+    }
+    if let Some(name) = quarantine {
+        let removed = edits.iter().filter(|edit| matches!(**edit, Edit::Remove { .. })).count();
+        if removed > 0 && !dry_run {
+            println!("Removed tracks are kept in \"{}\".", name);
+        }
+    }
+}
+
+fn import_many_command(matches: &ArgMatches) {
+    let jobs: usize = parse_arg(matches, "jobs").unwrap();
+    let dedupe_against = arg_values(matches, "dedupe_against");
+    let mut playlists = read_playlists(matches.value_of("path").unwrap()).unwrap();
+    let spotify = login_from_args(matches);
+    if !dedupe_against.is_empty() {
+        // The family is only read once and used for every playlist
+        let family_tracks = unwrap_api_result(get_family_tracks(&spotify, &dedupe_against));
+        for playlist in &mut playlists {
+            let songs = mem::take(&mut playlist.songs);
+            playlist.songs = skip_family_songs(songs, &family_tracks);
+        }
+    }
+    let mut api_error = None;
+    for import in import_playlists(&spotify, playlists, jobs) {
+        match import.result {
+            Ok(added) => println!("{}: added {} songs", import.playlist_name, added),
+            Err(PlaylistError::APIError(e)) => {
+                eprintln!("{}", render_api_error(&import.playlist_name, &e));
+                api_error = api_error.or(Some(e));
+            },
+            Err(e) => eprintln!("{}: {}", import.playlist_name, e),
+        }
+    }
+    // Every playlist is reported before exiting for the first API error
+    if let Some(e) = api_error {
+        exit_on_api_error(e);
+    }
+}
+
+fn export_command(matches: &ArgMatches) {
+    let playlist_name = matches.value_of("playlist_name").unwrap();
+    let spotify = login_from_args(matches);
+    let playlist_id = match spotify.get_playlist_id(playlist_name) {
+        Err(PlaylistError::PlaylistNotFound(e)) => {
+            eprintln!("{}: {}", e, playlist_name);
+            process::exit(exit_code::FAILURE);
+        },
+        result => unwrap_playlist_result(result),
+    };
+    let tracks = unwrap_api_result(spotify.get_tracks_with_metadata_in_playlist(&playlist_id));
+    let notes = notes_from_args(matches, playlist_name);
+    let write = |writer: &mut Write| match notes {
+        Some(ref notes) => write_csv_with_notes(writer, &tracks, notes),
+        None => write_csv(writer, &tracks),
+    };
+    match matches.value_of("out") {
+        Some(filename) => {
+            write(&mut File::create(filename).unwrap()).unwrap();
+            println!("Exported {} tracks to {}", tracks.len(), filename);
+        },
+        None => write(&mut stdout()).unwrap(),
+    }
+}
+
+/// Read the notes on a playlist's tracks when `--with-notes` is given
+fn notes_from_args(matches: &ArgMatches, playlist_name: &str) -> Option<BTreeMap<TrackUri, String>> {
+    if !matches.is_present("with_notes") {
+        return None;
+    }
+    let filename = matches.value_of("notes_file").unwrap();
+    let index = NoteIndex::read(filename).unwrap_or_else(|e| {
+        eprintln!("Could not read the notes {}: {}", filename, e);
+        process::exit(exit_code::FAILURE);
+    });
+    Some(index.playlist_notes(playlist_name))
+}
+
+fn show_command(matches: &ArgMatches) {
+    let playlist_name = matches.value_of("playlist_name").unwrap();
+    let spotify = provider_from_args(matches);
+    let playlist_id = match spotify.get_playlist_id(playlist_name) {
+        Err(PlaylistError::PlaylistNotFound(e)) => {
+            eprintln!("{}: {}", e, playlist_name);
+            process::exit(exit_code::FAILURE);
+        },
+        result => unwrap_playlist_result(result),
+    };
+    let tracks = unwrap_api_result(spotify.get_tracks_with_metadata_in_playlist(&playlist_id));
+    let notes = notes_from_args(matches, playlist_name).unwrap_or_default();
+    let stdout = stdout();
+    let mut out = stdout.lock();
+    for (position, track) in tracks.iter().enumerate() {
+        let song = song_from_metadata(track);
+        match notes.get(&track.uri) {
+            Some(note) => writeln!(out, "{}\t{}\t{}\t{}", position + 1, song.music, song.song_id, note),
+            None => writeln!(out, "{}\t{}\t{}", position + 1, song.music, song.song_id),
+        }.unwrap();
+    }
+}
+
+fn bandcamp_command(matches: &ArgMatches) {
+    let items = match matches.value_of("collection") {
+        Some(filename) => read_collection(filename),
+        None => get_collection(matches.value_of("identity").unwrap()),
+    }.unwrap_or_else(|e| {
+        eprintln!("Could not read the Bandcamp collection: {}", e);
+        process::exit(exit_code::FAILURE);
+    });
+    let spotify = login_from_args(matches);
+    let matches_found = unwrap_api_result(match_collection(&spotify, &items));
+    for item in &matches_found.unmatched {
+        eprintln!("No match on Spotify for: {} - {}", item.band_name, item.item_title);
+    }
+    match matches.value_of("out") {
+        Some(filename) => {
+            write_csv(File::create(filename).unwrap(), &matches_found.tracks).unwrap();
+            println!("Matched {} of {} purchases, saved {} tracks to {}",
+                     items.len() - matches_found.unmatched.len(), items.len(),
+                     matches_found.tracks.len(), filename);
+        },
+        None => write_csv(stdout(), &matches_found.tracks).unwrap(),
+    }
+}
+
+fn setlist_command(matches: &ArgMatches) {
+    let url = matches.value_of("url").unwrap();
+    let setlist_id = setlist_id_from_url(url).unwrap_or_else(|| {
+        eprintln!("Not a setlist.fm setlist link: {}", url);
+        process::exit(exit_code::FAILURE);
+    });
+    let setlist = get_setlist(matches.value_of("api_key").unwrap(), &setlist_id).unwrap_or_else(|e| {
+        eprintln!("Could not get the setlist: {}", e);
+        process::exit(exit_code::FAILURE);
+    });
+    let playlist_name = match matches.value_of("playlist_name") {
+        Some(name) => name.to_owned(),
+        None => format!("{} at {}, {}", setlist.artist, setlist.venue, setlist.event_date),
+    };
+    let spotify = login_from_args(matches);
+    let matches_found = unwrap_api_result(match_setlist(&spotify, &setlist));
+    for song in &matches_found.unmatched {
+        eprintln!("No match on Spotify for: {} - {}", song.artist, song.title);
+    }
+    let playlist_id = unwrap_playlist_result(
+        get_playlist_id_create_if_needed(&spotify, &playlist_name)
+    );
+    // Added directly rather than as songs so that the setlist order is kept
+    let tracks = unwrap_api_result(filter_duplicates(&spotify, &playlist_id, matches_found.track_ids()));
+    if tracks.is_empty() {
+        println!("Every song is already in {}", playlist_name);
+        process::exit(exit_code::NO_NEW_TRACKS);
+    }
+    unwrap_api_result(spotify.add_tracks_to_playlist(&playlist_id, &tracks, None));
+    println!("Added {} of {} songs to {}", tracks.len(), setlist.songs.len(), playlist_name);
+}
+
+fn chart_command(matches: &ArgMatches) {
+    let chart: Chart = parse_arg(matches, "chart").unwrap();
+    let date = match matches.value_of("date") {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap_or_else(|e| {
+            eprintln!("Invalid value for date: {}", e);
+            process::exit(exit_code::FAILURE);
+        }),
+        None => Local::today().naive_local(),
+    };
+    let dry_run = matches.is_present("dry_run");
+    let playlist_name = match matches.value_of("playlist_name") {
+        Some(name) => name.to_owned(),
+        None => format!("{} {}", chart, chart.chart_date(date).format("%Y-%m-%d")),
+    };
+    let entries = get_chart(chart, date).unwrap_or_else(|e| {
+        eprintln!("Could not read the {}: {}", chart, e);
+        process::exit(exit_code::FAILURE);
+    });
+    let spotify = login_from_args(matches);
+    let (songs, unmatched) = unwrap_api_result(match_chart(&spotify, &entries));
+    for entry in &unmatched {
+        eprintln!("No match on Spotify for #{}: {} - {}", entry.position, entry.artist, entry.title);
+    }
+    let edits = sync_songs(
+        &spotify, &playlist_name, &songs, None, &ChangeLimits::default(), CreatePolicy::default(),
+        Visibility::default(), dry_run
     );
+    for edit in &edits {
+        println!("{}", edit);
+    }
+    if dry_run {
+        println!("{} changes would be made to {}.", edits.len(), playlist_name);
+    } else {
+        println!("Matched {} of {} entries. Made {} changes to {}.",
+                 songs.len(), entries.len(), edits.len(), playlist_name);
+    }
+}
+
+fn segment_command(matches: &ArgMatches) {
+    let playlist_name = matches.value_of("playlist_name").unwrap();
+    let template = matches.value_of("name_template").unwrap();
+    let feature: Feature = parse_arg(matches, "by").unwrap();
+    let dry_run = matches.is_present("dry_run");
+    let mut segments: Vec<Segment> = arg_values(matches, "segment").iter()
+        .map(|segment| segment.parse().unwrap_or_else(|e| {
+            eprintln!("Invalid value for segment: {}", e);
+            process::exit(exit_code::FAILURE);
+        }))
+        .collect();
+    if segments.is_empty() {
+        segments = feature.default_segments();
+    }
+    let songs = read_song_file(matches, &local_filename(matches, matches.value_of("csv_filename").unwrap()));
+    let tracks = get_unique_track_ids(&songs);
+    let spotify = login_from_args(matches);
+    let features = unwrap_api_result(spotify.get_audio_features(&tracks));
+    let (segmented, unsegmented) = segment_tracks(&tracks, &features, feature, &segments);
+    if !unsegmented.is_empty() {
+        eprintln!("{} tracks weren't in any segment or haven't been analysed by Spotify",
+                  unsegmented.len());
+    }
+    let mut added_count = 0;
+    for (segment, segment_tracks) in segments.iter().zip(segmented) {
+        let name = segment_playlist_name(template, playlist_name, segment);
+        if dry_run || segment_tracks.is_empty() {
+            println!("{} tracks in {}", segment_tracks.len(), name);
+            continue;
+        }
+        let playlist_id = unwrap_playlist_result(get_playlist_id_create_if_needed(&spotify, &name));
+        let new_tracks = unwrap_api_result(filter_duplicates(&spotify, &playlist_id, segment_tracks.clone()));
+        if !new_tracks.is_empty() {
+            unwrap_api_result(spotify.add_tracks_to_playlist(&playlist_id, &new_tracks, None));
+        }
+        println!("Added {} of {} tracks to {}", new_tracks.len(), segment_tracks.len(), name);
+        added_count += new_tracks.len();
+    }
+    if !dry_run && added_count == 0 {
+        process::exit(exit_code::NO_NEW_TRACKS);
+    }
+}
+
+fn recap_command(matches: &ArgMatches) {
+    let period: Period = parse_arg(matches, "period").unwrap();
+    let top: usize = parse_arg(matches, "top").unwrap();
+    let min_plays: usize = parse_arg(matches, "min_plays").unwrap();
+    let template = matches.value_of("name_template").unwrap();
+    let dry_run = matches.is_present("dry_run");
+    let since = matches.value_of("since").map(|since| parse_played_at(since).unwrap_or_else(|| {
+        eprintln!("Invalid value for since: expected a date like 2024-01-01");
+        process::exit(exit_code::FAILURE);
+    }));
+    let mut plays = match matches.value_of("listenbrainz_user") {
+        Some(user) => {
+            // --since is required with --listenbrainz-user
+            let since = since.unwrap().timestamp();
+            let listens = get_listens_since(user, since).unwrap_or_else(|e| {
+                eprintln!("Could not read the listens of {}: {}", user, e);
+                process::exit(exit_code::FAILURE);
+            });
+            plays_from_listens(&listens)
+        },
+        None => unwrap_csv_result(read_plays(
+            &local_filename(matches, matches.value_of("csv_filename").unwrap()), &columns_from_args(matches)
+        )),
+    };
+    if let Some(since) = since {
+        plays.retain(|play| play.played_at >= since);
+    }
+    let periods = top_tracks(&plays, period, top, min_plays);
+    if periods.is_empty() {
+        println!("No tracks were played {} times or more in any period", min_plays);
+        process::exit(exit_code::NO_NEW_TRACKS);
+    }
+    let spotify = provider_from_args(matches);
+    let spotify = &*spotify;
+    for period in periods {
+        let name = recap_playlist_name(template, &period.label);
+        if dry_run {
+            println!("{}:", name);
+            for (i, track) in period.tracks.iter().enumerate() {
+                println!("{:>4}. {} ({} plays)", i + 1, track.song.music, track.plays);
+            }
+            continue;
+        }
+        let songs: Vec<Song> = period.tracks.into_iter().map(|track| track.song).collect();
+        let edits = sync_songs(
+            spotify, &name, &songs, None, &ChangeLimits::default(), CreatePolicy::CreateIfNeeded,
+            visibility_from_args(matches), false
+        );
+        println!("{} tracks in {}, made {} changes", songs.len(), name, edits.len());
+    }
+}
+
+fn preview_command(matches: &ArgMatches) {
+    let playlist_name = matches.value_of("playlist_name").unwrap();
+    let format: Format = parse_arg(matches, "format").unwrap();
+    let songs = read_song_file(matches, &local_filename(matches, matches.value_of("csv_filename").unwrap()));
+    let spotify = login_from_args(matches);
+    let preview = unwrap_api_result(build_preview(&spotify, playlist_name, &songs));
+    let output = preview.render(format);
+    match matches.value_of("out") {
+        Some(filename) => {
+            File::create(filename).unwrap().write_all(output.as_bytes()).unwrap();
+            println!("Saved preview of {} tracks to {}", preview.tracks.len(), filename);
+        },
+        None => print!("{}", output),
+    }
+}
+
+/// Clone the --from playlist into --to, printing what was copied
+fn clone_from_args(matches: &ArgMatches, spotify: &PlaylistAPI<failure::Error>) -> ClonedPlaylist {
+    let from = matches.value_of("from").unwrap();
+    let to = matches.value_of("to").unwrap();
+    let cloned = match clone_playlist(spotify, from, to, visibility_from_args(matches)) {
+        Err(PlaylistError::PlaylistNotFound(e)) => {
+            eprintln!("{}: {}", e, from);
+            process::exit(exit_code::FAILURE);
+        },
+        result => unwrap_playlist_result(result),
+    };
+    println!("Copied {} tracks from {} to {}", cloned.copied, from, to);
+    if !cloned.skipped.is_empty() {
+        println!("Skipped {} items that can't be added, eg. local files", cloned.skipped.len());
+    }
+    if cloned.copied_description {
+        println!("Copied the description.");
+    }
+    if cloned.copied_cover {
+        println!("Copied the cover image.");
+    }
+    cloned
+}
+
+fn clone_command(matches: &ArgMatches) {
+    let spotify = provider_from_args(matches);
+    clone_from_args(matches, &*spotify);
+}
+
+fn transfer_command(matches: &ArgMatches) {
+    let from = matches.value_of("from").unwrap();
+    let to = matches.value_of("to").unwrap();
+    let spotify = provider_from_args(matches);
+    let spotify = &*spotify;
+    let cloned = clone_from_args(matches, spotify);
+    let parity = unwrap_api_result(check_parity(spotify, &cloned));
+    if !parity.is_exact() {
+        eprintln!("{} doesn't match {}, so the original was left as is:", to, from);
+        eprintln!("  {} tracks are missing and {} are extra", parity.missing.len(), parity.extra.len());
+        if !parity.same_order {
+            eprintln!("  The tracks are in a different order");
+        }
+        process::exit(exit_code::FAILURE);
+    }
+    println!("{} matches {}.", to, from);
+    if let Some(name) = matches.value_of("rename_original") {
+        unwrap_api_result(spotify.rename_playlist(&cloned.source_id, name));
+        println!("Renamed {} to {}.", from, name);
+    }
+    let unfollowed = matches.is_present("unfollow_original");
+    if unfollowed {
+        unwrap_api_result(spotify.unfollow_playlist(&cloned.source_id));
+        println!("Unfollowed {}.", from);
+    }
+    println!();
+    println!("Followers of {} aren't moved to the new playlist. To bring them over:", from);
+    println!("  - Share the new playlist: https://open.spotify.com/playlist/{}", cloned.playlist_id);
+    if !matches.is_present("rename_original") && !unfollowed {
+        println!("  - Mention the move in the original's name, eg. with --rename-original");
+    }
+    if !unfollowed {
+        println!("  - Once they've moved, unfollow the original, or run this again with --unfollow-original");
+    }
+}
+
+fn snapshot_command(matches: &ArgMatches) {
+    let playlist_name = matches.value_of("playlist_name").unwrap();
+    let out = matches.value_of("out").unwrap();
+    let spotify = login_from_args(matches);
+    let playlist_id = match spotify.get_playlist_id(playlist_name) {
+        Err(PlaylistError::PlaylistNotFound(e)) => {
+            eprintln!("{}: {}", e, playlist_name);
+            process::exit(exit_code::FAILURE);
+        },
+        result => unwrap_playlist_result(result),
+    };
+    let snapshot = unwrap_api_result(take_snapshot(
+        &spotify, playlist_name, &playlist_id, &Local::now().to_rfc3339()
+    ));
+    snapshot.write(out).unwrap();
+    println!("Saved {} tracks to {}", snapshot.tracks.len(), out);
+}
+
+fn restore_command(matches: &ArgMatches) {
+    let snapshot_filename = matches.value_of("snapshot").unwrap();
+    let dry_run = matches.is_present("dry_run");
+    let snapshot = match matches.value_of("verify_key") {
+        Some(key_filename) => read_verified_file(snapshot_filename, key_filename)
+            .and_then(|data| Snapshot::parse(&data)),
+        None => Snapshot::read(snapshot_filename),
+    }.unwrap_or_else(|e| {
+        eprintln!("Could not read snapshot: {}", e);
+        process::exit(exit_code::FAILURE);
+    });
+    let playlist_name = matches.value_of("playlist_name")
+        .unwrap_or(&snapshot.manifest.playlist_name);
+    let spotify = login_from_args(matches);
+    let edits = sync_songs(
+        &spotify, playlist_name, &snapshot.tracks, None, &ChangeLimits::default(),
+        CreatePolicy::default(), Visibility::default(), dry_run
+    );
+    for edit in &edits {
+        println!("{}", edit);
+    }
+    if dry_run {
+        println!("{} changes would be made to restore \"{}\" from {}.",
+                 edits.len(), playlist_name, snapshot.manifest.created);
+    } else {
+        println!("Restored \"{}\" from {} with {} changes.",
+                 playlist_name, snapshot.manifest.created, edits.len());
+    }
+}
+
+fn snapshot_order_command(matches: &ArgMatches) {
+    let playlist_name = matches.value_of("playlist_name").unwrap();
+    let out = matches.value_of("out").unwrap();
+    let spotify = provider_from_args(matches);
+    let playlist_id = unwrap_playlist_result(spotify.get_playlist_id(playlist_name));
+    let snapshot = unwrap_api_result(take_order_snapshot(
+        &*spotify, playlist_name, &playlist_id, &Local::now().to_rfc3339()
+    ));
+    snapshot.write(out).unwrap();
+    println!("Saved the order of {} tracks to {}", snapshot.tracks.len(), out);
+}
+
+fn restore_order_command(matches: &ArgMatches) {
+    let snapshot_filename = matches.value_of("snapshot").unwrap();
+    let dry_run = matches.is_present("dry_run");
+    let snapshot = OrderSnapshot::read(snapshot_filename).unwrap_or_else(|e| {
+        eprintln!("Could not read snapshot: {}", e);
+        process::exit(exit_code::FAILURE);
+    });
+    let playlist_name = matches.value_of("playlist_name").unwrap_or(&snapshot.playlist_name);
+    let spotify = provider_from_args(matches);
+    let spotify = &*spotify;
+    let playlist_id = unwrap_playlist_result(spotify.get_playlist_id(playlist_name));
+    if !dry_run {
+        unwrap_playlist_result(check_can_modify(spotify, &playlist_id));
+    }
+    let moves = unwrap_api_result(restore_order(spotify, &playlist_id, &snapshot, dry_run));
+    for edit in &moves {
+        println!("{}", edit);
+    }
+    if dry_run {
+        println!("{} moves would restore the order of \"{}\" from {}.",
+                 moves.len(), playlist_name, snapshot.created);
+    } else {
+        println!("Restored the order of \"{}\" from {} with {} moves.",
+                 playlist_name, snapshot.created, moves.len());
+    }
+}
+
+/// Print every problem found in a config file
+fn print_diagnostics(filename: &str, diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    eprintln!("Found {} problems in {}.", diagnostics.len(), filename);
+}
+
+fn config_validate_command(matches: &ArgMatches) {
+    let filename = matches.value_of("config").unwrap();
+    match read_config(filename) {
+        Ok(config) => println!("{} is valid with {} jobs.", filename, config.jobs.len()),
+        Err(diagnostics) => {
+            print_diagnostics(filename, &diagnostics);
+            process::exit(exit_code::FAILURE);
+        },
+    }
+}
+
+fn clean_command(matches: &ArgMatches) {
+    let filename = matches.value_of("config").unwrap();
+    let config = read_config(filename).unwrap_or_else(|diagnostics| {
+        print_diagnostics(filename, &diagnostics);
+        process::exit(exit_code::FAILURE);
+    });
+    let state_dir = config.state_dir();
+    if matches.is_present("all") {
+        let cleaned = clean_all(&state_dir).unwrap();
+        println!("Deleted the state of {} jobs.", cleaned.len());
+        return;
+    }
+    for job_name in arg_values(matches, "job") {
+        if clean_job(&state_dir, &job_name).unwrap() {
+            println!("Deleted the state of {}.", job_name);
+        } else {
+            println!("{} has no state.", job_name);
+        }
+    }
+}
+
+/// Add the tracks that a job added to the daemon's Atom feed
+fn publish_to_feed(spotify: &PlaylistAPI<failure::Error>, feed_filename: &str, config_filename: &str,
+                   state_dir: &Path, job: &Job,
+                   changes: &JobChanges) -> Result<(), Box<Error>> {
+    fs::create_dir_all(state_dir)?;
+    let entries_filename = state_dir.join(feed::ENTRIES_FILENAME).to_string_lossy().into_owned();
+    let mut feed = Feed::read(&entries_filename)?;
+    feed.add(FeedEntry {
+        job_name: job.name.clone(),
+        playlist_name: job.playlist.clone(),
+        playlist_url: format!("https://open.spotify.com/playlist/{}", changes.playlist_id),
+        updated: Utc::now().to_rfc3339(),
+        tracks: spotify.get_tracks_metadata(&changes.added).map_err(|e| e.to_string())?,
+    });
+    feed.write(&entries_filename)?;
+    feed.write_atom(
+        feed_filename,
+        &format!("Tracks added by {}", config_filename),
+        &format!("urn:playlist-from-csv:{}", config_filename)
+    )
+}
+
+fn daemon_command(matches: &ArgMatches) {
+    let filename = matches.value_of("config").unwrap();
+    let feed_filename = matches.value_of("feed");
+    let mut watcher = ConfigWatcher::new(filename).unwrap_or_else(|diagnostics| {
+        print_diagnostics(filename, &diagnostics);
+        process::exit(exit_code::FAILURE);
+    });
+    let mut schedule = Schedule::default();
+    schedule.set_quiet_hours(parse_arg(matches, "quiet_hours"));
+    let drip: Option<DripRate> = parse_arg(matches, "drip");
+    #[cfg(unix)]
+    let control = matches.value_of("control_socket").map(|path| listen(path).unwrap_or_else(|e| {
+        eprintln!("Could not listen on {}: {}", path, e);
+        process::exit(exit_code::FAILURE);
+    }));
+    println!("Running {} jobs from {}.", watcher.config().jobs.len(), filename);
+    loop {
+        match watcher.reload_if_changed() {
+            Some(Ok(())) => println!("Reloaded {} with {} jobs.", filename, watcher.config().jobs.len()),
+            Some(Err(diagnostics)) => {
+                print_diagnostics(filename, &diagnostics);
+                eprintln!("Still running the previous config.");
+            },
+            None => {},
+        }
+        let now = Instant::now();
+        let due: Vec<Job> = schedule.due_jobs(watcher.config(), now, Local::now().time())
+            .into_iter()
+            .cloned()
+            .map(|mut job| {
+                // Import jobs without their own rate use --drip
+                if job.mode == Mode::Import && job.drip.is_none() {
+                    job.drip = drip.map(|drip| drip.to_string());
+                }
+                job
+            })
+            .collect();
+        if !due.is_empty() {
+            let state_dir = watcher.config().state_dir();
+            // Log in again each time so that the cached token is refreshed
+            let spotify = provider_from_args(matches);
+            let spotify = &*spotify;
+            let due: Vec<&Job> = due.iter().collect();
+            run_in_order(
+                &due,
+                |job| {
+                    let changes = run_job(spotify, job, &state_dir).map_err(|e| e.to_string())?;
+                    if let (Some(feed_filename), false) = (feed_filename, changes.added.is_empty()) {
+                        let published = publish_to_feed(
+                            spotify, feed_filename, filename, &state_dir, job, &changes
+                        );
+                        if let Err(e) = published {
+                            eprintln!("{}: could not update {}: {}", job.name, feed_filename, e);
+                        }
+                    }
+                    Ok(changes.changes)
+                },
+                |job, outcome| {
+                    match *outcome {
+                        JobOutcome::Changed(_) => println!("{}: {}.", job.name, outcome),
+                        _ => eprintln!("{}: {}.", job.name, outcome),
+                    }
+                    schedule.record_outcome(&job.name, outcome);
+                }
+            );
+            for job in due {
+                schedule.record_run(&job.name, now);
+            }
+        }
+        #[cfg(unix)]
+        {
+            if let Some(ref requests) = control {
+                handle_control_requests(requests, &mut schedule, watcher.config(), Duration::from_secs(5));
+                continue;
+            }
+        }
+        thread::sleep(Duration::from_secs(5));
+    }
+}
+
+/// Handle the commands sent to `--control-socket` until it's time to check
+/// for due jobs again. A triggered job stops the wait so that it runs
+/// straight away.
+#[cfg(unix)]
+fn handle_control_requests(requests: &std::sync::mpsc::Receiver<ControlRequest>,
+                           schedule: &mut Schedule,
+                           config: &Config,
+                           wait: Duration) {
+    let until = Instant::now() + wait;
+    while let Some(remaining) = until.checked_duration_since(Instant::now()) {
+        let request = match requests.recv_timeout(remaining) {
+            Ok(request) => request,
+            Err(_) => return,
+        };
+        match request.command.clone() {
+            ControlCommand::Trigger(name) => {
+                if config.jobs.iter().any(|job| job.name == name) {
+                    schedule.trigger(&name);
+                    request.reply(&format!("triggered {}", name));
+                    return;
+                }
+                request.reply(&format!("error: there's no job named {:?}", name));
+            },
+            ControlCommand::Status => request.reply(&schedule.status(config, Instant::now())),
+            ControlCommand::Pause => {
+                schedule.set_paused(true);
+                println!("Paused, only triggered jobs will run.");
+                request.reply("paused");
+            },
+            ControlCommand::Resume => {
+                schedule.set_paused(false);
+                println!("Resumed.");
+                request.reply("resumed");
+            },
+        }
+    }
+}
+
+fn completions_command(matches: &ArgMatches) {
+    let shell: Shell = parse_arg(matches, "shell").unwrap();
+    build_cli().gen_completions_to("playlist-from-csv", shell, &mut stdout());
+}
+
+#[cfg(feature = "lambda")]
+fn lambda_command(matches: &ArgMatches) {
+    let playlist_name = matches.value_of("playlist_name").unwrap();
+    let columns = columns_from_args(matches);
+    let result = run_lambda(|event| {
+        // Logging in for each event picks up the token once it's refreshed
+        let spotify = provider_from_args(matches);
+        handle_stream_event(&*spotify, playlist_name, &columns, event)
+    });
+    if let Err(e) = result {
+        eprintln!("The Lambda runtime failed: {}", e);
+        process::exit(exit_code::FAILURE);
+    }
+}
+
+#[cfg(feature = "gui")]
+fn gui_command(matches: &ArgMatches) {
+    let spotify = provider_from_args(matches);
+    let stdin = io::stdin();
+    if let Err(e) = serve_invokes(&*spotify, stdin.lock(), stdout()) {
+        eprintln!("Could not answer the GUI: {}", e);
+        process::exit(exit_code::FAILURE);
+    }
+}
+
+fn auth_command(matches: &ArgMatches) {
+    // Logging in caches the token, so later runs don't need to prompt
+    let spotify = login_from_args(matches);
+    println!("Logged in as {}", spotify.username());
+}
+
+fn list_command(matches: &ArgMatches) {
+    let spotify = provider_from_args(matches);
+    let playlists = list_playlists(&*spotify).unwrap_or_else(|e| exit_on_command_error(e));
+    print!("{}", format_playlist_table(&playlists));
+}
+
+fn profile_command(matches: &ArgMatches) {
+    let csv_filename = &local_filename(matches, matches.value_of("csv").unwrap());
+    let spotify = login_from_args(matches);
+    let mut stages = Vec::new();
+    let watch = Stopwatch::start(spotify.requests_made());
+    let songs = read_song_file(matches, csv_filename);
+    stages.push(watch.stop("parse", songs.len(), spotify.requests_made()));
+    let watch = Stopwatch::start(spotify.requests_made());
+    let tracks = get_unique_track_ids(&songs);
+    stages.push(watch.stop("normalize", tracks.len(), spotify.requests_made()));
+    let watch = Stopwatch::start(spotify.requests_made());
+    let matched = unwrap_api_result(spotify.get_tracks_metadata(&tracks));
+    stages.push(watch.stop("match", matched.len(), spotify.requests_made()));
+    if let Some(playlist_name) = matches.value_of("playlist") {
+        let watch = Stopwatch::start(spotify.requests_made());
+        let playlist_id = unwrap_playlist_result(spotify.get_playlist_id(playlist_name));
+        let new_tracks = unwrap_api_result(filter_duplicates(&spotify, &playlist_id, tracks));
+        stages.push(watch.stop("dedupe", new_tracks.len(), spotify.requests_made()));
+        if matches.is_present("add") {
+            let watch = Stopwatch::start(spotify.requests_made());
+            unwrap_api_result(spotify.add_tracks_to_playlist(&playlist_id, &new_tracks, None));
+            stages.push(watch.stop("add", new_tracks.len(), spotify.requests_made()));
+        }
+    }
+    print!("{}", format_breakdown(&stages));
+}
+
+fn serve_command(matches: &ArgMatches) {
+    let address = matches.value_of("address").unwrap();
+    let socket_address: SocketAddr = address.parse().unwrap_or_else(|e| {
+        eprintln!("Invalid address {}: {}", address, e);
+        process::exit(exit_code::FAILURE);
+    });
+    // The arguments for each integration are required together by the parser
+    let contribute = matches.value_of("contribute_secret").map(|secret| ContributeConfig {
+        secret: secret.to_owned(),
+        user_id: matches.value_of("contribute_user").unwrap().to_owned(),
+        uploads_per_hour: parse_arg(matches, "uploads_per_hour").unwrap(),
+    });
+    let slack = matches.value_of("slack_signing_secret").map(|secret| SlackConfig {
+        signing_secret: secret.to_owned(),
+        user_id: matches.value_of("slack_user").unwrap().to_owned(),
+        playlist_name: matches.value_of("slack_playlist").unwrap().to_owned(),
+    });
+    let details = login_details(matches);
+    let config = ServerConfig {
+        client_id: details.client_id,
+        client_secret: details.client_secret,
+        base_url: matches.value_of("base_url")
+            .map(|url| url.to_owned())
+            .unwrap_or_else(|| format!("http://{}", address)),
+        token_dir: PathBuf::from(matches.value_of("token_dir").unwrap()),
+        contribute: contribute,
+        slack: slack,
+    };
+    run_server(&socket_address, config).unwrap();
+}
+
+fn telegram_command(matches: &ArgMatches) {
+    let playlist_name = matches.value_of("playlist_name").unwrap();
+    let allowed_chats: Vec<i64> = arg_values(matches, "allow_chat").iter()
+        .map(|chat| chat.parse().unwrap_or_else(|e| {
+            eprintln!("Invalid value for allow_chat: {}", e);
+            process::exit(exit_code::FAILURE);
+        }))
+        .collect();
+    let spotify = login_from_args(matches);
+    let playlist_id = unwrap_playlist_result(
+        get_playlist_id_create_if_needed(&spotify, playlist_name)
+    );
+    let bot_token = matches.value_of("bot_token").unwrap();
+    run_bot(|| login_from_args(matches), &playlist_id, playlist_name, bot_token, &allowed_chats).unwrap();
+}
+
+/// Add a song a station played to the playlist if it can be found on
+/// Spotify and isn't already in the playlist. API errors are logged rather
+/// than stopping the poller, since the next song may work
+fn add_played_song(spotify: &SpotifyAPI, playlist_id: &str, song: &PlayedSong) {
+    let track = match find_track(spotify, &song.title, &song.artist) {
+        Ok(Some(track)) => track,
+        Ok(None) => {
+            warn!("No match on Spotify for: {}", song);
+            return;
+        },
+        Err(e) => {
+            warn!("Could not search Spotify for {}: {}", song, e);
+            return;
+        },
+    };
+    let songs = vec![Song { music: song.to_string(), song_id: track.uri.uri() }];
+    match add_songs_to_playlist(spotify, playlist_id, songs) {
+        Ok(_) => println!("Added {}", song),
+        Err(PlaylistAddError::NoNewTracks(_)) => info!("Already in the playlist: {}", song),
+        Err(PlaylistAddError::APIError(e)) => warn!("Could not add {}: {}", song, e),
+        Err(PlaylistAddError::PlaylistFull(e)) => {
+            eprintln!("{}", e);
+            process::exit(exit_code::PLAYLIST_FULL);
+        },
+        Err(PlaylistAddError::ReadError(e)) => unwrap_csv_result(Err(e)),
+    }
+}
+
+fn radio_command(matches: &ArgMatches) {
+    let playlist_name = matches.value_of("playlist_name").unwrap();
+    let RunTime(every) = parse_arg(matches, "every").unwrap();
+    let station: Box<StationSource> = match matches.value_of("feed_url") {
+        Some(url) => Box::new(JsonFeed {
+            url: url.to_owned(),
+            artist_pointer: matches.value_of("artist_field").unwrap().to_owned(),
+            title_pointer: matches.value_of("title_field").unwrap().to_owned(),
+        }),
+        None => Box::new(RadioParadise { channel: parse_arg(matches, "radio_paradise").unwrap() }),
+    };
+    let spotify = login_from_args(matches);
+    let playlist_id = unwrap_playlist_result(
+        get_playlist_id_create_if_needed(&spotify, playlist_name)
+    );
+    let mut poller = NowPlayingPoller::default();
+    loop {
+        match poller.poll(&*station) {
+            // Log in again each time so that the cached token is refreshed
+            Ok(Some(song)) => add_played_song(&login_from_args(matches), &playlist_id, &song),
+            Ok(None) => {},
+            // The station may be down for a moment, so keep polling
+            Err(e) => warn!("Could not get what's playing: {}", e),
+        }
+        thread::sleep(every);
+    }
+}
+
+/// Show log messages at the level picked with `-v` or `-q`. `RUST_LOG` can
+/// be used to pick levels for each module instead
+fn init_logger(matches: &ArgMatches) {
+    let matches = innermost_matches(matches);
+    let level = if matches.is_present("quiet") {
+        LevelFilter::Error
+    } else {
+        match matches.occurrences_of("verbose") {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    };
+    let mut builder = env_logger::Builder::new();
+    builder.filter(Some("playlist_from_csv"), level)
+        .format(|buf, record| match record.level() {
+            Level::Info => writeln!(buf, "{}", record.args()),
+            level => writeln!(buf, "{}: {}", level, record.args()),
+        });
+    if let Ok(filters) = env::var("RUST_LOG") {
+        builder.parse(&filters);
+    }
+    builder.init();
+}
+
+fn main() {
+    let matches = build_cli().get_matches();
+    init_logger(&matches);
+    let global_matches = innermost_matches(&matches);
+    set_verbose_errors(global_matches.is_present("verbose_errors"));
+    set_lang(parse_arg(global_matches, "lang").unwrap_or_else(Lang::from_env));
+    if let Some(format) = parse_arg(global_matches, "events") {
+        set_event_format(format);
+    }
+    set_user_agent(build_user_agent(
+        global_matches.value_of("user_agent"), global_matches.value_of("app_name")
+    ));
+    match matches.subcommand() {
+        ("auth", Some(m)) => auth_command(m),
+        ("list", Some(m)) => list_command(m),
+        ("import", Some(m)) => import_command(m),
+        ("import-many", Some(m)) => import_many_command(m),
+        ("export", Some(m)) => export_command(m),
+        ("show", Some(m)) => show_command(m),
+        ("bandcamp", Some(m)) => bandcamp_command(m),
+        ("setlist", Some(m)) => setlist_command(m),
+        ("chart", Some(m)) => chart_command(m),
+        ("segment", Some(m)) => segment_command(m),
+        ("recap", Some(m)) => recap_command(m),
+        ("profile", Some(m)) => profile_command(m),
+        #[cfg(feature = "lambda")]
+        ("lambda", Some(m)) => lambda_command(m),
+        #[cfg(feature = "gui")]
+        ("gui", Some(m)) => gui_command(m),
+        ("sync", Some(m)) => sync_command(m),
+        ("plan", Some(m)) => plan_command(m),
+        ("apply", Some(m)) => apply_command(m),
+        ("sign", Some(m)) => sign_command(m),
+        ("preview", Some(m)) => preview_command(m),
+        ("clone", Some(m)) => clone_command(m),
+        ("transfer", Some(m)) => transfer_command(m),
+        ("snapshot", Some(m)) => snapshot_command(m),
+        ("restore", Some(m)) => restore_command(m),
+        ("snapshot-order", Some(m)) => snapshot_order_command(m),
+        ("restore-order", Some(m)) => restore_order_command(m),
+        ("config", Some(m)) => match m.subcommand() {
+            ("validate", Some(m)) => config_validate_command(m),
+            _ => unreachable!(),
+        },
+        ("daemon", Some(m)) => daemon_command(m),
+        ("clean", Some(m)) => clean_command(m),
+        ("remove", Some(m)) => remove_command(m),
+        ("replace", Some(m)) => replace_command(m),
+        ("relink", Some(m)) => relink_command(m),
+        ("set-visibility", Some(m)) => set_visibility_command(m),
+        ("build", Some(m)) => build_command(m),
+        ("serve", Some(m)) => serve_command(m),
+        ("telegram", Some(m)) => telegram_command(m),
+        ("radio", Some(m)) => radio_command(m),
+        ("completions", Some(m)) => completions_command(m),
+        // The parser shows the help when there's no subcommand
+        _ => unreachable!(),
+    }
 }
@@ -0,0 +1,334 @@
+use std::cmp::Reverse;
+use std::error::Error;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::sync::{Mutex, MutexGuard};
+
+use events::{emit,Event};
+use playlist::{AudioFeatures,PlaylistAPI,PlaylistDetails,PlaylistError,PlaylistInfo,PlaylistNotFound,TrackAvailability,TrackMetadata,TrackVersion,Visibility};
+use track_uri::TrackUri;
+
+extern crate failure;
+
+extern crate serde_json;
+
+/// The owner shown for every playlist in the sandbox
+const SANDBOX_OWNER: &str = "sandbox";
+
+/// A playlist kept by `MemoryAPI`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct MemoryPlaylist {
+    id: String,
+    name: String,
+    public: bool,
+    description: Option<String>,
+    /// The size of the cover image that was uploaded, if any
+    cover_size: Option<usize>,
+    tracks: Vec<TrackUri>,
+    /// Bumped whenever the playlist is changed, like Spotify's snapshot ID
+    snapshot: u64,
+}
+
+/// Everything in the sandbox, as it's saved between runs
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct MemoryState {
+    playlists: Vec<MemoryPlaylist>,
+    /// Used to give each playlist its own ID
+    next_id: u64,
+}
+
+/// A playlist provider that keeps everything in memory and saves it to a JSON
+/// file after each change. This needs no credentials, so that imports and
+/// syncs can be rehearsed before they're pointed at Spotify. There's no
+/// catalogue, so searches find nothing and tracks are named by their URI.
+pub struct MemoryAPI {
+    filename: String,
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryAPI {
+    /// Open the sandbox saved in the file. A missing file is treated as an
+    /// empty sandbox
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the state file
+    pub fn open(filename: &str) -> Result<MemoryAPI, Box<Error>> {
+        let state = match File::open(filename) {
+            Ok(file) => serde_json::from_reader(file)?,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => MemoryState::default(),
+            Err(e) => return Err(Box::new(e)),
+        };
+        Ok(MemoryAPI { filename: filename.to_owned(), state: Mutex::new(state) })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MemoryState> {
+        self.state.lock().unwrap()
+    }
+
+    fn save(&self, state: &MemoryState) -> Result<(), failure::Error> {
+        let file = File::create(&self.filename)?;
+        serde_json::to_writer_pretty(file, state)?;
+        Ok(())
+    }
+
+    /// Read a playlist with the given ID
+    fn read<T, F>(&self, playlist_id: &str, f: F) -> Result<T, failure::Error>
+            where F: FnOnce(&MemoryPlaylist) -> T {
+        let state = self.lock();
+        match state.playlists.iter().find(|p| p.id == playlist_id) {
+            Some(playlist) => Ok(f(playlist)),
+            None => Err(no_playlist(playlist_id)),
+        }
+    }
+
+    /// Change a playlist with the given ID and save the sandbox
+    fn change<F>(&self, playlist_id: &str, f: F) -> Result<(), failure::Error>
+            where F: FnOnce(&mut MemoryPlaylist) -> Result<(), failure::Error> {
+        let mut state = self.lock();
+        match state.playlists.iter_mut().find(|p| p.id == playlist_id) {
+            Some(playlist) => {
+                f(playlist)?;
+                playlist.snapshot += 1;
+            },
+            None => return Err(no_playlist(playlist_id)),
+        }
+        self.save(&state)
+    }
+}
+
+fn no_playlist(playlist_id: &str) -> failure::Error {
+    failure::err_msg(format!("No playlist with ID {} in the sandbox", playlist_id))
+}
+
+/// The metadata for a track, which only knows its URI
+fn track_metadata(uri: &TrackUri) -> TrackMetadata {
+    TrackMetadata {
+        uri: uri.clone(),
+        name: uri.uri(),
+        artists: Vec::new(),
+        album: String::new(),
+        duration_ms: 0,
+        image_url: None,
+    }
+}
+
+impl PlaylistAPI<failure::Error> for MemoryAPI {
+    fn get_playlist_id(&self, playlist_name: &str) -> Result<String, PlaylistError<failure::Error>> {
+        let state = self.lock();
+        state.playlists.iter()
+            .find(|p| p.name == playlist_name)
+            .map(|p| p.id.clone())
+            .ok_or(PlaylistError::PlaylistNotFound(PlaylistNotFound {}))
+    }
+
+    fn create_playlist(&self, playlist_name: &str, visibility: Visibility) -> Result<String, failure::Error> {
+        let mut state = self.lock();
+        state.next_id += 1;
+        let id = format!("sandbox{}", state.next_id);
+        state.playlists.push(MemoryPlaylist {
+            id: id.clone(),
+            name: playlist_name.to_owned(),
+            public: visibility == Visibility::Public,
+            ..MemoryPlaylist::default()
+        });
+        self.save(&state)?;
+        Ok(id)
+    }
+
+    fn add_tracks_to_playlist(&self, playlist_id: &str, tracks: &[TrackUri], position: Option<usize>) -> Result<(), failure::Error> {
+        self.change(playlist_id, |playlist| {
+            // Like Spotify, items that can't be added are skipped
+            let addable: Vec<TrackUri> = tracks.iter().filter(|t| t.is_addable()).cloned().collect();
+            let index = position.unwrap_or(playlist.tracks.len()).min(playlist.tracks.len());
+            let after = playlist.tracks.split_off(index);
+            // The sandbox has no limit on each request, so it's one batch
+            emit(&Event::BatchAdded { playlist_id: playlist_id, batch: 1, batches: 1, tracks: addable.len() });
+            playlist.tracks.extend(addable);
+            playlist.tracks.extend(after);
+            Ok(())
+        })
+    }
+
+    fn get_track_ids_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackUri>, failure::Error> {
+        self.read(playlist_id, |playlist| playlist.tracks.clone())
+    }
+
+    fn get_tracks_with_metadata_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackMetadata>, failure::Error> {
+        self.read(playlist_id, |playlist| playlist.tracks.iter().map(track_metadata).collect())
+    }
+
+    fn get_playlist_snapshot_id(&self, playlist_id: &str) -> Result<String, failure::Error> {
+        self.read(playlist_id, |playlist| playlist.snapshot.to_string())
+    }
+
+    fn get_playlist_size(&self, playlist_id: &str) -> Result<usize, failure::Error> {
+        self.read(playlist_id, |playlist| playlist.tracks.len())
+    }
+
+    fn get_playlist_details(&self, playlist_id: &str) -> Result<PlaylistDetails, failure::Error> {
+        self.read(playlist_id, |playlist| PlaylistDetails { description: playlist.description.clone() })
+    }
+
+    fn get_cover_image(&self, playlist_id: &str) -> Result<Option<Vec<u8>>, failure::Error> {
+        // Only the size of an uploaded cover is kept, not the image
+        self.read(playlist_id, |_| None)
+    }
+
+    fn get_tracks_metadata(&self, tracks: &[TrackUri]) -> Result<Vec<TrackMetadata>, failure::Error> {
+        Ok(tracks.iter().filter(|t| t.id().is_some()).map(track_metadata).collect())
+    }
+
+    fn update_playlist_details(&self, playlist_id: &str, details: &PlaylistDetails) -> Result<(), failure::Error> {
+        self.change(playlist_id, |playlist| {
+            if let Some(ref description) = details.description {
+                playlist.description = Some(description.clone());
+            }
+            Ok(())
+        })
+    }
+
+    fn set_playlist_visibility(&self, playlist_id: &str, visibility: Visibility) -> Result<(), failure::Error> {
+        self.change(playlist_id, |playlist| {
+            playlist.public = visibility == Visibility::Public;
+            Ok(())
+        })
+    }
+
+    fn upload_cover_image(&self, playlist_id: &str, jpeg: &[u8]) -> Result<(), failure::Error> {
+        self.change(playlist_id, |playlist| {
+            playlist.cover_size = Some(jpeg.len());
+            Ok(())
+        })
+    }
+
+    #[allow(unused_variables)]
+    fn can_modify_playlist(&self, playlist_id: &str) -> Result<bool, failure::Error> {
+        Ok(true)
+    }
+
+    fn rename_playlist(&self, playlist_id: &str, name: &str) -> Result<(), failure::Error> {
+        self.change(playlist_id, |playlist| {
+            playlist.name = name.to_owned();
+            Ok(())
+        })
+    }
+
+    fn unfollow_playlist(&self, playlist_id: &str) -> Result<(), failure::Error> {
+        let mut state = self.lock();
+        let before = state.playlists.len();
+        state.playlists.retain(|p| p.id != playlist_id);
+        if state.playlists.len() == before {
+            return Err(no_playlist(playlist_id));
+        }
+        self.save(&state)
+    }
+
+    fn remove_tracks_from_playlist(&self, playlist_id: &str, tracks: &[TrackUri]) -> Result<(), failure::Error> {
+        self.change(playlist_id, |playlist| {
+            playlist.tracks.retain(|track| !tracks.contains(track));
+            Ok(())
+        })
+    }
+
+    fn insert_tracks_into_playlist(&self, playlist_id: &str, tracks: &[TrackUri], position: usize) -> Result<(), failure::Error> {
+        self.add_tracks_to_playlist(playlist_id, tracks, Some(position))
+    }
+
+    fn remove_tracks_at_positions(&self, playlist_id: &str, occurrences: &[(TrackUri, usize)]) -> Result<(), failure::Error> {
+        self.change(playlist_id, |playlist| {
+            let mut positions: Vec<&(TrackUri, usize)> = occurrences.iter().collect();
+            // Removing from the end keeps the earlier positions the same
+            positions.sort_by_key(|&&(_, position)| Reverse(position));
+            for &(ref track, position) in positions {
+                if playlist.tracks.get(position) != Some(track) {
+                    return Err(failure::err_msg(format!(
+                        "{} isn't at position {}", track.uri(), position
+                    )));
+                }
+                playlist.tracks.remove(position);
+            }
+            Ok(())
+        })
+    }
+
+    fn move_track_in_playlist(&self, playlist_id: &str, from: usize, insert_before: usize) -> Result<(), failure::Error> {
+        self.change(playlist_id, |playlist| {
+            if from >= playlist.tracks.len() || insert_before > playlist.tracks.len() {
+                return Err(failure::err_msg("Position is outside the playlist"));
+            }
+            let track = playlist.tracks.remove(from);
+            let index = if insert_before > from { insert_before - 1 } else { insert_before };
+            playlist.tracks.insert(index, track);
+            Ok(())
+        })
+    }
+
+    fn list_playlists(&self) -> Result<Vec<PlaylistInfo>, failure::Error> {
+        Ok(self.lock().playlists.iter().map(|p| PlaylistInfo {
+            id: p.id.clone(),
+            name: p.name.clone(),
+            track_count: p.tracks.len() as u32,
+            public: p.public,
+            owner: SANDBOX_OWNER.to_owned(),
+        }).collect())
+    }
+
+    #[allow(unused_variables)]
+    fn search_tracks(&self, query: &str, limit: u32) -> Result<Vec<TrackMetadata>, failure::Error> {
+        Ok(Vec::new())
+    }
+
+    #[allow(unused_variables)]
+    fn get_track_versions(&self, tracks: &[TrackUri]) -> Result<Vec<TrackVersion>, failure::Error> {
+        Ok(Vec::new())
+    }
+
+    #[allow(unused_variables)]
+    fn get_audio_features(&self, tracks: &[TrackUri]) -> Result<Vec<AudioFeatures>, failure::Error> {
+        Ok(Vec::new())
+    }
+
+    /// The sandbox has no markets, so every track can be played everywhere
+    #[allow(unused_variables)]
+    fn get_track_availability(&self, tracks: &[TrackUri], market: &str) -> Result<Vec<TrackAvailability>, failure::Error> {
+        Ok(tracks.iter()
+            .filter(|t| t.id().is_some())
+            .map(|t| TrackAvailability { track: track_metadata(t), playable: true, relinked: None })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn track(id: &str) -> TrackUri {
+        TrackUri::Track(format!("{:0<22}", id))
+    }
+
+    #[test]
+    fn memory_api_keeps_playlists_between_runs() {
+        // Given
+        let path = env::temp_dir().join("playlist-from-csv-sandbox-test.json");
+        let filename = path.to_str().unwrap();
+        let _ = ::std::fs::remove_file(filename);
+        let api = MemoryAPI::open(filename).unwrap();
+        let id = api.create_playlist("Road Trip", Visibility::Private).unwrap();
+        api.add_tracks_to_playlist(&id, &[track("a"), track("b"), track("c")], None).unwrap();
+        api.add_tracks_to_playlist(&id, &[track("d")], Some(0)).unwrap();
+        api.move_track_in_playlist(&id, 0, 4).unwrap();
+        api.remove_tracks_at_positions(&id, &[(track("b"), 1)]).unwrap();
+        // When
+        let reopened = MemoryAPI::open(filename).unwrap();
+        // Then
+        assert_eq!(Ok(id.clone()), reopened.get_playlist_id("Road Trip").map_err(|_| ()));
+        assert_eq!(
+            vec![track("a"), track("c"), track("d")],
+            reopened.get_track_ids_in_playlist(&id).unwrap()
+        );
+        assert_eq!("4", reopened.get_playlist_snapshot_id(&id).unwrap());
+        assert!(reopened.get_playlist_id("Other").is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use csv_to_playlist::{has_track_id,Song};
+
+/// Which source wins when the same track has different details in
+/// different sources
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Priority {
+    /// Sources given earlier win
+    FirstSource,
+    /// Sources given later win, eg. for newer exports
+    LastSource,
+}
+
+/// An error when a priority can't be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidPriority {}
+
+impl Error for InvalidPriority {
+    fn description(&self) -> &str {
+        "Priority must be `first` or `last`"
+    }
+}
+
+impl fmt::Display for InvalidPriority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl FromStr for Priority {
+    type Err = InvalidPriority;
+
+    fn from_str(s: &str) -> Result<Priority, InvalidPriority> {
+        match s {
+            "first" => Ok(Priority::FirstSource),
+            "last" => Ok(Priority::LastSource),
+            _ => Err(InvalidPriority {}),
+        }
+    }
+}
+
+/// A row that lost to a row from a higher priority source
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Conflict {
+    pub source: String,
+    pub music: String,
+}
+
+/// Where a merged song came from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Provenance {
+    pub song_id: String,
+    pub music: String,
+    /// The source that the song's details were taken from
+    pub source: String,
+    /// Rows from other sources with different details for the same track
+    pub conflicts: Vec<Conflict>,
+}
+
+/// The songs from all sources, with each song's provenance in the same
+/// order
+#[derive(Debug, Clone)]
+pub struct MergeResult {
+    pub songs: Vec<Song>,
+    pub provenance: Vec<Provenance>,
+}
+
+/// The key used to match rows across sources. Rows without a track ID are
+/// matched by name.
+fn merge_key(song: &Song) -> String {
+    if has_track_id(song) {
+        format!("id:{}", song.song_id)
+    } else {
+        format!("music:{}", song.music)
+    }
+}
+
+/// Combine songs from several sources. Each track is included once, using
+/// the details from the highest priority source that has it. Rows with
+/// different details from lower priority sources are recorded as conflicts.
+///
+/// # Arguments
+///
+/// * `sources` - The name of each source and its songs
+/// * `priority` - Which sources win conflicts
+pub fn merge_sources(sources: Vec<(String, Vec<Song>)>, priority: Priority) -> MergeResult {
+    let mut sources = sources;
+    if priority == Priority::LastSource {
+        sources.reverse();
+    }
+    let mut result = MergeResult { songs: Vec::new(), provenance: Vec::new() };
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for (source, songs) in sources {
+        for song in songs {
+            let key = merge_key(&song);
+            if let Some(&i) = positions.get(&key) {
+                if result.songs[i].music != song.music {
+                    result.provenance[i].conflicts.push(Conflict {
+                        source: source.to_owned(),
+                        music: song.music,
+                    });
+                }
+                continue;
+            }
+            positions.insert(key, result.songs.len());
+            result.provenance.push(Provenance {
+                song_id: song.song_id.to_owned(),
+                music: song.music.to_owned(),
+                source: source.to_owned(),
+                conflicts: Vec::new(),
+            });
+            result.songs.push(song);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(music: &str, song_id: &str) -> Song {
+        Song { music: music.to_string(), song_id: song_id.to_string() }
+    }
+
+    fn test_setup() -> Vec<(String, Vec<Song>)> {
+        vec![
+            ("a.csv".to_string(), vec![song("Robyn - Hang With Me", "1"), song("Queen", "2")]),
+            ("b.csv".to_string(), vec![song("Queen", "2"), song("Robyn – Hang with me", "1"), song("ABBA", "3")]),
+        ]
+    }
+
+    #[test]
+    fn merge_sources_includes_each_track_once() {
+        // When
+        let result = merge_sources(test_setup(), Priority::FirstSource);
+        // Then
+        let ids: Vec<&str> = result.songs.iter().map(|s| s.song_id.as_str()).collect();
+        assert_eq!(vec!["1", "2", "3"], ids);
+        assert_eq!("b.csv", result.provenance[2].source);
+    }
+
+    #[test]
+    fn merge_sources_first_source_wins_conflicts() {
+        // When
+        let result = merge_sources(test_setup(), Priority::FirstSource);
+        // Then
+        assert_eq!("Robyn - Hang With Me", result.songs[0].music);
+        assert_eq!("a.csv", result.provenance[0].source);
+        let expected = vec![Conflict {
+            source: "b.csv".to_string(),
+            music: "Robyn – Hang with me".to_string(),
+        }];
+        assert_eq!(expected, result.provenance[0].conflicts);
+        // Identical rows aren't conflicts
+        assert!(result.provenance[1].conflicts.is_empty());
+    }
+
+    #[test]
+    fn merge_sources_last_source_wins_conflicts() {
+        // When
+        let result = merge_sources(test_setup(), Priority::LastSource);
+        // Then
+        let robyn = result.songs.iter().position(|s| s.song_id == "1").unwrap();
+        assert_eq!("Robyn – Hang with me", result.songs[robyn].music);
+        assert_eq!("b.csv", result.provenance[robyn].source);
+        assert_eq!("a.csv", result.provenance[robyn].conflicts[0].source);
+    }
+
+    #[test]
+    fn merge_sources_matches_rows_without_id_by_name() {
+        // Given
+        let sources = vec![
+            ("a.csv".to_string(), vec![song("Unknown", "true")]),
+            ("b.csv".to_string(), vec![song("Unknown", "true"), song("Other", "true")]),
+        ];
+        // When
+        let result = merge_sources(sources, Priority::FirstSource);
+        // Then
+        assert_eq!(2, result.songs.len());
+    }
+}
@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+
+use csv_to_playlist::{add_songs_to_playlist,PlaylistAddError,Song};
+use playlist::{get_playlist_id_create_if_needed,PlaylistAPI,PlaylistError};
+use source::{CsvFile,SongSource};
+
+extern crate csv;
+
+/// A row of a grouped CSV, which has a column for the playlist each song
+/// belongs to
+#[derive(Debug, Deserialize)]
+struct GroupedRow {
+    playlist: String,
+    #[serde(rename = "music (S)")]
+    music: String,
+    #[serde(rename = "song_id (S)")]
+    song_id: String,
+}
+
+/// The column of an `import` CSV that says which playlist each row goes to
+pub const PLAYLIST_COLUMN: &str = "playlist (S)";
+
+/// The songs to import into a single playlist
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistSongs {
+    pub playlist_name: String,
+    pub songs: Vec<Song>,
+}
+
+/// The songs from a CSV that routes its rows to playlists, for one playlist
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutedSongs {
+    pub playlist_name: String,
+    pub songs: Vec<Song>,
+    /// The lines of the playlist's rows that can't be added
+    pub failed_lines: Vec<usize>,
+}
+
+/// The outcome of importing into a single playlist
+#[derive(Debug, PartialEq)]
+pub struct PlaylistImport<E> {
+    pub playlist_name: String,
+    /// The number of songs that were added
+    pub result: Result<usize, PlaylistError<E>>,
+}
+
+/// Read every CSV file in a directory, using each file's name without the
+/// extension as its playlist name. Playlists are sorted by name.
+///
+/// # Arguments
+///
+/// * `dir` - The directory containing the CSV files
+pub fn read_playlist_dir(dir: &str) -> Result<Vec<PlaylistSongs>, Box<Error>> {
+    let mut playlists = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("csv") {
+            continue;
+        }
+        let playlist_name = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(stem) => stem.to_owned(),
+            None => continue,
+        };
+        let songs = CsvFile::dynamodb_export(&path.to_string_lossy()).read_songs()?;
+        playlists.push(PlaylistSongs { playlist_name: playlist_name, songs: songs });
+    }
+    playlists.sort_by(|a, b| a.playlist_name.cmp(&b.playlist_name));
+    Ok(playlists)
+}
+
+/// Read the playlists to import from either a directory of CSVs or a single
+/// grouped CSV file, see `read_playlist_dir` and `parse_grouped_csv`.
+///
+/// # Arguments
+///
+/// * `path` - The directory or CSV file
+pub fn read_playlists(path: &str) -> Result<Vec<PlaylistSongs>, Box<Error>> {
+    if Path::new(path).is_dir() {
+        read_playlist_dir(path)
+    } else {
+        parse_grouped_csv(File::open(path)?)
+    }
+}
+
+/// Parse a CSV with "playlist", "music (S)" and "song_id (S)" columns into
+/// the songs for each playlist. Playlists are kept in the order they first
+/// appear.
+///
+/// # Arguments
+///
+/// * `reader` - Where the CSV data is read from
+pub fn parse_grouped_csv<R: Read>(reader: R) -> Result<Vec<PlaylistSongs>, Box<Error>> {
+    let mut rdr = csv::Reader::from_reader(reader);
+    let mut playlists: Vec<PlaylistSongs> = Vec::new();
+    let mut indexes: HashMap<String, usize> = HashMap::new();
+    for result in rdr.deserialize() {
+        let row: GroupedRow = result?;
+        let playlist_name = row.playlist;
+        let song = Song { music: row.music, song_id: row.song_id };
+        let index = *indexes.entry(playlist_name.clone()).or_insert_with(|| {
+            playlists.push(PlaylistSongs { playlist_name: playlist_name, songs: Vec::new() });
+            playlists.len() - 1
+        });
+        playlists[index].songs.push(song);
+    }
+    Ok(playlists)
+}
+
+/// Group the songs by the playlist their row names. Rows that leave the
+/// playlist empty go to the default playlist. Playlists are kept in the order
+/// they first appear.
+///
+/// # Arguments
+///
+/// * `rows` - The songs in the order they were read, with their lines
+/// * `failed_lines` - The lines of the rows that can't be added, where the
+/// first song is on line 2
+/// * `playlists` - The playlist named on each line, from the
+/// `PLAYLIST_COLUMN`, see `ParsedCsv::column`
+/// * `default_playlist` - Where rows without a playlist go
+pub fn route_songs(rows: Vec<(usize, Song)>,
+                   failed_lines: &[usize],
+                   playlists: &HashMap<usize, String>,
+                   default_playlist: &str) -> Vec<RoutedSongs> {
+    let mut routed: Vec<RoutedSongs> = Vec::new();
+    let mut indexes: HashMap<String, usize> = HashMap::new();
+    for (line, song) in rows {
+        let playlist_name = match playlists.get(&line).map(|name| name.trim()) {
+            Some(name) if !name.is_empty() => name.to_owned(),
+            _ => default_playlist.to_owned(),
+        };
+        let index = *indexes.entry(playlist_name.clone()).or_insert_with(|| {
+            routed.push(RoutedSongs {
+                playlist_name: playlist_name,
+                songs: Vec::new(),
+                failed_lines: Vec::new(),
+            });
+            routed.len() - 1
+        });
+        if failed_lines.contains(&line) {
+            routed[index].failed_lines.push(line);
+        }
+        routed[index].songs.push(song);
+    }
+    routed
+}
+
+/// Add the songs to a playlist, creating it if needed. Returns the number of
+/// songs that were added.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the playlist should be updated
+/// * `playlist` - The playlist name and its songs
+fn import_playlist<E>(playlist_api: &PlaylistAPI<E>,
+                      playlist: PlaylistSongs) -> Result<usize, PlaylistError<E>> {
+    let playlist_id = get_playlist_id_create_if_needed(
+        playlist_api, &playlist.playlist_name
+    )?;
+    match add_songs_to_playlist(playlist_api, &playlist_id, playlist.songs) {
+        Ok(added) => Ok(added),
+        Err(PlaylistAddError::NoNewTracks(_)) => Ok(0),
+        Err(PlaylistAddError::APIError(e)) => Err(PlaylistError::APIError(e)),
+        Err(PlaylistAddError::PlaylistFull(e)) => Err(PlaylistError::PlaylistFull(e)),
+        // The songs were read before the import started
+        Err(PlaylistAddError::ReadError(e)) => unreachable!("{}", e),
+    }
+}
+
+/// Import into many playlists at once. Up to `jobs` playlists are worked on
+/// concurrently, all sharing the same API instance. A failure only stops the
+/// playlist it happened in. Results are returned in the same order as the
+/// playlists.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the playlists should be updated
+/// * `playlists` - The playlist names and their songs
+/// * `jobs` - The most playlists to work on at the same time
+pub fn import_playlists<E: Send>(playlist_api: &(PlaylistAPI<E> + Sync),
+                                 playlists: Vec<PlaylistSongs>,
+                                 jobs: usize) -> Vec<PlaylistImport<E>> {
+    let count = playlists.len();
+    let queue = Mutex::new(playlists.into_iter().enumerate());
+    let results = Mutex::new(Vec::with_capacity(count));
+    thread::scope(|scope| {
+        for _ in 0..jobs.max(1).min(count) {
+            scope.spawn(|| loop {
+                // Release the queue before importing so others can take work
+                let next = queue.lock().unwrap().next();
+                let (index, playlist) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                let playlist_name = playlist.playlist_name.clone();
+                let result = import_playlist(playlist_api, playlist);
+                results.lock().unwrap().push((index, PlaylistImport {
+                    playlist_name: playlist_name,
+                    result: result,
+                }));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|&(index, _)| index);
+    results.into_iter().map(|(_, import)| import).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_api::{FakeError,FakePlaylistAPI};
+    use playlist::{PlaylistNotFound,Visibility};
+    use track_uri::TrackUri;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// A fake API that can be shared between threads. Playlists are named
+    /// after their IDs and track how many imports are running at once
+    struct MockPlaylistAPI {
+        added: Mutex<Vec<(String, Vec<TrackUri>)>>,
+        running: AtomicUsize,
+        most_running: AtomicUsize,
+    }
+
+    impl MockPlaylistAPI {
+        fn new() -> MockPlaylistAPI {
+            MockPlaylistAPI {
+                added: Mutex::new(Vec::new()),
+                running: AtomicUsize::new(0),
+                most_running: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    impl FakePlaylistAPI for MockPlaylistAPI {
+        #[allow(unused_variables)]
+        fn get_playlist_id(&self, playlist_name: &str) -> Result<String, PlaylistError<FakeError>> {
+            // Count imports from when they start until their tracks are added
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most_running.fetch_max(running, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            Err(PlaylistError::PlaylistNotFound(PlaylistNotFound {}))
+        }
+
+        #[allow(unused_variables)]
+        fn create_playlist(&self, playlist_name: &str, visibility: Visibility) -> Result<String, FakeError> {
+            if playlist_name == "broken" {
+                self.running.fetch_sub(1, Ordering::SeqCst);
+                return Err(FakeError {});
+            }
+            Ok(playlist_name.to_owned())
+        }
+
+        #[allow(unused_variables)]
+        fn add_tracks_to_playlist(&self, playlist_id: &str, tracks: &[TrackUri], position: Option<usize>) -> Result<(), FakeError> {
+            self.added.lock().unwrap().push((playlist_id.to_owned(), tracks.to_vec()));
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn playlist(name: &str, ids: &[&str]) -> PlaylistSongs {
+        PlaylistSongs {
+            playlist_name: name.to_string(),
+            songs: ids.iter()
+                .map(|id| Song { music: id.to_string(), song_id: id.to_string() })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn parse_grouped_csv_groups_by_playlist() {
+        // Given
+        let data = "playlist,music (S),song_id (S)\n\
+                    b,Song 1,id10000000000000000000\n\
+                    a,Song 2,id20000000000000000000\n\
+                    b,Song 3,id30000000000000000000\n";
+        // When
+        let result = parse_grouped_csv(data.as_bytes()).unwrap();
+        // Then
+        let mut expected = vec![playlist("b", &["id10000000000000000000", "id30000000000000000000"]), playlist("a", &["id20000000000000000000"])];
+        expected[0].songs[0].music = "Song 1".to_string();
+        expected[0].songs[1].music = "Song 3".to_string();
+        expected[1].songs[0].music = "Song 2".to_string();
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn route_songs_falls_back_to_the_default_playlist() {
+        // Given
+        let songs = playlist("", &["id10000000000000000000", "id20000000000000000000", "", "id40000000000000000000"]).songs;
+        // Line 3 was skipped, so the songs are on lines 2, 4, 5 and 6
+        let rows: Vec<(usize, Song)> = vec![2, 4, 5, 6].into_iter().zip(songs).collect();
+        let playlists: HashMap<usize, String> = vec![(2, "b"), (4, ""), (5, "b")].into_iter()
+            .map(|(line, name)| (line, name.to_string()))
+            .collect();
+        // When
+        let result = route_songs(rows, &[3, 5], &playlists, "default");
+        // Then
+        let expected = vec![
+            RoutedSongs {
+                playlist_name: "b".to_string(),
+                songs: playlist("", &["id10000000000000000000", ""]).songs,
+                failed_lines: vec![5],
+            },
+            RoutedSongs {
+                playlist_name: "default".to_string(),
+                songs: playlist("", &["id20000000000000000000", "id40000000000000000000"]).songs,
+                failed_lines: Vec::new(),
+            },
+        ];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn import_playlists_imports_each_playlist() {
+        // Given
+        let api = MockPlaylistAPI::new();
+        let playlists = vec![
+            playlist("one", &["id10000000000000000000", "id20000000000000000000"]),
+            playlist("broken", &["id30000000000000000000"]),
+            playlist("three", &["id40000000000000000000"]),
+        ];
+        // When
+        let result = import_playlists(&api, playlists, 2);
+        // Then
+        let expected = vec![
+            PlaylistImport { playlist_name: "one".to_string(), result: Ok(2) },
+            PlaylistImport {
+                playlist_name: "broken".to_string(),
+                result: Err(PlaylistError::APIError(FakeError {})),
+            },
+            PlaylistImport { playlist_name: "three".to_string(), result: Ok(1) },
+        ];
+        assert_eq!(expected, result);
+        let mut added = api.added.lock().unwrap().clone();
+        added.sort();
+        let expected_added = vec![
+            ("one".to_string(), vec![TrackUri::Track("id10000000000000000000".to_string()), TrackUri::Track("id20000000000000000000".to_string())]),
+            ("three".to_string(), vec![TrackUri::Track("id40000000000000000000".to_string())]),
+        ];
+        assert_eq!(expected_added, added);
+    }
+
+    #[test]
+    fn import_playlists_limits_concurrent_imports() {
+        // Given
+        let api = MockPlaylistAPI::new();
+        let playlists = (0..6).map(|i| playlist(&i.to_string(), &["id10000000000000000000"])).collect();
+        // When
+        let result = import_playlists(&api, playlists, 2);
+        // Then
+        assert!(result.iter().all(|import| import.result == Ok(1)));
+        assert_eq!(2, api.most_running.load(Ordering::SeqCst));
+    }
+}
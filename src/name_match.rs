@@ -0,0 +1,36 @@
+use playlist::{PlaylistAPI,TrackMetadata};
+
+/// The most tracks to look at when matching a single track
+const TRACK_SEARCH_LIMIT: u32 = 5;
+
+/// Names are compared without case or surrounding space since services
+/// don't always agree on them
+pub fn same_name(a: &str, b: &str) -> bool {
+    a.trim().to_lowercase() == b.trim().to_lowercase()
+}
+
+/// Quotes would end a field filter early, so they're left out of searches
+pub fn search_value(value: &str) -> String {
+    value.replace('"', "")
+}
+
+/// Whether one of the track's artists has this name
+pub fn has_artist(track: &TrackMetadata, artist: &str) -> bool {
+    track.artists.iter().any(|name| same_name(name, artist))
+}
+
+/// Find a track on Spotify with the same name and artist, for sources that
+/// only know what a song is called
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance to search with
+/// * `title` - The name of the track
+/// * `artist` - The name of one of the track's artists
+pub fn find_track<E>(playlist_api: &PlaylistAPI<E>,
+                     title: &str,
+                     artist: &str) -> Result<Option<TrackMetadata>, E> {
+    let query = format!("track:\"{}\" artist:\"{}\"", search_value(title), search_value(artist));
+    let results = playlist_api.search_tracks(&query, TRACK_SEARCH_LIMIT)?;
+    Ok(results.into_iter().find(|track| same_name(&track.name, title) && has_artist(track, artist)))
+}
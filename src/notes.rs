@@ -0,0 +1,129 @@
+use std::collections::{BTreeMap,HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::io::ErrorKind;
+
+use csv_to_playlist::{has_track_id,Song};
+use track_uri::TrackUri;
+
+extern crate serde_json;
+
+/// The CSV columns that a note on each song can be read from, in order of
+/// preference
+pub const NOTE_COLUMNS: [&str; 4] = ["note (S)", "comment (S)", "note", "comment"];
+
+/// Match each song to the note on its line. Songs without a usable ID or
+/// with an empty note are left out, and a later row's note wins.
+///
+/// # Arguments
+///
+/// * `rows` - The songs in the order they were read, with their lines
+/// * `notes` - The note on each line, from the first of `NOTE_COLUMNS` that
+/// the CSV has, see `ParsedCsv::column`
+pub fn notes_by_track(rows: &[(usize, Song)], notes: &HashMap<usize, String>) -> HashMap<TrackUri, String> {
+    rows.iter()
+        .filter_map(|(line, song)| notes.get(line).map(|note| (song, note)))
+        .filter(|&(song, note)| has_track_id(song) && !note.is_empty())
+        .map(|(song, note)| (TrackUri::parse(&song.song_id), note.to_owned()))
+        .filter(|(track, _)| track.is_addable())
+        .collect()
+}
+
+/// The notes on the tracks in each playlist. Spotify can't store a note on
+/// a track, so they're kept in a file between runs instead.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct NoteIndex {
+    /// The note on each track, keyed by playlist name
+    playlists: BTreeMap<String, BTreeMap<TrackUri, String>>,
+}
+
+impl NoteIndex {
+    /// Read an index that was saved using `write`. A missing file is treated
+    /// as an empty index.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the index file
+    pub fn read(filename: &str) -> Result<NoteIndex, Box<Error>> {
+        match File::open(filename) {
+            Ok(file) => Ok(serde_json::from_reader(file)?),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(NoteIndex::default()),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    /// Save the index as JSON
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the index file
+    pub fn write(&self, filename: &str) -> Result<(), Box<Error>> {
+        let file = File::create(filename)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Record the notes on songs that are now in a playlist, replacing any
+    /// earlier note on the same track
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_name` - The playlist the songs are in
+    /// * `songs` - The songs that are in the playlist
+    /// * `notes` - The note on each track, see `notes_by_track`
+    pub fn record(&mut self, playlist_name: &str, songs: &[Song], notes: &HashMap<TrackUri, String>) {
+        let noted: Vec<(TrackUri, String)> = songs.iter()
+            .filter_map(|song| {
+                let track = TrackUri::parse(&song.song_id);
+                notes.get(&track).map(|note| (track, note.to_owned()))
+            })
+            .collect();
+        if !noted.is_empty() {
+            self.playlists.entry(playlist_name.to_owned())
+                .or_default()
+                .extend(noted);
+        }
+    }
+
+    /// Get the note on each track in a playlist
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_name` - The name of the playlist
+    pub fn playlist_notes(&self, playlist_name: &str) -> BTreeMap<TrackUri, String> {
+        self.playlists.get(playlist_name).cloned().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(id: &str) -> Song {
+        Song { music: id.to_string(), song_id: id.to_string() }
+    }
+
+    #[test]
+    fn notes_are_kept_for_each_playlist() {
+        // Given
+        let songs = vec![song("ida0000000000000000000"), song("idb0000000000000000000"), song("true"), song("idc0000000000000000000")];
+        let rows: Vec<(usize, Song)> = songs.iter().cloned().enumerate().map(|(i, song)| (i + 2, song)).collect();
+        let notes: HashMap<usize, String> = vec![(2, "Opener"), (3, ""), (4, "Unusable"), (5, "Closer")].into_iter()
+            .map(|(line, note)| (line, note.to_owned()))
+            .collect();
+        let by_track = notes_by_track(&rows, &notes);
+        let mut index = NoteIndex::default();
+        // When
+        index.record("March", &songs[..2], &by_track);
+        index.record("April", &songs, &by_track);
+        let json = serde_json::to_string(&index).unwrap();
+        // Then
+        assert_eq!(2, by_track.len());
+        assert_eq!(
+            r#"{"playlists":{"April":{"spotify:track:ida0000000000000000000":"Opener","spotify:track:idc0000000000000000000":"Closer"},"March":{"spotify:track:ida0000000000000000000":"Opener"}}}"#,
+            json
+        );
+        assert_eq!(index, serde_json::from_str(&json).unwrap());
+        assert!(index.playlist_notes("May").is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fs::File;
+
+use playlist::PlaylistAPI;
+use sync::{apply_edits,longest_increasing_subsequence,Edit};
+use track_uri::TrackUri;
+
+extern crate serde_json;
+
+/// The order of the tracks in a playlist at a point in time, so that a
+/// curated order can be put back after the playlist is sorted by accident.
+/// Unlike a `Snapshot`, only the order is kept and restoring it never adds
+/// or removes tracks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderSnapshot {
+    /// The name of the playlist when the snapshot was taken
+    pub playlist_name: String,
+    /// The ID of the playlist
+    pub playlist_id: String,
+    /// When the snapshot was taken, eg. 2018-03-01T10:00:00+00:00
+    pub created: String,
+    /// The URIs of the tracks in the order they were in the playlist
+    pub tracks: Vec<String>,
+}
+
+impl OrderSnapshot {
+    /// Read a snapshot that was saved using `write`
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the snapshot file
+    pub fn read(filename: &str) -> Result<OrderSnapshot, Box<Error>> {
+        Ok(serde_json::from_reader(File::open(filename)?)?)
+    }
+
+    /// Save the snapshot as JSON
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the snapshot file
+    pub fn write(&self, filename: &str) -> Result<(), Box<Error>> {
+        serde_json::to_writer_pretty(File::create(filename)?, self)?;
+        Ok(())
+    }
+}
+
+/// Take a snapshot of the order of the tracks in a playlist
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the playlist is
+/// * `playlist_name` - The name of the playlist
+/// * `playlist_id` - The ID of the playlist
+/// * `created` - When the snapshot is being taken
+pub fn take_order_snapshot<E>(playlist_api: &PlaylistAPI<E>,
+                              playlist_name: &str,
+                              playlist_id: &str,
+                              created: &str) -> Result<OrderSnapshot, E> {
+    let tracks = playlist_api.get_track_ids_in_playlist(playlist_id)?;
+    Ok(OrderSnapshot {
+        playlist_name: playlist_name.to_owned(),
+        playlist_id: playlist_id.to_owned(),
+        created: created.to_owned(),
+        tracks: tracks.iter().map(TrackUri::uri).collect(),
+    })
+}
+
+/// Get the order to put the current items in, as their positions in
+/// `current`. Items are put in the order of the snapshot, matching
+/// duplicates in turn. Items that weren't in the snapshot go at the end in
+/// their current order.
+fn get_restored_order(current: &[TrackUri], snapshot: &[TrackUri]) -> Vec<usize> {
+    let mut positions: HashMap<&TrackUri, VecDeque<usize>> = HashMap::new();
+    for (position, track) in current.iter().enumerate() {
+        positions.entry(track).or_default().push_back(position);
+    }
+    let mut order: Vec<usize> = snapshot.iter()
+        .filter_map(|track| positions.get_mut(track).and_then(VecDeque::pop_front))
+        .collect();
+    let placed: HashSet<usize> = order.iter().cloned().collect();
+    order.extend((0..current.len()).filter(|position| !placed.contains(position)));
+    order
+}
+
+/// Work out the moves that put the items of `current` in `order`. Items
+/// that are already in order relative to each other are left where they
+/// are, like `sync::compute_edits`, but duplicates are moved rather than
+/// removed.
+fn compute_moves(current: &[TrackUri], order: &[usize]) -> Vec<Edit> {
+    let mut target_indexes = vec![0; order.len()];
+    for (i, &item) in order.iter().enumerate() {
+        target_indexes[item] = i;
+    }
+    // Each item is known by its position in `current`
+    let mut state: Vec<usize> = (0..current.len()).collect();
+    let in_place = longest_increasing_subsequence(&target_indexes);
+    let mut edits = Vec::new();
+    for (i, &item) in order.iter().enumerate() {
+        if in_place.contains(&item) {
+            continue;
+        }
+        let insert_before = if i == 0 {
+            0
+        } else {
+            state.iter().position(|&t| t == order[i - 1]).map_or(0, |p| p + 1)
+        };
+        let from = state.iter().position(|&t| t == item).unwrap();
+        if from != insert_before && from + 1 != insert_before {
+            edits.push(Edit::Move { from: from, insert_before: insert_before, track: current[item].clone() });
+        }
+        let moved = state.remove(from);
+        let position = if from < insert_before { insert_before - 1 } else { insert_before };
+        state.insert(position, moved);
+    }
+    edits
+}
+
+/// Put the tracks in a playlist back in the order of a snapshot. Tracks are
+/// only moved, so tracks added since the snapshot are kept at the end and
+/// tracks removed since are left out. Returns the moves that were made.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the playlist is
+/// * `playlist_id` - The playlist ID to reorder. This is the ID and *not*
+/// the name.
+/// * `snapshot` - The order to restore
+/// * `dry_run` - Work out the moves without making them
+pub fn restore_order<E>(playlist_api: &PlaylistAPI<E>,
+                        playlist_id: &str,
+                        snapshot: &OrderSnapshot,
+                        dry_run: bool) -> Result<Vec<Edit>, E> {
+    let current = playlist_api.get_track_ids_in_playlist(playlist_id)?;
+    let tracks: Vec<TrackUri> = snapshot.tracks.iter().map(|uri| TrackUri::parse(uri)).collect();
+    let moves = compute_moves(&current, &get_restored_order(&current, &tracks));
+    if !dry_run {
+        apply_edits(playlist_api, playlist_id, &moves)?;
+    }
+    Ok(moves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::MemoryAPI;
+    use playlist::Visibility;
+    use std::env;
+    use std::fs;
+
+    fn tracks(ids: &[&str]) -> Vec<TrackUri> {
+        ids.iter().map(|id| TrackUri::Track(format!("{:0<22}", id))).collect()
+    }
+
+    #[test]
+    fn order_is_restored_with_moves() {
+        // Given
+        let sandbox = env::temp_dir().join("playlist-from-csv-order-test.json");
+        let sandbox = sandbox.to_str().unwrap();
+        let _ = fs::remove_file(sandbox);
+        let api = MemoryAPI::open(sandbox).unwrap();
+        let id = api.create_playlist("Curated", Visibility::Private).unwrap();
+        api.add_tracks_to_playlist(&id, &tracks(&["d", "a", "c", "a", "b"]), None).unwrap();
+        let snapshot = take_order_snapshot(&api, "Curated", &id, "2018-03-01T10:00:00+00:00").unwrap();
+        // Sorted by a collaborator, who also added a track and removed one
+        api.remove_tracks_at_positions(&id, &[(tracks(&["c"]).remove(0), 2)]).unwrap();
+        api.add_tracks_to_playlist(&id, &tracks(&["e"]), Some(0)).unwrap();
+        api.move_track_in_playlist(&id, 1, 5).unwrap();
+        let before = api.get_track_ids_in_playlist(&id).unwrap();
+        // When
+        let planned = restore_order(&api, &id, &snapshot, true).unwrap();
+        let moves = restore_order(&api, &id, &snapshot, false).unwrap();
+        // Then
+        assert_eq!(tracks(&["e", "a", "a", "b", "d"]), before);
+        assert_eq!(planned, moves);
+        assert!(moves.iter().all(|edit| matches!(*edit, Edit::Move { .. })));
+        assert_eq!(tracks(&["d", "a", "a", "b", "e"]), api.get_track_ids_in_playlist(&id).unwrap());
+        assert!(restore_order(&api, &id, &snapshot, false).unwrap().is_empty());
+    }
+}
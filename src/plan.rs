@@ -0,0 +1,623 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+
+use std::collections::HashSet;
+
+use csv_to_playlist::{Song,Position,get_tracks_to_add,get_unique_track_ids};
+use playlist::{check_can_modify,PlaylistAPI,PlaylistError,NotAuthorizedToModify,Visibility};
+use track_uri::TrackUri;
+
+extern crate serde_json;
+
+/// The exact changes that will be made to a playlist. A plan is created
+/// ahead of time so that it can be reviewed and then applied later on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    /// The name of the playlist to be changed
+    pub playlist_name: String,
+    /// The ID of the playlist. This will be `None` when the playlist does not
+    /// exist yet and will be created on apply
+    pub playlist_id: Option<String>,
+    /// The snapshot ID of the playlist when the plan was created
+    pub snapshot_id: Option<String>,
+    /// The tracks and episodes to be added to the playlist, in order
+    pub additions: Vec<TrackUri>,
+    /// Where the additions are inserted, or `None` to append them. The
+    /// index is in the playlist once the removals have been made
+    #[serde(default)]
+    pub position: Option<usize>,
+    /// The tracks to remove from the playlist, every time they appear, before
+    /// anything is added
+    #[serde(default)]
+    pub removals: Vec<TrackUri>,
+    /// Whether the playlist is created as public when it doesn't exist yet
+    #[serde(default)]
+    pub public: bool,
+}
+
+/// The choices that shape a plan, matching the ones `import` takes
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct PlanOptions {
+    /// Where the added tracks go
+    pub position: Position,
+    /// Remove the tracks in the playlist that aren't in the songs
+    pub remove_missing: bool,
+    /// The visibility of the playlist if it's created
+    pub visibility: Visibility,
+}
+
+/// Plan error enum for different errors when applying a plan
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ApplyError<E> {
+    /// Generic playlist error from API
+    APIError(E),
+    /// The error when the playlist has been modified since planning
+    PlaylistChanged(PlaylistChanged),
+    /// The error when the playlist belongs to someone else
+    NotAuthorizedToModify(NotAuthorizedToModify),
+}
+
+/// An error when the playlist was changed after the plan was created
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PlaylistChanged {}
+
+impl Error for PlaylistChanged {
+    fn description(&self) -> &str {
+        "Playlist has changed since the plan was created"
+    }
+}
+
+impl fmt::Display for PlaylistChanged {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+/// Work out the changes needed to add the songs to the playlist without
+/// modifying anything. The playlist will not be created if it doesn't exist.
+/// Fails if the playlist exists but can't be modified.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the tracks should be added
+/// * `playlist_name` - The name of the playlist to be added to
+/// * `songs` - A slice of the songs
+/// * `options` - Where to add the tracks, what to remove and how to create
+/// the playlist
+pub fn create_plan<E>(playlist_api: &PlaylistAPI<E>,
+                      playlist_name: &str,
+                      songs: &[Song],
+                      options: &PlanOptions) -> Result<Plan, PlaylistError<E>> {
+    let public = options.visibility == Visibility::Public;
+    match playlist_api.get_playlist_id(playlist_name) {
+        Ok(playlist_id) => {
+            check_can_modify(playlist_api, &playlist_id)?;
+            let snapshot_id = playlist_api.get_playlist_snapshot_id(
+                &playlist_id
+            ).map_err(PlaylistError::APIError)?;
+            let additions = get_tracks_to_add(
+                playlist_api, &playlist_id, songs
+            ).map_err(PlaylistError::APIError)?;
+            let existing = playlist_api.get_track_ids_in_playlist(&playlist_id)
+                .map_err(PlaylistError::APIError)?;
+            let removals = if options.remove_missing {
+                missing_tracks(&existing, songs)
+            } else {
+                Vec::new()
+            };
+            let removed: HashSet<&TrackUri> = removals.iter().collect();
+            let remaining = existing.iter().filter(|track| !removed.contains(track)).count();
+            Ok(Plan {
+                playlist_name: playlist_name.to_owned(),
+                playlist_id: Some(playlist_id),
+                snapshot_id: Some(snapshot_id),
+                additions: additions,
+                position: options.position.index(remaining),
+                removals: removals,
+                public: public,
+            })
+        },
+        // If the playlist doesn't exist then every song is new
+        Err(PlaylistError::PlaylistNotFound(_)) => Ok(Plan {
+            playlist_name: playlist_name.to_owned(),
+            playlist_id: None,
+            snapshot_id: None,
+            additions: get_unique_track_ids(songs),
+            position: options.position.index(0),
+            removals: Vec::new(),
+            public: public,
+        }),
+        Err(e) => Err(e),
+    }
+}
+
+/// Get the tracks in the playlist that aren't in the songs, once each and
+/// in playlist order
+fn missing_tracks(existing: &[TrackUri], songs: &[Song]) -> Vec<TrackUri> {
+    let wanted: HashSet<TrackUri> = get_unique_track_ids(songs).into_iter().collect();
+    let mut seen = HashSet::new();
+    existing.iter()
+        .filter(|track| !wanted.contains(*track) && seen.insert(*track))
+        .cloned()
+        .collect()
+}
+
+/// Make the changes described by the plan. This will fail without making
+/// any changes if the playlist was modified after the plan was created.
+/// The removals are made first and then the additions are inserted at the
+/// plan's position.
+/// Returns the playlist ID that was changed.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the tracks should be added
+/// * `plan` - The plan to apply
+pub fn apply_plan<E>(playlist_api: &PlaylistAPI<E>,
+                     plan: &Plan) -> Result<String, ApplyError<E>> {
+    let playlist_id = match plan.playlist_id {
+        Some(ref playlist_id) => {
+            let snapshot_id = playlist_api.get_playlist_snapshot_id(
+                playlist_id
+            ).map_err(ApplyError::APIError)?;
+            if plan.snapshot_id.as_ref() != Some(&snapshot_id) {
+                return Err(ApplyError::PlaylistChanged(PlaylistChanged {}));
+            }
+            match check_can_modify(playlist_api, playlist_id) {
+                Ok(()) => {},
+                Err(PlaylistError::NotAuthorizedToModify(e)) => {
+                    return Err(ApplyError::NotAuthorizedToModify(e));
+                },
+                Err(PlaylistError::APIError(e)) => return Err(ApplyError::APIError(e)),
+                Err(PlaylistError::PlaylistNotFound(_)) |
+                Err(PlaylistError::PlaylistAlreadyExists(_)) |
+                Err(PlaylistError::PlaylistFull(_)) => {
+                    return Err(ApplyError::PlaylistChanged(PlaylistChanged {}));
+                },
+            }
+            playlist_id.to_owned()
+        },
+        None => {
+            // The playlist should still not exist
+            match playlist_api.get_playlist_id(&plan.playlist_name) {
+                Ok(_) | Err(PlaylistError::PlaylistAlreadyExists(_)) | Err(PlaylistError::PlaylistFull(_)) => {
+                    return Err(ApplyError::PlaylistChanged(PlaylistChanged {}));
+                },
+                Err(PlaylistError::PlaylistNotFound(_)) => {
+                    let visibility = if plan.public { Visibility::Public } else { Visibility::Private };
+                    playlist_api.create_playlist(
+                        &plan.playlist_name,
+                        visibility
+                    ).map_err(ApplyError::APIError)?
+                },
+                Err(PlaylistError::APIError(e)) => return Err(ApplyError::APIError(e)),
+                Err(PlaylistError::NotAuthorizedToModify(e)) => {
+                    return Err(ApplyError::NotAuthorizedToModify(e));
+                },
+            }
+        },
+    };
+    if !plan.removals.is_empty() {
+        playlist_api.remove_tracks_from_playlist(
+            &playlist_id, &plan.removals
+        ).map_err(ApplyError::APIError)?;
+    }
+    if !plan.additions.is_empty() {
+        playlist_api.add_tracks_to_playlist(
+            &playlist_id, &plan.additions, plan.position
+        ).map_err(ApplyError::APIError)?;
+    }
+    Ok(playlist_id)
+}
+
+/// Save the plan as JSON so that it can be applied later
+///
+/// # Arguments
+///
+/// * `plan` - The plan to save
+/// * `filename` - The path to write to
+pub fn write_plan(plan: &Plan, filename: &str) -> Result<(), Box<Error>> {
+    let file = File::create(filename)?;
+    serde_json::to_writer_pretty(file, plan)?;
+    Ok(())
+}
+
+/// Read a plan that was saved using `write_plan`
+///
+/// # Arguments
+///
+/// * `filename` - The path to the plan file
+pub fn read_plan(filename: &str) -> Result<Plan, Box<Error>> {
+    let file = File::open(filename)?;
+    let plan = serde_json::from_reader(file)?;
+    Ok(plan)
+}
+
+/// Parse a plan from the contents of a plan file
+///
+/// # Arguments
+///
+/// * `data` - The JSON written by `write_plan`
+pub fn parse_plan(data: &[u8]) -> Result<Plan, Box<Error>> {
+    let plan = serde_json::from_slice(data)?;
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_api::{FakeError,FakePlaylistAPI};
+    use playlist::{PlaylistError,PlaylistNotFound,NotAuthorizedToModify,Visibility};
+
+    use std::cell::RefCell;
+
+    /// Keep track of calls made to MockPlaylistAPI
+    #[derive(Debug, Clone)]
+    struct CallHistory {
+        create_playlist_called_with: Option<String>,
+        create_playlist_visibility: Option<Visibility>,
+        add_tracks_to_playlist_called_with: Option<(String, Vec<TrackUri>)>,
+        add_tracks_to_playlist_position: Option<usize>,
+        remove_tracks_from_playlist_called_with: Option<Vec<TrackUri>>,
+    }
+
+    /// A fake API where you can specify the return values to the functions
+    struct MockPlaylistAPI {
+        call_history: RefCell<CallHistory>,
+        get_playlist_id_returns: Result<String, PlaylistError<FakeError>>,
+        get_playlist_snapshot_id_returns: Result<String, FakeError>,
+        get_track_ids_in_playlist_returns: Result<Vec<TrackUri>, FakeError>,
+        can_modify_playlist_returns: Result<bool, FakeError>,
+    }
+
+    impl MockPlaylistAPI {
+        /// Create a new MockPlaylistAPI
+        fn new(get_playlist_id_returns: Result<String, PlaylistError<FakeError>>,
+               get_playlist_snapshot_id_returns: Result<String, FakeError>,
+               get_track_ids_in_playlist_returns: Result<Vec<TrackUri>, FakeError>) -> MockPlaylistAPI {
+            MockPlaylistAPI {
+                call_history: RefCell::new(
+                    CallHistory{
+                        create_playlist_called_with: None,
+                        create_playlist_visibility: None,
+                        add_tracks_to_playlist_called_with: None,
+                        add_tracks_to_playlist_position: None,
+                        remove_tracks_from_playlist_called_with: None,
+                    }
+                ),
+                get_playlist_id_returns: get_playlist_id_returns,
+                get_playlist_snapshot_id_returns: get_playlist_snapshot_id_returns,
+                get_track_ids_in_playlist_returns: get_track_ids_in_playlist_returns,
+                can_modify_playlist_returns: Ok(true),
+            }
+        }
+    }
+
+    impl FakePlaylistAPI for MockPlaylistAPI {
+        #[allow(unused_variables)]
+        fn get_playlist_id(&self, playlist_name: &str) -> Result<String, PlaylistError<FakeError>> {
+            self.get_playlist_id_returns.clone()
+        }
+
+        #[allow(unused_variables)]
+        fn create_playlist(&self, playlist_name: &str, visibility: Visibility) -> Result<String, FakeError> {
+            let mut calls = self.call_history.borrow_mut();
+            calls.create_playlist_called_with = Some(playlist_name.to_owned());
+            calls.create_playlist_visibility = Some(visibility);
+            Ok("new_id".to_string())
+        }
+
+        #[allow(unused_variables)]
+        fn add_tracks_to_playlist(&self, playlist_id: &str, tracks: &[TrackUri], position: Option<usize>) -> Result<(), FakeError> {
+            let mut calls = self.call_history.borrow_mut();
+            calls.add_tracks_to_playlist_called_with = Some(
+                (playlist_id.to_owned(), tracks.to_vec())
+            );
+            calls.add_tracks_to_playlist_position = position;
+            Ok(())
+        }
+
+        #[allow(unused_variables)]
+        fn get_track_ids_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackUri>, FakeError> {
+            self.get_track_ids_in_playlist_returns.clone()
+        }
+
+        #[allow(unused_variables)]
+        fn get_playlist_snapshot_id(&self, playlist_id: &str) -> Result<String, FakeError> {
+            self.get_playlist_snapshot_id_returns.clone()
+        }
+
+        #[allow(unused_variables)]
+        fn can_modify_playlist(&self, playlist_id: &str) -> Result<bool, FakeError> {
+            self.can_modify_playlist_returns
+        }
+
+        #[allow(unused_variables)]
+        fn remove_tracks_from_playlist(&self, playlist_id: &str, tracks: &[TrackUri]) -> Result<(), FakeError> {
+            self.call_history.borrow_mut().remove_tracks_from_playlist_called_with = Some(tracks.to_vec());
+            Ok(())
+        }
+    }
+
+    fn track(id: &str) -> TrackUri {
+        TrackUri::Track(format!("{:0<22}", id))
+    }
+
+    /// Used for declaring the inputs for each test
+    fn test_setup() -> (String, Vec<Song>) {
+        let songs = vec![
+            Song{music:"b".to_string(), song_id:"idb0000000000000000000".to_string()},
+            Song{music:"a".to_string(), song_id:"ida0000000000000000000".to_string()},
+            Song{music:"c".to_string(), song_id:"idc0000000000000000000".to_string()},
+        ];
+        ("test_playlist_name1".to_string(), songs)
+    }
+
+    #[test]
+    fn create_plan_for_existing_playlist() {
+        // Given
+        let (playlist_name, songs) = test_setup();
+        let api = MockPlaylistAPI::new(
+            Ok("id_123".to_string()),
+            Ok("snapshot_1".to_string()),
+            Ok(vec![track("idb0000000000000000000")]),
+        );
+        // When
+        let result = create_plan(&api, &playlist_name, &songs, &PlanOptions::default()).unwrap();
+        // Then
+        let expected = Plan {
+            playlist_name: playlist_name,
+            playlist_id: Some("id_123".to_string()),
+            snapshot_id: Some("snapshot_1".to_string()),
+            additions: vec![track("ida0000000000000000000"), track("idc0000000000000000000")],
+            position: None,
+            removals: Vec::new(),
+            public: false,
+        };
+        assert_eq!(expected, result);
+        // Ensure that nothing is changed while planning
+        let calls = api.call_history.borrow();
+        assert_eq!(None, calls.create_playlist_called_with);
+        assert_eq!(None, calls.add_tracks_to_playlist_called_with);
+    }
+
+    #[test]
+    fn create_plan_for_missing_playlist() {
+        // Given
+        let (playlist_name, songs) = test_setup();
+        let api = MockPlaylistAPI::new(
+            Err(PlaylistError::PlaylistNotFound(PlaylistNotFound{})),
+            Ok("snapshot_1".to_string()),
+            Ok(Vec::new()),
+        );
+        // When
+        let result = create_plan(&api, &playlist_name, &songs, &PlanOptions::default()).unwrap();
+        // Then
+        let expected = Plan {
+            playlist_name: playlist_name,
+            playlist_id: None,
+            snapshot_id: None,
+            additions: vec![track("ida0000000000000000000"), track("idb0000000000000000000"), track("idc0000000000000000000")],
+            position: None,
+            removals: Vec::new(),
+            public: false,
+        };
+        assert_eq!(expected, result);
+        // Ensure that the playlist isn't created while planning
+        let calls = api.call_history.borrow();
+        assert_eq!(None, calls.create_playlist_called_with);
+    }
+
+    #[test]
+    fn create_plan_records_position_removals_and_visibility() {
+        // Given
+        let (playlist_name, songs) = test_setup();
+        let api = MockPlaylistAPI::new(
+            Ok("id_123".to_string()),
+            Ok("snapshot_1".to_string()),
+            Ok(vec![track("old"), track("idb0000000000000000000"), track("old"), track("stale")]),
+        );
+        let options = PlanOptions {
+            position: Position::Index(2),
+            remove_missing: true,
+            visibility: Visibility::Public,
+        };
+        let start = PlanOptions { position: Position::Start, ..PlanOptions::default() };
+        // When
+        let result = create_plan(&api, &playlist_name, &songs, &options).unwrap();
+        let kept = create_plan(&api, &playlist_name, &songs, &start).unwrap();
+        // Then
+        assert_eq!(vec![track("old"), track("stale")], result.removals);
+        // Only id_b is left once the removals are made, so the tracks go at
+        // the end
+        assert_eq!(None, result.position);
+        assert!(result.public);
+        assert_eq!(Vec::<TrackUri>::new(), kept.removals);
+        assert_eq!(Some(0), kept.position);
+    }
+
+    #[test]
+    fn apply_plan_makes_removals_and_inserts_at_position() {
+        // Given
+        let plan = Plan {
+            playlist_name: "name".to_string(),
+            playlist_id: Some("id_123".to_string()),
+            snapshot_id: Some("snapshot_1".to_string()),
+            additions: vec![track("ida0000000000000000000")],
+            position: Some(1),
+            removals: vec![track("old")],
+            public: false,
+        };
+        let missing = Plan { playlist_id: None, snapshot_id: None, public: true, ..plan.clone() };
+        let api = MockPlaylistAPI::new(
+            Ok("id_123".to_string()),
+            Ok("snapshot_1".to_string()),
+            Ok(Vec::new()),
+        );
+        let new_api = MockPlaylistAPI::new(
+            Err(PlaylistError::PlaylistNotFound(PlaylistNotFound{})),
+            Ok("snapshot_1".to_string()),
+            Ok(Vec::new()),
+        );
+        // When
+        apply_plan(&api, &plan).unwrap();
+        apply_plan(&new_api, &missing).unwrap();
+        // Then
+        let calls = api.call_history.borrow();
+        assert_eq!(Some(vec![track("old")]), calls.remove_tracks_from_playlist_called_with);
+        assert_eq!(Some(1), calls.add_tracks_to_playlist_position);
+        assert_eq!(Some(Visibility::Public), new_api.call_history.borrow().create_playlist_visibility);
+    }
+
+    #[test]
+    fn apply_plan_adds_tracks() {
+        // Given
+        let plan = Plan {
+            playlist_name: "name".to_string(),
+            playlist_id: Some("id_123".to_string()),
+            snapshot_id: Some("snapshot_1".to_string()),
+            additions: vec![track("ida0000000000000000000")],
+            position: None,
+            removals: Vec::new(),
+            public: false,
+        };
+        let api = MockPlaylistAPI::new(
+            Ok("id_123".to_string()),
+            Ok("snapshot_1".to_string()),
+            Ok(Vec::new()),
+        );
+        // When
+        let result = apply_plan(&api, &plan).unwrap();
+        // Then
+        assert_eq!("id_123", result);
+        let calls = api.call_history.borrow();
+        let expected = Some(("id_123".to_string(), vec![track("ida0000000000000000000")]));
+        assert_eq!(expected, calls.add_tracks_to_playlist_called_with);
+        assert_eq!(None, calls.create_playlist_called_with);
+    }
+
+    #[test]
+    fn apply_plan_fails_when_snapshot_changed() {
+        // Given
+        let plan = Plan {
+            playlist_name: "name".to_string(),
+            playlist_id: Some("id_123".to_string()),
+            snapshot_id: Some("snapshot_1".to_string()),
+            additions: vec![track("ida0000000000000000000")],
+            position: None,
+            removals: Vec::new(),
+            public: false,
+        };
+        let api = MockPlaylistAPI::new(
+            Ok("id_123".to_string()),
+            // Someone else has changed the playlist
+            Ok("snapshot_2".to_string()),
+            Ok(Vec::new()),
+        );
+        // When
+        let result = apply_plan(&api, &plan);
+        // Then
+        assert_eq!(Err(ApplyError::PlaylistChanged(PlaylistChanged{})), result);
+        // Ensure nothing was added
+        let calls = api.call_history.borrow();
+        assert_eq!(None, calls.add_tracks_to_playlist_called_with);
+    }
+
+    #[test]
+    fn apply_plan_creates_missing_playlist() {
+        // Given
+        let plan = Plan {
+            playlist_name: "name".to_string(),
+            playlist_id: None,
+            snapshot_id: None,
+            additions: vec![track("ida0000000000000000000")],
+            position: None,
+            removals: Vec::new(),
+            public: false,
+        };
+        let api = MockPlaylistAPI::new(
+            Err(PlaylistError::PlaylistNotFound(PlaylistNotFound{})),
+            Ok("snapshot_1".to_string()),
+            Ok(Vec::new()),
+        );
+        // When
+        let result = apply_plan(&api, &plan).unwrap();
+        // Then
+        assert_eq!("new_id", result);
+        let calls = api.call_history.borrow();
+        assert_eq!(Some("name".to_string()), calls.create_playlist_called_with);
+        let expected = Some(("new_id".to_string(), vec![track("ida0000000000000000000")]));
+        assert_eq!(expected, calls.add_tracks_to_playlist_called_with);
+    }
+
+    #[test]
+    fn apply_plan_fails_when_playlist_created_since_planning() {
+        // Given
+        let plan = Plan {
+            playlist_name: "name".to_string(),
+            playlist_id: None,
+            snapshot_id: None,
+            additions: vec![track("ida0000000000000000000")],
+            position: None,
+            removals: Vec::new(),
+            public: false,
+        };
+        let api = MockPlaylistAPI::new(
+            // The playlist now exists
+            Ok("id_123".to_string()),
+            Ok("snapshot_1".to_string()),
+            Ok(Vec::new()),
+        );
+        // When
+        let result = apply_plan(&api, &plan);
+        // Then
+        assert_eq!(Err(ApplyError::PlaylistChanged(PlaylistChanged{})), result);
+        let calls = api.call_history.borrow();
+        assert_eq!(None, calls.create_playlist_called_with);
+        assert_eq!(None, calls.add_tracks_to_playlist_called_with);
+    }
+
+    #[test]
+    fn create_plan_fails_when_not_authorized() {
+        // Given
+        let (playlist_name, songs) = test_setup();
+        let mut api = MockPlaylistAPI::new(
+            Ok("id_123".to_string()),
+            Ok("snapshot_1".to_string()),
+            Ok(Vec::new()),
+        );
+        api.can_modify_playlist_returns = Ok(false);
+        // When
+        let result = create_plan(&api, &playlist_name, &songs, &PlanOptions::default());
+        // Then
+        let expected = PlaylistError::NotAuthorizedToModify(NotAuthorizedToModify{});
+        assert_eq!(Err(expected), result);
+    }
+
+    #[test]
+    fn apply_plan_fails_when_not_authorized() {
+        // Given
+        let plan = Plan {
+            playlist_name: "name".to_string(),
+            playlist_id: Some("id_123".to_string()),
+            snapshot_id: Some("snapshot_1".to_string()),
+            additions: vec![track("ida0000000000000000000")],
+            position: None,
+            removals: Vec::new(),
+            public: false,
+        };
+        let mut api = MockPlaylistAPI::new(
+            Ok("id_123".to_string()),
+            Ok("snapshot_1".to_string()),
+            Ok(Vec::new()),
+        );
+        // The playlist has stopped being collaborative
+        api.can_modify_playlist_returns = Ok(false);
+        // When
+        let result = apply_plan(&api, &plan);
+        // Then
+        assert_eq!(Err(ApplyError::NotAuthorizedToModify(NotAuthorizedToModify{})), result);
+        let calls = api.call_history.borrow();
+        assert_eq!(None, calls.add_tracks_to_playlist_called_with);
+    }
+}
@@ -1,5 +1,10 @@
 use std::error::Error;
 use std::fmt;
+use std::str::FromStr;
+
+use capacity::PlaylistFull;
+use i18n::tr;
+use track_uri::TrackUri;
 
 /// A trait for querying for playlists
 pub trait PlaylistAPI<E> {
@@ -14,20 +19,258 @@ pub trait PlaylistAPI<E> {
     /// # Arguments
     ///
     /// * `playlist_name` - A string slice that holds the playlist name
-    fn create_playlist(&self, playlist_name: &str) -> Result<String, E>;
-    /// Add tracks to a playlist with a given ID
+    /// * `visibility` - Whether the playlist is shown on the user's profile
+    fn create_playlist(&self, playlist_name: &str, visibility: Visibility) -> Result<String, E>;
+    /// Add tracks or episodes to a playlist with a given ID. Items that
+    /// can't be added, such as local files, are skipped
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    /// * `tracks` - The tracks and episodes to add to playlist
+    /// * `position` - Where to insert them, starting from 0, or `None` to
+    /// add them to the end
+    fn add_tracks_to_playlist(&self, playlist_id: &str, tracks: &[TrackUri], position: Option<usize>) -> Result<(), E>;
+    /// Get every item in the specified playlist, including episodes and
+    /// local files
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    fn get_track_ids_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackUri>, E>;
+    /// Get tracks along with their metadata in the specified playlist
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    fn get_tracks_with_metadata_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackMetadata>, E>;
+    /// Get the snapshot ID of the playlist. This changes whenever the playlist
+    /// is modified
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    fn get_playlist_snapshot_id(&self, playlist_id: &str) -> Result<String, E>;
+    /// Get the number of items in a playlist, without fetching them
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    fn get_playlist_size(&self, playlist_id: &str) -> Result<usize, E>;
+    /// Get the details of a playlist that can be changed with
+    /// `update_playlist_details`
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    fn get_playlist_details(&self, playlist_id: &str) -> Result<PlaylistDetails, E>;
+    /// Download the cover image of a playlist. Returns `None` when the
+    /// playlist has no cover
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    fn get_cover_image(&self, playlist_id: &str) -> Result<Option<Vec<u8>>, E>;
+    /// Look up the metadata for the tracks and episodes. Items that can't be
+    /// looked up are skipped
+    ///
+    /// # Arguments
+    ///
+    /// * `tracks` - The tracks and episodes to look up
+    fn get_tracks_metadata(&self, tracks: &[TrackUri]) -> Result<Vec<TrackMetadata>, E>;
+    /// Update the details of a playlist with a given ID. Only fields that
+    /// are set will be changed
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    /// * `details` - The details to be changed
+    fn update_playlist_details(&self, playlist_id: &str, details: &PlaylistDetails) -> Result<(), E>;
+    /// Make a playlist with a given ID public or private
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    /// * `visibility` - Whether the playlist is shown on the user's profile
+    fn set_playlist_visibility(&self, playlist_id: &str, visibility: Visibility) -> Result<(), E>;
+    /// Replace the cover image of a playlist with a given ID
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    /// * `jpeg` - The image, which should pass `check_cover_image`
+    fn upload_cover_image(&self, playlist_id: &str, jpeg: &[u8]) -> Result<(), E>;
+    /// Check whether the current user can add to the playlist, ie. they own
+    /// it or it's collaborative
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    fn can_modify_playlist(&self, playlist_id: &str) -> Result<bool, E>;
+    /// Change the name of a playlist with a given ID
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    /// * `name` - The new name
+    fn rename_playlist(&self, playlist_id: &str, name: &str) -> Result<(), E>;
+    /// Stop following a playlist so that it's no longer in the user's
+    /// library. Spotify has no way to delete a playlist, so this is how the
+    /// user's own playlists are removed too
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    fn unfollow_playlist(&self, playlist_id: &str) -> Result<(), E>;
+    /// Remove every occurrence of the tracks or episodes from a playlist
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    /// * `tracks` - The tracks and episodes to remove from the playlist
+    fn remove_tracks_from_playlist(&self, playlist_id: &str, tracks: &[TrackUri]) -> Result<(), E>;
+    /// Insert tracks or episodes into a playlist before the given position
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    /// * `tracks` - The tracks and episodes to insert, in order
+    /// * `position` - Where to insert them, starting from 0
+    fn insert_tracks_into_playlist(&self, playlist_id: &str, tracks: &[TrackUri], position: usize) -> Result<(), E>;
+    /// Remove single occurrences of tracks from a playlist. Positions are
+    /// those before any of the tracks are removed
     ///
     /// # Arguments
     ///
     /// * `playlist_id` - A string slice that holds the playlist ID
-    /// * `track_ids` - A vectors of strings with tracks IDs to add to playlist
-    fn add_tracks_to_playlist(&self, playlist_id: &str, track_ids: &[String]) -> Result<(), E>;
-    /// Get track IDs in the specified playlist
+    /// * `occurrences` - Each track along with its position, starting from 0
+    fn remove_tracks_at_positions(&self, playlist_id: &str, occurrences: &[(TrackUri, usize)]) -> Result<(), E>;
+    /// Move a single item within a playlist. This keeps the date it was
+    /// added, unlike removing and adding it again
     ///
     /// # Arguments
     ///
     /// * `playlist_id` - A string slice that holds the playlist ID
-    fn get_track_ids_in_playlist(&self, playlist_id: &str) -> Result<Vec<String>, E>;
+    /// * `from` - The position of the item to move, starting from 0
+    /// * `insert_before` - The position to move it in front of, counted
+    /// before the item is moved
+    fn move_track_in_playlist(&self, playlist_id: &str, from: usize, insert_before: usize) -> Result<(), E>;
+
+    /// Get every playlist the user follows along with how many tracks it has,
+    /// whether it's public and who owns it
+    fn list_playlists(&self) -> Result<Vec<PlaylistInfo>, E>;
+
+    /// Look up how popular tracks are and whether they're from an album, to
+    /// pick between versions of the same song. Tracks without these
+    /// details, such as episodes, are skipped
+    ///
+    /// # Arguments
+    ///
+    /// * `tracks` - The tracks to look up
+    fn get_track_versions(&self, tracks: &[TrackUri]) -> Result<Vec<TrackVersion>, E>;
+
+    /// Search for tracks, best matches first
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The search, which can use field filters such as
+    /// `artist:"Robyn"`
+    /// * `limit` - The most tracks to return
+    fn search_tracks(&self, query: &str, limit: u32) -> Result<Vec<TrackMetadata>, E>;
+
+    /// Look up the musical features of tracks, such as their key and tempo.
+    /// Tracks without features, such as episodes, are skipped
+    ///
+    /// # Arguments
+    ///
+    /// * `tracks` - The tracks to look up
+    fn get_audio_features(&self, tracks: &[TrackUri]) -> Result<Vec<AudioFeatures>, E>;
+
+    /// Check whether tracks can be played in a market, in the same order as
+    /// `tracks`. Unknown tracks are left out
+    ///
+    /// # Arguments
+    ///
+    /// * `tracks` - The tracks to check
+    /// * `market` - A country code, eg. `AU`
+    fn get_track_availability(&self, tracks: &[TrackUri], market: &str) -> Result<Vec<TrackAvailability>, E>;
+}
+
+/// A playlist that the user follows, with the details shown when listing
+/// playlists
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlaylistInfo {
+    pub id: String,
+    pub name: String,
+    pub track_count: u32,
+    pub public: bool,
+    /// The display name of the owner, or their ID when they don't have one
+    pub owner: String,
+}
+
+/// Human readable information about a track in a playlist
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackMetadata {
+    /// What the item is along with its ID
+    pub uri: TrackUri,
+    /// The name of the track
+    pub name: String,
+    /// The names of the artists on the track. For episodes this is the
+    /// show's publisher
+    pub artists: Vec<String>,
+    /// The name of the album the track is from, or the show for episodes
+    pub album: String,
+    /// How long the track is, or 0 when unknown, eg. for local files
+    pub duration_ms: u64,
+    /// The URL of the album artwork, or the show's artwork for episodes
+    pub image_url: Option<String>,
+}
+
+/// The details used to pick between versions of the same song, eg. the
+/// single and the album version
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackVersion {
+    pub track: TrackMetadata,
+    /// From 0 to 100, where 100 is the most popular
+    pub popularity: u32,
+    /// Whether the track is from an album rather than a single or a
+    /// compilation
+    pub on_album: bool,
+}
+
+/// Whether a track can be played in a market
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackAvailability {
+    /// The track that was checked
+    pub track: TrackMetadata,
+    /// Whether it can be played in the market, possibly as `relinked`
+    pub playable: bool,
+    /// Another release of the same track that Spotify plays instead in the
+    /// market, when there is one
+    pub relinked: Option<TrackUri>,
+}
+
+/// The musical features of a track that Spotify has analysed
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioFeatures {
+    pub uri: TrackUri,
+    /// The pitch class of the key, eg. 0 is C and 1 is C♯/D♭. This is `None`
+    /// when no key was detected
+    pub key: Option<u8>,
+    /// Whether the key is major rather than minor
+    pub major: bool,
+    /// The tempo in beats per minute
+    pub tempo: f64,
+    /// How intense the track feels, from 0 to 1
+    pub energy: f64,
+}
+
+/// Playlist details that can be changed after the playlist is created.
+/// Fields set to `None` are left as is
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlaylistDetails {
+    /// The description shown under the playlist name
+    pub description: Option<String>,
 }
 
 /// Playlist enum for different playlist errors
@@ -37,6 +280,24 @@ pub enum PlaylistError<E> {
     APIError(E),
     /// The error when the playlist cannot be found
     PlaylistNotFound(PlaylistNotFound),
+    /// The error when the playlist belongs to someone else
+    NotAuthorizedToModify(NotAuthorizedToModify),
+    /// The error when the playlist should be new but already exists
+    PlaylistAlreadyExists(PlaylistAlreadyExists),
+    /// The error when the tracks won't fit in the playlist
+    PlaylistFull(PlaylistFull),
+}
+
+impl<E: fmt::Display> fmt::Display for PlaylistError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlaylistError::APIError(ref e) => write!(f, "{}", e),
+            PlaylistError::PlaylistNotFound(ref e) => write!(f, "{}", e),
+            PlaylistError::NotAuthorizedToModify(ref e) => write!(f, "{}", e),
+            PlaylistError::PlaylistAlreadyExists(ref e) => write!(f, "{}", e),
+            PlaylistError::PlaylistFull(ref e) => write!(f, "{}", e),
+        }
+    }
 }
 
 /// An error when the playlist name is not found
@@ -55,51 +316,242 @@ impl fmt::Display for PlaylistNotFound {
     }
 }
 
+/// An error when the playlist is followed but owned by someone else and is not
+/// collaborative. Spotify would reject changes to it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NotAuthorizedToModify {}
+
+impl Error for NotAuthorizedToModify {
+    fn description(&self) -> &str {
+        "Playlist is owned by another user and is not collaborative"
+    }
+}
+
+impl fmt::Display for NotAuthorizedToModify {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}. Ask the owner to make it collaborative, or use a \
+                   different playlist name to create your own playlist",
+               self.description())
+    }
+}
+
+/// An error when a playlist should be created but one with the same name
+/// already exists
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PlaylistAlreadyExists {}
+
+impl Error for PlaylistAlreadyExists {
+    fn description(&self) -> &str {
+        "Playlist already exists"
+    }
+}
+
+impl fmt::Display for PlaylistAlreadyExists {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}. Use a different playlist name to create a new playlist",
+               self.description())
+    }
+}
+
+/// Whether a playlist can be seen by other people
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum Visibility {
+    /// Only the owner and collaborators can see the playlist
+    #[default]
+    Private,
+    /// The playlist is shown on the owner's profile and in search
+    Public,
+}
+
+/// The error when a visibility isn't public or private
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownVisibility {}
+
+impl Error for UnknownVisibility {
+    fn description(&self) -> &str {
+        "Expected public or private"
+    }
+}
+
+impl fmt::Display for UnknownVisibility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl FromStr for Visibility {
+    type Err = UnknownVisibility;
+
+    fn from_str(s: &str) -> Result<Visibility, UnknownVisibility> {
+        match s {
+            "public" => Ok(Visibility::Public),
+            "private" => Ok(Visibility::Private),
+            _ => Err(UnknownVisibility {}),
+        }
+    }
+}
+
+/// Whether a playlist should be created when it's looked up by name
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum CreatePolicy {
+    /// Use the playlist if it exists and create it otherwise
+    #[default]
+    CreateIfNeeded,
+    /// Only use a playlist that already exists, failing with
+    /// `PlaylistNotFound` otherwise
+    NoCreate,
+    /// Only create a new playlist, failing with `PlaylistAlreadyExists` if
+    /// there's one with the same name
+    CreateOnly,
+}
+
+/// Check that the current user can add to the playlist so that a helpful
+/// error is given instead of a rejected request
+///
+/// # Arguments
+///
+/// * `playlist_id` - A string slice that holds the playlist ID
+pub fn check_can_modify<E>(api: &PlaylistAPI<E>,
+                           playlist_id: &str) -> Result<(), PlaylistError<E>> {
+    if api.can_modify_playlist(playlist_id).map_err(PlaylistError::APIError)? {
+        Ok(())
+    } else {
+        Err(PlaylistError::NotAuthorizedToModify(NotAuthorizedToModify {}))
+    }
+}
+
 /// Get the playlist ID for the given name and if it doesn't exist then create
-/// a playlist with the given name.
+/// a playlist with the given name. Fails if the playlist exists but can't be
+/// modified.
 ///
 /// # Arguments
 ///
 /// * `playlist_name` - A string slice that holds the playlist name
 pub fn get_playlist_id_create_if_needed<E>(api: &PlaylistAPI<E>,
                                            playlist_name: &str) -> Result<String, PlaylistError<E>> {
+    get_playlist_id_with_policy(api, playlist_name, CreatePolicy::CreateIfNeeded, Visibility::Private)
+}
+
+/// Check whether a playlist could be looked up with a policy, without
+/// creating it. This is used before making changes, eg. for a dry run.
+///
+/// # Arguments
+///
+/// * `playlist_name` - A string slice that holds the playlist name
+/// * `policy` - Whether the playlist can be created or already exist
+pub fn check_create_policy<E>(api: &PlaylistAPI<E>,
+                              playlist_name: &str,
+                              policy: CreatePolicy) -> Result<(), PlaylistError<E>> {
+    if policy == CreatePolicy::CreateIfNeeded {
+        return Ok(());
+    }
     match api.get_playlist_id(playlist_name) {
-        Ok(playlist_id) => Ok(playlist_id),
+        Ok(_) if policy == CreatePolicy::CreateOnly => {
+            Err(PlaylistError::PlaylistAlreadyExists(PlaylistAlreadyExists {}))
+        },
+        Err(PlaylistError::PlaylistNotFound(e)) if policy == CreatePolicy::NoCreate => {
+            Err(PlaylistError::PlaylistNotFound(e))
+        },
+        Ok(_) | Err(PlaylistError::PlaylistNotFound(_)) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Get the playlist ID for the given name, creating the playlist if the
+/// policy allows it. Fails if the playlist exists but can't be modified.
+///
+/// # Arguments
+///
+/// * `playlist_name` - A string slice that holds the playlist name
+/// * `policy` - Whether the playlist can be created or already exist
+/// * `visibility` - The visibility of the playlist if it's created. An
+/// existing playlist is left as is
+pub fn get_playlist_id_with_policy<E>(api: &PlaylistAPI<E>,
+                                      playlist_name: &str,
+                                      policy: CreatePolicy,
+                                      visibility: Visibility) -> Result<String, PlaylistError<E>> {
+    match api.get_playlist_id(playlist_name) {
+        Ok(_) if policy == CreatePolicy::CreateOnly => {
+            Err(PlaylistError::PlaylistAlreadyExists(PlaylistAlreadyExists {}))
+        },
+        Err(PlaylistError::PlaylistNotFound(e)) if policy == CreatePolicy::NoCreate => {
+            Err(PlaylistError::PlaylistNotFound(e))
+        },
+        Ok(playlist_id) => {
+            debug!("Found playlist {} with ID {}", playlist_name, playlist_id);
+            check_can_modify(api, &playlist_id)?;
+            Ok(playlist_id)
+        },
         Err(error) => {
             match error {
                 PlaylistError::PlaylistNotFound(_) => {
-                    let result = api.create_playlist(playlist_name);
+                    let result = api.create_playlist(playlist_name, visibility);
                     let id = result.map_err(PlaylistError::APIError)?;
+                    info!("{}", tr("playlist-created", &[("name", playlist_name.into()), ("id", id.as_str().into())]));
                     Ok(id)
                 },
-                PlaylistError::APIError(e) => Err(PlaylistError::APIError(e)),
+                e => Err(e),
             }
         }
     }
 }
 
+/// Show a playlist name so that spaces at either end can be seen, since
+/// playlists are found by their exact name
+fn visible_name(name: &str) -> String {
+    if name.trim() == name {
+        name.to_owned()
+    } else {
+        format!("\"{}\"", name)
+    }
+}
+
+/// Format playlists as a table with a header, one playlist per line
+///
+/// # Arguments
+///
+/// * `playlists` - The playlists, see `PlaylistAPI::list_playlists`
+pub fn format_playlist_table(playlists: &[PlaylistInfo]) -> String {
+    let names: Vec<String> = playlists.iter().map(|p| visible_name(&p.name)).collect();
+    let name_width = names.iter().map(|name| name.chars().count()).max().unwrap_or(0).max(4);
+    let id_width = playlists.iter().map(|p| p.id.len()).max().unwrap_or(0).max(2);
+    let mut table = format!(
+        "{:name_width$}  {:id_width$}  {:>6}  {:7}  OWNER\n",
+        "NAME", "ID", "TRACKS", "PUBLIC", name_width = name_width, id_width = id_width
+    );
+    for (playlist, name) in playlists.iter().zip(names) {
+        table.push_str(&format!(
+            "{:name_width$}  {:id_width$}  {:>6}  {:7}  {}\n",
+            name, playlist.id, playlist.track_count,
+            if playlist.public { "public" } else { "private" }, playlist.owner,
+            name_width = name_width, id_width = id_width
+        ));
+    }
+    table
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use fake_api::{FakeError,FakePlaylistAPI};
     use std::cell::RefCell;
 
     /// Keep track of calls made to MockPlaylistAPI
     #[derive(Debug, Clone)]
     struct CallHistory {
         create_playlist_called_with: Option<String>,
+        create_playlist_visibility: Option<Visibility>,
         get_playlist_id_called_with: Option<String>,
         add_tracks_to_playlist_called_with: Option<String>,
         get_track_ids_in_playlist_called_with: Option<String>,
     }
 
-    #[derive(Debug, Copy, Clone, PartialEq)]
-    struct FakeError {}
-
     /// A fake API where you can specify the return values to the functions
     struct MockPlaylistAPI {
         call_history: RefCell<CallHistory>,
         get_playlist_id_returns: Result<String, PlaylistError<FakeError>>,
         create_playlist_returns: Result<String, FakeError>,
+        can_modify_playlist_returns: Result<bool, FakeError>,
     }
 
     impl MockPlaylistAPI {
@@ -110,6 +562,7 @@ mod tests {
                 call_history: RefCell::new(
                     CallHistory{
                         create_playlist_called_with: None,
+                        create_playlist_visibility: None,
                         get_playlist_id_called_with: None,
                         add_tracks_to_playlist_called_with: None,
                         get_track_ids_in_playlist_called_with: None,
@@ -117,36 +570,43 @@ mod tests {
                 ),
                 get_playlist_id_returns: get_playlist_id_returns,
                 create_playlist_returns: create_playlist_returns,
+                can_modify_playlist_returns: Ok(true),
             }
         }
     }
 
-    impl PlaylistAPI<FakeError> for MockPlaylistAPI {
+    impl FakePlaylistAPI for MockPlaylistAPI {
         fn get_playlist_id(&self, playlist_name: &str) -> Result<String, PlaylistError<FakeError>> {
             let mut calls = self.call_history.borrow_mut();
             calls.get_playlist_id_called_with = Some(playlist_name.to_owned());
             self.get_playlist_id_returns.clone()
         }
 
-        fn create_playlist(&self, playlist_name: &str) -> Result<String, FakeError> {
+        fn create_playlist(&self, playlist_name: &str, visibility: Visibility) -> Result<String, FakeError> {
             let mut calls = self.call_history.borrow_mut();
             calls.create_playlist_called_with = Some(playlist_name.to_owned());
+            calls.create_playlist_visibility = Some(visibility);
             self.create_playlist_returns.clone()
         }
 
         #[allow(unused_variables)]
-        fn add_tracks_to_playlist(&self, playlist_id: &str, track_ids: &[String]) -> Result<(), FakeError> {
+        fn add_tracks_to_playlist(&self, playlist_id: &str, tracks: &[TrackUri], position: Option<usize>) -> Result<(), FakeError> {
             let mut calls = self.call_history.borrow_mut();
             calls.add_tracks_to_playlist_called_with = Some(playlist_id.to_owned());
             Ok(())
         }
 
         #[allow(unused_variables)]
-        fn get_track_ids_in_playlist(&self, playlist_id: &str) -> Result<Vec<String>, FakeError> {
+        fn get_track_ids_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackUri>, FakeError> {
             let mut calls = self.call_history.borrow_mut();
             calls.get_track_ids_in_playlist_called_with = Some(playlist_id.to_owned());
             Ok(Vec::new())
         }
+
+        #[allow(unused_variables)]
+        fn can_modify_playlist(&self, playlist_id: &str) -> Result<bool, FakeError> {
+            self.can_modify_playlist_returns
+        }
     }
 
     /// Used for declaring the inputs for each test
@@ -254,4 +714,94 @@ mod tests {
         assert_eq!(None, calls.add_tracks_to_playlist_called_with);
         assert_eq!(None, calls.get_track_ids_in_playlist_called_with);
     }
+
+    #[test]
+    fn get_playlist_id_fails_when_not_authorized() {
+        // Given
+        let (playlist_name, expected_playlist_id) = test_setup();
+        let mut api = MockPlaylistAPI::new(
+            Ok(expected_playlist_id.to_owned()),
+            Ok(expected_playlist_id.to_owned())
+        );
+        // The playlist is followed but owned by someone else
+        api.can_modify_playlist_returns = Ok(false);
+        // When
+        let result = get_playlist_id_create_if_needed(&api, &playlist_name);
+        // Then
+        let expected = PlaylistError::NotAuthorizedToModify(NotAuthorizedToModify{});
+        assert_eq!(Err(expected), result);
+        // Ensure that we don't create a playlist with the same name
+        let calls = api.call_history.borrow();
+        assert_eq!(None, calls.create_playlist_called_with);
+    }
+
+    #[test]
+    fn get_playlist_id_with_policy_respects_policy() {
+        // Given
+        let (playlist_name, expected_playlist_id) = test_setup();
+        let missing = MockPlaylistAPI::new(
+            Err(PlaylistError::PlaylistNotFound(PlaylistNotFound{})),
+            Ok(expected_playlist_id.to_owned()),
+        );
+        let existing = MockPlaylistAPI::new(
+            Ok(expected_playlist_id.to_owned()),
+            Ok(expected_playlist_id.to_owned()),
+        );
+        // When
+        let no_create = get_playlist_id_with_policy(&missing, &playlist_name, CreatePolicy::NoCreate, Visibility::Private);
+        let create_only = get_playlist_id_with_policy(&existing, &playlist_name, CreatePolicy::CreateOnly, Visibility::Private);
+        // Then
+        assert_eq!(Err(PlaylistError::PlaylistNotFound(PlaylistNotFound{})), no_create);
+        assert_eq!(Err(PlaylistError::PlaylistAlreadyExists(PlaylistAlreadyExists{})), create_only);
+        // Ensure that neither policy creates a playlist
+        assert_eq!(None, missing.call_history.borrow().create_playlist_called_with);
+        assert_eq!(None, existing.call_history.borrow().create_playlist_called_with);
+    }
+
+    #[test]
+    fn get_playlist_id_with_policy_creates_with_visibility() {
+        // Given
+        let (playlist_name, expected_playlist_id) = test_setup();
+        let api = MockPlaylistAPI::new(
+            Err(PlaylistError::PlaylistNotFound(PlaylistNotFound{})),
+            Ok(expected_playlist_id.to_owned()),
+        );
+        // When
+        let result = get_playlist_id_with_policy(&api, &playlist_name, CreatePolicy::CreateIfNeeded, Visibility::Public);
+        // Then
+        assert_eq!(Ok(expected_playlist_id), result);
+        assert_eq!(Some(Visibility::Public), api.call_history.borrow().create_playlist_visibility);
+        assert_eq!(Ok(Visibility::Private), "private".parse());
+        assert_eq!(Err(UnknownVisibility {}), "secret".parse::<Visibility>());
+    }
+
+    #[test]
+    fn format_playlist_table_lines_up_columns() {
+        // Given
+        let playlists = vec![
+            PlaylistInfo {
+                id: "abc".to_string(),
+                name: "Road Trip ".to_string(),
+                track_count: 120,
+                public: true,
+                owner: "rob".to_string(),
+            },
+            PlaylistInfo {
+                id: "defg".to_string(),
+                name: "Gym".to_string(),
+                track_count: 8,
+                public: false,
+                owner: "Spotify".to_string(),
+            },
+        ];
+        // When
+        let table = format_playlist_table(&playlists);
+        // Then
+        assert_eq!(
+            "NAME          ID    TRACKS  PUBLIC   OWNER\n\
+             \"Road Trip \"  abc      120  public   rob\n\
+             Gym           defg       8  private  Spotify\n",
+            table
+        );
+    }
 }
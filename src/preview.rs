@@ -0,0 +1,170 @@
+use csv_to_playlist::{get_lines_without_track_id,get_unique_track_ids,Song};
+use playlist::{PlaylistAPI,TrackMetadata};
+use render::{escape_html,format_total_duration,html_page,html_track_table,join_lines,markdown_track_table,Format};
+
+/// Spotify shows the artwork of the first four albums as a playlist's cover
+const MOSAIC_SIZE: usize = 4;
+
+/// What a playlist would look like if it was created from a CSV
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preview {
+    pub playlist_name: String,
+    /// The tracks in the order they'd be added
+    pub tracks: Vec<TrackMetadata>,
+    /// The CSV lines of songs that couldn't be added
+    pub failed_lines: Vec<usize>,
+}
+
+/// Work out what a new playlist would contain without creating it. Only the
+/// track details are looked up on Spotify.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance to look up the tracks with
+/// * `playlist_name` - The name the playlist would have
+/// * `songs` - The songs from the CSV
+pub fn build_preview<E>(playlist_api: &PlaylistAPI<E>,
+                        playlist_name: &str,
+                        songs: &[Song]) -> Result<Preview, E> {
+    let track_ids = get_unique_track_ids(songs);
+    let tracks = playlist_api.get_tracks_metadata(&track_ids)?;
+    Ok(Preview {
+        playlist_name: playlist_name.to_owned(),
+        tracks: tracks,
+        failed_lines: get_lines_without_track_id(songs),
+    })
+}
+
+impl Preview {
+    /// The length of every track added together
+    pub fn duration_ms(&self) -> u64 {
+        self.tracks.iter().map(|track| track.duration_ms).sum()
+    }
+
+    /// The artwork that would make up the playlist's cover. This is the
+    /// artwork of the first few distinct albums
+    pub fn mosaic(&self) -> Vec<&str> {
+        let mut images: Vec<&str> = Vec::new();
+        for url in self.tracks.iter().filter_map(|track| track.image_url.as_deref()) {
+            if !images.contains(&url) {
+                images.push(url);
+            }
+            if images.len() == MOSAIC_SIZE {
+                break;
+            }
+        }
+        images
+    }
+
+    /// Render the preview so that it can be reviewed
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format to render in
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Markdown => self.render_markdown(),
+            Format::Html => self.render_html(),
+        }
+    }
+
+    fn summary(&self) -> String {
+        format!("{} tracks, {}", self.tracks.len(), format_total_duration(self.duration_ms()))
+    }
+
+    fn render_markdown(&self) -> String {
+        let mut output = format!("# {}\n\n{}\n\n", self.playlist_name, self.summary());
+        let mosaic = self.mosaic();
+        if !mosaic.is_empty() {
+            let images: Vec<String> = mosaic.iter().map(|url| format!("![]({})", url)).collect();
+            output += &format!("{}\n\n", images.join(" "));
+        }
+        output += &markdown_track_table(&self.tracks);
+        if !self.failed_lines.is_empty() {
+            output += &format!("\n{} songs can't be added, see CSV lines {}.\n",
+                               self.failed_lines.len(), join_lines(&self.failed_lines));
+        }
+        output
+    }
+
+    fn render_html(&self) -> String {
+        let mut output = format!("<p>{}</p>\n", self.summary());
+        let mosaic = self.mosaic();
+        if !mosaic.is_empty() {
+            output += "<div>";
+            for url in mosaic {
+                output += &format!("<img src=\"{}\" width=\"150\" height=\"150\">", escape_html(url));
+            }
+            output += "</div>\n";
+        }
+        output += &html_track_table(&self.tracks);
+        if !self.failed_lines.is_empty() {
+            output += &format!("<p>{} songs can't be added, see CSV lines {}.</p>\n",
+                               self.failed_lines.len(), join_lines(&self.failed_lines));
+        }
+        html_page(&self.playlist_name, &output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use track_uri::TrackUri;
+
+    fn track(id: &str, name: &str, duration_ms: u64, image_url: Option<&str>) -> TrackMetadata {
+        TrackMetadata {
+            uri: TrackUri::Track(id.to_string()),
+            name: name.to_string(),
+            artists: vec!["Robyn".to_string()],
+            album: "Body Talk".to_string(),
+            duration_ms: duration_ms,
+            image_url: image_url.map(|url| url.to_string()),
+        }
+    }
+
+    fn test_setup() -> Preview {
+        Preview {
+            playlist_name: "Requests".to_string(),
+            tracks: vec![
+                track("1", "Dancing On My Own", 281_000, Some("a.jpg")),
+                track("2", "Call Your Girlfriend | Remix", 3_780_000, Some("a.jpg")),
+                track("3", "Hang With Me", 259_000, Some("b.jpg")),
+            ],
+            failed_lines: vec![4],
+        }
+    }
+
+    #[test]
+    fn mosaic_uses_distinct_artwork() {
+        assert_eq!(vec!["a.jpg", "b.jpg"], test_setup().mosaic());
+    }
+
+    #[test]
+    fn render_markdown_lists_tracks() {
+        // When
+        let result = test_setup().render(Format::Markdown);
+        // Then
+        let expected = "# Requests\n\n\
+                        3 tracks, 1 hr 12 min\n\n\
+                        ![](a.jpg) ![](b.jpg)\n\n\
+                        | # | Track | Artists | Album | Length |\n\
+                        |---|---|---|---|---|\n\
+                        | 1 | [Dancing On My Own](https://open.spotify.com/track/1) | Robyn | Body Talk | 4:41 |\n\
+                        | 2 | [Call Your Girlfriend \\| Remix](https://open.spotify.com/track/2) | Robyn | Body Talk | 63:00 |\n\
+                        | 3 | [Hang With Me](https://open.spotify.com/track/3) | Robyn | Body Talk | 4:19 |\n\
+                        \n1 songs can't be added, see CSV lines 4.\n";
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn render_html_escapes_names() {
+        // Given
+        let mut preview = test_setup();
+        preview.playlist_name = "<b>Requests</b>".to_string();
+        // When
+        let result = preview.render(Format::Html);
+        // Then
+        assert!(result.contains("<h1>&lt;b&gt;Requests&lt;/b&gt;</h1>"));
+        assert!(result.contains("<img src=\"b.jpg\" width=\"150\" height=\"150\">"));
+    }
+}
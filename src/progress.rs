@@ -0,0 +1,108 @@
+use std::fmt;
+use std::sync::Mutex;
+
+extern crate indicatif;
+use self::indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+/// The parts of an import that report progress
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Stage {
+    /// Rows read from the CSV
+    CsvRows,
+    /// Pages of tracks fetched from a playlist
+    PlaylistPages,
+    /// Batches of tracks added to a playlist
+    TrackBatches,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Stage::CsvRows => write!(f, "Reading CSV rows"),
+            Stage::PlaylistPages => write!(f, "Fetching playlist pages"),
+            Stage::TrackBatches => write!(f, "Adding track batches"),
+        }
+    }
+}
+
+/// Told how far long running work has got, so that it can be shown without
+/// the work knowing how
+pub trait Progress {
+    /// Some of a stage has been done
+    ///
+    /// # Arguments
+    ///
+    /// * `stage` - The stage that's running
+    /// * `done` - How many steps of the stage are done so far
+    /// * `total` - How many steps there are, if that's known
+    fn update(&self, stage: Stage, done: u64, total: Option<u64>);
+
+    /// A stage has finished
+    fn finish(&self, stage: Stage);
+}
+
+/// Progress that isn't shown anywhere
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn update(&self, _stage: Stage, _done: u64, _total: Option<u64>) {}
+
+    fn finish(&self, _stage: Stage) {}
+}
+
+/// Progress shown as a bar on stderr for each stage, or a spinner when the
+/// total isn't known. Nothing is shown when stderr isn't a terminal, so
+/// that logs don't fill up with redraws.
+pub struct ProgressBars {
+    current: Mutex<Option<(Stage, ProgressBar)>>,
+}
+
+impl ProgressBars {
+    pub fn new() -> ProgressBars {
+        ProgressBars { current: Mutex::new(None) }
+    }
+
+    fn bar(stage: Stage, total: Option<u64>) -> ProgressBar {
+        let bar = match total {
+            Some(total) => ProgressBar::with_draw_target(Some(total), ProgressDrawTarget::stderr())
+                .with_style(ProgressStyle::with_template("{msg} [{bar:30}] {pos}/{len}")
+                    .unwrap()
+                    .progress_chars("=> ")),
+            None => ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr())
+                .with_style(ProgressStyle::with_template("{spinner} {msg} {pos}").unwrap()),
+        };
+        bar.set_message(stage.to_string());
+        bar
+    }
+}
+
+impl Default for ProgressBars {
+    fn default() -> ProgressBars {
+        ProgressBars::new()
+    }
+}
+
+impl Progress for ProgressBars {
+    fn update(&self, stage: Stage, done: u64, total: Option<u64>) {
+        let mut current = self.current.lock().unwrap();
+        let is_new_stage = current.as_ref().is_none_or(|&(current_stage, _)| current_stage != stage);
+        if is_new_stage {
+            if let Some((_, bar)) = current.take() {
+                bar.finish_and_clear();
+            }
+            *current = Some((stage, ProgressBars::bar(stage, total)));
+        }
+        if let Some((_, ref bar)) = *current {
+            bar.set_position(done);
+        }
+    }
+
+    fn finish(&self, stage: Stage) {
+        let mut current = self.current.lock().unwrap();
+        if current.as_ref().is_some_and(|&(current_stage, _)| current_stage == stage) {
+            if let Some((_, bar)) = current.take() {
+                bar.finish_and_clear();
+            }
+        }
+    }
+}
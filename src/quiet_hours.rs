@@ -0,0 +1,93 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+extern crate chrono;
+use self::chrono::NaiveTime;
+
+/// A time of day when jobs shouldn't change playlists, eg. `01:00-06:00`.
+/// The window can cross midnight, eg. `22:00-02:00`. It includes the start
+/// but not the end
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+/// An error when quiet hours can't be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidQuietHours {
+    value: String,
+}
+
+impl Error for InvalidQuietHours {
+    fn description(&self) -> &str {
+        "Expected two different times of day such as 01:00-06:00"
+    }
+}
+
+impl fmt::Display for InvalidQuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, got {:?}", self.description(), self.value)
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = InvalidQuietHours;
+
+    fn from_str(value: &str) -> Result<QuietHours, InvalidQuietHours> {
+        let invalid = || InvalidQuietHours { value: value.to_owned() };
+        let mut times = value.splitn(2, '-')
+            .map(|time| NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|_| invalid()));
+        let start = times.next().ok_or_else(invalid)??;
+        let end = times.next().ok_or_else(invalid)??;
+        if start == end {
+            return Err(invalid());
+        }
+        Ok(QuietHours { start: start, end: end })
+    }
+}
+
+impl fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+impl QuietHours {
+    /// Whether a time of day is within the quiet hours
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The local time of day
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quiet_hours_can_cross_midnight() {
+        // Given
+        let early: QuietHours = "01:00-06:00".parse().unwrap();
+        let overnight: QuietHours = "22:30 - 02:00".parse().unwrap();
+        let time = |hour, minute| NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+        // Then
+        assert!(early.contains(time(1, 0)));
+        assert!(!early.contains(time(6, 0)));
+        assert!(!early.contains(time(23, 0)));
+        assert!(overnight.contains(time(23, 0)));
+        assert!(overnight.contains(time(1, 59)));
+        assert!(!overnight.contains(time(12, 0)));
+        assert_eq!("22:30-02:00", overnight.to_string());
+        assert!("06:00-06:00".parse::<QuietHours>().is_err());
+        assert!("1am-6am".parse::<QuietHours>().is_err());
+    }
+}
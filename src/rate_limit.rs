@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// Limits how many requests each client can make within a time window
+pub struct RateLimiter<K> {
+    limit: usize,
+    window: Duration,
+    requests: HashMap<K, Vec<Instant>>,
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    /// Create a limiter that allows `limit` requests per client in each
+    /// `window`
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The number of requests allowed within the window
+    /// * `window` - How long requests are remembered for
+    pub fn new(limit: usize, window: Duration) -> RateLimiter<K> {
+        RateLimiter {
+            limit: limit,
+            window: window,
+            requests: HashMap::new(),
+        }
+    }
+
+    /// Record a request from the client. Returns false if the client has
+    /// already made too many requests, in which case nothing is recorded.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Who made the request, such as their IP address
+    /// * `now` - When the request was made
+    pub fn check(&mut self, client: K, now: Instant) -> bool {
+        let window = self.window;
+        // Forget about clients that have no recent requests so that the map
+        // doesn't grow forever
+        self.requests.retain(|_, times| {
+            times.retain(|t| now.duration_since(*t) < window);
+            !times.is_empty()
+        });
+        let times = self.requests.entry(client).or_default();
+        if times.len() >= self.limit {
+            return false;
+        }
+        times.push(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_blocks_after_limit() {
+        // Given
+        let mut limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+        // When
+        let results = vec![
+            limiter.check("a", now),
+            limiter.check("a", now),
+            limiter.check("a", now),
+        ];
+        // Then
+        assert_eq!(vec![true, true, false], results);
+    }
+
+    #[test]
+    fn check_limits_each_client_separately() {
+        // Given
+        let mut limiter = RateLimiter::new(1, Duration::from_secs(60));
+        let now = Instant::now();
+        limiter.check("a", now);
+        // When
+        let result = limiter.check("b", now);
+        // Then
+        assert!(result);
+    }
+
+    #[test]
+    fn check_allows_requests_after_window() {
+        // Given
+        let mut limiter = RateLimiter::new(1, Duration::from_secs(60));
+        let now = Instant::now();
+        limiter.check("a", now);
+        // When
+        let result = limiter.check("a", now + Duration::from_secs(61));
+        // Then
+        assert!(result);
+    }
+}
@@ -0,0 +1,259 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap,HashMap};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use added_at::parse_added_at;
+use csv_to_playlist::{parse_csv_file_with_columns,read_column,CsvColumns,MissingColumn,Song};
+use listenbrainz::{listens_to_songs,Listen};
+use track_uri::TrackUri;
+
+extern crate chrono;
+use self::chrono::{Datelike,NaiveDateTime};
+
+/// The CSV columns that the time of each play can be read from, in order of
+/// preference
+pub const PLAYED_AT_COLUMNS: [&str; 4] = ["played_at (S)", "played_at", "listened_at", "ts"];
+
+/// How long each recap playlist covers
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Period {
+    Year,
+    Quarter,
+    Month,
+}
+
+impl Period {
+    /// The name of the period that a play falls in, eg. `2024`, `2024 Q1` or
+    /// `2024-03`. Names sort in the order the periods happened
+    ///
+    /// # Arguments
+    ///
+    /// * `played_at` - When the track was played
+    pub fn label(&self, played_at: &NaiveDateTime) -> String {
+        match *self {
+            Period::Year => played_at.year().to_string(),
+            Period::Quarter => format!("{} Q{}", played_at.year(), played_at.month0() / 3 + 1),
+            Period::Month => format!("{}-{:02}", played_at.year(), played_at.month()),
+        }
+    }
+}
+
+/// The error when a period isn't one of `year`, `quarter` or `month`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownPeriod {}
+
+impl Error for UnknownPeriod {
+    fn description(&self) -> &str {
+        "Expected year, quarter or month"
+    }
+}
+
+impl fmt::Display for UnknownPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl FromStr for Period {
+    type Err = UnknownPeriod;
+
+    fn from_str(s: &str) -> Result<Period, UnknownPeriod> {
+        match s {
+            "year" => Ok(Period::Year),
+            "quarter" => Ok(Period::Quarter),
+            "month" => Ok(Period::Month),
+            _ => Err(UnknownPeriod {}),
+        }
+    }
+}
+
+/// The error when a row's play time isn't a time that can be read
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidPlayedAt {
+    /// The line of the row, where the first play is on line 2
+    pub line: usize,
+    pub value: String,
+}
+
+impl Error for InvalidPlayedAt {
+    fn description(&self) -> &str {
+        "Expected a played_at like 2024-05-01T12:00:00Z, 2024-05-01 12:00:00 or \
+        seconds since the Unix epoch"
+    }
+}
+
+impl fmt::Display for InvalidPlayedAt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, line {} has {}", self.description(), self.line, self.value)
+    }
+}
+
+/// A song that was played once
+#[derive(Debug, Clone, PartialEq)]
+pub struct Play {
+    pub song: Song,
+    pub played_at: NaiveDateTime,
+}
+
+/// Read a play time in any format `parse_added_at` reads, or as seconds
+/// since the Unix epoch the way scrobblers write them
+///
+/// # Arguments
+///
+/// * `value` - The time, eg. `1714564800`
+pub fn parse_played_at(value: &str) -> Option<NaiveDateTime> {
+    match value.parse() {
+        Ok(seconds) => NaiveDateTime::from_timestamp_opt(seconds, 0),
+        Err(_) => parse_added_at(value),
+    }
+}
+
+/// Read a play history CSV with a row for each play, eg. a scrobble export.
+/// Songs are read from `columns` and the time of each play from one of
+/// `PLAYED_AT_COLUMNS`.
+///
+/// # Arguments
+///
+/// * `filename` - The path to the CSV file
+/// * `columns` - The columns to read each song from
+pub fn read_plays(filename: &str, columns: &CsvColumns) -> Result<Vec<Play>, Box<Error>> {
+    let songs = parse_csv_file_with_columns(filename, columns)?;
+    let times = read_column(filename, &PLAYED_AT_COLUMNS)?
+        .ok_or_else(|| MissingColumn { column: "played_at".to_owned(), similar: None })?;
+    songs.into_iter()
+        .zip(times)
+        .enumerate()
+        .map(|(i, (song, value))| match parse_played_at(&value) {
+            Some(played_at) => Ok(Play { song: song, played_at: played_at }),
+            None => Err(From::from(InvalidPlayedAt { line: i + 2, value: value })),
+        })
+        .collect()
+}
+
+/// Turn ListenBrainz listens into plays. Listens without a Spotify link
+/// aren't counted, since they can't be added.
+///
+/// # Arguments
+///
+/// * `listens` - The listens to count
+pub fn plays_from_listens(listens: &[Listen]) -> Vec<Play> {
+    listens.iter()
+        .zip(listens_to_songs(listens))
+        .filter_map(|(listen, song)| {
+            NaiveDateTime::from_timestamp_opt(listen.listened_at, 0)
+                .map(|played_at| Play { song: song, played_at: played_at })
+        })
+        .collect()
+}
+
+/// A track's place in the top tracks of a period
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankedTrack {
+    /// The song as it was first played in the period
+    pub song: Song,
+    /// The number of times it was played in the period
+    pub plays: usize,
+}
+
+/// The most played tracks in a period, most played first
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopTracks {
+    /// The name of the period, see `Period::label`
+    pub label: String,
+    pub tracks: Vec<RankedTrack>,
+}
+
+/// Count the plays of each track in each period and keep the most played.
+/// Ties go to the track that was played first. Plays without a track that
+/// can be added are left out. Periods are returned in the order they
+/// happened.
+///
+/// # Arguments
+///
+/// * `plays` - Every play to count
+/// * `period` - How long each period is
+/// * `top` - The most tracks to keep for each period
+/// * `min_plays` - Tracks played fewer times than this in a period are left
+/// out
+pub fn top_tracks(plays: &[Play], period: Period, top: usize, min_plays: usize) -> Vec<TopTracks> {
+    let mut sorted: Vec<&Play> = plays.iter().collect();
+    sorted.sort_by_key(|play| play.played_at);
+    let mut periods: BTreeMap<String, (Vec<RankedTrack>, HashMap<TrackUri, usize>)> = BTreeMap::new();
+    for play in sorted {
+        let track = TrackUri::parse(&play.song.song_id);
+        if !track.is_addable() {
+            continue;
+        }
+        let (ranked, indexes) = periods.entry(period.label(&play.played_at)).or_default();
+        let index = *indexes.entry(track).or_insert_with(|| {
+            ranked.push(RankedTrack { song: play.song.clone(), plays: 0 });
+            ranked.len() - 1
+        });
+        ranked[index].plays += 1;
+    }
+    periods.into_iter()
+        .map(|(label, (mut tracks, _))| {
+            // The sort is stable, so ties keep the order they were first played
+            tracks.sort_by_key(|track| Reverse(track.plays));
+            tracks.retain(|track| track.plays >= min_plays);
+            tracks.truncate(top);
+            TopTracks { label: label, tracks: tracks }
+        })
+        .filter(|period| !period.tracks.is_empty())
+        .collect()
+}
+
+/// Get the name of the playlist for a period
+///
+/// # Arguments
+///
+/// * `template` - The name with `{period}` in place of the period's name,
+/// eg. `Top Tracks {period}`
+/// * `label` - The name of the period
+pub fn recap_playlist_name(template: &str, label: &str) -> String {
+    template.replace("{period}", label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(id: &str, played_at: &str) -> Play {
+        Play {
+            song: Song { music: id.to_string(), song_id: id.to_string() },
+            played_at: parse_played_at(played_at).unwrap(),
+        }
+    }
+
+    fn ids(top: &TopTracks) -> Vec<(&str, usize)> {
+        top.tracks.iter().map(|track| (track.song.song_id.as_str(), track.plays)).collect()
+    }
+
+    #[test]
+    fn top_tracks_are_counted_for_each_period() {
+        // Given
+        let plays = vec![
+            play("idb0000000000000000000", "2024-03-02"),
+            play("ida0000000000000000000", "2024-01-01"),
+            play("idb0000000000000000000", "2024-02-01 10:00:00"),
+            play("idc0000000000000000000", "2024-04-01T00:00:00Z"),
+            play("ida0000000000000000000", "2024-05-01"),
+            play("true", "2024-05-01"),
+            play("idc0000000000000000000", "1704067199"),
+        ];
+        // When
+        let years = top_tracks(&plays, Period::Year, 2, 1);
+        let quarters = top_tracks(&plays, Period::Quarter, 10, 2);
+        // Then
+        assert_eq!(vec!["2023", "2024"], years.iter().map(|top| top.label.as_str()).collect::<Vec<_>>());
+        assert_eq!(vec![("idc0000000000000000000", 1)], ids(&years[0]));
+        assert_eq!(vec![("ida0000000000000000000", 2), ("idb0000000000000000000", 2)], ids(&years[1]));
+        assert_eq!(1, quarters.len());
+        assert_eq!("2024 Q1", quarters[0].label);
+        assert_eq!(vec![("idb0000000000000000000", 2)], ids(&quarters[0]));
+        assert_eq!("Top Tracks 2024-05", recap_playlist_name("Top Tracks {period}", &Period::Month.label(&plays[4].played_at)));
+        assert!("week".parse::<Period>().is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use name_match::{has_artist,same_name,search_value};
+use playlist::{PlaylistAPI,TrackMetadata};
+use track_uri::TrackUri;
+
+/// The most tracks to look at when searching for an equivalent
+const EQUIVALENT_SEARCH_LIMIT: u32 = 5;
+
+/// A country that Spotify sells music in, as its two letter code, eg. `AU`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Market(String);
+
+impl FromStr for Market {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Market, String> {
+        if value.len() == 2 && value.chars().all(|c| c.is_ascii_alphabetic()) {
+            Ok(Market(value.to_ascii_uppercase()))
+        } else {
+            Err(format!("Expected a two letter country code such as AU, got {:?}", value))
+        }
+    }
+}
+
+impl fmt::Display for Market {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// What a track that can't be played in a market can be swapped for
+#[derive(Debug, Clone, PartialEq)]
+pub enum Equivalent {
+    /// Spotify plays another release of the same track in the market
+    Relinked(TrackUri),
+    /// A track with the same name and artist can be played in the market
+    Found(TrackUri),
+    /// Nothing that can be played was found
+    Missing,
+}
+
+/// A track in a playlist that isn't available in a market
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnavailableTrack {
+    pub track: TrackMetadata,
+    pub equivalent: Equivalent,
+}
+
+impl UnavailableTrack {
+    /// The track to swap this one for, if one was found
+    pub fn replacement(&self) -> Option<&TrackUri> {
+        match self.equivalent {
+            Equivalent::Relinked(ref uri) | Equivalent::Found(ref uri) => Some(uri),
+            Equivalent::Missing => None,
+        }
+    }
+}
+
+/// Search for a track with the same name and artist that can be played in
+/// the market
+fn find_available_equivalent<E>(playlist_api: &PlaylistAPI<E>,
+                                track: &TrackMetadata,
+                                market: &Market) -> Result<Option<TrackUri>, E> {
+    let artist = match track.artists.first() {
+        Some(artist) => artist,
+        None => return Ok(None),
+    };
+    let query = format!("track:\"{}\" artist:\"{}\"", search_value(&track.name), search_value(artist));
+    let candidates: Vec<TrackUri> = playlist_api.search_tracks(&query, EQUIVALENT_SEARCH_LIMIT)?
+        .into_iter()
+        .filter(|candidate| candidate.uri != track.uri)
+        .filter(|candidate| same_name(&candidate.name, &track.name) && has_artist(candidate, artist))
+        .map(|candidate| candidate.uri)
+        .collect();
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+    let availability = playlist_api.get_track_availability(&candidates, &market.0)?;
+    Ok(availability.into_iter()
+        .find(|candidate| candidate.playable)
+        .map(|candidate| candidate.relinked.unwrap_or(candidate.track.uri)))
+}
+
+/// Find the tracks in a playlist that aren't available in a market, eg.
+/// after moving country, along with what they could be swapped for. Each
+/// track is only reported once, even if it's in the playlist more than once.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the playlist is
+/// * `playlist_id` - The playlist ID to check. This is the ID and *not* the
+/// name.
+/// * `market` - The market to check the tracks in
+pub fn find_unavailable_tracks<E>(playlist_api: &PlaylistAPI<E>,
+                                  playlist_id: &str,
+                                  market: &Market) -> Result<Vec<UnavailableTrack>, E> {
+    let mut seen = HashSet::new();
+    let tracks: Vec<TrackUri> = playlist_api.get_track_ids_in_playlist(playlist_id)?
+        .into_iter()
+        .filter(|track| seen.insert(track.clone()))
+        .collect();
+    let mut unavailable = Vec::new();
+    for availability in playlist_api.get_track_availability(&tracks, &market.0)? {
+        let equivalent = match (availability.playable, availability.relinked) {
+            (true, None) => continue,
+            (true, Some(relinked)) => Equivalent::Relinked(relinked),
+            (false, _) => match find_available_equivalent(playlist_api, &availability.track, market)? {
+                Some(found) => Equivalent::Found(found),
+                None => Equivalent::Missing,
+            },
+        };
+        unavailable.push(UnavailableTrack { track: availability.track, equivalent: equivalent });
+    }
+    Ok(unavailable)
+}
+
+/// The old and new tracks for `replace::replace_tracks`, leaving out tracks
+/// that have nothing to be swapped for
+///
+/// # Arguments
+///
+/// * `unavailable` - The tracks from `find_unavailable_tracks`
+pub fn get_relink_mapping(unavailable: &[UnavailableTrack]) -> Vec<(TrackUri, TrackUri)> {
+    unavailable.iter()
+        .filter_map(|track| track.replacement().map(|new| (track.track.uri.clone(), new.clone())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_api::{FakeError,FakePlaylistAPI};
+    use playlist::{PlaylistError,TrackAvailability,Visibility};
+
+    use std::cell::RefCell;
+
+    /// A fake API where some of the playlist can't be played in Australia
+    struct MockPlaylistAPI {
+        search_tracks_called_with: RefCell<Vec<String>>,
+    }
+
+    fn track(id: &str) -> TrackUri {
+        TrackUri::Track(id.to_string())
+    }
+
+    /// Every track is the same song, apart from `lost` which has no artist
+    fn metadata(id: &str) -> TrackMetadata {
+        TrackMetadata {
+            uri: track(id),
+            name: "Honey".to_string(),
+            artists: if id == "lost" { Vec::new() } else { vec!["Robyn".to_string()] },
+            album: id.to_string(),
+            duration_ms: 0,
+            image_url: None,
+        }
+    }
+
+    impl FakePlaylistAPI for MockPlaylistAPI {
+        #[allow(unused_variables)]
+        fn get_playlist_id(&self, playlist_name: &str) -> Result<String, PlaylistError<FakeError>> {
+            Ok("".to_string())
+        }
+
+        #[allow(unused_variables)]
+        fn create_playlist(&self, playlist_name: &str, visibility: Visibility) -> Result<String, FakeError> {
+            Ok("".to_string())
+        }
+
+        #[allow(unused_variables)]
+        fn get_track_ids_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackUri>, FakeError> {
+            Ok(vec![track("home"), track("gone"), track("moved"), track("gone"), track("lost")])
+        }
+
+        #[allow(unused_variables)]
+        fn search_tracks(&self, query: &str, limit: u32) -> Result<Vec<TrackMetadata>, FakeError> {
+            self.search_tracks_called_with.borrow_mut().push(query.to_owned());
+            // The same song on another album, which is only released here
+            // on the album that can't be played
+            Ok(vec![metadata("gone"), metadata("elsewhere"), metadata("local")])
+        }
+
+        fn get_track_availability(&self, tracks: &[TrackUri], market: &str) -> Result<Vec<TrackAvailability>, FakeError> {
+            assert_eq!("AU", market);
+            Ok(tracks.iter().map(|uri| {
+                let id = uri.id().unwrap();
+                let mut available = TrackAvailability { track: metadata(id), playable: true, relinked: None };
+                match id {
+                    "gone" | "lost" | "elsewhere" => available.playable = false,
+                    "moved" => available.relinked = Some(track("moved_au")),
+                    _ => (),
+                }
+                available
+            }).collect())
+        }
+    }
+
+    #[test]
+    fn unavailable_tracks_are_relinked_or_searched_for() {
+        // Given
+        let api = MockPlaylistAPI { search_tracks_called_with: RefCell::new(Vec::new()) };
+        let market: Market = "au".parse().unwrap();
+        // When
+        let unavailable = find_unavailable_tracks(&api, "playlist", &market).unwrap();
+        // Then
+        let found: Vec<(String, Equivalent)> = unavailable.iter()
+            .map(|track| (track.track.uri.uri(), track.equivalent.clone()))
+            .collect();
+        assert_eq!(vec![
+            ("spotify:track:gone".to_string(), Equivalent::Found(track("local"))),
+            ("spotify:track:moved".to_string(), Equivalent::Relinked(track("moved_au"))),
+            ("spotify:track:lost".to_string(), Equivalent::Missing),
+        ], found);
+        // Duplicates are only looked up once and tracks without an artist
+        // can't be searched for
+        assert_eq!(vec!["track:\"Honey\" artist:\"Robyn\"".to_string()], *api.search_tracks_called_with.borrow());
+        assert_eq!(vec![
+            (track("gone"), track("local")),
+            (track("moved"), track("moved_au")),
+        ], get_relink_mapping(&unavailable));
+        assert!("Australia".parse::<Market>().is_err());
+    }
+}
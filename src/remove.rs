@@ -0,0 +1,187 @@
+use csv_to_playlist::{get_unique_track_ids,Song};
+use playlist::{check_can_modify,PlaylistAPI,PlaylistError};
+use track_uri::TrackUri;
+
+/// The tracks that were removed from a playlist, or would be in a dry run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Removal {
+    pub playlist_id: String,
+    /// Whether the playlist was left unchanged
+    pub dry_run: bool,
+    /// The tracks from the songs that were in the playlist
+    pub removed: Vec<TrackUri>,
+    /// The tracks from the songs that weren't in the playlist
+    pub not_in_playlist: Vec<TrackUri>,
+}
+
+/// Remove the songs from a playlist, eg. to undo a bad import. Every
+/// occurrence of each track is removed. The playlist is never created.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the tracks should be removed
+/// * `playlist_name` - The name of the playlist to remove from
+/// * `songs` - A slice of the songs to remove
+/// * `dry_run` - Work out what would be removed without changing anything
+pub fn remove_songs_from_playlist<E>(playlist_api: &PlaylistAPI<E>,
+                                     playlist_name: &str,
+                                     songs: &[Song],
+                                     dry_run: bool) -> Result<Removal, PlaylistError<E>> {
+    let playlist_id = playlist_api.get_playlist_id(playlist_name)?;
+    if !dry_run {
+        check_can_modify(playlist_api, &playlist_id)?;
+    }
+    let existing = playlist_api.get_track_ids_in_playlist(
+        &playlist_id
+    ).map_err(PlaylistError::APIError)?;
+    let (removed, not_in_playlist): (Vec<TrackUri>, Vec<TrackUri>) = get_unique_track_ids(songs)
+        .into_iter()
+        .partition(|track| existing.contains(track));
+    if !dry_run && !removed.is_empty() {
+        playlist_api.remove_tracks_from_playlist(
+            &playlist_id, &removed
+        ).map_err(PlaylistError::APIError)?;
+    }
+    Ok(Removal {
+        playlist_id: playlist_id,
+        dry_run: dry_run,
+        removed: removed,
+        not_in_playlist: not_in_playlist,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_api::{FakeError,FakePlaylistAPI};
+    use playlist::{PlaylistNotFound,NotAuthorizedToModify,Visibility};
+
+    use std::cell::RefCell;
+
+    /// A fake API where you can specify the return values to the functions
+    struct MockPlaylistAPI {
+        remove_tracks_from_playlist_called_with: RefCell<Option<(String, Vec<TrackUri>)>>,
+        get_playlist_id_returns: Result<String, PlaylistError<FakeError>>,
+        get_track_ids_in_playlist_returns: Result<Vec<TrackUri>, FakeError>,
+        can_modify_playlist_returns: Result<bool, FakeError>,
+    }
+
+    impl MockPlaylistAPI {
+        /// Create a new MockPlaylistAPI
+        fn new(get_playlist_id_returns: Result<String, PlaylistError<FakeError>>,
+               get_track_ids_in_playlist_returns: Result<Vec<TrackUri>, FakeError>) -> MockPlaylistAPI {
+            MockPlaylistAPI {
+                remove_tracks_from_playlist_called_with: RefCell::new(None),
+                get_playlist_id_returns: get_playlist_id_returns,
+                get_track_ids_in_playlist_returns: get_track_ids_in_playlist_returns,
+                can_modify_playlist_returns: Ok(true),
+            }
+        }
+    }
+
+    impl FakePlaylistAPI for MockPlaylistAPI {
+        #[allow(unused_variables)]
+        fn get_playlist_id(&self, playlist_name: &str) -> Result<String, PlaylistError<FakeError>> {
+            self.get_playlist_id_returns.clone()
+        }
+
+        #[allow(unused_variables)]
+        fn create_playlist(&self, playlist_name: &str, visibility: Visibility) -> Result<String, FakeError> {
+            Ok("".to_string())
+        }
+
+        #[allow(unused_variables)]
+        fn get_track_ids_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackUri>, FakeError> {
+            self.get_track_ids_in_playlist_returns.clone()
+        }
+
+        #[allow(unused_variables)]
+        fn can_modify_playlist(&self, playlist_id: &str) -> Result<bool, FakeError> {
+            self.can_modify_playlist_returns
+        }
+
+        fn remove_tracks_from_playlist(&self, playlist_id: &str, tracks: &[TrackUri]) -> Result<(), FakeError> {
+            *self.remove_tracks_from_playlist_called_with.borrow_mut() = Some(
+                (playlist_id.to_owned(), tracks.to_vec())
+            );
+            Ok(())
+        }
+    }
+
+    fn track(id: &str) -> TrackUri {
+        TrackUri::Track(format!("{:0<22}", id))
+    }
+
+    /// Used for declaring the inputs for each test
+    fn test_setup() -> (Vec<Song>, MockPlaylistAPI) {
+        let songs = vec![
+            Song{music:"a".to_string(), song_id:"ida0000000000000000000".to_string()},
+            Song{music:"b".to_string(), song_id:"idb0000000000000000000".to_string()},
+            Song{music:"a again".to_string(), song_id:"ida0000000000000000000".to_string()},
+            Song{music:"none".to_string(), song_id:"true".to_string()},
+        ];
+        let api = MockPlaylistAPI::new(
+            Ok("id_123".to_string()),
+            Ok(vec![track("idc0000000000000000000"), track("ida0000000000000000000"), track("ida0000000000000000000")]),
+        );
+        (songs, api)
+    }
+
+    #[test]
+    fn remove_songs_from_playlist_removes_tracks_in_playlist() {
+        // Given
+        let (songs, api) = test_setup();
+        // When
+        let result = remove_songs_from_playlist(&api, "name", &songs, false).unwrap();
+        // Then
+        let expected = Removal {
+            playlist_id: "id_123".to_string(),
+            dry_run: false,
+            removed: vec![track("ida0000000000000000000")],
+            not_in_playlist: vec![track("idb0000000000000000000")],
+        };
+        assert_eq!(expected, result);
+        let expected_call = Some(("id_123".to_string(), vec![track("ida0000000000000000000")]));
+        assert_eq!(expected_call, *api.remove_tracks_from_playlist_called_with.borrow());
+    }
+
+    #[test]
+    fn remove_songs_from_playlist_dry_run_changes_nothing() {
+        // Given
+        let (songs, mut api) = test_setup();
+        // Dry runs don't need to be able to modify the playlist
+        api.can_modify_playlist_returns = Ok(false);
+        // When
+        let result = remove_songs_from_playlist(&api, "name", &songs, true).unwrap();
+        // Then
+        assert_eq!(vec![track("ida0000000000000000000")], result.removed);
+        assert_eq!(None, *api.remove_tracks_from_playlist_called_with.borrow());
+    }
+
+    #[test]
+    fn remove_songs_from_playlist_fails_when_not_authorized() {
+        // Given
+        let (songs, mut api) = test_setup();
+        api.can_modify_playlist_returns = Ok(false);
+        // When
+        let result = remove_songs_from_playlist(&api, "name", &songs, false);
+        // Then
+        let expected = PlaylistError::NotAuthorizedToModify(NotAuthorizedToModify{});
+        assert_eq!(Err(expected), result);
+        assert_eq!(None, *api.remove_tracks_from_playlist_called_with.borrow());
+    }
+
+    #[test]
+    fn remove_songs_from_playlist_fails_for_missing_playlist() {
+        // Given
+        let (songs, _) = test_setup();
+        let api = MockPlaylistAPI::new(
+            Err(PlaylistError::PlaylistNotFound(PlaylistNotFound{})),
+            Ok(Vec::new()),
+        );
+        // When
+        let result = remove_songs_from_playlist(&api, "name", &songs, false);
+        // Then
+        assert_eq!(Err(PlaylistError::PlaylistNotFound(PlaylistNotFound{})), result);
+    }
+}
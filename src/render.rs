@@ -0,0 +1,126 @@
+use std::str::FromStr;
+
+use playlist::TrackMetadata;
+use track_uri::TrackUri;
+
+/// The formats that previews and reports can be rendered in
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format {
+    Markdown,
+    Html,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Format, String> {
+        match value {
+            "md" => Ok(Format::Markdown),
+            "html" => Ok(Format::Html),
+            _ => Err(format!("Expected md or html, got {:?}", value)),
+        }
+    }
+}
+
+/// Get the link to open a track or episode on Spotify
+pub fn web_url(uri: &TrackUri) -> Option<String> {
+    match *uri {
+        TrackUri::Track(ref id) => Some(format!("https://open.spotify.com/track/{}", id)),
+        TrackUri::Episode(ref id) => Some(format!("https://open.spotify.com/episode/{}", id)),
+        _ => None,
+    }
+}
+
+/// Format a track length as minutes and seconds, eg. 3:07
+pub fn format_duration(duration_ms: u64) -> String {
+    let seconds = duration_ms / 1000;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Format the length of a whole playlist, eg. 1 hr 5 min
+pub fn format_total_duration(duration_ms: u64) -> String {
+    let minutes = duration_ms / 1000 / 60;
+    if minutes >= 60 {
+        format!("{} hr {} min", minutes / 60, minutes % 60)
+    } else {
+        format!("{} min", minutes)
+    }
+}
+
+/// Join CSV line numbers for display, eg. 2, 5, 9
+pub fn join_lines(lines: &[usize]) -> String {
+    lines.iter().map(|line| line.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+/// Escape text so that it can't break out of a table cell or add formatting
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\|*_[]`<>#".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escape text so that it can be put in HTML content or attributes
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Render tracks as a Markdown table with a link to each track
+pub fn markdown_track_table(tracks: &[TrackMetadata]) -> String {
+    let mut output = String::from("| # | Track | Artists | Album | Length |\n|---|---|---|---|---|\n");
+    for (i, track) in tracks.iter().enumerate() {
+        let name = escape_markdown(&track.name);
+        let name = match web_url(&track.uri) {
+            Some(url) => format!("[{}]({})", name, url),
+            None => name,
+        };
+        output += &format!(
+            "| {} | {} | {} | {} | {} |\n",
+            i + 1, name, escape_markdown(&track.artists.join(", ")),
+            escape_markdown(&track.album), format_duration(track.duration_ms)
+        );
+    }
+    output
+}
+
+/// Render tracks as an HTML table with a link to each track
+pub fn html_track_table(tracks: &[TrackMetadata]) -> String {
+    let mut output = String::from(
+        "<table>\n<tr><th>#</th><th>Track</th><th>Artists</th><th>Album</th><th>Length</th></tr>\n"
+    );
+    for (i, track) in tracks.iter().enumerate() {
+        let name = escape_html(&track.name);
+        let name = match web_url(&track.uri) {
+            Some(url) => format!("<a href=\"{}\">{}</a>", url, name),
+            None => name,
+        };
+        output += &format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            i + 1, name, escape_html(&track.artists.join(", ")),
+            escape_html(&track.album), format_duration(track.duration_ms)
+        );
+    }
+    output + "</table>\n"
+}
+
+/// Wrap HTML in a standalone page with a heading
+///
+/// # Arguments
+///
+/// * `title` - The page title, which is escaped
+/// * `body` - The HTML to put after the heading
+pub fn html_page(title: &str, body: &str) -> String {
+    let title = escape_html(title);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{}</title></head>\n\
+         <body>\n<h1>{}</h1>\n{}</body>\n</html>\n",
+        title, title, body
+    )
+}
@@ -0,0 +1,249 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::slice;
+
+use playlist::PlaylistAPI;
+use track_uri::TrackUri;
+
+extern crate csv;
+
+/// A row of a mapping CSV
+#[derive(Debug, Deserialize)]
+struct MappingRow {
+    old_id: String,
+    new_id: String,
+}
+
+/// An error when a row of the mapping can't be used
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidMapping {
+    /// The line in the CSV, where the header is line 1
+    line: usize,
+    value: String,
+}
+
+impl Error for InvalidMapping {
+    fn description(&self) -> &str {
+        "Mapping contains a track that can't be added"
+    }
+}
+
+impl fmt::Display for InvalidMapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} on line {}: {}", self.description(), self.line, self.value)
+    }
+}
+
+/// A track in the playlist that will be swapped for another
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Replacement {
+    /// The position in the playlist, starting from 0
+    pub position: usize,
+    pub old: TrackUri,
+    pub new: TrackUri,
+}
+
+/// Parse a mapping file with `old_id` and `new_id` columns. IDs can be
+/// anything that `TrackUri` understands, such as URIs or links.
+///
+/// # Arguments
+///
+/// * `filename` - The path to the CSV file
+pub fn parse_mapping_file(filename: &str) -> Result<Vec<(TrackUri, TrackUri)>, Box<Error>> {
+    let file = File::open(filename)?;
+    parse_mapping(file)
+}
+
+/// Parse mapping CSV data, see `parse_mapping_file`. Fails if a new ID
+/// can't be added to a playlist, eg. a local file.
+///
+/// # Arguments
+///
+/// * `reader` - Where the CSV data is read from
+pub fn parse_mapping<R: Read>(reader: R) -> Result<Vec<(TrackUri, TrackUri)>, Box<Error>> {
+    let mut rdr = csv::Reader::from_reader(reader);
+    let mut mapping = Vec::new();
+    for (i, result) in rdr.deserialize().enumerate() {
+        let row: MappingRow = result?;
+        let new = TrackUri::parse(&row.new_id);
+        if !new.is_addable() {
+            return Err(Box::new(InvalidMapping { line: i + 2, value: row.new_id }));
+        }
+        mapping.push((TrackUri::parse(&row.old_id), new));
+    }
+    Ok(mapping)
+}
+
+/// Find every occurrence of the old tracks in the playlist. When an old
+/// track is mapped more than once, the first mapping is used.
+///
+/// # Arguments
+///
+/// * `current` - The items in the playlist, in order
+/// * `mapping` - Pairs of old and new tracks
+pub fn get_replacements(current: &[TrackUri],
+                        mapping: &[(TrackUri, TrackUri)]) -> Vec<Replacement> {
+    current.iter()
+        .enumerate()
+        .filter_map(|(position, track)| {
+            mapping.iter()
+                .find(|&(old, _)| old == track)
+                .filter(|&(old, new)| old != new)
+                .map(|(old, new)| Replacement {
+                    position: position,
+                    old: old.clone(),
+                    new: new.clone(),
+                })
+        })
+        .collect()
+}
+
+/// Swap tracks in a playlist for the tracks they're mapped to, keeping each
+/// new track at the same position as the old one. Returns the replacements
+/// that were made.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the tracks should be replaced
+/// * `playlist_id` - The playlist ID to be changed. This is the ID and *not*
+/// the name.
+/// * `mapping` - Pairs of old and new tracks
+/// * `dry_run` - Work out the replacements without changing anything
+pub fn replace_tracks<E>(playlist_api: &PlaylistAPI<E>,
+                         playlist_id: &str,
+                         mapping: &[(TrackUri, TrackUri)],
+                         dry_run: bool) -> Result<Vec<Replacement>, E> {
+    let current = playlist_api.get_track_ids_in_playlist(playlist_id)?;
+    let replacements = get_replacements(&current, mapping);
+    if dry_run || replacements.is_empty() {
+        return Ok(replacements);
+    }
+    let occurrences: Vec<(TrackUri, usize)> = replacements.iter()
+        .map(|r| (r.old.clone(), r.position))
+        .collect();
+    playlist_api.remove_tracks_at_positions(playlist_id, &occurrences)?;
+    // Once every old track is removed, inserting in order puts each new
+    // track back at its old position
+    for replacement in &replacements {
+        playlist_api.insert_tracks_into_playlist(
+            playlist_id, slice::from_ref(&replacement.new), replacement.position
+        )?;
+    }
+    Ok(replacements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_api::{FakeError,FakePlaylistAPI};
+    use playlist::{PlaylistError,Visibility};
+
+    use std::cell::RefCell;
+
+    /// A fake playlist that applies changes to its tracks
+    struct MockPlaylistAPI {
+        tracks: RefCell<Vec<TrackUri>>,
+    }
+
+    impl FakePlaylistAPI for MockPlaylistAPI {
+        #[allow(unused_variables)]
+        fn get_playlist_id(&self, playlist_name: &str) -> Result<String, PlaylistError<FakeError>> {
+            Ok("".to_string())
+        }
+
+        #[allow(unused_variables)]
+        fn create_playlist(&self, playlist_name: &str, visibility: Visibility) -> Result<String, FakeError> {
+            Ok("".to_string())
+        }
+
+        #[allow(unused_variables)]
+        fn get_track_ids_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackUri>, FakeError> {
+            Ok(self.tracks.borrow().clone())
+        }
+
+        #[allow(unused_variables)]
+        fn insert_tracks_into_playlist(&self, playlist_id: &str, tracks: &[TrackUri], position: usize) -> Result<(), FakeError> {
+            let mut current = self.tracks.borrow_mut();
+            for (i, track) in tracks.iter().enumerate() {
+                current.insert(position + i, track.clone());
+            }
+            Ok(())
+        }
+
+        #[allow(unused_variables)]
+        fn remove_tracks_at_positions(&self, playlist_id: &str, occurrences: &[(TrackUri, usize)]) -> Result<(), FakeError> {
+            let mut positions: Vec<usize> = occurrences.iter().map(|&(_, p)| p).collect();
+            // Remove from the end so that earlier positions stay the same
+            positions.sort();
+            let mut current = self.tracks.borrow_mut();
+            for position in positions.into_iter().rev() {
+                current.remove(position);
+            }
+            Ok(())
+        }
+    }
+
+    fn track(id: &str) -> TrackUri {
+        TrackUri::Track(format!("{:0<22}", id))
+    }
+
+    #[test]
+    fn parse_mapping_reads_ids_and_links() {
+        // Given
+        let data = "old_id,new_id\n\
+                    ida0000000000000000000,https://open.spotify.com/track/idb0000000000000000000\n\
+                    spotify:local:A:B:C:1,spotify:episode:idc0000000000000000000\n";
+        // When
+        let result = parse_mapping(data.as_bytes()).unwrap();
+        // Then
+        let expected = vec![
+            (track("ida0000000000000000000"), track("idb0000000000000000000")),
+            (TrackUri::Local("spotify:local:A:B:C:1".to_string()), TrackUri::Episode("idc0000000000000000000".to_string())),
+        ];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn parse_mapping_rejects_new_ids_that_cant_be_added() {
+        // Given
+        let data = "old_id,new_id\nida0000000000000000000,idb0000000000000000000\nidc0000000000000000000,spotify:album:x\n";
+        // When
+        let result = parse_mapping(data.as_bytes());
+        // Then
+        assert_eq!(
+            "Mapping contains a track that can't be added on line 3: spotify:album:x",
+            result.unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn replace_tracks_keeps_positions() {
+        // Given
+        let api = MockPlaylistAPI {
+            tracks: RefCell::new(vec![track("a"), track("x"), track("b"), track("x"), track("y")]),
+        };
+        let mapping = vec![(track("x"), track("new_x")), (track("y"), track("new_y"))];
+        // When
+        let result = replace_tracks(&api, "id_123", &mapping, false).unwrap();
+        // Then
+        assert_eq!(3, result.len());
+        assert_eq!(Replacement { position: 3, old: track("x"), new: track("new_x") }, result[1]);
+        let expected = vec![track("a"), track("new_x"), track("b"), track("new_x"), track("new_y")];
+        assert_eq!(expected, *api.tracks.borrow());
+    }
+
+    #[test]
+    fn replace_tracks_dry_run_changes_nothing() {
+        // Given
+        let tracks = vec![track("a"), track("x")];
+        let api = MockPlaylistAPI { tracks: RefCell::new(tracks.clone()) };
+        let mapping = vec![(track("x"), track("new_x"))];
+        // When
+        let result = replace_tracks(&api, "id_123", &mapping, true).unwrap();
+        // Then
+        assert_eq!(1, result.len());
+        assert_eq!(tracks, *api.tracks.borrow());
+    }
+}
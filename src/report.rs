@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use genius::SongAnnotation;
+use i18n::tr;
+use playlist::TrackMetadata;
+use render::{escape_html,escape_markdown,format_total_duration,html_page,html_track_table,join_lines,markdown_track_table,Format};
+use track_uri::TrackUri;
+
+/// What happened in an import, so that it can be shared, eg. on a wiki
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunReport {
+    pub playlist_name: String,
+    pub playlist_url: String,
+    /// The CSV that was imported
+    pub source: String,
+    /// When the import ran
+    pub date: String,
+    /// The number of songs that were read from the CSV
+    pub song_count: usize,
+    /// The tracks that were added, in the order they were added
+    pub added: Vec<TrackMetadata>,
+    /// Line numbers in the CSV of rows that could not be added
+    pub failed_lines: Vec<usize>,
+    /// Genius pages and writers of the added tracks that were found, see
+    /// `annotate_tracks`
+    pub annotations: HashMap<TrackUri, SongAnnotation>,
+}
+
+impl RunReport {
+    /// Render the report
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format to render in
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Markdown => self.render_markdown(),
+            Format::Html => self.render_html(),
+        }
+    }
+
+    fn stats(&self) -> String {
+        let duration_ms = self.added.iter().map(|track| track.duration_ms).sum();
+        tr("report-stats", &[
+            ("songs", self.song_count.into()),
+            ("added", self.added.len().into()),
+            ("duration", format_total_duration(duration_ms).into()),
+            ("failed", self.failed_lines.len().into()),
+        ])
+    }
+
+    /// Get the added tracks that have annotations, in the order they were
+    /// added
+    fn annotated_tracks(&self) -> Vec<(&TrackMetadata, &SongAnnotation)> {
+        self.added.iter()
+            .filter_map(|track| self.annotations.get(&track.uri).map(|annotation| (track, annotation)))
+            .collect()
+    }
+
+    fn render_markdown(&self) -> String {
+        let link = format!("[{}]({})", escape_markdown(&self.playlist_name), self.playlist_url);
+        let mut output = format!(
+            "# {}\n\n{}\n\n{}\n",
+            tr("report-title", &[("playlist", link.into())]),
+            tr("report-source", &[("date", self.date.as_str().into()), ("source", escape_markdown(&self.source).into())]),
+            self.stats()
+        );
+        if !self.added.is_empty() {
+            output += &format!("\n## {}\n\n{}", tr("report-added", &[]), markdown_track_table(&self.added));
+        }
+        let annotated = self.annotated_tracks();
+        if !annotated.is_empty() {
+            output += &format!("\n## {}\n\n", tr("report-songwriters", &[]));
+            for (track, annotation) in annotated {
+                output += &format!("- [{}]({}){}\n", escape_markdown(&track.name), annotation.url,
+                                   written_by(&annotation.writers, &escape_markdown));
+            }
+        }
+        if !self.failed_lines.is_empty() {
+            output += &format!("\n## {}\n\n{}\n", tr("report-failures", &[]), failed_lines(&self.failed_lines));
+        }
+        output
+    }
+
+    fn render_html(&self) -> String {
+        let source = tr("report-source", &[("date", self.date.as_str().into()), ("source", self.source.as_str().into())]);
+        let mut body = format!(
+            "<p><a href=\"{}\">{}</a></p>\n<p>{}</p>\n<p>{}</p>\n",
+            escape_html(&self.playlist_url), escape_html(&self.playlist_name),
+            escape_html(&source), self.stats()
+        );
+        if !self.added.is_empty() {
+            body += &format!("<h2>{}</h2>\n{}", tr("report-added", &[]), html_track_table(&self.added));
+        }
+        let annotated = self.annotated_tracks();
+        if !annotated.is_empty() {
+            body += &format!("<h2>{}</h2>\n<ul>\n", tr("report-songwriters", &[]));
+            for (track, annotation) in annotated {
+                body += &format!("<li><a href=\"{}\">{}</a>{}</li>\n", escape_html(&annotation.url),
+                                 escape_html(&track.name), written_by(&annotation.writers, &escape_html));
+            }
+            body += "</ul>\n";
+        }
+        if !self.failed_lines.is_empty() {
+            body += &format!("<h2>{}</h2>\n<p>{}</p>\n", tr("report-failures", &[]), failed_lines(&self.failed_lines));
+        }
+        html_page(&tr("report-title", &[("playlist", self.playlist_name.as_str().into())]), &body)
+    }
+}
+
+/// Credit the writers of a song, or nothing when they aren't known
+fn written_by(writers: &[String], escape: &Fn(&str) -> String) -> String {
+    if writers.is_empty() {
+        return String::new();
+    }
+    let names: Vec<String> = writers.iter().map(|writer| escape(writer)).collect();
+    tr("report-written-by", &[("writers", names.join(", ").into())])
+}
+
+/// List the CSV lines of songs that couldn't be added
+fn failed_lines(lines: &[usize]) -> String {
+    tr("report-failed-lines", &[("lines", join_lines(lines).into())])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_setup() -> RunReport {
+        RunReport {
+            playlist_name: "Requests".to_string(),
+            playlist_url: "https://open.spotify.com/playlist/p1".to_string(),
+            source: "songs.csv".to_string(),
+            date: "2018-03-01".to_string(),
+            song_count: 4,
+            added: vec![TrackMetadata {
+                uri: TrackUri::Track("1".to_string()),
+                name: "Dancing On My Own".to_string(),
+                artists: vec!["Robyn".to_string()],
+                album: "Body Talk".to_string(),
+                duration_ms: 281_000,
+                image_url: None,
+            }],
+            failed_lines: vec![3, 5],
+            annotations: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn render_markdown_includes_link_tracks_and_failures() {
+        // When
+        let result = test_setup().render(Format::Markdown);
+        // Then
+        let expected = "# Import into [Requests](https://open.spotify.com/playlist/p1)\n\n\
+                        2018-03-01 from songs.csv\n\n\
+                        4 songs read, 1 tracks added (4 min), 2 songs could not be added\n\n\
+                        ## Added\n\n\
+                        | # | Track | Artists | Album | Length |\n\
+                        |---|---|---|---|---|\n\
+                        | 1 | [Dancing On My Own](https://open.spotify.com/track/1) | Robyn | Body Talk | 4:41 |\n\
+                        \n## Failures\n\n\
+                        CSV lines without a usable track ID: 3, 5\n";
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn render_markdown_credits_songwriters() {
+        // Given
+        let mut report = test_setup();
+        report.annotations.insert(TrackUri::Track("1".to_string()), SongAnnotation {
+            url: "https://genius.com/Robyn-dancing-on-my-own-lyrics".to_string(),
+            writers: vec!["Robyn".to_string(), "Patrik Berger".to_string()],
+        });
+        // When
+        let result = report.render(Format::Markdown);
+        // Then
+        assert!(result.contains(
+            "## Songwriters\n\n- [Dancing On My Own](https://genius.com/Robyn-dancing-on-my-own-lyrics), \
+             written by Robyn, Patrik Berger\n"
+        ));
+    }
+
+    #[test]
+    fn render_html_without_additions() {
+        // Given
+        let mut report = test_setup();
+        report.added.clear();
+        // When
+        let result = report.render(Format::Html);
+        // Then
+        assert!(result.contains("<a href=\"https://open.spotify.com/playlist/p1\">Requests</a>"));
+        assert!(!result.contains("<table>"));
+        assert!(result.contains("<p>CSV lines without a usable track ID: 3, 5</p>"));
+    }
+}
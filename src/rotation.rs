@@ -0,0 +1,170 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use capacity::PLAYLIST_CAPACITY;
+use playlist::{PlaylistAPI,PlaylistError};
+
+/// When to stop adding to a playlist and start the next one in its rotation,
+/// eg. `Liked (2)` after `Liked`. Rotating before the playlist is completely
+/// full leaves room for tracks that are added by hand.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RotationPolicy {
+    /// The number of items at which the playlist is rotated
+    pub threshold: usize,
+}
+
+impl Default for RotationPolicy {
+    fn default() -> RotationPolicy {
+        RotationPolicy { threshold: PLAYLIST_CAPACITY }
+    }
+}
+
+impl RotationPolicy {
+    /// Whether a playlist of this size should be rotated
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The number of items in the playlist
+    pub fn should_rotate(&self, size: usize) -> bool {
+        size >= self.threshold
+    }
+}
+
+/// The error when a rotation threshold isn't a size a playlist can reach
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidRotationThreshold {}
+
+impl Error for InvalidRotationThreshold {
+    fn description(&self) -> &str {
+        "Expected a number of tracks from 1 to 10,000"
+    }
+}
+
+impl fmt::Display for InvalidRotationThreshold {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl FromStr for RotationPolicy {
+    type Err = InvalidRotationThreshold;
+
+    fn from_str(s: &str) -> Result<RotationPolicy, InvalidRotationThreshold> {
+        match s.parse() {
+            Ok(threshold) if threshold > 0 && threshold <= PLAYLIST_CAPACITY => {
+                Ok(RotationPolicy { threshold: threshold })
+            },
+            _ => Err(InvalidRotationThreshold {}),
+        }
+    }
+}
+
+/// The name of a playlist in a rotation. The first is the playlist itself
+/// and later ones carry on from it, eg. `Liked (2)`
+///
+/// # Arguments
+///
+/// * `playlist_name` - The name of the playlist
+/// * `generation` - Which playlist in the rotation this is, starting from 1
+pub fn rotation_name(playlist_name: &str, generation: usize) -> String {
+    match generation {
+        0 | 1 => playlist_name.to_owned(),
+        _ => format!("{} ({})", playlist_name, generation),
+    }
+}
+
+/// The playlist in a rotation that tracks should be added to
+#[derive(Debug, Clone, PartialEq)]
+pub struct RotationTarget {
+    /// Which playlist in the rotation, see `rotation_name`
+    pub generation: usize,
+    /// The name of the playlist
+    pub name: String,
+    /// Whether the newest playlist was full enough to start a new one. The
+    /// new playlist is created when tracks are added to it
+    pub rotated: bool,
+}
+
+/// Find the playlist in a rotation that tracks should be added to. This is
+/// the newest playlist in the rotation, or the one after it once the newest
+/// reaches the policy's threshold.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance to look up the playlists with
+/// * `playlist_name` - The name of the playlist, ie. the first in the
+/// rotation
+/// * `policy` - When to rotate
+pub fn find_rotation_target<E>(playlist_api: &PlaylistAPI<E>,
+                               playlist_name: &str,
+                               policy: RotationPolicy) -> Result<RotationTarget, PlaylistError<E>> {
+    let mut playlist_id = match playlist_api.get_playlist_id(playlist_name) {
+        Ok(playlist_id) => playlist_id,
+        Err(PlaylistError::PlaylistNotFound(_)) => {
+            return Ok(RotationTarget { generation: 1, name: playlist_name.to_owned(), rotated: false });
+        },
+        Err(e) => return Err(e),
+    };
+    let mut generation = 1;
+    loop {
+        match playlist_api.get_playlist_id(&rotation_name(playlist_name, generation + 1)) {
+            Ok(next_id) => {
+                generation += 1;
+                playlist_id = next_id;
+            },
+            Err(PlaylistError::PlaylistNotFound(_)) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    let size = playlist_api.get_playlist_size(&playlist_id).map_err(PlaylistError::APIError)?;
+    let rotated = policy.should_rotate(size);
+    if rotated {
+        generation += 1;
+    }
+    Ok(RotationTarget {
+        generation: generation,
+        name: rotation_name(playlist_name, generation),
+        rotated: rotated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::MemoryAPI;
+    use playlist::Visibility;
+    use std::env;
+    use track_uri::TrackUri;
+
+    fn tracks(count: usize) -> Vec<TrackUri> {
+        (0..count).map(|i| TrackUri::Track(i.to_string())).collect()
+    }
+
+    #[test]
+    fn rotation_moves_on_once_the_newest_playlist_is_nearly_full() {
+        // Given
+        let path = env::temp_dir().join("playlist-from-csv-rotation-test.json");
+        let filename = path.to_str().unwrap();
+        let _ = ::std::fs::remove_file(filename);
+        let api = MemoryAPI::open(filename).unwrap();
+        let policy: RotationPolicy = "3".parse().unwrap();
+        // When
+        let missing = find_rotation_target(&api, "Liked", policy).unwrap();
+        let first = api.create_playlist("Liked", Visibility::Private).unwrap();
+        api.add_tracks_to_playlist(&first, &tracks(2), None).unwrap();
+        let room = find_rotation_target(&api, "Liked", policy).unwrap();
+        api.add_tracks_to_playlist(&first, &tracks(3)[2..], None).unwrap();
+        let full = find_rotation_target(&api, "Liked", policy).unwrap();
+        api.create_playlist("Liked (2)", Visibility::Private).unwrap();
+        let second = find_rotation_target(&api, "Liked", policy).unwrap();
+        // Then
+        assert_eq!(RotationTarget { generation: 1, name: "Liked".to_owned(), rotated: false }, missing);
+        assert_eq!(RotationTarget { generation: 1, name: "Liked".to_owned(), rotated: false }, room);
+        assert_eq!(RotationTarget { generation: 2, name: "Liked (2)".to_owned(), rotated: true }, full);
+        assert_eq!(RotationTarget { generation: 2, name: "Liked (2)".to_owned(), rotated: false }, second);
+        assert!("0".parse::<RotationPolicy>().is_err());
+        assert!("10001".parse::<RotationPolicy>().is_err());
+        assert_eq!(PLAYLIST_CAPACITY, RotationPolicy::default().threshold);
+    }
+}
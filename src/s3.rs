@@ -0,0 +1,71 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path,PathBuf};
+
+use aws::{describe_error,runtime,AwsOptions};
+
+extern crate aws_sdk_s3;
+use self::aws_sdk_s3::Client;
+
+/// The prefix of S3 URLs
+pub const S3_PREFIX: &str = "s3://";
+
+/// An object in S3, given as `s3://bucket/key`
+#[derive(Debug, Clone, PartialEq)]
+pub struct S3Url {
+    pub bucket: String,
+    pub key: String,
+}
+
+/// Read an S3 URL, eg. `s3://exports/songs.csv`. Returns `None` when it's
+/// not an S3 URL or there's no key
+///
+/// # Arguments
+///
+/// * `url` - The URL
+pub fn parse_s3_url(url: &str) -> Option<S3Url> {
+    let path = url.strip_prefix(S3_PREFIX)?;
+    let (bucket, key) = path.split_at(path.find('/')?);
+    let key = &key[1..];
+    if bucket.is_empty() || key.is_empty() {
+        return None;
+    }
+    Some(S3Url { bucket: bucket.to_owned(), key: key.to_owned() })
+}
+
+/// Download an object to a temporary file, so that it can be read like any
+/// other file. The file keeps the key's name so that its extension can be
+/// used to pick its format.
+///
+/// # Arguments
+///
+/// * `url` - The object to download
+/// * `aws` - The credentials and region to download with
+pub fn download_object(url: &S3Url, aws: &AwsOptions) -> Result<PathBuf, Box<Error>> {
+    let runtime = runtime()?;
+    let client = Client::new(&aws.load(&runtime));
+    let object = runtime.block_on(client.get_object().bucket(url.bucket.as_str()).key(url.key.as_str()).send())
+        .map_err(|e| describe_error(&e))?;
+    let body = runtime.block_on(object.body.collect()).map_err(|e| describe_error(&e))?;
+    let name = Path::new(&url.key).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let path = env::temp_dir().join(format!("playlist-from-csv-{}-{}", url.bucket, name));
+    fs::write(&path, body.into_bytes())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn s3_urls_are_split_into_bucket_and_key() {
+        // When
+        let url = parse_s3_url("s3://exports/2024/songs.csv");
+        // Then
+        assert_eq!(Some(S3Url { bucket: "exports".to_owned(), key: "2024/songs.csv".to_owned() }), url);
+        assert_eq!(None, parse_s3_url("songs.csv"));
+        assert_eq!(None, parse_s3_url("s3://exports"));
+        assert_eq!(None, parse_s3_url("s3://exports/"));
+    }
+}
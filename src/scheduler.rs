@@ -0,0 +1,149 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use deadline::RunTime;
+
+extern crate chrono;
+use self::chrono::{DateTime, Local};
+
+extern crate cron;
+
+extern crate ctrlc;
+
+/// How often a sleeping schedule checks whether it should stop
+const STOP_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When to run an import that keeps running
+#[derive(Debug, Clone)]
+pub enum RunSchedule {
+    /// Run this long after the previous run started
+    Every(Duration),
+    /// Run at the times a cron expression matches
+    Cron(Box<cron::Schedule>),
+}
+
+/// An error when a cron expression can't be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidCron {
+    value: String,
+}
+
+impl Error for InvalidCron {
+    fn description(&self) -> &str {
+        "Expected a cron expression such as \"0 3 * * *\""
+    }
+}
+
+impl fmt::Display for InvalidCron {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, got {:?}", self.description(), self.value)
+    }
+}
+
+/// Parse a cron expression. The usual five fields of minute, hour, day of
+/// month, month and day of week are accepted, as well as the cron crate's
+/// form that starts with seconds.
+pub fn parse_cron(value: &str) -> Result<RunSchedule, InvalidCron> {
+    let value = value.trim();
+    let expression = if value.split_whitespace().count() == 5 {
+        format!("0 {}", value)
+    } else {
+        value.to_owned()
+    };
+    cron::Schedule::from_str(&expression)
+        .map(|schedule| RunSchedule::Cron(Box::new(schedule)))
+        .map_err(|_| InvalidCron { value: value.to_owned() })
+}
+
+impl From<RunTime> for RunSchedule {
+    fn from(every: RunTime) -> RunSchedule {
+        RunSchedule::Every(every.0)
+    }
+}
+
+impl RunSchedule {
+    /// Get when the next run should start, or `None` if it never should
+    ///
+    /// # Arguments
+    ///
+    /// * `last_start` - When the previous run started
+    pub fn next_run(&self, last_start: DateTime<Local>) -> Option<DateTime<Local>> {
+        match *self {
+            RunSchedule::Every(every) => chrono::Duration::from_std(every).ok().map(|every| last_start + every),
+            RunSchedule::Cron(ref schedule) => schedule.after(&last_start).next(),
+        }
+    }
+}
+
+/// Set when the user asks the process to stop, eg. with Ctrl-C, so that the
+/// run that's going can finish first
+#[derive(Clone)]
+pub struct StopSignal {
+    stopped: Arc<AtomicBool>,
+}
+
+impl StopSignal {
+    /// Catch Ctrl-C and SIGTERM. Sending either a second time stops straight
+    /// away in case a run is stuck.
+    pub fn install() -> Result<StopSignal, Box<Error>> {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let handler_stopped = stopped.clone();
+        ctrlc::set_handler(move || {
+            if handler_stopped.swap(true, Ordering::SeqCst) {
+                ::std::process::exit(130);
+            }
+            eprintln!("Stopping once the current run finishes. Press Ctrl-C again to stop now.");
+        })?;
+        Ok(StopSignal { stopped: stopped })
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Sleep until a time, waking early if asked to stop. Returns whether
+    /// the time was reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - When to wake up
+    pub fn sleep_until(&self, time: DateTime<Local>) -> bool {
+        while !self.is_stopped() {
+            let remaining = match (time - Local::now()).to_std() {
+                Ok(remaining) => remaining,
+                // The time has passed
+                Err(_) => return true,
+            };
+            thread::sleep(remaining.min(STOP_CHECK_INTERVAL));
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use self::chrono::TimeZone;
+
+    #[test]
+    fn cron_accepts_five_fields() {
+        // Given
+        let schedule = parse_cron("0 3 * * *").unwrap();
+        let last_start = Local.ymd(2018, 3, 1).and_hms(12, 0, 0);
+        // Then
+        assert_eq!(Some(Local.ymd(2018, 3, 2).and_hms(3, 0, 0)), schedule.next_run(last_start));
+        assert!(parse_cron("every day").is_err());
+    }
+
+    #[test]
+    fn every_runs_after_the_last_start() {
+        let schedule = RunSchedule::from("6h".parse::<RunTime>().unwrap());
+        let last_start = Local.ymd(2018, 3, 1).and_hms(12, 0, 0);
+        assert_eq!(Some(Local.ymd(2018, 3, 1).and_hms(18, 0, 0)), schedule.next_run(last_start));
+    }
+}
@@ -0,0 +1,210 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use playlist::AudioFeatures;
+use track_uri::TrackUri;
+
+/// The audio feature that tracks are split by
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Feature {
+    /// How intense a track feels, from 0 to 1
+    Energy,
+    /// Beats per minute
+    Tempo,
+}
+
+/// The error when a feature isn't known
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownFeature {}
+
+impl Error for UnknownFeature {
+    fn description(&self) -> &str {
+        "Expected energy or tempo"
+    }
+}
+
+impl fmt::Display for UnknownFeature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl FromStr for Feature {
+    type Err = UnknownFeature;
+
+    fn from_str(s: &str) -> Result<Feature, UnknownFeature> {
+        match s {
+            "energy" => Ok(Feature::Energy),
+            "tempo" => Ok(Feature::Tempo),
+            _ => Err(UnknownFeature {}),
+        }
+    }
+}
+
+impl Feature {
+    fn value(&self, features: &AudioFeatures) -> f64 {
+        match *self {
+            Feature::Energy => features.energy,
+            Feature::Tempo => features.tempo,
+        }
+    }
+
+    /// The segments to use when none are given. Energy is split the way a
+    /// set is played, and tempo into slow, mid-tempo and fast.
+    pub fn default_segments(&self) -> Vec<Segment> {
+        let segment = |name: &str, min: f64, max: f64| Segment { name: name.to_owned(), min: min, max: max };
+        match *self {
+            Feature::Energy => vec![
+                segment("warmup", 0.4, 0.7),
+                segment("peak", 0.7, 1.0),
+                segment("cooldown", 0.0, 0.4),
+            ],
+            Feature::Tempo => vec![
+                segment("slow", 0.0, 100.0),
+                segment("mid", 100.0, 125.0),
+                segment("fast", 125.0, 1000.0),
+            ],
+        }
+    }
+}
+
+/// A range of an audio feature that gets its own playlist
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub name: String,
+    /// The lowest value in the segment
+    pub min: f64,
+    /// The highest value in the segment
+    pub max: f64,
+}
+
+impl Segment {
+    fn contains(&self, value: f64) -> bool {
+        self.min <= value && value <= self.max
+    }
+}
+
+/// The error when a segment isn't written as `name:min-max`
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidSegment {}
+
+impl Error for InvalidSegment {
+    fn description(&self) -> &str {
+        "Expected name:min-max, eg. peak:0.7-1"
+    }
+}
+
+impl fmt::Display for InvalidSegment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl FromStr for Segment {
+    type Err = InvalidSegment;
+
+    fn from_str(s: &str) -> Result<Segment, InvalidSegment> {
+        let (name, range) = s.split_once(':').ok_or(InvalidSegment {})?;
+        let (min, max) = range.split_once('-').ok_or(InvalidSegment {})?;
+        let min: f64 = min.trim().parse().map_err(|_| InvalidSegment {})?;
+        let max: f64 = max.trim().parse().map_err(|_| InvalidSegment {})?;
+        if name.trim().is_empty() || min > max {
+            return Err(InvalidSegment {});
+        }
+        Ok(Segment { name: name.trim().to_owned(), min: min, max: max })
+    }
+}
+
+/// Get the name of a segment's playlist from a template. `{playlist}` is
+/// replaced with the playlist name and `{segment}` with the segment's name
+///
+/// # Arguments
+///
+/// * `template` - The template, eg. `{playlist} ({segment})`
+/// * `playlist_name` - The name the playlists are based on
+/// * `segment` - The segment the playlist is for
+pub fn segment_playlist_name(template: &str, playlist_name: &str, segment: &Segment) -> String {
+    template.replace("{playlist}", playlist_name).replace("{segment}", &segment.name)
+}
+
+/// Split tracks into segments by an audio feature. Each track goes in the
+/// first segment that its value is in. Returns the tracks for each segment
+/// in the same order as the segments, and the tracks that aren't in any
+/// segment or haven't been analysed.
+///
+/// # Arguments
+///
+/// * `tracks` - The tracks to split, which keep their order in each segment
+/// * `features` - The features of the tracks, see
+/// `PlaylistAPI::get_audio_features`
+/// * `feature` - The feature to split by
+/// * `segments` - The ranges of the feature
+pub fn segment_tracks(tracks: &[TrackUri],
+                      features: &[AudioFeatures],
+                      feature: Feature,
+                      segments: &[Segment]) -> (Vec<Vec<TrackUri>>, Vec<TrackUri>) {
+    let mut segmented = vec![Vec::new(); segments.len()];
+    let mut unsegmented = Vec::new();
+    for track in tracks {
+        let value = features.iter().find(|f| f.uri == *track).map(|f| feature.value(f));
+        match value.and_then(|value| segments.iter().position(|s| s.contains(value))) {
+            Some(index) => segmented[index].push(track.clone()),
+            None => unsegmented.push(track.clone()),
+        }
+    }
+    (segmented, unsegmented)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features(id: &str, tempo: f64, energy: f64) -> AudioFeatures {
+        AudioFeatures {
+            uri: TrackUri::Track(id.to_string()),
+            key: None,
+            major: true,
+            tempo: tempo,
+            energy: energy,
+        }
+    }
+
+    fn track(id: &str) -> TrackUri {
+        TrackUri::Track(id.to_string())
+    }
+
+    #[test]
+    fn segment_parses_name_and_range() {
+        assert_eq!(Ok(Segment { name: "peak".to_string(), min: 0.7, max: 1.0 }),
+                   "peak:0.7-1".parse());
+        assert_eq!(Err(InvalidSegment {}), "peak".parse::<Segment>());
+        assert_eq!(Err(InvalidSegment {}), "peak:1-0.7".parse::<Segment>());
+    }
+
+    #[test]
+    fn segment_tracks_splits_by_energy() {
+        // Given
+        let tracks = vec![track("a"), track("b"), track("c"), track("d"), track("e")];
+        let features = vec![
+            features("a", 120.0, 0.9),
+            features("b", 90.0, 0.2),
+            features("c", 128.0, 0.5),
+            features("d", 124.0, 0.8),
+        ];
+        // When
+        let (segmented, unsegmented) = segment_tracks(
+            &tracks, &features, Feature::Energy, &Feature::Energy.default_segments()
+        );
+        // Then
+        assert_eq!(vec![
+            vec![track("c")],
+            vec![track("a"), track("d")],
+            vec![track("b")],
+        ], segmented);
+        assert_eq!(vec![track("e")], unsegmented);
+        assert_eq!("Party (peak)", segment_playlist_name(
+            "{playlist} ({segment})", "Party", &Feature::Energy.default_segments()[1]
+        ));
+    }
+}
@@ -0,0 +1,786 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::str;
+use std::time::{Duration, Instant};
+
+use csv_to_playlist::{parse_csv,add_songs_to_playlist,PlaylistAddError,Song};
+use diff::format_track;
+use links::parse_track_id;
+use track_uri::TrackUri;
+use playlist::{get_playlist_id_create_if_needed,PlaylistAPI,PlaylistError};
+use rate_limit::RateLimiter;
+use slack::{verify_request,format_reply};
+use spotify::SpotifyAPI;
+
+use chrono::Utc;
+
+use rspotify::spotify::oauth2::SpotifyOAuth;
+use rspotify::spotify::util::generate_random_string;
+
+extern crate futures;
+use self::futures::{Future, Stream};
+
+extern crate hyper;
+use self::hyper::{Body, Headers, Method, StatusCode};
+use self::hyper::header::{ContentLength, ContentType, Cookie, Location, SetCookie};
+use self::hyper::server::{Http, Request, Response, Service};
+
+extern crate url;
+use self::url::form_urlencoded;
+
+extern crate multipart;
+use self::multipart::server::Multipart;
+
+extern crate openssl;
+use self::openssl::memcmp;
+
+/// The name of the cookie used to identify logged in users
+const SESSION_COOKIE: &str = "session";
+
+/// How long a login can take before its OAuth state is forgotten
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// The most logins that can be in progress at once, so that abandoned
+/// logins can't use up the server's memory
+const MAX_PENDING_LOGINS: usize = 1000;
+
+/// The page that lets people contribute songs without logging in
+const CONTRIBUTE_PAGE: &str = include_str!("contribute.html");
+
+/// The largest request body that's read, so that a client can't use up the
+/// server's memory before the request is checked
+const MAX_BODY_BYTES: usize = 10 * 1024 * 1024;
+
+/// Settings for running the server
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Spotify Client ID
+    pub client_id: String,
+    /// Spotify Client Secret
+    pub client_secret: String,
+    /// The URL that users reach the server on. `<base_url>/callback` must be
+    /// added as a redirect URI in the Spotify dashboard
+    pub base_url: String,
+    /// Where each user's token is stored
+    pub token_dir: PathBuf,
+    /// Settings for the public upload form, which is disabled when this is
+    /// not set
+    pub contribute: Option<ContributeConfig>,
+    /// Settings for the Slack slash command, which is disabled when this is
+    /// not set
+    pub slack: Option<SlackConfig>,
+}
+
+/// Settings for the public upload form at `/contribute`
+#[derive(Debug, Clone)]
+pub struct ContributeConfig {
+    /// The secret that must be entered to upload songs
+    pub secret: String,
+    /// The user whose playlists the songs are added to. They must have
+    /// logged in through `/login` first
+    pub user_id: String,
+    /// How many uploads each IP address can make per hour
+    pub uploads_per_hour: usize,
+}
+
+/// Settings for the Slack slash command at `/slack`
+#[derive(Debug, Clone)]
+pub struct SlackConfig {
+    /// The Slack app's signing secret, used to check requests came from Slack
+    pub signing_secret: String,
+    /// The user who owns the playlist. They must have logged in through
+    /// `/login` first
+    pub user_id: String,
+    /// The playlist that tracks are added to
+    pub playlist_name: String,
+}
+
+/// State that's shared between requests
+struct ServerState {
+    config: ServerConfig,
+    /// The OAuth states that have been handed out but not used yet, along
+    /// with when they were handed out
+    pending_logins: RefCell<HashMap<String, Instant>>,
+    /// Session IDs mapped to the user they belong to
+    sessions: RefCell<HashMap<String, String>>,
+    /// Limits how often each IP address can use the upload form
+    rate_limiter: RefCell<RateLimiter<IpAddr>>,
+}
+
+/// The details of a request that are needed to handle it
+struct RequestInfo {
+    method: Method,
+    path: String,
+    query: HashMap<String, String>,
+    session: Option<String>,
+    /// The multipart boundary, when the body is a multipart form
+    boundary: Option<String>,
+    remote_addr: Option<SocketAddr>,
+    headers: Headers,
+    body: Vec<u8>,
+}
+
+/// Parse a query string into a map, decoding any escaped characters
+///
+/// # Arguments
+///
+/// * `query` - The part of the URL after the `?`
+fn parse_query(query: &str) -> HashMap<String, String> {
+    form_urlencoded::parse(query.as_bytes()).into_owned().collect()
+}
+
+/// Make a user ID safe to use as a filename. Anything other than ASCII
+/// letters, digits, `-` and `_` is percent-encoded, so that different IDs
+/// never share a token file
+fn token_filename(user_id: &str) -> String {
+    let safe: String = user_id
+        .bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!("{}.json", safe)
+}
+
+/// Parse a multipart form into a map of field names to their contents
+///
+/// # Arguments
+///
+/// * `body` - The request body
+/// * `boundary` - The boundary from the request's content type
+fn parse_form(body: &[u8], boundary: &str) -> io::Result<HashMap<String, Vec<u8>>> {
+    let mut fields = HashMap::new();
+    let mut form = Multipart::with_body(body, boundary);
+    let mut result = Ok(());
+    form.foreach_entry(|mut field| {
+        let mut data = Vec::new();
+        match field.data.read_to_end(&mut data) {
+            Ok(_) => {
+                fields.insert(field.headers.name.to_string(), data);
+            },
+            Err(e) => result = Err(e),
+        }
+    })?;
+    result.map(|_| fields)
+}
+
+/// Compare secrets in constant time so that they can't be guessed based on
+/// how long the comparison takes
+fn secret_matches(expected: &str, given: &[u8]) -> bool {
+    expected.len() == given.len() && memcmp::eq(expected.as_bytes(), given)
+}
+
+/// Why a request body couldn't be read, see `read_body`
+enum BodyError {
+    TooLarge,
+    Hyper(hyper::Error),
+}
+
+/// Read a request body, giving up as soon as it's bigger than `limit`
+/// bytes. Returns `None` when it's too large
+///
+/// # Arguments
+///
+/// * `body` - The body of the request
+/// * `limit` - The most bytes to read
+fn read_body(body: Body, limit: usize) -> Box<Future<Item = Option<Vec<u8>>, Error = hyper::Error>> {
+    Box::new(body
+        .map_err(BodyError::Hyper)
+        .fold(Vec::new(), move |mut body, chunk| {
+            if body.len() + chunk.len() > limit {
+                return Err(BodyError::TooLarge);
+            }
+            body.extend_from_slice(&chunk);
+            Ok(body)
+        })
+        .then(|result| match result {
+            Ok(body) => Ok(Some(body)),
+            Err(BodyError::TooLarge) => Ok(None),
+            Err(BodyError::Hyper(e)) => Err(e),
+        }))
+}
+
+/// The `Set-Cookie` value that starts a session. The cookie is only sent
+/// over https when the server is reached through https
+///
+/// # Arguments
+///
+/// * `session` - The session ID
+/// * `base_url` - The URL that users reach the server on
+fn session_cookie(session: &str, base_url: &str) -> String {
+    let secure = if base_url.starts_with("https://") { "; Secure" } else { "" };
+    format!("{}={}; HttpOnly; SameSite=Lax; Path=/{}", SESSION_COOKIE, session, secure)
+}
+
+/// Create a plain text response
+fn text_response(status: StatusCode, body: &str) -> Response {
+    Response::new()
+        .with_status(status)
+        .with_body(body.to_owned())
+}
+
+impl ServerState {
+    fn new(config: ServerConfig) -> ServerState {
+        let uploads_per_hour = config.contribute.as_ref()
+            .map(|c| c.uploads_per_hour)
+            .unwrap_or(0);
+        ServerState {
+            config: config,
+            pending_logins: RefCell::new(HashMap::new()),
+            sessions: RefCell::new(HashMap::new()),
+            rate_limiter: RefCell::new(
+                RateLimiter::new(uploads_per_hour, Duration::from_secs(60 * 60))
+            ),
+        }
+    }
+
+    /// Create the OAuth settings used for each user, storing the token at
+    /// the given path
+    fn oauth(&self, cache_path: PathBuf) -> SpotifyOAuth {
+        SpotifyOAuth::default()
+            .scope("playlist-read-private playlist-modify-private playlist-modify-public ugc-image-upload")
+            .client_id(&self.config.client_id)
+            .client_secret(&self.config.client_secret)
+            .redirect_uri(&format!("{}/callback", self.config.base_url))
+            .cache_path(cache_path)
+            .build()
+    }
+
+    fn handle(&self, request: RequestInfo) -> Response {
+        match (request.method.clone(), request.path.as_str()) {
+            (Method::Get, "/login") => self.login(),
+            (Method::Get, "/callback") => self.callback(&request.query),
+            (Method::Post, "/upload") => {
+                match request.session.and_then(|s| self.user_for_session(&s)) {
+                    Some(user_id) => self.upload(&user_id, &request.query, &request.body),
+                    None => text_response(
+                        StatusCode::Unauthorized, "Log in at /login first"
+                    ),
+                }
+            },
+            (Method::Get, "/contribute") if self.config.contribute.is_some() => {
+                Response::new()
+                    .with_header(ContentType::html())
+                    .with_body(CONTRIBUTE_PAGE)
+            },
+            (Method::Post, "/contribute") => match self.config.contribute {
+                Some(ref contribute) => self.contribute(contribute, &request),
+                None => text_response(StatusCode::NotFound, "Not found"),
+            },
+            (Method::Post, "/slack") => match self.config.slack {
+                Some(ref slack) => self.slack(slack, &request),
+                None => text_response(StatusCode::NotFound, "Not found"),
+            },
+            _ => text_response(StatusCode::NotFound, "Not found"),
+        }
+    }
+
+    /// Redirect the user to Spotify to log in
+    fn login(&self) -> Response {
+        let now = Instant::now();
+        let mut pending_logins = self.pending_logins.borrow_mut();
+        pending_logins.retain(|_, started| now.duration_since(*started) < LOGIN_TIMEOUT);
+        if pending_logins.len() >= MAX_PENDING_LOGINS {
+            return text_response(StatusCode::ServiceUnavailable, "Too many logins in progress, try again later");
+        }
+        let state = generate_random_string(32);
+        let oauth = self.oauth(PathBuf::new());
+        let url = oauth.get_authorize_url(Some(&state), None);
+        pending_logins.insert(state, now);
+        Response::new()
+            .with_status(StatusCode::Found)
+            .with_header(Location::new(url))
+    }
+
+    /// Handle the redirect back from Spotify by storing the user's token and
+    /// starting a session
+    fn callback(&self, query: &HashMap<String, String>) -> Response {
+        let (code, state) = match (query.get("code"), query.get("state")) {
+            (Some(code), Some(state)) => (code, state),
+            _ => return text_response(StatusCode::BadRequest, "Login failed"),
+        };
+        // Make sure this is a login that we started recently
+        match self.pending_logins.borrow_mut().remove(state) {
+            Some(started) if started.elapsed() < LOGIN_TIMEOUT => {},
+            _ => return text_response(StatusCode::BadRequest, "Unknown login attempt"),
+        }
+        // We don't know who the user is until we have a token so store it
+        // under the state first
+        let pending_path = self.config.token_dir.join(token_filename(state));
+        let token_info = match self.oauth(pending_path.clone()).get_access_token(code) {
+            Some(token_info) => token_info,
+            None => return text_response(StatusCode::BadGateway, "Could not get token"),
+        };
+        let user_id = match SpotifyAPI::for_token_owner(token_info) {
+            Ok(spotify) => spotify.username().to_owned(),
+            Err(e) => {
+                let _ = fs::remove_file(&pending_path);
+                return text_response(StatusCode::BadGateway, &e.to_string());
+            },
+        };
+        let user_path = self.config.token_dir.join(token_filename(&user_id));
+        if let Err(e) = fs::rename(&pending_path, &user_path) {
+            return text_response(StatusCode::InternalServerError, &e.to_string());
+        }
+        let session = generate_random_string(32);
+        self.sessions.borrow_mut().insert(session.to_owned(), user_id.to_owned());
+        let cookie = session_cookie(&session, &self.config.base_url);
+        text_response(
+            StatusCode::Ok,
+            &format!("Logged in as {}. POST a CSV to /upload?playlist=<name>", user_id)
+        ).with_header(SetCookie(vec![cookie]))
+    }
+
+    fn user_for_session(&self, session: &str) -> Option<String> {
+        self.sessions.borrow().get(session).cloned()
+    }
+
+    /// Add the songs in the uploaded CSV to the user's playlist
+    fn upload(&self, user_id: &str, query: &HashMap<String, String>, body: &[u8]) -> Response {
+        match query.get("playlist") {
+            Some(name) => self.import(user_id, name, body),
+            None => text_response(StatusCode::BadRequest, "Missing playlist"),
+        }
+    }
+
+    /// Add the songs from the upload form to the shared user's playlist
+    fn contribute(&self, config: &ContributeConfig, request: &RequestInfo) -> Response {
+        // Every attempt counts towards the limit so that the secret can't be
+        // guessed quickly
+        let allowed = match request.remote_addr {
+            Some(address) => self.rate_limiter.borrow_mut().check(address.ip(), Instant::now()),
+            None => false,
+        };
+        if !allowed {
+            return text_response(StatusCode::TooManyRequests, "Too many uploads, try again later");
+        }
+        let fields = match request.boundary {
+            Some(ref boundary) => parse_form(&request.body, boundary),
+            None => return text_response(StatusCode::BadRequest, "Expected a form upload"),
+        };
+        let fields = match fields {
+            Ok(fields) => fields,
+            Err(e) => return text_response(StatusCode::BadRequest, &e.to_string()),
+        };
+        let secret = fields.get("secret").map(|s| s.as_slice()).unwrap_or(&[]);
+        if !secret_matches(&config.secret, secret) {
+            return text_response(StatusCode::Forbidden, "Incorrect secret");
+        }
+        let playlist_name = fields.get("playlist")
+            .and_then(|p| String::from_utf8(p.to_vec()).ok())
+            .map(|p| p.trim().to_owned())
+            .unwrap_or_default();
+        if playlist_name.is_empty() {
+            return text_response(StatusCode::BadRequest, "Missing playlist");
+        }
+        match fields.get("csv") {
+            Some(csv) => self.import(&config.user_id, &playlist_name, csv),
+            None => text_response(StatusCode::BadRequest, "Missing CSV"),
+        }
+    }
+
+    /// Add the track linked in a Slack slash command to the configured
+    /// playlist
+    fn slack(&self, config: &SlackConfig, request: &RequestInfo) -> Response {
+        let header = |name| {
+            request.headers.get_raw(name)
+                .and_then(|h| h.one())
+                .and_then(|h| str::from_utf8(h).ok())
+                .unwrap_or("")
+        };
+        if let Err(e) = verify_request(
+            &config.signing_secret,
+            header("X-Slack-Request-Timestamp"),
+            header("X-Slack-Signature"),
+            &request.body,
+            Utc::now().timestamp()
+        ) {
+            return text_response(StatusCode::Unauthorized, &e.to_string());
+        }
+        let form = parse_query(&String::from_utf8_lossy(&request.body));
+        let reply = match form.get("text").and_then(|t| parse_track_id(t)) {
+            Some(track_id) => self.add_track(config, &track_id),
+            None => "Usage: /addsong <Spotify track link>".to_string(),
+        };
+        Response::new()
+            .with_header(ContentType::json())
+            .with_body(format_reply(&reply))
+    }
+
+    /// Add a single track to the Slack playlist, returning a message
+    /// describing what happened
+    fn add_track(&self, config: &SlackConfig, track_id: &str) -> String {
+        let spotify = match self.spotify_for_user(&config.user_id) {
+            Some(spotify) => spotify,
+            None => return format!("{} needs to log in again", config.user_id),
+        };
+        let track = match spotify.get_tracks_metadata(&[TrackUri::Track(track_id.to_owned())]) {
+            Ok(ref tracks) if !tracks.is_empty() => tracks[0].clone(),
+            _ => return "Could not find that track".to_string(),
+        };
+        let playlist_id = match get_playlist_id_create_if_needed(
+            &spotify, &config.playlist_name
+        ) {
+            Ok(playlist_id) => playlist_id,
+            Err(PlaylistError::NotAuthorizedToModify(e)) => return e.to_string(),
+            Err(e) => return format!("Could not find playlist: {:?}", e),
+        };
+        let song = Song { music: track.name.to_owned(), song_id: track.uri.to_string() };
+        match add_songs_to_playlist(&spotify, &playlist_id, vec![song]) {
+            Ok(_) => format!("Added {} to {}", format_track(&track), config.playlist_name),
+            Err(PlaylistAddError::NoNewTracks(_)) => {
+                format!("{} is already in {}", format_track(&track), config.playlist_name)
+            },
+            Err(PlaylistAddError::APIError(e)) => format!("Could not add track: {}", e),
+            Err(PlaylistAddError::PlaylistFull(e)) => format!("Could not add track: {}", e),
+            Err(PlaylistAddError::ReadError(e)) => format!("Could not add track: {}", e),
+        }
+    }
+
+    /// Log in as a user with their stored token. The token is refreshed if
+    /// it has expired
+    fn spotify_for_user(&self, user_id: &str) -> Option<SpotifyAPI> {
+        let token_path = self.config.token_dir.join(token_filename(user_id));
+        self.oauth(token_path)
+            .get_cached_token()
+            .map(|token_info| SpotifyAPI::with_token(user_id, token_info))
+    }
+
+    /// Add the songs in the CSV to one of the user's playlists
+    fn import(&self, user_id: &str, playlist_name: &str, csv: &[u8]) -> Response {
+        let songs = match parse_csv(csv) {
+            Ok(songs) => songs,
+            Err(e) => return text_response(StatusCode::BadRequest, &e.to_string()),
+        };
+        let spotify = match self.spotify_for_user(user_id) {
+            Some(spotify) => spotify,
+            None => return text_response(
+                StatusCode::Unauthorized, "Session expired, log in at /login"
+            ),
+        };
+        let playlist_id = match get_playlist_id_create_if_needed(&spotify, playlist_name) {
+            Ok(playlist_id) => playlist_id,
+            Err(PlaylistError::NotAuthorizedToModify(e)) => {
+                return text_response(StatusCode::Forbidden, &e.to_string());
+            },
+            Err(e) => return text_response(StatusCode::BadGateway, &format!("{:?}", e)),
+        };
+        match add_songs_to_playlist(&spotify, &playlist_id, songs) {
+            Ok(_) => text_response(StatusCode::Ok, "Successfully added songs!"),
+            Err(PlaylistAddError::NoNewTracks(_)) => {
+                text_response(StatusCode::Ok, "No new tracks to add.")
+            },
+            Err(PlaylistAddError::APIError(e)) => {
+                text_response(StatusCode::BadGateway, &e.to_string())
+            },
+            Err(PlaylistAddError::PlaylistFull(e)) => {
+                text_response(StatusCode::Conflict, &e.to_string())
+            },
+            Err(PlaylistAddError::ReadError(e)) => text_response(StatusCode::BadRequest, &e),
+        }
+    }
+}
+
+/// The hyper service that passes requests on to the shared state
+struct PlaylistService {
+    state: Rc<ServerState>,
+}
+
+impl Service for PlaylistService {
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = Box<Future<Item = Response, Error = hyper::Error>>;
+
+    fn call(&self, request: Request) -> Self::Future {
+        let state = self.state.clone();
+        let method = request.method().clone();
+        let path = request.path().to_owned();
+        let query = parse_query(request.query().unwrap_or(""));
+        let session = request.headers()
+            .get::<Cookie>()
+            .and_then(|c| c.get(SESSION_COOKIE))
+            .map(|s| s.to_owned());
+        let boundary = request.headers()
+            .get::<ContentType>()
+            .and_then(|c| c.get_param("boundary").map(|b| b.as_str().to_owned()));
+        // hyper 0.11 has no other way to get the client's address when using
+        // `Http::bind`
+        #[allow(deprecated)]
+        let remote_addr = request.remote_addr();
+        let headers = request.headers().clone();
+        let too_large = || text_response(StatusCode::PayloadTooLarge, "The request is too large");
+        if headers.get::<ContentLength>().is_some_and(|&ContentLength(length)| length > MAX_BODY_BYTES as u64) {
+            return Box::new(futures::future::ok(too_large()));
+        }
+        // Wait for the whole body before handling the request
+        Box::new(read_body(request.body(), MAX_BODY_BYTES).map(move |body| match body {
+            Some(body) => state.handle(RequestInfo {
+                method: method,
+                path: path,
+                query: query,
+                session: session,
+                boundary: boundary,
+                remote_addr: remote_addr,
+                headers: headers,
+                body: body,
+            }),
+            None => too_large(),
+        }))
+    }
+}
+
+/// Run a server that lets multiple users log in with Spotify and upload
+/// CSVs into their own playlists. Requests are handled one at a time.
+///
+/// # Arguments
+///
+/// * `address` - The address to listen on
+/// * `config` - Settings for the server
+pub fn run_server(address: &SocketAddr, config: ServerConfig) -> Result<(), hyper::Error> {
+    fs::create_dir_all(&config.token_dir)?;
+    let state = Rc::new(ServerState::new(config));
+    let server = Http::new().bind(address, move || {
+        Ok(PlaylistService { state: state.clone() })
+    })?;
+    println!("Listening on http://{}", server.local_addr()?);
+    server.run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_setup(contribute: Option<ContributeConfig>) -> ServerState {
+        ServerState::new(ServerConfig {
+            client_id: "id".to_string(),
+            client_secret: "secret".to_string(),
+            base_url: "http://localhost:8888".to_string(),
+            token_dir: PathBuf::from("tokens"),
+            contribute: contribute,
+            slack: None,
+        })
+    }
+
+    fn contribute_request(body: &str) -> RequestInfo {
+        RequestInfo {
+            method: Method::Post,
+            path: "/contribute".to_string(),
+            query: HashMap::new(),
+            session: None,
+            boundary: Some("XYZ".to_string()),
+            remote_addr: Some("10.0.0.1:5000".parse().unwrap()),
+            headers: Headers::new(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn contribute_config() -> Option<ContributeConfig> {
+        Some(ContributeConfig {
+            secret: "letmein".to_string(),
+            user_id: "host".to_string(),
+            uploads_per_hour: 1,
+        })
+    }
+
+    #[test]
+    fn parse_query_decodes_values() {
+        // When
+        let result = parse_query("playlist=Road%20Trip&other=a+b");
+        // Then
+        assert_eq!(Some(&"Road Trip".to_string()), result.get("playlist"));
+        assert_eq!(Some(&"a b".to_string()), result.get("other"));
+    }
+
+    #[test]
+    fn bodies_over_the_limit_are_not_read() {
+        // When
+        let small = read_body(Body::from("music (S),song_id (S)"), 100).wait().unwrap();
+        let large = read_body(Body::from("music (S),song_id (S)"), 10).wait().unwrap();
+        // Then
+        assert_eq!(Some(b"music (S),song_id (S)".to_vec()), small);
+        assert_eq!(None, large);
+    }
+
+    #[test]
+    fn token_filename_escapes_path_characters() {
+        // When
+        let result = token_filename("../../etc/passwd");
+        // Then
+        assert_eq!("%2E%2E%2F%2E%2E%2Fetc%2Fpasswd.json", result);
+    }
+
+    #[test]
+    fn token_filename_keeps_user_ids_apart() {
+        // When
+        let dotted = token_filename("a.b");
+        let plain = token_filename("ab");
+        let escaped = token_filename("a%2Eb");
+        // Then
+        assert_ne!(dotted, plain);
+        assert_ne!(dotted, escaped);
+        assert_eq!("a%2Eb.json", dotted);
+    }
+
+    #[test]
+    fn upload_requires_session() {
+        // Given
+        let state = test_setup(None);
+        let request = RequestInfo {
+            method: Method::Post,
+            path: "/upload".to_string(),
+            query: parse_query("playlist=test"),
+            session: Some("unknown".to_string()),
+            boundary: None,
+            remote_addr: None,
+            headers: Headers::new(),
+            body: Vec::new(),
+        };
+        // When
+        let response = state.handle(request);
+        // Then
+        assert_eq!(StatusCode::Unauthorized, response.status());
+    }
+
+    #[test]
+    fn callback_rejects_unknown_state() {
+        // Given
+        let state = test_setup(None);
+        // When
+        let response = state.callback(&parse_query("code=abc&state=forged"));
+        // Then
+        assert_eq!(StatusCode::BadRequest, response.status());
+    }
+
+    #[test]
+    fn stale_logins_are_forgotten() {
+        // Given
+        let state = test_setup(None);
+        let started = Instant::now() - LOGIN_TIMEOUT;
+        state.pending_logins.borrow_mut().insert("stale".to_string(), started);
+        state.pending_logins.borrow_mut().insert("expired".to_string(), started);
+        // When
+        let callback = state.callback(&parse_query("code=abc&state=expired"));
+        let login = state.login();
+        // Then
+        assert_eq!(StatusCode::BadRequest, callback.status());
+        assert_eq!(StatusCode::Found, login.status());
+        let pending_logins = state.pending_logins.borrow();
+        assert_eq!(1, pending_logins.len());
+        assert!(!pending_logins.contains_key("stale"));
+    }
+
+    #[test]
+    fn logins_are_limited() {
+        // Given
+        let state = test_setup(None);
+        for i in 0..MAX_PENDING_LOGINS {
+            state.pending_logins.borrow_mut().insert(i.to_string(), Instant::now());
+        }
+        // When
+        let response = state.login();
+        // Then
+        assert_eq!(StatusCode::ServiceUnavailable, response.status());
+    }
+
+    #[test]
+    fn session_cookie_is_secure_over_https() {
+        // When
+        let http = session_cookie("abc", "http://localhost:8888");
+        let https = session_cookie("abc", "https://playlists.example.com");
+        // Then
+        assert_eq!("session=abc; HttpOnly; SameSite=Lax; Path=/", http);
+        assert_eq!("session=abc; HttpOnly; SameSite=Lax; Path=/; Secure", https);
+    }
+
+    #[test]
+    fn parse_form_reads_fields() {
+        // Given
+        let body = "--XYZ\r\n\
+                    Content-Disposition: form-data; name=\"playlist\"\r\n\r\n\
+                    Road Trip\r\n\
+                    --XYZ\r\n\
+                    Content-Disposition: form-data; name=\"csv\"; filename=\"songs.csv\"\r\n\
+                    Content-Type: text/csv\r\n\r\n\
+                    Artist,Title\r\n\
+                    --XYZ--\r\n";
+        // When
+        let result = parse_form(body.as_bytes(), "XYZ").unwrap();
+        // Then
+        assert_eq!(Some(&b"Road Trip".to_vec()), result.get("playlist"));
+        assert_eq!(Some(&b"Artist,Title".to_vec()), result.get("csv"));
+    }
+
+    #[test]
+    fn secret_matches_only_exact_secret() {
+        assert!(secret_matches("letmein", b"letmein"));
+        assert!(!secret_matches("letmein", b"letmeout"));
+        assert!(!secret_matches("letmein", b"letme"));
+    }
+
+    #[test]
+    fn contribute_form_disabled_without_config() {
+        // Given
+        let state = test_setup(None);
+        // When
+        let response = state.handle(contribute_request(""));
+        // Then
+        assert_eq!(StatusCode::NotFound, response.status());
+    }
+
+    #[test]
+    fn contribute_rejects_wrong_secret() {
+        // Given
+        let state = test_setup(contribute_config());
+        let body = "--XYZ\r\n\
+                    Content-Disposition: form-data; name=\"secret\"\r\n\r\n\
+                    guess\r\n\
+                    --XYZ--\r\n";
+        // When
+        let response = state.handle(contribute_request(body));
+        // Then
+        assert_eq!(StatusCode::Forbidden, response.status());
+    }
+
+    #[test]
+    fn contribute_is_rate_limited() {
+        // Given
+        let state = test_setup(contribute_config());
+        state.handle(contribute_request(""));
+        // When
+        let response = state.handle(contribute_request(""));
+        // Then
+        assert_eq!(StatusCode::TooManyRequests, response.status());
+    }
+
+    #[test]
+    fn slack_rejects_unsigned_request() {
+        // Given
+        let mut state = test_setup(None);
+        state.config.slack = Some(SlackConfig {
+            signing_secret: "secret".to_string(),
+            user_id: "host".to_string(),
+            playlist_name: "Requests".to_string(),
+        });
+        let request = RequestInfo {
+            method: Method::Post,
+            path: "/slack".to_string(),
+            query: HashMap::new(),
+            session: None,
+            boundary: None,
+            remote_addr: None,
+            headers: Headers::new(),
+            body: b"text=spotify%3Atrack%3Aabc".to_vec(),
+        };
+        // When
+        let response = state.handle(request);
+        // Then
+        assert_eq!(StatusCode::Unauthorized, response.status());
+    }
+}
@@ -0,0 +1,285 @@
+use std::error::Error;
+
+use name_match::{has_artist,same_name,search_value};
+use playlist::{PlaylistAPI,TrackMetadata};
+use track_uri::TrackUri;
+use user_agent;
+
+extern crate reqwest;
+use self::reqwest::header::{Accept, Headers};
+
+const API_URL: &str = "https://api.setlist.fm/rest/1.0/setlist";
+
+/// Live recordings and studio versions share a name, so more results are
+/// looked at than when matching a single track
+const SEARCH_LIMIT: u32 = 20;
+
+#[derive(Deserialize)]
+struct SetlistResponse {
+    artist: Artist,
+    #[serde(rename = "eventDate")]
+    event_date: String,
+    venue: Venue,
+    sets: Sets,
+}
+
+#[derive(Deserialize)]
+struct Artist {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Venue {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Sets {
+    set: Vec<Set>,
+}
+
+#[derive(Deserialize)]
+struct Set {
+    song: Vec<SetSong>,
+}
+
+#[derive(Deserialize)]
+struct SetSong {
+    name: String,
+    /// The original artist when the song is a cover
+    cover: Option<Artist>,
+    /// Whether the song was played from a recording instead of performed
+    #[serde(default)]
+    tape: bool,
+}
+
+/// A song that was performed at a concert
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetlistSong {
+    pub title: String,
+    /// The artist of the studio version, which is the original artist for
+    /// covers
+    pub artist: String,
+}
+
+/// The songs performed at a concert, in the order they were played,
+/// including encores
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setlist {
+    pub artist: String,
+    /// The date of the concert, eg. 23-08-2017
+    pub event_date: String,
+    pub venue: String,
+    pub songs: Vec<SetlistSong>,
+}
+
+impl From<SetlistResponse> for Setlist {
+    fn from(response: SetlistResponse) -> Setlist {
+        let artist = response.artist.name;
+        let songs = response.sets.set.into_iter()
+            .flat_map(|set| set.song)
+            .filter(|song| !song.tape && !song.name.trim().is_empty())
+            .map(|song| SetlistSong {
+                title: song.name,
+                artist: song.cover.map(|cover| cover.name).unwrap_or_else(|| artist.clone()),
+            })
+            .collect();
+        Setlist {
+            artist: artist,
+            event_date: response.event_date,
+            venue: response.venue.name,
+            songs: songs,
+        }
+    }
+}
+
+/// Get the setlist ID from a setlist.fm link. The ID is the end of the
+/// page name, eg. `63de4613` in
+/// `https://www.setlist.fm/setlist/radiohead/2017/x-y-63de4613.html`
+///
+/// # Arguments
+///
+/// * `url` - The link to the setlist
+pub fn setlist_id_from_url(url: &str) -> Option<String> {
+    let page = url.split(['?', '#']).next()?.trim_end_matches('/').rsplit('/').next()?;
+    let id = page.strip_suffix(".html")?.rsplit('-').next()?;
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(id.to_owned())
+}
+
+/// Get a setlist from setlist.fm
+///
+/// # Arguments
+///
+/// * `api_key` - A setlist.fm API key, see https://www.setlist.fm/settings/api
+/// * `setlist_id` - The setlist ID, see `setlist_id_from_url`
+pub fn get_setlist(api_key: &str, setlist_id: &str) -> Result<Setlist, Box<Error>> {
+    let mut headers = Headers::new();
+    headers.set(Accept::json());
+    headers.set_raw("x-api-key", api_key.to_owned());
+    let response: SetlistResponse = user_agent::client()
+        .get(&format!("{}/{}", API_URL, setlist_id))
+        .headers(headers)
+        .send()?
+        .error_for_status()?
+        .json()?;
+    Ok(Setlist::from(response))
+}
+
+/// Whether a track looks like a live recording from its name or album
+fn is_live(track: &TrackMetadata) -> bool {
+    let mentions_live = |name: &str| {
+        name.to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| word == "live")
+    };
+    mentions_live(&track.name) || mentions_live(&track.album)
+}
+
+/// Pick the studio version of a song from search results. Live recordings
+/// are only used when there's nothing else, since the playlist is for
+/// listening to the songs from the concert.
+///
+/// # Arguments
+///
+/// * `results` - The search results, best match first
+/// * `song` - The song that was performed
+pub fn pick_studio_version(results: Vec<TrackMetadata>, song: &SetlistSong) -> Option<TrackMetadata> {
+    let (live, studio): (Vec<TrackMetadata>, Vec<TrackMetadata>) = results.into_iter()
+        .filter(|track| has_artist(track, &song.artist))
+        .partition(is_live);
+    studio.into_iter()
+        .find(|track| same_name(&track.name, &song.title))
+        .or_else(|| live.into_iter().find(|track| same_name(without_version(&track.name), &song.title)))
+}
+
+/// Remove the version from a track name, eg. `Creep - Live at the BBC`
+/// becomes `Creep`
+fn without_version(name: &str) -> &str {
+    name.split(" - ").next().unwrap_or(name)
+}
+
+/// The tracks for a setlist and the songs that couldn't be found
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetlistMatches {
+    /// The matching tracks in setlist order
+    pub tracks: Vec<TrackMetadata>,
+    pub unmatched: Vec<SetlistSong>,
+}
+
+impl SetlistMatches {
+    /// Get the tracks in setlist order. Songs that were played more than
+    /// once, eg. as an encore, are only kept where they were first played
+    pub fn track_ids(&self) -> Vec<TrackUri> {
+        let mut ids: Vec<TrackUri> = Vec::new();
+        for track in &self.tracks {
+            if !ids.contains(&track.uri) {
+                ids.push(track.uri.clone());
+            }
+        }
+        ids
+    }
+}
+
+/// Find each song of a setlist on Spotify
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance to search with
+/// * `setlist` - The setlist to match
+pub fn match_setlist<E>(playlist_api: &PlaylistAPI<E>,
+                        setlist: &Setlist) -> Result<SetlistMatches, E> {
+    let mut matches = SetlistMatches { tracks: Vec::new(), unmatched: Vec::new() };
+    for song in &setlist.songs {
+        let query = format!("track:\"{}\" artist:\"{}\"", search_value(&song.title), search_value(&song.artist));
+        let results = playlist_api.search_tracks(&query, SEARCH_LIMIT)?;
+        match pick_studio_version(results, song) {
+            Some(track) => matches.tracks.push(track),
+            None => matches.unmatched.push(song.clone()),
+        }
+    }
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate serde_json;
+
+    fn track(id: &str, name: &str, album: &str) -> TrackMetadata {
+        TrackMetadata {
+            uri: TrackUri::Track(id.to_string()),
+            name: name.to_string(),
+            artists: vec!["Radiohead".to_string()],
+            album: album.to_string(),
+            duration_ms: 0,
+            image_url: None,
+        }
+    }
+
+    fn song(title: &str) -> SetlistSong {
+        SetlistSong { title: title.to_string(), artist: "Radiohead".to_string() }
+    }
+
+    #[test]
+    fn setlist_id_from_url_reads_page_name() {
+        assert_eq!(
+            Some("63de4613".to_string()),
+            setlist_id_from_url("https://www.setlist.fm/setlist/radiohead/2017/x-y-63de4613.html?x=1")
+        );
+        assert_eq!(None, setlist_id_from_url("https://www.setlist.fm/search?query=radiohead"));
+    }
+
+    #[test]
+    fn pick_studio_version_skips_live_recordings() {
+        // Given
+        let results = vec![
+            track("1", "Creep - Live", "Live at the BBC"),
+            track("2", "Creep", "Pablo Honey (Live)"),
+            track("3", "Creep", "Pablo Honey"),
+        ];
+        // Then
+        assert_eq!(Some(TrackUri::Track("3".to_string())),
+                   pick_studio_version(results.clone(), &song("Creep")).map(|t| t.uri));
+        // Live versions are better than nothing
+        assert_eq!(Some(TrackUri::Track("1".to_string())),
+                   pick_studio_version(results[..2].to_vec(), &song("Creep")).map(|t| t.uri));
+        assert_eq!(None, pick_studio_version(results, &song("Nude")));
+    }
+
+    #[test]
+    fn track_ids_keeps_first_play_of_each_song() {
+        // Given
+        let matches = SetlistMatches {
+            tracks: vec![track("2", "Nude", ""), track("1", "Creep", ""), track("2", "Nude", "")],
+            unmatched: Vec::new(),
+        };
+        // Then
+        assert_eq!(vec![TrackUri::Track("2".to_string()), TrackUri::Track("1".to_string())],
+                   matches.track_ids());
+    }
+
+    #[test]
+    fn setlist_keeps_order_and_credits_covers() {
+        // Given
+        let response: SetlistResponse = serde_json::from_str(r#"{
+            "artist": {"name": "Radiohead"}, "eventDate": "23-08-2017",
+            "venue": {"name": "Arena"},
+            "sets": {"set": [
+                {"song": [{"name": "Intro", "tape": true}, {"name": "Airbag"}, {"name": "Nude"}]},
+                {"encore": 1, "song": [{"name": "Ceremony", "cover": {"name": "Joy Division"}}]}
+            ]}
+        }"#).unwrap();
+        // When
+        let setlist = Setlist::from(response);
+        // Then
+        assert_eq!(vec![
+            song("Airbag"),
+            song("Nude"),
+            SetlistSong { title: "Ceremony".to_string(), artist: "Joy Division".to_string() },
+        ], setlist.songs);
+    }
+}
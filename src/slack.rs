@@ -0,0 +1,165 @@
+use std::error::Error;
+use std::fmt;
+
+use signature::InvalidSignature;
+
+extern crate openssl;
+use self::openssl::hash::MessageDigest;
+use self::openssl::memcmp;
+use self::openssl::pkey::PKey;
+use self::openssl::sign::Signer;
+
+extern crate serde_json;
+
+/// How old a request can be before it's rejected, to prevent replays
+const MAX_REQUEST_AGE_SECS: i64 = 5 * 60;
+
+/// An error when a request's timestamp is too far from the current time
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StaleRequest {}
+
+impl Error for StaleRequest {
+    fn description(&self) -> &str {
+        "Request timestamp is too old"
+    }
+}
+
+impl fmt::Display for StaleRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+/// A response to a slash command
+#[derive(Serialize)]
+struct SlackReply<'a> {
+    response_type: &'a str,
+    text: &'a str,
+}
+
+/// Calculate the signature Slack sends in the `X-Slack-Signature` header
+///
+/// # Arguments
+///
+/// * `signing_secret` - The Slack app's signing secret
+/// * `timestamp` - The `X-Slack-Request-Timestamp` header
+/// * `body` - The raw request body
+fn expected_signature(signing_secret: &str,
+                      timestamp: &str,
+                      body: &[u8]) -> Result<String, Box<Error>> {
+    let key = PKey::hmac(signing_secret.as_bytes())?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(format!("v0:{}:", timestamp).as_bytes())?;
+    signer.update(body)?;
+    let hex: Vec<String> = signer.sign_to_vec()?
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok(format!("v0={}", hex.concat()))
+}
+
+/// Check that a request came from Slack, see
+/// https://api.slack.com/authentication/verifying-requests-from-slack
+///
+/// # Arguments
+///
+/// * `signing_secret` - The Slack app's signing secret
+/// * `timestamp` - The `X-Slack-Request-Timestamp` header
+/// * `signature` - The `X-Slack-Signature` header
+/// * `body` - The raw request body
+/// * `now` - The current unix time in seconds
+pub fn verify_request(signing_secret: &str,
+                      timestamp: &str,
+                      signature: &str,
+                      body: &[u8],
+                      now: i64) -> Result<(), Box<Error>> {
+    let sent_at: i64 = timestamp.parse()?;
+    if (now - sent_at).abs() > MAX_REQUEST_AGE_SECS {
+        return Err(Box::new(StaleRequest {}));
+    }
+    let expected = expected_signature(signing_secret, timestamp, body)?;
+    if expected.len() != signature.len() ||
+        !memcmp::eq(expected.as_bytes(), signature.as_bytes()) {
+        return Err(Box::new(InvalidSignature {}));
+    }
+    Ok(())
+}
+
+/// Create the JSON response to a slash command. The reply is shown to the
+/// whole channel so that everyone can see what was added.
+///
+/// # Arguments
+///
+/// * `text` - The message to reply with
+pub fn format_reply(text: &str) -> String {
+    let reply = SlackReply {
+        response_type: "in_channel",
+        text: text,
+    };
+    serde_json::to_string(&reply).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_request_accepts_valid_signature() {
+        // Given
+        let body = b"command=%2Faddsong&text=spotify%3Atrack%3Aabc";
+        let signature = expected_signature("secret", "1531420618", body).unwrap();
+        // When
+        let result = verify_request("secret", "1531420618", &signature, body, 1531420700);
+        // Then
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn verify_request_matches_slack_example() {
+        // Given
+        // Example from Slack's documentation
+        let body = b"token=xyzz0WbapA4vBCDEFasx0q6G&team_id=T1DC2JH3J&team_domain=testteamnow&channel_id=G8PSS9T3V&channel_name=foobar&user_id=U2CERLKJA&user_name=roadrunner&command=%2Fwebhook-collect&text=&response_url=https%3A%2F%2Fhooks.slack.com%2Fcommands%2FT1DC2JH3J%2F397700885554%2F96rGlfmibIGlgcZRskXaIFfN&trigger_id=398738663015.47445629121.803a0bc887a14d10d2c447fce8b6703c";
+        // When
+        let result = expected_signature(
+            "8f742231b10e8888abcd99yyyzzz85a5", "1531420618", body
+        ).unwrap();
+        // Then
+        assert_eq!(
+            "v0=a2114d57b48eac39b9ad189dd8316235a7b4a8d21a10bd27519666489c69b503",
+            result
+        );
+    }
+
+    #[test]
+    fn verify_request_rejects_wrong_signature() {
+        // Given
+        let body = b"text=spotify%3Atrack%3Aabc";
+        let signature = expected_signature("other", "1531420618", body).unwrap();
+        // When
+        let result = verify_request("secret", "1531420618", &signature, body, 1531420618);
+        // Then
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn verify_request_rejects_old_request() {
+        // Given
+        let body = b"text=spotify%3Atrack%3Aabc";
+        let signature = expected_signature("secret", "1531420618", body).unwrap();
+        // When
+        let result = verify_request("secret", "1531420618", &signature, body, 1531430000);
+        // Then
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn format_reply_is_shown_in_channel() {
+        // When
+        let result = format_reply("Added \"Song\"");
+        // Then
+        assert_eq!(
+            "{\"response_type\":\"in_channel\",\"text\":\"Added \\\"Song\\\"\"}",
+            result
+        );
+    }
+}
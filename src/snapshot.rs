@@ -0,0 +1,238 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+
+use checksum::sha256_hex;
+use csv_to_playlist::Song;
+use export::song_from_metadata;
+use playlist::PlaylistAPI;
+
+extern crate serde_json;
+use self::serde_json::Value;
+
+/// The newest snapshot format that can be read and the one that is written.
+/// Snapshots from older versions are upgraded when they're read, so this
+/// should be increased whenever the format changes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Details about a snapshot that are needed to check it before restoring
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The version of the snapshot format, see `SNAPSHOT_VERSION`
+    pub version: u32,
+    /// The name of the playlist when the snapshot was taken
+    pub playlist_name: String,
+    /// The ID of the playlist
+    pub playlist_id: String,
+    /// The snapshot ID of the playlist from Spotify
+    pub snapshot_id: String,
+    /// When the snapshot was taken, eg. 2018-03-01T10:00:00+00:00
+    pub created: String,
+    /// The number of tracks in the snapshot
+    pub track_count: usize,
+    /// The hex encoded SHA-256 of the tracks, see `tracks_checksum`
+    pub sha256: String,
+}
+
+/// The tracks in a playlist at a point in time, so that they can be backed
+/// up and restored later on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub manifest: Manifest,
+    /// The tracks in the order they were in the playlist
+    pub tracks: Vec<Song>,
+}
+
+/// An error when a snapshot was written by a newer version of this tool
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct UnsupportedVersion {
+    version: u64,
+}
+
+impl Error for UnsupportedVersion {
+    fn description(&self) -> &str {
+        "Snapshot was created by a newer version"
+    }
+}
+
+impl fmt::Display for UnsupportedVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: version {} is newer than {}",
+               self.description(), self.version, SNAPSHOT_VERSION)
+    }
+}
+
+/// An error when a snapshot is missing its manifest or its tracks don't
+/// match the manifest
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CorruptSnapshot {}
+
+impl Error for CorruptSnapshot {
+    fn description(&self) -> &str {
+        "Snapshot tracks do not match the manifest"
+    }
+}
+
+impl fmt::Display for CorruptSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+/// Get the checksum of the tracks in a snapshot. The tracks are hashed as
+/// JSON with sorted keys so that the checksum doesn't depend on the
+/// formatting of the file.
+fn tracks_checksum(tracks: &Value) -> Result<String, Box<Error>> {
+    Ok(sha256_hex(&serde_json::to_vec(tracks)?))
+}
+
+impl Snapshot {
+    /// Create a snapshot of the tracks in the current format
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_name` - The name of the playlist
+    /// * `playlist_id` - The ID of the playlist
+    /// * `snapshot_id` - The snapshot ID of the playlist from Spotify
+    /// * `created` - When the snapshot was taken
+    /// * `tracks` - The tracks in the playlist, in order
+    pub fn new(playlist_name: &str,
+               playlist_id: &str,
+               snapshot_id: &str,
+               created: &str,
+               tracks: Vec<Song>) -> Result<Snapshot, Box<Error>> {
+        let sha256 = tracks_checksum(&serde_json::to_value(&tracks)?)?;
+        Ok(Snapshot {
+            manifest: Manifest {
+                version: SNAPSHOT_VERSION,
+                playlist_name: playlist_name.to_owned(),
+                playlist_id: playlist_id.to_owned(),
+                snapshot_id: snapshot_id.to_owned(),
+                created: created.to_owned(),
+                track_count: tracks.len(),
+                sha256: sha256,
+            },
+            tracks: tracks,
+        })
+    }
+
+    /// Parse a snapshot, checking it against its manifest. Snapshots from
+    /// older versions are upgraded to the current format.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The JSON encoded snapshot
+    pub fn parse(data: &[u8]) -> Result<Snapshot, Box<Error>> {
+        let value: Value = serde_json::from_slice(data)?;
+        let version = value.pointer("/manifest/version")
+            .and_then(Value::as_u64)
+            .ok_or(CorruptSnapshot {})?;
+        if version > u64::from(SNAPSHOT_VERSION) {
+            return Err(Box::new(UnsupportedVersion { version: version }));
+        }
+        let tracks = value.get("tracks").ok_or(CorruptSnapshot {})?;
+        let expected = value.pointer("/manifest/sha256").and_then(Value::as_str);
+        if expected != Some(tracks_checksum(tracks)?.as_str()) {
+            return Err(Box::new(CorruptSnapshot {}));
+        }
+        // Version 1 is the current format. Upgrades from older versions
+        // should be added here once the format changes
+        let mut snapshot: Snapshot = serde_json::from_value(value)?;
+        snapshot.manifest.version = SNAPSHOT_VERSION;
+        Ok(snapshot)
+    }
+
+    /// Read a snapshot that was saved using `write`
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the snapshot file
+    pub fn read(filename: &str) -> Result<Snapshot, Box<Error>> {
+        let mut data = Vec::new();
+        File::open(filename)?.read_to_end(&mut data)?;
+        Snapshot::parse(&data)
+    }
+
+    /// Save the snapshot as JSON
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the snapshot file
+    pub fn write(&self, filename: &str) -> Result<(), Box<Error>> {
+        let file = File::create(filename)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+/// Take a snapshot of the tracks currently in a playlist
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the playlist is
+/// * `playlist_name` - The name of the playlist
+/// * `playlist_id` - The ID of the playlist
+/// * `created` - When the snapshot is being taken
+pub fn take_snapshot<E>(playlist_api: &PlaylistAPI<E>,
+                        playlist_name: &str,
+                        playlist_id: &str,
+                        created: &str) -> Result<Snapshot, E> {
+    let snapshot_id = playlist_api.get_playlist_snapshot_id(playlist_id)?;
+    let tracks = playlist_api.get_tracks_with_metadata_in_playlist(playlist_id)?;
+    let songs = tracks.iter().map(song_from_metadata).collect();
+    // Songs can always be converted to JSON
+    Ok(Snapshot::new(playlist_name, playlist_id, &snapshot_id, created, songs).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_setup() -> Snapshot {
+        let tracks = vec![
+            Song{music:"Robyn - Dancing On My Own".to_string(), song_id:"spotify:track:1".to_string()},
+            Song{music:"Chromeo - Jealous".to_string(), song_id:"spotify:track:2".to_string()},
+        ];
+        Snapshot::new("Requests", "playlist1", "snapshot1", "2018-03-01T10:00:00+00:00", tracks).unwrap()
+    }
+
+    #[test]
+    fn parse_reads_written_snapshot() {
+        // Given
+        let snapshot = test_setup();
+        let data = serde_json::to_vec_pretty(&snapshot).unwrap();
+        // When
+        let result = Snapshot::parse(&data).unwrap();
+        // Then
+        assert_eq!(snapshot, result);
+        assert_eq!(2, result.manifest.track_count);
+    }
+
+    #[test]
+    fn parse_fails_when_tracks_are_changed() {
+        // Given
+        let mut snapshot = test_setup();
+        snapshot.tracks.pop();
+        let data = serde_json::to_vec(&snapshot).unwrap();
+        // When
+        let result = Snapshot::parse(&data);
+        // Then
+        assert_eq!(
+            "Snapshot tracks do not match the manifest",
+            result.unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn parse_fails_on_newer_version() {
+        // Given
+        let mut snapshot = test_setup();
+        snapshot.manifest.version = SNAPSHOT_VERSION + 1;
+        let data = serde_json::to_vec(&snapshot).unwrap();
+        // When
+        let result = Snapshot::parse(&data);
+        // Then
+        assert!(result.unwrap_err().to_string().starts_with("Snapshot was created by a newer version"));
+    }
+}
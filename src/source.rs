@@ -0,0 +1,210 @@
+use std::error::Error;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use csv_to_playlist::{parse_csv_file_with_columns,parse_csv_with_columns,CsvColumns,Song};
+use dynamodb::parse_dynamodb_json_file;
+use json_songs::parse_json_songs_file;
+use m3u::parse_m3u_file;
+
+/// Somewhere songs can be read from, eg. a CSV export. Anything that adds
+/// songs to a playlist takes a source so that new input formats only need
+/// to say how their songs are read.
+pub trait SongSource {
+    /// Read every song, in the order they should be added
+    fn read_songs(self) -> Result<Vec<Song>, Box<Error>>;
+}
+
+/// Songs that have already been read
+impl SongSource for Vec<Song> {
+    fn read_songs(self) -> Result<Vec<Song>, Box<Error>> {
+        Ok(self)
+    }
+}
+
+/// A CSV file with a row for each song
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvFile {
+    /// The path to the CSV file, or `-` to read from stdin
+    pub filename: String,
+    pub columns: CsvColumns,
+}
+
+impl CsvFile {
+    /// A DynamoDB export, with "music (S)" and "song_id (S)" columns
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the CSV file, or `-` to read from stdin
+    pub fn dynamodb_export(filename: &str) -> CsvFile {
+        CsvFile::with_columns(filename, CsvColumns::default())
+    }
+
+    /// Any CSV, with songs read from the given columns
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the CSV file, or `-` to read from stdin
+    /// * `columns` - The columns to read each song from
+    pub fn with_columns(filename: &str, columns: CsvColumns) -> CsvFile {
+        CsvFile { filename: filename.to_owned(), columns: columns }
+    }
+}
+
+impl SongSource for CsvFile {
+    fn read_songs(self) -> Result<Vec<Song>, Box<Error>> {
+        parse_csv_file_with_columns(&self.filename, &self.columns)
+    }
+}
+
+/// The formats that songs can be read from a file in
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InputFormat {
+    Csv,
+    /// DynamoDB JSON, see `parse_dynamodb_json`
+    DynamoDbJson,
+    /// A JSON array of songs, see `parse_json_songs`
+    Json,
+    /// An M3U or M3U8 playlist, see `parse_m3u`
+    M3u,
+}
+
+impl InputFormat {
+    /// Guess a file's format from its extension. `.json` and `.jsonl`
+    /// files are DynamoDB JSON, `.m3u` and `.m3u8` files are M3U playlists
+    /// and anything else is a CSV. A JSON array of songs has to be asked
+    /// for.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the file
+    pub fn from_filename(filename: &str) -> InputFormat {
+        match Path::new(filename).extension().and_then(|extension| extension.to_str()) {
+            Some("json") | Some("jsonl") => InputFormat::DynamoDbJson,
+            Some("m3u") | Some("m3u8") => InputFormat::M3u,
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<InputFormat, String> {
+        match value {
+            "csv" => Ok(InputFormat::Csv),
+            "dynamodb-json" => Ok(InputFormat::DynamoDbJson),
+            "json" => Ok(InputFormat::Json),
+            "m3u" => Ok(InputFormat::M3u),
+            _ => Err(format!("Expected csv, dynamodb-json, json or m3u, got {:?}", value)),
+        }
+    }
+}
+
+/// A file of songs in any `InputFormat`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SongFile {
+    /// The path to the file, or `-` to read from stdin
+    pub filename: String,
+    pub format: InputFormat,
+    pub columns: CsvColumns,
+}
+
+impl SongFile {
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the file, or `-` to read from stdin
+    /// * `format` - The format of the file. Guessed from its extension
+    /// when not given, see `InputFormat::from_filename`
+    /// * `columns` - The columns to read each song from
+    pub fn new(filename: &str, format: Option<InputFormat>, columns: CsvColumns) -> SongFile {
+        SongFile {
+            filename: filename.to_owned(),
+            format: format.unwrap_or_else(|| InputFormat::from_filename(filename)),
+            columns: columns,
+        }
+    }
+}
+
+impl SongSource for SongFile {
+    fn read_songs(self) -> Result<Vec<Song>, Box<Error>> {
+        match self.format {
+            InputFormat::Csv => CsvFile::with_columns(&self.filename, self.columns).read_songs(),
+            InputFormat::DynamoDbJson => parse_dynamodb_json_file(&self.filename, &self.columns),
+            InputFormat::Json => parse_json_songs_file(&self.filename, &self.columns),
+            InputFormat::M3u => parse_m3u_file(&self.filename),
+        }
+    }
+}
+
+/// CSV data that isn't in a file, eg. an upload
+pub struct CsvData<R: Read> {
+    reader: R,
+    columns: CsvColumns,
+}
+
+impl<R: Read> CsvData<R> {
+    /// DynamoDB export data, with "music (S)" and "song_id (S)" columns
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Where the CSV data is read from
+    pub fn dynamodb_export(reader: R) -> CsvData<R> {
+        CsvData::with_columns(reader, CsvColumns::default())
+    }
+
+    /// Any CSV data, with songs read from the given columns
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Where the CSV data is read from
+    /// * `columns` - The columns to read each song from
+    pub fn with_columns(reader: R, columns: CsvColumns) -> CsvData<R> {
+        CsvData { reader: reader, columns: columns }
+    }
+}
+
+impl<R: Read> SongSource for CsvData<R> {
+    fn read_songs(self) -> Result<Vec<Song>, Box<Error>> {
+        parse_csv_with_columns(self.reader, &self.columns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_to_playlist::{add_songs_to_playlist,PlaylistAddError};
+    use memory::MemoryAPI;
+    use playlist::{PlaylistAPI,Visibility};
+    use std::env;
+    use track_uri::TrackUri;
+
+    #[test]
+    fn songs_are_added_from_any_source() {
+        // Given
+        let path = env::temp_dir().join("playlist-from-csv-source-test.json");
+        let filename = path.to_str().unwrap();
+        let _ = ::std::fs::remove_file(filename);
+        let api = MemoryAPI::open(filename).unwrap();
+        let id = api.create_playlist("Mix", Visibility::Private).unwrap();
+        let export = "music (S),song_id (S)\nRobyn - Dancing On My Own,abc0000000000000000000\n";
+        let plain = "Track,URI\nRobyn - Hang With Me,spotify:track:def0000000000000000000\n";
+        let columns = CsvColumns::new(Some("Track"), Some("URI"));
+        // When
+        let from_export = add_songs_to_playlist(&api, &id, CsvData::dynamodb_export(export.as_bytes()));
+        let from_plain = add_songs_to_playlist(&api, &id, CsvData::with_columns(plain.as_bytes(), columns));
+        let unreadable = add_songs_to_playlist(&api, &id, CsvData::dynamodb_export(plain.as_bytes()));
+        // Then
+        assert_eq!(1, from_export.unwrap());
+        assert_eq!(1, from_plain.unwrap());
+        assert!(match unreadable {
+            Err(PlaylistAddError::ReadError(e)) => e == "The CSV has no column named \"music (S)\"",
+            _ => false,
+        });
+        assert_eq!(
+            vec![TrackUri::Track("abc0000000000000000000".to_owned()), TrackUri::Track("def0000000000000000000".to_owned())],
+            api.get_track_ids_in_playlist(&id).unwrap()
+        );
+    }
+}
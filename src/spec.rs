@@ -0,0 +1,430 @@
+use std::collections::{HashMap,HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::{ErrorKind,Read};
+use std::path::Path;
+
+use checksum::sha256_hex;
+use csv_to_playlist::Song;
+use dedupe::get_family_tracks;
+use harmonic::{sort_tracks,SortOrder};
+use playlist::{get_playlist_id_with_policy,CreatePolicy,PlaylistAPI,PlaylistDetails,PlaylistError,Visibility};
+use source::{CsvData,SongSource};
+use spread::spread_tracks;
+use sync::{apply_edits,compute_edits,get_sync_target,Edit};
+use track_uri::TrackUri;
+use versions::{collapse_tracks,VersionPreference};
+
+extern crate serde_json;
+extern crate serde_yaml;
+
+/// A playlist described in a spec file. Building the spec makes the playlist
+/// match it, so the file can be kept in version control like any other
+/// config.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlaylistSpec {
+    /// The Spotify playlist name
+    pub name: String,
+    /// The description shown under the playlist name
+    pub description: Option<String>,
+    /// Whether the playlist is shown on your profile
+    #[serde(default)]
+    pub public: bool,
+    /// Where the tracks come from, in order
+    pub sources: Vec<Source>,
+    #[serde(default)]
+    pub filters: Filters,
+    #[serde(default)]
+    pub order: Order,
+    /// The fewest tracks between two tracks by the same artist, see
+    /// `spread_artists`. This is done after ordering
+    pub spread_artists: Option<usize>,
+    /// The most tracks the playlist should have. Tracks past this are left
+    /// out
+    pub size: Option<usize>,
+}
+
+/// Where the tracks of a spec come from
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// A CSV file. Relative paths are from the spec file
+    Csv(String),
+    /// The tracks in one of your playlists
+    Playlist(String),
+}
+
+/// The tracks to leave out of a spec's playlist
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Filters {
+    /// Leave out tracks that are in these playlists, see `is_in_family`
+    #[serde(default)]
+    pub exclude_playlists: Vec<String>,
+    /// Leave out these tracks, given as URIs, links or IDs
+    #[serde(default)]
+    pub exclude_tracks: Vec<String>,
+    /// Keep only one version of each song, either `popular` or `album`
+    pub collapse_versions: Option<String>,
+}
+
+/// How the tracks of a spec are ordered
+#[derive(Debug, Copy, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Order {
+    /// The order the sources list them in
+    #[default]
+    Listed,
+    /// See `SortOrder::Harmonic`
+    Harmonic,
+}
+
+/// The error when a spec can't be built
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError<E> {
+    /// The error when a CSV source couldn't be read
+    ReadError(String),
+    /// Generic playlist error from API
+    PlaylistError(PlaylistError<E>),
+}
+
+fn api_error<E>(error: E) -> BuildError<E> {
+    BuildError::PlaylistError(PlaylistError::APIError(error))
+}
+
+/// The outcome of building a spec
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuiltPlaylist {
+    /// The playlist's ID, or `None` for a dry run of a playlist that doesn't
+    /// exist yet
+    pub playlist_id: Option<String>,
+    /// The changes that were made, or would be made for a dry run
+    pub edits: Vec<Edit>,
+}
+
+/// Parse a spec written in YAML
+///
+/// # Arguments
+///
+/// * `data` - The spec
+/// * `dir` - The directory that relative CSV paths are from
+pub fn parse_spec(data: &str, dir: &Path) -> Result<PlaylistSpec, Box<Error>> {
+    let mut spec: PlaylistSpec = serde_yaml::from_str(data)?;
+    if spec.sources.is_empty() {
+        return Err(From::from("The spec needs at least one source"));
+    }
+    if let Some(ref preference) = spec.filters.collapse_versions {
+        preference.parse::<VersionPreference>()
+            .map_err(|e| format!("Invalid collapse_versions: {}", e))?;
+    }
+    for source in &mut spec.sources {
+        if let Source::Csv(ref mut path) = *source {
+            *path = dir.join(&*path).to_string_lossy().into_owned();
+        }
+    }
+    Ok(spec)
+}
+
+/// Read a spec file, see `parse_spec`
+///
+/// # Arguments
+///
+/// * `filename` - The path to the spec file
+pub fn read_spec(filename: &str) -> Result<PlaylistSpec, Box<Error>> {
+    let mut data = String::new();
+    File::open(filename)?.read_to_string(&mut data)?;
+    let dir = Path::new(filename).parent().unwrap_or_else(|| Path::new(""));
+    parse_spec(&data, dir)
+}
+
+/// The tracks read from each source, so that specs that share a source only
+/// read and match it once. CSV sources can also be kept on disk between
+/// runs, where they're found by the SHA-256 of the CSV so that an edited CSV
+/// is read again. Playlists change, so they're only kept for a single run.
+#[derive(Debug, Default)]
+pub struct SourceCache {
+    tracks: HashMap<Source, Vec<TrackUri>>,
+    /// The tracks in each set of `exclude_playlists`
+    families: HashMap<Vec<String>, HashSet<TrackUri>>,
+    /// Where the CSV tracks are saved, if anywhere
+    filename: Option<String>,
+    /// The tracks of each CSV by its SHA-256
+    csv_tracks: HashMap<String, Vec<TrackUri>>,
+    /// The number of sources that weren't in the cache
+    pub reads: usize,
+}
+
+impl SourceCache {
+    /// A cache that's only kept in memory
+    pub fn new() -> SourceCache {
+        SourceCache::default()
+    }
+
+    /// Open the cache saved in the file. A missing file is treated as an
+    /// empty cache
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The path to the cache file
+    pub fn open(filename: &str) -> Result<SourceCache, Box<Error>> {
+        let csv_tracks = match File::open(filename) {
+            Ok(file) => serde_json::from_reader(file)?,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(Box::new(e)),
+        };
+        Ok(SourceCache {
+            filename: Some(filename.to_owned()),
+            csv_tracks: csv_tracks,
+            ..SourceCache::default()
+        })
+    }
+
+    /// Save the CSV tracks, if the cache was opened from a file
+    pub fn save(&self) -> Result<(), Box<Error>> {
+        if let Some(ref filename) = self.filename {
+            serde_json::to_writer(File::create(filename)?, &self.csv_tracks)?;
+        }
+        Ok(())
+    }
+
+    fn read_csv(&mut self, path: &str) -> Result<Vec<TrackUri>, Box<Error>> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+        let checksum = sha256_hex(&data);
+        if let Some(tracks) = self.csv_tracks.get(&checksum) {
+            return Ok(tracks.clone());
+        }
+        self.reads += 1;
+        let tracks = get_sync_target(&CsvData::dynamodb_export(&data[..]).read_songs()?);
+        self.csv_tracks.insert(checksum, tracks.clone());
+        Ok(tracks)
+    }
+
+    /// Get the tracks in a source, in order
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_api` - The instance to read playlist sources with
+    /// * `source` - The source to read
+    pub fn get_tracks<E>(&mut self,
+                         playlist_api: &PlaylistAPI<E>,
+                         source: &Source) -> Result<Vec<TrackUri>, BuildError<E>> {
+        if let Some(tracks) = self.tracks.get(source) {
+            return Ok(tracks.clone());
+        }
+        let tracks = match *source {
+            Source::Csv(ref path) => self.read_csv(path)
+                .map_err(|e| BuildError::ReadError(format!("{}: {}", path, e)))?,
+            Source::Playlist(ref name) => {
+                let playlist_id = playlist_api.get_playlist_id(name)
+                    .map_err(BuildError::PlaylistError)?;
+                self.reads += 1;
+                let tracks = playlist_api.get_track_ids_in_playlist(&playlist_id)
+                    .map_err(api_error)?;
+                let songs: Vec<Song> = tracks.into_iter().map(|track| Song {
+                    music: track.uri(),
+                    song_id: track.uri(),
+                }).collect();
+                get_sync_target(&songs)
+            },
+        };
+        self.tracks.insert(source.clone(), tracks.clone());
+        Ok(tracks)
+    }
+
+    /// Get the tracks in the playlists that match the patterns, see
+    /// `get_family_tracks`
+    fn get_family_tracks<E>(&mut self,
+                            playlist_api: &PlaylistAPI<E>,
+                            patterns: &[String]) -> Result<HashSet<TrackUri>, E> {
+        if let Some(tracks) = self.families.get(patterns) {
+            return Ok(tracks.clone());
+        }
+        let tracks = get_family_tracks(playlist_api, patterns)?;
+        self.families.insert(patterns.to_vec(), tracks.clone());
+        Ok(tracks)
+    }
+}
+
+/// Work out the tracks a spec's playlist should have, in order
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance to read sources and look up tracks with
+/// * `spec` - The spec to build
+/// * `cache` - The sources that have already been read
+pub fn get_spec_tracks<E>(playlist_api: &PlaylistAPI<E>,
+                          spec: &PlaylistSpec,
+                          cache: &mut SourceCache) -> Result<Vec<TrackUri>, BuildError<E>> {
+    let mut seen = HashSet::new();
+    let mut tracks = Vec::new();
+    for source in &spec.sources {
+        // Only the first of any duplicates across sources is kept
+        let source_tracks = cache.get_tracks(playlist_api, source)?;
+        tracks.extend(source_tracks.into_iter().filter(|track| seen.insert(track.clone())));
+    }
+    let mut excluded: HashSet<TrackUri> = spec.filters.exclude_tracks.iter()
+        .map(|track| TrackUri::parse(track))
+        .collect();
+    if !spec.filters.exclude_playlists.is_empty() {
+        excluded.extend(
+            cache.get_family_tracks(playlist_api, &spec.filters.exclude_playlists).map_err(api_error)?
+        );
+    }
+    tracks.retain(|track| !excluded.contains(track));
+    if let Some(ref preference) = spec.filters.collapse_versions {
+        // Checked when the spec was parsed
+        let preference = preference.parse().unwrap();
+        tracks = collapse_tracks(playlist_api, &tracks, preference).map_err(api_error)?;
+    }
+    if spec.order == Order::Harmonic {
+        tracks = sort_tracks(playlist_api, &tracks, SortOrder::Harmonic).map_err(api_error)?;
+    }
+    if let Some(spacing) = spec.spread_artists {
+        tracks = spread_tracks(playlist_api, &tracks, spacing).map_err(api_error)?;
+    }
+    if let Some(size) = spec.size {
+        tracks.truncate(size);
+    }
+    Ok(tracks)
+}
+
+/// Make the playlist match the spec, creating it if needed. The tracks, the
+/// description and whether it's public are all set from the spec
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the playlist should be built
+/// * `spec` - The spec to build
+/// * `cache` - The sources that have already been read, see `SourceCache`
+/// * `dry_run` - Work out the changes without making them
+pub fn build_playlist<E>(playlist_api: &PlaylistAPI<E>,
+                         spec: &PlaylistSpec,
+                         cache: &mut SourceCache,
+                         dry_run: bool) -> Result<BuiltPlaylist, BuildError<E>> {
+    let target = get_spec_tracks(playlist_api, spec, cache)?;
+    if dry_run {
+        let playlist_id = match playlist_api.get_playlist_id(&spec.name) {
+            Ok(playlist_id) => Some(playlist_id),
+            Err(PlaylistError::PlaylistNotFound(_)) => None,
+            Err(e) => return Err(BuildError::PlaylistError(e)),
+        };
+        let current = match playlist_id {
+            Some(ref playlist_id) => playlist_api.get_track_ids_in_playlist(playlist_id)
+                .map_err(api_error)?,
+            None => Vec::new(),
+        };
+        return Ok(BuiltPlaylist { playlist_id: playlist_id, edits: compute_edits(&current, &target) });
+    }
+    let visibility = if spec.public { Visibility::Public } else { Visibility::Private };
+    let playlist_id = get_playlist_id_with_policy(
+        playlist_api, &spec.name, CreatePolicy::CreateIfNeeded, visibility
+    ).map_err(BuildError::PlaylistError)?;
+    let current = playlist_api.get_track_ids_in_playlist(&playlist_id).map_err(api_error)?;
+    let edits = compute_edits(&current, &target);
+    apply_edits(playlist_api, &playlist_id, &edits).map_err(api_error)?;
+    if spec.description.is_some() {
+        let details = PlaylistDetails { description: spec.description.clone() };
+        playlist_api.update_playlist_details(&playlist_id, &details).map_err(api_error)?;
+    }
+    playlist_api.set_playlist_visibility(&playlist_id, visibility).map_err(api_error)?;
+    Ok(BuiltPlaylist { playlist_id: Some(playlist_id), edits: edits })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::MemoryAPI;
+    use std::env;
+
+    fn track(id: &str) -> TrackUri {
+        TrackUri::Track(format!("{:0<22}", id))
+    }
+
+    #[test]
+    fn parse_spec_reads_sources_relative_to_the_spec() {
+        // Given
+        let data = "name: Road Trip\n\
+                    sources:\n  - csv: songs.csv\n  - playlist: Liked\n\
+                    filters:\n  exclude_playlists: [Heard]\n\
+                    order: harmonic\n\
+                    size: 50\n";
+        // When
+        let spec = parse_spec(data, Path::new("specs")).unwrap();
+        let unknown = parse_spec("name: A\nsources: []\nshuffle: true\n", Path::new(""));
+        let no_sources = parse_spec("name: A\nsources: []\n", Path::new(""));
+        // Then
+        assert_eq!(
+            vec![Source::Csv("specs/songs.csv".to_owned()), Source::Playlist("Liked".to_owned())],
+            spec.sources
+        );
+        assert_eq!(vec!["Heard".to_owned()], spec.filters.exclude_playlists);
+        assert_eq!(Order::Harmonic, spec.order);
+        assert_eq!(Some(50), spec.size);
+        assert!(unknown.is_err());
+        assert!(no_sources.is_err());
+    }
+
+    #[test]
+    fn build_playlist_makes_the_playlist_match_the_spec() {
+        // Given
+        let path = env::temp_dir().join("playlist-from-csv-spec-test.json");
+        let filename = path.to_str().unwrap();
+        let _ = ::std::fs::remove_file(filename);
+        let api = MemoryAPI::open(filename).unwrap();
+        let liked = api.create_playlist("Liked", Visibility::Private).unwrap();
+        api.add_tracks_to_playlist(&liked, &[track("a"), track("b"), track("c"), track("d")], None).unwrap();
+        let heard = api.create_playlist("Heard", Visibility::Private).unwrap();
+        api.add_tracks_to_playlist(&heard, &[track("b")], None).unwrap();
+        let data = "name: Mix\n\
+                    description: Not heard yet\n\
+                    public: true\n\
+                    sources:\n  - playlist: Liked\n\
+                    filters:\n  exclude_playlists: [Heard]\n  exclude_tracks: [d]\n\
+                    size: 1\n";
+        let spec = parse_spec(data, Path::new("")).unwrap();
+        // When
+        let preview = build_playlist(&api, &spec, &mut SourceCache::new(), true).unwrap();
+        let built = build_playlist(&api, &spec, &mut SourceCache::new(), false).unwrap();
+        let rebuilt = build_playlist(&api, &spec, &mut SourceCache::new(), false).unwrap();
+        // Then
+        assert_eq!(None, preview.playlist_id);
+        assert_eq!(1, preview.edits.len());
+        let playlist_id = built.playlist_id.unwrap();
+        assert_eq!(vec![track("a")], api.get_track_ids_in_playlist(&playlist_id).unwrap());
+        assert_eq!(Vec::<Edit>::new(), rebuilt.edits);
+        let info = api.list_playlists().unwrap().into_iter().find(|p| p.name == "Mix").unwrap();
+        assert!(info.public);
+    }
+
+    #[test]
+    fn source_cache_reads_each_source_once() {
+        // Given
+        let dir = env::temp_dir();
+        let csv = dir.join("playlist-from-csv-spec-cache-test.csv");
+        ::std::fs::write(&csv, "song_id (S),music (S)\n\
+                                spotify:track:a000000000000000000000,A\n\
+                                spotify:track:b000000000000000000000,B\n").unwrap();
+        let cache_file = dir.join("playlist-from-csv-spec-cache-test.json");
+        let cache_filename = cache_file.to_str().unwrap();
+        let _ = ::std::fs::remove_file(cache_filename);
+        let sandbox = dir.join("playlist-from-csv-spec-cache-sandbox.json");
+        let _ = ::std::fs::remove_file(&sandbox);
+        let api = MemoryAPI::open(sandbox.to_str().unwrap()).unwrap();
+        let source = Source::Csv(csv.to_str().unwrap().to_owned());
+        let mut cache = SourceCache::open(cache_filename).unwrap();
+        // When
+        let first = cache.get_tracks(&api, &source).unwrap();
+        let second = cache.get_tracks(&api, &source).unwrap();
+        cache.save().unwrap();
+        let mut reopened = SourceCache::open(cache_filename).unwrap();
+        let from_disk = reopened.get_tracks(&api, &source).unwrap();
+        // Then
+        assert_eq!(vec![track("a"), track("b")], first);
+        assert_eq!(first, second);
+        assert_eq!(1, cache.reads);
+        assert_eq!(first, from_disk);
+        assert_eq!(0, reopened.reads);
+    }
+}
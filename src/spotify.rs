@@ -1,17 +1,205 @@
 use std::error::Error;
 use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-use playlist::{PlaylistAPI,PlaylistError,PlaylistNotFound};
+use events::{emit,Event};
+use progress::{NoProgress,Progress,Stage};
+use playlist::{AudioFeatures,PlaylistAPI,PlaylistError,PlaylistNotFound,TrackAvailability,TrackMetadata,PlaylistDetails,PlaylistInfo,TrackVersion,Visibility};
+use track_uri::TrackUri;
+use user_agent;
 
 extern crate rspotify;
 
 use rspotify::spotify::client::Spotify;
 use rspotify::spotify::util::get_token;
-use rspotify::spotify::oauth2::{SpotifyClientCredentials,SpotifyOAuth};
-use rspotify::spotify::model::playlist::PlaylistTrack;
+use rspotify::spotify::oauth2::{SpotifyClientCredentials,SpotifyOAuth,TokenInfo};
+use rspotify::spotify::model::track::FullTrack;
 
 extern crate failure;
 
+extern crate reqwest;
+use self::reqwest::header::{Authorization, Bearer, ContentType};
+
+extern crate base64;
+
+extern crate serde;
+use self::serde::de::DeserializeOwned;
+
+extern crate serde_json;
+use self::serde_json::Value;
+
+const API_URL: &str = "https://api.spotify.com/v1";
+
+/// Tokens are refreshed this long before they expire so that they don't
+/// expire part way through a run
+const TOKEN_REFRESH_MARGIN_SECS: i64 = 300;
+
+/// A page of playlist items. These are read without rspotify since it
+/// expects every item to be a track with an ID, which isn't the case for
+/// episodes and local files
+#[derive(Deserialize)]
+struct PlaylistItemsPage {
+    items: Vec<PlaylistItem>,
+    next: Option<String>,
+    /// The number of items in the whole playlist
+    total: u64,
+}
+
+#[derive(Deserialize)]
+struct PlaylistItem {
+    /// This is null when the track is no longer available
+    track: Option<Item>,
+}
+
+/// A track or episode, including local files
+#[derive(Deserialize)]
+struct Item {
+    uri: String,
+    name: Option<String>,
+    #[serde(default)]
+    artists: Vec<Named>,
+    album: Option<Album>,
+    show: Option<Show>,
+    /// This is missing for local files
+    duration_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct Named {
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Album {
+    name: Option<String>,
+    #[serde(default)]
+    images: Vec<Image>,
+}
+
+#[derive(Deserialize)]
+struct Show {
+    name: String,
+    publisher: String,
+    #[serde(default)]
+    images: Vec<Image>,
+}
+
+/// Artwork, largest first
+#[derive(Deserialize)]
+struct Image {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct EpisodesResponse {
+    /// Unknown episodes are null
+    episodes: Vec<Option<Item>>,
+}
+
+/// Tracks in a market are read without rspotify since it doesn't read
+/// whether they can be played
+#[derive(Deserialize)]
+struct MarketTracksResponse {
+    /// Unknown tracks are null
+    tracks: Vec<Option<MarketTrack>>,
+}
+
+#[derive(Deserialize)]
+struct MarketTrack {
+    #[serde(flatten)]
+    item: Item,
+    #[serde(default)]
+    is_playable: bool,
+    /// The track that was asked for, when Spotify relinked it to another
+    /// release that can be played in the market
+    linked_from: Option<LinkedTrack>,
+}
+
+#[derive(Deserialize)]
+struct LinkedTrack {
+    uri: String,
+}
+
+/// Audio features are read without rspotify since it doesn't support them
+#[derive(Deserialize)]
+struct AudioFeaturesResponse {
+    /// Tracks that haven't been analysed are null
+    audio_features: Vec<Option<TrackFeatures>>,
+}
+
+#[derive(Deserialize)]
+struct TrackFeatures {
+    id: String,
+    /// -1 when no key was detected
+    key: i32,
+    /// 1 for major and 0 for minor
+    mode: i32,
+    tempo: f64,
+    energy: f64,
+}
+
+/// A page of the playlists the user follows
+#[derive(Deserialize)]
+struct PlaylistsPage {
+    items: Vec<SimplePlaylist>,
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SimplePlaylist {
+    id: String,
+    name: String,
+    tracks: PlaylistTracksRef,
+    /// Null for playlists whose visibility isn't known, eg. those that
+    /// aren't the user's own
+    public: Option<bool>,
+    owner: PlaylistOwner,
+}
+
+#[derive(Deserialize)]
+struct PlaylistTracksRef {
+    total: u32,
+}
+
+#[derive(Deserialize)]
+struct PlaylistOwner {
+    id: String,
+    display_name: Option<String>,
+}
+
+#[derive(Serialize)]
+struct AddItemsRequest {
+    uris: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    position: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ReorderItemsRequest {
+    range_start: usize,
+    insert_before: usize,
+}
+
+#[derive(Clone, Serialize)]
+struct RemoveItem {
+    uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    positions: Option<Vec<usize>>,
+}
+
+#[derive(Serialize)]
+struct RemoveItemsRequest {
+    tracks: Vec<RemoveItem>,
+    /// Positions are relative to this snapshot, even after earlier chunks
+    /// have changed the playlist
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snapshot_id: Option<String>,
+}
+
 /// An error when authentication fails to Spotify servers
 #[derive(Debug)]
 pub struct AuthenticationFailed;
@@ -28,10 +216,103 @@ impl fmt::Display for AuthenticationFailed {
     }
 }
 
-/// Stores necessary information for calling Spotify API
+/// An error when logging in would need the user, eg. to open a browser, but
+/// running non-interactively
+#[derive(Debug)]
+pub struct LoginRequired;
+
+impl Error for LoginRequired {
+    fn description(&self) -> &str {
+        "There's no cached token and logging in needs a browser. Log in once \
+         interactively to cache a token"
+    }
+}
+
+impl fmt::Display for LoginRequired {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+/// Login error enum for the different ways logging in can fail
+#[derive(Debug)]
+pub enum LoginError {
+    /// The error when Spotify didn't give a token
+    AuthenticationFailed(AuthenticationFailed),
+    /// The error when logging in needs the user but they can't be asked
+    LoginRequired(LoginRequired),
+}
+
+impl fmt::Display for LoginError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoginError::AuthenticationFailed(ref e) => write!(f, "{}", e),
+            LoginError::LoginRequired(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// How to get a token when logging in
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AuthStrategy {
+    /// Use the cached token, or open a browser and wait for the user to
+    /// paste the URL they were redirected to
+    Interactive,
+    /// Only use the cached token, refreshing it if needed, and fail with
+    /// `LoginRequired` when there isn't one. Used for CI and cron
+    NonInteractive,
+}
+
+/// An error when the run has made as many requests as it's allowed to
+#[derive(Debug)]
+pub struct RequestBudgetExhausted {
+    max_requests: usize,
+}
+
+impl Error for RequestBudgetExhausted {
+    fn description(&self) -> &str {
+        "Request budget exhausted"
+    }
+}
+
+impl fmt::Display for RequestBudgetExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} after {} requests", self.description(), self.max_requests)
+    }
+}
+
+/// An error when the run has gone past its deadline
+#[derive(Debug)]
+pub struct DeadlineExceeded {
+    requests_made: usize,
+}
+
+impl Error for DeadlineExceeded {
+    fn description(&self) -> &str {
+        "Deadline reached"
+    }
+}
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} after {} requests", self.description(), self.requests_made)
+    }
+}
+
+/// Stores necessary information for calling Spotify API. This can be shared
+/// between threads so that they use the same token, HTTP client and request
+/// budget
 pub struct SpotifyAPI {
     spotify: Spotify,
     username: String,
+    http: reqwest::Client,
+    /// The most requests that can be made, if limited
+    max_requests: Option<usize>,
+    requests_made: AtomicUsize,
+    /// When requests should stop being made, if limited
+    deadline: Option<Instant>,
+    /// Told about each page fetched and each batch of tracks added
+    progress: Arc<Progress + Send + Sync>,
 }
 
 impl SpotifyAPI {
@@ -41,29 +322,187 @@ impl SpotifyAPI {
     ///
     /// * `username` - A string slice that holds the username
     /// * `spotify_oauth` - A setup OAuth struct
+    /// * `strategy` - Whether the user can be asked to log in
     pub fn new(username: &str,
-               mut spotify_oauth: &mut SpotifyOAuth) -> Result<SpotifyAPI, AuthenticationFailed> {
-        match get_token(&mut spotify_oauth) {
-            Some(token_info) => {
-                let client_credential = SpotifyClientCredentials::default()
-                    .token_info(token_info)
-                    .build();
-                let spotify = Spotify::default()
-                    .client_credentials_manager(client_credential)
-                    .build();
-                Ok(SpotifyAPI{spotify: spotify, username: username.to_owned()})
-            }
-            None => Err(AuthenticationFailed{}),
+               spotify_oauth: &mut SpotifyOAuth,
+               strategy: AuthStrategy) -> Result<SpotifyAPI, LoginError> {
+        let token_info = match strategy {
+            AuthStrategy::Interactive => get_token(spotify_oauth)
+                .ok_or(LoginError::AuthenticationFailed(AuthenticationFailed{}))?,
+            AuthStrategy::NonInteractive => spotify_oauth.get_cached_token()
+                .ok_or(LoginError::LoginRequired(LoginRequired{}))?,
+        };
+        Ok(SpotifyAPI::with_token(username, token_info))
+    }
+
+    /// Returns a SpotifyAPI that will query using the given username and an
+    /// existing token
+    ///
+    /// # Arguments
+    ///
+    /// * `username` - A string slice that holds the username
+    /// * `token_info` - A token that has already been retrieved
+    pub fn with_token(username: &str, token_info: TokenInfo) -> SpotifyAPI {
+        let client_credential = SpotifyClientCredentials::default()
+            .token_info(token_info)
+            .build();
+        let spotify = Spotify::default()
+            .client_credentials_manager(client_credential)
+            .build();
+        SpotifyAPI{
+            spotify: spotify,
+            username: username.to_owned(),
+            http: user_agent::client(),
+            max_requests: None,
+            requests_made: AtomicUsize::new(0),
+            deadline: None,
+            progress: Arc::new(NoProgress),
         }
     }
+
+    /// Limit how many requests can be made to Spotify. Once the limit is
+    /// reached every call fails with `RequestBudgetExhausted`, so that a
+    /// runaway job can't get a shared client ID rate limited
+    ///
+    /// # Arguments
+    ///
+    /// * `max_requests` - The most requests to make
+    pub fn with_max_requests(mut self, max_requests: usize) -> SpotifyAPI {
+        self.max_requests = Some(max_requests);
+        self
+    }
+
+    /// Stop making requests after a deadline. Calls fail with
+    /// `DeadlineExceeded` once it has passed, and requests that are still
+    /// waiting on Spotify time out at the deadline
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - When to stop
+    pub fn with_deadline(mut self, deadline: Instant) -> SpotifyAPI {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Report progress through large playlists, see `Progress`
+    ///
+    /// # Arguments
+    ///
+    /// * `progress` - Where to report progress to
+    pub fn with_progress(mut self, progress: Arc<Progress + Send + Sync>) -> SpotifyAPI {
+        self.progress = progress;
+        self
+    }
+
+    /// Returns a SpotifyAPI for whoever the token belongs to. This is used
+    /// when the username isn't known ahead of time
+    ///
+    /// # Arguments
+    ///
+    /// * `token_info` - A token that has already been retrieved
+    pub fn for_token_owner(token_info: TokenInfo) -> Result<SpotifyAPI, failure::Error> {
+        let user_id = get_current_user_id(&token_info.access_token)?;
+        Ok(SpotifyAPI::with_token(&user_id, token_info))
+    }
+
+    /// The number of requests made to Spotify so far, including any over
+    /// the budget
+    pub fn requests_made(&self) -> usize {
+        self.requests_made.load(Ordering::SeqCst)
+    }
+
+    /// The username that queries are made on behalf of
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// The token that queries are made with, so that it can be refreshed
+    /// for later runs, see `refresh_token_if_needed`
+    pub fn token_info(&self) -> Option<TokenInfo> {
+        self.spotify.client_credentials_manager
+            .as_ref()
+            .and_then(|credentials| credentials.token_info.clone())
+    }
+}
+
+fn unix_time_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs() as i64).unwrap_or(0)
+}
+
+/// Get a token that won't expire soon, refreshing it if needed. This is for
+/// processes that keep running, since logging in only refreshes a token
+/// once. Spotify doesn't always send back a refresh token and rspotify
+/// doesn't record when a refreshed token expires, so both are kept from the
+/// old token and the cached token is saved again with them.
+///
+/// # Arguments
+///
+/// * `spotify_oauth` - The OAuth setup that the token came from
+/// * `token_info` - The current token
+pub fn refresh_token_if_needed(spotify_oauth: &SpotifyOAuth,
+                               token_info: TokenInfo) -> Result<TokenInfo, AuthenticationFailed> {
+    let now = unix_time_now();
+    match token_info.expires_at {
+        Some(expires_at) if expires_at - TOKEN_REFRESH_MARGIN_SECS > now => return Ok(token_info),
+        _ => {},
+    }
+    let refresh_token = token_info.refresh_token.ok_or(AuthenticationFailed {})?;
+    let mut refreshed = spotify_oauth.refresh_access_token(&refresh_token).ok_or(AuthenticationFailed {})?;
+    refreshed.expires_at = Some(now + i64::from(refreshed.expires_in));
+    refreshed.refresh_token = refreshed.refresh_token.or(Some(refresh_token));
+    debug!("Refreshed the Spotify token, it now expires in {}s", refreshed.expires_in);
+    let saved = serde_json::to_string(&refreshed).map_err(|e| e.to_string()).and_then(|json| {
+        File::create(&spotify_oauth.cache_path)
+            .and_then(|mut file| file.write_all(json.as_bytes()))
+            .map_err(|e| e.to_string())
+    });
+    if let Err(e) = saved {
+        warn!("Could not save the refreshed token: {}", e);
+    }
+    Ok(refreshed)
 }
 
 impl SpotifyAPI {
+    /// Count a request against the budget, failing if there's none left
+    fn spend_request(&self) -> Result<(), failure::Error> {
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                let requests_made = self.requests_made.load(Ordering::SeqCst);
+                return Err(DeadlineExceeded { requests_made: requests_made }.into());
+            }
+        }
+        // Other threads may be spending at the same time, so the request is
+        // counted before checking whether it was within the budget
+        let requests_made = self.requests_made.fetch_add(1, Ordering::SeqCst);
+        trace!("Making request {}", requests_made + 1);
+        if let Some(max_requests) = self.max_requests {
+            if requests_made >= max_requests {
+                return Err(RequestBudgetExhausted { max_requests: max_requests }.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// The HTTP client for a single request, counted against the budget. With
+    /// a deadline the client is built to time out when it passes
+    fn client(&self) -> Result<reqwest::Client, failure::Error> {
+        self.spend_request()?;
+        match self.deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                Ok(user_agent::client_builder().timeout(remaining).build()?)
+            },
+            None => Ok(self.http.clone()),
+        }
+    }
+
     /// Get playlist ID by searching through pages of playlists.
     /// This will be recursively called incrementing offset for each page.
     fn get_playlist_id_with_offset(&self,
                                    playlist_name: &str,
                                    offset: u32) -> Result<String, PlaylistError<failure::Error>> {
+        self.spend_request().map_err(PlaylistError::APIError)?;
+        debug!("Searching for {} in playlists from {}", playlist_name, offset);
         let result = self.spotify.current_user_playlists(None, Some(offset));
         let playlist_page = result.map_err(PlaylistError::APIError)?;
         // Find the first playlist with the matching name
@@ -82,42 +521,70 @@ impl SpotifyAPI {
         self.get_playlist_id_with_offset(playlist_name, offset + playlist_page.total)
     }
 
-    /// Get track IDs for all tracks in playlist.
-    /// This will be recursively called incrementing offset for each page.
+    /// The current access token, used for requests that rspotify can't make
+    fn access_token(&self) -> String {
+        self.spotify.client_credentials_manager
+            .as_ref()
+            .map(|credentials| credentials.get_access_token())
+            .unwrap_or_default()
+    }
+
+    /// Make a GET request to the API and parse the response
+    fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, failure::Error> {
+        let response = self.client()?
+            .get(url)
+            .header(Authorization(Bearer { token: self.access_token() }))
+            .send()?;
+        Ok(check_status(response, url)?.json()?)
+    }
+
+    /// Remove items from the playlist
+    ///
     /// # Arguments
     ///
-    /// * `playlist_id` - A string slice that holds the playlist name
-    /// * `offset` - How far into the playlist we want to start querying from
-    /// * `track_ids` - The track IDs we've retrieved so far, as this is called
-    /// recursively. This should be an empty vec when first
-    /// called
-    fn get_track_ids_in_playlist_with_offset(&self,
-                                             playlist_id: &str,
-                                             offset: u32,
-                                             track_ids: &mut Vec<String>) -> Result<Vec<String>, failure::Error> {
-        let results = self.spotify.user_playlist_tracks(
-            &self.username,
-            playlist_id,
-            None,
-            None,
-            Some(offset),
-            None
-        )?;
-        // Get the IDs for the new results
-        let mut new_ids = get_track_ids(results.items);
-        // Append it to the current set
-        track_ids.append(&mut new_ids);
-        // If there are less results than the limit then we've reached the
-        // last page
-        if results.total < results.limit {
-            return Ok(track_ids.to_vec());
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    /// * `items` - The items to remove
+    /// * `snapshot_id` - The snapshot that item positions refer to
+    fn remove_items(&self,
+                    playlist_id: &str,
+                    items: &[RemoveItem],
+                    snapshot_id: Option<String>) -> Result<(), failure::Error> {
+        let url = format!("{}/playlists/{}/tracks", API_URL, playlist_id);
+        // Spotify will only remove 100 items per request
+        for chunk in items.chunks(100) {
+            let response = self.client()?
+                .delete(&url)
+                .header(Authorization(Bearer { token: self.access_token() }))
+                .json(&RemoveItemsRequest { tracks: chunk.to_vec(), snapshot_id: snapshot_id.clone() })
+                .send()?;
+            check_status(response, &url)?;
         }
-        // Recurse over the next page
-        self.get_track_ids_in_playlist_with_offset(
-            playlist_id,
-            offset + results.total,
-            track_ids
-        )
+        Ok(())
+    }
+
+    /// Get every item in the playlist by following each page of results.
+    /// Items that are no longer available are skipped
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_id` - A string slice that holds the playlist ID
+    fn get_playlist_items(&self, playlist_id: &str) -> Result<Vec<Item>, failure::Error> {
+        let mut items = Vec::new();
+        let mut next = Some(format!(
+            "{}/playlists/{}/tracks?limit=100&additional_types=track,episode",
+            API_URL, playlist_id
+        ));
+        let mut pages = 0;
+        while let Some(url) = next {
+            let page: PlaylistItemsPage = self.get_json(&url)?;
+            pages += 1;
+            self.progress.update(Stage::PlaylistPages, pages, Some(page.total.div_ceil(100).max(1)));
+            items.extend(page.items.into_iter().filter_map(|item| item.track));
+            debug!("Fetched {} items from playlist {}", items.len(), playlist_id);
+            next = page.next;
+        }
+        self.progress.finish(Stage::PlaylistPages);
+        Ok(items)
     }
 }
 
@@ -128,11 +595,13 @@ impl PlaylistAPI<failure::Error> for SpotifyAPI {
     }
 
     fn create_playlist(&self,
-                       playlist_name: &str) -> Result<String, failure::Error> {
+                       playlist_name: &str,
+                       visibility: Visibility) -> Result<String, failure::Error> {
+        self.spend_request()?;
         let playlist = self.spotify.user_playlist_create(
             &self.username,
             playlist_name,
-            false,
+            visibility == Visibility::Public,
             None
         )?;
         Ok(playlist.id)
@@ -140,30 +609,415 @@ impl PlaylistAPI<failure::Error> for SpotifyAPI {
 
     fn add_tracks_to_playlist(&self,
                               playlist_id: &str,
-                              track_ids: &[String]) -> Result<(), failure::Error> {
-        if track_ids.is_empty() {
-            return Ok(());
+                              tracks: &[TrackUri],
+                              position: Option<usize>) -> Result<(), failure::Error> {
+        // rspotify can only add tracks, so episodes are added by URI directly
+        let uris: Vec<String> = tracks.iter()
+            .filter(|t| t.is_addable())
+            .map(|t| t.uri())
+            .collect();
+        let url = format!("{}/playlists/{}/tracks", API_URL, playlist_id);
+        // Spotify will only add 100 items per request, so when inserting
+        // each chunk goes after the last
+        let batches = uris.len().div_ceil(100) as u64;
+        for (i, chunk) in uris.chunks(100).enumerate() {
+            debug!("Adding {} of {} tracks to playlist {}", i * 100 + chunk.len(), uris.len(), playlist_id);
+            let response = self.client()?
+                .post(&url)
+                .header(Authorization(Bearer { token: self.access_token() }))
+                .json(&AddItemsRequest {
+                    uris: chunk.to_vec(),
+                    position: position.map(|position| position + i * 100),
+                })
+                .send()?;
+            check_status(response, &url)?;
+            emit(&Event::BatchAdded {
+                playlist_id: playlist_id,
+                batch: i as u64 + 1,
+                batches: batches,
+                tracks: chunk.len(),
+            });
+            self.progress.update(Stage::TrackBatches, i as u64 + 1, Some(batches));
         }
-        self.spotify.user_playlist_add_tracks(
+        self.progress.finish(Stage::TrackBatches);
+        Ok(())
+    }
+
+    fn get_track_ids_in_playlist(&self,
+                                 playlist_id: &str) -> Result<Vec<TrackUri>, failure::Error> {
+        let items = self.get_playlist_items(playlist_id)?;
+        Ok(items.iter().map(|item| TrackUri::parse(&item.uri)).collect())
+    }
+
+    fn get_tracks_with_metadata_in_playlist(&self,
+                                            playlist_id: &str) -> Result<Vec<TrackMetadata>, failure::Error> {
+        let items = self.get_playlist_items(playlist_id)?;
+        Ok(items.into_iter().map(get_item_metadata).collect())
+    }
+
+    fn get_playlist_snapshot_id(&self,
+                                playlist_id: &str) -> Result<String, failure::Error> {
+        let mut playlist_id = playlist_id.to_owned();
+        self.spend_request()?;
+        let playlist = self.spotify.user_playlist(
+            &self.username,
+            Some(&mut playlist_id),
+            None
+        )?;
+        Ok(playlist.snapshot_id)
+    }
+
+    fn get_playlist_size(&self,
+                         playlist_id: &str) -> Result<usize, failure::Error> {
+        let mut playlist_id = playlist_id.to_owned();
+        self.spend_request()?;
+        // The playlist comes with its first page of tracks, which has the
+        // total so the rest don't need to be fetched
+        let playlist = self.spotify.user_playlist(
+            &self.username,
+            Some(&mut playlist_id),
+            None
+        )?;
+        Ok(playlist.tracks.total as usize)
+    }
+
+    fn get_playlist_details(&self,
+                            playlist_id: &str) -> Result<PlaylistDetails, failure::Error> {
+        let mut playlist_id = playlist_id.to_owned();
+        self.spend_request()?;
+        let playlist = self.spotify.user_playlist(
+            &self.username,
+            Some(&mut playlist_id),
+            None
+        )?;
+        Ok(PlaylistDetails {
+            description: Some(playlist.description).filter(|description| !description.is_empty()),
+        })
+    }
+
+    fn get_cover_image(&self,
+                       playlist_id: &str) -> Result<Option<Vec<u8>>, failure::Error> {
+        let mut playlist_id = playlist_id.to_owned();
+        self.spend_request()?;
+        let playlist = self.spotify.user_playlist(
+            &self.username,
+            Some(&mut playlist_id),
+            None
+        )?;
+        // Images are listed largest first
+        let url = match playlist.images.first() {
+            Some(image) => image.url.to_owned(),
+            None => return Ok(None),
+        };
+        let mut response = check_status(self.client()?.get(&url).send()?, &url)?;
+        let mut jpeg = Vec::new();
+        response.read_to_end(&mut jpeg)?;
+        Ok(Some(jpeg))
+    }
+
+    fn get_tracks_metadata(&self,
+                           tracks: &[TrackUri]) -> Result<Vec<TrackMetadata>, failure::Error> {
+        let mut metadata = Vec::new();
+        let track_ids: Vec<&str> = tracks.iter().filter_map(|t| match *t {
+            TrackUri::Track(ref id) => Some(id.as_str()),
+            _ => None,
+        }).collect();
+        let episode_ids: Vec<&str> = tracks.iter().filter_map(|t| match *t {
+            TrackUri::Episode(ref id) => Some(id.as_str()),
+            _ => None,
+        }).collect();
+        // Spotify will only return 50 tracks per request
+        for chunk in track_ids.chunks(50) {
+            self.spend_request()?;
+            let results = self.spotify.tracks(chunk.to_vec(), None)?;
+            metadata.extend(results.tracks.iter().map(get_track_metadata));
+        }
+        for chunk in episode_ids.chunks(50) {
+            let url = format!("{}/episodes?ids={}", API_URL, chunk.join(","));
+            let results: EpisodesResponse = self.get_json(&url)?;
+            metadata.extend(results.episodes.into_iter().filter_map(|e| e.map(get_item_metadata)));
+        }
+        Ok(metadata)
+    }
+
+    fn update_playlist_details(&self,
+                               playlist_id: &str,
+                               details: &PlaylistDetails) -> Result<(), failure::Error> {
+        self.spend_request()?;
+        self.spotify.user_playlist_change_detail(
             &self.username,
             playlist_id,
-            &track_ids,
+            None,
+            None,
+            details.description.clone(),
             None
         )?;
         Ok(())
     }
 
-    fn get_track_ids_in_playlist(&self,
-                                 playlist_id: &str) -> Result<Vec<String>, failure::Error> {
-        self.get_track_ids_in_playlist_with_offset(
+    fn set_playlist_visibility(&self,
+                               playlist_id: &str,
+                               visibility: Visibility) -> Result<(), failure::Error> {
+        self.spend_request()?;
+        self.spotify.user_playlist_change_detail(
+            &self.username,
             playlist_id,
-            0,
-            &mut Vec::new()
-        )
+            None,
+            Some(visibility == Visibility::Public),
+            None,
+            None
+        )?;
+        Ok(())
+    }
+
+    fn upload_cover_image(&self,
+                          playlist_id: &str,
+                          jpeg: &[u8]) -> Result<(), failure::Error> {
+        let url = format!("{}/playlists/{}/images", API_URL, playlist_id);
+        // Spotify expects the JPEG to be sent base64 encoded
+        let response = self.client()?
+            .put(&url)
+            .header(Authorization(Bearer { token: self.access_token() }))
+            .header(ContentType::jpeg())
+            .body(base64::encode(jpeg))
+            .send()?;
+        check_status(response, &url)?;
+        Ok(())
+    }
+
+    fn can_modify_playlist(&self,
+                           playlist_id: &str) -> Result<bool, failure::Error> {
+        let mut playlist_id = playlist_id.to_owned();
+        self.spend_request()?;
+        let playlist = self.spotify.user_playlist(
+            &self.username,
+            Some(&mut playlist_id),
+            None
+        )?;
+        Ok(playlist.collaborative || playlist.owner.id == self.username)
+    }
+
+    fn rename_playlist(&self,
+                       playlist_id: &str,
+                       name: &str) -> Result<(), failure::Error> {
+        self.spend_request()?;
+        self.spotify.user_playlist_change_detail(
+            &self.username,
+            playlist_id,
+            Some(name),
+            None,
+            None,
+            None
+        )?;
+        Ok(())
+    }
+
+    fn unfollow_playlist(&self,
+                         playlist_id: &str) -> Result<(), failure::Error> {
+        self.spend_request()?;
+        self.spotify.user_playlist_unfollow(&self.username, playlist_id)?;
+        Ok(())
+    }
+
+    fn remove_tracks_from_playlist(&self,
+                                   playlist_id: &str,
+                                   tracks: &[TrackUri]) -> Result<(), failure::Error> {
+        let items: Vec<RemoveItem> = tracks.iter()
+            .filter(|t| t.is_addable())
+            .map(|t| RemoveItem { uri: t.uri(), positions: None })
+            .collect();
+        self.remove_items(playlist_id, &items, None)
+    }
+
+    fn insert_tracks_into_playlist(&self,
+                                   playlist_id: &str,
+                                   tracks: &[TrackUri],
+                                   position: usize) -> Result<(), failure::Error> {
+        self.add_tracks_to_playlist(playlist_id, tracks, Some(position))
+    }
+
+    fn remove_tracks_at_positions(&self,
+                                  playlist_id: &str,
+                                  occurrences: &[(TrackUri, usize)]) -> Result<(), failure::Error> {
+        let items: Vec<RemoveItem> = occurrences.iter()
+            .map(|&(ref track, position)| RemoveItem {
+                uri: track.uri(),
+                positions: Some(vec![position]),
+            })
+            .collect();
+        let snapshot_id = self.get_playlist_snapshot_id(playlist_id)?;
+        self.remove_items(playlist_id, &items, Some(snapshot_id))
+    }
+
+    fn move_track_in_playlist(&self,
+                              playlist_id: &str,
+                              from: usize,
+                              insert_before: usize) -> Result<(), failure::Error> {
+        let url = format!("{}/playlists/{}/tracks", API_URL, playlist_id);
+        let response = self.client()?
+            .put(&url)
+            .header(Authorization(Bearer { token: self.access_token() }))
+            .json(&ReorderItemsRequest { range_start: from, insert_before: insert_before })
+            .send()?;
+        check_status(response, &url)?;
+        Ok(())
+    }
+
+    fn list_playlists(&self) -> Result<Vec<PlaylistInfo>, failure::Error> {
+        let mut playlists = Vec::new();
+        let mut next = Some(format!("{}/me/playlists?limit=50", API_URL));
+        while let Some(url) = next {
+            let page: PlaylistsPage = self.get_json(&url)?;
+            playlists.extend(page.items.into_iter().map(|p| PlaylistInfo {
+                id: p.id,
+                name: p.name,
+                track_count: p.tracks.total,
+                public: p.public.unwrap_or(false),
+                owner: p.owner.display_name.unwrap_or(p.owner.id),
+            }));
+            debug!("Fetched {} playlists", playlists.len());
+            next = page.next;
+        }
+        Ok(playlists)
+    }
+
+    fn search_tracks(&self, query: &str, limit: u32) -> Result<Vec<TrackMetadata>, failure::Error> {
+        self.spend_request()?;
+        let result = self.spotify.search_track(query, limit, 0, None)?;
+        Ok(result.tracks.items.iter().map(get_track_metadata).collect())
+    }
+
+    fn get_track_versions(&self, tracks: &[TrackUri]) -> Result<Vec<TrackVersion>, failure::Error> {
+        let track_ids: Vec<&str> = tracks.iter().filter_map(|t| match *t {
+            TrackUri::Track(ref id) => Some(id.as_str()),
+            _ => None,
+        }).collect();
+        let mut versions = Vec::new();
+        // Spotify will only return 50 tracks per request
+        for chunk in track_ids.chunks(50) {
+            self.spend_request()?;
+            let results = self.spotify.tracks(chunk.to_vec(), None)?;
+            versions.extend(results.tracks.iter().map(|track| TrackVersion {
+                track: get_track_metadata(track),
+                popularity: track.popularity.max(0) as u32,
+                on_album: track.album.album_type == "album",
+            }));
+        }
+        Ok(versions)
+    }
+
+    fn get_audio_features(&self, tracks: &[TrackUri]) -> Result<Vec<AudioFeatures>, failure::Error> {
+        let track_ids: Vec<&str> = tracks.iter().filter_map(|t| match *t {
+            TrackUri::Track(ref id) => Some(id.as_str()),
+            _ => None,
+        }).collect();
+        let mut features = Vec::new();
+        // Spotify will only return 100 tracks per request
+        for chunk in track_ids.chunks(100) {
+            let url = format!("{}/audio-features?ids={}", API_URL, chunk.join(","));
+            let results: AudioFeaturesResponse = self.get_json(&url)?;
+            features.extend(results.audio_features.into_iter().flatten().map(|f| AudioFeatures {
+                uri: TrackUri::Track(f.id),
+                key: if f.key >= 0 { Some(f.key as u8) } else { None },
+                major: f.mode == 1,
+                tempo: f.tempo,
+                energy: f.energy,
+            }));
+        }
+        Ok(features)
+    }
+
+    fn get_track_availability(&self,
+                              tracks: &[TrackUri],
+                              market: &str) -> Result<Vec<TrackAvailability>, failure::Error> {
+        let track_ids: Vec<&str> = tracks.iter().filter_map(|t| match *t {
+            TrackUri::Track(ref id) => Some(id.as_str()),
+            _ => None,
+        }).collect();
+        let mut availability = Vec::new();
+        // Spotify will only return 50 tracks per request
+        for chunk in track_ids.chunks(50) {
+            let url = format!("{}/tracks?ids={}&market={}", API_URL, chunk.join(","), market);
+            let results: MarketTracksResponse = self.get_json(&url)?;
+            availability.extend(results.tracks.into_iter().flatten().map(|track| {
+                let playable = track.is_playable;
+                let mut metadata = get_item_metadata(track.item);
+                // The track that was asked for is reported, along with the
+                // release that plays in its place
+                let relinked = track.linked_from.map(|original| {
+                    let relinked = metadata.uri.clone();
+                    metadata.uri = TrackUri::parse(&original.uri);
+                    relinked
+                });
+                TrackAvailability { track: metadata, playable: playable, relinked: relinked }
+            }));
+        }
+        Ok(availability)
+    }
+}
+
+/// Get the ID of the user that the access token belongs to. This doesn't use
+/// `Spotify::current_user` since that expects private fields such as the
+/// user's email, which aren't available with our scopes
+fn get_current_user_id(access_token: &str) -> Result<String, failure::Error> {
+    let mut response = user_agent::client()
+        .get(&format!("{}/me", API_URL))
+        .header(Authorization(Bearer { token: access_token.to_owned() }))
+        .send()?;
+    if !response.status().is_success() {
+        return Err(failure::err_msg(format!(
+            "Failed to get current user, http code: {}", response.status()
+        )));
+    }
+    let user: Value = response.json()?;
+    match user["id"].as_str() {
+        Some(id) => Ok(id.to_owned()),
+        None => Err(failure::err_msg("Current user has no ID")),
     }
 }
 
-/// Converts playlist track into just the IDs
-fn get_track_ids(result: Vec<PlaylistTrack>) -> Vec<String> {
-    result.iter().map(|x| x.clone().track.id).collect()
+/// Turn unsuccessful responses into an error
+fn check_status(response: reqwest::Response,
+                url: &str) -> Result<reqwest::Response, failure::Error> {
+    if !response.status().is_success() {
+        return Err(failure::err_msg(format!(
+            "Request to {} failed, http code: {}", url, response.status()
+        )));
+    }
+    Ok(response)
+}
+
+/// Converts a track into the metadata we care about
+fn get_track_metadata(track: &FullTrack) -> TrackMetadata {
+    TrackMetadata {
+        uri: TrackUri::Track(track.id.to_owned()),
+        name: track.name.to_owned(),
+        artists: track.artists.iter().map(|a| a.name.to_owned()).collect(),
+        album: track.album.name.to_owned(),
+        duration_ms: u64::from(track.duration_ms),
+        image_url: track.album.images.first().map(|image| image.url.to_owned()),
+    }
+}
+
+/// Converts a playlist item into the metadata we care about. Episodes use
+/// the show in place of the album and its publisher in place of the artists
+fn get_item_metadata(item: Item) -> TrackMetadata {
+    let (artists, album, images) = match item.show {
+        Some(show) => (vec![show.publisher], show.name, show.images),
+        None => {
+            let artists = item.artists.into_iter().filter_map(|a| a.name).collect();
+            match item.album {
+                Some(album) => (artists, album.name.unwrap_or_default(), album.images),
+                None => (artists, String::new(), Vec::new()),
+            }
+        },
+    };
+    TrackMetadata {
+        uri: TrackUri::parse(&item.uri),
+        name: item.name.unwrap_or_default(),
+        artists: artists,
+        album: album,
+        duration_ms: item.duration_ms.unwrap_or_default(),
+        image_url: images.into_iter().next().map(|image| image.url),
+    }
 }
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use playlist::{PlaylistAPI,TrackMetadata};
+use track_uri::TrackUri;
+
+/// Order tracks so that the same artist doesn't play twice within `spacing`
+/// tracks, eg. a spacing of 1 keeps an artist's tracks from being next to
+/// each other. Tracks keep their order where they can. When no track fits,
+/// eg. when most of the tracks are by one artist, the track whose artist
+/// played longest ago is used.
+///
+/// # Arguments
+///
+/// * `tracks` - The tracks to order
+/// * `spacing` - The fewest tracks there should be between two tracks by the
+/// same artist
+pub fn spread_artists(tracks: &[TrackMetadata], spacing: usize) -> Vec<TrackUri> {
+    let mut remaining: Vec<&TrackMetadata> = tracks.iter().collect();
+    // The last position each artist was placed at
+    let mut last_played: HashMap<String, usize> = HashMap::new();
+    let mut ordered = Vec::with_capacity(tracks.len());
+    while !remaining.is_empty() {
+        let position = ordered.len();
+        let last_played_at = |track: &TrackMetadata| {
+            track.artists.iter()
+                .filter_map(|artist| last_played.get(&artist.to_lowercase()))
+                .max()
+                .cloned()
+        };
+        let fits = |track: &&TrackMetadata| match last_played_at(track) {
+            Some(last) => position - last > spacing,
+            None => true,
+        };
+        let next = remaining.iter().position(fits).unwrap_or_else(|| {
+            // Every track has an artist that played too recently
+            let (index, _) = remaining.iter()
+                .enumerate()
+                .min_by_key(|&(_, track)| last_played_at(track))
+                .unwrap();
+            debug!("Couldn't keep {} apart from the same artist", remaining[index].name);
+            index
+        });
+        let track = remaining.remove(next);
+        for artist in &track.artists {
+            last_played.insert(artist.to_lowercase(), position);
+        }
+        ordered.push(track.uri.clone());
+    }
+    ordered
+}
+
+/// Spread out the tracks of each artist, see `spread_artists`. Tracks
+/// without metadata, eg. local files, are treated as having no artist.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance to look up the tracks with
+/// * `tracks` - The tracks to order
+/// * `spacing` - The fewest tracks there should be between two tracks by the
+/// same artist
+pub fn spread_tracks<E>(playlist_api: &PlaylistAPI<E>,
+                        tracks: &[TrackUri],
+                        spacing: usize) -> Result<Vec<TrackUri>, E> {
+    let metadata = playlist_api.get_tracks_metadata(tracks)?;
+    let tracks: Vec<TrackMetadata> = tracks.iter()
+        .map(|track| {
+            metadata.iter().find(|m| m.uri == *track).cloned().unwrap_or_else(|| TrackMetadata {
+                uri: track.clone(),
+                name: track.to_string(),
+                artists: Vec::new(),
+                album: String::new(),
+                duration_ms: 0,
+                image_url: None,
+            })
+        })
+        .collect();
+    Ok(spread_artists(&tracks, spacing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(id: &str, artist: &str) -> TrackMetadata {
+        TrackMetadata {
+            uri: TrackUri::Track(id.to_string()),
+            name: id.to_string(),
+            artists: vec![artist.to_string()],
+            album: String::new(),
+            duration_ms: 0,
+            image_url: None,
+        }
+    }
+
+    fn ids(tracks: Vec<TrackUri>) -> Vec<String> {
+        tracks.iter().map(|track| track.id().unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn spread_artists_keeps_artists_apart() {
+        // Given
+        let tracks = vec![
+            track("a1", "A"), track("a2", "A"), track("b1", "B"),
+            track("c1", "C"), track("a3", "a"), track("d1", "D"),
+        ];
+        // When
+        let ordered = spread_artists(&tracks, 2);
+        // Then
+        assert_eq!(vec!["a1", "b1", "c1", "a2", "d1", "a3"], ids(ordered));
+    }
+
+    #[test]
+    fn spread_artists_does_its_best_when_artists_cant_be_kept_apart() {
+        // Given
+        let tracks = vec![track("a1", "A"), track("a2", "A"), track("b1", "B"), track("a3", "A")];
+        // When
+        let ordered = spread_artists(&tracks, 1);
+        // Then
+        assert_eq!(vec!["a1", "b1", "a2", "a3"], ids(ordered));
+    }
+}
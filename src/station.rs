@@ -0,0 +1,147 @@
+use std::error::Error;
+use std::fmt;
+
+use user_agent;
+
+extern crate serde_json;
+use self::serde_json::Value;
+
+const RADIO_PARADISE_URL: &str = "https://api.radioparadise.com/api/now_playing";
+
+/// A song that a station played, as the station names it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayedSong {
+    pub artist: String,
+    pub title: String,
+}
+
+impl fmt::Display for PlayedSong {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} - {}", self.artist, self.title)
+    }
+}
+
+/// A radio station that says what it's playing. Each station's API is
+/// different, so each one has its own implementation
+pub trait StationSource {
+    /// Get the song that's playing now, or `None` between songs, eg. during
+    /// talk or ads
+    fn now_playing(&self) -> Result<Option<PlayedSong>, Box<Error>>;
+}
+
+/// Get a string from JSON, treating missing and empty values the same
+fn json_string(value: &Value, pointer: &str) -> Option<String> {
+    value.pointer(pointer)
+        .and_then(Value::as_str)
+        .map(|s| s.trim().to_owned())
+        .filter(|s| !s.is_empty())
+}
+
+/// Get the song from a JSON response, if both the artist and title are set
+fn song_from_json(value: &Value, artist_pointer: &str, title_pointer: &str) -> Option<PlayedSong> {
+    match (json_string(value, artist_pointer), json_string(value, title_pointer)) {
+        (Some(artist), Some(title)) => Some(PlayedSong { artist: artist, title: title }),
+        _ => None,
+    }
+}
+
+fn get_json(url: &str) -> Result<Value, Box<Error>> {
+    Ok(user_agent::client().get(url).send()?.error_for_status()?.json()?)
+}
+
+/// Radio Paradise, see https://radioparadise.com
+pub struct RadioParadise {
+    /// The channel to follow, eg. 0 for the main mix
+    pub channel: u32,
+}
+
+impl StationSource for RadioParadise {
+    fn now_playing(&self) -> Result<Option<PlayedSong>, Box<Error>> {
+        let value = get_json(&format!("{}?chan={}", RADIO_PARADISE_URL, self.channel))?;
+        Ok(song_from_json(&value, "/artist", "/title"))
+    }
+}
+
+/// Any station with a JSON feed of what's playing. The fields are given as
+/// JSON pointers, eg. `/now/artist`
+pub struct JsonFeed {
+    pub url: String,
+    pub artist_pointer: String,
+    pub title_pointer: String,
+}
+
+impl StationSource for JsonFeed {
+    fn now_playing(&self) -> Result<Option<PlayedSong>, Box<Error>> {
+        let value = get_json(&self.url)?;
+        Ok(song_from_json(&value, &self.artist_pointer, &self.title_pointer))
+    }
+}
+
+/// Remembers the last song a station played so that a song is only
+/// reported once however many times the station is asked
+#[derive(Debug, Default)]
+pub struct NowPlayingPoller {
+    last: Option<PlayedSong>,
+}
+
+impl NowPlayingPoller {
+    /// Ask the station what's playing, returning the song only if it
+    /// started since the last poll
+    ///
+    /// # Arguments
+    ///
+    /// * `station` - The station to ask
+    pub fn poll(&mut self, station: &StationSource) -> Result<Option<PlayedSong>, Box<Error>> {
+        let playing = station.now_playing()?;
+        if playing.is_none() || playing == self.last {
+            return Ok(None);
+        }
+        self.last = playing.clone();
+        Ok(playing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Plays each of the songs in turn, one per request
+    struct FakeStation {
+        songs: RefCell<Vec<Option<PlayedSong>>>,
+    }
+
+    impl StationSource for FakeStation {
+        fn now_playing(&self) -> Result<Option<PlayedSong>, Box<Error>> {
+            Ok(self.songs.borrow_mut().remove(0))
+        }
+    }
+
+    fn song(title: &str) -> Option<PlayedSong> {
+        Some(PlayedSong { artist: "Robyn".to_string(), title: title.to_string() })
+    }
+
+    #[test]
+    fn poll_only_returns_new_songs() {
+        // Given
+        let station = FakeStation {
+            songs: RefCell::new(vec![song("a"), song("a"), None, song("b"), song("a")]),
+        };
+        let mut poller = NowPlayingPoller::default();
+        // When
+        let polled: Vec<Option<PlayedSong>> = (0..5).map(|_| poller.poll(&station).unwrap()).collect();
+        // Then
+        assert_eq!(vec![song("a"), None, None, song("b"), song("a")], polled);
+    }
+
+    #[test]
+    fn song_from_json_needs_artist_and_title() {
+        // Given
+        let value: Value = serde_json::from_str(
+            r#"{"now": {"artist": "Robyn ", "title": "Hang With Me"}, "next": {"artist": "", "title": "x"}}"#
+        ).unwrap();
+        // Then
+        assert_eq!(song("Hang With Me"), song_from_json(&value, "/now/artist", "/now/title"));
+        assert_eq!(None, song_from_json(&value, "/next/artist", "/next/title"));
+    }
+}
@@ -0,0 +1,563 @@
+use std::collections::{HashMap,HashSet};
+use std::error::Error;
+use std::fmt;
+
+use csv_to_playlist::{has_track_id,Song};
+use playlist::PlaylistAPI;
+use track_uri::TrackUri;
+
+/// The most items Spotify will insert in one request
+const INSERT_BATCH_SIZE: usize = 100;
+
+/// A single change needed to make a playlist match a CSV
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Edit {
+    /// Remove the item at a position in the playlist as it was before any
+    /// edits. All removals are made first
+    Remove { position: usize, track: TrackUri },
+    /// Move an item in front of the item at `insert_before`
+    Move { from: usize, insert_before: usize, track: TrackUri },
+    /// Add an item at a position
+    Insert { position: usize, track: TrackUri },
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Edit::Remove { position, ref track } => write!(f, "-{} {}", position + 1, track),
+            Edit::Move { from, insert_before, ref track } => {
+                write!(f, "~{} {} (before {})", from + 1, track, insert_before + 1)
+            },
+            Edit::Insert { position, ref track } => write!(f, "+{} {}", position + 1, track),
+        }
+    }
+}
+
+/// Limits on how much of a playlist a sync can change, so that an empty or
+/// broken CSV can't wipe out a playlist. Limits set to `None` aren't checked
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct ChangeLimits {
+    /// The most tracks that can be removed
+    pub max_removals: Option<usize>,
+    /// The most of the playlist's current tracks, as a percentage, that can be
+    /// removed or moved
+    pub max_changes_percent: Option<f64>,
+}
+
+/// Sync error enum for different errors when syncing a playlist
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SyncError<E> {
+    /// Generic playlist error from API
+    APIError(E),
+    /// The error when the sync would change more than the limits allow
+    TooManyChanges(TooManyChanges),
+}
+
+/// An error when a sync would change more of the playlist than allowed
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TooManyChanges {
+    removals: usize,
+    moves: usize,
+    /// The number of tracks in the playlist before syncing
+    total: usize,
+}
+
+impl Error for TooManyChanges {
+    fn description(&self) -> &str {
+        "Sync would change more of the playlist than allowed"
+    }
+}
+
+impl fmt::Display for TooManyChanges {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} of {} tracks would be removed and {} moved",
+               self.description(), self.removals, self.total, self.moves)
+    }
+}
+
+/// Check that the edits are within the limits before they're applied
+///
+/// # Arguments
+///
+/// * `current_len` - The number of tracks in the playlist before syncing
+/// * `edits` - The edits from `compute_edits`
+/// * `limits` - How much the playlist can be changed
+pub fn check_change_limits(current_len: usize,
+                           edits: &[Edit],
+                           limits: &ChangeLimits) -> Result<(), TooManyChanges> {
+    let removals = edits.iter().filter(|edit| matches!(**edit, Edit::Remove { .. })).count();
+    let moves = edits.iter().filter(|edit| matches!(**edit, Edit::Move { .. })).count();
+    let error = TooManyChanges { removals: removals, moves: moves, total: current_len };
+    if let Some(max_removals) = limits.max_removals {
+        if removals > max_removals {
+            return Err(error);
+        }
+    }
+    // Adding to an empty playlist doesn't put any tracks at risk
+    if let (Some(max_percent), true) = (limits.max_changes_percent, current_len > 0) {
+        if (removals + moves) as f64 * 100.0 / current_len as f64 > max_percent {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+/// Get the tracks a synced playlist should contain, in CSV order. Songs
+/// without a usable ID are skipped and only the first of any duplicates is
+/// kept.
+///
+/// # Arguments
+///
+/// * `songs` - A slice of the songs in the order they were read
+pub fn get_sync_target(songs: &[Song]) -> Vec<TrackUri> {
+    let mut seen = HashSet::new();
+    songs.iter()
+        .filter(|song| has_track_id(song))
+        .map(|song| TrackUri::parse(&song.song_id))
+        .filter(|track| track.is_addable() && seen.insert(track.clone()))
+        .collect()
+}
+
+/// Get the positions in `indexes` that make up its longest increasing
+/// subsequence
+pub fn longest_increasing_subsequence(indexes: &[usize]) -> HashSet<usize> {
+    // `tails[i]` is the position of the smallest value that ends an
+    // increasing subsequence of length `i + 1`
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; indexes.len()];
+    for (position, &value) in indexes.iter().enumerate() {
+        let length = match tails.binary_search_by(|&t| indexes[t].cmp(&value)) {
+            Ok(length) | Err(length) => length,
+        };
+        if length > 0 {
+            previous[position] = Some(tails[length - 1]);
+        }
+        if length == tails.len() {
+            tails.push(position);
+        } else {
+            tails[length] = position;
+        }
+    }
+    let mut result = HashSet::new();
+    let mut position = tails.last().cloned();
+    while let Some(p) = position {
+        result.insert(p);
+        position = previous[p];
+    }
+    result
+}
+
+/// Work out the fewest edits that turn `current` into `target`. Tracks in
+/// the longest common subsequence of the two are left where they are so that
+/// their added dates are kept. Other tracks that are in both are moved
+/// rather than added again.
+///
+/// # Arguments
+///
+/// * `current` - The items in the playlist, in order
+/// * `target` - The items the playlist should have, without duplicates
+pub fn compute_edits(current: &[TrackUri], target: &[TrackUri]) -> Vec<Edit> {
+    let target_indexes: HashMap<&TrackUri, usize> = target.iter()
+        .enumerate()
+        .map(|(i, track)| (track, i))
+        .collect();
+    let mut edits = Vec::new();
+    // Remove tracks that shouldn't be there, along with any duplicates
+    let mut state: Vec<TrackUri> = Vec::new();
+    let mut kept: HashSet<&TrackUri> = HashSet::new();
+    for (position, track) in current.iter().enumerate() {
+        if target_indexes.contains_key(track) && kept.insert(track) {
+            state.push(track.clone());
+        } else {
+            edits.push(Edit::Remove { position: position, track: track.clone() });
+        }
+    }
+    // Since both are now free of duplicates, their longest common
+    // subsequence is the longest run of tracks already in target order
+    let state_indexes: Vec<usize> = state.iter().map(|track| target_indexes[track]).collect();
+    let in_place: HashSet<TrackUri> = longest_increasing_subsequence(&state_indexes)
+        .into_iter()
+        .map(|position| state[position].clone())
+        .collect();
+    // Place every other track directly after the track before it in the
+    // target. Tracks before it are always in the right order by then.
+    for (i, track) in target.iter().enumerate() {
+        if in_place.contains(track) {
+            continue;
+        }
+        let insert_before = if i == 0 {
+            0
+        } else {
+            state.iter().position(|t| *t == target[i - 1]).map_or(0, |p| p + 1)
+        };
+        match state.iter().position(|t| t == track) {
+            Some(from) => {
+                // Moving in front of itself or the next track does nothing
+                if from != insert_before && from + 1 != insert_before {
+                    edits.push(Edit::Move {
+                        from: from,
+                        insert_before: insert_before,
+                        track: track.clone(),
+                    });
+                }
+                let moved = state.remove(from);
+                let position = if from < insert_before { insert_before - 1 } else { insert_before };
+                state.insert(position, moved);
+            },
+            None => {
+                edits.push(Edit::Insert { position: insert_before, track: track.clone() });
+                state.insert(insert_before, track.clone());
+            },
+        }
+    }
+    edits
+}
+
+/// Make the edits to the playlist. Removals are made together first, then
+/// each move and insert in order. Inserts at adjacent positions are made
+/// together, up to 100 at a time, so that filling a playlist doesn't take a
+/// request for each track.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the playlist should be changed
+/// * `playlist_id` - The playlist ID to be changed. This is the ID and *not*
+/// the name.
+/// * `edits` - The edits from `compute_edits`
+pub fn apply_edits<E>(playlist_api: &PlaylistAPI<E>,
+                      playlist_id: &str,
+                      edits: &[Edit]) -> Result<(), E> {
+    let removals: Vec<(TrackUri, usize)> = edits.iter()
+        .filter_map(|edit| match *edit {
+            Edit::Remove { position, ref track } => Some((track.clone(), position)),
+            _ => None,
+        })
+        .collect();
+    if !removals.is_empty() {
+        playlist_api.remove_tracks_at_positions(playlist_id, &removals)?;
+    }
+    // The inserts waiting to be made, and where the first one goes
+    let mut batch: Vec<TrackUri> = Vec::new();
+    let mut batch_position = 0;
+    for edit in edits {
+        match *edit {
+            Edit::Remove { .. } => {},
+            Edit::Move { from, insert_before, .. } => {
+                flush_inserts(playlist_api, playlist_id, &mut batch, batch_position)?;
+                playlist_api.move_track_in_playlist(playlist_id, from, insert_before)?;
+            },
+            Edit::Insert { position, ref track } => {
+                let follows = !batch.is_empty() && position == batch_position + batch.len();
+                if !follows || batch.len() == INSERT_BATCH_SIZE {
+                    flush_inserts(playlist_api, playlist_id, &mut batch, batch_position)?;
+                    batch_position = position;
+                }
+                batch.push(track.clone());
+            },
+        }
+    }
+    flush_inserts(playlist_api, playlist_id, &mut batch, batch_position)
+}
+
+/// Insert a batch of tracks that go one after another, see `apply_edits`
+fn flush_inserts<E>(playlist_api: &PlaylistAPI<E>,
+                    playlist_id: &str,
+                    batch: &mut Vec<TrackUri>,
+                    position: usize) -> Result<(), E> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    playlist_api.insert_tracks_into_playlist(playlist_id, batch, position)?;
+    batch.clear();
+    Ok(())
+}
+
+/// Add the tracks that the edits remove to a quarantine playlist, so that
+/// they can be recovered if the CSV was wrong. Tracks already in the
+/// quarantine playlist and items that can't be added, such as local files,
+/// are skipped. Returns the tracks that were added.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the quarantine playlist is
+/// * `quarantine_id` - The quarantine playlist ID. This is the ID and *not*
+/// the name.
+/// * `edits` - The edits that will be made to the synced playlist
+pub fn quarantine_removals<E>(playlist_api: &PlaylistAPI<E>,
+                              quarantine_id: &str,
+                              edits: &[Edit]) -> Result<Vec<TrackUri>, E> {
+    let mut seen: HashSet<TrackUri> = playlist_api.get_track_ids_in_playlist(quarantine_id)?
+        .into_iter()
+        .collect();
+    let removed: Vec<TrackUri> = edits.iter()
+        .filter_map(|edit| match *edit {
+            Edit::Remove { ref track, .. } if track.is_addable() => Some(track.clone()),
+            _ => None,
+        })
+        .filter(|track| seen.insert(track.clone()))
+        .collect();
+    if !removed.is_empty() {
+        playlist_api.add_tracks_to_playlist(quarantine_id, &removed, None)?;
+    }
+    Ok(removed)
+}
+
+/// Make the playlist contain exactly the songs, in the same order, with as
+/// few changes as possible. Returns the edits that were made.
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance where the playlist should be changed
+/// * `playlist_id` - The playlist ID to be changed. This is the ID and *not*
+/// the name.
+/// * `songs` - A slice of the songs in the order they were read
+/// * `quarantine_id` - A playlist to move removed tracks into instead of
+/// deleting them outright, see `quarantine_removals`
+/// * `limits` - How much the playlist can be changed. Dry runs aren't limited
+/// so that the changes can be reviewed
+/// * `dry_run` - Work out the edits without changing anything
+pub fn sync_playlist<E>(playlist_api: &PlaylistAPI<E>,
+                        playlist_id: &str,
+                        songs: &[Song],
+                        quarantine_id: Option<&str>,
+                        limits: &ChangeLimits,
+                        dry_run: bool) -> Result<Vec<Edit>, SyncError<E>> {
+    let current = playlist_api.get_track_ids_in_playlist(
+        playlist_id
+    ).map_err(SyncError::APIError)?;
+    let edits = compute_edits(&current, &get_sync_target(songs));
+    if !dry_run {
+        check_change_limits(current.len(), &edits, limits).map_err(SyncError::TooManyChanges)?;
+        // Tracks are kept safe before anything is removed
+        if let Some(quarantine_id) = quarantine_id {
+            quarantine_removals(
+                playlist_api, quarantine_id, &edits
+            ).map_err(SyncError::APIError)?;
+        }
+        apply_edits(playlist_api, playlist_id, &edits).map_err(SyncError::APIError)?;
+    }
+    Ok(edits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_api::{FakeError,FakePlaylistAPI};
+    use playlist::{PlaylistError,Visibility};
+
+    use std::cell::RefCell;
+
+    /// A fake playlist that applies changes to its tracks. Tracks added to
+    /// any playlist go into the quarantine playlist
+    struct MockPlaylistAPI {
+        tracks: RefCell<Vec<TrackUri>>,
+        quarantined: RefCell<Vec<TrackUri>>,
+        /// The number of tracks in each insert
+        inserts: RefCell<Vec<usize>>,
+    }
+
+    impl MockPlaylistAPI {
+        fn new(tracks: Vec<TrackUri>) -> MockPlaylistAPI {
+            MockPlaylistAPI {
+                tracks: RefCell::new(tracks),
+                quarantined: RefCell::new(Vec::new()),
+                inserts: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl FakePlaylistAPI for MockPlaylistAPI {
+        #[allow(unused_variables)]
+        fn get_playlist_id(&self, playlist_name: &str) -> Result<String, PlaylistError<FakeError>> {
+            Ok("".to_string())
+        }
+
+        #[allow(unused_variables)]
+        fn create_playlist(&self, playlist_name: &str, visibility: Visibility) -> Result<String, FakeError> {
+            Ok("".to_string())
+        }
+
+        #[allow(unused_variables)]
+        fn add_tracks_to_playlist(&self, playlist_id: &str, tracks: &[TrackUri], position: Option<usize>) -> Result<(), FakeError> {
+            self.quarantined.borrow_mut().extend_from_slice(tracks);
+            Ok(())
+        }
+
+        fn get_track_ids_in_playlist(&self, playlist_id: &str) -> Result<Vec<TrackUri>, FakeError> {
+            if playlist_id == "quarantine" {
+                return Ok(self.quarantined.borrow().clone());
+            }
+            Ok(self.tracks.borrow().clone())
+        }
+
+        #[allow(unused_variables)]
+        fn insert_tracks_into_playlist(&self, playlist_id: &str, tracks: &[TrackUri], position: usize) -> Result<(), FakeError> {
+            self.inserts.borrow_mut().push(tracks.len());
+            let mut current = self.tracks.borrow_mut();
+            for (i, track) in tracks.iter().enumerate() {
+                current.insert(position + i, track.clone());
+            }
+            Ok(())
+        }
+
+        #[allow(unused_variables)]
+        fn remove_tracks_at_positions(&self, playlist_id: &str, occurrences: &[(TrackUri, usize)]) -> Result<(), FakeError> {
+            let mut positions: Vec<usize> = occurrences.iter().map(|&(_, p)| p).collect();
+            // Remove from the end so that earlier positions stay the same
+            positions.sort();
+            let mut current = self.tracks.borrow_mut();
+            for position in positions.into_iter().rev() {
+                current.remove(position);
+            }
+            Ok(())
+        }
+
+        #[allow(unused_variables)]
+        fn move_track_in_playlist(&self, playlist_id: &str, from: usize, insert_before: usize) -> Result<(), FakeError> {
+            let mut current = self.tracks.borrow_mut();
+            let track = current.remove(from);
+            let position = if from < insert_before { insert_before - 1 } else { insert_before };
+            current.insert(position, track);
+            Ok(())
+        }
+    }
+
+    fn tracks(ids: &str) -> Vec<TrackUri> {
+        ids.chars().map(|id| TrackUri::Track(format!("{:0<22}", id))).collect()
+    }
+
+    fn song(name: &str) -> Song {
+        Song { music: name.to_string(), song_id: format!("{:0<22}", name) }
+    }
+
+    /// Apply the edits to the playlist and check that it ends up as `target`
+    fn assert_syncs(current: &str, target: &str) -> Vec<Edit> {
+        let edits = compute_edits(&tracks(current), &tracks(target));
+        let api = MockPlaylistAPI::new(tracks(current));
+        apply_edits(&api, "id_123", &edits).unwrap();
+        assert_eq!(tracks(target), *api.tracks.borrow());
+        edits
+    }
+
+    #[test]
+    fn compute_edits_moves_as_few_tracks_as_possible() {
+        // When
+        let edits = assert_syncs("dabc", "abcd");
+        // Then
+        let expected = vec![Edit::Move {
+            from: 0,
+            insert_before: 4,
+            track: tracks("d").remove(0),
+        }];
+        assert_eq!(expected, edits);
+    }
+
+    #[test]
+    fn compute_edits_removes_and_inserts() {
+        // When
+        let edits = assert_syncs("axbyc", "abzc");
+        // Then
+        let removals = edits.iter().filter(|e| matches!(**e, Edit::Remove { .. })).count();
+        assert_eq!(2, removals);
+        assert_eq!(3, edits.len());
+    }
+
+    #[test]
+    fn compute_edits_handles_reordering_and_duplicates() {
+        assert_syncs("edcbaa", "abcde");
+        assert_syncs("", "abc");
+        assert_syncs("abc", "");
+        assert_syncs("cxaybz", "zyxcba");
+    }
+
+    #[test]
+    fn apply_edits_inserts_adjacent_tracks_together() {
+        // Given
+        let target: Vec<TrackUri> = (0..250).map(|i| TrackUri::Track(format!("t{}", i))).collect();
+        let api = MockPlaylistAPI::new(Vec::new());
+        let edits = compute_edits(&[], &target);
+        // When
+        apply_edits(&api, "id_123", &edits).unwrap();
+        // Then
+        assert_eq!(target, *api.tracks.borrow());
+        assert_eq!(vec![100, 100, 50], *api.inserts.borrow());
+        // Inserts that aren't next to each other are made separately
+        assert_syncs("axbyc", "abzc");
+        assert_syncs("ac", "xaybzc");
+    }
+
+    #[test]
+    fn compute_edits_with_no_changes() {
+        assert!(assert_syncs("abc", "abc").is_empty());
+    }
+
+    #[test]
+    fn get_sync_target_keeps_csv_order() {
+        // Given
+        let no_id = Song { music: "b".to_string(), song_id: "true".to_string() };
+        let local = Song { music: "x".to_string(), song_id: "spotify:local:x".to_string() };
+        let songs = vec![song("c"), no_id, song("a"), song("c"), local];
+        // When
+        let result = get_sync_target(&songs);
+        // Then
+        assert_eq!(tracks("ca"), result);
+    }
+
+    #[test]
+    fn sync_playlist_quarantines_removed_tracks() {
+        // Given
+        let api = MockPlaylistAPI::new(tracks("abcdb"));
+        api.quarantined.borrow_mut().extend(tracks("d"));
+        let songs = vec![song("a"), song("c")];
+        // When
+        sync_playlist(&api, "id_123", &songs, Some("quarantine"), &ChangeLimits::default(), false).unwrap();
+        // Then
+        assert_eq!(tracks("ac"), *api.tracks.borrow());
+        // Duplicates and tracks already quarantined are only kept once
+        assert_eq!(tracks("db"), *api.quarantined.borrow());
+    }
+
+    #[test]
+    fn sync_playlist_dry_run_quarantines_nothing() {
+        // Given
+        let api = MockPlaylistAPI::new(tracks("ab"));
+        // When
+        let limits = ChangeLimits { max_removals: Some(0), max_changes_percent: None };
+        let edits = sync_playlist(&api, "id_123", &[song("a")], Some("quarantine"), &limits, true).unwrap();
+        // Then
+        assert_eq!(1, edits.len());
+        assert_eq!(tracks("ab"), *api.tracks.borrow());
+        assert!(api.quarantined.borrow().is_empty());
+    }
+
+    #[test]
+    fn sync_playlist_stops_when_over_the_limits() {
+        // Given
+        let api = MockPlaylistAPI::new(tracks("abcd"));
+        let limits = ChangeLimits { max_removals: Some(2), max_changes_percent: None };
+        // When
+        let result = sync_playlist(&api, "id_123", &[song("a")], Some("quarantine"), &limits, false);
+        // Then
+        let expected = TooManyChanges { removals: 3, moves: 0, total: 4 };
+        assert_eq!(Err(SyncError::TooManyChanges(expected)), result);
+        assert_eq!(tracks("abcd"), *api.tracks.borrow());
+        assert!(api.quarantined.borrow().is_empty());
+    }
+
+    #[test]
+    fn check_change_limits_counts_removals_and_moves() {
+        // Given
+        let edits = compute_edits(&tracks("abcd"), &tracks("bace"));
+        let limits = |max_removals, max_changes_percent| ChangeLimits {
+            max_removals: max_removals,
+            max_changes_percent: max_changes_percent,
+        };
+        // Then
+        assert!(check_change_limits(4, &edits, &limits(Some(1), Some(50.0))).is_ok());
+        assert!(check_change_limits(4, &edits, &limits(Some(0), None)).is_err());
+        assert!(check_change_limits(4, &edits, &limits(None, Some(49.0))).is_err());
+        // Filling an empty playlist is always allowed
+        let edits = compute_edits(&[], &tracks("abcd"));
+        assert!(check_change_limits(0, &edits, &limits(Some(0), Some(0.0))).is_ok());
+    }
+}
@@ -0,0 +1,348 @@
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::thread;
+use std::time::Duration;
+
+use csv_to_playlist::{parse_csv,get_tracks_to_add,Song};
+use diff::format_track;
+use links::find_track_ids;
+use playlist::PlaylistAPI;
+use track_uri::TrackUri;
+use user_agent;
+
+extern crate reqwest;
+use self::reqwest::Client;
+
+/// How long to wait for new messages in each request to Telegram
+const POLL_TIMEOUT_SECS: u64 = 30;
+
+/// An error reported by the Telegram API
+#[derive(Debug, Clone, PartialEq)]
+pub struct TelegramError {
+    description: String,
+}
+
+impl Error for TelegramError {
+    fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl fmt::Display for TelegramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Telegram error: {}", self.description)
+    }
+}
+
+/// The wrapper around every Telegram API result
+#[derive(Deserialize)]
+struct TelegramResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
+
+/// An incoming update, see https://core.telegram.org/bots/api#update
+#[derive(Debug, Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    chat: Chat,
+    text: Option<String>,
+    caption: Option<String>,
+    document: Option<Document>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Document {
+    file_id: String,
+    file_name: Option<String>,
+    mime_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TelegramFile {
+    file_path: Option<String>,
+}
+
+/// What a message is asking to be added to the playlist
+#[derive(Debug, PartialEq)]
+enum Contribution {
+    /// The file ID of an uploaded CSV
+    Csv(String),
+    /// Track IDs from Spotify links in the message
+    Tracks(Vec<String>),
+}
+
+/// Work out what should be added from a message. Links are found in the
+/// text of normal or forwarded messages and in file captions.
+fn get_contribution(message: &Message) -> Option<Contribution> {
+    if let Some(ref document) = message.document {
+        let is_csv = document.file_name.as_ref().is_some_and(|n| n.to_lowercase().ends_with(".csv")) ||
+            document.mime_type.as_ref().is_some_and(|m| m == "text/csv");
+        if is_csv {
+            return Some(Contribution::Csv(document.file_id.to_owned()));
+        }
+    }
+    let text = message.text.as_ref().or(message.caption.as_ref())?;
+    let track_ids = find_track_ids(text);
+    if track_ids.is_empty() {
+        None
+    } else {
+        Some(Contribution::Tracks(track_ids))
+    }
+}
+
+/// A client for the Telegram Bot API
+struct TelegramBot {
+    token: String,
+    client: Client,
+}
+
+impl TelegramBot {
+    fn new(token: &str) -> Result<TelegramBot, Box<Error>> {
+        let client = user_agent::client_builder()
+            .timeout(Duration::from_secs(POLL_TIMEOUT_SECS + 10))
+            .build()?;
+        Ok(TelegramBot {
+            token: token.to_owned(),
+            client: client,
+        })
+    }
+
+    fn method_url(&self, method: &str) -> String {
+        format!("https://api.telegram.org/bot{}/{}", self.token, method)
+    }
+
+    fn unwrap_response<T>(response: TelegramResponse<T>) -> Result<T, Box<Error>> {
+        match response.result {
+            Some(result) if response.ok => Ok(result),
+            _ => Err(Box::new(TelegramError {
+                description: response.description.unwrap_or_default(),
+            })),
+        }
+    }
+
+    /// Wait for new updates after `offset`
+    fn get_updates(&self, offset: i64) -> Result<Vec<Update>, Box<Error>> {
+        let response = self.client.get(&self.method_url("getUpdates"))
+            .query(&[
+                ("offset", offset.to_string()),
+                ("timeout", POLL_TIMEOUT_SECS.to_string()),
+            ])
+            .send()?
+            .json()?;
+        TelegramBot::unwrap_response(response)
+    }
+
+    fn send_message(&self, chat_id: i64, text: &str) -> Result<(), Box<Error>> {
+        let response: TelegramResponse<Message> = self.client.post(&self.method_url("sendMessage"))
+            .form(&[("chat_id", chat_id.to_string()), ("text", text.to_owned())])
+            .send()?
+            .json()?;
+        TelegramBot::unwrap_response(response).map(|_| ())
+    }
+
+    fn download_file(&self, file_id: &str) -> Result<Vec<u8>, Box<Error>> {
+        let response = self.client.get(&self.method_url("getFile"))
+            .query(&[("file_id", file_id)])
+            .send()?
+            .json()?;
+        let file: TelegramFile = TelegramBot::unwrap_response(response)?;
+        let file_path = file.file_path.ok_or_else(|| TelegramError {
+            description: "File is not available".to_string(),
+        })?;
+        let url = format!("https://api.telegram.org/file/bot{}/{}", self.token, file_path);
+        let mut data = Vec::new();
+        self.client.get(&url).send()?.error_for_status()?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+/// Add songs to the playlist and describe what happened
+fn add_songs<E: fmt::Display>(playlist_api: &PlaylistAPI<E>,
+                              playlist_id: &str,
+                              playlist_name: &str,
+                              songs: &[Song]) -> String {
+    let track_ids = match get_tracks_to_add(playlist_api, playlist_id, songs) {
+        Ok(track_ids) => track_ids,
+        Err(e) => return format!("Could not read {}: {}", playlist_name, e),
+    };
+    if track_ids.is_empty() {
+        return "No new tracks to add.".to_string();
+    }
+    match playlist_api.add_tracks_to_playlist(playlist_id, &track_ids, None) {
+        Ok(()) => format!("Added {} tracks to {}.", track_ids.len(), playlist_name),
+        Err(e) => format!("Could not add tracks: {}", e),
+    }
+}
+
+/// Add the songs from a message to the playlist, returning the reply
+fn handle_contribution<E: fmt::Display>(bot: &TelegramBot,
+                                        playlist_api: &PlaylistAPI<E>,
+                                        playlist_id: &str,
+                                        playlist_name: &str,
+                                        contribution: Contribution) -> String {
+    match contribution {
+        Contribution::Csv(file_id) => {
+            let songs = bot.download_file(&file_id).and_then(|data| parse_csv(&data[..]));
+            match songs {
+                Ok(songs) => add_songs(playlist_api, playlist_id, playlist_name, &songs),
+                Err(e) => format!("Could not read CSV: {}", e),
+            }
+        },
+        Contribution::Tracks(track_ids) => {
+            // Look up the tracks so that unknown links are skipped and the
+            // reply can name them
+            let track_ids: Vec<TrackUri> = track_ids.into_iter().map(TrackUri::Track).collect();
+            let tracks = match playlist_api.get_tracks_metadata(&track_ids) {
+                Ok(tracks) => tracks,
+                Err(e) => return format!("Could not find tracks: {}", e),
+            };
+            if tracks.is_empty() {
+                return "Could not find those tracks.".to_string();
+            }
+            let songs: Vec<Song> = tracks.iter()
+                .map(|t| Song { music: format_track(t), song_id: t.uri.to_string() })
+                .collect();
+            let names: Vec<&str> = songs.iter().map(|s| s.music.as_str()).collect();
+            format!("{}\n{}", add_songs(playlist_api, playlist_id, playlist_name, &songs), names.join("\n"))
+        },
+    }
+}
+
+/// Run a Telegram bot that adds songs from Spotify links or CSV files sent
+/// to it to a playlist. This runs until the process is stopped.
+///
+/// # Arguments
+///
+/// * `login` - Gets the instance where the tracks should be added. It's
+/// called for each batch of messages so that the token doesn't expire
+/// * `playlist_id` - The playlist ID to be added to
+/// * `playlist_name` - The name of the playlist, used in replies
+/// * `bot_token` - The token from @BotFather
+/// * `allowed_chats` - Chat IDs that can use the bot. All chats are allowed
+/// when this is empty
+pub fn run_bot<E, A, L>(login: L,
+                        playlist_id: &str,
+                        playlist_name: &str,
+                        bot_token: &str,
+                        allowed_chats: &[i64]) -> Result<(), Box<Error>>
+    where E: fmt::Display, A: PlaylistAPI<E>, L: Fn() -> A {
+    let bot = TelegramBot::new(bot_token)?;
+    let mut offset = 0;
+    loop {
+        let updates = match bot.get_updates(offset) {
+            Ok(updates) => updates,
+            Err(e) => {
+                eprintln!("Could not get updates: {}", e);
+                thread::sleep(Duration::from_secs(5));
+                continue;
+            },
+        };
+        if updates.is_empty() {
+            continue;
+        }
+        // Log in again each time so that the cached token is refreshed
+        let playlist_api = login();
+        for update in updates {
+            // Confirm the update so that it's not received again
+            offset = update.update_id + 1;
+            let message = match update.message {
+                Some(message) => message,
+                None => continue,
+            };
+            let chat_id = message.chat.id;
+            if !allowed_chats.is_empty() && !allowed_chats.contains(&chat_id) {
+                continue;
+            }
+            let contribution = match get_contribution(&message) {
+                Some(contribution) => contribution,
+                None => continue,
+            };
+            let reply = handle_contribution(
+                &bot, &playlist_api, playlist_id, playlist_name, contribution
+            );
+            println!("{}", reply);
+            if let Err(e) = bot.send_message(chat_id, &reply) {
+                eprintln!("Could not reply to {}: {}", chat_id, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate serde_json;
+
+    fn parse_message(json: &str) -> Message {
+        let response: TelegramResponse<Vec<Update>> = serde_json::from_str(json).unwrap();
+        let mut updates = response.result.unwrap();
+        updates.remove(0).message.unwrap()
+    }
+
+    #[test]
+    fn get_contribution_finds_links_in_forwarded_message() {
+        // Given
+        let message = parse_message(r#"{"ok": true, "result": [{
+            "update_id": 1,
+            "message": {
+                "message_id": 2,
+                "chat": {"id": -100, "type": "group"},
+                "forward_from": {"id": 3, "is_bot": false, "first_name": "A"},
+                "text": "listen https://open.spotify.com/track/abc123?si=x"
+            }
+        }]}"#);
+        // When
+        let result = get_contribution(&message);
+        // Then
+        assert_eq!(Some(Contribution::Tracks(vec!["abc123".to_string()])), result);
+    }
+
+    #[test]
+    fn get_contribution_finds_csv_upload() {
+        // Given
+        let message = parse_message(r#"{"ok": true, "result": [{
+            "update_id": 1,
+            "message": {
+                "message_id": 2,
+                "chat": {"id": 5, "type": "private"},
+                "document": {"file_id": "file1", "file_name": "Songs.CSV"},
+                "caption": "spotify:track:ignored"
+            }
+        }]}"#);
+        // When
+        let result = get_contribution(&message);
+        // Then
+        assert_eq!(Some(Contribution::Csv("file1".to_string())), result);
+    }
+
+    #[test]
+    fn get_contribution_ignores_other_messages() {
+        // Given
+        let message = parse_message(r#"{"ok": true, "result": [{
+            "update_id": 1,
+            "message": {
+                "message_id": 2,
+                "chat": {"id": 5, "type": "private"},
+                "text": "hello"
+            }
+        }]}"#);
+        // When
+        let result = get_contribution(&message);
+        // Then
+        assert_eq!(None, result);
+    }
+}
@@ -0,0 +1,115 @@
+use std::time::{Duration, Instant};
+
+/// How long one stage of an import took
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageTiming {
+    /// The name of the stage, eg. `parse`
+    pub name: &'static str,
+    /// How long the stage ran for
+    pub duration: Duration,
+    /// How many items the stage ended up with, eg. rows parsed
+    pub items: usize,
+    /// How many requests the stage made to Spotify
+    pub requests: usize,
+}
+
+/// Times a single stage, see `Stopwatch::stop`
+pub struct Stopwatch {
+    started: Instant,
+    requests: usize,
+}
+
+impl Stopwatch {
+    /// Start timing a stage
+    ///
+    /// # Arguments
+    ///
+    /// * `requests_made` - How many requests have been made so far
+    pub fn start(requests_made: usize) -> Stopwatch {
+        Stopwatch { started: Instant::now(), requests: requests_made }
+    }
+
+    /// Finish timing a stage
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the stage
+    /// * `items` - How many items the stage ended up with
+    /// * `requests_made` - How many requests have been made so far
+    pub fn stop(self, name: &'static str, items: usize, requests_made: usize) -> StageTiming {
+        StageTiming {
+            name: name,
+            duration: self.started.elapsed(),
+            items: items,
+            requests: requests_made.saturating_sub(self.requests),
+        }
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Format the stages as a table with each stage's share of the total time,
+/// so that the slowest stage stands out
+///
+/// # Arguments
+///
+/// * `stages` - The stages in the order they ran
+pub fn format_breakdown(stages: &[StageTiming]) -> String {
+    let total: Duration = stages.iter().map(|stage| stage.duration).sum();
+    let name_width = stages.iter().map(|stage| stage.name.len()).max().unwrap_or(0).max(5);
+    let mut table = format!(
+        "{:name_width$}  {:>10}  {:>6}  {:>6}  {:>8}\n",
+        "STAGE", "TIME", "SHARE", "ITEMS", "REQUESTS", name_width = name_width
+    );
+    for stage in stages {
+        let share = if total.as_nanos() == 0 {
+            0.0
+        } else {
+            stage.duration.as_secs_f64() / total.as_secs_f64() * 100.0
+        };
+        table.push_str(&format!(
+            "{:name_width$}  {:>10}  {:>5.1}%  {:>6}  {:>8}\n",
+            stage.name, format_duration(stage.duration), share, stage.items, stage.requests,
+            name_width = name_width
+        ));
+    }
+    table.push_str(&format!(
+        "{:name_width$}  {:>10}  {:>6}  {:>6}  {:>8}\n",
+        "total", format_duration(total), "", "",
+        stages.iter().map(|stage| stage.requests).sum::<usize>(),
+        name_width = name_width
+    ));
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(name: &'static str, millis: u64, items: usize, requests: usize) -> StageTiming {
+        StageTiming {
+            name: name,
+            duration: Duration::from_millis(millis),
+            items: items,
+            requests: requests,
+        }
+    }
+
+    #[test]
+    fn format_breakdown_shows_share_of_total() {
+        // Given
+        let stages = vec![stage("parse", 10, 120, 0), stage("match", 30, 118, 3)];
+        // When
+        let table = format_breakdown(&stages);
+        // Then
+        assert_eq!(
+            "STAGE        TIME   SHARE   ITEMS  REQUESTS\n\
+             parse      10.0ms   25.0%     120         0\n\
+             match      30.0ms   75.0%     118         3\n\
+             total      40.0ms                         3\n",
+            table
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+
+extern crate serde;
+use self::serde::{Serialize,Serializer,Deserialize,Deserializer};
+
+/// An item in a playlist. Playlists can hold podcast episodes and local
+/// files as well as tracks, so IDs are kept along with what kind of item
+/// they refer to.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TrackUri {
+    /// A Spotify track ID
+    Track(String),
+    /// A Spotify podcast episode ID
+    Episode(String),
+    /// The full URI of a local file, eg. `spotify:local:Artist:Album:Title:180`.
+    /// Local files can't be added through the API
+    Local(String),
+    /// Anything that couldn't be recognised, as it was given
+    Unknown(String),
+}
+
+/// The length of a Spotify ID
+const ID_LEN: usize = 22;
+
+/// Whether the value is a Spotify ID, ie. 22 base62 characters. Whether
+/// the ID exists is left to Spotify
+fn is_valid_id(id: &str) -> bool {
+    id.len() == ID_LEN && id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+impl TrackUri {
+    /// Parse a Spotify URI, an open.spotify.com link or a bare ID. Bare IDs
+    /// are assumed to be tracks since that's what older CSVs contain.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The URI, link or ID
+    pub fn parse(value: &str) -> TrackUri {
+        let value = value.trim();
+        if value.starts_with("spotify:local:") {
+            return TrackUri::Local(value.to_owned());
+        }
+        let (kind, id) = if let Some(rest) = value.strip_prefix("spotify:") {
+            let mut parts = rest.splitn(2, ':');
+            (parts.next().unwrap_or(""), parts.next().unwrap_or(""))
+        } else if value.contains("open.spotify.com/") {
+            let path = value.split('?').next().unwrap_or("");
+            let mut parts = path.rsplitn(3, '/');
+            let id = parts.next().unwrap_or("");
+            (parts.next().unwrap_or(""), id)
+        } else {
+            ("track", value)
+        };
+        if !is_valid_id(id) {
+            return TrackUri::Unknown(value.to_owned());
+        }
+        match kind {
+            "track" => TrackUri::Track(id.to_owned()),
+            "episode" => TrackUri::Episode(id.to_owned()),
+            _ => TrackUri::Unknown(value.to_owned()),
+        }
+    }
+
+    /// The Spotify URI, eg. `spotify:track:6rqhFgbbKwnb9MLmUQDhG6`. Unknown
+    /// values are returned as they were given
+    pub fn uri(&self) -> String {
+        match *self {
+            TrackUri::Track(ref id) => format!("spotify:track:{}", id),
+            TrackUri::Episode(ref id) => format!("spotify:episode:{}", id),
+            TrackUri::Local(ref uri) | TrackUri::Unknown(ref uri) => uri.to_owned(),
+        }
+    }
+
+    /// The Spotify ID of tracks and episodes
+    pub fn id(&self) -> Option<&str> {
+        match *self {
+            TrackUri::Track(ref id) | TrackUri::Episode(ref id) => Some(id),
+            TrackUri::Local(_) | TrackUri::Unknown(_) => None,
+        }
+    }
+
+    /// Whether the item can be added to a playlist through the API
+    pub fn is_addable(&self) -> bool {
+        self.id().is_some()
+    }
+}
+
+impl fmt::Display for TrackUri {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.uri())
+    }
+}
+
+impl Serialize for TrackUri {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.uri())
+    }
+}
+
+impl<'de> Deserialize<'de> for TrackUri {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TrackUri, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Ok(TrackUri::parse(&value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate serde_json;
+
+    #[test]
+    fn parse_handles_ids_uris_and_links() {
+        let expected = TrackUri::Track("6rqhFgbbKwnb9MLmUQDhG6".to_string());
+        assert_eq!(expected, TrackUri::parse("6rqhFgbbKwnb9MLmUQDhG6"));
+        assert_eq!(expected, TrackUri::parse("spotify:track:6rqhFgbbKwnb9MLmUQDhG6"));
+        assert_eq!(expected, TrackUri::parse("https://open.spotify.com/track/6rqhFgbbKwnb9MLmUQDhG6?si=abc"));
+        let expected = TrackUri::Episode("512ojhOuo1ktJprKbVcKyQ".to_string());
+        assert_eq!(expected, TrackUri::parse("spotify:episode:512ojhOuo1ktJprKbVcKyQ"));
+        assert_eq!(expected, TrackUri::parse("https://open.spotify.com/episode/512ojhOuo1ktJprKbVcKyQ"));
+    }
+
+    #[test]
+    fn parse_keeps_local_and_unknown_values() {
+        let local = "spotify:local:Artist:Album:Title:180";
+        assert_eq!(TrackUri::Local(local.to_string()), TrackUri::parse(local));
+        assert_eq!(local, TrackUri::parse(local).uri());
+        let unknown = ["spotify:album:6rqhFgbbKwnb9MLmUQDhG6", "not an id", "", "spotify:track:"];
+        for value in unknown.iter() {
+            let result = TrackUri::parse(value);
+            assert_eq!(TrackUri::Unknown(value.to_string()), result);
+            assert!(!result.is_addable());
+        }
+    }
+
+    #[test]
+    fn parse_rejects_ids_that_arent_spotify_ids() {
+        // Too short, too long and not base62
+        let invalid = [
+            "bad",
+            "spotify:track:6rqhFgbbKwnb9MLmUQDhG",
+            "6rqhFgbbKwnb9MLmUQDhG6a",
+            "spotify:episode:512ojhOuo1ktJprKbVc_yQ",
+            "https://open.spotify.com/track/6rqhFgbbKwnb9MLmUQDh-6",
+            "6rqhFgbbKwnb9MLmUQDhGé",
+        ];
+        for value in invalid.iter() {
+            let result = TrackUri::parse(value);
+            assert_eq!(TrackUri::Unknown(value.to_string()), result);
+            assert!(!result.is_addable());
+        }
+    }
+
+    #[test]
+    fn serializes_as_uri() {
+        // Given
+        let tracks = vec![TrackUri::Track("6rqhFgbbKwnb9MLmUQDhG6".to_string()), TrackUri::Episode("512ojhOuo1ktJprKbVcKyQ".to_string())];
+        // When
+        let json = serde_json::to_string(&tracks).unwrap();
+        // Then
+        assert_eq!(r#"["spotify:track:6rqhFgbbKwnb9MLmUQDhG6","spotify:episode:512ojhOuo1ktJprKbVcKyQ"]"#, json);
+        let parsed: Vec<TrackUri> = serde_json::from_str(&json).unwrap();
+        assert_eq!(tracks, parsed);
+        // Bare IDs from older files are still read as tracks
+        let parsed: Vec<TrackUri> = serde_json::from_str(r#"["6rqhFgbbKwnb9MLmUQDhG6"]"#).unwrap();
+        assert_eq!(vec![TrackUri::Track("6rqhFgbbKwnb9MLmUQDhG6".to_string())], parsed);
+    }
+}
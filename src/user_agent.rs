@@ -0,0 +1,77 @@
+use std::sync::OnceLock;
+
+extern crate reqwest;
+use self::reqwest::header::{Headers, UserAgent};
+
+/// The User-Agent sent when none is configured, so that providers can tell
+/// where requests come from
+pub const DEFAULT_USER_AGENT: &str = concat!(
+    "playlist-from-csv/", env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/oliveroneill/playlist-from-csv)"
+);
+
+static USER_AGENT: OnceLock<String> = OnceLock::new();
+
+/// Work out the User-Agent to send
+///
+/// # Arguments
+///
+/// * `user_agent` - Replaces the whole User-Agent when set
+/// * `app_name` - Put in front of the default User-Agent, eg. to tell
+/// deployments apart
+pub fn build_user_agent(user_agent: Option<&str>, app_name: Option<&str>) -> String {
+    match (user_agent, app_name) {
+        (Some(user_agent), _) => user_agent.to_owned(),
+        (None, Some(app_name)) => format!("{} {}", app_name, DEFAULT_USER_AGENT),
+        (None, None) => DEFAULT_USER_AGENT.to_owned(),
+    }
+}
+
+/// Set the User-Agent for every client built after this. This should be
+/// called once at startup, later calls are ignored
+///
+/// # Arguments
+///
+/// * `user_agent` - The User-Agent, see `build_user_agent`
+pub fn set_user_agent(user_agent: String) {
+    if USER_AGENT.set(user_agent).is_err() {
+        warn!("The User-Agent was already set");
+    }
+}
+
+/// The User-Agent that requests are sent with
+pub fn user_agent() -> &'static str {
+    USER_AGENT.get().map(|agent| agent.as_str()).unwrap_or(DEFAULT_USER_AGENT)
+}
+
+/// A client builder that sends the configured User-Agent, for clients that
+/// need other settings such as a timeout
+pub fn client_builder() -> reqwest::ClientBuilder {
+    let mut headers = Headers::new();
+    headers.set(UserAgent::new(user_agent().to_owned()));
+    let mut builder = reqwest::Client::builder();
+    builder.default_headers(headers);
+    builder
+}
+
+/// A client that sends the configured User-Agent
+pub fn client() -> reqwest::Client {
+    client_builder().build().expect("Failed to build HTTP client")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_user_agent_prefers_the_full_user_agent() {
+        // When
+        let replaced = build_user_agent(Some("my-bot/1.0"), Some("Radio"));
+        let named = build_user_agent(None, Some("Radio"));
+        let default = build_user_agent(None, None);
+        // Then
+        assert_eq!("my-bot/1.0", replaced);
+        assert_eq!(format!("Radio {}", DEFAULT_USER_AGENT), named);
+        assert_eq!(DEFAULT_USER_AGENT, default);
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use playlist::{PlaylistAPI,TrackVersion};
+use track_uri::TrackUri;
+
+/// Words in brackets after a track name that mean it's another version of
+/// the same recording, eg. `(Remastered 2011)` or `[Single Version]`
+const VERSION_WORDS: [&str; 10] = [
+    "remaster", "remastered", "version", "edit", "mono", "stereo", "single", "album",
+    "deluxe", "anniversary",
+];
+
+/// Which version of a song to keep when there's more than one
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum VersionPreference {
+    /// The version with the most plays on Spotify
+    Popular,
+    /// The version from an album, or the most popular when none are
+    Album,
+}
+
+/// The error when a version preference isn't known
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownVersionPreference {}
+
+impl Error for UnknownVersionPreference {
+    fn description(&self) -> &str {
+        "Expected popular or album"
+    }
+}
+
+impl fmt::Display for UnknownVersionPreference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl FromStr for VersionPreference {
+    type Err = UnknownVersionPreference;
+
+    fn from_str(s: &str) -> Result<VersionPreference, UnknownVersionPreference> {
+        match s {
+            "popular" => Ok(VersionPreference::Popular),
+            "album" => Ok(VersionPreference::Album),
+            _ => Err(UnknownVersionPreference {}),
+        }
+    }
+}
+
+/// Whether text in brackets describes a version, eg. `Remastered 2011`
+fn is_version_note(note: &str) -> bool {
+    note.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| VERSION_WORDS.contains(&word))
+}
+
+/// Remove the version from a track name, eg. `Heroes - 2017 Remaster` and
+/// `Heroes (Single Version)` both become `heroes`. Notes that aren't about
+/// the version, eg. `(feat. Robyn)`, are kept
+///
+/// # Arguments
+///
+/// * `name` - The track name
+pub fn song_title(name: &str) -> String {
+    let mut title = name.split(" - ").next().unwrap_or(name).to_owned();
+    for &(open, close) in &[('(', ')'), ('[', ']')] {
+        while let Some(start) = title.rfind(open) {
+            let end = match title[start..].find(close) {
+                Some(end) => start + end,
+                None => break,
+            };
+            if !is_version_note(&title[start + 1..end]) {
+                break;
+            }
+            title.replace_range(start..=end, "");
+        }
+    }
+    title.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// The title and lead artist that versions of a song share
+fn song_key(version: &TrackVersion) -> (String, String) {
+    let artist = version.track.artists.first().map(|a| a.to_lowercase()).unwrap_or_default();
+    (song_title(&version.track.name), artist)
+}
+
+/// Whether `a` is preferred over `b`
+fn is_preferred(a: &TrackVersion, b: &TrackVersion, preference: VersionPreference) -> bool {
+    match preference {
+        VersionPreference::Album if a.on_album != b.on_album => a.on_album,
+        _ => a.popularity > b.popularity,
+    }
+}
+
+/// Keep only one version of each song. The kept version takes the place of
+/// the first version in the list. Returns the tracks to keep and each track
+/// that was left out along with the version kept instead.
+///
+/// # Arguments
+///
+/// * `tracks` - The tracks to collapse
+/// * `versions` - The details of the tracks, see
+/// `PlaylistAPI::get_track_versions`. Tracks without details are kept
+/// * `preference` - Which version to keep
+pub fn collapse_versions(tracks: &[TrackUri],
+                         versions: &[TrackVersion],
+                         preference: VersionPreference) -> (Vec<TrackUri>, Vec<(TrackUri, TrackUri)>) {
+    // The index in `kept` of each song's preferred version so far
+    let mut songs: HashMap<(String, String), usize> = HashMap::new();
+    let mut kept: Vec<(TrackUri, Option<&TrackVersion>)> = Vec::new();
+    let mut collapsed = Vec::new();
+    for track in tracks {
+        let version = match versions.iter().find(|v| v.track.uri == *track) {
+            Some(version) => version,
+            None => {
+                kept.push((track.clone(), None));
+                continue;
+            },
+        };
+        let key = song_key(version);
+        match songs.get(&key) {
+            Some(&index) => {
+                let current = kept[index].1.unwrap();
+                if is_preferred(version, current, preference) {
+                    collapsed.push((current.track.uri.clone(), track.clone()));
+                    kept[index] = (track.clone(), Some(version));
+                } else {
+                    collapsed.push((track.clone(), current.track.uri.clone()));
+                }
+            },
+            None => {
+                songs.insert(key, kept.len());
+                kept.push((track.clone(), Some(version)));
+            },
+        }
+    }
+    // A version that was replaced should point at the version finally kept
+    let kept: Vec<TrackUri> = kept.into_iter().map(|(track, _)| track).collect();
+    let collapsed = collapsed.iter()
+        .map(|(dropped, instead)| {
+            let mut instead = instead;
+            while !kept.contains(instead) {
+                match collapsed.iter().find(|(d, _)| d == instead) {
+                    Some((_, next)) => instead = next,
+                    None => break,
+                }
+            }
+            (dropped.clone(), instead.clone())
+        })
+        .collect();
+    (kept, collapsed)
+}
+
+/// Keep only one version of each song, see `collapse_versions`
+///
+/// # Arguments
+///
+/// * `playlist_api` - The instance to look up the tracks with
+/// * `tracks` - The tracks to collapse
+/// * `preference` - Which version to keep
+pub fn collapse_tracks<E>(playlist_api: &PlaylistAPI<E>,
+                          tracks: &[TrackUri],
+                          preference: VersionPreference) -> Result<Vec<TrackUri>, E> {
+    let versions = playlist_api.get_track_versions(tracks)?;
+    let (kept, collapsed) = collapse_versions(tracks, &versions, preference);
+    for (dropped, instead) in collapsed {
+        info!("Skipping {} since it's another version of {}", dropped, instead);
+    }
+    Ok(kept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use playlist::TrackMetadata;
+
+    fn version(id: &str, name: &str, popularity: u32, on_album: bool) -> TrackVersion {
+        TrackVersion {
+            track: TrackMetadata {
+                uri: TrackUri::Track(id.to_string()),
+                name: name.to_string(),
+                artists: vec!["David Bowie".to_string()],
+                album: String::new(),
+                duration_ms: 0,
+                image_url: None,
+            },
+            popularity: popularity,
+            on_album: on_album,
+        }
+    }
+
+    fn track(id: &str) -> TrackUri {
+        TrackUri::Track(id.to_string())
+    }
+
+    #[test]
+    fn song_title_removes_versions() {
+        assert_eq!("heroes", song_title("Heroes - 2017 Remaster"));
+        assert_eq!("heroes", song_title("\"Heroes\" (Single Version)"));
+        assert_eq!("under pressure feat queen", song_title("Under Pressure (feat. Queen) [Remastered]"));
+    }
+
+    #[test]
+    fn collapse_versions_keeps_preferred_version() {
+        // Given
+        let tracks = vec![track("single"), track("changes"), track("album"), track("remaster")];
+        let versions = vec![
+            version("single", "Heroes - Single Version", 40, false),
+            version("changes", "Changes", 70, true),
+            version("album", "Heroes", 30, true),
+            version("remaster", "Heroes - 2017 Remaster", 60, false),
+        ];
+        // When
+        let (popular, popular_collapsed) = collapse_versions(&tracks, &versions, VersionPreference::Popular);
+        let (album, _) = collapse_versions(&tracks, &versions, VersionPreference::Album);
+        // Then
+        assert_eq!(vec![track("remaster"), track("changes")], popular);
+        assert_eq!(vec![
+            (track("album"), track("remaster")),
+            (track("single"), track("remaster")),
+        ], popular_collapsed);
+        assert_eq!(vec![track("album"), track("changes")], album);
+    }
+}